@@ -1,6 +1,8 @@
 pub mod refresh {
+    use oxide_auth::code_grant::error::AccessTokenErrorType;
     use oxide_auth::code_grant::refresh::{BearerToken, Error, Input, Output, Refresh, Request};
-    use oxide_auth::primitives::{grant::Grant, registrar::RegistrarError};
+    use oxide_auth::primitives::clock::{Clock, SystemClock};
+    use oxide_auth::primitives::{error::PrimitiveError, grant::Grant, registrar::RegistrarError};
 
     pub trait Endpoint {
         /// Authenticate the requesting confidential client.
@@ -8,6 +10,13 @@ pub mod refresh {
 
         /// Recover and test the provided refresh token then issue new tokens.
         fn issuer(&mut self) -> &mut (dyn crate::primitives::Issuer + Send);
+
+        /// The clock deciding whether the refresh token has expired.
+        ///
+        /// The system clock is the default implementation.
+        fn clock(&self) -> &dyn Clock {
+            &SystemClock
+        }
     }
 
     pub async fn refresh(
@@ -19,25 +28,31 @@ pub mod refresh {
             RecoverRefresh { token: String },
             Authenticate { client: String, pass: Option<Vec<u8>> },
         }
-        let mut refresh = Refresh::new(request);
+        let mut refresh = Refresh::new(request).at(handler.clock().now());
         let mut requested = Requested::None;
         loop {
             let input = match requested {
                 Requested::None => Input::None,
                 Requested::Refresh { token, grant } => {
-                    let refreshed = handler
-                        .issuer()
-                        .refresh(&token, *grant)
-                        .await
-                        .map_err(|()| Error::Primitive)?;
+                    let refreshed =
+                        handler
+                            .issuer()
+                            .refresh(&token, *grant)
+                            .await
+                            .map_err(|err| match err {
+                                // The token was revoked or used concurrently since it was recovered.
+                                PrimitiveError::NotFound => {
+                                    Error::invalid(AccessTokenErrorType::InvalidGrant)
+                                }
+                                err => Error::Primitive(err),
+                            })?;
                     Input::Refreshed(refreshed)
                 }
                 Requested::RecoverRefresh { token } => {
-                    let recovered = handler
-                        .issuer()
-                        .recover_refresh(&token)
-                        .await
-                        .map_err(|()| Error::Primitive)?;
+                    let recovered = match handler.issuer().recover_refresh(&token).await {
+                        Err(PrimitiveError::NotFound) => None,
+                        recovered => recovered.map_err(Error::Primitive)?,
+                    };
                     Input::Recovered {
                         scope: request.scope(),
                         grant: recovered.map(Box::new),
@@ -49,8 +64,12 @@ pub mod refresh {
                         .check(&client, pass.as_deref())
                         .await
                         .map_err(|err| match err {
-                            RegistrarError::PrimitiveError => Error::Primitive,
-                            RegistrarError::Unspecified => Error::unauthorized("basic"),
+                            RegistrarError::PrimitiveError => {
+                                Error::Primitive(PrimitiveError::Invariant)
+                            }
+                            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                                Error::unauthorized("basic")
+                            }
                         })?;
                     Input::Authenticated {
                         scope: request.scope(),
@@ -79,6 +98,8 @@ pub mod refresh {
 
 pub mod resource {
     use oxide_auth::code_grant::resource::{Error, Input, Output, Request, Resource};
+    use oxide_auth::primitives::clock::{Clock, SystemClock};
+    use oxide_auth::primitives::error::PrimitiveError;
     use oxide_auth::primitives::grant::Grant;
     use oxide_auth::primitives::scope::Scope;
 
@@ -88,6 +109,13 @@ pub mod resource {
 
         /// Recover and test the provided refresh token then issue new tokens.
         fn issuer(&mut self) -> &mut (dyn crate::primitives::Issuer + Send);
+
+        /// The clock deciding whether the token has expired.
+        ///
+        /// The system clock is the default implementation.
+        fn clock(&self) -> &dyn Clock {
+            &SystemClock
+        }
     }
 
     pub async fn protect(
@@ -100,7 +128,7 @@ pub mod resource {
             Grant(String),
         }
 
-        let mut resource = Resource::new();
+        let mut resource = Resource::at(handler.clock().now());
         let mut requested = Requested::None;
         loop {
             let input = match requested {
@@ -108,11 +136,11 @@ pub mod resource {
                 Requested::Request => Input::Request { request: req },
                 Requested::Scopes => Input::Scopes(handler.scopes()),
                 Requested::Grant(token) => {
-                    let grant = handler
-                        .issuer()
-                        .recover_token(&token)
-                        .await
-                        .map_err(|_| Error::PrimitiveError)?;
+                    let grant = match handler.issuer().recover_token(&token).await {
+                        // A token that vanished in the meantime is as invalid as an unknown one.
+                        Err(PrimitiveError::NotFound) => None,
+                        grant => grant.map_err(Error::PrimitiveError)?,
+                    };
                     Input::Recovered(grant)
                 }
             };
@@ -135,6 +163,8 @@ pub mod access_token {
             AccessToken, BearerToken, Error, Input, Output, PrimitiveError, Request as TokenRequest,
         },
         primitives::{
+            clock::{Clock, SystemClock},
+            error::PrimitiveError as Cause,
             grant::{Extensions, Grant},
             registrar::RegistrarError,
         },
@@ -175,6 +205,13 @@ pub mod access_token {
         ///
         /// It is possible to use `&mut ()`.
         fn extension(&mut self) -> &mut (dyn Extension + Send);
+
+        /// The clock deciding whether the authorization code has expired.
+        ///
+        /// The system clock is the default implementation.
+        fn clock(&self) -> &dyn Clock {
+            &SystemClock
+        }
     }
 
     pub async fn access_token(
@@ -195,7 +232,7 @@ pub mod access_token {
             },
         }
 
-        let mut access_token = AccessToken::new(request);
+        let mut access_token = AccessToken::new(request).at(handler.clock().now());
         let mut requested = Requested::None;
 
         loop {
@@ -207,23 +244,32 @@ pub mod access_token {
                         .check(client, passdata)
                         .await
                         .map_err(|err| match err {
-                            RegistrarError::Unspecified => Error::unauthorized("basic"),
+                            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                                Error::unauthorized("basic")
+                            }
                             RegistrarError::PrimitiveError => {
                                 Error::Primitive(Box::new(PrimitiveError {
                                     grant: None,
                                     extensions: None,
+                                    cause: Cause::Invariant,
                                 }))
                             }
                         })?;
                     Input::Authenticated
                 }
                 Requested::Recover(code) => {
-                    let opt_grant = handler.authorizer().extract(code).await.map_err(|_| {
-                        Error::Primitive(Box::new(PrimitiveError {
-                            grant: None,
-                            extensions: None,
-                        }))
-                    })?;
+                    let opt_grant = match handler.authorizer().extract(code).await {
+                        // A code that vanished in the meantime is as invalid as an unknown one.
+                        Err(Cause::NotFound) => None,
+                        Err(cause) => {
+                            return Err(Error::Primitive(Box::new(PrimitiveError {
+                                grant: None,
+                                extensions: None,
+                                cause,
+                            })))
+                        }
+                        Ok(grant) => grant,
+                    };
                     Input::Recovered(opt_grant.map(Box::new))
                 }
                 Requested::Extend { extensions } => {
@@ -236,11 +282,12 @@ pub mod access_token {
                     Input::Extended { access_extensions }
                 }
                 Requested::Issue { grant } => {
-                    let token = handler.issuer().issue(grant.clone()).await.map_err(|_| {
+                    let token = handler.issuer().issue(grant.clone()).await.map_err(|cause| {
                         Error::Primitive(Box::new(PrimitiveError {
                             // FIXME: endpoint should get and handle these.
                             grant: None,
                             extensions: None,
+                            cause,
                         }))
                     })?;
                     Input::Issued(token)
@@ -263,19 +310,23 @@ pub mod access_token {
 
 pub mod authorization {
     use async_trait::async_trait;
-    use chrono::{Duration, Utc};
+    use chrono::Duration;
     use oxide_auth::{
         code_grant::{
             authorization::{Authorization, Error, ErrorUrl, Input, Output, Request},
             error::{AuthorizationError, AuthorizationErrorType},
+            extensions::attach_claims,
         },
         endpoint::{PreGrant, Scope, Solicitation},
         primitives::{
+            clock::{Clock, SystemClock},
+            error::PrimitiveError,
             grant::{Extensions, Grant},
             prelude::ClientUrl,
             registrar::{BoundClient, ExactUrl, RegistrarError},
         },
     };
+    use serde_json::{Map, Value};
     use url::Url;
 
     use std::borrow::Cow;
@@ -314,6 +365,13 @@ pub mod authorization {
         ///
         /// It is possible to use `&mut ()`.
         fn extension(&mut self) -> &mut (dyn Extension + Send);
+
+        /// The clock from which the lifetime of authorization codes is computed.
+        ///
+        /// The system clock is the default implementation.
+        fn clock(&self) -> &dyn Clock {
+            &SystemClock
+        }
     }
 
     /// Represents a valid, currently pending authorization request not bound to an owner. The frontend
@@ -337,9 +395,15 @@ pub mod authorization {
 
         /// Denies the request, which redirects to the client for which the request originated.
         pub fn deny(self) -> Result<Url, Error> {
+            self.deny_with(AuthorizationErrorType::AccessDenied)
+        }
+
+        /// Fails the request with an error, which redirects to the client for which the request
+        /// originated.
+        pub fn deny_with(self, kind: AuthorizationErrorType) -> Result<Url, Error> {
             let url = self.pre_grant.redirect_uri;
             let mut error = AuthorizationError::default();
-            error.set_type(AuthorizationErrorType::AccessDenied);
+            error.set_type(kind);
             let error = ErrorUrl::new(url.into(), self.state.as_deref(), error);
             Err(Error::Redirect(error))
         }
@@ -350,21 +414,33 @@ pub mod authorization {
         /// same endpoint as was used to create the pending request.
         pub async fn authorize(
             self, handler: &mut (dyn Endpoint + Send), owner_id: Cow<'_, str>,
+        ) -> Result<Url, Error> {
+            self.authorize_with_claims(handler, owner_id, Map::new()).await
+        }
+
+        /// Inform the backend about consent from a resource owner, with custom claims for the
+        /// grant.
+        pub async fn authorize_with_claims(
+            self, handler: &mut (dyn Endpoint + Send), owner_id: Cow<'_, str>,
+            claims: Map<String, Value>,
         ) -> Result<Url, Error> {
             let mut url = self.pre_grant.redirect_uri.to_url();
 
+            let mut grant = Grant {
+                owner_id: owner_id.into_owned(),
+                client_id: self.pre_grant.client_id,
+                redirect_uri: self.pre_grant.redirect_uri.into(),
+                scope: self.pre_grant.scope,
+                until: handler.clock().now() + Duration::minutes(10),
+                extensions: self.extensions,
+            };
+            attach_claims(&mut grant.extensions, claims);
+
             let grant = handler
                 .authorizer()
-                .authorize(Grant {
-                    owner_id: owner_id.into_owned(),
-                    client_id: self.pre_grant.client_id,
-                    redirect_uri: self.pre_grant.redirect_uri.into(),
-                    scope: self.pre_grant.scope,
-                    until: Utc::now() + Duration::minutes(10),
-                    extensions: self.extensions,
-                })
+                .authorize(grant)
                 .await
-                .map_err(|()| Error::PrimitiveError)?;
+                .map_err(Error::PrimitiveError)?;
 
             url.query_pairs_mut()
                 .append_pair("code", grant.as_str())
@@ -423,7 +499,12 @@ pub mod authorization {
                     };
                     let bound_client = match handler.registrar().bound_redirect(client_url).await {
                         Err(RegistrarError::Unspecified) => return Err(Error::Ignore),
-                        Err(RegistrarError::PrimitiveError) => return Err(Error::PrimitiveError),
+                        Err(RegistrarError::RedirectMismatch(mismatch)) => {
+                            return Err(Error::RedirectMismatch(mismatch))
+                        }
+                        Err(RegistrarError::PrimitiveError) => {
+                            return Err(Error::PrimitiveError(PrimitiveError::Invariant))
+                        }
                         Ok(pre_grant) => pre_grant,
                     };
                     the_redirect_uri = Some(bound_client.redirect_uri.clone().into_owned());
@@ -457,8 +538,10 @@ pub mod authorization {
                     };
                     let pre_grant = handler.registrar().negotiate(bound_client, scope).await.map_err(
                         |err| match err {
-                            RegistrarError::PrimitiveError => Error::PrimitiveError,
-                            RegistrarError::Unspecified => {
+                            RegistrarError::PrimitiveError => {
+                                Error::PrimitiveError(PrimitiveError::Invariant)
+                            }
+                            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
                                 let prepared_error = ErrorUrl::with_request(
                                     request,
                                     redirect_uri,
@@ -505,3 +588,423 @@ pub mod authorization {
         }
     }
 }
+
+pub mod client_credentials {
+    use async_trait::async_trait;
+    use chrono::Duration;
+    use oxide_auth::{
+        code_grant::{
+            accesstoken::{BearerToken, PrimitiveError},
+            client_credentials::{ClientCredentials, Error, Input, Output, Request},
+            extensions::attach_claims,
+        },
+        endpoint::{PreGrant, Scope, Solicitation},
+        primitives::{
+            clock::{Clock, SystemClock},
+            error::PrimitiveError as Cause,
+            grant::{Extensions, Grant},
+            registrar::{BoundClient, ClientUrl, RegistrarError},
+        },
+    };
+    use serde_json::{Map, Value};
+
+    use std::borrow::Cow;
+
+    /// A system of addons provided additional data.
+    ///
+    /// An endpoint not having any extension may use `&mut ()` as the result of system.
+    #[async_trait]
+    pub trait Extension {
+        /// Inspect the request to produce extension data.
+        async fn extend(
+            &mut self, request: &(dyn Request + Sync),
+        ) -> std::result::Result<Extensions, ()>;
+    }
+
+    #[async_trait]
+    impl Extension for () {
+        async fn extend(&mut self, _: &(dyn Request + Sync)) -> std::result::Result<Extensions, ()> {
+            Ok(Extensions::new())
+        }
+    }
+
+    /// Required functionality to respond to client credentials requests.
+    pub trait Endpoint {
+        /// Get the client corresponding to some id.
+        fn registrar(&self) -> &(dyn crate::primitives::Registrar + Sync);
+
+        /// Return the issuer instance to create the client credentials.
+        fn issuer(&mut self) -> &mut (dyn crate::primitives::Issuer + Send);
+
+        /// The system of used extension, extending responses.
+        ///
+        /// It is possible to use `&mut ()`.
+        fn extension(&mut self) -> &mut (dyn Extension + Send);
+
+        /// The clock from which the lifetime of the grant is computed.
+        ///
+        /// The system clock is the default implementation.
+        fn clock(&self) -> &dyn Clock {
+            &SystemClock
+        }
+    }
+
+    /// Represents a valid, currently pending client credentials not bound to an owner.
+    ///
+    /// This will be passed along to the solicitor to obtain the owner ID, and then a token will be
+    /// issued.
+    pub struct Pending {
+        pre_grant: PreGrant,
+        extensions: Extensions,
+    }
+
+    impl Pending {
+        /// Reference this pending state as a solicitation.
+        pub fn as_solicitation(&self) -> Solicitation<'_> {
+            Solicitation::new(&self.pre_grant)
+        }
+
+        /// Inform the backend about consent from a resource owner.
+        ///
+        /// Use negotiated parameters to issue a token for the owner. The endpoint SHOULD be the
+        /// same endpoint as was used to create the pending request.
+        pub async fn issue(
+            self, handler: &mut (dyn Endpoint + Send), owner_id: String, allow_refresh_token: bool,
+        ) -> Result<BearerToken, Error> {
+            self.issue_with_claims(handler, owner_id, Map::new(), allow_refresh_token)
+                .await
+        }
+
+        /// Inform the backend about consent from a resource owner, with custom claims for the
+        /// grant.
+        pub async fn issue_with_claims(
+            self, handler: &mut (dyn Endpoint + Send), owner_id: String, claims: Map<String, Value>,
+            allow_refresh_token: bool,
+        ) -> Result<BearerToken, Error> {
+            let scope = self.pre_grant.scope.clone();
+            let mut grant = Grant {
+                owner_id,
+                client_id: self.pre_grant.client_id,
+                redirect_uri: self.pre_grant.redirect_uri.into_url(),
+                scope: self.pre_grant.scope,
+                until: handler.clock().now() + Duration::minutes(10),
+                extensions: self.extensions,
+            };
+            attach_claims(&mut grant.extensions, claims);
+
+            let mut token = handler.issuer().issue(grant).await.map_err(primitive_error)?;
+
+            if !allow_refresh_token {
+                token.refresh = None;
+            }
+
+            Ok(BearerToken::new(token, &scope))
+        }
+    }
+
+    fn primitive_error(cause: Cause) -> Error {
+        Error::Primitive(Box::new(PrimitiveError {
+            grant: None,
+            extensions: None,
+            cause,
+        }))
+    }
+
+    /// Authenticate the client and negotiate the scope of its token.
+    pub async fn client_credentials(
+        handler: &mut (dyn Endpoint + Send + Sync), request: &(dyn Request + Sync),
+    ) -> Result<Pending, Error> {
+        enum Requested {
+            None,
+            Authenticate {
+                client: String,
+                passdata: Vec<u8>,
+            },
+            Bind {
+                client_id: String,
+            },
+            Extend,
+            Negotiate {
+                bound_client: BoundClient<'static>,
+                scope: Option<Scope>,
+            },
+        }
+
+        let mut client_credentials = ClientCredentials::new(request);
+        let mut requested = Requested::None;
+
+        loop {
+            let input = match requested {
+                Requested::None => Input::None,
+                Requested::Authenticate { client, passdata } => {
+                    handler
+                        .registrar()
+                        .check(&client, Some(passdata.as_slice()))
+                        .await
+                        .map_err(|err| match err {
+                            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                                Error::unauthorized("basic")
+                            }
+                            RegistrarError::PrimitiveError => primitive_error(Cause::Invariant),
+                        })?;
+                    Input::Authenticated
+                }
+                Requested::Bind { client_id } => {
+                    let client_url = ClientUrl {
+                        client_id: Cow::Owned(client_id),
+                        redirect_uri: None,
+                    };
+                    let bound_client = match handler.registrar().bound_redirect(client_url).await {
+                        Err(RegistrarError::Unspecified) | Err(RegistrarError::RedirectMismatch(_)) => {
+                            return Err(Error::Ignore)
+                        }
+                        Err(RegistrarError::PrimitiveError) => {
+                            return Err(primitive_error(Cause::Invariant))
+                        }
+                        Ok(bound_client) => bound_client,
+                    };
+                    Input::Bound { bound_client }
+                }
+                Requested::Extend => {
+                    let extensions = handler
+                        .extension()
+                        .extend(request)
+                        .await
+                        .map_err(|_| Error::invalid())?;
+                    Input::Extended { extensions }
+                }
+                Requested::Negotiate { bound_client, scope } => {
+                    let pre_grant = handler.registrar().negotiate(bound_client, scope).await.map_err(
+                        |err| match err {
+                            RegistrarError::PrimitiveError => primitive_error(Cause::Invariant),
+                            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                                Error::Ignore
+                            }
+                        },
+                    )?;
+                    Input::Negotiated { pre_grant }
+                }
+            };
+
+            requested = match client_credentials.advance(input) {
+                Output::Authenticate { client, passdata } => Requested::Authenticate {
+                    client: client.to_owned(),
+                    passdata: passdata.to_vec(),
+                },
+                Output::Binding { client_id } => Requested::Bind {
+                    client_id: client_id.to_owned(),
+                },
+                Output::Extend => Requested::Extend,
+                Output::Negotiate { bound_client, scope } => Requested::Negotiate {
+                    bound_client: bound_client.clone(),
+                    scope,
+                },
+                Output::Ok {
+                    pre_grant,
+                    extensions,
+                } => {
+                    return Ok(Pending {
+                        pre_grant: pre_grant.clone(),
+                        extensions: extensions.clone(),
+                    })
+                }
+                Output::Err(e) => return Err(*e),
+            };
+        }
+    }
+}
+
+pub mod introspection {
+    use oxide_auth::code_grant::introspection::{Error, IntrospectionResponse, Request, INTROSPECTION_SCOPE};
+    use oxide_auth::primitives::clock::{Clock, SystemClock};
+    use oxide_auth::primitives::{error::PrimitiveError, grant::Grant, registrar::RegistrarError};
+    use oxide_auth::primitives::scope::Scope;
+
+    /// Required functionality to respond to introspection requests.
+    pub trait Endpoint {
+        /// Authenticate resource servers using client credentials.
+        fn registrar(&self) -> &(dyn crate::primitives::Registrar + Sync);
+
+        /// Recover the introspected token and bearer tokens of resource servers.
+        fn issuer(&mut self) -> &mut (dyn crate::primitives::Issuer + Send);
+
+        /// Whether the client may introspect tokens after authenticating with its credentials.
+        ///
+        /// The default implementation allows no client.
+        fn resource_server(&self, _client_id: &str) -> bool {
+            false
+        }
+
+        /// The scope the bearer token of a resource server must include.
+        ///
+        /// The default implementation requires the `introspection` scope.
+        fn introspection_scope(&self) -> Scope {
+            INTROSPECTION_SCOPE.parse().unwrap()
+        }
+
+        /// The clock deciding whether a token has expired.
+        ///
+        /// The system clock is the default implementation.
+        fn clock(&self) -> &dyn Clock {
+            &SystemClock
+        }
+    }
+
+    /// Describe the token of the request to the authenticated resource server.
+    pub async fn introspect(
+        handler: &mut (dyn Endpoint + Send + Sync), request: &(dyn Request + Sync),
+    ) -> Result<IntrospectionResponse, Error> {
+        if !request.valid() {
+            return Err(Error::invalid());
+        }
+
+        authenticate(handler, request).await?;
+        let token = request.token().ok_or_else(Error::invalid)?;
+
+        let now = handler.clock().now();
+        let issuer = handler.issuer();
+        let refresh_first = request.token_type_hint().as_deref() == Some("refresh_token");
+        // The hint only decides the order of lookups, any token is still found.
+        let recovered: Result<Option<(Grant, bool)>, PrimitiveError> = if refresh_first {
+            match issuer.recover_refresh(&token).await {
+                Ok(None) => issuer.recover_token(&token).await.map(|g| g.map(|g| (g, true))),
+                found => found.map(|g| g.map(|g| (g, false))),
+            }
+        } else {
+            match issuer.recover_token(&token).await {
+                Ok(None) => issuer
+                    .recover_refresh(&token)
+                    .await
+                    .map(|g| g.map(|g| (g, false))),
+                found => found.map(|g| g.map(|g| (g, true))),
+            }
+        };
+
+        match recovered {
+            Ok(Some((grant, access))) if grant.until > now => {
+                Ok(IntrospectionResponse::active(grant, access))
+            }
+            // A token that vanished in the meantime is no longer active either.
+            Ok(_) | Err(PrimitiveError::NotFound) => Ok(IntrospectionResponse::inactive()),
+            Err(err) => Err(Error::Primitive(err)),
+        }
+    }
+
+    /// Authenticate the resource server with client credentials or with an active bearer token.
+    async fn authenticate(
+        handler: &mut (dyn Endpoint + Send + Sync), request: &(dyn Request + Sync),
+    ) -> Result<(), Error> {
+        if let Some((client_id, passphrase)) = request.authorization() {
+            handler
+                .registrar()
+                .check(&client_id, Some(&passphrase))
+                .await
+                .map_err(|err| match err {
+                    RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+                    RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                        Error::unauthorized("basic")
+                    }
+                })?;
+            // Valid credentials of an ordinary client do not suffice.
+            if !handler.resource_server(&client_id) {
+                return Err(Error::unauthorized("basic"));
+            }
+            return Ok(());
+        }
+
+        let bearer = request.bearer().ok_or_else(|| Error::unauthorized("basic"))?;
+        let now = handler.clock().now();
+        let scope = handler.introspection_scope();
+        match handler.issuer().recover_token(&bearer).await {
+            Ok(Some(grant)) if grant.until > now && scope.allow_access(&grant.scope) => Ok(()),
+            Ok(_) | Err(PrimitiveError::NotFound) => Err(Error::unauthorized("Bearer")),
+            Err(err) => Err(Error::Primitive(err)),
+        }
+    }
+}
+
+pub mod revocation {
+    use oxide_auth::code_grant::error::AccessTokenErrorType;
+    use oxide_auth::code_grant::revocation::{Error, Request};
+    use oxide_auth::primitives::{error::PrimitiveError, registrar::RegistrarError};
+
+    /// Required functionality to respond to revocation requests.
+    pub trait Endpoint {
+        /// Authenticate the requesting client.
+        fn registrar(&self) -> &(dyn crate::primitives::Registrar + Sync);
+
+        /// Recover and revoke the token.
+        fn issuer(&mut self) -> &mut (dyn crate::primitives::Issuer + Send);
+    }
+
+    /// Revoke the token of the request on behalf of the authenticated client.
+    pub async fn revoke(
+        handler: &mut (dyn Endpoint + Send + Sync), request: &(dyn Request + Sync),
+    ) -> Result<(), Error> {
+        if !request.valid() {
+            return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+        }
+
+        let client_id = authenticate(handler, request).await?;
+        let token = request
+            .token()
+            .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidRequest))?;
+
+        let issuer = handler.issuer();
+        // The hint only decides the order of lookups, any token is still found.
+        let recovered = if request.token_type_hint().as_deref() == Some("refresh_token") {
+            match issuer.recover_refresh(&token).await {
+                Ok(None) => issuer.recover_token(&token).await,
+                found => found,
+            }
+        } else {
+            match issuer.recover_token(&token).await {
+                Ok(None) => issuer.recover_refresh(&token).await,
+                found => found,
+            }
+        };
+
+        let revoked = match recovered {
+            // Tokens that vanished in the meantime need not be revoked.
+            Ok(None) | Err(PrimitiveError::NotFound) => return Ok(()),
+            Ok(Some(grant)) if grant.client_id != client_id => {
+                return Err(Error::invalid(AccessTokenErrorType::UnauthorizedClient))
+            }
+            Ok(Some(_)) => issuer.revoke(&token).await,
+            Err(err) => Err(err),
+        };
+
+        match revoked {
+            Ok(()) | Err(PrimitiveError::NotFound) => Ok(()),
+            Err(err) => Err(Error::Primitive(err)),
+        }
+    }
+
+    /// Authenticate the client with its credentials or, for public clients, its id.
+    async fn authenticate(
+        handler: &mut (dyn Endpoint + Send + Sync), request: &(dyn Request + Sync),
+    ) -> Result<String, Error> {
+        let (client_id, passphrase) = match (request.authorization(), request.client_id()) {
+            // An authenticated client may still name itself, but not as another client.
+            (Some((client_id, passphrase)), Some(named)) if named == client_id => {
+                (client_id, Some(passphrase))
+            }
+            (Some(_), Some(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+            (Some((client_id, passphrase)), None) => (client_id, Some(passphrase)),
+            (None, Some(client_id)) => (client_id, None),
+            (None, None) => return Err(Error::unauthorized("basic")),
+        };
+
+        handler
+            .registrar()
+            .check(&client_id, passphrase.as_deref())
+            .await
+            .map_err(|err| match err {
+                RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+                RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                    Error::unauthorized("basic")
+                }
+            })?;
+        Ok(client_id.into_owned())
+    }
+}
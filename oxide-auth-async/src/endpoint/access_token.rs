@@ -4,6 +4,7 @@ use std::{borrow::Cow, marker::PhantomData};
 use oxide_auth::{
     endpoint::{QueryParameter, WebRequest, OAuthError, WebResponse, Template, NormalizedParameter},
     code_grant::accesstoken::{Error as TokenError, Request as TokenRequest},
+    primitives::clock::{Clock, SystemClock},
 };
 
 use super::Endpoint;
@@ -175,9 +176,9 @@ where
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
-        TokenError::Primitive(_) => {
+        TokenError::Primitive(err) => {
             // FIXME: give the context for restoration.
-            return Err(endpoint.error(OAuthError::PrimitiveError));
+            return Err(endpoint.error(err.cause.into()));
         }
     })
 }
@@ -205,6 +206,10 @@ where
             .and_then(super::Extension::access_token)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
 }
 
 impl<R: WebRequest> WrappedRequest<R> {
@@ -3,6 +3,7 @@ use std::{borrow::Cow, marker::PhantomData};
 use oxide_auth::{
     endpoint::{WebResponse, QueryParameter, NormalizedParameter},
     code_grant::authorization::{Error as AuthorizationError, Request as AuthorizationRequest},
+    code_grant::error::{AuthorizationError as ErrorDescription, AuthorizationErrorType},
 };
 
 use crate::code_grant::authorization::{
@@ -10,6 +11,7 @@ use crate::code_grant::authorization::{
 };
 
 use super::*;
+use serde_json::{Map, Value};
 use url::Url;
 
 /// All relevant methods for handling authorization code requests.
@@ -209,7 +211,17 @@ where
                 .map_err(|err| endpoint.web_error(err))?;
             Ok(response)
         }
-        AuthorizationError::PrimitiveError => Err(endpoint.error(OAuthError::PrimitiveError)),
+        AuthorizationError::RedirectMismatch(mismatch) => {
+            let mut description = ErrorDescription::default();
+            description.explain(mismatch.description());
+            let mut response =
+                endpoint.response(request, Template::new_invalid_redirect(Some(&mut description)))?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            let text = description.explanation().unwrap_or(mismatch.description());
+            response.body_text(text).map_err(|err| endpoint.web_error(err))?;
+            Ok(response)
+        }
+        AuthorizationError::PrimitiveError(err) => Err(endpoint.error(err.into())),
     }
 }
 
@@ -229,8 +241,10 @@ where
         match checked {
             OwnerConsent::Denied => self.deny(),
             OwnerConsent::InProgress(resp) => self.in_progress(resp),
-            OwnerConsent::Authorized(who) => self.authorize(who).await,
+            OwnerConsent::Authorized(who) => self.authorize(who, Map::new()).await,
+            OwnerConsent::AuthorizedWithClaims(who, claims) => self.authorize(who, claims).await,
             OwnerConsent::Error(err) => (self.request, Err(self.endpoint.inner.web_error(err))),
+            OwnerConsent::Failed(kind) => self.fail(kind),
         }
     }
 
@@ -245,16 +259,26 @@ where
     }
 
     /// Denies the request, the client is not allowed access.
-    fn deny(mut self) -> (R, Result<R::Response, E::Error>) {
-        let result = self.pending.deny();
+    fn deny(self) -> (R, Result<R::Response, E::Error>) {
+        self.fail(AuthorizationErrorType::AccessDenied)
+    }
+
+    /// Fails the request with an error for the client.
+    fn fail(mut self, kind: AuthorizationErrorType) -> (R, Result<R::Response, E::Error>) {
+        let result = self.pending.deny_with(kind);
         let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request);
 
         (self.request, result)
     }
 
     /// Tells the system that the resource owner with the given id has approved the grant.
-    async fn authorize(mut self, who: String) -> (R, Result<R::Response, E::Error>) {
-        let result = self.pending.authorize(self.endpoint, who.into()).await;
+    async fn authorize(
+        mut self, who: String, claims: Map<String, Value>,
+    ) -> (R, Result<R::Response, E::Error>) {
+        let result = self
+            .pending
+            .authorize_with_claims(self.endpoint, who.into(), claims)
+            .await;
         let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request);
 
         (self.request, result)
@@ -303,6 +327,10 @@ where
             .and_then(super::Extension::authorization)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
 }
 
 impl<'a, R> WrappedRequest<R>
@@ -0,0 +1,321 @@
+use std::{borrow::Cow, marker::PhantomData, str::from_utf8};
+
+use oxide_auth::{
+    code_grant::client_credentials::{Error, Request},
+    endpoint::{
+        WebRequest, WebResponse, OAuthError, OwnerConsent, QueryParameter, Template, NormalizedParameter,
+    },
+    primitives::clock::{Clock, SystemClock},
+};
+use serde_json::Map;
+
+use super::Endpoint;
+use crate::{
+    code_grant::client_credentials::{
+        client_credentials, Endpoint as ClientCredentialsEndpoint, Extension,
+    },
+    primitives::{Issuer, Registrar},
+};
+
+/// Offers access tokens to authenticated third parties.
+///
+/// A client may request a token that provides access to their own resources. The owner of the
+/// token is determined by the owner solicitor of the endpoint, which must not require any user
+/// interaction.
+pub struct ClientCredentialsFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: WrappedToken<E, R>,
+    allow_credentials_in_body: bool,
+    allow_refresh_token: bool,
+}
+
+struct WrappedToken<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    inner: E,
+    extension_fallback: (),
+    r_type: PhantomData<R>,
+}
+
+struct WrappedRequest<R: WebRequest> {
+    /// The query in the body.
+    body: NormalizedParameter,
+
+    /// The authorization tuple.
+    authorization: Option<Authorization>,
+
+    /// An error if one occurred.
+    error: Option<Option<R::Error>>,
+
+    /// The credentials-in-body flag from the flow.
+    allow_credentials_in_body: bool,
+}
+
+struct Authorization(String, Vec<u8>);
+
+impl<E, R> ClientCredentialsFlow<E, R>
+where
+    E: Endpoint<R> + Send + Sync,
+    R: WebRequest + Send + Sync,
+    <R as WebRequest>::Error: Send + Sync,
+{
+    /// Wrap the endpoint if it supports handling client credentials requests.
+    ///
+    /// Also binds the endpoint to the particular `WebRequest` type through the type system. The
+    /// endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    ///
+    /// ## Panics
+    ///
+    /// Indirectly `execute` may panic when this flow is instantiated with an inconsistent
+    /// endpoint, for details see the documentation of `Endpoint` and `execute`. For
+    /// consistent endpoints, the panic is instead caught as an error here.
+    pub fn prepare(mut endpoint: E) -> Result<Self, E::Error> {
+        if endpoint.registrar().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(ClientCredentialsFlow {
+            endpoint: WrappedToken {
+                inner: endpoint,
+                extension_fallback: (),
+                r_type: PhantomData,
+            },
+            allow_credentials_in_body: false,
+            allow_refresh_token: false,
+        })
+    }
+
+    /// Credentials in body should only be enabled if use of HTTP Basic is not possible.
+    ///
+    /// Allows the request body to contain the `client_secret` as a form parameter. This is NOT
+    /// RECOMMENDED and need not be supported. The parameters MUST NOT appear in the request URI
+    /// itself.
+    ///
+    /// Thus support is disabled by default and must be explicitely enabled.
+    pub fn allow_credentials_in_body(&mut self, allow: bool) {
+        self.allow_credentials_in_body = allow;
+    }
+
+    /// Allow the refresh token to be included in the response.
+    ///
+    /// A refresh token SHOULD NOT be included in the response for the client credentials grant,
+    /// so any refresh token returned from the issuer is discarded by default.
+    pub fn allow_refresh_token(&mut self, allow: bool) {
+        self.allow_refresh_token = allow;
+    }
+
+    pub async fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let pending = client_credentials(
+            &mut self.endpoint,
+            &WrappedRequest::new(&mut request, self.allow_credentials_in_body),
+        )
+        .await;
+        let pending = match pending {
+            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(pending) => pending,
+        };
+
+        let consent = self
+            .endpoint
+            .inner
+            .owner_solicitor()
+            .unwrap()
+            .check_consent(&mut request, pending.as_solicitation())
+            .await;
+
+        let (owner_id, claims) = match consent {
+            OwnerConsent::Authorized(owner_id) => (owner_id, Map::new()),
+            OwnerConsent::AuthorizedWithClaims(owner_id, claims) => (owner_id, claims),
+            OwnerConsent::Error(error) => return Err(self.endpoint.inner.web_error(error)),
+            OwnerConsent::InProgress(..) => {
+                // User interaction is not permitted in the client credentials flow.
+                return Err(self.endpoint.inner.error(OAuthError::PrimitiveError));
+            }
+            OwnerConsent::Denied | OwnerConsent::Failed(_) => {
+                return denied(&mut self.endpoint.inner, &mut request);
+            }
+        };
+
+        let issued = pending
+            .issue_with_claims(&mut self.endpoint, owner_id, claims, self.allow_refresh_token)
+            .await;
+        let token = match issued {
+            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(token) => token,
+        };
+
+        let mut response = self.endpoint.inner.response(&mut request, Template::new_ok())?;
+        response
+            .body_json(&token.to_json())
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        Ok(response)
+    }
+}
+
+/// Respond to a client for which the owner solicitor denied a token.
+fn denied<E, R>(endpoint: &mut E, request: &mut R) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    let mut json = match Error::unauthorized("basic") {
+        Error::Unauthorized(json, _) => json,
+        _ => unreachable!("Constructed an unauthorized error"),
+    };
+    let mut response = endpoint.response(
+        request,
+        Template::new_unauthorized(None, Some(json.description())),
+    )?;
+    response.client_error().map_err(|err| endpoint.web_error(err))?;
+    response
+        .body_json(&json.to_json())
+        .map_err(|err| endpoint.web_error(err))?;
+    Ok(response)
+}
+
+fn token_error<E, R>(endpoint: &mut E, request: &mut R, error: Error) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    Ok(match error {
+        Error::Ignore => return Err(endpoint.error(OAuthError::DenySilently)),
+        Error::Invalid(mut json) => {
+            let mut response =
+                endpoint.response(request, Template::new_bad(Some(json.description())))?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Unauthorized(mut json, scheme) => {
+            let mut response = endpoint.response(
+                request,
+                Template::new_unauthorized(None, Some(json.description())),
+            )?;
+            response
+                .unauthorized(&scheme)
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Primitive(err) => {
+            // FIXME: give the context for restoration.
+            return Err(endpoint.error(err.cause.into()));
+        }
+    })
+}
+
+impl<'a, R: WebRequest> WrappedRequest<R> {
+    pub fn new(request: &'a mut R, allow_credentials_in_body: bool) -> Self {
+        Self::new_or_fail(request, allow_credentials_in_body)
+            .unwrap_or_else(|err| Self::from_err(err, allow_credentials_in_body))
+    }
+
+    fn new_or_fail(
+        request: &'a mut R, allow_credentials_in_body: bool,
+    ) -> Result<Self, Option<R::Error>> {
+        // If there is a header, it must parse correctly.
+        let authorization = match request.authheader() {
+            Err(err) => return Err(Some(err)),
+            Ok(Some(header)) => Self::parse_header(header).map(Some)?,
+            Ok(None) => None,
+        };
+
+        Ok(WrappedRequest {
+            body: request.urlbody()?.into_owned(),
+            authorization,
+            error: None,
+            allow_credentials_in_body,
+        })
+    }
+
+    fn from_err(err: Option<R::Error>, allow_credentials_in_body: bool) -> Self {
+        WrappedRequest {
+            body: Default::default(),
+            authorization: None,
+            error: Some(err),
+            allow_credentials_in_body,
+        }
+    }
+
+    fn parse_header(header: Cow<str>) -> Result<Authorization, Option<R::Error>> {
+        let auth_data = header.strip_prefix("Basic ").ok_or(None)?;
+        let combined = base64::decode(auth_data).map_err(|_| None)?;
+
+        let mut split = combined.splitn(2, |&c| c == b':');
+        let client_bin = split.next().ok_or(None)?;
+        let passwd = split.next().ok_or(None)?;
+        let client = from_utf8(client_bin).map_err(|_| None)?;
+
+        Ok(Authorization(client.to_string(), passwd.to_vec()))
+    }
+}
+
+impl<E, R> ClientCredentialsEndpoint for WrappedToken<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    fn registrar(&self) -> &(dyn Registrar + Sync) {
+        self.inner.registrar().unwrap()
+    }
+
+    fn issuer(&mut self) -> &mut (dyn Issuer + Send) {
+        self.inner.issuer_mut().unwrap()
+    }
+
+    fn extension(&mut self) -> &mut (dyn Extension + Send) {
+        self.inner
+            .extension()
+            .and_then(super::Extension::client_credentials)
+            .unwrap_or(&mut self.extension_fallback)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
+}
+
+impl<R: WebRequest> Request for WrappedRequest<R> {
+    fn valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        self.authorization
+            .as_ref()
+            .map(|auth| (auth.0.as_str().into(), auth.1.as_slice().into()))
+    }
+
+    fn scope(&self) -> Option<Cow<str>> {
+        self.body.unique_value("scope")
+    }
+
+    fn grant_type(&self) -> Option<Cow<str>> {
+        self.body.unique_value("grant_type")
+    }
+
+    fn extension(&self, key: &str) -> Option<Cow<str>> {
+        self.body.unique_value(key)
+    }
+
+    fn allow_credentials_in_body(&self) -> bool {
+        self.allow_credentials_in_body
+    }
+}
@@ -0,0 +1,258 @@
+use std::{borrow::Cow, marker::PhantomData, str::from_utf8};
+
+use oxide_auth::{
+    code_grant::introspection::{Error, Request, INTROSPECTION_SCOPE},
+    endpoint::{WebRequest, WebResponse, OAuthError, QueryParameter, Template, NormalizedParameter},
+    primitives::{
+        clock::{Clock, SystemClock},
+        scope::Scope,
+    },
+};
+
+use super::Endpoint;
+use crate::{
+    code_grant::introspection::{introspect, Endpoint as IntrospectionEndpoint},
+    primitives::{Issuer, Registrar},
+};
+
+/// Answers resource servers asking about the state of a token.
+///
+/// This is the introspection endpoint of [RFC 7662]. Resource servers authenticate with client
+/// credentials in a basic authorization header or with a valid bearer token of their own. Only
+/// the clients named with `resource_server` and bearer tokens with the `introspection_scope` are
+/// accepted.
+///
+/// [RFC 7662]: https://tools.ietf.org/html/rfc7662
+pub struct IntrospectionFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: WrappedIntrospection<E, R>,
+}
+
+struct WrappedIntrospection<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    inner: E,
+    r_type: PhantomData<R>,
+    resource_servers: Vec<String>,
+    scope: Scope,
+}
+
+struct WrappedRequest<R: WebRequest> {
+    /// The query in the body.
+    body: NormalizedParameter,
+
+    /// The authorization of the resource server.
+    authorization: Option<Authorization>,
+
+    /// An error if one occurred.
+    error: Option<Option<R::Error>>,
+}
+
+enum Authorization {
+    Basic(String, Vec<u8>),
+    Bearer(String),
+}
+
+impl<E, R> IntrospectionFlow<E, R>
+where
+    E: Endpoint<R> + Send + Sync,
+    R: WebRequest + Send + Sync,
+    <R as WebRequest>::Error: Send + Sync,
+{
+    /// Wrap the endpoint if it supports handling introspection requests.
+    ///
+    /// Also binds the endpoint to the particular `WebRequest` type through the type system. The
+    /// endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    pub fn prepare(mut endpoint: E) -> Result<Self, E::Error> {
+        if endpoint.registrar().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(IntrospectionFlow {
+            endpoint: WrappedIntrospection {
+                inner: endpoint,
+                r_type: PhantomData,
+                resource_servers: Vec::new(),
+                scope: INTROSPECTION_SCOPE.parse().unwrap(),
+            },
+        })
+    }
+
+    /// Allow the client to introspect tokens with its credentials.
+    ///
+    /// No client may do so by default, since introspection describes the grant of any token.
+    pub fn resource_server(&mut self, client_id: &str) {
+        self.endpoint.resource_servers.push(client_id.to_owned());
+    }
+
+    /// The scope a bearer token must include to introspect tokens, `introspection` by default.
+    pub fn introspection_scope(&mut self, scope: Scope) {
+        self.endpoint.scope = scope;
+    }
+
+    pub async fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let introspected = introspect(&mut self.endpoint, &WrappedRequest::new(&mut request)).await;
+
+        let description = match introspected {
+            Err(error) => return introspection_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(description) => description,
+        };
+
+        let mut response = self.endpoint.inner.response(&mut request, Template::new_ok())?;
+        response
+            .body_json(&description.to_json())
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        Ok(response)
+    }
+}
+
+fn introspection_error<E, R>(
+    endpoint: &mut E, request: &mut R, error: Error,
+) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    Ok(match error {
+        Error::Invalid(mut json) => {
+            let mut response =
+                endpoint.response(request, Template::new_bad(Some(json.description())))?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Unauthorized(mut json, scheme) => {
+            let mut response = endpoint.response(
+                request,
+                Template::new_unauthorized(None, Some(json.description())),
+            )?;
+            response
+                .unauthorized(&scheme)
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Primitive(err) => return Err(endpoint.error(err.into())),
+    })
+}
+
+impl<'a, R: WebRequest> WrappedRequest<R> {
+    pub fn new(request: &'a mut R) -> Self {
+        Self::new_or_fail(request).unwrap_or_else(Self::from_err)
+    }
+
+    fn new_or_fail(request: &'a mut R) -> Result<Self, Option<R::Error>> {
+        // If there is a header, it must parse correctly.
+        let authorization = match request.authheader() {
+            Err(err) => return Err(Some(err)),
+            Ok(Some(header)) => Self::parse_header(header).map(Some)?,
+            Ok(None) => None,
+        };
+
+        Ok(WrappedRequest {
+            body: request.urlbody()?.into_owned(),
+            authorization,
+            error: None,
+        })
+    }
+
+    fn from_err(err: Option<R::Error>) -> Self {
+        WrappedRequest {
+            body: Default::default(),
+            authorization: None,
+            error: Some(err),
+        }
+    }
+
+    fn parse_header(header: Cow<str>) -> Result<Authorization, Option<R::Error>> {
+        if let Some(token) = header.strip_prefix("Bearer ") {
+            return Ok(Authorization::Bearer(token.to_string()));
+        }
+
+        let auth_data = header.strip_prefix("Basic ").ok_or(None)?;
+        let combined = base64::decode(auth_data).map_err(|_| None)?;
+
+        let mut split = combined.splitn(2, |&c| c == b':');
+        let client_bin = split.next().ok_or(None)?;
+        let passwd = split.next().ok_or(None)?;
+        let client = from_utf8(client_bin).map_err(|_| None)?;
+
+        Ok(Authorization::Basic(client.to_string(), passwd.to_vec()))
+    }
+}
+
+impl<E, R> IntrospectionEndpoint for WrappedIntrospection<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    fn registrar(&self) -> &(dyn Registrar + Sync) {
+        self.inner.registrar().unwrap()
+    }
+
+    fn issuer(&mut self) -> &mut (dyn Issuer + Send) {
+        self.inner.issuer_mut().unwrap()
+    }
+
+    fn resource_server(&self, client_id: &str) -> bool {
+        self.resource_servers.iter().any(|allowed| allowed == client_id)
+    }
+
+    fn introspection_scope(&self) -> Scope {
+        self.scope.clone()
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
+}
+
+impl<R: WebRequest> Request for WrappedRequest<R> {
+    fn valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        match &self.authorization {
+            Some(Authorization::Basic(client, passwd)) => {
+                Some((client.as_str().into(), passwd.as_slice().into()))
+            }
+            _ => None,
+        }
+    }
+
+    fn bearer(&self) -> Option<Cow<str>> {
+        match &self.authorization {
+            Some(Authorization::Bearer(token)) => Some(token.as_str().into()),
+            _ => None,
+        }
+    }
+
+    fn token(&self) -> Option<Cow<str>> {
+        self.body.unique_value("token")
+    }
+
+    fn token_type_hint(&self) -> Option<Cow<str>> {
+        self.body.unique_value("token_type_hint")
+    }
+
+    fn extension(&self, key: &str) -> Option<Cow<str>> {
+        self.body.unique_value(key)
+    }
+}
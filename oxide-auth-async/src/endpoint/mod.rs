@@ -1,14 +1,19 @@
 use async_trait::async_trait;
 use oxide_auth::endpoint::{OAuthError, Template, WebRequest, OwnerConsent, Solicitation, Scopes};
+use oxide_auth::primitives::clock::{Clock, SystemClock};
 
 pub use crate::code_grant::access_token::{Extension as AccessTokenExtension};
 pub use crate::code_grant::authorization::Extension as AuthorizationExtension;
+pub use crate::code_grant::client_credentials::Extension as ClientCredentialsExtension;
 use crate::primitives::{Authorizer, Registrar, Issuer};
 
 pub mod authorization;
 pub mod access_token;
+pub mod client_credentials;
+pub mod introspection;
 pub mod refresh;
 pub mod resource;
+pub mod revocation;
 
 pub trait Endpoint<Request>
 where
@@ -67,6 +72,13 @@ where
     fn extension(&mut self) -> Option<&mut (dyn Extension + Send)> {
         None
     }
+
+    /// The clock deciding the expiry of codes and tokens in the flows.
+    ///
+    /// Returning `None` is the default implementation and uses the system clock.
+    fn clock(&self) -> Option<&dyn Clock> {
+        None
+    }
 }
 
 pub trait Extension {
@@ -79,6 +91,11 @@ pub trait Extension {
     fn access_token(&mut self) -> Option<&mut (dyn AccessTokenExtension + Send)> {
         None
     }
+
+    /// The handler for client credentials extensions.
+    fn client_credentials(&mut self) -> Option<&mut (dyn ClientCredentialsExtension + Send)> {
+        None
+    }
 }
 
 /// Checks consent with the owner of a resource, identified in a request.
@@ -3,6 +3,7 @@ use std::{borrow::Cow, marker::PhantomData, str::from_utf8};
 use oxide_auth::{
     code_grant::refresh::{Error, Request},
     endpoint::{WebRequest, WebResponse, OAuthError, QueryParameter, Template, NormalizedParameter},
+    primitives::clock::{Clock, SystemClock},
 };
 
 use super::Endpoint;
@@ -122,9 +123,9 @@ where
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
-        Error::Primitive => {
+        Error::Primitive(err) => {
             // FIXME: give the context for restoration.
-            return Err(endpoint.error(OAuthError::PrimitiveError));
+            return Err(endpoint.error(err.into()));
         }
     })
 }
@@ -202,6 +203,10 @@ where
     fn issuer(&mut self) -> &mut (dyn Issuer + Send) {
         self.inner.issuer_mut().unwrap()
     }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
 }
 
 impl<R: WebRequest> Request for WrappedRequest<R> {
@@ -98,9 +98,7 @@ where
             ResourceError::AccessDenied { .. } => Template::new_unauthorized(None, None),
             ResourceError::NoAuthentication { .. } => Template::new_unauthorized(None, None),
             ResourceError::InvalidRequest { .. } => Template::new_bad(None),
-            ResourceError::PrimitiveError => {
-                return Err(self.endpoint.0.error(OAuthError::PrimitiveError))
-            }
+            ResourceError::PrimitiveError(err) => return Err(self.endpoint.0.error((*err).into())),
         };
 
         let mut response = self.endpoint.0.response(request, template)?;
@@ -149,6 +147,10 @@ where
     fn issuer(&mut self) -> &mut (dyn Issuer + Send) {
         self.endpoint.issuer_mut().unwrap()
     }
+
+    fn clock(&self) -> &dyn Clock {
+        self.endpoint.clock().unwrap_or(&SystemClock)
+    }
 }
 
 impl<R: WebRequest> ResourceRequest for WrappedRequest<R> {
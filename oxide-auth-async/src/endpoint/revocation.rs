@@ -0,0 +1,207 @@
+use std::{borrow::Cow, marker::PhantomData, str::from_utf8};
+
+use oxide_auth::{
+    code_grant::revocation::{Error, Request},
+    endpoint::{WebRequest, WebResponse, OAuthError, QueryParameter, Template, NormalizedParameter},
+};
+
+use super::Endpoint;
+use crate::{
+    code_grant::revocation::{revoke, Endpoint as RevocationEndpoint},
+    primitives::{Issuer, Registrar},
+};
+
+/// Revokes access and refresh tokens on request of the client they were issued to.
+///
+/// This is the revocation endpoint of [RFC 7009]. Confidential clients authenticate with their
+/// credentials in a basic authorization header, public clients name themselves with `client_id`.
+/// The response is successful and empty for unknown tokens as well.
+///
+/// [RFC 7009]: https://tools.ietf.org/html/rfc7009
+pub struct RevocationFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: WrappedRevocation<E, R>,
+}
+
+struct WrappedRevocation<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    inner: E,
+    r_type: PhantomData<R>,
+}
+
+struct WrappedRequest<R: WebRequest> {
+    /// The query in the body.
+    body: NormalizedParameter,
+
+    /// The authorization tuple.
+    authorization: Option<Authorization>,
+
+    /// An error if one occurred.
+    error: Option<Option<R::Error>>,
+}
+
+struct Authorization(String, Vec<u8>);
+
+impl<E, R> RevocationFlow<E, R>
+where
+    E: Endpoint<R> + Send + Sync,
+    R: WebRequest + Send + Sync,
+    <R as WebRequest>::Error: Send + Sync,
+{
+    /// Wrap the endpoint if it supports handling revocation requests.
+    ///
+    /// Also binds the endpoint to the particular `WebRequest` type through the type system. The
+    /// endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    pub fn prepare(mut endpoint: E) -> Result<Self, E::Error> {
+        if endpoint.registrar().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(RevocationFlow {
+            endpoint: WrappedRevocation {
+                inner: endpoint,
+                r_type: PhantomData,
+            },
+        })
+    }
+
+    pub async fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let revoked = revoke(&mut self.endpoint, &WrappedRequest::new(&mut request)).await;
+
+        if let Err(error) = revoked {
+            return revocation_error(&mut self.endpoint.inner, &mut request, error);
+        }
+
+        self.endpoint.inner.response(&mut request, Template::new_ok())
+    }
+}
+
+fn revocation_error<E, R>(
+    endpoint: &mut E, request: &mut R, error: Error,
+) -> Result<R::Response, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    Ok(match error {
+        Error::Invalid(mut json) => {
+            let mut response =
+                endpoint.response(request, Template::new_bad(Some(json.description())))?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Unauthorized(mut json, scheme) => {
+            let mut response = endpoint.response(
+                request,
+                Template::new_unauthorized(None, Some(json.description())),
+            )?;
+            response
+                .unauthorized(&scheme)
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Primitive(err) => return Err(endpoint.error(err.into())),
+    })
+}
+
+impl<'a, R: WebRequest> WrappedRequest<R> {
+    pub fn new(request: &'a mut R) -> Self {
+        Self::new_or_fail(request).unwrap_or_else(Self::from_err)
+    }
+
+    fn new_or_fail(request: &'a mut R) -> Result<Self, Option<R::Error>> {
+        // If there is a header, it must parse correctly.
+        let authorization = match request.authheader() {
+            Err(err) => return Err(Some(err)),
+            Ok(Some(header)) => Self::parse_header(header).map(Some)?,
+            Ok(None) => None,
+        };
+
+        Ok(WrappedRequest {
+            body: request.urlbody()?.into_owned(),
+            authorization,
+            error: None,
+        })
+    }
+
+    fn from_err(err: Option<R::Error>) -> Self {
+        WrappedRequest {
+            body: Default::default(),
+            authorization: None,
+            error: Some(err),
+        }
+    }
+
+    fn parse_header(header: Cow<str>) -> Result<Authorization, Option<R::Error>> {
+        let auth_data = header.strip_prefix("Basic ").ok_or(None)?;
+        let combined = base64::decode(auth_data).map_err(|_| None)?;
+
+        let mut split = combined.splitn(2, |&c| c == b':');
+        let client_bin = split.next().ok_or(None)?;
+        let passwd = split.next().ok_or(None)?;
+        let client = from_utf8(client_bin).map_err(|_| None)?;
+
+        Ok(Authorization(client.to_string(), passwd.to_vec()))
+    }
+}
+
+impl<E, R> RevocationEndpoint for WrappedRevocation<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    fn registrar(&self) -> &(dyn Registrar + Sync) {
+        self.inner.registrar().unwrap()
+    }
+
+    fn issuer(&mut self) -> &mut (dyn Issuer + Send) {
+        self.inner.issuer_mut().unwrap()
+    }
+}
+
+impl<R: WebRequest> Request for WrappedRequest<R> {
+    fn valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        self.authorization
+            .as_ref()
+            .map(|auth| (auth.0.as_str().into(), auth.1.as_slice().into()))
+    }
+
+    fn client_id(&self) -> Option<Cow<str>> {
+        self.body.unique_value("client_id")
+    }
+
+    fn token(&self) -> Option<Cow<str>> {
+        self.body.unique_value("token")
+    }
+
+    fn token_type_hint(&self) -> Option<Cow<str>> {
+        self.body.unique_value("token_type_hint")
+    }
+
+    fn extension(&self, key: &str) -> Option<Cow<str>> {
+        self.body.unique_value(key)
+    }
+}
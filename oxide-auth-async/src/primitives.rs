@@ -1,6 +1,6 @@
 //! Async versions of all primitives traits.
 use async_trait::async_trait;
-use oxide_auth::primitives::{grant::Grant, scope::Scope};
+use oxide_auth::primitives::{error::PrimitiveError, grant::Grant, scope::Scope};
 use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken};
 use oxide_auth::primitives::{
     authorizer, registrar, issuer,
@@ -9,9 +9,9 @@ use oxide_auth::primitives::{
 
 #[async_trait]
 pub trait Authorizer {
-    async fn authorize(&mut self, _: Grant) -> Result<String, ()>;
+    async fn authorize(&mut self, _: Grant) -> Result<String, PrimitiveError>;
 
-    async fn extract(&mut self, _: &str) -> Result<Option<Grant>, ()>;
+    async fn extract(&mut self, _: &str) -> Result<Option<Grant>, PrimitiveError>;
 }
 
 #[async_trait]
@@ -19,24 +19,32 @@ impl<T> Authorizer for T
 where
     T: authorizer::Authorizer + Send + ?Sized,
 {
-    async fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+    async fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
         authorizer::Authorizer::authorize(self, grant)
     }
 
-    async fn extract(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+    async fn extract(&mut self, token: &str) -> Result<Option<Grant>, PrimitiveError> {
         authorizer::Authorizer::extract(self, token)
     }
 }
 
 #[async_trait]
 pub trait Issuer {
-    async fn issue(&mut self, _: Grant) -> Result<IssuedToken, ()>;
+    async fn issue(&mut self, _: Grant) -> Result<IssuedToken, PrimitiveError>;
 
-    async fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, ()>;
+    async fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, PrimitiveError>;
 
-    async fn recover_token(&mut self, _: &str) -> Result<Option<Grant>, ()>;
+    async fn recover_token(&mut self, _: &str) -> Result<Option<Grant>, PrimitiveError>;
 
-    async fn recover_refresh(&mut self, _: &str) -> Result<Option<Grant>, ()>;
+    async fn recover_refresh(&mut self, _: &str) -> Result<Option<Grant>, PrimitiveError>;
+
+    /// Revoke an access or refresh token.
+    ///
+    /// Revoking a refresh token should also revoke the access token issued with it. Unknown tokens
+    /// are not an error. The default implementation fails, as for issuers that can not revoke.
+    async fn revoke(&mut self, _: &str) -> Result<(), PrimitiveError> {
+        Err(PrimitiveError::Invariant)
+    }
 }
 
 #[async_trait]
@@ -44,21 +52,25 @@ impl<T> Issuer for T
 where
     T: issuer::Issuer + Send + ?Sized,
 {
-    async fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+    async fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
         issuer::Issuer::issue(self, grant)
     }
 
-    async fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+    async fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
         issuer::Issuer::refresh(self, token, grant)
     }
 
-    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, PrimitiveError> {
         issuer::Issuer::recover_token(self, token)
     }
 
-    async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, ()> {
+    async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, PrimitiveError> {
         issuer::Issuer::recover_refresh(self, token)
     }
+
+    async fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        issuer::Issuer::revoke(self, token)
+    }
 }
 
 #[async_trait]
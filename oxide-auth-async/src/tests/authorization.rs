@@ -2,14 +2,14 @@ use std::collections::HashMap;
 
 use oxide_auth::primitives::authorizer::AuthMap;
 use oxide_auth::{
-    primitives::registrar::{Client, ClientMap, RegisteredUrl},
+    primitives::registrar::{Client, ClientMap, RedirectMismatch, RegisteredUrl},
     frontends::simple::endpoint::Error,
     endpoint::WebRequest,
 };
 
 use crate::endpoint::{Endpoint, OwnerSolicitor, authorization::AuthorizationFlow};
 
-use super::{CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
+use super::{Body, CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
 use super::{Allow, Deny};
 use super::defaults::*;
 
@@ -119,6 +119,29 @@ impl AuthorizationSetup {
         }
     }
 
+    fn test_invalid_redirect(&mut self, request: CraftedRequest, mismatch: RedirectMismatch) {
+        let mut solicitor = Allow(EXAMPLE_OWNER_ID.to_string());
+        let mut authorization_flow = AuthorizationFlow::prepare(AuthorizationEndpoint::new(
+            &self.registrar,
+            &mut self.authorizer,
+            &mut solicitor,
+        ))
+        .unwrap();
+        let response = smol::block_on(authorization_flow.execute(request))
+            .expect("Expected the mismatch to be shown");
+
+        assert_eq!(response.status, Status::BadRequest);
+        assert!(
+            response.location.is_none(),
+            "Redirect to rejected url {:?}",
+            response
+        );
+        match response.body {
+            Some(Body::Text(ref text)) if text == mismatch.description() => (),
+            other => panic!("Expected explanation of {:?}: {:?}", mismatch, other),
+        }
+    }
+
     fn test_error_redirect<P: Send + Sync>(&mut self, request: CraftedRequest, mut pagehandler: P)
     where
         P: OwnerSolicitor<CraftedRequest>,
@@ -202,8 +225,8 @@ fn auth_request_silent_unknown_client() {
 }
 
 #[test]
-fn auth_request_silent_mismatching_redirect() {
-    // The redirect_uri does not match
+fn auth_request_mismatching_redirect() {
+    // The redirect_uri does not match, the reason is shown instead of redirecting
     let mismatching_redirect = CraftedRequest {
         query: Some(
             vec![
@@ -218,7 +241,8 @@ fn auth_request_silent_mismatching_redirect() {
         auth: None,
     };
 
-    AuthorizationSetup::new().test_silent_error(mismatching_redirect);
+    AuthorizationSetup::new()
+        .test_invalid_redirect(mismatching_redirect, RedirectMismatch::Unregistered);
 }
 
 #[test]
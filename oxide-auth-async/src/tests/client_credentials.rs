@@ -0,0 +1,151 @@
+use oxide_auth::primitives::issuer::TokenMap;
+use oxide_auth::primitives::generator::RandomGenerator;
+use oxide_auth::{
+    code_grant::accesstoken::TokenResponse,
+    endpoint::WebRequest,
+    primitives::registrar::{Client, ClientMap, RegisteredUrl},
+    primitives::scope::Scope,
+    frontends::simple::endpoint::Error,
+};
+
+use crate::endpoint::{client_credentials::ClientCredentialsFlow, Endpoint, OwnerSolicitor};
+
+use super::{Allow, Body, CraftedRequest, Deny, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+struct ClientCredentialsEndpoint<'a, S> {
+    registrar: &'a ClientMap,
+    issuer: &'a mut TokenMap<RandomGenerator>,
+    solicitor: S,
+}
+
+impl<'a, S> Endpoint<CraftedRequest> for ClientCredentialsEndpoint<'a, S>
+where
+    S: OwnerSolicitor<CraftedRequest> + Send,
+{
+    type Error = Error<CraftedRequest>;
+
+    fn registrar(&self) -> Option<&(dyn crate::primitives::Registrar + Sync)> {
+        Some(self.registrar)
+    }
+    fn authorizer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Authorizer + Send)> {
+        None
+    }
+    fn issuer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Issuer + Send)> {
+        Some(self.issuer)
+    }
+    fn response(
+        &mut self, _: &mut CraftedRequest, _: oxide_auth::endpoint::Template,
+    ) -> Result<<CraftedRequest as WebRequest>::Response, Self::Error> {
+        Ok(Default::default())
+    }
+    fn error(&mut self, err: oxide_auth::endpoint::OAuthError) -> Self::Error {
+        Error::OAuth(err)
+    }
+    fn web_error(&mut self, err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
+        Error::Web(err)
+    }
+    fn scopes(&mut self) -> Option<&mut dyn oxide_auth::endpoint::Scopes<CraftedRequest>> {
+        None
+    }
+    fn owner_solicitor(
+        &mut self,
+    ) -> Option<&mut (dyn crate::endpoint::OwnerSolicitor<CraftedRequest> + Send)> {
+        Some(&mut self.solicitor)
+    }
+}
+
+struct ClientCredentialsSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+    basic_authorization: String,
+}
+
+impl ClientCredentialsSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        let basic_authorization =
+            base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+
+        ClientCredentialsSetup {
+            registrar,
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            basic_authorization: format!("Basic {}", basic_authorization),
+        }
+    }
+
+    fn request(&self) -> CraftedRequest {
+        CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [("grant_type", "client_credentials")]
+                    .iter()
+                    .to_single_value_query(),
+            ),
+            auth: Some(self.basic_authorization.clone()),
+        }
+    }
+
+    fn flow<S>(
+        &mut self, solicitor: S,
+    ) -> ClientCredentialsFlow<ClientCredentialsEndpoint<'_, S>, CraftedRequest>
+    where
+        S: OwnerSolicitor<CraftedRequest> + Send + Sync,
+    {
+        ClientCredentialsFlow::prepare(ClientCredentialsEndpoint {
+            registrar: &self.registrar,
+            issuer: &mut self.issuer,
+            solicitor,
+        })
+        .unwrap_or_else(|_| panic!("Not violating any requirements on client credentials flow."))
+    }
+}
+
+#[test]
+fn client_credentials_success() {
+    let mut setup = ClientCredentialsSetup::new();
+    let request = setup.request();
+    let response = smol::block_on(setup.flow(Allow(EXAMPLE_OWNER_ID.to_owned())).execute(request))
+        .unwrap_or_else(|_| panic!("Expected non-error response"));
+    assert_eq!(response.status, Status::Ok);
+
+    let body = match response.body {
+        Some(Body::Json(body)) => body,
+        _ => panic!("Expected json body"),
+    };
+    let token: TokenResponse = serde_json::from_str(&body).expect("Expected valid json body");
+    assert!(token.access_token.is_some());
+    assert!(token.refresh_token.is_none());
+    let scope = token.scope.expect("Expected a scope");
+    assert_eq!(scope.parse::<Scope>().unwrap(), EXAMPLE_SCOPE.parse().unwrap());
+}
+
+#[test]
+fn client_credentials_denied() {
+    let mut setup = ClientCredentialsSetup::new();
+    let request = setup.request();
+    let response = smol::block_on(setup.flow(Deny).execute(request))
+        .unwrap_or_else(|_| panic!("Expected non-error response"));
+    assert_eq!(response.status, Status::BadRequest);
+}
+
+#[test]
+fn client_credentials_wrong_passphrase() {
+    let mut setup = ClientCredentialsSetup::new();
+    setup.basic_authorization = format!(
+        "Basic {}",
+        base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, "wrong"))
+    );
+    let request = setup.request();
+    let response = smol::block_on(setup.flow(Allow(EXAMPLE_OWNER_ID.to_owned())).execute(request))
+        .unwrap_or_else(|_| panic!("Expected non-error response"));
+    assert_eq!(response.status, Status::Unauthorized);
+    assert!(response.www_authenticate.is_some());
+}
@@ -0,0 +1,168 @@
+use oxide_auth::primitives::issuer::{IssuedToken, TokenMap};
+use oxide_auth::primitives::generator::RandomGenerator;
+use oxide_auth::primitives::grant::{Extensions, Grant};
+use oxide_auth::{
+    code_grant::introspection::IntrospectionResponse,
+    endpoint::WebRequest,
+    primitives::registrar::{Client, ClientMap, RegisteredUrl},
+    frontends::simple::endpoint::Error,
+};
+
+use crate::{
+    endpoint::{introspection::IntrospectionFlow, Endpoint},
+    primitives::Issuer,
+};
+
+use chrono::{Duration, Utc};
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+pub struct TokenEndpoint<'a> {
+    registrar: &'a ClientMap,
+    issuer: &'a mut TokenMap<RandomGenerator>,
+}
+
+impl<'a> TokenEndpoint<'a> {
+    pub fn new(registrar: &'a ClientMap, issuer: &'a mut TokenMap<RandomGenerator>) -> Self {
+        Self { registrar, issuer }
+    }
+}
+
+impl<'a> Endpoint<CraftedRequest> for TokenEndpoint<'a> {
+    type Error = Error<CraftedRequest>;
+
+    fn registrar(&self) -> Option<&(dyn crate::primitives::Registrar + Sync)> {
+        Some(self.registrar)
+    }
+    fn authorizer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Authorizer + Send)> {
+        None
+    }
+    fn issuer_mut(&mut self) -> Option<&mut (dyn crate::primitives::Issuer + Send)> {
+        Some(self.issuer)
+    }
+    fn response(
+        &mut self, _: &mut CraftedRequest, _: oxide_auth::endpoint::Template,
+    ) -> Result<<CraftedRequest as WebRequest>::Response, Self::Error> {
+        Ok(Default::default())
+    }
+    fn error(&mut self, err: oxide_auth::endpoint::OAuthError) -> Self::Error {
+        Error::OAuth(err)
+    }
+    fn web_error(&mut self, err: <CraftedRequest as WebRequest>::Error) -> Self::Error {
+        Error::Web(err)
+    }
+    fn scopes(&mut self) -> Option<&mut dyn oxide_auth::endpoint::Scopes<CraftedRequest>> {
+        None
+    }
+    fn owner_solicitor(
+        &mut self,
+    ) -> Option<&mut (dyn crate::endpoint::OwnerSolicitor<CraftedRequest> + Send)> {
+        None
+    }
+}
+
+fn grant(scope: &str) -> Grant {
+    Grant {
+        client_id: EXAMPLE_CLIENT_ID.to_string(),
+        owner_id: EXAMPLE_OWNER_ID.to_string(),
+        redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+        scope: scope.parse().unwrap(),
+        until: Utc::now() + Duration::hours(1),
+        extensions: Extensions::new(),
+    }
+}
+
+pub struct TokenSetup {
+    pub registrar: ClientMap,
+    pub issuer: TokenMap<RandomGenerator>,
+    pub issued: IssuedToken,
+    pub basic_authorization: String,
+}
+
+impl TokenSetup {
+    pub fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        let issued = smol::block_on(issuer.issue(grant(EXAMPLE_SCOPE))).unwrap();
+
+        let basic_authorization =
+            base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+
+        TokenSetup {
+            registrar,
+            issuer,
+            issued,
+            basic_authorization: format!("Basic {}", basic_authorization),
+        }
+    }
+
+    pub fn introspect(&mut self, token: &str, auth: Option<String>) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some([("token", token)].iter().to_single_value_query()),
+            auth,
+        };
+        let mut flow = IntrospectionFlow::prepare(TokenEndpoint::new(&self.registrar, &mut self.issuer))
+            .unwrap_or_else(|_| panic!("Not violating any requirements on introspection flow."));
+        flow.resource_server(EXAMPLE_CLIENT_ID);
+        smol::block_on(flow.execute(request)).unwrap_or_else(|_| panic!("Expected non-error response"))
+    }
+
+    pub fn description(&mut self, token: &str) -> IntrospectionResponse {
+        let response = self.introspect(token, Some(self.basic_authorization.clone()));
+        assert_eq!(response.status, Status::Ok);
+        match response.body {
+            Some(Body::Json(body)) => serde_json::from_str(&body).expect("Expected valid json body"),
+            _ => panic!("Expected json body"),
+        }
+    }
+}
+
+#[test]
+fn introspect_active_and_inactive() {
+    let mut setup = TokenSetup::new();
+    let access = setup.issued.token.clone();
+    let refresh = setup.issued.refresh.clone().unwrap();
+
+    let description = setup.description(&access);
+    assert!(description.active);
+    assert_eq!(description.client_id.as_deref(), Some(EXAMPLE_CLIENT_ID));
+    assert_eq!(description.sub.as_deref(), Some(EXAMPLE_OWNER_ID));
+    assert_eq!(description.token_type.as_deref(), Some("bearer"));
+
+    let description = setup.description(&refresh);
+    assert!(description.active);
+    assert_eq!(description.token_type, None);
+
+    assert_eq!(setup.description("unknown"), IntrospectionResponse::inactive());
+}
+
+#[test]
+fn introspect_with_bearer() {
+    let mut setup = TokenSetup::new();
+    let access = setup.issued.token.clone();
+    let introspection = smol::block_on(setup.issuer.issue(grant("introspection"))).unwrap();
+
+    let response = setup.introspect(&access, Some(format!("Bearer {}", introspection.token)));
+    assert_eq!(response.status, Status::Ok);
+
+    // The token of an ordinary client lacks the introspection scope.
+    let response = setup.introspect(&access, Some(format!("Bearer {}", access)));
+    assert_eq!(response.status, Status::Unauthorized);
+
+    let response = setup.introspect(&access, Some("Bearer unknown".to_owned()));
+    assert_eq!(response.status, Status::Unauthorized);
+    assert_eq!(response.www_authenticate.as_deref(), Some("Bearer"));
+
+    let response = setup.introspect(&access, None);
+    assert_eq!(response.status, Status::Unauthorized);
+}
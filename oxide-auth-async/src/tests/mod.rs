@@ -225,6 +225,9 @@ pub mod defaults {
 
 mod authorization;
 mod access_token;
+mod client_credentials;
+mod introspection;
+mod revocation;
 mod type_properties;
 mod resource;
 mod refresh;
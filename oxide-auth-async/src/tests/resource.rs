@@ -1,3 +1,4 @@
+use oxide_auth::primitives::clock::{Clock, ManualClock};
 use oxide_auth::primitives::issuer::TokenMap;
 use oxide_auth::primitives::generator::RandomGenerator;
 use oxide_auth::primitives::grant::{Grant, Extensions};
@@ -12,6 +13,7 @@ use crate::endpoint::{resource::ResourceFlow, Endpoint};
 pub struct ResourceEndpoint<'a> {
     issuer: &'a mut TokenMap<RandomGenerator>,
     scopes: &'a mut [Scope],
+    clock: Option<&'a ManualClock>,
 }
 
 impl<'a> Endpoint<CraftedRequest> for ResourceEndpoint<'a> {
@@ -45,11 +47,18 @@ impl<'a> Endpoint<CraftedRequest> for ResourceEndpoint<'a> {
     ) -> Option<&mut (dyn crate::endpoint::OwnerSolicitor<CraftedRequest> + Send)> {
         None
     }
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.clock.map(|clock| clock as &dyn Clock)
+    }
 }
 
 impl<'a> ResourceEndpoint<'a> {
     pub fn new(issuer: &'a mut TokenMap<RandomGenerator>, scopes: &'a mut [Scope]) -> Self {
-        Self { issuer, scopes }
+        Self {
+            issuer,
+            scopes,
+            clock: None,
+        }
     }
 }
 
@@ -202,3 +211,20 @@ fn resource_wrong_scope() {
 
     setup.test_access_error(wrong_scope);
 }
+
+#[test]
+fn resource_expired_by_clock() {
+    let mut setup = ResourceSetup::new();
+    let expired = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &setup.authtoken),
+    };
+
+    // The token is still known to the issuer but has expired at the time of the endpoint.
+    let clock = ManualClock::new(Utc::now() + Duration::hours(2));
+    let mut endpoint = ResourceEndpoint::new(&mut setup.issuer, &mut setup.resource_scope);
+    endpoint.clock = Some(&clock);
+    let mut resource_flow = ResourceFlow::prepare(endpoint).unwrap();
+    assert!(smol::block_on(resource_flow.execute(expired)).is_err());
+}
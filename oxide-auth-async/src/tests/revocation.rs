@@ -0,0 +1,51 @@
+use crate::endpoint::revocation::RevocationFlow;
+
+use super::{CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::introspection::{TokenEndpoint, TokenSetup};
+
+impl TokenSetup {
+    fn revoke(&mut self, body: &[(&str, &str)], auth: Option<String>) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(body.iter().to_single_value_query()),
+            auth,
+        };
+        let mut flow = RevocationFlow::prepare(TokenEndpoint::new(&self.registrar, &mut self.issuer))
+            .unwrap_or_else(|_| panic!("Not violating any requirements on revocation flow."));
+        smol::block_on(flow.execute(request)).unwrap_or_else(|_| panic!("Expected non-error response"))
+    }
+}
+
+#[test]
+fn revoke_refresh_token() {
+    let mut setup = TokenSetup::new();
+    let access = setup.issued.token.clone();
+    let refresh = setup.issued.refresh.clone().unwrap();
+    let auth = Some(setup.basic_authorization.clone());
+
+    let response = setup.revoke(
+        &[("token", &refresh), ("token_type_hint", "refresh_token")],
+        auth.clone(),
+    );
+    assert_eq!(response.status, Status::Ok);
+    assert!(!setup.description(&refresh).active);
+    assert!(!setup.description(&access).active);
+
+    // Unknown and already revoked tokens are revoked all the same.
+    let response = setup.revoke(&[("token", &refresh)], auth);
+    assert_eq!(response.status, Status::Ok);
+}
+
+#[test]
+fn revoke_unauthenticated() {
+    let mut setup = TokenSetup::new();
+    let access = setup.issued.token.clone();
+
+    let response = setup.revoke(&[("token", &access)], None);
+    assert_eq!(response.status, Status::Unauthorized);
+    assert!(setup.description(&access).active);
+
+    let response = setup.revoke(&[("token", &access), ("client_id", "OtherClient")], None);
+    assert_eq!(response.status, Status::Unauthorized);
+    assert!(setup.description(&access).active);
+}
@@ -0,0 +1,432 @@
+//! An audit trail of changes to clients, with optional soft deletion.
+//!
+//! An [`AuditedRepository`] wraps any client repository and appends an [`AuditEvent`] to an
+//! [`AuditLog`] for every change made through it, recording who changed which client when and
+//! what changed. Events are only ever appended. The log is kept by `KvAuditLog` on any key-value
+//! store and, with their features, by the SQL repositories in an `oauth_client_audit` table.
+//!
+//! The actor is set per request, just like the tenant of a `TenantRepository`:
+//!
+//! ```no_run
+//! # use oxide_auth_db::db_service::audit::{AuditedRepository, KvAuditLog};
+//! # use oxide_auth_db::db_service::kv::{KvClientRepository, MemoryStore};
+//! # use oxide_auth_db::primitives::db_registrar::DBRegistrar;
+//! let store = MemoryStore::new();
+//! let audited = AuditedRepository::new(KvClientRepository::new(store.clone()), KvAuditLog::new(store))
+//!     .soft_delete(true);
+//!
+//! let mut registrar = DBRegistrar::with_repository(audited.acting_as("alice@example.com"));
+//! registrar.delete_client("LocalClient")?;
+//! # Ok::<(), oxide_auth::primitives::registrar::RegistrarError>(())
+//! ```
+//!
+//! With soft deletion, deleting a client disables it and hides it from `list` instead of removing
+//! its record, so it can be inspected and restored later. Soft deleted clients are found from the
+//! log, the last lifecycle event of each client tells whether it is deleted.
+//!
+//! [`AuditedRepository`]: struct.AuditedRepository.html
+//! [`AuditEvent`]: struct.AuditEvent.html
+//! [`AuditLog`]: trait.AuditLog.html
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use oxide_auth::primitives::registrar::{ClientType, EncodedClient};
+use serde::{Deserialize, Serialize};
+
+use crate::db_service::kv::KeyValueBackend;
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+use crate::registration::random_string;
+
+/// A change made to a client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// The client was registered, or registered again over an existing record.
+    Registered,
+
+    /// The record of the client was replaced.
+    Updated,
+
+    /// The client was removed.
+    Deleted,
+
+    /// The client was marked as deleted but kept.
+    SoftDeleted,
+
+    /// A soft deleted client was restored.
+    Restored,
+
+    /// The client was disabled.
+    Disabled,
+
+    /// The client was enabled again.
+    Enabled,
+
+    /// The metadata of the client was replaced.
+    MetadataChanged,
+}
+
+/// One entry of the audit trail.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuditEvent {
+    /// When the change was made.
+    pub at: DateTime<Utc>,
+
+    /// Who made the change, if known.
+    pub actor: Option<String>,
+
+    /// The changed client.
+    pub client_id: String,
+
+    /// What was done to the client.
+    pub action: AuditAction,
+
+    /// The names of the changed fields, for registrations and updates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<String>,
+}
+
+/// An append-only store of audit events.
+pub trait AuditLog {
+    /// Record an event after all previously recorded ones.
+    fn append(&self, event: &AuditEvent) -> anyhow::Result<()>;
+
+    /// The recorded events of one client or, with `None`, of all clients, oldest first.
+    fn events(&self, client_id: Option<&str>) -> anyhow::Result<Vec<AuditEvent>>;
+}
+
+impl<L: AuditLog + ?Sized> AuditLog for Box<L> {
+    fn append(&self, event: &AuditEvent) -> anyhow::Result<()> {
+        (**self).append(event)
+    }
+
+    fn events(&self, client_id: Option<&str>) -> anyhow::Result<Vec<AuditEvent>> {
+        (**self).events(client_id)
+    }
+}
+
+/// An audit log in a key-value store, one key for each event under `audit:`.
+#[derive(Clone, Debug)]
+pub struct KvAuditLog<B: KeyValueBackend> {
+    backend: B,
+}
+
+impl<B: KeyValueBackend> KvAuditLog<B> {
+    /// Record events in the store.
+    pub fn new(backend: B) -> Self {
+        KvAuditLog { backend }
+    }
+}
+
+impl<B: KeyValueBackend> AuditLog for KvAuditLog<B> {
+    fn append(&self, event: &AuditEvent) -> anyhow::Result<()> {
+        let nanos = event.at.timestamp_nanos_opt().unwrap_or_default();
+        // The random suffix keeps events of the same instant apart, the key sorts by time.
+        let key = format!("audit:{:020}:{}", nanos, random_string(6));
+        let value = serde_json::to_vec(event)?;
+        if !self.backend.compare_and_swap(&key, None, Some(&value))? {
+            return Err(anyhow::anyhow!("Audit event {} already exists", key));
+        }
+        Ok(())
+    }
+
+    fn events(&self, client_id: Option<&str>) -> anyhow::Result<Vec<AuditEvent>> {
+        let mut events = Vec::new();
+        for (_, value) in self.backend.scan_prefix("audit:")? {
+            let event: AuditEvent = serde_json::from_slice(&value)?;
+            if client_id.iter().all(|&id| id == event.client_id) {
+                events.push(event);
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Records every change made through it in an audit log.
+#[derive(Clone, Debug)]
+pub struct AuditedRepository<R: OauthClientDBRepository, L: AuditLog> {
+    inner: R,
+    log: L,
+    actor: Option<String>,
+    soft_delete: bool,
+}
+
+/// The names of the fields that differ between two records of a client.
+fn changed_fields(old: Option<&EncodedClient>, new: &EncodedClient) -> Vec<String> {
+    let old = match old {
+        Some(old) => old,
+        None => return Vec::new(),
+    };
+    let mut changes = Vec::new();
+    if old.redirect_uri != new.redirect_uri {
+        changes.push("redirect_uri");
+    }
+    if old.additional_redirect_uris != new.additional_redirect_uris {
+        changes.push("additional_redirect_uris");
+    }
+    if old.default_scope != new.default_scope {
+        changes.push("default_scope");
+    }
+    match (&old.encoded_client, &new.encoded_client) {
+        (ClientType::Public, ClientType::Public) => (),
+        // Hashes are salted, so storing the same secret again is reported as a change as well.
+        (ClientType::Confidential { passdata: old }, ClientType::Confidential { passdata: new }) => {
+            if old != new {
+                changes.push("client_secret");
+            }
+        }
+        _ => changes.push("client_type"),
+    }
+    if old.tls_client_auth != new.tls_client_auth {
+        changes.push("tls_client_auth");
+    }
+    if old.jwks != new.jwks {
+        changes.push("jwks");
+    }
+    if old.jwt_secret != new.jwt_secret {
+        changes.push("jwt_secret");
+    }
+    if old.redirect_matching != new.redirect_matching {
+        changes.push("redirect_matching");
+    }
+    changes.into_iter().map(str::to_owned).collect()
+}
+
+impl<R: OauthClientDBRepository, L: AuditLog> AuditedRepository<R, L> {
+    /// Record the changes to clients of the repository in the log.
+    pub fn new(inner: R, log: L) -> Self {
+        AuditedRepository {
+            inner,
+            log,
+            actor: None,
+            soft_delete: false,
+        }
+    }
+
+    /// Keep deleted clients as disabled records instead of removing them.
+    pub fn soft_delete(mut self, soft_delete: bool) -> Self {
+        self.soft_delete = soft_delete;
+        self
+    }
+
+    /// Record the changes made through the returned repository as made by the actor.
+    pub fn acting_as(&self, actor: &str) -> Self
+    where
+        R: Clone,
+        L: Clone,
+    {
+        AuditedRepository {
+            actor: Some(actor.to_owned()),
+            ..self.clone()
+        }
+    }
+
+    /// The wrapped repository.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// The recorded changes of a client, oldest first.
+    pub fn history(&self, client_id: &str) -> anyhow::Result<Vec<AuditEvent>> {
+        self.log.events(Some(client_id))
+    }
+
+    /// Whether the client was soft deleted and not restored or registered again since.
+    pub fn is_client_deleted(&self, client_id: &str) -> anyhow::Result<bool> {
+        Ok(deleted_clients(self.log.events(Some(client_id))?).contains(client_id))
+    }
+
+    /// Enable a soft deleted client again.
+    pub fn restore_client(&self, client_id: &str) -> anyhow::Result<()> {
+        if !self.is_client_deleted(client_id)? {
+            return Err(anyhow::anyhow!("Client {} is not deleted", client_id));
+        }
+        self.inner.set_client_disabled(client_id, false)?;
+        self.record(client_id, AuditAction::Restored, Vec::new())
+    }
+
+    /// Remove a client for good, even with soft deletion.
+    pub fn purge_client(&self, client_id: &str) -> anyhow::Result<bool> {
+        let deleted = self.inner.delete_client(client_id)?;
+        if deleted {
+            self.record(client_id, AuditAction::Deleted, Vec::new())?;
+        }
+        Ok(deleted)
+    }
+
+    fn record(&self, client_id: &str, action: AuditAction, changes: Vec<String>) -> anyhow::Result<()> {
+        self.log.append(&AuditEvent {
+            at: Utc::now(),
+            actor: self.actor.clone(),
+            client_id: client_id.to_owned(),
+            action,
+            changes,
+        })
+    }
+
+    /// The current record of a client, even if it is disabled.
+    fn current(&self, client_id: &str) -> anyhow::Result<Option<EncodedClient>> {
+        if let Ok(client) = self.inner.find_client_by_id(client_id) {
+            return Ok(Some(client));
+        }
+        Ok(self
+            .inner
+            .list()?
+            .into_iter()
+            .find(|client| client.client_id == client_id))
+    }
+}
+
+/// The clients whose last lifecycle event is a soft deletion.
+fn deleted_clients(events: Vec<AuditEvent>) -> HashSet<String> {
+    let mut deleted = HashSet::new();
+    for event in events {
+        match event.action {
+            AuditAction::SoftDeleted => {
+                deleted.insert(event.client_id);
+            }
+            AuditAction::Registered | AuditAction::Restored | AuditAction::Deleted => {
+                deleted.remove(&event.client_id);
+            }
+            _ => (),
+        }
+    }
+    deleted
+}
+
+impl<R: OauthClientDBRepository, L: AuditLog> OauthClientDBRepository for AuditedRepository<R, L> {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        let clients = self.inner.list()?;
+        if !self.soft_delete {
+            return Ok(clients);
+        }
+        let deleted = deleted_clients(self.log.events(None)?);
+        Ok(clients
+            .into_iter()
+            .filter(|client| !deleted.contains(&client.client_id))
+            .collect())
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        self.inner.find_client_by_id(id)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let old = self.current(&client.client_id)?;
+        let changes = changed_fields(old.as_ref(), &client);
+        let id = client.client_id.clone();
+        // Registering over a soft deleted client brings it back.
+        if self.soft_delete && self.is_client_deleted(&id)? {
+            self.inner.set_client_disabled(&id, false)?;
+        }
+        self.inner.regist_from_encoded_client(client)?;
+        self.record(&id, AuditAction::Registered, changes)
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let old = self.current(&client.client_id)?;
+        let changes = changed_fields(old.as_ref(), &client);
+        let id = client.client_id.clone();
+        self.inner.update_client(client)?;
+        self.record(&id, AuditAction::Updated, changes)
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        if !self.soft_delete {
+            return self.purge_client(id);
+        }
+        if self.current(id)?.is_none() || self.is_client_deleted(id)? {
+            return Ok(false);
+        }
+        self.inner.set_client_disabled(id, true)?;
+        self.record(id, AuditAction::SoftDeleted, Vec::new())?;
+        Ok(true)
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        if !disabled && self.soft_delete && self.is_client_deleted(id)? {
+            return Err(anyhow::anyhow!("Client {} is deleted, restore it instead", id));
+        }
+        self.inner.set_client_disabled(id, disabled)?;
+        let action = if disabled {
+            AuditAction::Disabled
+        } else {
+            AuditAction::Enabled
+        };
+        self.record(id, action, Vec::new())
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        self.inner.is_client_disabled(id)
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        self.inner.find_client_metadata(id)
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        self.inner.store_client_metadata(id, metadata)?;
+        self.record(id, AuditAction::MetadataChanged, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::registrar::{Client, RegisteredUrl, Registrar};
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::primitives::db_registrar::DBRegistrar;
+
+    fn client(scope: &str) -> Client {
+        Client::confidential(
+            "Client",
+            RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            scope.parse().unwrap(),
+            b"secret",
+        )
+    }
+
+    #[test]
+    fn soft_delete_and_history() {
+        let store = MemoryStore::new();
+        let audited = AuditedRepository::new(
+            KvClientRepository::new(store.clone()),
+            KvAuditLog::new(store.clone()),
+        )
+        .soft_delete(true);
+        let mut registrar = DBRegistrar::with_repository(audited.acting_as("admin"));
+
+        registrar.register_client(client("default")).unwrap();
+        registrar.update_client(client("default extra")).unwrap();
+        assert!(registrar.delete_client("Client").unwrap());
+        assert!(!registrar.delete_client("Client").unwrap());
+        assert!(registrar.check("Client", Some(b"secret")).is_err());
+        assert!(registrar.repo.list().unwrap().is_empty());
+        // The record is kept in the wrapped repository.
+        assert_eq!(audited.inner().list().unwrap().len(), 1);
+        assert!(registrar.enable_client("Client").is_err());
+
+        audited.restore_client("Client").unwrap();
+        registrar.check("Client", Some(b"secret")).unwrap();
+        assert!(audited.purge_client("Client").unwrap());
+        assert!(audited.inner().list().unwrap().is_empty());
+
+        let history = audited.history("Client").unwrap();
+        let actions: Vec<_> = history.iter().map(|event| event.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                AuditAction::Registered,
+                AuditAction::Updated,
+                AuditAction::SoftDeleted,
+                AuditAction::Restored,
+                AuditAction::Deleted,
+            ]
+        );
+        assert_eq!(history[1].changes, vec!["default_scope", "client_secret"]);
+        assert_eq!(history[1].actor.as_deref(), Some("admin"));
+        assert_eq!(history[3].actor, None);
+    }
+}
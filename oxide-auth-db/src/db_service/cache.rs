@@ -0,0 +1,301 @@
+//! An in-process cache in front of any client repository.
+//!
+//! Every `check` and `bound_redirect` of a `DBRegistrar` looks up the client. Wrapping its
+//! repository in a [`CachedRepository`] answers repeated lookups of hot clients from memory:
+//!
+//! ```no_run
+//! # use oxide_auth_db::db_service::cache::CachedRepository;
+//! # use oxide_auth_db::db_service::kv::{KvClientRepository, MemoryStore};
+//! # use oxide_auth_db::primitives::db_registrar::DBRegistrar;
+//! let repository = KvClientRepository::new(MemoryStore::new());
+//! let registrar = DBRegistrar::with_repository(
+//!     CachedRepository::new(repository)
+//!         .with_capacity(256)
+//!         .with_ttl(chrono::Duration::seconds(30)),
+//! );
+//! ```
+//!
+//! [`CachedRepository`]: struct.CachedRepository.html
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::registrar::EncodedClient;
+
+use crate::db_service::telemetry::Instrumentation;
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+/// A client repository remembering the most recently used clients for a limited time.
+///
+/// Registering a client through the cache replaces its entry, while updating, deleting or
+/// disabling it drops the entry. Changes made to the underlying repository by other processes
+/// become visible once the entry expired, or after `invalidate`.
+pub struct CachedRepository<R: OauthClientDBRepository> {
+    inner: R,
+    capacity: usize,
+    ttl: Duration,
+    cache: Mutex<Lru>,
+    instrumentation: Option<(String, Arc<dyn Instrumentation + Send + Sync>)>,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    clock: u64,
+}
+
+struct Entry {
+    client: EncodedClient,
+    fetched: DateTime<Utc>,
+    last_used: u64,
+}
+
+impl<R: OauthClientDBRepository> CachedRepository<R> {
+    /// Cache up to 1024 clients for one minute each.
+    pub fn new(inner: R) -> Self {
+        CachedRepository {
+            inner,
+            capacity: 1024,
+            ttl: Duration::minutes(1),
+            cache: Mutex::new(Lru::default()),
+            instrumentation: None,
+        }
+    }
+
+    /// Change how many clients are kept, the least recently used one is dropped first.
+    ///
+    /// A capacity of zero disables the cache.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Change how long a client is used before it is looked up again.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Report whether lookups were answered from the cache under the name.
+    pub fn with_instrumentation<I>(mut self, name: &str, instrumentation: I) -> Self
+    where
+        I: Instrumentation + Send + Sync + 'static,
+    {
+        self.instrumentation = Some((name.to_owned(), Arc::new(instrumentation)));
+        self
+    }
+
+    /// The wrapped repository.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Drop the cached entry of a client.
+    pub fn invalidate(&self, id: &str) {
+        self.lock().entries.remove(id);
+    }
+
+    /// Drop all cached clients.
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Lru> {
+        // The cache is always consistent, a panic while holding the lock does not matter.
+        self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn cached(&self, id: &str) -> Option<EncodedClient> {
+        let mut cache = self.lock();
+        let now = Utc::now();
+        cache.clock += 1;
+        let clock = cache.clock;
+
+        match cache.entries.get_mut(id) {
+            Some(entry) if now < entry.fetched + self.ttl => {
+                entry.last_used = clock;
+                Some(entry.client.clone())
+            }
+            Some(_) => {
+                cache.entries.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn remember(&self, client: &EncodedClient) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut cache = self.lock();
+        cache.clock += 1;
+        let clock = cache.clock;
+
+        if cache.entries.len() >= self.capacity && !cache.entries.contains_key(&client.client_id) {
+            let oldest = cache
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                cache.entries.remove(&oldest);
+            }
+        }
+
+        cache.entries.insert(
+            client.client_id.clone(),
+            Entry {
+                client: client.clone(),
+                fetched: Utc::now(),
+                last_used: clock,
+            },
+        );
+    }
+}
+
+impl<R: OauthClientDBRepository> OauthClientDBRepository for CachedRepository<R> {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        self.inner.list()
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        let cached = self.cached(id);
+        if let Some((name, instrumentation)) = &self.instrumentation {
+            instrumentation.cache_lookup(name, cached.is_some());
+        }
+        if let Some(client) = cached {
+            return Ok(client);
+        }
+
+        let client = self.inner.find_client_by_id(id)?;
+        self.remember(&client);
+        Ok(client)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        // Not cached before the write succeeded, a failed write must not be visible.
+        self.invalidate(&client.client_id);
+        self.inner.regist_from_encoded_client(client.clone())?;
+        // Registering keeps a disabled client disabled, it must not be served from the cache.
+        if !self.inner.is_client_disabled(&client.client_id)? {
+            self.remember(&client);
+        }
+        Ok(())
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.invalidate(&client.client_id);
+        self.inner.update_client(client)
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        self.invalidate(id);
+        self.inner.delete_client(id)
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        self.invalidate(id);
+        self.inner.set_client_disabled(id, disabled)
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        self.inner.is_client_disabled(id)
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        self.inner.find_client_metadata(id)
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        self.inner.store_client_metadata(id, metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    use oxide_auth::primitives::registrar::{Client, RegisteredUrl};
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+
+    struct Counting {
+        inner: KvClientRepository<MemoryStore>,
+        lookups: Cell<usize>,
+    }
+
+    impl OauthClientDBRepository for Counting {
+        fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+            self.inner.list()
+        }
+
+        fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+            self.lookups.set(self.lookups.get() + 1);
+            self.inner.find_client_by_id(id)
+        }
+
+        fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+            self.inner.regist_from_encoded_client(client)
+        }
+    }
+
+    fn client(id: &str, scope: &str) -> EncodedClient {
+        Client::public(
+            id,
+            RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            scope.parse().unwrap(),
+        )
+        .encode(&oxide_auth::primitives::registrar::Argon2::default())
+    }
+
+    fn cached() -> CachedRepository<Counting> {
+        let store = MemoryStore::new();
+        let inner = KvClientRepository::new(store);
+        for id in &["a", "b", "c"] {
+            inner.regist_from_encoded_client(client(id, "default")).unwrap();
+        }
+        CachedRepository::new(Counting {
+            inner,
+            lookups: Cell::new(0),
+        })
+    }
+
+    #[test]
+    fn hits_and_evicts() {
+        let cache = cached().with_capacity(2);
+        cache.find_client_by_id("a").unwrap();
+        cache.find_client_by_id("a").unwrap();
+        assert_eq!(cache.inner().lookups.get(), 1);
+
+        // Evicts "a", the least recently used.
+        cache.find_client_by_id("b").unwrap();
+        cache.find_client_by_id("c").unwrap();
+        cache.find_client_by_id("c").unwrap();
+        assert_eq!(cache.inner().lookups.get(), 3);
+        cache.find_client_by_id("a").unwrap();
+        assert_eq!(cache.inner().lookups.get(), 4);
+    }
+
+    #[test]
+    fn expires_and_invalidates() {
+        let cache = cached().with_ttl(Duration::zero());
+        cache.find_client_by_id("a").unwrap();
+        cache.find_client_by_id("a").unwrap();
+        assert_eq!(cache.inner().lookups.get(), 2);
+
+        let cache = cached();
+        cache.find_client_by_id("a").unwrap();
+        cache.invalidate("a");
+        cache.find_client_by_id("a").unwrap();
+        assert_eq!(cache.inner().lookups.get(), 2);
+
+        cache.regist_from_encoded_client(client("a", "changed")).unwrap();
+        let changed = cache.find_client_by_id("a").unwrap();
+        assert_eq!(changed.default_scope, "changed".parse().unwrap());
+        assert_eq!(cache.inner().lookups.get(), 2);
+    }
+}
@@ -0,0 +1,237 @@
+//! Client registries combined from several repositories.
+//!
+//! A [`ChainedRepository`] looks up clients in its sources in order, for example the static
+//! configuration first, then a key-value store and finally a remote service. The first source
+//! knowing a client answers, so earlier sources shadow clients of the same id in later ones:
+//!
+//! ```no_run
+//! # use oxide_auth_db::db_service::chain::{ChainedRegistrar, ChainedRepository};
+//! # use oxide_auth_db::db_service::kv::{KvClientRepository, MemoryStore};
+//! # use oxide_auth_db::db_service::static_clients::StaticClientRepository;
+//! # let configured = StaticClientRepository::default();
+//! let registry = ChainedRepository::new()
+//!     .then(configured)
+//!     .then(KvClientRepository::new(MemoryStore::new()))
+//!     .write_through(KvClientRepository::with_prefix(MemoryStore::new(), "cached:".into()));
+//! let registrar = ChainedRegistrar::with_repository(registry);
+//! ```
+//!
+//! Clients found in a source are written through to the cache repository, if there is one, and
+//! served from it afterwards. Changes made through the chain remove the client from the cache, but
+//! changes made directly to a source are only seen once the cache forgets the client on its own.
+//!
+//! [`ChainedRepository`]: struct.ChainedRepository.html
+use oxide_auth::primitives::registrar::EncodedClient;
+
+use crate::primitives::db_registrar::{DBRegistrar, OauthClientDBRepository};
+use crate::primitives::metadata::ClientMetadata;
+
+/// A registrar looking up clients in several repositories in order.
+pub type ChainedRegistrar = DBRegistrar<ChainedRepository>;
+
+/// Repositories consulted in order, with an optional write-through cache.
+#[derive(Default)]
+pub struct ChainedRepository {
+    sources: Vec<Box<dyn OauthClientDBRepository>>,
+    cache: Option<Box<dyn OauthClientDBRepository>>,
+}
+
+impl ChainedRepository {
+    /// Create a chain without sources, which knows no clients.
+    pub fn new() -> Self {
+        ChainedRepository::default()
+    }
+
+    /// Consult the repository after all sources added before it.
+    pub fn then<R: OauthClientDBRepository + 'static>(mut self, source: R) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Remember clients found in the sources in the cache repository.
+    pub fn write_through<R: OauthClientDBRepository + 'static>(mut self, cache: R) -> Self {
+        self.cache = Some(Box::new(cache));
+        self
+    }
+
+    /// The first source knowing the client, whether it is enabled or disabled.
+    ///
+    /// A client disabled in a source must not be found in a later one.
+    fn owner(&self, id: &str) -> Option<&dyn OauthClientDBRepository> {
+        self.sources
+            .iter()
+            .find(|source| {
+                source.find_client_by_id(id).is_ok() || source.is_client_disabled(id).unwrap_or(false)
+            })
+            .map(|source| &**source)
+    }
+
+    fn owner_of(&self, id: &str) -> anyhow::Result<&dyn OauthClientDBRepository> {
+        self.owner(id)
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))
+    }
+
+    fn invalidate(&self, id: &str) {
+        if let Some(cache) = &self.cache {
+            if let Err(err) = cache.delete_client(id) {
+                log::warn!("Failed to remove {} from the client cache: {}", id, err);
+            }
+        }
+    }
+}
+
+impl OauthClientDBRepository for ChainedRepository {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        let mut clients: Vec<EncodedClient> = Vec::new();
+        for source in &self.sources {
+            for client in source.list()? {
+                if clients.iter().all(|known| known.client_id != client.client_id) {
+                    clients.push(client);
+                }
+            }
+        }
+        Ok(clients)
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        if let Some(client) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.find_client_by_id(id).ok())
+        {
+            return Ok(client);
+        }
+
+        for source in &self.sources {
+            match source.find_client_by_id(id) {
+                Ok(client) => {
+                    if let Some(cache) = &self.cache {
+                        if let Err(err) = cache.regist_from_encoded_client(client.clone()) {
+                            log::warn!("Failed to cache client {}: {}", id, err);
+                        }
+                    }
+                    return Ok(client);
+                }
+                Err(_) if source.is_client_disabled(id).unwrap_or(false) => {
+                    return Err(anyhow::anyhow!("Client {} is disabled", id));
+                }
+                Err(_) => continue,
+            }
+        }
+
+        Err(anyhow::anyhow!("No client with id {}", id))
+    }
+
+    /// Register the client in the first source accepting it.
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.invalidate(&client.client_id);
+        let mut last_error = None;
+        for source in &self.sources {
+            match source.regist_from_encoded_client(client.clone()) {
+                Ok(()) => return Ok(()),
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No repository to register clients in")))
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.invalidate(&client.client_id);
+        self.owner_of(&client.client_id)?.update_client(client)
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        self.invalidate(id);
+        match self.owner(id) {
+            Some(owner) => owner.delete_client(id),
+            None => Ok(false),
+        }
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        self.invalidate(id);
+        self.owner_of(id)?.set_client_disabled(id, disabled)
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        match self.owner(id) {
+            Some(owner) => owner.is_client_disabled(id),
+            None => Ok(false),
+        }
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        match self.owner(id) {
+            Some(owner) => owner.find_client_metadata(id),
+            None => Ok(None),
+        }
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        self.owner_of(id)?.store_client_metadata(id, metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::registrar::{Client, RedirectMatching, RegisteredUrl, Registrar};
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::db_service::static_clients::{ClientConfig, StaticClientRepository};
+    use crate::primitives::db_registrar::DEFAULT_PASSWORD_POLICY;
+
+    fn client(id: &str) -> Client {
+        Client::public(
+            id,
+            RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn sources_in_order() {
+        let configured = StaticClientRepository::new(vec![ClientConfig {
+            client_id: "Configured".to_owned(),
+            redirect_uri: "https://example.com/configured".to_owned(),
+            additional_redirect_uris: Vec::new(),
+            default_scope: "default".to_owned(),
+            client_secret: None,
+            tls_client_auth: None,
+            jwks: None,
+            jwt_secret: None,
+            redirect_matching: RedirectMatching::Exact,
+        }])
+        .unwrap();
+        let store = KvClientRepository::new(MemoryStore::new());
+        let cache = KvClientRepository::new(MemoryStore::new());
+        let mut registrar = ChainedRegistrar::with_repository(
+            ChainedRepository::new()
+                .then(configured)
+                .then(store.clone())
+                .write_through(cache.clone()),
+        );
+
+        // Registration skips the read-only configuration.
+        registrar.register_client(client("Stored")).unwrap();
+        assert!(store.find_client_by_id("Stored").is_ok());
+        registrar.check("Configured", None).unwrap();
+        registrar.check("Stored", None).unwrap();
+        assert!(cache.find_client_by_id("Configured").is_ok());
+        assert_eq!(registrar.repo.list().unwrap().len(), 2);
+
+        // Configured clients shadow stored ones of the same id.
+        let shadowed = client("Configured").encode(&*DEFAULT_PASSWORD_POLICY);
+        store.regist_from_encoded_client(shadowed).unwrap();
+        assert_eq!(registrar.repo.list().unwrap().len(), 2);
+        let found = registrar.repo.find_client_by_id("Configured").unwrap();
+        assert_eq!(found.redirect_uri.as_str(), "https://example.com/configured");
+
+        registrar.disable_client("Stored").unwrap();
+        assert!(cache.find_client_by_id("Stored").is_err());
+        assert!(registrar.check("Stored", None).is_err());
+        assert!(registrar.repo.is_client_disabled("Stored").unwrap());
+        assert!(registrar.delete_client("Stored").unwrap());
+        assert!(!registrar.delete_client("Unknown").unwrap());
+    }
+}
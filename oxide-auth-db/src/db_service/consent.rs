@@ -0,0 +1,146 @@
+//! Remembering the scopes resource owners approved for clients.
+//!
+//! A [`ConsentStore`] keeps the last approval of each owner for each client, so the consent page
+//! is only shown again for scopes the owner has not approved yet, or once the approval is too old.
+//! Approvals are kept by `KvConsentStore` on any key-value store and, with their features, by the
+//! SQL repositories in an `oauth_consents` table. The `ConsentSolicitor` of the
+//! `primitives::consent` module consults the store before asking the owner.
+//!
+//! [`ConsentStore`]: trait.ConsentStore.html
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::scope::{Scope, ScopePolicy};
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded::byte_serialize;
+
+use crate::db_service::kv::KeyValueBackend;
+
+/// The scope a resource owner approved for a client.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Consent {
+    /// The resource owner who approved the scope.
+    pub owner_id: String,
+
+    /// The client the scope was approved for.
+    pub client_id: String,
+
+    /// The approved scope.
+    pub scope: Scope,
+
+    /// When the owner approved the scope.
+    pub approved_at: DateTime<Utc>,
+}
+
+impl Consent {
+    /// Whether the approval covers the scope and was given less than `validity` before `now`.
+    pub fn allows(&self, scope: &Scope, validity: Duration, now: DateTime<Utc>) -> bool {
+        self.approved_at + validity > now && self.scope.priviledged_to(scope)
+    }
+
+    /// Like `allows`, but the policy decides whether the approved scope covers the scope.
+    pub fn allows_with(
+        &self, policy: &dyn ScopePolicy, scope: &Scope, validity: Duration, now: DateTime<Utc>,
+    ) -> bool {
+        self.approved_at + validity > now && policy.covers(&self.scope, scope)
+    }
+}
+
+/// Stores the approval of each resource owner for each client.
+pub trait ConsentStore {
+    /// The approval of the owner for the client, if one was stored.
+    fn find_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<Option<Consent>>;
+
+    /// Store an approval, replacing a previous one of the same owner for the same client.
+    fn store_consent(&self, consent: &Consent) -> anyhow::Result<()>;
+
+    /// Forget the approval of the owner for the client, returning whether there was one.
+    fn revoke_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<bool>;
+}
+
+impl<S: ConsentStore + ?Sized> ConsentStore for Box<S> {
+    fn find_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<Option<Consent>> {
+        (**self).find_consent(owner_id, client_id)
+    }
+
+    fn store_consent(&self, consent: &Consent) -> anyhow::Result<()> {
+        (**self).store_consent(consent)
+    }
+
+    fn revoke_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<bool> {
+        (**self).revoke_consent(owner_id, client_id)
+    }
+}
+
+/// Approvals in a key-value store, one key for each owner and client under `consent:`.
+#[derive(Clone, Debug)]
+pub struct KvConsentStore<B: KeyValueBackend> {
+    backend: B,
+}
+
+impl<B: KeyValueBackend> KvConsentStore<B> {
+    /// Store approvals in the store.
+    pub fn new(backend: B) -> Self {
+        KvConsentStore { backend }
+    }
+
+    /// Both ids are percent encoded, so no colon of an id can be mistaken for the separator.
+    fn key(owner_id: &str, client_id: &str) -> String {
+        format!(
+            "consent:{}:{}",
+            byte_serialize(owner_id.as_bytes()).collect::<String>(),
+            byte_serialize(client_id.as_bytes()).collect::<String>()
+        )
+    }
+}
+
+impl<B: KeyValueBackend> ConsentStore for KvConsentStore<B> {
+    fn find_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<Option<Consent>> {
+        match self.backend.get(&Self::key(owner_id, client_id))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_consent(&self, consent: &Consent) -> anyhow::Result<()> {
+        let value = serde_json::to_vec(consent)?;
+        self.backend
+            .set(&Self::key(&consent.owner_id, &consent.client_id), &value, None)
+    }
+
+    fn revoke_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<bool> {
+        self.backend.delete(&Self::key(owner_id, client_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::db_service::kv::MemoryStore;
+
+    #[test]
+    fn remembers_consent() {
+        let store = KvConsentStore::new(MemoryStore::new());
+        let consent = Consent {
+            owner_id: "alice:admin".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "read write".parse().unwrap(),
+            approved_at: Utc::now(),
+        };
+        store.store_consent(&consent).unwrap();
+        assert_eq!(store.find_consent("alice", "admin:Client").unwrap(), None);
+        let found = store.find_consent("alice:admin", "Client").unwrap().unwrap();
+        assert_eq!(found, consent);
+
+        let now = Utc::now();
+        assert!(found.allows(&"read".parse().unwrap(), Duration::days(1), now));
+        assert!(!found.allows(&"read admin".parse().unwrap(), Duration::days(1), now));
+        assert!(!found.allows(
+            &"read".parse().unwrap(),
+            Duration::days(1),
+            now + Duration::days(2)
+        ));
+
+        assert!(store.revoke_consent("alice:admin", "Client").unwrap());
+        assert!(!store.revoke_consent("alice:admin", "Client").unwrap());
+    }
+}
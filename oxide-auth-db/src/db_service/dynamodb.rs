@@ -0,0 +1,297 @@
+//! Client storage in Amazon DynamoDB, for serverless deployments.
+//!
+//! The SDK is asynchronous only, so the DynamoDB backed primitives implement the traits of
+//! `oxide-auth-async` instead of the blocking ones. The repository and the primitives share one
+//! `aws_sdk_dynamodb::Client`, configured by the application, and three tables:
+//!
+//! * the clients table with the partition key `client_id`, the JSON encoded client in `client`, a
+//!   `disabled` flag and the JSON encoded metadata in `metadata`,
+//! * the codes table with the partition key `code`, the stored grant in `grant` and its expiry in
+//!   seconds since the epoch in `expires_at`,
+//! * the tokens table with the partition key `token`, holding one item for each access and each
+//!   refresh token. Both items of a pair name each other in `pair` and carry the stored grant, the
+//!   `owner_id` and the `client_id`. Refresh items remember the end of their session in
+//!   `session_expires_at`.
+//!
+//! Time to live should be enabled on the `expires_at` attribute of the codes and tokens tables.
+//! Deployments usually create the tables with their infrastructure, [`create_tables`] does the
+//! same for development against DynamoDB Local.
+//!
+//! [`create_tables`]: struct.DynamoClientRepository.html#method.create_tables
+use crate::db_service::health::Health;
+use crate::primitives::async_registrar::AsyncOauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, AttributeValue, BillingMode, KeySchemaElement, KeyType, ReturnValue,
+    ScalarAttributeType, TimeToLiveSpecification,
+};
+use aws_sdk_dynamodb::Client;
+use chrono::Utc;
+use oxide_auth::primitives::registrar::EncodedClient;
+
+/// A DynamoDB item.
+pub(crate) type Item = HashMap<String, AttributeValue>;
+
+/// The names of the tables used by the DynamoDB backend.
+#[derive(Clone, Debug)]
+pub struct DynamoTables {
+    /// The table of registered clients.
+    pub clients: String,
+
+    /// The table of unredeemed authorization codes.
+    pub codes: String,
+
+    /// The table of issued access and refresh tokens.
+    pub tokens: String,
+}
+
+impl Default for DynamoTables {
+    fn default() -> Self {
+        DynamoTables {
+            clients: "oauth_clients".to_owned(),
+            codes: "oauth_codes".to_owned(),
+            tokens: "oauth_tokens".to_owned(),
+        }
+    }
+}
+
+/// DynamoDB datasource to Client entries.
+#[derive(Clone, Debug)]
+pub struct DynamoClientRepository {
+    client: Client,
+    tables: DynamoTables,
+}
+
+/// Read a string attribute of an item.
+pub(crate) fn string<'a>(item: &'a Item, name: &str) -> Option<&'a str> {
+    item.get(name)?.as_s().ok().map(String::as_str)
+}
+
+impl DynamoClientRepository {
+    /// Use the tables with their default names.
+    pub fn new(client: Client) -> Self {
+        Self::with_tables(client, DynamoTables::default())
+    }
+
+    /// Use tables with custom names.
+    pub fn with_tables(client: Client, tables: DynamoTables) -> Self {
+        DynamoClientRepository { client, tables }
+    }
+
+    /// The SDK client of this repository, to be shared with `DynamoAuthorizer` and `DynamoIssuer`.
+    pub fn get_client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// The names of the tables.
+    pub fn tables(&self) -> &DynamoTables {
+        &self.tables
+    }
+
+    /// Create the three tables with on-demand capacity and enable their time to live.
+    pub async fn create_tables(&self) -> anyhow::Result<()> {
+        let tables = [
+            (&self.tables.clients, "client_id", false),
+            (&self.tables.codes, "code", true),
+            (&self.tables.tokens, "token", true),
+        ];
+
+        for (table, key, expiring) in tables {
+            self.client
+                .create_table()
+                .table_name(table)
+                .key_schema(
+                    KeySchemaElement::builder()
+                        .attribute_name(key)
+                        .key_type(KeyType::Hash)
+                        .build()?,
+                )
+                .attribute_definitions(
+                    AttributeDefinition::builder()
+                        .attribute_name(key)
+                        .attribute_type(ScalarAttributeType::S)
+                        .build()?,
+                )
+                .billing_mode(BillingMode::PayPerRequest)
+                .send()
+                .await?;
+
+            if expiring {
+                self.client
+                    .update_time_to_live()
+                    .table_name(table)
+                    .time_to_live_specification(
+                        TimeToLiveSpecification::builder()
+                            .attribute_name("expires_at")
+                            .enabled(true)
+                            .build()?,
+                    )
+                    .send()
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Probe the availability of the clients table.
+    pub async fn health_check(&self) -> Health {
+        let start = Utc::now();
+        let result = self
+            .client
+            .describe_table()
+            .table_name(&self.tables.clients)
+            .send()
+            .await;
+        Health {
+            store: "dynamodb",
+            latency: Utc::now() - start,
+            error: result.err().map(|err| err.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncOauthClientDBRepository for DynamoClientRepository {
+    async fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        let items: Vec<Item> = self
+            .client
+            .scan()
+            .table_name(&self.tables.clients)
+            .into_paginator()
+            .items()
+            .send()
+            .collect::<Result<_, _>>()
+            .await?;
+        items.iter().map(decode_client).collect()
+    }
+
+    async fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.tables.clients)
+            .key("client_id", AttributeValue::S(id.to_owned()))
+            .consistent_read(true)
+            .send()
+            .await?;
+        let item = output
+            .item()
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        if is_disabled(item) {
+            return Err(anyhow::anyhow!("Client {} is disabled", id));
+        }
+        decode_client(item)
+    }
+
+    async fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        // Only the client is replaced, a disabled client stays disabled.
+        let encoded = serde_json::to_string(&client)?;
+        self.client
+            .update_item()
+            .table_name(&self.tables.clients)
+            .key("client_id", AttributeValue::S(client.client_id))
+            .update_expression("SET client = :client")
+            .expression_attribute_values(":client", AttributeValue::S(encoded))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let encoded = serde_json::to_string(&client)?;
+        self.client
+            .update_item()
+            .table_name(&self.tables.clients)
+            .key("client_id", AttributeValue::S(client.client_id))
+            .update_expression("SET client = :client")
+            .condition_expression("attribute_exists(client_id)")
+            .expression_attribute_values(":client", AttributeValue::S(encoded))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        let output = self
+            .client
+            .delete_item()
+            .table_name(&self.tables.clients)
+            .key("client_id", AttributeValue::S(id.to_owned()))
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await?;
+        Ok(output.attributes().is_some())
+    }
+
+    async fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        self.client
+            .update_item()
+            .table_name(&self.tables.clients)
+            .key("client_id", AttributeValue::S(id.to_owned()))
+            .update_expression("SET disabled = :disabled")
+            .condition_expression("attribute_exists(client_id)")
+            .expression_attribute_values(":disabled", AttributeValue::Bool(disabled))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.tables.clients)
+            .key("client_id", AttributeValue::S(id.to_owned()))
+            .consistent_read(true)
+            .send()
+            .await?;
+        let item = output
+            .item()
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        Ok(is_disabled(item))
+    }
+
+    async fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.tables.clients)
+            .key("client_id", AttributeValue::S(id.to_owned()))
+            .send()
+            .await?;
+        match output.item().and_then(|item| string(item, "metadata")) {
+            Some(metadata) => Ok(Some(serde_json::from_str(metadata)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        self.client
+            .update_item()
+            .table_name(&self.tables.clients)
+            .key("client_id", AttributeValue::S(id.to_owned()))
+            .update_expression("SET metadata = :metadata")
+            .condition_expression("attribute_exists(client_id)")
+            .expression_attribute_values(
+                ":metadata",
+                AttributeValue::S(serde_json::to_string(metadata)?),
+            )
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+fn is_disabled(item: &Item) -> bool {
+    matches!(item.get("disabled"), Some(AttributeValue::Bool(true)))
+}
+
+fn decode_client(item: &Item) -> anyhow::Result<EncodedClient> {
+    let client = string(item, "client").ok_or_else(|| anyhow::anyhow!("Client item without data"))?;
+    Ok(serde_json::from_str(client)?)
+}
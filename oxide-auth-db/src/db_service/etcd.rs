@@ -0,0 +1,325 @@
+//! A strongly consistent key-value store on an etcd cluster.
+//!
+//! [`EtcdBackend`] implements `KeyValueBackend` through the JSON gateway of the etcd v3 API, so it
+//! carries clients, codes and tokens with `KvClientRepository`, `DBAuthorizer` and `DBIssuer`:
+//!
+//! ```no_run
+//! # use oxide_auth::primitives::generator::RandomGenerator;
+//! # use oxide_auth_db::db_service::etcd::EtcdBackend;
+//! # use oxide_auth_db::db_service::kv::KvClientRepository;
+//! # use oxide_auth_db::primitives::db_registrar::DBRegistrar;
+//! # use oxide_auth_db::primitives::kv::{DBAuthorizer, DBIssuer};
+//! let etcd = EtcdBackend::new("http://localhost:2379")?;
+//! let registrar = DBRegistrar::with_repository(KvClientRepository::new(etcd.clone()));
+//! let authorizer = DBAuthorizer::new(etcd.clone(), RandomGenerator::new(16));
+//! let issuer = DBIssuer::new(etcd, RandomGenerator::new(16));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Reads are linearizable and swaps are etcd transactions comparing the current value, takes
+//! delete a key and return its previous value in one request. Redeeming a code or a refresh
+//! token therefore succeeds exactly once across all instances, and no concurrent refresh can
+//! overwrite the pair issued by another. Entries with an expiry are attached to a lease of the
+//! same time to live, so etcd removes them on its own.
+//!
+//! [`EtcdBackend`]: struct.EtcdBackend.html
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::db_service::health::Health;
+use crate::db_service::kv::KeyValueBackend;
+use crate::db_service::pool::RetryPolicy;
+
+/// A key-value store on an etcd cluster, reached through its JSON gateway.
+#[derive(Clone, Debug)]
+pub struct EtcdBackend {
+    client: Client,
+    endpoint: String,
+    authorization: Option<String>,
+    retry: RetryPolicy,
+}
+
+/// A key with its value, as returned by the gateway.
+#[derive(Deserialize)]
+struct KeyValue {
+    key: String,
+    #[serde(default)]
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct RangeResponse {
+    #[serde(default)]
+    kvs: Vec<KeyValue>,
+}
+
+#[derive(Deserialize)]
+struct DeleteRangeResponse {
+    #[serde(default)]
+    deleted: Option<String>,
+    #[serde(default)]
+    prev_kvs: Vec<KeyValue>,
+}
+
+#[derive(Deserialize)]
+struct TxnResponse {
+    // Omitted by the gateway when false, as are all default values.
+    #[serde(default)]
+    succeeded: bool,
+}
+
+#[derive(Deserialize)]
+struct LeaseGrantResponse {
+    #[serde(rename = "ID")]
+    id: String,
+}
+
+#[derive(Serialize)]
+struct Put<'a> {
+    key: &'a str,
+    value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lease: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    ignore_lease: bool,
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::encode(bytes)
+}
+
+fn decode(encoded: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(base64::decode(encoded)?)
+}
+
+/// The end of the key range of all keys starting with the prefix.
+fn prefix_end(prefix: &str) -> Vec<u8> {
+    let mut end = prefix.as_bytes().to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // All keys, etcd reads a range end of a single zero byte as no upper bound.
+    vec![0]
+}
+
+impl EtcdBackend {
+    /// Use the cluster at the endpoint, such as `http://localhost:2379`.
+    pub fn new(endpoint: &str) -> anyhow::Result<Self> {
+        Ok(EtcdBackend {
+            client: Client::builder().build()?,
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+            authorization: None,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    /// Send the token of an authenticated etcd user with each request.
+    pub fn with_authorization(mut self, token: String) -> Self {
+        self.authorization = Some(token);
+        self
+    }
+
+    /// Retry reads and plain writes with the policy, swaps and takes are never retried.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Probe the availability of the cluster.
+    pub fn health_check(&self) -> Health {
+        Health::probe("etcd", || {
+            self.call::<Value>("/v3/maintenance/status", &json!({}))?;
+            Ok(())
+        })
+    }
+
+    fn call<T: DeserializeOwned>(&self, path: &str, body: &impl Serialize) -> anyhow::Result<T> {
+        let mut request = self
+            .client
+            .post(format!("{}{}", self.endpoint, path))
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_vec(body)?);
+        if let Some(token) = &self.authorization {
+            request = request.header("Authorization", token);
+        }
+        let response = request.send()?;
+        let status = response.status();
+        let body = response.bytes()?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "etcd answered {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// A lease expiring at the instant, at least one second from now.
+    fn lease(&self, expires_at: DateTime<Utc>) -> anyhow::Result<String> {
+        let ttl = (expires_at - Utc::now()).num_seconds().max(1);
+        let lease: LeaseGrantResponse = self.call("/v3/lease/grant", &json!({ "TTL": ttl }))?;
+        Ok(lease.id)
+    }
+}
+
+impl KeyValueBackend for EtcdBackend {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let range: RangeResponse = self
+            .retry
+            .run(|| self.call("/v3/kv/range", &json!({ "key": encode(key.as_bytes()) })))?;
+        range.kvs.first().map(|kv| decode(&kv.value)).transpose()
+    }
+
+    fn set(&self, key: &str, value: &[u8], expires_at: Option<DateTime<Utc>>) -> anyhow::Result<()> {
+        self.retry.run(|| {
+            let put = Put {
+                key: &encode(key.as_bytes()),
+                value: encode(value),
+                lease: expires_at.map(|at| self.lease(at)).transpose()?,
+                ignore_lease: false,
+            };
+            self.call::<Value>("/v3/kv/put", &put)?;
+            Ok(())
+        })
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        let deleted: DeleteRangeResponse = self
+            .retry
+            .run(|| self.call("/v3/kv/deleterange", &json!({ "key": encode(key.as_bytes()) })))?;
+        Ok(deleted.deleted.is_some_and(|count| count != "0"))
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        // etcd requires a key, the smallest one stands for the empty prefix.
+        let start: &[u8] = if prefix.is_empty() {
+            &[0]
+        } else {
+            prefix.as_bytes()
+        };
+        let request = json!({
+            "key": encode(start),
+            "range_end": encode(&prefix_end(prefix)),
+        });
+        let range: RangeResponse = self.retry.run(|| self.call("/v3/kv/range", &request))?;
+        // etcd returns the range sorted by key.
+        range
+            .kvs
+            .iter()
+            .map(|kv| Ok((String::from_utf8(decode(&kv.key)?)?, decode(&kv.value)?)))
+            .collect()
+    }
+
+    fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool> {
+        let encoded_key = encode(key.as_bytes());
+        let compare = match old {
+            Some(old) => json!({
+                "key": encoded_key,
+                "target": "VALUE",
+                "result": "EQUAL",
+                "value": encode(old),
+            }),
+            // A key that does not exist has no create revision.
+            None => json!({
+                "key": encoded_key,
+                "target": "CREATE",
+                "result": "EQUAL",
+                "create_revision": "0",
+            }),
+        };
+        let success = match new {
+            // Replacing a value keeps the lease, and with it the expiry, of the key.
+            Some(new) => json!({
+                "request_put": Put {
+                    key: &encoded_key,
+                    value: encode(new),
+                    lease: None,
+                    ignore_lease: old.is_some(),
+                },
+            }),
+            None => json!({ "request_delete_range": { "key": encoded_key } }),
+        };
+        let txn: TxnResponse = self.call(
+            "/v3/kv/txn",
+            &json!({ "compare": [compare], "success": [success] }),
+        )?;
+        Ok(txn.succeeded)
+    }
+
+    fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let deleted: DeleteRangeResponse = self.call(
+            "/v3/kv/deleterange",
+            &json!({ "key": encode(key.as_bytes()), "prev_kv": true }),
+        )?;
+        deleted.prev_kvs.first().map(|kv| decode(&kv.value)).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::{Extensions, Grant};
+    use oxide_auth::primitives::issuer::Issuer;
+
+    use crate::primitives::kv::DBIssuer;
+
+    /// Tests run against the cluster in `OXIDE_AUTH_ETCD_URL` and are skipped when it is unset:
+    ///
+    /// `OXIDE_AUTH_ETCD_URL=http://localhost:2379 cargo test --features with-etcd`
+    fn backend() -> Option<EtcdBackend> {
+        let url = std::env::var("OXIDE_AUTH_ETCD_URL").ok()?;
+        Some(EtcdBackend::new(&url).unwrap())
+    }
+
+    #[test]
+    fn prefix_ranges() {
+        assert_eq!(prefix_end("code:"), b"code;".to_vec());
+        assert_eq!(prefix_end(""), vec![0]);
+    }
+
+    #[test]
+    fn swap_and_take() {
+        let etcd = match backend() {
+            Some(etcd) => etcd,
+            None => return,
+        };
+        assert!(etcd.health_check().is_healthy());
+        etcd.delete("oxide-test:key").unwrap();
+        assert!(etcd
+            .compare_and_swap("oxide-test:key", None, Some(b"one"))
+            .unwrap());
+        assert!(!etcd
+            .compare_and_swap("oxide-test:key", None, Some(b"two"))
+            .unwrap());
+        assert!(etcd
+            .compare_and_swap("oxide-test:key", Some(b"one"), Some(b"two"))
+            .unwrap());
+        assert_eq!(etcd.scan_prefix("oxide-test:").unwrap().len(), 1);
+        assert_eq!(etcd.take("oxide-test:key").unwrap(), Some(b"two".to_vec()));
+        assert_eq!(etcd.take("oxide-test:key").unwrap(), None);
+
+        let mut issuer = DBIssuer::new(etcd, RandomGenerator::new(16));
+        let grant = Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + chrono::Duration::minutes(10),
+            extensions: Extensions::new(),
+        };
+        let issued = issuer.issue(grant.clone()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        issuer.refresh(&refresh, grant.clone()).unwrap();
+        assert!(issuer.refresh(&refresh, grant).is_err());
+    }
+}
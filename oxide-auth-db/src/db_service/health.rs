@@ -0,0 +1,52 @@
+//! Reporting the availability of a store, for readiness probes.
+use chrono::{Duration, Utc};
+
+/// The outcome of a round-trip to a store.
+#[derive(Clone, Debug)]
+pub struct Health {
+    /// A short name of the probed store, such as `sqlite` or `redis`.
+    pub store: &'static str,
+
+    /// How long the round-trip took.
+    pub latency: Duration,
+
+    /// The failure of the round-trip, `None` if the store answered.
+    pub error: Option<String>,
+}
+
+impl Health {
+    /// Run a probe against the store and record its outcome.
+    pub fn probe<F>(store: &'static str, probe: F) -> Self
+    where
+        F: FnOnce() -> anyhow::Result<()>,
+    {
+        let start = Utc::now();
+        let error = probe().err().map(|err| err.to_string());
+        Health {
+            store,
+            latency: Utc::now() - start,
+            error,
+        }
+    }
+
+    /// Whether the store answered.
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_outcome() {
+        let health = Health::probe("memory", || Ok(()));
+        assert!(health.is_healthy());
+        assert_eq!(health.store, "memory");
+
+        let health = Health::probe("memory", || Err(anyhow::anyhow!("unreachable")));
+        assert!(!health.is_healthy());
+        assert_eq!(health.error.as_deref(), Some("unreachable"));
+    }
+}
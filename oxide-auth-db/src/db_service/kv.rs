@@ -0,0 +1,487 @@
+//! A storage-agnostic key-value interface for the generic `Kv*` primitives.
+//!
+//! A new key-value store only needs to implement [`KeyValueBackend`] to be usable with
+//! `KvClientRepository`, `KvAuthorizer` and `KvIssuer`. Records are stored as JSON under prefixed
+//! keys, the primitives never rely on the store to expire them. Backends with native expiry may
+//! still drop entries once `expires_at` passed, which saves calling `purge_expired`.
+//!
+//! Stores with an asynchronous client implement [`AsyncKeyValueBackend`] instead, for use with
+//! `AsyncKvAuthorizer` and `AsyncKvIssuer`.
+//!
+//! [`KeyValueBackend`]: trait.KeyValueBackend.html
+//! [`AsyncKeyValueBackend`]: trait.AsyncKeyValueBackend.html
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "async")]
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use oxide_auth::primitives::registrar::EncodedClient;
+
+/// The operations the generic primitives need from a key-value store.
+pub trait KeyValueBackend {
+    /// Read the value of a key.
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Write the value of a key, which is no longer needed after `expires_at`.
+    fn set(&self, key: &str, value: &[u8], expires_at: Option<DateTime<Utc>>) -> anyhow::Result<()>;
+
+    /// Remove a key, returning whether it existed.
+    fn delete(&self, key: &str) -> anyhow::Result<bool>;
+
+    /// All keys starting with the prefix, with their values.
+    fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>>;
+
+    /// Atomically replace the value of a key if it currently is `old`.
+    ///
+    /// `None` stands for a missing key on both sides, so this can also insert or remove. Returns
+    /// whether the value was replaced.
+    fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool>;
+
+    /// Atomically read and remove a key.
+    ///
+    /// Only one of several concurrent callers receives the value.
+    fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        loop {
+            let value = match self.get(key)? {
+                None => return Ok(None),
+                Some(value) => value,
+            };
+            if self.compare_and_swap(key, Some(&value), None)? {
+                return Ok(Some(value));
+            }
+        }
+    }
+}
+
+impl<B: KeyValueBackend + ?Sized> KeyValueBackend for Arc<B> {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        (**self).get(key)
+    }
+
+    fn set(&self, key: &str, value: &[u8], expires_at: Option<DateTime<Utc>>) -> anyhow::Result<()> {
+        (**self).set(key, value, expires_at)
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        (**self).delete(key)
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        (**self).scan_prefix(prefix)
+    }
+
+    fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool> {
+        (**self).compare_and_swap(key, old, new)
+    }
+
+    fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        (**self).take(key)
+    }
+}
+
+/// The operations of `KeyValueBackend` for stores with an asynchronous client.
+///
+/// Every `KeyValueBackend` is also an `AsyncKeyValueBackend`, its operations complete immediately.
+#[cfg(feature = "async")]
+#[async_trait]
+pub trait AsyncKeyValueBackend {
+    /// Read the value of a key.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Write the value of a key, which is no longer needed after `expires_at`.
+    async fn set(
+        &self, key: &str, value: &[u8], expires_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()>;
+
+    /// Remove a key, returning whether it existed.
+    async fn delete(&self, key: &str) -> anyhow::Result<bool>;
+
+    /// All keys starting with the prefix, with their values.
+    async fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>>;
+
+    /// Atomically replace the value of a key if it currently is `old`.
+    async fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool>;
+
+    /// Atomically read and remove a key.
+    async fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        loop {
+            let value = match self.get(key).await? {
+                None => return Ok(None),
+                Some(value) => value,
+            };
+            if self.compare_and_swap(key, Some(&value), None).await? {
+                return Ok(Some(value));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<B: KeyValueBackend + Sync + ?Sized> AsyncKeyValueBackend for B {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        KeyValueBackend::get(self, key)
+    }
+
+    async fn set(
+        &self, key: &str, value: &[u8], expires_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        KeyValueBackend::set(self, key, value, expires_at)
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        KeyValueBackend::delete(self, key)
+    }
+
+    async fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        KeyValueBackend::scan_prefix(self, prefix)
+    }
+
+    async fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool> {
+        KeyValueBackend::compare_and_swap(self, key, old, new)
+    }
+
+    async fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        KeyValueBackend::take(self, key)
+    }
+}
+
+/// A key-value store in the memory of the process, for tests and single instance deployments.
+///
+/// Clones share the same entries.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    entries: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    fn entries(&self) -> anyhow::Result<std::sync::MutexGuard<'_, BTreeMap<String, Vec<u8>>>> {
+        self.entries
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Memory store poisoned"))
+    }
+}
+
+impl KeyValueBackend for MemoryStore {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.entries()?.get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &[u8], _: Option<DateTime<Utc>>) -> anyhow::Result<()> {
+        self.entries()?.insert(key.to_owned(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        Ok(self.entries()?.remove(key).is_some())
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        Ok(self
+            .entries()?
+            .range(prefix.to_owned()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool> {
+        let mut entries = self.entries()?;
+        if entries.get(key).map(Vec::as_slice) != old {
+            return Ok(false);
+        }
+        match new {
+            Some(new) => entries.insert(key.to_owned(), new.to_vec()),
+            None => entries.remove(key),
+        };
+        Ok(true)
+    }
+
+    fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.entries()?.remove(key))
+    }
+}
+
+#[cfg(feature = "with-sled")]
+impl KeyValueBackend for sled::Tree {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(sled::Tree::get(self, key)?.map(|value| value.to_vec()))
+    }
+
+    fn set(&self, key: &str, value: &[u8], _: Option<DateTime<Utc>>) -> anyhow::Result<()> {
+        self.insert(key, value)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        Ok(self.remove(key)?.is_some())
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        sled::Tree::scan_prefix(self, prefix)
+            .map(|entry| {
+                let (key, value) = entry?;
+                Ok((String::from_utf8(key.to_vec())?, value.to_vec()))
+            })
+            .collect()
+    }
+
+    fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool> {
+        Ok(sled::Tree::compare_and_swap(self, key, old, new)?.is_ok())
+    }
+
+    fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.remove(key)?.map(|value| value.to_vec()))
+    }
+}
+
+/// Client entries in any key-value store, stored as JSON under a prefixed client id.
+///
+/// Disabled clients are marked by an additional key, under the client prefix with `disabled-` in
+/// front of it.
+#[derive(Clone, Debug)]
+pub struct KvClientRepository<B: KeyValueBackend> {
+    backend: B,
+    client_prefix: String,
+    disabled_prefix: String,
+    metadata_prefix: String,
+}
+
+impl<B: KeyValueBackend> KvClientRepository<B> {
+    /// Store clients under `client:`.
+    pub fn new(backend: B) -> Self {
+        Self::with_prefix(backend, "client:".to_owned())
+    }
+
+    /// Store clients under a custom prefix.
+    pub fn with_prefix(backend: B, client_prefix: String) -> Self {
+        KvClientRepository {
+            backend,
+            disabled_prefix: format!("disabled-{}", client_prefix),
+            metadata_prefix: format!("metadata-{}", client_prefix),
+            client_prefix,
+        }
+    }
+
+    /// The store of this repository.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    fn client_key(&self, id: &str) -> String {
+        format!("{}{}", self.client_prefix, id)
+    }
+
+    fn disabled_key(&self, id: &str) -> String {
+        format!("{}{}", self.disabled_prefix, id)
+    }
+
+    fn metadata_key(&self, id: &str) -> String {
+        format!("{}{}", self.metadata_prefix, id)
+    }
+}
+
+impl<B: KeyValueBackend> OauthClientDBRepository for KvClientRepository<B> {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        self.backend
+            .scan_prefix(&self.client_prefix)?
+            .into_iter()
+            .map(|(_, client)| Ok(serde_json::from_slice(&client)?))
+            .collect()
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        if self.is_client_disabled(id)? {
+            return Err(anyhow::anyhow!("Client {} is disabled", id));
+        }
+        let client = self
+            .backend
+            .get(&self.client_key(id))?
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        Ok(serde_json::from_slice(&client)?)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let key = self.client_key(&client.client_id);
+        self.backend.set(&key, &serde_json::to_vec(&client)?, None)
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let key = self.client_key(&client.client_id);
+        let old = self
+            .backend
+            .get(&key)?
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", client.client_id))?;
+        // Fails instead of recreating a client that was deleted in the meantime.
+        if !self
+            .backend
+            .compare_and_swap(&key, Some(&old), Some(&serde_json::to_vec(&client)?))?
+        {
+            return Err(anyhow::anyhow!(
+                "Client {} changed concurrently",
+                client.client_id
+            ));
+        }
+        Ok(())
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        self.backend.delete(&self.disabled_key(id))?;
+        self.backend.delete(&self.metadata_key(id))?;
+        self.backend.delete(&self.client_key(id))
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        if self.backend.get(&self.client_key(id))?.is_none() {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        if disabled {
+            self.backend.set(&self.disabled_key(id), b"1", None)
+        } else {
+            self.backend.delete(&self.disabled_key(id)).map(drop)
+        }
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        Ok(self.backend.get(&self.disabled_key(id))?.is_some())
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        match self.backend.get(&self.metadata_key(id))? {
+            Some(metadata) => Ok(Some(serde_json::from_slice(&metadata)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        if self.backend.get(&self.client_key(id))?.is_none() {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        self.backend
+            .set(&self.metadata_key(id), &serde_json::to_vec(metadata)?, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyValueBackend, KvClientRepository, MemoryStore};
+    use crate::primitives::db_registrar::{DBRegistrar, OauthClientDBRepository};
+    use crate::primitives::metadata::ClientMetadata;
+    use oxide_auth::primitives::registrar::{Client, RegisteredUrl, Registrar};
+
+    #[test]
+    fn memory_store_operations() {
+        let store = MemoryStore::new();
+        store.set("a:1", b"one", None).unwrap();
+        store.set("a:2", b"two", None).unwrap();
+        store.set("b:1", b"other", None).unwrap();
+
+        let keys: Vec<_> = store
+            .scan_prefix("a:")
+            .unwrap()
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec!["a:1", "a:2"]);
+
+        assert!(!store.compare_and_swap("a:1", Some(b"two"), None).unwrap());
+        assert!(store.compare_and_swap("a:1", Some(b"one"), Some(b"uno")).unwrap());
+        assert!(store.compare_and_swap("a:3", None, Some(b"three")).unwrap());
+
+        assert_eq!(store.take("a:1").unwrap().as_deref(), Some(&b"uno"[..]));
+        assert_eq!(store.take("a:1").unwrap(), None);
+        assert!(store.delete("a:2").unwrap());
+        assert!(!store.delete("a:2").unwrap());
+    }
+
+    #[test]
+    fn manage_clients() {
+        let repository = KvClientRepository::new(MemoryStore::new());
+        let mut registrar = DBRegistrar::with_repository(repository);
+        let client = Client::confidential(
+            "KvClient",
+            RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+            b"secret",
+        );
+        assert!(registrar.update_client(client.clone()).is_err());
+        registrar.register_client(client).unwrap();
+
+        let secret = registrar.rotate_secret("KvClient").unwrap();
+        assert!(registrar.check("KvClient", Some(b"secret")).is_err());
+        registrar.check("KvClient", Some(secret.as_bytes())).unwrap();
+
+        registrar.disable_client("KvClient").unwrap();
+        assert!(registrar.check("KvClient", Some(secret.as_bytes())).is_err());
+        // The marker of a disabled client is not listed as a client.
+        assert_eq!(registrar.repo.list().unwrap().len(), 1);
+        registrar.enable_client("KvClient").unwrap();
+        registrar.check("KvClient", Some(secret.as_bytes())).unwrap();
+
+        assert!(registrar.delete_client("KvClient").unwrap());
+        assert!(!registrar.delete_client("KvClient").unwrap());
+        assert!(registrar.rotate_secret("KvClient").is_err());
+    }
+
+    #[test]
+    fn client_metadata() {
+        let repository = KvClientRepository::new(MemoryStore::new());
+        let mut registrar = DBRegistrar::with_repository(repository);
+        let metadata = ClientMetadata {
+            client_name: Some("Example".to_owned()),
+            contacts: vec!["admin@example.com".to_owned()],
+            ..ClientMetadata::default()
+        };
+        assert!(registrar
+            .set_client_metadata("KvClient", metadata.clone())
+            .is_err());
+
+        let client = Client::public(
+            "KvClient",
+            RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+        );
+        registrar.register_client(client).unwrap();
+        assert_eq!(registrar.client_metadata("KvClient").unwrap(), None);
+
+        registrar
+            .set_client_metadata("KvClient", metadata.clone())
+            .unwrap();
+        let stored = registrar.client_metadata("KvClient").unwrap().unwrap();
+        assert_eq!(stored.client_name, metadata.client_name);
+        assert_eq!(stored.contacts, metadata.contacts);
+        assert!(stored.created_at.is_some());
+
+        registrar
+            .set_client_metadata("KvClient", ClientMetadata::default())
+            .unwrap();
+        let updated = registrar.client_metadata("KvClient").unwrap().unwrap();
+        assert_eq!(updated.client_name, None);
+        assert_eq!(updated.created_at, stored.created_at);
+        // The metadata is not listed as a client and is removed with it.
+        assert_eq!(registrar.repo.list().unwrap().len(), 1);
+        registrar.delete_client("KvClient").unwrap();
+        assert_eq!(registrar.repo.find_client_metadata("KvClient").unwrap(), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! Versioned schema migrations shared by the SQL backends.
+//!
+//! Every backend lists its migrations as `(version, statements)` pairs in its dialect and records
+//! the applied versions in an `oxide_auth_migrations` table. The bookkeeping of which versions
+//! still need to run is the same for all of them and lives here, each backend only describes how
+//! to read the applied versions and how to apply one migration.
+//!
+//! The repositories apply pending migrations when they are opened and offer `migrate` and
+//! `migration_status` for operators who upgrade the schema as a separate deployment step. Several
+//! processes may migrate the same database at once, every backend serializes the application of a
+//! version with a lock of its dialect and skips versions another process applied meanwhile.
+
+/// A database whose schema can be migrated.
+pub trait SchemaTarget {
+    /// Create the `oxide_auth_migrations` table if needed and return the applied versions.
+    fn applied_versions(&mut self) -> anyhow::Result<Vec<i64>>;
+
+    /// Execute the statements of one migration and record its version.
+    ///
+    /// Backends should do this in a transaction where their dialect allows schema changes in one.
+    /// Returns `false` without executing anything if the version was applied concurrently.
+    fn apply(&mut self, version: i64, statements: &str) -> anyhow::Result<bool>;
+}
+
+/// The versions of a database compared to the migrations of this crate.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationStatus {
+    /// Versions known to this crate that have been applied.
+    pub applied: Vec<i64>,
+
+    /// Versions known to this crate that still need to be applied.
+    pub pending: Vec<i64>,
+
+    /// Applied versions this crate does not know, recorded by a newer version of it.
+    pub unknown: Vec<i64>,
+}
+
+impl MigrationStatus {
+    /// Whether the schema matches the migrations of this crate exactly.
+    pub fn is_current(&self) -> bool {
+        self.pending.is_empty() && self.unknown.is_empty()
+    }
+}
+
+/// Compare the applied versions of the database with the migrations.
+pub fn status<T>(target: &mut T, migrations: &[(i64, &str)]) -> anyhow::Result<MigrationStatus>
+where
+    T: SchemaTarget + ?Sized,
+{
+    let mut applied = target.applied_versions()?;
+    applied.sort_unstable();
+
+    let (known, pending) = migrations
+        .iter()
+        .map(|&(version, _)| version)
+        .partition(|version| applied.contains(version));
+    let unknown = applied
+        .into_iter()
+        .filter(|version| !migrations.iter().any(|&(known, _)| known == *version))
+        .collect();
+
+    Ok(MigrationStatus {
+        applied: known,
+        pending,
+        unknown,
+    })
+}
+
+/// Apply all migrations whose version was not applied yet, in order.
+///
+/// Returns the versions that were applied by this call.
+pub fn run<T>(target: &mut T, migrations: &[(i64, &str)]) -> anyhow::Result<Vec<i64>>
+where
+    T: SchemaTarget + ?Sized,
+{
+    let applied = target.applied_versions()?;
+    let mut newly_applied = Vec::new();
+
+    for &(version, statements) in migrations {
+        if applied.contains(&version) {
+            continue;
+        }
+
+        if target.apply(version, statements)? {
+            newly_applied.push(version);
+        }
+    }
+
+    Ok(newly_applied)
+}
+
+/// Split a migration into its single statements, for drivers that only execute one at a time.
+pub fn statements(migration: &str) -> impl Iterator<Item = &str> {
+    migration.split(';').map(str::trim).filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        applied: Vec<i64>,
+        executed: Vec<String>,
+    }
+
+    impl SchemaTarget for Recorder {
+        fn applied_versions(&mut self) -> anyhow::Result<Vec<i64>> {
+            Ok(self.applied.clone())
+        }
+
+        fn apply(&mut self, version: i64, migration: &str) -> anyhow::Result<bool> {
+            self.executed.extend(statements(migration).map(str::to_owned));
+            self.applied.push(version);
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn applies_pending_once() {
+        let migrations = &[
+            (1, "CREATE TABLE a (x INT);"),
+            (2, "ALTER TABLE a ADD y INT; CREATE INDEX b ON a (y)"),
+        ];
+        let mut target = Recorder {
+            applied: vec![1],
+            ..Recorder::default()
+        };
+
+        assert_eq!(run(&mut target, migrations).unwrap(), vec![2]);
+        assert_eq!(
+            target.executed,
+            vec!["ALTER TABLE a ADD y INT", "CREATE INDEX b ON a (y)"]
+        );
+        assert!(run(&mut target, migrations).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_status() {
+        let migrations = &[(1, "CREATE TABLE a (x INT)"), (2, "CREATE TABLE b (y INT)")];
+        let mut target = Recorder {
+            applied: vec![3, 1],
+            ..Recorder::default()
+        };
+
+        let status = status(&mut target, migrations).unwrap();
+        assert_eq!(
+            status,
+            MigrationStatus {
+                applied: vec![1],
+                pending: vec![2],
+                unknown: vec![3],
+            }
+        );
+        assert!(!status.is_current());
+    }
+}
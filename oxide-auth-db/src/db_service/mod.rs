@@ -1,6 +1,64 @@
 #[cfg(feature = "with-redis")]
 pub mod redis;
 
+pub mod audit;
+
+pub mod cache;
+
+pub mod chain;
+
+pub mod consent;
+
+pub mod health;
+
+pub mod kv;
+
+pub mod migrations;
+
+pub mod pool;
+
+#[cfg(feature = "with-dynamodb")]
+pub mod dynamodb;
+
+#[cfg(feature = "with-etcd")]
+pub mod etcd;
+
+#[cfg(feature = "with-mongodb")]
+pub mod mongodb;
+
+#[cfg(feature = "with-mysql")]
+pub mod mysql;
+
+#[cfg(feature = "with-postgres")]
+pub mod postgres;
+
+pub mod replicate;
+
+pub mod single_flight;
+
+pub mod static_clients;
+
+pub mod telemetry;
+
+pub mod tenant;
+
+pub mod transfer;
+
+#[cfg(feature = "with-sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "with-sled")]
+pub mod sled;
+
+#[cfg(feature = "with-spin")]
+pub mod spin_http;
+
+#[cfg(feature = "with-spin")]
+pub mod spin_redis;
+
+#[cfg(feature = "with-spin")]
+pub mod spin_sqlite;
+
 #[cfg(feature = "with-redis")]
 use redis::RedisDataSource;
 
@@ -10,3 +68,10 @@ use redis::RedisDataSource;
 /// users can change to another database, mysql or postgresql .etc. and add corresponding implements.
 /// for example: pub type DataSource = MysqslDataSource;
 pub type DataSource = RedisDataSource;
+
+/// A datasource service to restore clients.
+///
+/// Without the redis feature there is no single default database, any repository can be boxed and
+/// used with `DBRegistrar::with_repository`.
+#[cfg(not(feature = "with-redis"))]
+pub type DataSource = Box<dyn crate::primitives::db_registrar::OauthClientDBRepository>;
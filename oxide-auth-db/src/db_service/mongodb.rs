@@ -0,0 +1,230 @@
+//! Client storage in a MongoDB database.
+//!
+//! The same database also holds the collections of `MongoAuthorizer` and `MongoIssuer`:
+//!
+//! * `oauth_clients` stores each client as a document keyed by its id, with a flag for disabled
+//!   clients and their optional metadata,
+//! * `oauth_codes` holds unredeemed authorization codes keyed by the code,
+//! * `oauth_tokens` holds issued token pairs keyed by the access token, with a unique index on the
+//!   refresh token and indexes on the owner and client.
+//!
+//! Codes and tokens carry an `expires_at` date with a TTL index, so the server deletes them on its
+//! own. A token pair keeps its document until its refresh token expired as well, and refresh
+//! tokens kept until they are used have none. The indexes are created by [`ensure_indexes`] when
+//! the repository is opened.
+//!
+//! [`ensure_indexes`]: fn.ensure_indexes.html
+use crate::db_service::health::Health;
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+use crate::primitives::stored::StoredGrant;
+
+use std::time::Duration;
+
+use mongodb::bson::{doc, to_bson, DateTime, Document};
+use mongodb::options::IndexOptions;
+use mongodb::sync::{Client, Collection, Database};
+use mongodb::IndexModel;
+use oxide_auth::primitives::registrar::EncodedClient;
+use serde::{Deserialize, Serialize};
+
+/// A client, keyed by its id.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct ClientDocument {
+    #[serde(rename = "_id")]
+    pub client_id: String,
+    pub client: EncodedClient,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub metadata: Option<ClientMetadata>,
+}
+
+/// An unredeemed authorization code.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct CodeDocument {
+    #[serde(rename = "_id")]
+    pub code: String,
+    pub grant: StoredGrant,
+    pub expires_at: DateTime,
+}
+
+/// An issued token pair, keyed by the access token.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct TokenDocument {
+    #[serde(rename = "_id")]
+    pub access_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub owner_id: String,
+    pub client_id: String,
+    pub grant: StoredGrant,
+    /// When both tokens expired, absent while the refresh token is kept until used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_at: Option<DateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_expires_at: Option<DateTime>,
+}
+
+pub(crate) fn clients(database: &Database) -> Collection<ClientDocument> {
+    database.collection("oauth_clients")
+}
+
+pub(crate) fn codes(database: &Database) -> Collection<CodeDocument> {
+    database.collection("oauth_codes")
+}
+
+pub(crate) fn tokens(database: &Database) -> Collection<TokenDocument> {
+    database.collection("oauth_tokens")
+}
+
+/// Create the indexes of all collections.
+///
+/// Creating an index that already exists with the same options does nothing, so this is safe to
+/// call on every start.
+pub fn ensure_indexes(database: &Database) -> anyhow::Result<()> {
+    let expiring = || {
+        IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build()
+    };
+    let by = |field: &str| IndexModel::builder().keys(doc! { field: 1 }).build();
+
+    codes(database).create_index(expiring()).run()?;
+    let tokens = tokens(database);
+    tokens.create_index(expiring()).run()?;
+    tokens
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "refresh_token": 1 })
+                // Tokens without a refresh token must not collide in the unique index.
+                .options(IndexOptions::builder().unique(true).sparse(true).build())
+                .build(),
+        )
+        .run()?;
+    tokens.create_index(by("owner_id")).run()?;
+    tokens.create_index(by("client_id")).run()?;
+    Ok(())
+}
+
+/// MongoDB datasource to Client entries.
+#[derive(Clone, Debug)]
+pub struct MongoClientRepository {
+    database: Database,
+}
+
+impl MongoClientRepository {
+    /// Connect to the server at `uri`, for example `mongodb://localhost:27017`, and use the named
+    /// database.
+    pub fn new(uri: &str, database: &str) -> anyhow::Result<Self> {
+        let client = Client::with_uri_str(uri)?;
+        Self::from_database(client.database(database))
+    }
+
+    /// Use an existing database handle, creating the indexes if needed.
+    pub fn from_database(database: Database) -> anyhow::Result<Self> {
+        ensure_indexes(&database)?;
+        Ok(MongoClientRepository { database })
+    }
+
+    /// The database of this repository, to be shared with `MongoAuthorizer` and `MongoIssuer`.
+    pub fn get_database(&self) -> Database {
+        self.database.clone()
+    }
+
+    /// Probe the availability of the database.
+    pub fn health_check(&self) -> Health {
+        Health::probe("mongodb", || {
+            self.database.run_command(doc! { "ping": 1 }).run()?;
+            Ok(())
+        })
+    }
+}
+
+impl OauthClientDBRepository for MongoClientRepository {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        clients(&self.database)
+            .find(Document::new())
+            .sort(doc! { "_id": 1 })
+            .run()?
+            .map(|document| Ok(document?.client))
+            .collect()
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        let document = clients(&self.database)
+            .find_one(doc! { "_id": id, "disabled": { "$ne": true } })
+            .run()?
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        Ok(document.client)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        // Only the client is replaced, a disabled client stays disabled.
+        clients(&self.database)
+            .update_one(
+                doc! { "_id": &client.client_id },
+                doc! { "$set": { "client": to_bson(&client)? } },
+            )
+            .upsert(true)
+            .run()?;
+        Ok(())
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let result = clients(&self.database)
+            .update_one(
+                doc! { "_id": &client.client_id },
+                doc! { "$set": { "client": to_bson(&client)? } },
+            )
+            .run()?;
+        if result.matched_count == 0 {
+            return Err(anyhow::anyhow!("No client with id {}", client.client_id));
+        }
+        Ok(())
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        let result = clients(&self.database).delete_one(doc! { "_id": id }).run()?;
+        Ok(result.deleted_count > 0)
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        let result = clients(&self.database)
+            .update_one(doc! { "_id": id }, doc! { "$set": { "disabled": disabled } })
+            .run()?;
+        if result.matched_count == 0 {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        Ok(())
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        let document = clients(&self.database)
+            .find_one(doc! { "_id": id })
+            .run()?
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        Ok(document.disabled)
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        let document = clients(&self.database).find_one(doc! { "_id": id }).run()?;
+        Ok(document.and_then(|document| document.metadata))
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        let result = clients(&self.database)
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "metadata": to_bson(metadata)? } },
+            )
+            .run()?;
+        if result.matched_count == 0 {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        Ok(())
+    }
+}
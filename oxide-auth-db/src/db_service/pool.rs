@@ -0,0 +1,214 @@
+//! Connection pool and retry configuration of the SQL and Redis backends.
+//!
+//! Every pooled repository has a constructor taking a [`PoolConfig`], which sizes its pool and
+//! sets the [`RetryPolicy`] for transient failures:
+//!
+//! ```no_run
+//! # #[cfg(feature = "with-postgres")] {
+//! # use std::time::Duration;
+//! # use oxide_auth_db::db_service::pool::{PoolConfig, RetryPolicy};
+//! # use oxide_auth_db::db_service::postgres::PgClientRepository;
+//! let config = PoolConfig::new(16)
+//!     .with_min_idle(Some(2))
+//!     .with_connection_timeout(Duration::from_secs(5))
+//!     .with_retry(RetryPolicy::new(5).with_backoff(Duration::from_millis(20), Duration::from_secs(2)));
+//! let repository = PgClientRepository::with_config("postgres://localhost/oauth", &config).unwrap();
+//! # }
+//! ```
+//!
+//! Retries cover opening the repository, the client lookups and writes of the repository and the
+//! token lookups of the issuers. Consuming a code or a refresh token is never retried since the
+//! first attempt may have been applied even though its response got lost.
+//!
+//! [`PoolConfig`]: struct.PoolConfig.html
+//! [`RetryPolicy`]: struct.RetryPolicy.html
+use std::thread;
+use std::time::Duration;
+
+/// How often and how patiently a failed database operation is attempted.
+///
+/// The delay between attempts starts at the initial backoff and doubles up to the maximum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+/// Sizing and timeouts of a connection pool, together with the retry policy of its users.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub(crate) max_size: u32,
+    pub(crate) min_idle: Option<u32>,
+    pub(crate) connection_timeout: Duration,
+    pub(crate) idle_timeout: Option<Duration>,
+    pub(crate) max_lifetime: Option<Duration>,
+    pub(crate) retry: RetryPolicy,
+}
+
+impl RetryPolicy {
+    /// Attempt operations up to `attempts` times in total, backing off from 50ms up to 1s.
+    pub fn new(attempts: u32) -> Self {
+        RetryPolicy {
+            attempts: attempts.max(1),
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Attempt every operation only once.
+    pub fn none() -> Self {
+        RetryPolicy::new(1)
+    }
+
+    /// Change the delay before the first retry and the limit it doubles up to.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// The number of attempts, including the first one.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The delay after the failed attempt with the given zero-based index.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+
+    /// Run the operation until it succeeds or all attempts failed, returning the last error.
+    pub fn run<T, E, F>(&self, mut operation: F) -> Result<T, E>
+    where
+        F: FnMut() -> Result<T, E>,
+    {
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt + 1 >= self.attempts => return Err(err),
+                Err(_) => {
+                    thread::sleep(self.backoff(attempt));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, backing off from 50ms up to 1s.
+    fn default() -> Self {
+        RetryPolicy::new(3)
+    }
+}
+
+impl PoolConfig {
+    /// A pool of at most `max_size` connections, waiting up to 30 seconds for a free one.
+    pub fn new(max_size: u32) -> Self {
+        PoolConfig {
+            max_size: max_size.max(1),
+            min_idle: None,
+            connection_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Keep at least this many idle connections open, by default as many as the maximum.
+    pub fn with_min_idle(mut self, min_idle: Option<u32>) -> Self {
+        self.min_idle = min_idle;
+        self
+    }
+
+    /// Change how long to wait for a connection before failing.
+    pub fn with_connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = timeout;
+        self
+    }
+
+    /// Close connections that have been idle for this long.
+    pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Close connections once they have been open for this long.
+    pub fn with_max_lifetime(mut self, lifetime: Option<Duration>) -> Self {
+        self.max_lifetime = lifetime;
+        self
+    }
+
+    /// Change the policy for retrying transient failures.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// The policy for retrying transient failures.
+    pub fn retry(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// An `r2d2` pool builder with the sizing and timeouts of this configuration.
+    #[cfg(any(feature = "with-redis", feature = "with-postgres", feature = "with-sqlite"))]
+    pub(crate) fn r2d2<M: r2d2::ManageConnection>(&self) -> r2d2::Builder<M> {
+        r2d2::Pool::builder()
+            .max_size(self.max_size)
+            .min_idle(self.min_idle)
+            .connection_timeout(self.connection_timeout)
+            .idle_timeout(self.idle_timeout)
+            .max_lifetime(self.max_lifetime)
+    }
+}
+
+impl Default for PoolConfig {
+    /// A pool of at most 10 connections.
+    fn default() -> Self {
+        PoolConfig::new(10)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn retries_until_success() {
+        let policy =
+            RetryPolicy::new(3).with_backoff(Duration::from_millis(1), Duration::from_millis(2));
+        let calls = Cell::new(0);
+        let result: Result<u32, ()> = policy.run(|| {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(())
+            } else {
+                Ok(calls.get())
+            }
+        });
+        assert_eq!(result, Ok(3));
+
+        calls.set(0);
+        let result: Result<(), u32> = RetryPolicy::none().run(|| {
+            calls.set(calls.get() + 1);
+            Err(calls.get())
+        });
+        assert_eq!(result, Err(1));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy =
+            RetryPolicy::new(10).with_backoff(Duration::from_millis(10), Duration::from_millis(50));
+        assert_eq!(policy.backoff(0), Duration::from_millis(10));
+        assert_eq!(policy.backoff(2), Duration::from_millis(40));
+        assert_eq!(policy.backoff(3), Duration::from_millis(50));
+        assert_eq!(policy.backoff(40), Duration::from_millis(50));
+    }
+}
@@ -1,10 +1,16 @@
+use crate::db_service::kv::KeyValueBackend;
+use crate::db_service::pool::{PoolConfig, RetryPolicy};
 use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
 
+use chrono::{DateTime, Utc};
 use oxide_auth::primitives::prelude::Scope;
-use oxide_auth::primitives::registrar::{ClientType, EncodedClient, RegisteredUrl, ExactUrl};
+use oxide_auth::primitives::registrar::{
+    ClientType, EncodedClient, RegisteredUrl, ExactUrl, RedirectMatching, TlsClientAuth,
+};
 
 use r2d2_redis::r2d2::Pool;
-use r2d2_redis::redis::{Commands, RedisError, ErrorKind};
+use r2d2_redis::redis::{self, Commands, RedisError, ErrorKind};
 use r2d2_redis::RedisConnectionManager;
 use std::str::FromStr;
 use serde::{Serialize, Deserialize};
@@ -19,6 +25,7 @@ pub struct RedisDataSource {
     url: String,
     pool: Pool<RedisConnectionManager>,
     client_prefix: String,
+    retry: RetryPolicy,
 }
 
 /// A client whose credentials have been wrapped by a password policy.
@@ -45,6 +52,22 @@ pub struct StringfiedEncodedClient {
 
     /// client_secret, for authentication.
     pub client_secret: Option<String>,
+
+    /// The certificates accepted for mutual TLS authentication.
+    #[serde(default)]
+    pub tls_client_auth: Option<TlsClientAuth>,
+
+    /// The JSON Web Key Set of the client.
+    #[serde(default)]
+    pub jwks: Option<String>,
+
+    /// The base64 encoded secret of `client_secret_jwt` assertions.
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
+
+    /// How redirect URIs of requests are matched.
+    #[serde(default)]
+    pub redirect_matching: RedirectMatching,
 }
 
 impl StringfiedEncodedClient {
@@ -72,6 +95,13 @@ impl StringfiedEncodedClient {
             )
             .unwrap(),
             encoded_client: client_type,
+            tls_client_auth: self.tls_client_auth.clone(),
+            jwks: self.jwks.clone(),
+            jwt_secret: match &self.jwt_secret {
+                None => None,
+                Some(secret) => Some(base64::decode(secret)?),
+            },
+            redirect_matching: self.redirect_matching,
         })
     }
 
@@ -92,19 +122,31 @@ impl StringfiedEncodedClient {
             additional_redirect_uris,
             default_scope,
             client_secret,
+            tls_client_auth: encoded_client.tls_client_auth.clone(),
+            jwks: encoded_client.jwks.clone(),
+            jwt_secret: encoded_client.jwt_secret.as_ref().map(base64::encode),
+            redirect_matching: encoded_client.redirect_matching,
         }
     }
 }
 
 impl RedisDataSource {
     pub fn new(url: String, max_pool_size: u32, client_prefix: String) -> Result<Self, RedisError> {
+        RedisDataSource::with_config(url, &PoolConfig::new(max_pool_size), client_prefix)
+    }
+
+    /// Connect with the pool and retry configuration.
+    pub fn with_config(
+        url: String, config: &PoolConfig, client_prefix: String,
+    ) -> Result<Self, RedisError> {
         let manager = r2d2_redis::RedisConnectionManager::new(url.as_str())?;
-        let pool = Pool::builder().max_size(max_pool_size).build(manager);
+        let pool = config.r2d2().build(manager);
         match pool {
             Ok(pool) => Ok(RedisDataSource {
                 url,
                 pool,
                 client_prefix,
+                retry: config.retry(),
             }),
             Err(_e) => Err(RedisError::from((ErrorKind::ClientError, "Build pool error."))),
         }
@@ -122,34 +164,63 @@ impl RedisDataSource {
     pub fn get_pool(&self) -> Pool<RedisConnectionManager> {
         self.pool.clone()
     }
+
+    /// The retry policy of this data source, shared with `RedisIssuer`.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Disabled clients are marked under the client prefix with `disabled-` in front of it.
+    fn disabled_key(&self, id: &str) -> String {
+        format!("disabled-{}{}", self.client_prefix, id)
+    }
+
+    /// The metadata of clients is stored under the client prefix with `metadata-` in front of it.
+    fn metadata_key(&self, id: &str) -> String {
+        format!("metadata-{}{}", self.client_prefix, id)
+    }
 }
 
 impl RedisDataSource {
     /// users can regist to redis a custom client struct which can be Serialized and Deserialized.
     pub fn regist(&self, detail: &StringfiedEncodedClient) -> anyhow::Result<()> {
-        let mut pool = self.pool.get()?;
         let client_str = serde_json::to_string(&detail)?;
-        pool.set(&(self.client_prefix.to_owned() + &detail.client_id), client_str)?;
-        Ok(())
+        let key = self.client_prefix.to_owned() + detail.client_id.as_str();
+        self.retry.run(|| {
+            self.pool.get()?.set::<_, _, ()>(&key, &client_str)?;
+            Ok(())
+        })
     }
 }
 
 impl OauthClientDBRepository for RedisDataSource {
     fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
-        let mut encoded_clients: Vec<EncodedClient> = vec![];
-        let mut r = self.pool.get()?;
-        let keys = r.keys::<&str, Vec<String>>(&self.client_prefix)?;
-        for key in keys {
-            let clients_str = r.get::<String, String>(key)?;
-            let stringfied_client = serde_json::from_str::<StringfiedEncodedClient>(&clients_str)?;
-            encoded_clients.push(stringfied_client.to_encoded_client()?);
-        }
-        Ok(encoded_clients)
+        self.retry.run(|| {
+            let mut encoded_clients: Vec<EncodedClient> = vec![];
+            let mut r = self.pool.get()?;
+            let keys = r.keys::<&str, Vec<String>>(&self.client_prefix)?;
+            for key in keys {
+                let clients_str = r.get::<String, String>(key)?;
+                let stringfied_client = serde_json::from_str::<StringfiedEncodedClient>(&clients_str)?;
+                encoded_clients.push(stringfied_client.to_encoded_client()?);
+            }
+            Ok(encoded_clients)
+        })
     }
 
     fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
-        let mut r = self.pool.get()?;
-        let client_str = r.get::<&str, String>(&(self.client_prefix.to_owned() + id))?;
+        let keys = [self.client_prefix.to_owned() + id, self.disabled_key(id)];
+        let (client_str, disabled) = self.retry.run(|| {
+            Ok::<_, anyhow::Error>(
+                self.pool
+                    .get()?
+                    .get::<_, (Option<String>, Option<String>)>(&keys)?,
+            )
+        })?;
+        if disabled.is_some() {
+            return Err(anyhow::anyhow!("Client {} is disabled", id));
+        }
+        let client_str = client_str.ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
         let stringfied_client = serde_json::from_str::<StringfiedEncodedClient>(&client_str)?;
         Ok(stringfied_client.to_encoded_client()?)
     }
@@ -158,4 +229,186 @@ impl OauthClientDBRepository for RedisDataSource {
         let detail = StringfiedEncodedClient::from_encoded_client(&client);
         self.regist(&detail)
     }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let detail = StringfiedEncodedClient::from_encoded_client(&client);
+        let client_str = serde_json::to_string(&detail)?;
+        let key = self.client_prefix.to_owned() + detail.client_id.as_str();
+        // `XX` only replaces an existing key, the reply is nil otherwise.
+        let updated = self.retry.run(|| {
+            Ok::<_, anyhow::Error>(
+                redis::cmd("SET")
+                    .arg(&key)
+                    .arg(&client_str)
+                    .arg("XX")
+                    .query::<Option<String>>(&mut *self.pool.get()?)?,
+            )
+        })?;
+        match updated {
+            Some(_) => Ok(()),
+            None => Err(anyhow::anyhow!("No client with id {}", client.client_id)),
+        }
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        let keys = [
+            self.client_prefix.to_owned() + id,
+            self.disabled_key(id),
+            self.metadata_key(id),
+        ];
+        let removed = self
+            .retry
+            .run(|| Ok::<_, anyhow::Error>(self.pool.get()?.del::<_, u32>(&keys[..])?))?;
+        // Marker and metadata are removed along with the client, so anything removed was the client.
+        Ok(removed > 0)
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        let key = self.client_prefix.to_owned() + id;
+        let marker = self.disabled_key(id);
+        self.retry.run(|| {
+            let mut connection = self.pool.get()?;
+            if !connection.exists::<_, bool>(&key)? {
+                return Err(anyhow::anyhow!("No client with id {}", id));
+            }
+            if disabled {
+                connection.set::<_, _, ()>(&marker, 1)?;
+            } else {
+                connection.del::<_, ()>(&marker)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        let marker = self.disabled_key(id);
+        self.retry
+            .run(|| Ok::<_, anyhow::Error>(self.pool.get()?.exists::<_, bool>(&marker)?))
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        let key = self.metadata_key(id);
+        let metadata = self
+            .retry
+            .run(|| Ok::<_, anyhow::Error>(self.pool.get()?.get::<_, Option<String>>(&key)?))?;
+        match metadata {
+            Some(metadata) => Ok(Some(serde_json::from_str(&metadata)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        let key = self.client_prefix.to_owned() + id;
+        let metadata_key = self.metadata_key(id);
+        let metadata_str = serde_json::to_string(metadata)?;
+        self.retry.run(|| {
+            let mut connection = self.pool.get()?;
+            if !connection.exists::<_, bool>(&key)? {
+                return Err(anyhow::anyhow!("No client with id {}", id));
+            }
+            connection.set::<_, _, ()>(&metadata_key, &metadata_str)?;
+            Ok(())
+        })
+    }
+}
+
+/// Replaces the value of `KEYS[1]` if it is `ARGV[2]`, or missing when `ARGV[1]` is `0`.
+///
+/// The new value is `ARGV[4]`, or the key is removed when `ARGV[3]` is `0`. The remaining expiry
+/// of the key carries over to the new value.
+const COMPARE_AND_SWAP: &str = r"
+local current = redis.call('GET', KEYS[1])
+if ARGV[1] == '1' then
+    if current ~= ARGV[2] then return 0 end
+elseif current then
+    return 0
+end
+if ARGV[3] == '0' then
+    redis.call('DEL', KEYS[1])
+    return 1
+end
+local ttl = redis.call('PTTL', KEYS[1])
+redis.call('SET', KEYS[1], ARGV[4])
+if ttl > 0 then redis.call('PEXPIRE', KEYS[1], ttl) end
+return 1
+";
+
+/// Escape the glob characters of `SCAN MATCH` in a key prefix.
+fn match_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    pattern
+}
+
+/// Keys are used as they are, independent of the client prefix, and expire with their entries.
+///
+/// Reads and writes are retried with the retry policy of the data source. Swaps and takes are not,
+/// since a failed reply does not tell whether the server applied them.
+impl KeyValueBackend for RedisDataSource {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.retry
+            .run(|| Ok(self.pool.get()?.get::<_, Option<Vec<u8>>>(key)?))
+    }
+
+    fn set(&self, key: &str, value: &[u8], expires_at: Option<DateTime<Utc>>) -> anyhow::Result<()> {
+        let mut command = redis::cmd("SET");
+        command.arg(key).arg(value);
+        if let Some(expires_at) = expires_at {
+            // Redis rejects non-positive expiries, entries already expired live for a millisecond.
+            let millis = (expires_at - Utc::now()).num_milliseconds().max(1);
+            command.arg("PX").arg(millis);
+        }
+        self.retry
+            .run(|| Ok(command.query::<()>(&mut *self.pool.get()?)?))
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        self.retry.run(|| Ok(self.pool.get()?.del::<_, u32>(key)? > 0))
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        let pattern = match_prefix(prefix);
+        self.retry.run(|| {
+            let mut connection = self.pool.get()?;
+            let keys: Vec<String> = connection.scan_match(&pattern)?.collect();
+            let mut entries = Vec::with_capacity(keys.len());
+            for key in keys {
+                // Keys expiring or taken during the scan are skipped.
+                if let Some(value) = connection.get::<_, Option<Vec<u8>>>(&key)? {
+                    entries.push((key, value));
+                }
+            }
+            entries.sort();
+            Ok(entries)
+        })
+    }
+
+    fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool> {
+        let swapped = redis::Script::new(COMPARE_AND_SWAP)
+            .key(key)
+            .arg(old.is_some() as u8)
+            .arg(old.unwrap_or_default())
+            .arg(new.is_some() as u8)
+            .arg(new.unwrap_or_default())
+            .invoke::<u8>(&mut *self.pool.get()?)?;
+        Ok(swapped == 1)
+    }
+
+    fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        // A transaction instead of `GETDEL` keeps this working on servers before Redis 6.2.
+        let (value, _): (Option<Vec<u8>>, i64) = redis::pipe()
+            .atomic()
+            .get(key)
+            .del(key)
+            .query(&mut *self.pool.get()?)?;
+        Ok(value)
+    }
 }
@@ -0,0 +1,235 @@
+//! Write-through replication of client registrations.
+//!
+//! A [`ReplicatingRepository`] serves all lookups from its primary repository and repeats every
+//! change made through it in one or more secondaries, for example to keep a warm standby registry
+//! in another region or another kind of store:
+//!
+//! ```no_run
+//! # use oxide_auth_db::db_service::kv::{KvClientRepository, MemoryStore};
+//! # use oxide_auth_db::db_service::replicate::{ReplicatingRepository, ReplicationPolicy};
+//! # use oxide_auth_db::primitives::db_registrar::DBRegistrar;
+//! # let (primary, standby) = (MemoryStore::new(), MemoryStore::new());
+//! let repository = ReplicatingRepository::new(KvClientRepository::new(primary))
+//!     .replicate_to(KvClientRepository::new(standby))
+//!     .policy(ReplicationPolicy::FailFast);
+//! repository.sync()?;
+//! let registrar = DBRegistrar::with_repository(repository);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! A change is always made in the primary first and only replicated once it succeeded there. The
+//! [`ReplicationPolicy`] decides what a failure in a secondary means for the change as a whole.
+//! Neither policy rolls back the primary, a secondary that missed changes is brought up to date by
+//! `sync`.
+//!
+//! [`ReplicatingRepository`]: struct.ReplicatingRepository.html
+//! [`ReplicationPolicy`]: enum.ReplicationPolicy.html
+use oxide_auth::primitives::registrar::EncodedClient;
+
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+/// How a failure to replicate a change to a secondary is handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplicationPolicy {
+    /// Report the change as failed and skip the remaining secondaries.
+    FailFast,
+
+    /// Log the failure and continue, the change succeeds as soon as the primary has it.
+    #[default]
+    BestEffort,
+}
+
+/// A client repository repeating every change in its secondaries.
+pub struct ReplicatingRepository<R: OauthClientDBRepository> {
+    primary: R,
+    secondaries: Vec<Box<dyn OauthClientDBRepository>>,
+    policy: ReplicationPolicy,
+}
+
+impl<R: OauthClientDBRepository> ReplicatingRepository<R> {
+    /// Serve clients from the primary, without secondaries and with best-effort replication.
+    pub fn new(primary: R) -> Self {
+        ReplicatingRepository {
+            primary,
+            secondaries: Vec::new(),
+            policy: ReplicationPolicy::default(),
+        }
+    }
+
+    /// Replicate changes to the repository, after all secondaries added before it.
+    pub fn replicate_to<S: OauthClientDBRepository + 'static>(mut self, secondary: S) -> Self {
+        self.secondaries.push(Box::new(secondary));
+        self
+    }
+
+    /// Handle failures of secondaries with the policy.
+    pub fn policy(mut self, policy: ReplicationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The repository serving all lookups.
+    pub fn primary(&self) -> &R {
+        &self.primary
+    }
+
+    /// Copy every client of the primary to all secondaries, returning the number of clients.
+    ///
+    /// Clients only known to a secondary are left in place. Failures are handled by the policy.
+    pub fn sync(&self) -> anyhow::Result<usize> {
+        let clients = self.primary.list()?;
+        for client in &clients {
+            let id = &client.client_id;
+            self.replicate(id, |secondary| {
+                secondary.regist_from_encoded_client(client.clone())?;
+                secondary.set_client_disabled(id, self.primary.is_client_disabled(id)?)?;
+                if let Some(metadata) = self.primary.find_client_metadata(id)? {
+                    secondary.store_client_metadata(id, &metadata)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(clients.len())
+    }
+
+    fn replicate<F>(&self, id: &str, mut change: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&dyn OauthClientDBRepository) -> anyhow::Result<()>,
+    {
+        for (index, secondary) in self.secondaries.iter().enumerate() {
+            if let Err(err) = change(&**secondary) {
+                match self.policy {
+                    ReplicationPolicy::FailFast => {
+                        return Err(err.context(format!(
+                            "Failed to replicate client {} to secondary {}",
+                            id, index
+                        )))
+                    }
+                    ReplicationPolicy::BestEffort => log::warn!(
+                        "Failed to replicate client {} to secondary {}: {}",
+                        id,
+                        index,
+                        err
+                    ),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: OauthClientDBRepository> OauthClientDBRepository for ReplicatingRepository<R> {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        self.primary.list()
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        self.primary.find_client_by_id(id)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.primary.regist_from_encoded_client(client.clone())?;
+        self.replicate(&client.client_id, |secondary| {
+            secondary.regist_from_encoded_client(client.clone())
+        })
+    }
+
+    /// Update the client in the primary, and register it in the secondaries.
+    ///
+    /// A secondary that missed the registration of the client thereby catches up.
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.primary.update_client(client.clone())?;
+        self.replicate(&client.client_id, |secondary| {
+            secondary.regist_from_encoded_client(client.clone())
+        })
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        let deleted = self.primary.delete_client(id)?;
+        self.replicate(id, |secondary| secondary.delete_client(id).map(|_| ()))?;
+        Ok(deleted)
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        self.primary.set_client_disabled(id, disabled)?;
+        self.replicate(id, |secondary| secondary.set_client_disabled(id, disabled))
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        self.primary.is_client_disabled(id)
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        self.primary.find_client_metadata(id)
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        self.primary.store_client_metadata(id, metadata)?;
+        self.replicate(id, |secondary| secondary.store_client_metadata(id, metadata))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::registrar::{Client, RegisteredUrl};
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::primitives::db_registrar::DEFAULT_PASSWORD_POLICY;
+
+    /// A secondary that is down.
+    struct Unavailable;
+
+    impl OauthClientDBRepository for Unavailable {
+        fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+            Err(anyhow::anyhow!("Unavailable"))
+        }
+
+        fn find_client_by_id(&self, _: &str) -> anyhow::Result<EncodedClient> {
+            Err(anyhow::anyhow!("Unavailable"))
+        }
+
+        fn regist_from_encoded_client(&self, _: EncodedClient) -> anyhow::Result<()> {
+            Err(anyhow::anyhow!("Unavailable"))
+        }
+    }
+
+    fn client(id: &str) -> EncodedClient {
+        Client::public(
+            id,
+            RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+        )
+        .encode(&*DEFAULT_PASSWORD_POLICY)
+    }
+
+    #[test]
+    fn changes_are_replicated() {
+        let primary = KvClientRepository::new(MemoryStore::new());
+        let standby = KvClientRepository::new(MemoryStore::new());
+        primary.regist_from_encoded_client(client("Existing")).unwrap();
+
+        let best_effort = ReplicatingRepository::new(primary.clone())
+            .replicate_to(Unavailable)
+            .replicate_to(standby.clone());
+        assert_eq!(best_effort.sync().unwrap(), 1);
+        assert!(standby.find_client_by_id("Existing").is_ok());
+
+        best_effort.regist_from_encoded_client(client("Client")).unwrap();
+        assert!(standby.find_client_by_id("Client").is_ok());
+        best_effort.set_client_disabled("Client", true).unwrap();
+        assert!(standby.is_client_disabled("Client").unwrap());
+        assert!(best_effort.delete_client("Client").unwrap());
+        assert!(standby.list().unwrap().iter().all(|c| c.client_id != "Client"));
+
+        // The primary keeps the change, the standby after the failing secondary is not reached.
+        let fail_fast = ReplicatingRepository::new(primary.clone())
+            .replicate_to(Unavailable)
+            .replicate_to(standby.clone())
+            .policy(ReplicationPolicy::FailFast);
+        assert!(fail_fast.regist_from_encoded_client(client("Other")).is_err());
+        assert!(primary.find_client_by_id("Other").is_ok());
+        assert!(standby.find_client_by_id("Other").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Coalescing concurrent lookups of the same client.
+//!
+//! When many requests of the same client arrive at once, each `check` of a `DBRegistrar` would
+//! fetch the client from the backend. A [`SingleFlightRepository`] lets the first lookup of an id
+//! go to the backend while concurrent lookups of the same id wait for and share its result:
+//!
+//! ```no_run
+//! # use oxide_auth_db::db_service::cache::CachedRepository;
+//! # use oxide_auth_db::db_service::kv::{KvClientRepository, MemoryStore};
+//! # use oxide_auth_db::db_service::single_flight::SingleFlightRepository;
+//! # use oxide_auth_db::primitives::db_registrar::DBRegistrar;
+//! let repository = KvClientRepository::new(MemoryStore::new());
+//! let registrar = DBRegistrar::with_repository(CachedRepository::new(
+//!     SingleFlightRepository::new(repository),
+//! ));
+//! ```
+//!
+//! Combined with a `CachedRepository` in front of it, a hot client is fetched once per expiry of
+//! its cache entry no matter how many requests miss the cache at the same time.
+//!
+//! [`SingleFlightRepository`]: struct.SingleFlightRepository.html
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use oxide_auth::primitives::registrar::EncodedClient;
+
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+/// A client repository sharing the result of a lookup with concurrent lookups of the same id.
+///
+/// Only `find_client_by_id` is coalesced, all other operations go to the wrapped repository. A
+/// lookup joining one that started before a change may return the client as it was before.
+pub struct SingleFlightRepository<R: OauthClientDBRepository> {
+    inner: R,
+    flights: Mutex<HashMap<String, Arc<Flight>>>,
+}
+
+/// A lookup in progress and, once it landed, its result.
+#[derive(Default)]
+struct Flight {
+    result: Mutex<Option<Result<EncodedClient, String>>>,
+    landed: Condvar,
+}
+
+/// Publishes the result of the leading lookup, or an error if it panicked.
+struct Landing<'a> {
+    flights: &'a Mutex<HashMap<String, Arc<Flight>>>,
+    id: &'a str,
+    flight: Arc<Flight>,
+    result: Option<Result<EncodedClient, String>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Flights are always consistent, a panic while holding the lock does not matter.
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Drop for Landing<'_> {
+    fn drop(&mut self) {
+        // Removed first, so lookups from now on start a new flight instead of joining this one.
+        lock(self.flights).remove(self.id);
+        let result = self
+            .result
+            .take()
+            .unwrap_or_else(|| Err(format!("The lookup of client {} was aborted", self.id)));
+        *lock(&self.flight.result) = Some(result);
+        self.flight.landed.notify_all();
+    }
+}
+
+impl<R: OauthClientDBRepository> SingleFlightRepository<R> {
+    /// Coalesce the lookups of the repository.
+    pub fn new(inner: R) -> Self {
+        SingleFlightRepository {
+            inner,
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The wrapped repository.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    fn lead(&self, id: &str, flight: Arc<Flight>) -> anyhow::Result<EncodedClient> {
+        let mut landing = Landing {
+            flights: &self.flights,
+            id,
+            flight,
+            result: None,
+        };
+        let result = self.inner.find_client_by_id(id);
+        landing.result = Some(match &result {
+            Ok(client) => Ok(client.clone()),
+            Err(err) => Err(err.to_string()),
+        });
+        result
+    }
+
+    fn follow(flight: &Flight) -> anyhow::Result<EncodedClient> {
+        let mut result = lock(&flight.result);
+        loop {
+            match &*result {
+                Some(Ok(client)) => return Ok(client.clone()),
+                Some(Err(err)) => return Err(anyhow::anyhow!("{}", err)),
+                None => {
+                    result = flight
+                        .landed
+                        .wait(result)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                }
+            }
+        }
+    }
+}
+
+impl<R: OauthClientDBRepository> OauthClientDBRepository for SingleFlightRepository<R> {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        self.inner.list()
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        let (flight, leading) = {
+            let mut flights = lock(&self.flights);
+            match flights.get(id) {
+                Some(flight) => (flight.clone(), false),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    flights.insert(id.to_owned(), flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+
+        if leading {
+            self.lead(id, flight)
+        } else {
+            Self::follow(&flight)
+        }
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.inner.regist_from_encoded_client(client)
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.inner.update_client(client)
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        self.inner.delete_client(id)
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        self.inner.set_client_disabled(id, disabled)
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        self.inner.is_client_disabled(id)
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        self.inner.find_client_metadata(id)
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        self.inner.store_client_metadata(id, metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    use oxide_auth::primitives::registrar::{Client, RegisteredUrl};
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::primitives::db_registrar::DEFAULT_PASSWORD_POLICY;
+
+    struct Slow {
+        inner: KvClientRepository<MemoryStore>,
+        lookups: AtomicUsize,
+    }
+
+    impl OauthClientDBRepository for Slow {
+        fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+            self.inner.list()
+        }
+
+        fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(200));
+            self.inner.find_client_by_id(id)
+        }
+
+        fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+            self.inner.regist_from_encoded_client(client)
+        }
+    }
+
+    #[test]
+    fn concurrent_lookups_are_shared() {
+        let inner = KvClientRepository::new(MemoryStore::new());
+        let client = Client::public(
+            "Client",
+            RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+        );
+        inner
+            .regist_from_encoded_client(client.encode(&*DEFAULT_PASSWORD_POLICY))
+            .unwrap();
+        let repository = Arc::new(SingleFlightRepository::new(Slow {
+            inner,
+            lookups: AtomicUsize::new(0),
+        }));
+
+        let barrier = Arc::new(Barrier::new(8));
+        let lookups: Vec<_> = (0..8)
+            .map(|i| {
+                let repository = repository.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let id = if i == 0 { "Unknown" } else { "Client" };
+                    repository.find_client_by_id(id).is_ok()
+                })
+            })
+            .collect();
+        let found: Vec<bool> = lookups.into_iter().map(|lookup| lookup.join().unwrap()).collect();
+
+        assert_eq!(found.iter().filter(|&&found| found).count(), 7);
+        assert_eq!(repository.inner().lookups.load(Ordering::SeqCst), 2);
+        assert!(repository.flights.lock().unwrap().is_empty());
+
+        repository.find_client_by_id("Client").unwrap();
+        assert_eq!(repository.inner().lookups.load(Ordering::SeqCst), 3);
+    }
+}
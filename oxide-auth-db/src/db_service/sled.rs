@@ -0,0 +1,260 @@
+//! Client storage in an embedded sled database, for deployments without an external database.
+//!
+//! The database holds one tree per kind of record. Clients are stored as JSON under their id in
+//! `oauth_clients`, their metadata in `oauth_client_metadata`, and disabled clients are marked in
+//! `oauth_disabled_clients`. The trees of `SledAuthorizer` and `SledIssuer` are:
+//!
+//! * `oauth_codes`, a `CodeEntry` under each unredeemed authorization code,
+//! * `oauth_access_tokens`, a `StoredToken` under each access token,
+//! * `oauth_refresh_tokens`, a `StoredRefresh` under each refresh token.
+//!
+//! sled has no notion of expiry, expired entries stay on disk until they are redeemed or removed
+//! by [`purge_expired`]. A background thread doing so periodically is started with
+//! [`spawn_compaction`].
+//!
+//! [`purge_expired`]: fn.purge_expired.html
+//! [`spawn_compaction`]: struct.SledClientRepository.html#method.spawn_compaction
+use crate::db_service::health::Health;
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+use crate::primitives::stored::{StoredGrant, StoredRefresh, StoredToken};
+
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use oxide_auth::primitives::registrar::EncodedClient;
+use serde::de::DeserializeOwned;
+use sled::{Db, Tree};
+
+pub(crate) const CLIENTS: &str = "oauth_clients";
+pub(crate) const DISABLED_CLIENTS: &str = "oauth_disabled_clients";
+pub(crate) const CLIENT_METADATA: &str = "oauth_client_metadata";
+pub(crate) const CODES: &str = "oauth_codes";
+pub(crate) const ACCESS_TOKENS: &str = "oauth_access_tokens";
+pub(crate) const REFRESH_TOKENS: &str = "oauth_refresh_tokens";
+
+/// An unredeemed authorization code, valid until its grant expires.
+pub(crate) type CodeEntry = StoredGrant;
+
+/// Something stored in a tree that may expire.
+trait Expiring: DeserializeOwned {
+    fn until(&self) -> Option<DateTime<Utc>>;
+}
+
+impl Expiring for StoredGrant {
+    fn until(&self) -> Option<DateTime<Utc>> {
+        Some(self.until)
+    }
+}
+
+impl Expiring for StoredToken {
+    fn until(&self) -> Option<DateTime<Utc>> {
+        Some(self.grant.until)
+    }
+}
+
+impl Expiring for StoredRefresh {
+    fn until(&self) -> Option<DateTime<Utc>> {
+        self.until
+    }
+}
+
+/// Remove all entries of a tree that expired before `now`, or can not be decoded at all.
+fn purge_tree<T: Expiring>(tree: &Tree, now: DateTime<Utc>) -> anyhow::Result<usize> {
+    let mut removed = 0;
+    for entry in tree.iter() {
+        let (key, value) = entry?;
+        let expired = match serde_json::from_slice::<T>(&value) {
+            Ok(entry) => entry.until().is_some_and(|until| until < now),
+            Err(_) => true,
+        };
+        // Only remove the entry if it was not replaced since it was read.
+        if expired
+            && tree
+                .compare_and_swap(&key, Some(&value), None as Option<&[u8]>)?
+                .is_ok()
+        {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Remove all expired codes and tokens from the database.
+///
+/// Returns the number of removed entries.
+pub fn purge_expired(db: &Db) -> anyhow::Result<usize> {
+    let now = Utc::now();
+    Ok(purge_tree::<CodeEntry>(&db.open_tree(CODES)?, now)?
+        + purge_tree::<StoredToken>(&db.open_tree(ACCESS_TOKENS)?, now)?
+        + purge_tree::<StoredRefresh>(&db.open_tree(REFRESH_TOKENS)?, now)?)
+}
+
+/// A background thread periodically removing expired entries.
+///
+/// The thread is stopped and joined when this handle is dropped.
+#[derive(Debug)]
+pub struct Compaction {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Compaction {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes up the thread.
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// sled datasource to Client entries.
+#[derive(Clone, Debug)]
+pub struct SledClientRepository {
+    db: Db,
+    clients: Tree,
+    disabled: Tree,
+    metadata: Tree,
+}
+
+impl SledClientRepository {
+    /// Open or create the database in the directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Self::from_db(sled::open(path)?)
+    }
+
+    /// Create a database that is removed when the last handle to it is dropped.
+    pub fn temporary() -> anyhow::Result<Self> {
+        Self::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    /// Use an already opened database.
+    pub fn from_db(db: Db) -> anyhow::Result<Self> {
+        let clients = db.open_tree(CLIENTS)?;
+        let disabled = db.open_tree(DISABLED_CLIENTS)?;
+        let metadata = db.open_tree(CLIENT_METADATA)?;
+        Ok(SledClientRepository {
+            db,
+            clients,
+            disabled,
+            metadata,
+        })
+    }
+
+    /// The database of this repository, to be shared with `SledAuthorizer` and `SledIssuer`.
+    pub fn get_db(&self) -> Db {
+        self.db.clone()
+    }
+
+    /// Remove expired codes and tokens every `interval` on a background thread.
+    pub fn spawn_compaction(&self, interval: Duration) -> Compaction {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let db = self.db.clone();
+        let thread = thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Err(err) = purge_expired(&db) {
+                        log::warn!("Failed to purge expired entries: {}", err);
+                    }
+                }
+                _ => return,
+            }
+        });
+
+        Compaction {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Probe the availability of the database.
+    pub fn health_check(&self) -> Health {
+        Health::probe("sled", || {
+            self.clients.first()?;
+            Ok(())
+        })
+    }
+}
+
+impl OauthClientDBRepository for SledClientRepository {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        self.clients
+            .iter()
+            .values()
+            .map(|client| Ok(serde_json::from_slice(&client?)?))
+            .collect()
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        if self.disabled.contains_key(id)? {
+            return Err(anyhow::anyhow!("Client {} is disabled", id));
+        }
+        let client = self
+            .clients
+            .get(id)?
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        Ok(serde_json::from_slice(&client)?)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let encoded = serde_json::to_vec(&client)?;
+        self.clients.insert(client.client_id.as_bytes(), encoded)?;
+        Ok(())
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let encoded = serde_json::to_vec(&client)?;
+        // Only replaces an existing record, a concurrently deleted client stays deleted.
+        let mut updated = false;
+        self.clients
+            .fetch_and_update(client.client_id.as_bytes(), |old| {
+                updated = old.is_some();
+                old.map(|_| encoded.clone())
+            })?;
+        if !updated {
+            return Err(anyhow::anyhow!("No client with id {}", client.client_id));
+        }
+        Ok(())
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        self.disabled.remove(id)?;
+        self.metadata.remove(id)?;
+        Ok(self.clients.remove(id)?.is_some())
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        if !self.clients.contains_key(id)? {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        if disabled {
+            self.disabled.insert(id, &[][..])?;
+        } else {
+            self.disabled.remove(id)?;
+        }
+        Ok(())
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        Ok(self.disabled.contains_key(id)?)
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        match self.metadata.get(id)? {
+            Some(metadata) => Ok(Some(serde_json::from_slice(&metadata)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        if !self.clients.contains_key(id)? {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        self.metadata.insert(id, serde_json::to_vec(metadata)?)?;
+        Ok(())
+    }
+}
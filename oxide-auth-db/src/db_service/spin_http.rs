@@ -0,0 +1,131 @@
+//! Clients managed by a central service, fetched through the outbound HTTP interface of Spin.
+//!
+//! The remote service is expected to expose the clients as JSON encoded `EncodedClient` records:
+//!
+//! * `GET {base}/clients` lists all clients,
+//! * `GET {base}/clients/{client_id}` returns a single client, or `404` if there is none,
+//! * `PUT {base}/clients/{client_id}` creates or replaces a client.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::registrar::EncodedClient;
+use spin_sdk::http::{Method, Request, Response};
+
+use crate::primitives::db_registrar::OauthClientDBRepository;
+
+/// A client repository backed by a remote http service, with a local cache.
+pub struct HttpClientRegistrar {
+    base_url: String,
+    authorization: Option<String>,
+    refresh_interval: Duration,
+    cache: Mutex<HashMap<String, (EncodedClient, DateTime<Utc>)>>,
+}
+
+impl HttpClientRegistrar {
+    /// Fetch clients from the service at `base_url`, caching them for one minute.
+    pub fn new(base_url: String) -> Self {
+        HttpClientRegistrar {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            authorization: None,
+            refresh_interval: Duration::minutes(1),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Send this value as `Authorization` header with each request to the service.
+    pub fn with_authorization(mut self, authorization: String) -> Self {
+        self.authorization = Some(authorization);
+        self
+    }
+
+    /// Change how long a fetched client is used before it is requested again.
+    ///
+    /// A zero duration disables the cache.
+    pub fn with_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = refresh_interval;
+        self
+    }
+
+    /// Drop all cached clients.
+    pub fn invalidate(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn send(&self, method: Method, path: &str, body: Vec<u8>) -> anyhow::Result<Response> {
+        let mut builder = Request::builder();
+        builder
+            .method(method)
+            .uri(format!("{}{}", self.base_url, path))
+            .header("accept", "application/json");
+        if !body.is_empty() {
+            builder.header("content-type", "application/json");
+        }
+        if let Some(authorization) = &self.authorization {
+            builder.header("authorization", authorization.as_str());
+        }
+        let request = builder.body(body).build();
+
+        let response: Response = spin_executor::run(spin_sdk::http::send(request))?;
+        Ok(response)
+    }
+
+    fn cached(&self, id: &str) -> Option<EncodedClient> {
+        let cache = self.cache.lock().unwrap();
+        match cache.get(id) {
+            Some((client, fetched)) if Utc::now() < *fetched + self.refresh_interval => {
+                Some(client.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn remember(&self, client: &EncodedClient) {
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(client.client_id.clone(), (client.clone(), Utc::now()));
+    }
+}
+
+fn client_path(id: &str) -> String {
+    let id: String = url::form_urlencoded::byte_serialize(id.as_bytes()).collect();
+    format!("/clients/{}", id)
+}
+
+fn expect_success(response: &Response) -> anyhow::Result<()> {
+    match *response.status() {
+        200..=299 => Ok(()),
+        status => Err(anyhow::anyhow!("Client service responded with status {}", status)),
+    }
+}
+
+impl OauthClientDBRepository for HttpClientRegistrar {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        let response = self.send(Method::Get, "/clients", Vec::new())?;
+        expect_success(&response)?;
+        let clients: Vec<EncodedClient> = serde_json::from_slice(response.body())?;
+        clients.iter().for_each(|client| self.remember(client));
+        Ok(clients)
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        if let Some(client) = self.cached(id) {
+            return Ok(client);
+        }
+
+        let response = self.send(Method::Get, &client_path(id), Vec::new())?;
+        expect_success(&response)?;
+        let client: EncodedClient = serde_json::from_slice(response.body())?;
+        self.remember(&client);
+        Ok(client)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(&client)?;
+        let response = self.send(Method::Put, &client_path(&client.client_id), body)?;
+        expect_success(&response)?;
+        self.remember(&client);
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+//! Client storage on a Redis server, reached through the outbound Redis interface of Spin.
+use crate::db_service::health::Health;
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+use oxide_auth::primitives::registrar::EncodedClient;
+use spin_sdk::redis::{Connection, RedisParameter, RedisResult};
+
+/// Probe the server with a `PING`.
+pub fn health_check(connection: &Connection) -> Health {
+    Health::probe("redis", || {
+        connection.execute("PING", &[])?;
+        Ok(())
+    })
+}
+
+/// Spin redis datasource to Client entries.
+pub struct SpinRedisDataSource {
+    connection: Connection,
+    client_prefix: String,
+}
+
+impl SpinRedisDataSource {
+    /// Connect to the Redis server at `address`, storing clients under `client_prefix`.
+    pub fn open(address: &str, client_prefix: String) -> anyhow::Result<Self> {
+        let connection = Connection::open(address)?;
+        Ok(SpinRedisDataSource {
+            connection,
+            client_prefix,
+        })
+    }
+
+    /// The underlying connection.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Probe the availability of the server.
+    pub fn health_check(&self) -> Health {
+        health_check(&self.connection)
+    }
+
+    fn key(&self, client_id: &str) -> String {
+        format!("{}{}", self.client_prefix, client_id)
+    }
+
+    /// Disabled clients are marked under the client prefix with `disabled-` in front of it.
+    fn disabled_key(&self, client_id: &str) -> String {
+        format!("disabled-{}{}", self.client_prefix, client_id)
+    }
+
+    /// The metadata of clients is stored under the client prefix with `metadata-` in front of it.
+    fn metadata_key(&self, client_id: &str) -> String {
+        format!("metadata-{}{}", self.client_prefix, client_id)
+    }
+
+    fn exists(&self, key: String) -> anyhow::Result<bool> {
+        Ok(self.connection.get(&key)?.is_some())
+    }
+}
+
+impl OauthClientDBRepository for SpinRedisDataSource {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        let pattern = format!("{}*", self.client_prefix);
+        let keys = self
+            .connection
+            .execute("KEYS", &[RedisParameter::Binary(pattern.into_bytes())])?;
+
+        let mut encoded_clients = vec![];
+        for key in keys {
+            let key = match key {
+                RedisResult::Binary(key) => String::from_utf8(key)?,
+                _ => continue,
+            };
+            if let Some(client) = self.connection.get(&key)? {
+                encoded_clients.push(serde_json::from_slice(&client)?);
+            }
+        }
+        Ok(encoded_clients)
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        if self.exists(self.disabled_key(id))? {
+            return Err(anyhow::anyhow!("Client {} is disabled", id));
+        }
+        let client = self
+            .connection
+            .get(&self.key(id))?
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        Ok(serde_json::from_slice(&client)?)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let encoded = serde_json::to_vec(&client)?;
+        self.connection.set(&self.key(&client.client_id), &encoded)?;
+        Ok(())
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let encoded = serde_json::to_vec(&client)?;
+        // `XX` only replaces an existing key, the reply is nil otherwise.
+        let reply = self.connection.execute(
+            "SET",
+            &[
+                RedisParameter::Binary(self.key(&client.client_id).into_bytes()),
+                RedisParameter::Binary(encoded),
+                RedisParameter::Binary(b"XX".to_vec()),
+            ],
+        )?;
+        match reply.first() {
+            Some(RedisResult::Status(_)) => Ok(()),
+            _ => Err(anyhow::anyhow!("No client with id {}", client.client_id)),
+        }
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        self.connection
+            .del(&[self.disabled_key(id), self.metadata_key(id)])?;
+        Ok(self.connection.del(&[self.key(id)])? > 0)
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        if !self.exists(self.key(id))? {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        if disabled {
+            self.connection.set(&self.disabled_key(id), &b"1".to_vec())?;
+        } else {
+            self.connection.del(&[self.disabled_key(id)])?;
+        }
+        Ok(())
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        self.exists(self.disabled_key(id))
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        match self.connection.get(&self.metadata_key(id))? {
+            Some(metadata) => Ok(Some(serde_json::from_slice(&metadata)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        if !self.exists(self.key(id))? {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        self.connection
+            .set(&self.metadata_key(id), &serde_json::to_vec(metadata)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+//! Client storage in the SQLite database of a Spin component.
+//!
+//! The same database also holds the tables of `SpinSqliteAuthorizer` and `SpinSqliteIssuer`, the
+//! schema for all of them is created by [`migrate`].
+//!
+//! [`migrate`]: fn.migrate.html
+use crate::db_service::audit::{AuditEvent, AuditLog};
+use crate::db_service::consent::{Consent, ConsentStore};
+use crate::db_service::health::Health;
+use crate::db_service::migrations::{self, SchemaTarget};
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+use oxide_auth::primitives::registrar::EncodedClient;
+use spin_sdk::sqlite::{Connection, Value};
+
+/// Ordered schema migrations, identified by their version.
+///
+/// Each version is applied at most once, the applied versions are recorded in the
+/// `oxide_auth_migrations` table.
+pub const MIGRATIONS: &[(i64, &str)] = &[
+    (
+        1,
+        "CREATE TABLE IF NOT EXISTS oauth_clients (
+        client_id TEXT PRIMARY KEY NOT NULL,
+        client TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS oauth_codes (
+        code TEXT PRIMARY KEY NOT NULL,
+        grant_data TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS oauth_codes_expires_at ON oauth_codes (expires_at);
+    CREATE TABLE IF NOT EXISTS oauth_tokens (
+        access_token TEXT PRIMARY KEY NOT NULL,
+        refresh_token TEXT UNIQUE,
+        grant_data TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS oauth_tokens_expires_at ON oauth_tokens (expires_at);",
+    ),
+    (
+        2,
+        "ALTER TABLE oauth_tokens ADD COLUMN owner_id TEXT;
+    ALTER TABLE oauth_tokens ADD COLUMN client_id TEXT;
+    CREATE INDEX IF NOT EXISTS oauth_tokens_owner_id ON oauth_tokens (owner_id);
+    CREATE INDEX IF NOT EXISTS oauth_tokens_client_id ON oauth_tokens (client_id);",
+    ),
+    (
+        3,
+        "ALTER TABLE oauth_clients ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0;",
+    ),
+    (4, "ALTER TABLE oauth_clients ADD COLUMN metadata TEXT;"),
+    (
+        5,
+        "CREATE TABLE IF NOT EXISTS oauth_client_audit (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        client_id TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS oauth_client_audit_client_id ON oauth_client_audit (client_id);",
+    ),
+    (
+        6,
+        "CREATE TABLE IF NOT EXISTS oauth_consents (
+        owner_id TEXT NOT NULL,
+        client_id TEXT NOT NULL,
+        consent TEXT NOT NULL,
+        PRIMARY KEY (owner_id, client_id)
+    );",
+    ),
+    (
+        7,
+        "ALTER TABLE oauth_tokens ADD COLUMN refresh_expires_at INTEGER;",
+    ),
+    (
+        8,
+        "ALTER TABLE oauth_tokens ADD COLUMN session_expires_at INTEGER;",
+    ),
+];
+
+/// Bring the schema of the database up to date.
+///
+/// This is idempotent and cheap when all migrations were already applied, so it is safe to call
+/// on every component instantiation.
+pub fn migrate(connection: &Connection) -> anyhow::Result<()> {
+    migrations::run(&mut Target(connection), MIGRATIONS)?;
+    Ok(())
+}
+
+/// Spin connections are used through shared references.
+struct Target<'a>(&'a Connection);
+
+impl SchemaTarget for Target<'_> {
+    fn applied_versions(&mut self) -> anyhow::Result<Vec<i64>> {
+        self.0.execute(
+            "CREATE TABLE IF NOT EXISTS oxide_auth_migrations (version INTEGER PRIMARY KEY NOT NULL)",
+            &[],
+        )?;
+        let applied = self.0.execute("SELECT version FROM oxide_auth_migrations", &[])?;
+        Ok(applied.rows.iter().filter_map(|row| row.get(0)).collect())
+    }
+
+    fn apply(&mut self, version: i64, statements: &str) -> anyhow::Result<bool> {
+        // Takes the write lock right away, serializing concurrent migrations.
+        self.0.execute("BEGIN IMMEDIATE", &[])?;
+        match self.apply_locked(version, statements) {
+            Ok(true) => {
+                self.0.execute("COMMIT", &[])?;
+                Ok(true)
+            }
+            result => {
+                self.0.execute("ROLLBACK", &[])?;
+                result
+            }
+        }
+    }
+}
+
+impl Target<'_> {
+    /// Apply the migration within the transaction unless another process already did.
+    fn apply_locked(&mut self, version: i64, statements: &str) -> anyhow::Result<bool> {
+        let applied = self.0.execute(
+            "SELECT version FROM oxide_auth_migrations WHERE version = ?",
+            &[Value::Integer(version)],
+        )?;
+        if !applied.rows.is_empty() {
+            return Ok(false);
+        }
+
+        for statement in migrations::statements(statements) {
+            self.0.execute(statement, &[])?;
+        }
+        self.0.execute(
+            "INSERT INTO oxide_auth_migrations (version) VALUES (?)",
+            &[Value::Integer(version)],
+        )?;
+        Ok(true)
+    }
+}
+
+/// Probe the database with a trivial query.
+pub fn health_check(connection: &Connection) -> Health {
+    Health::probe("sqlite", || {
+        connection.execute("SELECT 1", &[])?;
+        Ok(())
+    })
+}
+
+/// Spin SQLite datasource to Client entries.
+pub struct SpinSqliteDataSource {
+    connection: Connection,
+}
+
+impl SpinSqliteDataSource {
+    /// Open the named database and apply all pending migrations.
+    pub fn open(database: &str) -> anyhow::Result<Self> {
+        Self::from_connection(Connection::open(database)?)
+    }
+
+    /// Open the `default` database of the component.
+    pub fn open_default() -> anyhow::Result<Self> {
+        Self::from_connection(Connection::open_default()?)
+    }
+
+    /// Use an existing connection, applying all pending migrations.
+    pub fn from_connection(connection: Connection) -> anyhow::Result<Self> {
+        migrate(&connection)?;
+        Ok(SpinSqliteDataSource { connection })
+    }
+
+    /// The underlying connection.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Probe the availability of the database.
+    pub fn health_check(&self) -> Health {
+        health_check(&self.connection)
+    }
+}
+
+impl OauthClientDBRepository for SpinSqliteDataSource {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        let result = self
+            .connection
+            .execute("SELECT client FROM oauth_clients ORDER BY client_id", &[])?;
+        result
+            .rows
+            .iter()
+            .map(|row| {
+                let client: &str = row
+                    .get(0)
+                    .ok_or_else(|| anyhow::anyhow!("Malformed client row"))?;
+                Ok(serde_json::from_str(client)?)
+            })
+            .collect()
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        let result = self.connection.execute(
+            "SELECT client FROM oauth_clients WHERE client_id = ? AND NOT disabled",
+            &[Value::Text(id.to_owned())],
+        )?;
+        let client: &str = result
+            .rows
+            .first()
+            .and_then(|row| row.get(0))
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        Ok(serde_json::from_str(client)?)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let encoded = serde_json::to_string(&client)?;
+        self.connection.execute(
+            "INSERT INTO oauth_clients (client_id, client) VALUES (?, ?)
+                ON CONFLICT (client_id) DO UPDATE SET client = excluded.client",
+            &[Value::Text(client.client_id), Value::Text(encoded)],
+        )?;
+        Ok(())
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let encoded = serde_json::to_string(&client)?;
+        let result = self.connection.execute(
+            "UPDATE oauth_clients SET client = ? WHERE client_id = ? RETURNING client_id",
+            &[Value::Text(encoded), Value::Text(client.client_id.clone())],
+        )?;
+        if result.rows.is_empty() {
+            return Err(anyhow::anyhow!("No client with id {}", client.client_id));
+        }
+        Ok(())
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        let result = self.connection.execute(
+            "DELETE FROM oauth_clients WHERE client_id = ? RETURNING client_id",
+            &[Value::Text(id.to_owned())],
+        )?;
+        Ok(!result.rows.is_empty())
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        let result = self.connection.execute(
+            "UPDATE oauth_clients SET disabled = ? WHERE client_id = ? RETURNING client_id",
+            &[Value::Integer(disabled.into()), Value::Text(id.to_owned())],
+        )?;
+        if result.rows.is_empty() {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        Ok(())
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        let result = self.connection.execute(
+            "SELECT disabled FROM oauth_clients WHERE client_id = ?",
+            &[Value::Text(id.to_owned())],
+        )?;
+        let disabled: i64 = result
+            .rows
+            .first()
+            .and_then(|row| row.get(0))
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        Ok(disabled != 0)
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        let result = self.connection.execute(
+            "SELECT metadata FROM oauth_clients WHERE client_id = ?",
+            &[Value::Text(id.to_owned())],
+        )?;
+        match result.rows.first().and_then(|row| row.get::<&str>(0)) {
+            Some(metadata) => Ok(Some(serde_json::from_str(metadata)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        let result = self.connection.execute(
+            "UPDATE oauth_clients SET metadata = ? WHERE client_id = ? RETURNING client_id",
+            &[
+                Value::Text(serde_json::to_string(metadata)?),
+                Value::Text(id.to_owned()),
+            ],
+        )?;
+        if result.rows.is_empty() {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        Ok(())
+    }
+}
+
+impl AuditLog for SpinSqliteDataSource {
+    fn append(&self, event: &AuditEvent) -> anyhow::Result<()> {
+        self.connection.execute(
+            "INSERT INTO oauth_client_audit (client_id, event) VALUES (?, ?)",
+            &[
+                Value::Text(event.client_id.clone()),
+                Value::Text(serde_json::to_string(event)?),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn events(&self, client_id: Option<&str>) -> anyhow::Result<Vec<AuditEvent>> {
+        let client_id = match client_id {
+            Some(id) => Value::Text(id.to_owned()),
+            None => Value::Null,
+        };
+        let result = self.connection.execute(
+            "SELECT event FROM oauth_client_audit WHERE ?1 IS NULL OR client_id = ?1 ORDER BY id",
+            &[client_id],
+        )?;
+        result
+            .rows
+            .iter()
+            .map(|row| {
+                let event: &str = row.get(0).ok_or_else(|| anyhow::anyhow!("Malformed audit row"))?;
+                Ok(serde_json::from_str(event)?)
+            })
+            .collect()
+    }
+}
+
+impl ConsentStore for SpinSqliteDataSource {
+    fn find_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<Option<Consent>> {
+        let result = self.connection.execute(
+            "SELECT consent FROM oauth_consents WHERE owner_id = ? AND client_id = ?",
+            &[
+                Value::Text(owner_id.to_owned()),
+                Value::Text(client_id.to_owned()),
+            ],
+        )?;
+        match result.rows.first().and_then(|row| row.get::<&str>(0)) {
+            Some(consent) => Ok(Some(serde_json::from_str(consent)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_consent(&self, consent: &Consent) -> anyhow::Result<()> {
+        self.connection.execute(
+            "INSERT INTO oauth_consents (owner_id, client_id, consent) VALUES (?, ?, ?)
+                ON CONFLICT (owner_id, client_id) DO UPDATE SET consent = excluded.consent",
+            &[
+                Value::Text(consent.owner_id.clone()),
+                Value::Text(consent.client_id.clone()),
+                Value::Text(serde_json::to_string(consent)?),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn revoke_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<bool> {
+        let result = self.connection.execute(
+            "DELETE FROM oauth_consents WHERE owner_id = ? AND client_id = ? RETURNING owner_id",
+            &[
+                Value::Text(owner_id.to_owned()),
+                Value::Text(client_id.to_owned()),
+            ],
+        )?;
+        Ok(!result.rows.is_empty())
+    }
+}
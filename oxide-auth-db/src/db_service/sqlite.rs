@@ -0,0 +1,412 @@
+//! Client storage in an embedded SQLite database, for single binary deployments.
+//!
+//! The same database also holds the tables of `SqliteAuthorizer` and `SqliteIssuer`, with the
+//! same layout as the Spin SQLite backend. The schema is created by [`migrate`] when the
+//! repository is opened and file databases are switched to write-ahead logging, so that readers do
+//! not block the writer.
+//!
+//! [`migrate`]: fn.migrate.html
+use crate::db_service::audit::{AuditEvent, AuditLog};
+use crate::db_service::consent::{Consent, ConsentStore};
+use crate::db_service::health::Health;
+use crate::db_service::migrations::{self, MigrationStatus, SchemaTarget};
+use crate::db_service::pool::{PoolConfig, RetryPolicy};
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+use std::path::Path;
+
+use oxide_auth::primitives::registrar::EncodedClient;
+use r2d2::Pool;
+use r2d2_sqlite::rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use r2d2_sqlite::SqliteConnectionManager;
+
+/// The pool of connections shared by the SQLite backed primitives.
+pub type SqlitePool = Pool<SqliteConnectionManager>;
+
+/// Ordered schema migrations, identified by their version.
+///
+/// Each version is applied at most once, the applied versions are recorded in the
+/// `oxide_auth_migrations` table.
+pub const MIGRATIONS: &[(i64, &str)] = &[
+    (
+        1,
+        "CREATE TABLE IF NOT EXISTS oauth_clients (
+        client_id TEXT PRIMARY KEY NOT NULL,
+        client TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS oauth_codes (
+        code TEXT PRIMARY KEY NOT NULL,
+        grant_data TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS oauth_codes_expires_at ON oauth_codes (expires_at);
+    CREATE TABLE IF NOT EXISTS oauth_tokens (
+        access_token TEXT PRIMARY KEY NOT NULL,
+        refresh_token TEXT UNIQUE,
+        grant_data TEXT NOT NULL,
+        expires_at INTEGER NOT NULL,
+        owner_id TEXT,
+        client_id TEXT
+    );
+    CREATE INDEX IF NOT EXISTS oauth_tokens_expires_at ON oauth_tokens (expires_at);
+    CREATE INDEX IF NOT EXISTS oauth_tokens_owner_id ON oauth_tokens (owner_id);
+    CREATE INDEX IF NOT EXISTS oauth_tokens_client_id ON oauth_tokens (client_id);",
+    ),
+    (
+        2,
+        "ALTER TABLE oauth_clients ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0;",
+    ),
+    (3, "ALTER TABLE oauth_clients ADD COLUMN metadata TEXT;"),
+    (
+        4,
+        "CREATE TABLE IF NOT EXISTS oauth_client_audit (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        client_id TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS oauth_client_audit_client_id ON oauth_client_audit (client_id);",
+    ),
+    (
+        5,
+        "CREATE TABLE IF NOT EXISTS oauth_consents (
+        owner_id TEXT NOT NULL,
+        client_id TEXT NOT NULL,
+        consent TEXT NOT NULL,
+        PRIMARY KEY (owner_id, client_id)
+    );",
+    ),
+    (
+        6,
+        "ALTER TABLE oauth_tokens ADD COLUMN refresh_expires_at INTEGER;
+    ALTER TABLE oauth_tokens ADD COLUMN session_expires_at INTEGER;",
+    ),
+];
+
+/// Bring the schema of the database up to date, returning the versions applied by this call.
+pub fn migrate(connection: &mut Connection) -> anyhow::Result<Vec<i64>> {
+    migrations::run(connection, MIGRATIONS)
+}
+
+/// Compare the schema of the database with the migrations of this crate.
+pub fn migration_status(connection: &mut Connection) -> anyhow::Result<MigrationStatus> {
+    migrations::status(connection, MIGRATIONS)
+}
+
+impl SchemaTarget for Connection {
+    fn applied_versions(&mut self) -> anyhow::Result<Vec<i64>> {
+        self.execute_batch(
+            "CREATE TABLE IF NOT EXISTS oxide_auth_migrations (version INTEGER PRIMARY KEY NOT NULL)",
+        )?;
+        let versions = self
+            .prepare("SELECT version FROM oxide_auth_migrations")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(versions)
+    }
+
+    fn apply(&mut self, version: i64, statements: &str) -> anyhow::Result<bool> {
+        // Takes the write lock right away, serializing concurrent migrations.
+        let transaction = self.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let applied = transaction
+            .query_row(
+                "SELECT version FROM oxide_auth_migrations WHERE version = ?1",
+                params![version],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+        if applied.is_some() {
+            return Ok(false);
+        }
+        transaction.execute_batch(statements)?;
+        transaction.execute(
+            "INSERT INTO oxide_auth_migrations (version) VALUES (?1)",
+            params![version],
+        )?;
+        transaction.commit()?;
+        Ok(true)
+    }
+}
+
+/// SQLite datasource to Client entries.
+#[derive(Clone, Debug)]
+pub struct SqliteClientRepository {
+    pool: SqlitePool,
+    retry: RetryPolicy,
+}
+
+impl SqliteClientRepository {
+    /// Open or create the database file, enabling write-ahead logging and applying all pending
+    /// migrations.
+    pub fn open<P: AsRef<Path>>(path: P, max_pool_size: u32) -> anyhow::Result<Self> {
+        Self::open_with_config(path, &PoolConfig::new(max_pool_size))
+    }
+
+    /// Open or create the database file with the pool and retry configuration.
+    pub fn open_with_config<P: AsRef<Path>>(path: P, config: &PoolConfig) -> anyhow::Result<Self> {
+        let manager = SqliteConnectionManager::file(path).with_init(|connection| {
+            connection.execute_batch("PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000;")
+        });
+        let pool = config.r2d2().build(manager)?;
+        Self::from_pool_with_retry(pool, config.retry())
+    }
+
+    /// Create a database that only lives in memory.
+    ///
+    /// Every connection to `:memory:` opens a separate database, so the pool holds exactly one.
+    pub fn in_memory() -> anyhow::Result<Self> {
+        let pool = Pool::builder()
+            .max_size(1)
+            .build(SqliteConnectionManager::memory())?;
+        Self::from_pool(pool)
+    }
+
+    /// Use an existing pool, applying all pending migrations.
+    pub fn from_pool(pool: SqlitePool) -> anyhow::Result<Self> {
+        Self::from_pool_with_retry(pool, RetryPolicy::default())
+    }
+
+    /// Use an existing pool and retry policy, applying all pending migrations.
+    pub fn from_pool_with_retry(pool: SqlitePool, retry: RetryPolicy) -> anyhow::Result<Self> {
+        retry.run(|| migrate(&mut *pool.get()?))?;
+        Ok(SqliteClientRepository { pool, retry })
+    }
+
+    /// The pool of this repository, to be shared with `SqliteAuthorizer` and `SqliteIssuer`.
+    pub fn get_pool(&self) -> SqlitePool {
+        self.pool.clone()
+    }
+
+    /// Apply all pending migrations, returning their versions.
+    pub fn migrate(&self) -> anyhow::Result<Vec<i64>> {
+        self.retry.run(|| migrate(&mut *self.pool.get()?))
+    }
+
+    /// Compare the schema of the database with the migrations of this crate.
+    pub fn migration_status(&self) -> anyhow::Result<MigrationStatus> {
+        self.retry.run(|| migration_status(&mut *self.pool.get()?))
+    }
+
+    /// The retry policy of this repository, shared with `SqliteIssuer`.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Probe the availability of the database.
+    pub fn health_check(&self) -> Health {
+        Health::probe("sqlite", || {
+            self.pool.get()?.execute_batch("SELECT 1")?;
+            Ok(())
+        })
+    }
+}
+
+impl OauthClientDBRepository for SqliteClientRepository {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        self.retry.run(|| {
+            let connection = self.pool.get()?;
+            let mut statement =
+                connection.prepare("SELECT client FROM oauth_clients ORDER BY client_id")?;
+            let clients = statement.query_map([], |row| row.get::<_, String>(0))?;
+            clients
+                .map(|client| {
+                    let client: String = client?;
+                    Ok(serde_json::from_str::<EncodedClient>(&client)?)
+                })
+                .collect()
+        })
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        let client: String = self
+            .retry
+            .run(|| {
+                self.pool
+                    .get()?
+                    .query_row(
+                        "SELECT client FROM oauth_clients WHERE client_id = ?1 AND NOT disabled",
+                        params![id],
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .map_err(anyhow::Error::from)
+            })?
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))?;
+        Ok(serde_json::from_str(&client)?)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let encoded = serde_json::to_string(&client)?;
+        self.retry.run(|| {
+            self.pool.get()?.execute(
+                "INSERT INTO oauth_clients (client_id, client) VALUES (?1, ?2)
+                    ON CONFLICT (client_id) DO UPDATE SET client = excluded.client",
+                params![client.client_id, encoded],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        let encoded = serde_json::to_string(&client)?;
+        let updated = self.retry.run(|| {
+            self.pool
+                .get()?
+                .execute(
+                    "UPDATE oauth_clients SET client = ?2 WHERE client_id = ?1",
+                    params![client.client_id, encoded],
+                )
+                .map_err(anyhow::Error::from)
+        })?;
+        if updated == 0 {
+            return Err(anyhow::anyhow!("No client with id {}", client.client_id));
+        }
+        Ok(())
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        let deleted = self.retry.run(|| {
+            self.pool
+                .get()?
+                .execute("DELETE FROM oauth_clients WHERE client_id = ?1", params![id])
+                .map_err(anyhow::Error::from)
+        })?;
+        Ok(deleted > 0)
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        let updated = self.retry.run(|| {
+            self.pool
+                .get()?
+                .execute(
+                    "UPDATE oauth_clients SET disabled = ?2 WHERE client_id = ?1",
+                    params![id, disabled],
+                )
+                .map_err(anyhow::Error::from)
+        })?;
+        if updated == 0 {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        Ok(())
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        self.retry
+            .run(|| {
+                self.pool
+                    .get()?
+                    .query_row(
+                        "SELECT disabled FROM oauth_clients WHERE client_id = ?1",
+                        params![id],
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .map_err(anyhow::Error::from)
+            })?
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        let metadata: Option<Option<String>> = self.retry.run(|| {
+            self.pool
+                .get()?
+                .query_row(
+                    "SELECT metadata FROM oauth_clients WHERE client_id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(anyhow::Error::from)
+        })?;
+        match metadata.flatten() {
+            Some(metadata) => Ok(Some(serde_json::from_str(&metadata)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        let encoded = serde_json::to_string(metadata)?;
+        let updated = self.retry.run(|| {
+            self.pool
+                .get()?
+                .execute(
+                    "UPDATE oauth_clients SET metadata = ?2 WHERE client_id = ?1",
+                    params![id, encoded],
+                )
+                .map_err(anyhow::Error::from)
+        })?;
+        if updated == 0 {
+            return Err(anyhow::anyhow!("No client with id {}", id));
+        }
+        Ok(())
+    }
+}
+
+impl AuditLog for SqliteClientRepository {
+    fn append(&self, event: &AuditEvent) -> anyhow::Result<()> {
+        let encoded = serde_json::to_string(event)?;
+        self.retry.run(|| {
+            self.pool.get()?.execute(
+                "INSERT INTO oauth_client_audit (client_id, event) VALUES (?1, ?2)",
+                params![event.client_id, encoded],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn events(&self, client_id: Option<&str>) -> anyhow::Result<Vec<AuditEvent>> {
+        self.retry.run(|| {
+            let connection = self.pool.get()?;
+            let mut statement = connection.prepare(
+                "SELECT event FROM oauth_client_audit WHERE ?1 IS NULL OR client_id = ?1 ORDER BY id",
+            )?;
+            let events = statement.query_map(params![client_id], |row| row.get::<_, String>(0))?;
+            events.map(|event| Ok(serde_json::from_str(&event?)?)).collect()
+        })
+    }
+}
+
+impl ConsentStore for SqliteClientRepository {
+    fn find_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<Option<Consent>> {
+        let consent: Option<String> = self.retry.run(|| {
+            self.pool
+                .get()?
+                .query_row(
+                    "SELECT consent FROM oauth_consents WHERE owner_id = ?1 AND client_id = ?2",
+                    params![owner_id, client_id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(anyhow::Error::from)
+        })?;
+        match consent {
+            Some(consent) => Ok(Some(serde_json::from_str(&consent)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_consent(&self, consent: &Consent) -> anyhow::Result<()> {
+        let encoded = serde_json::to_string(consent)?;
+        self.retry.run(|| {
+            self.pool.get()?.execute(
+                "INSERT INTO oauth_consents (owner_id, client_id, consent) VALUES (?1, ?2, ?3)
+                    ON CONFLICT (owner_id, client_id) DO UPDATE SET consent = excluded.consent",
+                params![consent.owner_id, consent.client_id, encoded],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn revoke_consent(&self, owner_id: &str, client_id: &str) -> anyhow::Result<bool> {
+        let deleted = self.retry.run(|| {
+            self.pool
+                .get()?
+                .execute(
+                    "DELETE FROM oauth_consents WHERE owner_id = ?1 AND client_id = ?2",
+                    params![owner_id, client_id],
+                )
+                .map_err(anyhow::Error::from)
+        })?;
+        Ok(deleted > 0)
+    }
+}
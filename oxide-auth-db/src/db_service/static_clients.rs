@@ -0,0 +1,193 @@
+//! A fixed set of clients read from configuration.
+//!
+//! Suitable for small deployments where clients are known ahead of time and no database is
+//! available for registration. The clients are described in TOML:
+//!
+//! ```toml
+//! [[clients]]
+//! client_id = "LocalClient"
+//! redirect_uri = "http://localhost:8021/endpoint"
+//! additional_redirect_uris = []
+//! default_scope = "default-scope"
+//! # Encoded by the password policy of the `DBRegistrar`, Argon2 by default. Omit for public clients.
+//! client_secret = "$argon2i$v=19$m=4096,t=3,p=1$..."
+//! ```
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use oxide_auth::primitives::prelude::Scope;
+use oxide_auth::primitives::registrar::{
+    ClientType, EncodedClient, ExactUrl, RedirectMatching, RegisteredUrl, TlsClientAuth,
+};
+use serde::Deserialize;
+
+use crate::primitives::db_registrar::OauthClientDBRepository;
+
+/// The configured description of a single client.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClientConfig {
+    /// The id of this client.
+    pub client_id: String,
+
+    /// The registered redirect uri.
+    pub redirect_uri: String,
+
+    /// The redirect uris that can be used in addition to the `redirect_uri`.
+    #[serde(default)]
+    pub additional_redirect_uris: Vec<String>,
+
+    /// The scope the client gets if none was given.
+    #[serde(default)]
+    pub default_scope: String,
+
+    /// The client secret as encoded by the password policy, `None` for public clients.
+    pub client_secret: Option<String>,
+
+    /// The certificates accepted for mutual TLS authentication of the client.
+    #[serde(default)]
+    pub tls_client_auth: Option<TlsClientAuth>,
+
+    /// The JSON Web Key Set of the client's public keys.
+    #[serde(default)]
+    pub jwks: Option<String>,
+
+    /// The secret of the client's `client_secret_jwt` assertions.
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
+
+    /// How redirect URIs of requests are matched, `exact`, `loopback` or `prefix`.
+    #[serde(default)]
+    pub redirect_matching: RedirectMatching,
+}
+
+#[derive(Deserialize)]
+struct ClientsFile {
+    #[serde(default)]
+    clients: Vec<ClientConfig>,
+}
+
+/// A read-only repository of clients loaded from configuration.
+#[derive(Clone, Debug, Default)]
+pub struct StaticClientRepository {
+    clients: HashMap<String, EncodedClient>,
+}
+
+impl ClientConfig {
+    /// Convert into the stored form used by `DBRegistrar`.
+    pub fn to_encoded_client(&self) -> anyhow::Result<EncodedClient> {
+        let redirect_uri = RegisteredUrl::from(ExactUrl::from_str(&self.redirect_uri)?);
+        let additional_redirect_uris = self
+            .additional_redirect_uris
+            .iter()
+            .map(|uri| Ok(RegisteredUrl::from(ExactUrl::from_str(uri)?)))
+            .collect::<anyhow::Result<_>>()?;
+        let default_scope = Scope::from_str(&self.default_scope)
+            .map_err(|_| anyhow::anyhow!("Invalid default scope of client {}", self.client_id))?;
+        let encoded_client = match &self.client_secret {
+            None => ClientType::Public,
+            Some(secret) => ClientType::Confidential {
+                passdata: secret.as_bytes().to_vec(),
+            },
+        };
+
+        Ok(EncodedClient {
+            client_id: self.client_id.clone(),
+            redirect_uri,
+            additional_redirect_uris,
+            default_scope,
+            encoded_client,
+            tls_client_auth: self.tls_client_auth.clone(),
+            jwks: self.jwks.clone(),
+            jwt_secret: self.jwt_secret.as_ref().map(|secret| secret.as_bytes().to_vec()),
+            redirect_matching: self.redirect_matching,
+        })
+    }
+}
+
+impl StaticClientRepository {
+    /// Create a repository from already parsed client descriptions.
+    pub fn new<I: IntoIterator<Item = ClientConfig>>(clients: I) -> anyhow::Result<Self> {
+        let clients = clients
+            .into_iter()
+            .map(|client| Ok((client.client_id.clone(), client.to_encoded_client()?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(StaticClientRepository { clients })
+    }
+
+    /// Parse the clients from a TOML document with a `[[clients]]` array.
+    pub fn from_toml(document: &str) -> anyhow::Result<Self> {
+        let file: ClientsFile = toml::from_str(document)?;
+        Self::new(file.clients)
+    }
+
+    /// Parse the clients from the TOML document stored in a Spin variable.
+    #[cfg(feature = "with-spin")]
+    pub fn from_spin_variable(name: &str) -> anyhow::Result<Self> {
+        let document = spin_sdk::variables::get(name)?;
+        Self::from_toml(&document)
+    }
+}
+
+impl OauthClientDBRepository for StaticClientRepository {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        Ok(self.clients.values().cloned().collect())
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        self.clients
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))
+    }
+
+    fn regist_from_encoded_client(&self, _: EncodedClient) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("Static client configuration can not be modified"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::db_registrar::DBRegistrar;
+    use oxide_auth::primitives::registrar::{Argon2, PasswordPolicy, Registrar};
+
+    #[test]
+    fn load_and_check() {
+        let secret = Argon2::default().store("LocalClient", b"WOJJCcS8WyS2aGmJK6ZADg==");
+        let document = format!(
+            r#"
+            [[clients]]
+            client_id = "LocalClient"
+            redirect_uri = "http://localhost:8021/endpoint"
+            default_scope = "default-scope"
+            client_secret = "{}"
+
+            [[clients]]
+            client_id = "PublicClient"
+            redirect_uri = "http://localhost:8021/public"
+            additional_redirect_uris = ["http://localhost:8021/other"]
+            "#,
+            String::from_utf8(secret).unwrap()
+        );
+
+        let repo = StaticClientRepository::from_toml(&document).unwrap();
+        assert_eq!(repo.list().unwrap().len(), 2);
+        assert_eq!(
+            repo.find_client_by_id("PublicClient")
+                .unwrap()
+                .additional_redirect_uris
+                .len(),
+            1
+        );
+
+        let registrar = DBRegistrar::with_repository(repo);
+        registrar
+            .check("LocalClient", Some(b"WOJJCcS8WyS2aGmJK6ZADg=="))
+            .expect("Configured secret was not accepted");
+        assert!(registrar.check("LocalClient", Some(b"wrong")).is_err());
+        registrar
+            .check("PublicClient", None)
+            .expect("Public client required authentication");
+        assert!(registrar.check("Unknown", None).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+//! Reporting the latency, failures and cache hit rate of the storage layer.
+//!
+//! An [`Instrumentation`] is told about every storage operation and every cache lookup. Client
+//! repositories are wrapped in an [`InstrumentedRepository`], key-value stores, and with them the
+//! codes and tokens of the `Kv*` primitives, in an [`InstrumentedBackend`], and a
+//! `CachedRepository` reports its lookups with `with_instrumentation`:
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use oxide_auth_db::db_service::cache::CachedRepository;
+//! # use oxide_auth_db::db_service::kv::{KvClientRepository, MemoryStore};
+//! # use oxide_auth_db::db_service::telemetry::*;
+//! # use oxide_auth_db::primitives::db_registrar::DBRegistrar;
+//! let metrics = Arc::new(StorageMetrics::new());
+//! let store = InstrumentedBackend::new(MemoryStore::new(), "memory", metrics.clone());
+//! let clients = KvClientRepository::new(store);
+//! let repository = InstrumentedRepository::new(clients, "clients", metrics.clone());
+//! let registrar = DBRegistrar::with_repository(
+//!     CachedRepository::new(repository).with_instrumentation("clients", metrics.clone()),
+//! );
+//!
+//! for (operation, stats) in metrics.operations() {
+//!     println!("{}: {} calls, {} errors, {:?} mean", operation, stats.count, stats.errors, stats.mean());
+//! }
+//! ```
+//!
+//! `StorageMetrics` aggregates the reports in memory for exporting them periodically, and
+//! `LogInstrumentation` writes them to the `log` facade. Other metrics or tracing libraries are
+//! connected by implementing `Instrumentation`.
+//!
+//! [`Instrumentation`]: trait.Instrumentation.html
+//! [`InstrumentedRepository`]: struct.InstrumentedRepository.html
+//! [`InstrumentedBackend`]: struct.InstrumentedBackend.html
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use oxide_auth::primitives::registrar::EncodedClient;
+
+use crate::db_service::kv::KeyValueBackend;
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+/// Receives reports about the storage layer.
+///
+/// Both methods do nothing by default, so implementations only provide the reports they need.
+pub trait Instrumentation {
+    /// An operation of the named store completed after `elapsed`, successfully or not.
+    fn operation(&self, store: &str, operation: &str, elapsed: Duration, success: bool) {
+        let _ = (store, operation, elapsed, success);
+    }
+
+    /// A lookup in the named cache was answered from the cache, or had to go to the store.
+    fn cache_lookup(&self, cache: &str, hit: bool) {
+        let _ = (cache, hit);
+    }
+}
+
+impl<I: Instrumentation + ?Sized> Instrumentation for Box<I> {
+    fn operation(&self, store: &str, operation: &str, elapsed: Duration, success: bool) {
+        (**self).operation(store, operation, elapsed, success)
+    }
+
+    fn cache_lookup(&self, cache: &str, hit: bool) {
+        (**self).cache_lookup(cache, hit)
+    }
+}
+
+impl<I: Instrumentation + ?Sized> Instrumentation for Arc<I> {
+    fn operation(&self, store: &str, operation: &str, elapsed: Duration, success: bool) {
+        (**self).operation(store, operation, elapsed, success)
+    }
+
+    fn cache_lookup(&self, cache: &str, hit: bool) {
+        (**self).cache_lookup(cache, hit)
+    }
+}
+
+/// Writes failures as warnings and everything else as debug messages to the `log` facade.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogInstrumentation;
+
+impl Instrumentation for LogInstrumentation {
+    fn operation(&self, store: &str, operation: &str, elapsed: Duration, success: bool) {
+        if success {
+            log::debug!("{} {} took {:?}", store, operation, elapsed);
+        } else {
+            log::warn!("{} {} failed after {:?}", store, operation, elapsed);
+        }
+    }
+
+    fn cache_lookup(&self, cache: &str, hit: bool) {
+        log::debug!("{} lookup {}", cache, if hit { "hit" } else { "missed" });
+    }
+}
+
+/// The aggregated reports of one operation of one store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OperationStats {
+    /// How often the operation ran.
+    pub count: u64,
+
+    /// How often the operation failed.
+    pub errors: u64,
+
+    /// The summed latency of all runs.
+    pub total: Duration,
+
+    /// The latency of the slowest run.
+    pub max: Duration,
+}
+
+impl OperationStats {
+    /// The mean latency, zero if the operation never ran.
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::default(),
+            count => Duration::from_nanos((self.total.as_nanos() / u128::from(count)) as u64),
+        }
+    }
+}
+
+/// The aggregated lookups of one cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+
+    /// Lookups that went to the store.
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// The share of lookups answered from the cache, zero if there were none.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+/// Aggregates reports in memory, keyed by `store/operation` and by cache name.
+#[derive(Debug)]
+pub struct StorageMetrics {
+    state: Mutex<MetricsState>,
+}
+
+#[derive(Debug)]
+struct MetricsState {
+    operations: BTreeMap<String, OperationStats>,
+    caches: BTreeMap<String, CacheStats>,
+    since: DateTime<Utc>,
+}
+
+impl StorageMetrics {
+    /// Start aggregating without any reports.
+    pub fn new() -> Self {
+        StorageMetrics {
+            state: Mutex::new(MetricsState {
+                operations: BTreeMap::new(),
+                caches: BTreeMap::new(),
+                since: Utc::now(),
+            }),
+        }
+    }
+
+    /// The statistics of every reported operation.
+    pub fn operations(&self) -> BTreeMap<String, OperationStats> {
+        self.lock().operations.clone()
+    }
+
+    /// The statistics of every reported cache.
+    pub fn caches(&self) -> BTreeMap<String, CacheStats> {
+        self.lock().caches.clone()
+    }
+
+    /// When aggregation started, or was last reset.
+    pub fn since(&self) -> DateTime<Utc> {
+        self.lock().since
+    }
+
+    /// Drop all aggregated reports, for exporters that report per interval.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.operations.clear();
+        state.caches.clear();
+        state.since = Utc::now();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MetricsState> {
+        // Statistics stay usable after a panic while holding the lock.
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for StorageMetrics {
+    fn default() -> Self {
+        StorageMetrics::new()
+    }
+}
+
+impl Instrumentation for StorageMetrics {
+    fn operation(&self, store: &str, operation: &str, elapsed: Duration, success: bool) {
+        let mut state = self.lock();
+        let stats = state
+            .operations
+            .entry(format!("{}/{}", store, operation))
+            .or_default();
+        stats.count += 1;
+        stats.errors += u64::from(!success);
+        stats.total += elapsed;
+        stats.max = stats.max.max(elapsed);
+    }
+
+    fn cache_lookup(&self, cache: &str, hit: bool) {
+        let mut state = self.lock();
+        let stats = state.caches.entry(cache.to_owned()).or_default();
+        if hit {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+    }
+}
+
+/// Run the operation and report its latency and outcome.
+fn observe<T, I: Instrumentation + ?Sized>(
+    instrumentation: &I, store: &str, operation: &str, run: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let start = Instant::now();
+    let result = run();
+    instrumentation.operation(store, operation, start.elapsed(), result.is_ok());
+    result
+}
+
+/// Reports every operation of a client repository.
+///
+/// Lookups of unknown or disabled clients are reported as failures, as they are by the repository.
+#[derive(Clone, Debug)]
+pub struct InstrumentedRepository<R: OauthClientDBRepository, I: Instrumentation> {
+    inner: R,
+    name: String,
+    instrumentation: I,
+}
+
+impl<R: OauthClientDBRepository, I: Instrumentation> InstrumentedRepository<R, I> {
+    /// Report the operations of the repository under the name.
+    pub fn new(inner: R, name: &str, instrumentation: I) -> Self {
+        InstrumentedRepository {
+            inner,
+            name: name.to_owned(),
+            instrumentation,
+        }
+    }
+
+    /// The wrapped repository.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    fn observe<T>(&self, operation: &str, run: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+        observe(&self.instrumentation, &self.name, operation, run)
+    }
+}
+
+impl<R: OauthClientDBRepository, I: Instrumentation> OauthClientDBRepository
+    for InstrumentedRepository<R, I>
+{
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        self.observe("list", || self.inner.list())
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        self.observe("find_client_by_id", || self.inner.find_client_by_id(id))
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.observe("regist_from_encoded_client", || {
+            self.inner.regist_from_encoded_client(client)
+        })
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.observe("update_client", || self.inner.update_client(client))
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        self.observe("delete_client", || self.inner.delete_client(id))
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        self.observe("set_client_disabled", || {
+            self.inner.set_client_disabled(id, disabled)
+        })
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        self.observe("is_client_disabled", || self.inner.is_client_disabled(id))
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        self.observe("find_client_metadata", || self.inner.find_client_metadata(id))
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        self.observe("store_client_metadata", || {
+            self.inner.store_client_metadata(id, metadata)
+        })
+    }
+}
+
+/// Reports every operation of a key-value store.
+#[derive(Clone, Debug)]
+pub struct InstrumentedBackend<B: KeyValueBackend, I: Instrumentation> {
+    inner: B,
+    name: String,
+    instrumentation: I,
+}
+
+impl<B: KeyValueBackend, I: Instrumentation> InstrumentedBackend<B, I> {
+    /// Report the operations of the store under the name.
+    pub fn new(inner: B, name: &str, instrumentation: I) -> Self {
+        InstrumentedBackend {
+            inner,
+            name: name.to_owned(),
+            instrumentation,
+        }
+    }
+
+    /// The wrapped store.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn observe<T>(&self, operation: &str, run: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+        observe(&self.instrumentation, &self.name, operation, run)
+    }
+}
+
+impl<B: KeyValueBackend, I: Instrumentation> KeyValueBackend for InstrumentedBackend<B, I> {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.observe("get", || self.inner.get(key))
+    }
+
+    fn set(&self, key: &str, value: &[u8], expires_at: Option<DateTime<Utc>>) -> anyhow::Result<()> {
+        self.observe("set", || self.inner.set(key, value, expires_at))
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        self.observe("delete", || self.inner.delete(key))
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        self.observe("scan_prefix", || self.inner.scan_prefix(prefix))
+    }
+
+    fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool> {
+        self.observe("compare_and_swap", || self.inner.compare_and_swap(key, old, new))
+    }
+
+    fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.observe("take", || self.inner.take(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::registrar::{Client, RegisteredUrl, Registrar};
+
+    use crate::db_service::cache::CachedRepository;
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::primitives::db_registrar::DBRegistrar;
+
+    #[test]
+    fn metrics_are_aggregated() {
+        let metrics = Arc::new(StorageMetrics::new());
+        let store = InstrumentedBackend::new(MemoryStore::new(), "memory", metrics.clone());
+        let repository =
+            InstrumentedRepository::new(KvClientRepository::new(store), "clients", metrics.clone());
+        let mut registrar = DBRegistrar::with_repository(
+            CachedRepository::new(repository).with_instrumentation("clients", metrics.clone()),
+        );
+        registrar
+            .register_client(Client::public(
+                "Client",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+            ))
+            .unwrap();
+        registrar.check("Client", None).unwrap();
+        registrar.check("Client", None).unwrap();
+        assert!(registrar.check("Unknown", None).is_err());
+
+        let operations = metrics.operations();
+        let lookups = operations["clients/find_client_by_id"];
+        assert_eq!((lookups.count, lookups.errors), (1, 1));
+        assert!(operations["memory/get"].count >= 1);
+        let cache = metrics.caches()["clients"];
+        assert_eq!((cache.hits, cache.misses), (2, 1));
+        assert!((cache.hit_rate() - 2.0 / 3.0).abs() < 1e-9);
+
+        metrics.reset();
+        assert!(metrics.operations().is_empty());
+    }
+}
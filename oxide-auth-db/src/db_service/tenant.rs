@@ -0,0 +1,251 @@
+//! Partitioning one store between many tenants.
+//!
+//! Every tenant gets its own namespace by prefixing keys with its id and a colon. A
+//! [`TenantRepository`] does so for the client ids of any repository, a [`TenantBackend`] for all
+//! keys of a key-value store, so the `Kv*` primitives on it keep codes and tokens apart as well.
+//! Issuers and authorizers on shared SQL tables are partitioned with `TenantIssuer` and
+//! `TenantAuthorizer` of the `primitives::tenant` module.
+//!
+//! The tenant of a request is found by a [`TenantResolver`], for example from its host name, and
+//! the partitioned primitives are cheap to construct for every request:
+//!
+//! ```no_run
+//! # use oxide_auth_db::db_service::kv::{KvClientRepository, MemoryStore};
+//! # use oxide_auth_db::db_service::tenant::{TenantRepository, TenantResolver};
+//! # use oxide_auth_db::primitives::db_registrar::DBRegistrar;
+//! # struct Request { host: String }
+//! # let request = Request { host: "acme.example.com".into() };
+//! let repository = KvClientRepository::new(MemoryStore::new());
+//! let resolver = |request: &Request| request.host.split('.').next().map(str::to_owned);
+//!
+//! let tenant = resolver.resolve_tenant(&request).expect("Unknown tenant");
+//! let registrar = DBRegistrar::with_repository(TenantRepository::new(repository.clone(), &tenant)?);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! [`TenantRepository`]: struct.TenantRepository.html
+//! [`TenantBackend`]: struct.TenantBackend.html
+//! [`TenantResolver`]: trait.TenantResolver.html
+use chrono::{DateTime, Utc};
+use oxide_auth::primitives::registrar::EncodedClient;
+
+use crate::db_service::kv::KeyValueBackend;
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+/// Finds the tenant a request belongs to.
+///
+/// Implemented for all functions from a request to an optional tenant id.
+pub trait TenantResolver<Request: ?Sized> {
+    /// The id of the tenant of the request, or `None` if it belongs to no known tenant.
+    fn resolve_tenant(&self, request: &Request) -> Option<String>;
+}
+
+impl<Request: ?Sized, F> TenantResolver<Request> for F
+where
+    F: Fn(&Request) -> Option<String>,
+{
+    fn resolve_tenant(&self, request: &Request) -> Option<String> {
+        self(request)
+    }
+}
+
+/// The key prefix of a tenant.
+///
+/// Tenant ids are limited to ASCII letters, digits, `-` and `_`, so no prefix is the beginning of
+/// another.
+pub(crate) fn tenant_prefix(tenant: &str) -> anyhow::Result<String> {
+    let valid = !tenant.is_empty()
+        && tenant
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid {
+        return Err(anyhow::anyhow!("Invalid tenant id {:?}", tenant));
+    }
+    Ok(format!("{}:", tenant))
+}
+
+/// The clients of one tenant in a shared repository.
+///
+/// Clients are stored with the tenant prefix on their id. Lookups only find clients of the tenant
+/// and return them with their unprefixed id.
+#[derive(Clone, Debug)]
+pub struct TenantRepository<R: OauthClientDBRepository> {
+    inner: R,
+    prefix: String,
+}
+
+impl<R: OauthClientDBRepository> TenantRepository<R> {
+    /// Partition the repository for the tenant.
+    pub fn new(inner: R, tenant: &str) -> anyhow::Result<Self> {
+        Ok(TenantRepository {
+            inner,
+            prefix: tenant_prefix(tenant)?,
+        })
+    }
+
+    /// The shared repository.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    fn scoped(&self, id: &str) -> String {
+        format!("{}{}", self.prefix, id)
+    }
+
+    fn enter(&self, mut client: EncodedClient) -> EncodedClient {
+        client.client_id = self.scoped(&client.client_id);
+        client
+    }
+
+    fn leave(&self, mut client: EncodedClient) -> Option<EncodedClient> {
+        client.client_id = client.client_id.strip_prefix(&self.prefix)?.to_owned();
+        Some(client)
+    }
+}
+
+impl<R: OauthClientDBRepository> OauthClientDBRepository for TenantRepository<R> {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        Ok(self
+            .inner
+            .list()?
+            .into_iter()
+            .filter_map(|client| self.leave(client))
+            .collect())
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        let client = self.inner.find_client_by_id(&self.scoped(id))?;
+        self.leave(client)
+            .ok_or_else(|| anyhow::anyhow!("No client with id {}", id))
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.inner.regist_from_encoded_client(self.enter(client))
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.inner.update_client(self.enter(client))
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        self.inner.delete_client(&self.scoped(id))
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        self.inner.set_client_disabled(&self.scoped(id), disabled)
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        self.inner.is_client_disabled(&self.scoped(id))
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        self.inner.find_client_metadata(&self.scoped(id))
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        self.inner.store_client_metadata(&self.scoped(id), metadata)
+    }
+}
+
+/// The keys of one tenant in a shared key-value store.
+#[derive(Clone, Debug)]
+pub struct TenantBackend<B: KeyValueBackend> {
+    inner: B,
+    prefix: String,
+}
+
+impl<B: KeyValueBackend> TenantBackend<B> {
+    /// Partition the store for the tenant.
+    pub fn new(inner: B, tenant: &str) -> anyhow::Result<Self> {
+        Ok(TenantBackend {
+            inner,
+            prefix: tenant_prefix(tenant)?,
+        })
+    }
+
+    /// The shared store.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn scoped(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+impl<B: KeyValueBackend> KeyValueBackend for TenantBackend<B> {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.inner.get(&self.scoped(key))
+    }
+
+    fn set(&self, key: &str, value: &[u8], expires_at: Option<DateTime<Utc>>) -> anyhow::Result<()> {
+        self.inner.set(&self.scoped(key), value, expires_at)
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        self.inner.delete(&self.scoped(key))
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        Ok(self
+            .inner
+            .scan_prefix(&self.scoped(prefix))?
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix(&self.prefix)?.to_owned(), value)))
+            .collect())
+    }
+
+    fn compare_and_swap(
+        &self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>,
+    ) -> anyhow::Result<bool> {
+        self.inner.compare_and_swap(&self.scoped(key), old, new)
+    }
+
+    fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.inner.take(&self.scoped(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::registrar::{Client, RegisteredUrl, Registrar};
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::primitives::db_registrar::DBRegistrar;
+
+    #[test]
+    fn tenants_are_isolated() {
+        let repository = KvClientRepository::new(MemoryStore::new());
+        let registrar = |tenant: &str| {
+            DBRegistrar::with_repository(TenantRepository::new(repository.clone(), tenant).unwrap())
+        };
+        let client = Client::confidential(
+            "Client",
+            RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+            b"secret",
+        );
+
+        let mut acme = registrar("acme");
+        acme.register_client(client).unwrap();
+        acme.check("Client", Some(b"secret")).unwrap();
+        assert_eq!(acme.repo.list().unwrap()[0].client_id, "Client");
+
+        let other = registrar("other");
+        assert!(other.check("Client", Some(b"secret")).is_err());
+        assert!(other.repo.list().unwrap().is_empty());
+        assert!(TenantRepository::new(repository.clone(), "acme:other").is_err());
+
+        let store = MemoryStore::new();
+        let acme = TenantBackend::new(store.clone(), "acme").unwrap();
+        acme.set("code:1", b"grant", None).unwrap();
+        assert_eq!(acme.scan_prefix("code:").unwrap()[0].0, "code:1");
+        assert_eq!(
+            TenantBackend::new(store, "other").unwrap().get("code:1").unwrap(),
+            None
+        );
+    }
+}
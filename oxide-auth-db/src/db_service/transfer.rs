@@ -0,0 +1,285 @@
+//! Exporting and importing all clients of a repository.
+//!
+//! The export is a versioned JSON document independent of the backend, so it serves as a backup,
+//! for promoting clients from one environment to the next, and for moving them to another
+//! backend:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "clients": [
+//!     {
+//!       "client_id": "LocalClient",
+//!       "redirect_uri": { "Exact": "http://localhost:8021/endpoint" },
+//!       "additional_redirect_uris": [],
+//!       "default_scope": "default-scope",
+//!       "client_secret_hash": "JGFyZ29uMmkkdj0xOSRtPTQwOTYsdD0zLHA9MSQuLi4=",
+//!       "disabled": false
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Exported secrets are the base64 encoded hashes as stored, they are imported unchanged and keep
+//! working with the password policy that created them. A record may instead carry the plain
+//! `client_secret`, for example when written by hand, which is hashed with the password policy
+//! passed to [`import_clients`]. Hashes of an outdated policy are upgraded on the next successful
+//! authentication when the importing registrar uses a `SecretPolicy`.
+//!
+//! [`import_clients`]: fn.import_clients.html
+use std::str::FromStr;
+
+use oxide_auth::primitives::registrar::{
+    ClientType, EncodedClient, PasswordPolicy, RedirectMatching, RegisteredUrl, TlsClientAuth,
+};
+use oxide_auth::primitives::scope::Scope;
+use serde::{Deserialize, Serialize};
+
+use crate::primitives::db_registrar::OauthClientDBRepository;
+use crate::primitives::metadata::ClientMetadata;
+
+/// The version of the export format written by this crate.
+pub const EXPORT_VERSION: u32 = 1;
+
+/// All clients of a repository.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClientExport {
+    /// The version of the format, imports of other versions are refused.
+    pub version: u32,
+
+    /// The exported clients.
+    pub clients: Vec<ExportedClient>,
+}
+
+/// A client with its state and metadata.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExportedClient {
+    /// The id of the client.
+    pub client_id: String,
+
+    /// The default redirect uri.
+    pub redirect_uri: RegisteredUrl,
+
+    /// The redirect uris that can be used in addition to the `redirect_uri`.
+    #[serde(default)]
+    pub additional_redirect_uris: Vec<RegisteredUrl>,
+
+    /// The scope the client gets if none was given.
+    #[serde(default)]
+    pub default_scope: String,
+
+    /// The secret as stored by the password policy, base64 encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret_hash: Option<String>,
+
+    /// The plain secret, hashed on import. Never written by exports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+
+    /// The certificates accepted for mutual TLS authentication of the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_client_auth: Option<TlsClientAuth>,
+
+    /// The JSON Web Key Set of the client's public keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks: Option<String>,
+
+    /// The base64 encoded secret of `client_secret_jwt` assertions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwt_secret: Option<String>,
+
+    /// How redirect URIs of requests are matched.
+    #[serde(default)]
+    pub redirect_matching: RedirectMatching,
+
+    /// Whether the client is disabled.
+    #[serde(default)]
+    pub disabled: bool,
+
+    /// The descriptive metadata of the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ClientMetadata>,
+}
+
+impl ClientExport {
+    /// Parse an export, refusing unknown versions.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let export: ClientExport = serde_json::from_str(json)?;
+        export.check_version()?;
+        Ok(export)
+    }
+
+    /// Serialize the export as indented JSON.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    fn check_version(&self) -> anyhow::Result<()> {
+        if self.version != EXPORT_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported client export version {}",
+                self.version
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl ExportedClient {
+    fn export<R: OauthClientDBRepository + ?Sized>(
+        repository: &R, client: EncodedClient,
+    ) -> anyhow::Result<Self> {
+        let client_secret_hash = match &client.encoded_client {
+            ClientType::Public => None,
+            ClientType::Confidential { passdata } => Some(base64::encode(passdata)),
+        };
+        Ok(ExportedClient {
+            disabled: repository.is_client_disabled(&client.client_id)?,
+            metadata: repository.find_client_metadata(&client.client_id)?,
+            client_id: client.client_id,
+            redirect_uri: client.redirect_uri,
+            additional_redirect_uris: client.additional_redirect_uris,
+            default_scope: client.default_scope.to_string(),
+            client_secret_hash,
+            client_secret: None,
+            tls_client_auth: client.tls_client_auth,
+            jwks: client.jwks,
+            jwt_secret: client.jwt_secret.map(base64::encode),
+            redirect_matching: client.redirect_matching,
+        })
+    }
+
+    /// Convert into the stored form, hashing a plain secret with the policy.
+    pub fn to_encoded_client(&self, policy: &dyn PasswordPolicy) -> anyhow::Result<EncodedClient> {
+        let default_scope = Scope::from_str(&self.default_scope)
+            .map_err(|_| anyhow::anyhow!("Invalid default scope of client {}", self.client_id))?;
+        let encoded_client = match (&self.client_secret, &self.client_secret_hash) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow::anyhow!(
+                    "Client {} has both a secret and a secret hash",
+                    self.client_id
+                ))
+            }
+            (Some(secret), None) => ClientType::Confidential {
+                passdata: policy.store(&self.client_id, secret.as_bytes()),
+            },
+            (None, Some(hash)) => ClientType::Confidential {
+                passdata: base64::decode(hash)?,
+            },
+            (None, None) => ClientType::Public,
+        };
+
+        Ok(EncodedClient {
+            client_id: self.client_id.clone(),
+            redirect_uri: self.redirect_uri.clone(),
+            additional_redirect_uris: self.additional_redirect_uris.clone(),
+            default_scope,
+            encoded_client,
+            tls_client_auth: self.tls_client_auth.clone(),
+            jwks: self.jwks.clone(),
+            jwt_secret: match &self.jwt_secret {
+                None => None,
+                Some(secret) => Some(base64::decode(secret)?),
+            },
+            redirect_matching: self.redirect_matching,
+        })
+    }
+}
+
+/// Export all clients of the repository, including disabled ones.
+pub fn export_clients<R: OauthClientDBRepository + ?Sized>(
+    repository: &R,
+) -> anyhow::Result<ClientExport> {
+    let clients = repository
+        .list()?
+        .into_iter()
+        .map(|client| ExportedClient::export(repository, client))
+        .collect::<anyhow::Result<_>>()?;
+    Ok(ClientExport {
+        version: EXPORT_VERSION,
+        clients,
+    })
+}
+
+/// Import clients into the repository, returning how many were imported.
+///
+/// Clients with an id already in the repository are replaced. All records are checked before the
+/// first one is written, so a malformed export leaves the repository unchanged.
+pub fn import_clients<R: OauthClientDBRepository + ?Sized>(
+    repository: &R, export: &ClientExport, policy: &dyn PasswordPolicy,
+) -> anyhow::Result<usize> {
+    export.check_version()?;
+    let clients = export
+        .clients
+        .iter()
+        .map(|client| Ok((client, client.to_encoded_client(policy)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    for (record, client) in &clients {
+        repository.regist_from_encoded_client(client.clone())?;
+        if record.disabled || repository.is_client_disabled(&record.client_id)? {
+            repository.set_client_disabled(&record.client_id, record.disabled)?;
+        }
+        if let Some(metadata) = &record.metadata {
+            repository.store_client_metadata(&record.client_id, metadata)?;
+        }
+    }
+    Ok(clients.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::registrar::{Client, Registrar};
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::primitives::db_registrar::{DBRegistrar, DEFAULT_PASSWORD_POLICY};
+
+    #[test]
+    fn export_and_import() {
+        let mut source = DBRegistrar::with_repository(KvClientRepository::new(MemoryStore::new()));
+        let url = RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap());
+        let scope = "default".parse().unwrap();
+        source
+            .register_client(Client::confidential(
+                "Confidential",
+                url.clone(),
+                scope,
+                b"secret",
+            ))
+            .unwrap();
+        source
+            .register_client(Client::public("Public", url, "default".parse().unwrap()))
+            .unwrap();
+        source.disable_client("Public").unwrap();
+        let metadata = ClientMetadata {
+            client_name: Some("Example".to_owned()),
+            ..ClientMetadata::default()
+        };
+        source.set_client_metadata("Confidential", metadata).unwrap();
+
+        let json = export_clients(&source.repo).unwrap().to_json().unwrap();
+        assert!(!json.contains("\"client_secret\""));
+        let export = ClientExport::from_json(&json).unwrap();
+
+        let target = DBRegistrar::with_repository(KvClientRepository::new(MemoryStore::new()));
+        assert_eq!(
+            import_clients(&target.repo, &export, &*DEFAULT_PASSWORD_POLICY).unwrap(),
+            2
+        );
+        target.check("Confidential", Some(b"secret")).unwrap();
+        assert!(target.repo.is_client_disabled("Public").unwrap());
+        let metadata = target.client_metadata("Confidential").unwrap().unwrap();
+        assert_eq!(metadata.client_name.as_deref(), Some("Example"));
+
+        let mut plain = export.clone();
+        plain.clients.retain(|client| client.client_id == "Confidential");
+        plain.clients[0].client_secret_hash = None;
+        plain.clients[0].client_secret = Some("rotated".to_owned());
+        import_clients(&target.repo, &plain, &*DEFAULT_PASSWORD_POLICY).unwrap();
+        target.check("Confidential", Some(b"rotated")).unwrap();
+
+        plain.version = 2;
+        assert!(import_clients(&target.repo, &plain, &*DEFAULT_PASSWORD_POLICY).is_err());
+    }
+}
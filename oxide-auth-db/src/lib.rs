@@ -1,7 +1,8 @@
 pub mod db_service;
 pub mod primitives;
+pub mod registration;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "with-redis"))]
 fn requires_redis_and_should_skip() -> bool {
     match std::env::var("OXIDE_AUTH_SKIP_REDIS") {
         Err(_) => false,
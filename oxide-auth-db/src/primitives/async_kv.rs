@@ -0,0 +1,451 @@
+//! Authorizer and issuer on any `AsyncKeyValueBackend`.
+//!
+//! These store their codes and tokens exactly like `KvAuthorizer` and `KvIssuer`, so both kinds
+//! can share one store, but implement the asynchronous primitives of `oxide-auth-async`.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+use oxide_auth_async::primitives::{Authorizer, Issuer};
+use serde::de::DeserializeOwned;
+
+use crate::db_service::kv::AsyncKeyValueBackend;
+use crate::primitives::kv::decode;
+use crate::primitives::stored::{StoredGrant, StoredRefresh, StoredToken};
+use crate::primitives::{system_clock, SharedClock};
+
+/// An authorizer keeping its codes in an asynchronous key-value store.
+pub struct AsyncKvAuthorizer<
+    B: AsyncKeyValueBackend,
+    I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>,
+> {
+    backend: B,
+    tagger: I,
+    code_prefix: String,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// An issuer keeping its tokens in an asynchronous key-value store.
+pub struct AsyncKvIssuer<
+    B: AsyncKeyValueBackend,
+    G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>,
+> {
+    backend: B,
+    generator: G,
+    access_prefix: String,
+    refresh_prefix: String,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// Delete all entries under the prefix that expired before `now`, returning how many were removed.
+async fn purge<B, T, F>(backend: &B, prefix: &str, until: F, now: DateTime<Utc>) -> anyhow::Result<usize>
+where
+    B: AsyncKeyValueBackend,
+    T: DeserializeOwned,
+    F: Fn(&T) -> Option<DateTime<Utc>>,
+{
+    let mut removed = 0;
+    for (key, value) in backend.scan_prefix(prefix).await? {
+        let expired = match serde_json::from_slice::<T>(&value) {
+            Ok(entry) => until(&entry).is_some_and(|until| until < now),
+            Err(_) => true,
+        };
+        // Only remove the entry if it was not replaced since it was read.
+        if expired && backend.compare_and_swap(&key, Some(&value), None).await? {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+impl<B: AsyncKeyValueBackend, I: TagGrant> AsyncKvAuthorizer<B, I> {
+    /// Create an authorizer storing codes under `code:`.
+    pub fn new(backend: B, tagger: I) -> Self {
+        Self::with_prefix(backend, tagger, "code:".to_owned())
+    }
+
+    /// Create an authorizer storing codes under a custom prefix.
+    pub fn with_prefix(backend: B, tagger: I, code_prefix: String) -> Self {
+        AsyncKvAuthorizer {
+            backend,
+            tagger,
+            code_prefix,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Purge expired codes by the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Delete all codes whose grant has expired.
+    pub async fn purge_expired(&self) -> anyhow::Result<usize> {
+        let until = |grant: &StoredGrant| Some(grant.until);
+        purge(&self.backend, &self.code_prefix, until, self.clock.now()).await
+    }
+}
+
+#[async_trait]
+impl<B, I> Authorizer for AsyncKvAuthorizer<B, I>
+where
+    B: AsyncKeyValueBackend + Send + Sync,
+    I: TagGrant + Send,
+{
+    async fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let value = serde_json::to_vec(&StoredGrant::from_grant(&grant))
+            .map_err(|_| PrimitiveError::Invariant)?;
+        let key = format!("{}{}", self.code_prefix, code);
+        self.backend
+            .set(&key, &value, Some(grant.until))
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    async fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.code_prefix, code);
+        let stored: Option<StoredGrant> = decode(
+            self.backend
+                .take(&key)
+                .await
+                .map_err(|_| PrimitiveError::Unavailable)?,
+        )?;
+        match stored {
+            None => Ok(None),
+            Some(stored) => stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+}
+
+impl<B: AsyncKeyValueBackend, G: TagGrant> AsyncKvIssuer<B, G> {
+    /// Create an issuer storing tokens under `token:` and `refresh:`.
+    pub fn new(backend: B, generator: G) -> Self {
+        Self::with_prefixes(backend, generator, "token:".to_owned(), "refresh:".to_owned())
+    }
+
+    /// Create an issuer storing access and refresh tokens under custom prefixes.
+    pub fn with_prefixes(
+        backend: B, generator: G, access_prefix: String, refresh_prefix: String,
+    ) -> Self {
+        AsyncKvIssuer {
+            backend,
+            generator,
+            access_prefix,
+            refresh_prefix,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Delete all access tokens whose grant has expired and all expired refresh tokens.
+    pub async fn purge_expired(&self) -> anyhow::Result<usize> {
+        let now = self.clock.now();
+        let access_until = |token: &StoredToken| Some(token.grant.until);
+        let access = purge(&self.backend, &self.access_prefix, access_until, now).await?;
+        let refresh_until = |refresh: &StoredRefresh| refresh.until;
+        let refresh = purge(&self.backend, &self.refresh_prefix, refresh_until, now).await?;
+        Ok(access + refresh)
+    }
+
+    /// Store a new access token, with a refresh token unless the grant lacks `offline_access`.
+    async fn store_pair(
+        &mut self, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>,
+    ) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+
+        let token = StoredToken {
+            access: access.clone(),
+            refresh: refresh.clone(),
+            grant: StoredGrant::from_grant(grant),
+        };
+        let access_value = serde_json::to_vec(&token).map_err(|_| PrimitiveError::Invariant)?;
+        let access_key = format!("{}{}", self.access_prefix, access);
+        self.backend
+            .set(&access_key, &access_value, Some(grant.until))
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+
+        let refresh_key = match &refresh {
+            Some(refresh) => format!("{}{}", self.refresh_prefix, refresh),
+            None => return Ok((access, None)),
+        };
+        let refresh_entry = StoredRefresh::new(token, refresh_until, session_until);
+        let refresh_value = serde_json::to_vec(&refresh_entry).map_err(|_| PrimitiveError::Invariant)?;
+        self.backend
+            .set(&refresh_key, &refresh_value, refresh_entry.until)
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok((access, refresh))
+    }
+}
+
+#[async_trait]
+impl<B, G> Issuer for AsyncKvIssuer<B, G>
+where
+    B: AsyncKeyValueBackend + Send + Sync,
+    G: TagGrant + Send + Sync,
+{
+    async fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, session_until).await?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn refresh(
+        &mut self, refresh: &str, mut grant: Grant,
+    ) -> Result<RefreshedToken, PrimitiveError> {
+        let refresh_key = format!("{}{}", self.refresh_prefix, refresh);
+        // Should only be called on valid refresh tokens.
+        let taken = self
+            .backend
+            .take(&refresh_key)
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        let old: StoredRefresh = decode(taken)?.ok_or(PrimitiveError::NotFound)?;
+        // Invalidates the access token of the old pair as well.
+        let access_key = format!("{}{}", self.access_prefix, old.token.access);
+        self.backend
+            .delete(&access_key)
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, old.session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, old.session_until).await?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.access_prefix, token);
+        let stored: Option<StoredToken> = decode(
+            self.backend
+                .get(&key)
+                .await
+                .map_err(|_| PrimitiveError::Unavailable)?,
+        )?;
+        match stored {
+            None => Ok(None),
+            Some(stored) => stored
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+
+    async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.refresh_prefix, token);
+        let entry: Option<StoredRefresh> = decode(
+            self.backend
+                .get(&key)
+                .await
+                .map_err(|_| PrimitiveError::Unavailable)?,
+        )?;
+        match entry {
+            // The flows only check the expiry of the grant, not the one of the refresh token.
+            Some(entry) if entry.is_expired(self.clock.now()) => Ok(None),
+            Some(entry) => entry
+                .token
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+            None => Ok(None),
+        }
+    }
+
+    async fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        let refresh_key = format!("{}{}", self.refresh_prefix, token);
+        let taken = self
+            .backend
+            .take(&refresh_key)
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        // A refresh token takes its access token with it, but not the other way around.
+        let access = match decode::<StoredRefresh>(taken)? {
+            Some(refresh) => refresh.token.access,
+            None => token.to_owned(),
+        };
+        let access_key = format!("{}{}", self.access_prefix, access);
+        self.backend
+            .delete(&access_key)
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::prelude::Client;
+    use oxide_auth::primitives::registrar::RegisteredUrl;
+    use oxide_auth_async::primitives::Registrar;
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::primitives::async_registrar::AsyncDBRegistrar;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn register_and_check() {
+        let mut registrar =
+            AsyncDBRegistrar::with_repository(KvClientRepository::new(MemoryStore::new()));
+        registrar
+            .register_client(Client::confidential(
+                "KvClient",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .await
+            .unwrap();
+        registrar.check("KvClient", Some(b"secret")).await.unwrap();
+        assert!(registrar.check("KvClient", Some(b"wrong")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn code_is_single_use() {
+        let mut authorizer = AsyncKvAuthorizer::new(MemoryStore::new(), RandomGenerator::new(16));
+        let grant = grant();
+        let code = authorizer.authorize(grant.clone()).await.unwrap();
+        assert_eq!(authorizer.extract(&code).await.unwrap(), Some(grant));
+        assert_eq!(authorizer.extract(&code).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn refresh_replaces_pair() {
+        let store = MemoryStore::new();
+        let mut issuer = AsyncKvIssuer::new(store.clone(), RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).await.unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let refreshed = issuer.refresh(&refresh, grant()).await.unwrap();
+        assert!(issuer.recover_token(&issued.token).await.unwrap().is_none());
+        assert!(issuer.recover_refresh(&refresh).await.unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).await.unwrap().is_some());
+        assert!(issuer.refresh(&refresh, grant()).await.is_err());
+
+        let mut expired = grant();
+        expired.until = Utc::now() - Duration::minutes(1);
+        issuer.lifetime_policy(Lifetimes {
+            refresh: Some(Duration::minutes(-1)),
+            ..Lifetimes::default()
+        });
+        issuer.issue(expired).await.unwrap();
+        assert_eq!(issuer.purge_expired().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn revoke_refresh_pair() {
+        let mut issuer = AsyncKvIssuer::new(MemoryStore::new(), RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).await.unwrap();
+        let refresh = issued.refresh.unwrap();
+        issuer.revoke(&refresh).await.unwrap();
+        assert!(issuer.recover_refresh(&refresh).await.unwrap().is_none());
+        assert!(issuer.recover_token(&issued.token).await.unwrap().is_none());
+        issuer.revoke("unknown").await.unwrap();
+    }
+}
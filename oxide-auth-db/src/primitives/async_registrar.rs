@@ -0,0 +1,286 @@
+//! The asynchronous counterpart of `DBRegistrar`, for repositories on async database drivers.
+//!
+//! `AsyncDBRegistrar` implements the `Registrar` of `oxide-auth-async`, so it can be awaited
+//! within the handlers of async servers without blocking their executor.
+use async_trait::async_trait;
+use oxide_auth::primitives::prelude::{ClientUrl, PreGrant, Scope};
+use oxide_auth::primitives::scope::ScopePolicy;
+use oxide_auth::primitives::registrar::{
+    BoundClient, Client, EncodedClient, PasswordPolicy, RegisteredClient, RegistrarError,
+};
+use oxide_auth_async::primitives::Registrar;
+
+use crate::primitives::db_registrar::{
+    bind_redirect, negotiate_scope, with_secret, OauthClientDBRepository, DEFAULT_PASSWORD_POLICY,
+};
+use crate::primitives::metadata::ClientMetadata;
+use crate::primitives::secret_policy::SecretPolicy;
+use crate::registration::random_string;
+
+/// Methods to search and register clients, awaiting the database.
+///
+/// Every blocking `OauthClientDBRepository` is also an asynchronous one.
+#[async_trait]
+pub trait AsyncOauthClientDBRepository: Sync {
+    async fn list(&self) -> anyhow::Result<Vec<EncodedClient>>;
+
+    async fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient>;
+
+    async fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()>;
+
+    /// Replace the record of a client that is already registered.
+    async fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.find_client_by_id(&client.client_id).await?;
+        self.regist_from_encoded_client(client).await
+    }
+
+    /// Remove a client, returning whether it was registered.
+    async fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        Err(anyhow::anyhow!("Deleting client {} is not supported", id))
+    }
+
+    /// Disable or enable a registered client, disabled clients are not found.
+    async fn set_client_disabled(&self, id: &str, _disabled: bool) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("Disabling client {} is not supported", id))
+    }
+
+    /// Whether a registered client is currently disabled.
+    async fn is_client_disabled(&self, _id: &str) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    /// The descriptive metadata stored alongside a client, if any.
+    async fn find_client_metadata(&self, _id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        Ok(None)
+    }
+
+    /// Replace the descriptive metadata of a registered client.
+    async fn store_client_metadata(&self, id: &str, _metadata: &ClientMetadata) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Storing metadata of client {} is not supported",
+            id
+        ))
+    }
+}
+
+#[async_trait]
+impl<R: OauthClientDBRepository + Send + Sync + ?Sized> AsyncOauthClientDBRepository for R {
+    async fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        OauthClientDBRepository::list(self)
+    }
+
+    async fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        OauthClientDBRepository::find_client_by_id(self, id)
+    }
+
+    async fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        OauthClientDBRepository::regist_from_encoded_client(self, client)
+    }
+
+    async fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        OauthClientDBRepository::update_client(self, client)
+    }
+
+    async fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        OauthClientDBRepository::delete_client(self, id)
+    }
+
+    async fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        OauthClientDBRepository::set_client_disabled(self, id, disabled)
+    }
+
+    async fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        OauthClientDBRepository::is_client_disabled(self, id)
+    }
+
+    async fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        OauthClientDBRepository::find_client_metadata(self, id)
+    }
+
+    async fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        OauthClientDBRepository::store_client_metadata(self, id, metadata)
+    }
+}
+
+/// A registrar looking up clients in an asynchronous repository.
+pub struct AsyncDBRegistrar<R: AsyncOauthClientDBRepository> {
+    pub repo: R,
+    password_policy: Option<Box<dyn PasswordPolicy>>,
+    scope_policy: Option<Box<dyn ScopePolicy>>,
+    rehash: Option<SecretPolicy>,
+}
+
+impl<R: AsyncOauthClientDBRepository> AsyncDBRegistrar<R> {
+    /// Create a registrar on top of an already constructed repository.
+    pub fn with_repository(repo: R) -> Self {
+        AsyncDBRegistrar {
+            repo,
+            password_policy: None,
+            scope_policy: None,
+            rehash: None,
+        }
+    }
+
+    /// Insert or update the client record.
+    pub async fn register_client(&mut self, client: Client) -> Result<(), RegistrarError> {
+        let encoded_client = client.encode(self.current_policy());
+        self.repo
+            .regist_from_encoded_client(encoded_client)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Replace the record of a registered client, failing if there is none with its id.
+    pub async fn update_client(&mut self, client: Client) -> Result<(), RegistrarError> {
+        let encoded_client = client.encode(self.current_policy());
+        self.repo
+            .update_client(encoded_client)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Remove a client, returning whether it was registered.
+    pub async fn delete_client(&mut self, client_id: &str) -> Result<bool, RegistrarError> {
+        self.repo
+            .delete_client(client_id)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Refuse all requests of the client until it is enabled again.
+    pub async fn disable_client(&mut self, client_id: &str) -> Result<(), RegistrarError> {
+        self.repo
+            .set_client_disabled(client_id, true)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Accept the requests of a disabled client again.
+    pub async fn enable_client(&mut self, client_id: &str) -> Result<(), RegistrarError> {
+        self.repo
+            .set_client_disabled(client_id, false)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Replace the secret of a confidential client with a random one and return it.
+    pub async fn rotate_secret(&mut self, client_id: &str) -> Result<String, RegistrarError> {
+        let secret = random_string(32);
+        self.set_client_secret(client_id, secret.as_bytes()).await?;
+        Ok(secret)
+    }
+
+    /// Replace the secret of a confidential client.
+    pub async fn set_client_secret(
+        &mut self, client_id: &str, secret: &[u8],
+    ) -> Result<(), RegistrarError> {
+        let client = self
+            .repo
+            .find_client_by_id(client_id)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)?;
+        let client = with_secret(client, self.current_policy(), secret)?;
+        self.repo
+            .update_client(client)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// The descriptive metadata of a client, if any is stored.
+    pub async fn client_metadata(
+        &self, client_id: &str,
+    ) -> Result<Option<ClientMetadata>, RegistrarError> {
+        self.repo
+            .find_client_metadata(client_id)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Replace the descriptive metadata of a registered client.
+    ///
+    /// The creation time of previously stored metadata is kept and the update time set to now.
+    pub async fn set_client_metadata(
+        &mut self, client_id: &str, metadata: ClientMetadata,
+    ) -> Result<(), RegistrarError> {
+        let previous = self.client_metadata(client_id).await?;
+        let metadata = metadata.stamped(previous.as_ref());
+
+        self.repo
+            .store_client_metadata(client_id, &metadata)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Change how passwords are encoded while stored.
+    pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
+        self.password_policy = Some(Box::new(new_policy));
+        self.rehash = None;
+    }
+
+    /// Validate requested scopes with a policy instead of granting the default scope.
+    ///
+    /// See `ClientMap::set_scope_policy` for how the policy is applied.
+    pub fn set_scope_policy<P: ScopePolicy + 'static>(&mut self, new_policy: P) {
+        self.scope_policy = Some(Box::new(new_policy));
+    }
+
+    /// Hash secrets with the secret policy, re-hashing outdated secrets on authentication.
+    pub fn set_secret_policy(&mut self, new_policy: SecretPolicy) {
+        self.password_policy = Some(Box::new(new_policy.clone()));
+        self.rehash = Some(new_policy);
+    }
+
+    fn current_policy(&self) -> &dyn PasswordPolicy {
+        self.password_policy
+            .as_deref()
+            .unwrap_or(&*DEFAULT_PASSWORD_POLICY)
+    }
+}
+
+#[async_trait]
+impl<R: AsyncOauthClientDBRepository + Send + Sync> Registrar for AsyncDBRegistrar<R> {
+    async fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        let client = self
+            .repo
+            .find_client_by_id(&bound.client_id)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)?;
+        bind_redirect(&client, bound)
+    }
+
+    async fn negotiate<'a>(
+        &self, bound: BoundClient<'a>, scope: Option<Scope>,
+    ) -> Result<PreGrant, RegistrarError> {
+        let client = self
+            .repo
+            .find_client_by_id(&bound.client_id)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)?;
+        Ok(PreGrant {
+            scope: negotiate_scope(&self.scope_policy, scope, client.default_scope)?,
+            client_id: bound.client_id.into_owned(),
+            redirect_uri: bound.redirect_uri.into_owned(),
+        })
+    }
+
+    async fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        let client = self
+            .repo
+            .find_client_by_id(client_id)
+            .await
+            .map_err(|_e| RegistrarError::Unspecified)?;
+        RegisteredClient::new(&client, self.current_policy()).check_authentication(passphrase)?;
+
+        let rehashed = self
+            .rehash
+            .as_ref()
+            .and_then(|policy| policy.rehashed(&client, passphrase));
+        if let Some(rehashed) = rehashed {
+            // The client already authenticated, failing to upgrade its hash is not its problem.
+            if let Err(err) = self.repo.update_client(rehashed).await {
+                log::warn!("Failed to re-hash the secret of {}: {}", client.client_id, err);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! A solicitor that only asks resource owners for scopes they have not approved recently.
+//!
+//! The [`ConsentSolicitor`] wraps the solicitor rendering the consent page. When the owner of a
+//! request is already known, for example from a session cookie, and approved the requested scope
+//! for the client within the validity window, the request is authorized right away. Otherwise the
+//! wrapped solicitor asks the owner and an approval is remembered in the `ConsentStore`:
+//!
+//! ```no_run
+//! # use chrono::Duration;
+//! # use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+//! # use oxide_auth::frontends::simple::endpoint::FnSolicitor;
+//! # use oxide_auth::frontends::simple::request::{Request, Response};
+//! # use oxide_auth_db::db_service::consent::KvConsentStore;
+//! # use oxide_auth_db::db_service::kv::MemoryStore;
+//! # use oxide_auth_db::primitives::consent::ConsentSolicitor;
+//! fn session_owner(request: &mut Request) -> Option<String> {
+//!     // Look up the owner of the session of the request.
+//! #   let _ = request;
+//! #   None
+//! }
+//!
+//! fn consent_page(_: &mut Request, solicitation: Solicitation) -> OwnerConsent<Response> {
+//!     // Render the consent page or evaluate its submission.
+//! #   let _ = solicitation;
+//!     OwnerConsent::InProgress(Response::default())
+//! }
+//!
+//! let solicitor = ConsentSolicitor::new(
+//!     KvConsentStore::new(MemoryStore::new()),
+//!     session_owner,
+//!     FnSolicitor(consent_page),
+//! )
+//! .valid_for(Duration::days(90));
+//! ```
+//!
+//! Only the scope of the last approval is remembered, so a request for another scope is presented
+//! to the owner and its approval replaces the previous one.
+//!
+//! [`ConsentSolicitor`]: struct.ConsentSolicitor.html
+use std::sync::Arc;
+
+use chrono::Duration;
+use oxide_auth::endpoint::{OwnerConsent, OwnerSolicitor, Solicitation, WebRequest};
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::scope::{Scope, ScopePolicy};
+
+use crate::db_service::consent::{Consent, ConsentStore};
+use crate::primitives::{system_clock, SharedClock};
+
+/// Authorizes repeated requests from the consent store and asks the wrapped solicitor otherwise.
+pub struct ConsentSolicitor<S, F, O> {
+    store: S,
+    owner: F,
+    solicitor: O,
+    validity: Duration,
+    scope_policy: Option<Box<dyn ScopePolicy>>,
+    clock: SharedClock,
+}
+
+impl<S: ConsentStore, F, O> ConsentSolicitor<S, F, O> {
+    /// Remember approvals in the store for 30 days.
+    ///
+    /// The function returns the authenticated owner of a request, if any, without asking them.
+    pub fn new(store: S, owner: F, solicitor: O) -> Self {
+        ConsentSolicitor {
+            store,
+            owner,
+            solicitor,
+            validity: Duration::days(30),
+            scope_policy: None,
+            clock: system_clock(),
+        }
+    }
+
+    /// Authorize repeated requests for the duration after an approval.
+    pub fn valid_for(mut self, validity: Duration) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    /// Compare requested scopes with the approved scope through a policy.
+    ///
+    /// By default an approval only covers the scope-tokens it contains. With a policy, approving
+    /// `repo:admin` may for example also cover later requests for `repo:read`.
+    pub fn scope_policy<P: ScopePolicy + 'static>(mut self, policy: P) -> Self {
+        self.scope_policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Date approvals and check their validity by the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn approves(&self, consent: &Consent, scope: &Scope) -> bool {
+        let now = self.clock.now();
+        match &self.scope_policy {
+            Some(policy) => consent.allows_with(&**policy, scope, self.validity, now),
+            None => consent.allows(scope, self.validity, now),
+        }
+    }
+}
+
+impl<W, S, F, O> OwnerSolicitor<W> for ConsentSolicitor<S, F, O>
+where
+    W: WebRequest,
+    S: ConsentStore,
+    F: FnMut(&mut W) -> Option<String>,
+    O: OwnerSolicitor<W>,
+{
+    fn check_consent(
+        &mut self, request: &mut W, solicitation: Solicitation,
+    ) -> OwnerConsent<W::Response> {
+        let client_id = solicitation.pre_grant().client_id.clone();
+        let scope = solicitation.pre_grant().scope.clone();
+
+        if let Some(owner_id) = (self.owner)(request) {
+            match self.store.find_consent(&owner_id, &client_id) {
+                Ok(Some(consent)) if self.approves(&consent, &scope) => {
+                    return OwnerConsent::Authorized(owner_id)
+                }
+                Ok(_) => (),
+                Err(err) => log::warn!("Failed to look up the consent of {}: {}", owner_id, err),
+            }
+        }
+
+        let consent = self.solicitor.check_consent(request, solicitation);
+        if let OwnerConsent::Authorized(owner_id) | OwnerConsent::AuthorizedWithClaims(owner_id, _) =
+            &consent
+        {
+            let approval = Consent {
+                owner_id: owner_id.clone(),
+                client_id,
+                scope,
+                approved_at: self.clock.now(),
+            };
+            if let Err(err) = self.store.store_consent(&approval) {
+                log::warn!("Failed to store the consent of {}: {}", owner_id, err);
+            }
+        }
+        consent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    use oxide_auth::endpoint::PreGrant;
+    use oxide_auth::frontends::simple::endpoint::FnSolicitor;
+    use oxide_auth::frontends::simple::request::{Request, Response};
+    use oxide_auth::primitives::registrar::RegisteredUrl;
+
+    use crate::db_service::consent::KvConsentStore;
+    use crate::db_service::kv::MemoryStore;
+
+    fn pre_grant(scope: &str) -> PreGrant {
+        PreGrant {
+            client_id: "Client".to_owned(),
+            redirect_uri: RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            scope: scope.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn approvals_are_remembered() {
+        let asked = Cell::new(0);
+        let mut solicitor = ConsentSolicitor::new(
+            KvConsentStore::new(MemoryStore::new()),
+            |_: &mut Request| Some("Owner".to_owned()),
+            FnSolicitor(|_: &mut Request, _: Solicitation| {
+                asked.set(asked.get() + 1);
+                OwnerConsent::<Response>::Authorized("Owner".to_owned())
+            }),
+        );
+        let mut check = |scope: &str| {
+            let grant = pre_grant(scope);
+            let consent = solicitor.check_consent(&mut Request::default(), Solicitation::new(&grant));
+            match consent {
+                OwnerConsent::Authorized(owner) => assert_eq!(owner, "Owner"),
+                _ => panic!("Consent was not given"),
+            }
+        };
+
+        check("read write");
+        check("read");
+        assert_eq!(asked.get(), 1);
+        // Only the last approval is remembered.
+        check("admin");
+        check("read");
+        assert_eq!(asked.get(), 3);
+    }
+
+    #[test]
+    fn approvals_with_scope_policy() {
+        use oxide_auth::primitives::scope::ScopeHierarchy;
+
+        let mut hierarchy = ScopeHierarchy::new();
+        hierarchy.imply("repo:admin", "repo:read");
+        let asked = Cell::new(0);
+        let mut solicitor = ConsentSolicitor::new(
+            KvConsentStore::new(MemoryStore::new()),
+            |_: &mut Request| Some("Owner".to_owned()),
+            FnSolicitor(|_: &mut Request, _: Solicitation| {
+                asked.set(asked.get() + 1);
+                OwnerConsent::<Response>::Authorized("Owner".to_owned())
+            }),
+        )
+        .scope_policy(hierarchy);
+        let mut check = |scope: &str| {
+            let grant = pre_grant(scope);
+            solicitor.check_consent(&mut Request::default(), Solicitation::new(&grant));
+        };
+
+        check("repo:admin");
+        check("repo:read");
+        assert_eq!(asked.get(), 1);
+        check("repo:write");
+        assert_eq!(asked.get(), 2);
+    }
+}
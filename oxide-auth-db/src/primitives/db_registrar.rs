@@ -2,19 +2,28 @@ use std::borrow::Cow;
 use std::iter::Extend;
 use once_cell::sync::Lazy;
 use oxide_auth::primitives::registrar::{
-    Argon2, BoundClient, Client, EncodedClient, PasswordPolicy, RegisteredClient, Registrar,
-    RegistrarError,
+    Argon2, BoundClient, Client, ClientCertificate, ClientType, EncodedClient, PasswordPolicy,
+    RegisteredClient, Registrar, RegistrarError,
 };
 use oxide_auth::primitives::prelude::{ClientUrl, PreGrant, Scope};
+use oxide_auth::primitives::scope::ScopePolicy;
+use crate::db_service::transfer::{self, ClientExport};
 use crate::db_service::DataSource;
+use crate::primitives::metadata::ClientMetadata;
+use crate::primitives::secret_policy::SecretPolicy;
+use crate::registration::random_string;
+#[cfg(feature = "with-redis")]
 use r2d2_redis::redis::RedisError;
 
 /// A database client service which implemented Registrar.
 /// db: repository service to query stored clients or regist new client.
 /// password_policy: to encode client_secret.
-pub struct DBRegistrar {
-    pub repo: DataSource,
+/// rehash: the secret policy upgrading outdated hashes on successful authentication.
+pub struct DBRegistrar<R: OauthClientDBRepository = DataSource> {
+    pub repo: R,
     password_policy: Option<Box<dyn PasswordPolicy>>,
+    scope_policy: Option<Box<dyn ScopePolicy>>,
+    rehash: Option<SecretPolicy>,
 }
 
 /// methods to search and regist clients from DataSource.
@@ -25,22 +34,112 @@ pub trait OauthClientDBRepository {
     fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient>;
 
     fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()>;
+
+    /// Replace the record of a client that is already registered.
+    ///
+    /// Unlike `regist_from_encoded_client` this fails instead of creating a new client.
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        self.find_client_by_id(&client.client_id)?;
+        self.regist_from_encoded_client(client)
+    }
+
+    /// Remove a client, returning whether it was registered.
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        Err(anyhow::anyhow!("Deleting client {} is not supported", id))
+    }
+
+    /// Disable or enable a registered client.
+    ///
+    /// A disabled client keeps its record but is not found by `find_client_by_id`, so the registrar
+    /// refuses it until it is enabled again.
+    fn set_client_disabled(&self, id: &str, _disabled: bool) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("Disabling client {} is not supported", id))
+    }
+
+    /// Whether a registered client is currently disabled.
+    fn is_client_disabled(&self, _id: &str) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    /// The descriptive metadata stored alongside a client, if any.
+    fn find_client_metadata(&self, _id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        Ok(None)
+    }
+
+    /// Replace the descriptive metadata of a registered client.
+    fn store_client_metadata(&self, id: &str, _metadata: &ClientMetadata) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Storing metadata of client {} is not supported",
+            id
+        ))
+    }
+}
+
+impl<R: OauthClientDBRepository + ?Sized> OauthClientDBRepository for Box<R> {
+    fn list(&self) -> anyhow::Result<Vec<EncodedClient>> {
+        (**self).list()
+    }
+
+    fn find_client_by_id(&self, id: &str) -> anyhow::Result<EncodedClient> {
+        (**self).find_client_by_id(id)
+    }
+
+    fn regist_from_encoded_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        (**self).regist_from_encoded_client(client)
+    }
+
+    fn update_client(&self, client: EncodedClient) -> anyhow::Result<()> {
+        (**self).update_client(client)
+    }
+
+    fn delete_client(&self, id: &str) -> anyhow::Result<bool> {
+        (**self).delete_client(id)
+    }
+
+    fn set_client_disabled(&self, id: &str, disabled: bool) -> anyhow::Result<()> {
+        (**self).set_client_disabled(id, disabled)
+    }
+
+    fn is_client_disabled(&self, id: &str) -> anyhow::Result<bool> {
+        (**self).is_client_disabled(id)
+    }
+
+    fn find_client_metadata(&self, id: &str) -> anyhow::Result<Option<ClientMetadata>> {
+        (**self).find_client_metadata(id)
+    }
+
+    fn store_client_metadata(&self, id: &str, metadata: &ClientMetadata) -> anyhow::Result<()> {
+        (**self).store_client_metadata(id, metadata)
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////
 //                             Implementations of DB Registrars                                  //
 ///////////////////////////////////////////////////////////////////////////////////////////////////
 
-static DEFAULT_PASSWORD_POLICY: Lazy<Argon2> = Lazy::new(|| Argon2::default());
+pub(crate) static DEFAULT_PASSWORD_POLICY: Lazy<Argon2> = Lazy::new(|| Argon2::default());
 
+#[cfg(feature = "with-redis")]
 impl DBRegistrar {
     /// Create an DB connection recording to features.
     pub fn new(url: String, max_pool_size: u32, client_prefix: String) -> Result<Self, RedisError> {
         let repo = DataSource::new(url, max_pool_size, client_prefix)?;
-        Ok(DBRegistrar {
+        Ok(DBRegistrar::with_repository(repo))
+    }
+}
+
+impl<R: OauthClientDBRepository> DBRegistrar<R> {
+    /// Create a registrar on top of an already constructed repository.
+    ///
+    /// This is the way to use any repository other than the default `DataSource` of the enabled
+    /// features.
+    pub fn with_repository(repo: R) -> Self {
+        DBRegistrar {
             repo,
             password_policy: None,
-        })
+            scope_policy: None,
+            rehash: None,
+        }
     }
 
     /// Insert or update the client record.
@@ -53,9 +152,126 @@ impl DBRegistrar {
             .map_err(|_e| RegistrarError::Unspecified)
     }
 
+    /// Replace the record of a registered client, failing if there is none with its id.
+    pub fn update_client(&mut self, client: Client) -> Result<(), RegistrarError> {
+        let password_policy = Self::current_policy(&self.password_policy);
+        let encoded_client = client.encode(password_policy);
+
+        self.repo
+            .update_client(encoded_client)
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Remove a client, returning whether it was registered.
+    pub fn delete_client(&mut self, client_id: &str) -> Result<bool, RegistrarError> {
+        self.repo
+            .delete_client(client_id)
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Refuse all requests of the client until it is enabled again.
+    pub fn disable_client(&mut self, client_id: &str) -> Result<(), RegistrarError> {
+        self.repo
+            .set_client_disabled(client_id, true)
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Accept the requests of a disabled client again.
+    pub fn enable_client(&mut self, client_id: &str) -> Result<(), RegistrarError> {
+        self.repo
+            .set_client_disabled(client_id, false)
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Replace the secret of a confidential client with a random one and return it.
+    ///
+    /// The previous secret stops working immediately. Public clients and disabled clients can not
+    /// be rotated.
+    pub fn rotate_secret(&mut self, client_id: &str) -> Result<String, RegistrarError> {
+        let secret = random_string(32);
+        self.set_client_secret(client_id, secret.as_bytes())?;
+        Ok(secret)
+    }
+
+    /// Replace the secret of a confidential client.
+    pub fn set_client_secret(&mut self, client_id: &str, secret: &[u8]) -> Result<(), RegistrarError> {
+        let password_policy = Self::current_policy(&self.password_policy);
+        let client = self
+            .repo
+            .find_client_by_id(client_id)
+            .map_err(|_e| RegistrarError::Unspecified)?;
+        let client = with_secret(client, password_policy, secret)?;
+
+        self.repo
+            .update_client(client)
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// The descriptive metadata of a client, if any is stored.
+    pub fn client_metadata(&self, client_id: &str) -> Result<Option<ClientMetadata>, RegistrarError> {
+        self.repo
+            .find_client_metadata(client_id)
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Replace the descriptive metadata of a registered client.
+    ///
+    /// The creation time of previously stored metadata is kept and the update time set to now.
+    pub fn set_client_metadata(
+        &mut self, client_id: &str, metadata: ClientMetadata,
+    ) -> Result<(), RegistrarError> {
+        let previous = self.client_metadata(client_id)?;
+        let metadata = metadata.stamped(previous.as_ref());
+
+        self.repo
+            .store_client_metadata(client_id, &metadata)
+            .map_err(|_e| RegistrarError::Unspecified)
+    }
+
+    /// Export all clients, see the `db_service::transfer` module.
+    pub fn export_clients(&self) -> anyhow::Result<ClientExport> {
+        transfer::export_clients(&self.repo)
+    }
+
+    /// Import clients, hashing plain secrets with the password policy of this registrar.
+    pub fn import_clients(&mut self, export: &ClientExport) -> anyhow::Result<usize> {
+        let password_policy = Self::current_policy(&self.password_policy);
+        transfer::import_clients(&self.repo, export, password_policy)
+    }
+
     /// Change how passwords are encoded while stored.
     pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
-        self.password_policy = Some(Box::new(new_policy))
+        self.password_policy = Some(Box::new(new_policy));
+        self.rehash = None;
+    }
+
+    /// Validate requested scopes with a policy instead of granting the default scope.
+    ///
+    /// See `ClientMap::set_scope_policy` for how the policy is applied.
+    pub fn set_scope_policy<P: ScopePolicy + 'static>(&mut self, new_policy: P) {
+        self.scope_policy = Some(Box::new(new_policy));
+    }
+
+    /// Hash secrets with the secret policy.
+    ///
+    /// Unlike other password policies, secrets hashed with an outdated algorithm, outdated
+    /// parameters or without the current pepper are hashed again when their client authenticates.
+    pub fn set_secret_policy(&mut self, new_policy: SecretPolicy) {
+        self.password_policy = Some(Box::new(new_policy.clone()));
+        self.rehash = Some(new_policy);
+    }
+
+    fn rehash_secret(&self, client: &EncodedClient, passphrase: Option<&[u8]>) {
+        let rehashed = self
+            .rehash
+            .as_ref()
+            .and_then(|policy| policy.rehashed(client, passphrase));
+        if let Some(rehashed) = rehashed {
+            // The client already authenticated, failing to upgrade its hash is not its problem.
+            if let Err(err) = self.repo.update_client(rehashed) {
+                log::warn!("Failed to re-hash the secret of {}: {}", client.client_id, err);
+            }
+        }
     }
 
     // This is not an instance method because it needs to borrow the box but register needs &mut
@@ -67,7 +283,7 @@ impl DBRegistrar {
     }
 }
 
-impl Extend<Client> for DBRegistrar {
+impl<R: OauthClientDBRepository> Extend<Client> for DBRegistrar<R> {
     fn extend<I>(&mut self, iter: I)
     where
         I: IntoIterator<Item = Client>,
@@ -78,45 +294,64 @@ impl Extend<Client> for DBRegistrar {
     }
 }
 
-impl Registrar for DBRegistrar {
+/// Encode a new secret of a confidential client.
+pub(crate) fn with_secret(
+    mut client: EncodedClient, policy: &dyn PasswordPolicy, secret: &[u8],
+) -> Result<EncodedClient, RegistrarError> {
+    if let ClientType::Public = client.encoded_client {
+        return Err(RegistrarError::Unspecified);
+    }
+    client.encoded_client = ClientType::Confidential {
+        passdata: policy.store(&client.client_id, secret),
+    };
+    Ok(client)
+}
+
+/// The scope granted to a request, the default scope of the client unless a policy validates it.
+pub(crate) fn negotiate_scope(
+    policy: &Option<Box<dyn ScopePolicy>>, requested: Option<Scope>, default_scope: Scope,
+) -> Result<Scope, RegistrarError> {
+    match (policy, requested) {
+        (Some(policy), Some(requested)) => policy
+            .validate(&requested, &default_scope)
+            .map_err(|()| RegistrarError::Unspecified),
+        _ => Ok(default_scope),
+    }
+}
+
+/// Bind the requested redirect uri to one registered for the client.
+pub(crate) fn bind_redirect<'a>(
+    client: &EncodedClient, bound: ClientUrl<'a>,
+) -> Result<BoundClient<'a>, RegistrarError> {
+    let registered_url = client
+        .match_redirect(bound.redirect_uri.as_deref())
+        .map_err(RegistrarError::RedirectMismatch)?;
+    Ok(BoundClient {
+        client_id: bound.client_id,
+        redirect_uri: Cow::Owned(registered_url),
+    })
+}
+
+impl<R: OauthClientDBRepository> Registrar for DBRegistrar<R> {
     fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
         let client = match self.repo.find_client_by_id(bound.client_id.as_ref()) {
             Ok(detail) => detail,
             _ => return Err(RegistrarError::Unspecified),
         };
-        // Perform exact matching as motivated in the rfc
-        let registered_url = match bound.redirect_uri {
-            None => client.redirect_uri.clone(),
-            Some(ref url) => {
-                let original = std::iter::once(&client.redirect_uri);
-                let alternatives = client.additional_redirect_uris.iter();
-                if let Some(registered) = original
-                    .chain(alternatives)
-                    .find(|&registered| *registered == *url.as_ref())
-                {
-                    registered.clone()
-                } else {
-                    return Err(RegistrarError::Unspecified);
-                }
-            }
-        };
-        Ok(BoundClient {
-            client_id: bound.client_id,
-            redirect_uri: Cow::Owned(registered_url),
-        })
+        bind_redirect(&client, bound)
     }
 
     fn negotiate<'a>(
-        &self, bound: BoundClient<'a>, _scope: Option<Scope>,
+        &self, bound: BoundClient<'a>, scope: Option<Scope>,
     ) -> Result<PreGrant, RegistrarError> {
         let client = self
             .repo
             .find_client_by_id(&bound.client_id)
             .map_err(|_e| RegistrarError::Unspecified)?;
         Ok(PreGrant {
+            scope: negotiate_scope(&self.scope_policy, scope, client.default_scope)?,
             client_id: bound.client_id.into_owned(),
             redirect_uri: bound.redirect_uri.into_owned(),
-            scope: client.default_scope,
         })
     }
 
@@ -126,12 +361,39 @@ impl Registrar for DBRegistrar {
         let client = self
             .repo
             .find_client_by_id(client_id)
-            .map_err(|_e| RegistrarError::Unspecified);
-        client.and_then(|op_client| {
-            RegisteredClient::new(&op_client, password_policy).check_authentication(passphrase)
-        })?;
+            .map_err(|_e| RegistrarError::Unspecified)?;
+        RegisteredClient::new(&client, password_policy).check_authentication(passphrase)?;
+        self.rehash_secret(&client, passphrase);
         Ok(())
     }
+
+    fn check_certificate(
+        &self, client_id: &str, certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        let password_policy = Self::current_policy(&self.password_policy);
+
+        let client = self
+            .repo
+            .find_client_by_id(client_id)
+            .map_err(|_e| RegistrarError::Unspecified)?;
+        RegisteredClient::new(&client, password_policy).check_certificate(certificate)
+    }
+
+    fn jwks(&self, client_id: &str) -> Result<Option<String>, RegistrarError> {
+        let client = self
+            .repo
+            .find_client_by_id(client_id)
+            .map_err(|_e| RegistrarError::Unspecified)?;
+        Ok(client.jwks)
+    }
+
+    fn jwt_secret(&self, client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        let client = self
+            .repo
+            .find_client_by_id(client_id)
+            .map_err(|_e| RegistrarError::Unspecified)?;
+        Ok(client.jwt_secret)
+    }
 }
 
 #[cfg(test)]
@@ -176,6 +438,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "with-redis")]
     fn with_additional_redirect_uris() {
         if crate::requires_redis_and_should_skip() {
             return;
@@ -233,6 +496,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "with-redis")]
     fn client_service() {
         if crate::requires_redis_and_should_skip() {
             return;
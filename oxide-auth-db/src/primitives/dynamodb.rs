@@ -0,0 +1,429 @@
+//! Registrar, authorizer and issuer on Amazon DynamoDB.
+//!
+//! These implement the asynchronous primitives of `oxide-auth-async`, so they can be used directly
+//! within the async handlers of a serverless function. All state lives in the tables of the
+//! `DynamoClientRepository` apart from the usage counter of the tagger, which should be random,
+//! such as the `RandomGenerator`.
+//!
+//! Codes and refresh tokens are deleted with a returned old image, which makes redeeming them a
+//! single atomic request. Expired items are removed by the time to live of DynamoDB, which may
+//! take a while. This is not a problem since every grant carries its own expiry which the flows
+//! check.
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use aws_sdk_dynamodb::Client;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+use oxide_auth_async::primitives::{Authorizer, Issuer};
+
+use crate::db_service::dynamodb::{string, DynamoClientRepository, Item};
+use crate::primitives::async_registrar::AsyncDBRegistrar;
+use crate::primitives::{system_clock, SharedClock};
+use crate::primitives::stored::StoredGrant;
+
+/// A registrar looking up clients in the clients table.
+pub type DynamoRegistrar = AsyncDBRegistrar<DynamoClientRepository>;
+
+/// An authorizer keeping its codes in the codes table.
+pub struct DynamoAuthorizer<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    client: Client,
+    table: String,
+    tagger: I,
+    usage: u64,
+}
+
+/// An issuer keeping its tokens in the tokens table.
+pub struct DynamoIssuer<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    client: Client,
+    table: String,
+    generator: G,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+fn encode_grant(grant: &Grant) -> Result<AttributeValue, PrimitiveError> {
+    serde_json::to_string(&StoredGrant::from_grant(grant))
+        .map(AttributeValue::S)
+        .map_err(|_| PrimitiveError::Invariant)
+}
+
+fn decode_grant(item: Option<&Item>) -> Result<Option<Grant>, PrimitiveError> {
+    let data = match item {
+        None => return Ok(None),
+        Some(item) => string(item, "grant").ok_or(PrimitiveError::Invariant)?,
+    };
+    let stored: StoredGrant = serde_json::from_str(data).map_err(|_| PrimitiveError::Invariant)?;
+    stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant)
+}
+
+fn epoch_seconds(seconds: i64) -> AttributeValue {
+    AttributeValue::N(seconds.to_string())
+}
+
+/// Read an instant stored in epoch seconds.
+fn epoch(item: &Item, name: &str) -> Option<DateTime<Utc>> {
+    let seconds = item.get(name)?.as_n().ok()?.parse().ok()?;
+    Utc.timestamp_opt(seconds, 0).single()
+}
+
+impl<I: TagGrant> DynamoAuthorizer<I> {
+    /// Create an authorizer on the codes table of the repository.
+    pub fn new(repository: &DynamoClientRepository, tagger: I) -> Self {
+        DynamoAuthorizer {
+            client: repository.get_client(),
+            table: repository.tables().codes.clone(),
+            tagger,
+            usage: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl<I: TagGrant + Send> Authorizer for DynamoAuthorizer<I> {
+    async fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        self.client
+            .put_item()
+            .table_name(&self.table)
+            .item("code", AttributeValue::S(code.clone()))
+            .item("grant", encode_grant(&grant)?)
+            .item("expires_at", epoch_seconds(grant.until.timestamp()))
+            .send()
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    async fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        // Only the request that actually deleted the item gets its old image back.
+        let output = self
+            .client
+            .delete_item()
+            .table_name(&self.table)
+            .key("code", AttributeValue::S(code.to_owned()))
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        decode_grant(output.attributes())
+    }
+}
+
+impl<G: TagGrant> DynamoIssuer<G> {
+    /// Create an issuer on the tokens table of the repository.
+    pub fn new(repository: &DynamoClientRepository, generator: G) -> Self {
+        DynamoIssuer {
+            client: repository.get_client(),
+            table: repository.tables().tokens.clone(),
+            generator,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    async fn put(
+        &self, token: &str, pair: Option<&str>, grant: &Grant, expires_at: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>,
+    ) -> Result<(), PrimitiveError> {
+        let mut request = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .item("token", AttributeValue::S(token.to_owned()))
+            .item("grant", encode_grant(grant)?)
+            .item("owner_id", AttributeValue::S(grant.owner_id.clone()))
+            .item("client_id", AttributeValue::S(grant.client_id.clone()));
+        if let Some(pair) = pair {
+            request = request.item("pair", AttributeValue::S(pair.to_owned()));
+        }
+        if let Some(expires_at) = expires_at {
+            request = request.item("expires_at", epoch_seconds(expires_at.timestamp()));
+        }
+        if let Some(session_until) = session_until {
+            request = request.item("session_expires_at", epoch_seconds(session_until.timestamp()));
+        }
+        request.send().await.map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+
+    /// Store a new access token, with a refresh token unless the grant lacks `offline_access`.
+    async fn store_pair(
+        &mut self, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>,
+    ) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+
+        self.put(&access, refresh.as_deref(), grant, Some(grant.until), None)
+            .await?;
+        if let Some(refresh) = &refresh {
+            self.put(refresh, Some(&access), grant, refresh_until, session_until)
+                .await?;
+        }
+        Ok((access, refresh))
+    }
+
+    async fn delete(&self, token: &str) -> Result<Option<Item>, PrimitiveError> {
+        let output = self
+            .client
+            .delete_item()
+            .table_name(&self.table)
+            .key("token", AttributeValue::S(token.to_owned()))
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(output.attributes)
+    }
+
+    async fn get(&self, token: &str) -> Result<Option<Item>, PrimitiveError> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key("token", AttributeValue::S(token.to_owned()))
+            .consistent_read(true)
+            .send()
+            .await
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(output.item)
+    }
+}
+
+#[async_trait]
+impl<G: TagGrant + Send + Sync> Issuer for DynamoIssuer<G> {
+    async fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, session_until).await?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn refresh(
+        &mut self, refresh: &str, mut grant: Grant,
+    ) -> Result<RefreshedToken, PrimitiveError> {
+        // Should only be called on valid refresh tokens.
+        let old = self.delete(refresh).await?.ok_or(PrimitiveError::NotFound)?;
+        // Invalidates the access token of the old pair as well.
+        if let Some(access) = string(&old, "pair") {
+            self.delete(access).await?;
+        }
+
+        let now = self.clock.now();
+        let session_until = epoch(&old, "session_expires_at");
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, session_until).await?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, PrimitiveError> {
+        decode_grant(self.get(token).await?.as_ref())
+    }
+
+    async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let item = self.get(token).await?;
+        // The time to live may keep the item long after it expired.
+        match item {
+            Some(item) if epoch(&item, "expires_at").is_some_and(|until| until < self.clock.now()) => {
+                Ok(None)
+            }
+            item => decode_grant(item.as_ref()),
+        }
+    }
+
+    async fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // Both items of a pair point to each other, so revoking either ends the other as well.
+        if let Some(old) = self.delete(token).await? {
+            if let Some(pair) = string(&old, "pair") {
+                self.delete(pair).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_dynamodb::config::{BehaviorVersion, Credentials, Region};
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::registrar::{Client as OAuthClient, RegisteredUrl};
+    use oxide_auth_async::primitives::Registrar;
+
+    use crate::db_service::dynamodb::DynamoTables;
+
+    /// The tests need DynamoDB Local, for example from a test container:
+    ///
+    /// `docker run -p 8000:8000 amazon/dynamodb-local`
+    /// `OXIDE_AUTH_DYNAMODB_URL=http://localhost:8000 cargo test --features with-dynamodb`
+    async fn repository(suffix: &str) -> Option<DynamoClientRepository> {
+        let url = std::env::var("OXIDE_AUTH_DYNAMODB_URL").ok()?;
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .endpoint_url(url)
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("local", "local", None, None, "test"))
+            .build();
+        let tables = DynamoTables {
+            clients: format!("clients_{}", suffix),
+            codes: format!("codes_{}", suffix),
+            tokens: format!("tokens_{}", suffix),
+        };
+        let repository = DynamoClientRepository::with_tables(Client::from_conf(config), tables);
+        // The tables of an earlier run are reused.
+        let _ = repository.create_tables().await;
+        Some(repository)
+    }
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn register_and_check() {
+        let repository = match repository("register").await {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut registrar = DynamoRegistrar::with_repository(repository);
+        registrar
+            .register_client(OAuthClient::confidential(
+                "DynamoClient",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .await
+            .unwrap();
+        registrar.check("DynamoClient", Some(b"secret")).await.unwrap();
+        assert!(registrar.check("DynamoClient", Some(b"wrong")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn code_is_single_use() {
+        let repository = match repository("codes").await {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut authorizer = DynamoAuthorizer::new(&repository, RandomGenerator::new(16));
+        let grant = grant();
+        let code = authorizer.authorize(grant.clone()).await.unwrap();
+        assert_eq!(authorizer.extract(&code).await.unwrap(), Some(grant));
+        assert_eq!(authorizer.extract(&code).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn refresh_replaces_pair() {
+        let repository = match repository("tokens").await {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut issuer = DynamoIssuer::new(&repository, RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).await.unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let refreshed = issuer.refresh(&refresh, grant()).await.unwrap();
+        assert!(issuer.recover_token(&issued.token).await.unwrap().is_none());
+        assert!(issuer.recover_refresh(&refresh).await.unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).await.unwrap().is_some());
+        assert!(issuer.refresh(&refresh, grant()).await.is_err());
+
+        issuer.revoke(refreshed.refresh.as_ref().unwrap()).await.unwrap();
+        assert!(issuer.recover_token(&refreshed.token).await.unwrap().is_none());
+    }
+}
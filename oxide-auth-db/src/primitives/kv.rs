@@ -0,0 +1,564 @@
+//! Registrar, authorizer and issuer on any `KeyValueBackend`.
+//!
+//! Codes are stored as a `StoredGrant` under `code:`, access tokens as a `StoredToken` under
+//! `token:` and refresh tokens as a `StoredRefresh` under `refresh:`. Codes and refresh tokens are
+//! taken atomically when redeemed, so several instances can share one store as long as the tagger
+//! is random, such as the `RandomGenerator`.
+//!
+//! With `KvIssuer::hash_tokens` the issuer keys and stores tokens by their SHA-256 hash, so the
+//! contents of the store do not allow replaying them.
+//!
+//! `DBAuthorizer` and `DBIssuer` are the backend independent names of the authorizer and issuer,
+//! matching `DBRegistrar`. Every store implementing `KeyValueBackend` gets all three primitives,
+//! including the `RedisDataSource` used by `DBRegistrar::new`:
+//!
+//! ```no_run
+//! # use oxide_auth::primitives::generator::RandomGenerator;
+//! # use oxide_auth_db::db_service::redis::RedisDataSource;
+//! # use oxide_auth_db::primitives::db_registrar::DBRegistrar;
+//! # use oxide_auth_db::primitives::kv::{DBAuthorizer, DBIssuer};
+//! let source = RedisDataSource::new("redis://localhost/3".into(), 4, "client:".into())?;
+//! let registrar = DBRegistrar::with_repository(source.clone());
+//! let authorizer = DBAuthorizer::new(source.clone(), RandomGenerator::new(16));
+//! let issuer = DBIssuer::new(source, RandomGenerator::new(16));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+use serde::de::DeserializeOwned;
+
+use crate::db_service::kv::{KeyValueBackend, KvClientRepository};
+use crate::primitives::db_registrar::DBRegistrar;
+use crate::primitives::stored::{token_hash, StoredGrant, StoredRefresh, StoredToken};
+use crate::primitives::{system_clock, SharedClock};
+
+/// A registrar storing its clients in a key-value store.
+pub type KvRegistrar<B> = DBRegistrar<KvClientRepository<B>>;
+
+/// An authorizer keeping its codes in a key-value store.
+pub struct KvAuthorizer<B: KeyValueBackend, I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    backend: B,
+    tagger: I,
+    code_prefix: String,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// An issuer keeping its tokens in a key-value store.
+pub struct KvIssuer<B: KeyValueBackend, G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    backend: B,
+    generator: G,
+    access_prefix: String,
+    refresh_prefix: String,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    hash_tokens: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// An authorizer on the storage of any backend, the counterpart of `DBRegistrar`.
+pub type DBAuthorizer<B, I = Box<dyn TagGrant + Send + Sync + 'static>> = KvAuthorizer<B, I>;
+
+/// An issuer on the storage of any backend, the counterpart of `DBRegistrar`.
+pub type DBIssuer<B, G = Box<dyn TagGrant + Send + Sync + 'static>> = KvIssuer<B, G>;
+
+pub(crate) fn decode<T: DeserializeOwned>(value: Option<Vec<u8>>) -> Result<Option<T>, PrimitiveError> {
+    match value {
+        None => Ok(None),
+        Some(value) => serde_json::from_slice(&value)
+            .map(Some)
+            .map_err(|_| PrimitiveError::Invariant),
+    }
+}
+
+/// Delete all entries under the prefix that expired before `now`, returning how many were removed.
+fn purge<B, T, F>(backend: &B, prefix: &str, until: F, now: DateTime<Utc>) -> anyhow::Result<usize>
+where
+    B: KeyValueBackend,
+    T: DeserializeOwned,
+    F: Fn(&T) -> Option<DateTime<Utc>>,
+{
+    let mut removed = 0;
+    for (key, value) in backend.scan_prefix(prefix)? {
+        let expired = match serde_json::from_slice::<T>(&value) {
+            Ok(entry) => until(&entry).is_some_and(|until| until < now),
+            Err(_) => true,
+        };
+        // Only remove the entry if it was not replaced since it was read.
+        if expired && backend.compare_and_swap(&key, Some(&value), None)? {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+impl<B: KeyValueBackend, I: TagGrant> KvAuthorizer<B, I> {
+    /// Create an authorizer storing codes under `code:`.
+    pub fn new(backend: B, tagger: I) -> Self {
+        Self::with_prefix(backend, tagger, "code:".to_owned())
+    }
+
+    /// Create an authorizer storing codes under a custom prefix.
+    pub fn with_prefix(backend: B, tagger: I, code_prefix: String) -> Self {
+        KvAuthorizer {
+            backend,
+            tagger,
+            code_prefix,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Purge expired codes by the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Delete all codes whose grant has expired.
+    pub fn purge_expired(&self) -> anyhow::Result<usize> {
+        let until = |grant: &StoredGrant| Some(grant.until);
+        purge(&self.backend, &self.code_prefix, until, self.clock.now())
+    }
+}
+
+impl<B: KeyValueBackend, I: TagGrant> Authorizer for KvAuthorizer<B, I> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let value = serde_json::to_vec(&StoredGrant::from_grant(&grant))
+            .map_err(|_| PrimitiveError::Invariant)?;
+        let key = format!("{}{}", self.code_prefix, code);
+        self.backend
+            .set(&key, &value, Some(grant.until))
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.code_prefix, code);
+        let stored: Option<StoredGrant> =
+            decode(self.backend.take(&key).map_err(|_| PrimitiveError::Unavailable)?)?;
+        match stored {
+            None => Ok(None),
+            Some(stored) => stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+}
+
+impl<B: KeyValueBackend, G: TagGrant> KvIssuer<B, G> {
+    /// Create an issuer storing tokens under `token:` and `refresh:`.
+    pub fn new(backend: B, generator: G) -> Self {
+        Self::with_prefixes(backend, generator, "token:".to_owned(), "refresh:".to_owned())
+    }
+
+    /// Create an issuer storing access and refresh tokens under custom prefixes.
+    pub fn with_prefixes(
+        backend: B, generator: G, access_prefix: String, refresh_prefix: String,
+    ) -> Self {
+        KvIssuer {
+            backend,
+            generator,
+            access_prefix,
+            refresh_prefix,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            hash_tokens: false,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Key and store tokens by their SHA-256 hash instead of the tokens themselves.
+    ///
+    /// Tokens issued before hashing was enabled are no longer found, and the other way around.
+    pub fn hash_tokens(&mut self, enabled: bool) {
+        self.hash_tokens = enabled;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// The form of a token written to the store.
+    fn stored_form(&self, token: &str) -> String {
+        if self.hash_tokens {
+            token_hash(token)
+        } else {
+            token.to_owned()
+        }
+    }
+
+    /// Delete all access tokens whose grant has expired and all expired refresh tokens.
+    pub fn purge_expired(&self) -> anyhow::Result<usize> {
+        let now = self.clock.now();
+        let access_until = |token: &StoredToken| Some(token.grant.until);
+        let refresh_until = |refresh: &StoredRefresh| refresh.until;
+        Ok(purge(&self.backend, &self.access_prefix, access_until, now)?
+            + purge(&self.backend, &self.refresh_prefix, refresh_until, now)?)
+    }
+
+    /// Store a new access token, with a refresh token unless the grant lacks `offline_access`.
+    fn store_pair(
+        &mut self, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>,
+    ) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+
+        let token = StoredToken {
+            access: self.stored_form(&access),
+            refresh: refresh.as_deref().map(|refresh| self.stored_form(refresh)),
+            grant: StoredGrant::from_grant(grant),
+        };
+        let access_value = serde_json::to_vec(&token).map_err(|_| PrimitiveError::Invariant)?;
+        let access_key = format!("{}{}", self.access_prefix, token.access);
+        self.backend
+            .set(&access_key, &access_value, Some(grant.until))
+            .map_err(|_| PrimitiveError::Unavailable)?;
+
+        let refresh_key = match &token.refresh {
+            Some(stored) => format!("{}{}", self.refresh_prefix, stored),
+            None => return Ok((access, None)),
+        };
+        let refresh_entry = StoredRefresh::new(token, refresh_until, session_until);
+        let refresh_value = serde_json::to_vec(&refresh_entry).map_err(|_| PrimitiveError::Invariant)?;
+        self.backend
+            .set(&refresh_key, &refresh_value, refresh_entry.until)
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok((access, refresh))
+    }
+}
+
+impl<B: KeyValueBackend, G: TagGrant> Issuer for KvIssuer<B, G> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, session_until)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        let refresh_key = format!("{}{}", self.refresh_prefix, self.stored_form(refresh));
+        // Should only be called on valid refresh tokens.
+        let old: StoredRefresh = decode(
+            self.backend
+                .take(&refresh_key)
+                .map_err(|_| PrimitiveError::Unavailable)?,
+        )?
+        .ok_or(PrimitiveError::NotFound)?;
+        // Invalidates the access token of the old pair as well.
+        let access_key = format!("{}{}", self.access_prefix, old.token.access);
+        self.backend
+            .delete(&access_key)
+            .map_err(|_| PrimitiveError::Unavailable)?;
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, old.session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, old.session_until)?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.access_prefix, self.stored_form(token));
+        let stored: Option<StoredToken> =
+            decode(self.backend.get(&key).map_err(|_| PrimitiveError::Unavailable)?)?;
+        match stored {
+            None => Ok(None),
+            Some(stored) => stored
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.refresh_prefix, self.stored_form(token));
+        let entry: Option<StoredRefresh> =
+            decode(self.backend.get(&key).map_err(|_| PrimitiveError::Unavailable)?)?;
+        match entry {
+            // The flows only check the expiry of the grant, not the one of the refresh token.
+            Some(entry) if entry.is_expired(self.clock.now()) => Ok(None),
+            Some(entry) => entry
+                .token
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+            None => Ok(None),
+        }
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        let stored = self.stored_form(token);
+        let refresh_key = format!("{}{}", self.refresh_prefix, stored);
+        let refresh: Option<StoredRefresh> = decode(
+            self.backend
+                .take(&refresh_key)
+                .map_err(|_| PrimitiveError::Unavailable)?,
+        )?;
+        // A refresh token takes its access token with it, but not the other way around.
+        let access = refresh.map_or(stored, |refresh| refresh.token.access);
+        let access_key = format!("{}{}", self.access_prefix, access);
+        self.backend
+            .delete(&access_key)
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::clock::ManualClock;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::prelude::Client;
+    use oxide_auth::primitives::registrar::{RegisteredUrl, Registrar};
+
+    use crate::db_service::kv::MemoryStore;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn register_and_check() {
+        let mut registrar: KvRegistrar<_> =
+            DBRegistrar::with_repository(KvClientRepository::new(MemoryStore::new()));
+        registrar
+            .register_client(Client::confidential(
+                "KvClient",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .unwrap();
+        registrar.check("KvClient", Some(b"secret")).unwrap();
+        assert!(registrar.check("KvClient", Some(b"wrong")).is_err());
+    }
+
+    #[test]
+    fn code_is_single_use() {
+        let mut authorizer = KvAuthorizer::new(MemoryStore::new(), RandomGenerator::new(16));
+        let grant = grant();
+        let code = authorizer.authorize(grant.clone()).unwrap();
+        assert_eq!(authorizer.extract(&code).unwrap(), Some(grant));
+        assert_eq!(authorizer.extract(&code).unwrap(), None);
+    }
+
+    #[test]
+    fn refresh_replaces_pair() {
+        let store = MemoryStore::new();
+        let mut issuer = KvIssuer::new(store.clone(), RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let refreshed = issuer.refresh(&refresh, grant()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+        assert!(issuer.refresh(&refresh, grant()).is_err());
+
+        let mut expired = grant();
+        expired.until = Utc::now() - Duration::minutes(1);
+        issuer.lifetime_policy(Lifetimes {
+            refresh: Some(Duration::minutes(-1)),
+            ..Lifetimes::default()
+        });
+        issuer.issue(expired).unwrap();
+        assert_eq!(issuer.purge_expired().unwrap(), 2);
+        assert_eq!(store.scan_prefix("token:").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn revoke_tokens() {
+        let mut issuer = KvIssuer::new(MemoryStore::new(), RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).unwrap();
+        issuer.revoke(&issued.token).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer
+            .recover_refresh(issued.refresh.as_ref().unwrap())
+            .unwrap()
+            .is_some());
+
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        issuer.revoke(&refresh).unwrap();
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        issuer.revoke("unknown").unwrap();
+    }
+
+    #[test]
+    fn offline_access_required() {
+        let mut issuer = KvIssuer::new(MemoryStore::new(), RandomGenerator::new(16));
+        issuer.require_offline_access(true);
+        let issued = issuer.issue(grant()).unwrap();
+        assert!(issued.refresh.is_none());
+        assert!(issuer.recover_token(&issued.token).unwrap().is_some());
+
+        let mut offline = grant();
+        offline.scope = "default offline_access".parse().unwrap();
+        let issued = issuer.issue(offline).unwrap();
+        // Narrowing the scope to exclude `offline_access` ends the refresh token.
+        let refreshed = issuer.refresh(&issued.refresh.unwrap(), grant()).unwrap();
+        assert!(refreshed.refresh.is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+    }
+
+    #[test]
+    fn refresh_session_ends() {
+        let mut issuer = KvIssuer::new(MemoryStore::new(), RandomGenerator::new(16));
+        issuer.lifetime_policy(Lifetimes {
+            access: None,
+            refresh: Some(Duration::days(14)),
+            session: Some(Duration::minutes(5)),
+        });
+        let issued = issuer.issue(grant()).unwrap();
+        let session_end = issued.until;
+        assert!(session_end <= Utc::now() + Duration::minutes(5));
+
+        // Refreshing restarts the refresh token lifetime but not the session.
+        let refreshed = issuer.refresh(&issued.refresh.unwrap(), grant()).unwrap();
+        assert_eq!(refreshed.until, session_end);
+
+        issuer.lifetime_policy(Lifetimes {
+            session: Some(Duration::minutes(-1)),
+            ..Lifetimes::default()
+        });
+        let issued = issuer.issue(grant()).unwrap();
+        assert!(issuer
+            .recover_refresh(&issued.refresh.unwrap())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn hashed_tokens() {
+        let store = MemoryStore::new();
+        let mut issuer = KvIssuer::new(store.clone(), RandomGenerator::new(16));
+        issuer.hash_tokens(true);
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let stored: String = store
+            .scan_prefix("")
+            .unwrap()
+            .into_iter()
+            .map(|(key, value)| format!("{}{}", key, String::from_utf8(value).unwrap()))
+            .collect();
+        assert!(!stored.contains(&issued.token) && !stored.contains(&refresh));
+        assert!(issuer.recover_token(&issued.token).unwrap().is_some());
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_some());
+
+        let refreshed = issuer.refresh(&refresh, grant()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+        assert_eq!(store.scan_prefix("token:").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn refresh_expires_by_clock() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let mut issuer = KvIssuer::new(MemoryStore::new(), RandomGenerator::new(16));
+        issuer.clock(clock.clone());
+        issuer.lifetime_policy(Lifetimes {
+            refresh: Some(Duration::days(1)),
+            ..Lifetimes::default()
+        });
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_some());
+        assert_eq!(issuer.purge_expired().unwrap(), 0);
+
+        // Both tokens expired at the time of the clock, although the store still holds them.
+        clock.advance(Duration::days(2));
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        assert_eq!(issuer.purge_expired().unwrap(), 2);
+    }
+}
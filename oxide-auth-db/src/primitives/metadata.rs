@@ -0,0 +1,124 @@
+//! Descriptive metadata of clients, for consent screens and administration.
+//!
+//! The OAuth fields of a client live in its `EncodedClient`. A [`ClientMetadata`] record with the
+//! names, links and contacts of [RFC 7591] is stored alongside it by the repositories of this
+//! crate, and a [`MetadataSolicitor`] hands it to the code rendering the consent page:
+//!
+//! ```no_run
+//! # use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+//! # use oxide_auth::frontends::simple::request::{Request, Response};
+//! # use oxide_auth_db::db_service::kv::{KvClientRepository, MemoryStore};
+//! # use oxide_auth_db::primitives::metadata::{ClientMetadata, MetadataSolicitor};
+//! fn consent(
+//!     _: &mut Request, solicitation: Solicitation, metadata: Option<ClientMetadata>,
+//! ) -> OwnerConsent<Response> {
+//!     let name = metadata
+//!         .and_then(|metadata| metadata.client_name)
+//!         .unwrap_or_else(|| solicitation.pre_grant().client_id.clone());
+//!     // Render a consent page presenting the client by its name.
+//! #   let _ = name;
+//!     OwnerConsent::InProgress(Response::default())
+//! }
+//!
+//! let repository = KvClientRepository::new(MemoryStore::new());
+//! let solicitor = MetadataSolicitor::new(repository, consent);
+//! ```
+//!
+//! [`ClientMetadata`]: struct.ClientMetadata.html
+//! [`MetadataSolicitor`]: struct.MetadataSolicitor.html
+//! [RFC 7591]: https://tools.ietf.org/html/rfc7591#section-2
+use chrono::{DateTime, Utc};
+use oxide_auth::endpoint::{OwnerConsent, OwnerSolicitor, Solicitation, WebRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::primitives::db_registrar::OauthClientDBRepository;
+
+/// Human readable information about a client.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClientMetadata {
+    /// The name of the client presented to resource owners.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+
+    /// A logo of the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+
+    /// Ways to contact the people responsible for the client, usually email addresses.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contacts: Vec<String>,
+
+    /// How the client uses the data of resource owners.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_uri: Option<String>,
+
+    /// The terms of service of the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tos_uri: Option<String>,
+
+    /// Identifies the software the client runs, shared by all its instances.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub software_id: Option<String>,
+
+    /// Whether the registration of the client still awaits the approval of an administrator.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pending_approval: bool,
+
+    /// When the metadata was first stored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+
+    /// When the metadata was last changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl ClientMetadata {
+    /// Stamp the metadata as changed now, keeping the creation time of the previous record.
+    pub(crate) fn stamped(mut self, previous: Option<&ClientMetadata>) -> Self {
+        let now = Utc::now();
+        self.created_at = previous.and_then(|previous| previous.created_at).or(Some(now));
+        self.updated_at = Some(now);
+        self
+    }
+}
+
+/// A solicitor that looks up the metadata of the requesting client before asking for consent.
+///
+/// Lookup failures are logged and passed on as missing metadata, the consent page can still
+/// present the client by its id.
+pub struct MetadataSolicitor<R, F> {
+    repository: R,
+    solicitor: F,
+}
+
+impl<R: OauthClientDBRepository, F> MetadataSolicitor<R, F> {
+    /// Look up clients in the repository and pass their metadata to the function.
+    pub fn new(repository: R, solicitor: F) -> Self {
+        MetadataSolicitor {
+            repository,
+            solicitor,
+        }
+    }
+}
+
+impl<W, R, F> OwnerSolicitor<W> for MetadataSolicitor<R, F>
+where
+    W: WebRequest,
+    R: OauthClientDBRepository,
+    F: FnMut(&mut W, Solicitation, Option<ClientMetadata>) -> OwnerConsent<W::Response>,
+{
+    fn check_consent(
+        &mut self, request: &mut W, solicitation: Solicitation,
+    ) -> OwnerConsent<W::Response> {
+        let client_id = &solicitation.pre_grant().client_id;
+        let metadata = match self.repository.find_client_metadata(client_id) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                log::warn!("Failed to look up the metadata of {}: {}", client_id, err);
+                None
+            }
+        };
+        (self.solicitor)(request, solicitation, metadata)
+    }
+}
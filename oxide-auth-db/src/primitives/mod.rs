@@ -1 +1,52 @@
+pub mod consent;
 pub mod db_registrar;
+pub mod kv;
+pub mod metadata;
+pub mod secret_policy;
+pub mod stored;
+pub mod tenant;
+
+#[cfg(feature = "async")]
+pub mod async_kv;
+
+#[cfg(feature = "async")]
+pub mod async_registrar;
+
+#[cfg(feature = "with-dynamodb")]
+pub mod dynamodb;
+
+#[cfg(feature = "with-mongodb")]
+pub mod mongodb;
+
+#[cfg(feature = "with-mysql")]
+pub mod mysql;
+
+#[cfg(feature = "with-postgres")]
+pub mod postgres;
+
+#[cfg(feature = "with-redis")]
+pub mod redis;
+
+#[cfg(feature = "with-sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "with-sled")]
+pub mod sled;
+
+#[cfg(feature = "with-spin")]
+pub mod spin_redis;
+
+#[cfg(feature = "with-spin")]
+pub mod spin_sqlite;
+
+use std::sync::Arc;
+
+use oxide_auth::primitives::clock::{Clock, SystemClock};
+
+/// The clock of a primitive, which may be shared with the endpoint.
+pub(crate) type SharedClock = Arc<dyn Clock + Send + Sync>;
+
+/// The system clock, the default of all primitives.
+pub(crate) fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
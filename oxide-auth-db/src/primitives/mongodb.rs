@@ -0,0 +1,377 @@
+//! Authorizer and issuer persisting to a MongoDB database.
+//!
+//! Both primitives share the database of `MongoClientRepository`. Expired codes and tokens are
+//! deleted by the TTL monitor of the server, which only runs about once a minute. This is not a
+//! problem since the grant of every code and token carries its own expiry which the flows check,
+//! and the issuer checks that of refresh tokens.
+//! The tagger should be random, such as the `RandomGenerator`.
+use std::sync::Arc;
+
+use chrono::{Duration, TimeZone, Utc};
+use mongodb::bson::{doc, DateTime};
+use mongodb::sync::Database;
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+
+use crate::db_service::mongodb::{codes, tokens, CodeDocument, MongoClientRepository, TokenDocument};
+use crate::primitives::{system_clock, SharedClock};
+use crate::primitives::stored::StoredGrant;
+
+/// An authorizer keeping its codes in the `oauth_codes` collection.
+pub struct MongoAuthorizer<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    database: Database,
+    tagger: I,
+    usage: u64,
+}
+
+/// An issuer keeping its tokens in the `oauth_tokens` collection.
+pub struct MongoIssuer<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    database: Database,
+    generator: G,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+fn date(at: chrono::DateTime<Utc>) -> DateTime {
+    DateTime::from_millis(at.timestamp_millis())
+}
+
+fn from_date(date: DateTime) -> Option<chrono::DateTime<Utc>> {
+    Utc.timestamp_millis_opt(date.timestamp_millis()).single()
+}
+
+fn decode_grant(stored: Option<StoredGrant>) -> Result<Option<Grant>, PrimitiveError> {
+    match stored {
+        None => Ok(None),
+        Some(stored) => stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant),
+    }
+}
+
+impl<I: TagGrant> MongoAuthorizer<I> {
+    /// Create an authorizer on the database of the repository.
+    pub fn new(repository: &MongoClientRepository, tagger: I) -> Self {
+        MongoAuthorizer {
+            database: repository.get_database(),
+            tagger,
+            usage: 0,
+        }
+    }
+}
+
+impl<I: TagGrant> Authorizer for MongoAuthorizer<I> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let document = CodeDocument {
+            code: code.clone(),
+            grant: StoredGrant::from_grant(&grant),
+            expires_at: date(grant.until),
+        };
+        codes(&self.database)
+            .insert_one(document)
+            .run()
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        // Finding and deleting in one operation ensures a code can only be redeemed once.
+        let document = codes(&self.database)
+            .find_one_and_delete(doc! { "_id": code })
+            .run()
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        decode_grant(document.map(|document| document.grant))
+    }
+}
+
+impl<G: TagGrant> MongoIssuer<G> {
+    /// Create an issuer on the database of the repository.
+    pub fn new(repository: &MongoClientRepository, generator: G) -> Self {
+        MongoIssuer {
+            database: repository.get_database(),
+            generator,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Revoke all access and refresh tokens issued to the resource owner.
+    ///
+    /// Returns the number of revoked token pairs.
+    pub fn revoke_all_for_owner(&self, owner_id: &str) -> anyhow::Result<u64> {
+        let result = tokens(&self.database)
+            .delete_many(doc! { "owner_id": owner_id })
+            .run()?;
+        Ok(result.deleted_count)
+    }
+
+    /// Revoke all access and refresh tokens issued to the client.
+    ///
+    /// Returns the number of revoked token pairs.
+    pub fn revoke_all_for_client(&self, client_id: &str) -> anyhow::Result<u64> {
+        let result = tokens(&self.database)
+            .delete_many(doc! { "client_id": client_id })
+            .run()?;
+        Ok(result.deleted_count)
+    }
+
+    /// Store a new access token, with a refresh token unless the grant lacks `offline_access`.
+    fn store_pair(
+        &mut self, grant: &Grant, refresh_until: Option<chrono::DateTime<Utc>>,
+        session_until: Option<chrono::DateTime<Utc>>,
+    ) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+
+        // The document lives as long as the longer lived of its tokens.
+        let expires_at = match (&refresh, refresh_until) {
+            (None, _) => Some(grant.until),
+            (Some(_), Some(until)) => Some(until.max(grant.until)),
+            (Some(_), None) => None,
+        };
+        let document = TokenDocument {
+            access_token: access.clone(),
+            refresh_token: refresh.clone(),
+            owner_id: grant.owner_id.clone(),
+            client_id: grant.client_id.clone(),
+            grant: StoredGrant::from_grant(grant),
+            expires_at: expires_at.map(date),
+            refresh_expires_at: refresh_until.map(date),
+            session_expires_at: session_until.map(date),
+        };
+        tokens(&self.database)
+            .insert_one(document)
+            .run()
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok((access, refresh))
+    }
+
+    fn find(&self, field: &str, token: &str) -> Result<Option<TokenDocument>, PrimitiveError> {
+        tokens(&self.database)
+            .find_one(doc! { field: token })
+            .run()
+            .map_err(|_| PrimitiveError::Unavailable)
+    }
+}
+
+impl<G: TagGrant> Issuer for MongoIssuer<G> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, session_until)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        // Invalidates both the old refresh token and its access token.
+        let removed = tokens(&self.database)
+            .find_one_and_delete(doc! { "refresh_token": refresh })
+            .run()
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        // Should only be called on valid refresh tokens.
+        let session_until = removed
+            .ok_or(PrimitiveError::NotFound)?
+            .session_expires_at
+            .and_then(from_date);
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, session_until)?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        decode_grant(self.find("_id", token)?.map(|document| document.grant))
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let now = self.clock.now();
+        let document = self.find("refresh_token", token)?.filter(|document| {
+            // The TTL monitor may not have removed the expired document yet.
+            document
+                .refresh_expires_at
+                .and_then(from_date)
+                .is_none_or(|until| until >= now)
+        });
+        decode_grant(document.map(|document| document.grant))
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // Both tokens of a pair share one document, so revoking either ends the other as well.
+        tokens(&self.database)
+            .delete_one(doc! { "$or": [{ "_id": token }, { "refresh_token": token }] })
+            .run()
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::prelude::Client;
+    use oxide_auth::primitives::registrar::{RegisteredUrl, Registrar};
+
+    use crate::primitives::db_registrar::DBRegistrar;
+
+    /// The tests need a server, for example from a test container:
+    ///
+    /// `docker run -p 27017:27017 mongo`
+    /// `OXIDE_AUTH_MONGODB_URL=mongodb://localhost:27017 cargo test --features with-mongodb`
+    fn repository() -> Option<MongoClientRepository> {
+        let url = std::env::var("OXIDE_AUTH_MONGODB_URL").ok()?;
+        Some(MongoClientRepository::new(&url, "oxide_auth_test").expect("Could not connect to mongodb"))
+    }
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn register_and_check() {
+        let repository = match repository() {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut registrar = DBRegistrar::with_repository(repository);
+        registrar
+            .register_client(Client::confidential(
+                "MongoClient",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .unwrap();
+        registrar.check("MongoClient", Some(b"secret")).unwrap();
+        assert!(registrar.check("MongoClient", Some(b"wrong")).is_err());
+    }
+
+    #[test]
+    fn code_is_single_use() {
+        let repository = match repository() {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut authorizer = MongoAuthorizer::new(&repository, RandomGenerator::new(16));
+        let grant = grant();
+        let code = authorizer.authorize(grant.clone()).unwrap();
+        assert_eq!(authorizer.extract(&code).unwrap(), Some(grant));
+        assert_eq!(authorizer.extract(&code).unwrap(), None);
+    }
+
+    #[test]
+    fn refresh_and_revoke() {
+        let repository = match repository() {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut issuer = MongoIssuer::new(&repository, RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let refreshed = issuer.refresh(&refresh, grant()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+
+        let issued = issuer.issue(grant()).unwrap();
+        issuer.revoke(issued.refresh.as_ref().unwrap()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+
+        assert!(issuer.revoke_all_for_owner("Owner").unwrap() >= 1);
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_none());
+    }
+}
@@ -0,0 +1,441 @@
+//! Authorizer and issuer persisting to a MySQL or MariaDB database.
+//!
+//! Both primitives share the pool and schema of `MySqlClientRepository` and only ever look up rows
+//! through their primary or an indexed key, so any number of application servers can work on the
+//! same database. The tagger should be random, such as the `RandomGenerator`.
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use mysql::prelude::Queryable;
+use mysql::{Params, Pool, PooledConn, TxOpts};
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+
+use crate::db_service::mysql::MySqlClientRepository;
+use crate::db_service::pool::RetryPolicy;
+use crate::primitives::{system_clock, SharedClock};
+use crate::primitives::stored::StoredGrant;
+
+/// An authorizer keeping its codes in the `oauth_codes` table.
+pub struct MySqlAuthorizer<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    pool: Pool,
+    tagger: I,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// An issuer keeping its tokens in the `oauth_tokens` table.
+pub struct MySqlIssuer<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    pool: Pool,
+    retry: RetryPolicy,
+    generator: G,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+fn connection(pool: &Pool) -> Result<PooledConn, PrimitiveError> {
+    pool.get_conn().map_err(|_| PrimitiveError::Unavailable)
+}
+
+fn encode_grant(grant: &Grant) -> Result<String, PrimitiveError> {
+    serde_json::to_string(&StoredGrant::from_grant(grant)).map_err(|_| PrimitiveError::Invariant)
+}
+
+fn decode_grant(data: Option<String>) -> Result<Option<Grant>, PrimitiveError> {
+    let data = match data {
+        None => return Ok(None),
+        Some(data) => data,
+    };
+    let stored: StoredGrant = serde_json::from_str(&data).map_err(|_| PrimitiveError::Invariant)?;
+    stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant)
+}
+
+impl<I: TagGrant> MySqlAuthorizer<I> {
+    /// Create an authorizer on the pool of the repository.
+    pub fn new(repository: &MySqlClientRepository, tagger: I) -> Self {
+        MySqlAuthorizer {
+            pool: repository.get_pool(),
+            tagger,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Purge expired codes by the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Delete all codes whose grant has expired.
+    pub fn purge_expired(&self) -> anyhow::Result<u64> {
+        let mut connection = self.pool.get_conn()?;
+        connection.exec_drop(
+            "DELETE FROM oauth_codes WHERE expires_at < ?",
+            (self.clock.now().timestamp(),),
+        )?;
+        Ok(connection.affected_rows())
+    }
+}
+
+impl<I: TagGrant> Authorizer for MySqlAuthorizer<I> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        connection(&self.pool)?
+            .exec_drop(
+                "INSERT INTO oauth_codes (code, grant_data, expires_at) VALUES (?, ?, ?)",
+                (&code, encode_grant(&grant)?, grant.until.timestamp()),
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        // There is no `DELETE .. RETURNING`, the row lock held until commit ensures a code can
+        // only be redeemed once.
+        let mut connection = connection(&self.pool)?;
+        let mut transaction = connection
+            .start_transaction(TxOpts::default())
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        let data: Option<String> = transaction
+            .exec_first(
+                "SELECT grant_data FROM oauth_codes WHERE code = ? FOR UPDATE",
+                (code,),
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        if data.is_some() {
+            transaction
+                .exec_drop("DELETE FROM oauth_codes WHERE code = ?", (code,))
+                .map_err(|_| PrimitiveError::Unavailable)?;
+        }
+        transaction.commit().map_err(|_| PrimitiveError::Unavailable)?;
+        decode_grant(data)
+    }
+}
+
+impl<G: TagGrant> MySqlIssuer<G> {
+    /// Create an issuer on the pool of the repository.
+    pub fn new(repository: &MySqlClientRepository, generator: G) -> Self {
+        MySqlIssuer {
+            pool: repository.get_pool(),
+            retry: repository.retry_policy(),
+            generator,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Delete all tokens whose grant has expired, unless their refresh token is still valid.
+    pub fn purge_expired(&self) -> anyhow::Result<u64> {
+        let now = self.clock.now().timestamp();
+        self.delete(
+            "DELETE FROM oauth_tokens WHERE expires_at < ?
+                AND (refresh_token IS NULL OR refresh_expires_at < ?)",
+            (now, now),
+        )
+    }
+
+    /// Revoke all access and refresh tokens issued to the resource owner.
+    ///
+    /// Returns the number of revoked token pairs.
+    pub fn revoke_all_for_owner(&self, owner_id: &str) -> anyhow::Result<u64> {
+        self.delete("DELETE FROM oauth_tokens WHERE owner_id = ?", (owner_id,))
+    }
+
+    /// Revoke all access and refresh tokens issued to the client.
+    ///
+    /// Returns the number of revoked token pairs.
+    pub fn revoke_all_for_client(&self, client_id: &str) -> anyhow::Result<u64> {
+        self.delete("DELETE FROM oauth_tokens WHERE client_id = ?", (client_id,))
+    }
+
+    fn delete<P: Into<Params>>(&self, statement: &str, params: P) -> anyhow::Result<u64> {
+        let mut connection = self.pool.get_conn()?;
+        connection.exec_drop(statement, params)?;
+        Ok(connection.affected_rows())
+    }
+
+    /// A new access token, with a refresh token unless the grant lacks `offline_access`.
+    fn token_pair(&mut self, grant: &Grant) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+        Ok((access, refresh))
+    }
+
+    fn store(
+        &self, access: &str, refresh: Option<&str>, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>,
+    ) -> Result<(), PrimitiveError> {
+        connection(&self.pool)?
+            .exec_drop(
+                "INSERT INTO oauth_tokens
+                    (access_token, refresh_token, grant_data, expires_at, owner_id, client_id,
+                    refresh_expires_at, session_expires_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                (
+                    access,
+                    refresh,
+                    encode_grant(grant)?,
+                    grant.until.timestamp(),
+                    &grant.owner_id,
+                    &grant.client_id,
+                    refresh_until.map(|until| until.timestamp()),
+                    session_until.map(|until| until.timestamp()),
+                ),
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+
+    fn recover<P>(&self, statement: &str, params: P) -> Result<Option<Grant>, PrimitiveError>
+    where
+        P: Into<Params> + Clone,
+    {
+        let data = self.retry.run(|| {
+            connection(&self.pool)?
+                .exec_first(statement, params.clone())
+                .map_err(|_| PrimitiveError::Unavailable)
+        })?;
+        decode_grant(data)
+    }
+}
+
+impl<G: TagGrant> Issuer for MySqlIssuer<G> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.token_pair(&grant)?;
+        self.store(&access, refresh.as_deref(), &grant, refresh_until, session_until)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        // Invalidates both the old refresh token and its access token. The row lock held until
+        // commit ensures the session is read from a refresh token that is only redeemed once.
+        let mut connection = connection(&self.pool)?;
+        let mut transaction = connection
+            .start_transaction(TxOpts::default())
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        let removed: Option<Option<i64>> = transaction
+            .exec_first(
+                "SELECT session_expires_at FROM oauth_tokens WHERE refresh_token = ? FOR UPDATE",
+                (refresh,),
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        // Should only be called on valid refresh tokens.
+        let session_until = removed
+            .ok_or(PrimitiveError::NotFound)?
+            .and_then(|session| Utc.timestamp_opt(session, 0).single());
+        transaction
+            .exec_drop("DELETE FROM oauth_tokens WHERE refresh_token = ?", (refresh,))
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        transaction.commit().map_err(|_| PrimitiveError::Unavailable)?;
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, session_until, now);
+        let (new_access, new_refresh) = self.token_pair(&grant)?;
+        self.store(
+            &new_access,
+            new_refresh.as_deref(),
+            &grant,
+            refresh_until,
+            session_until,
+        )?;
+        Ok(RefreshedToken {
+            token: new_access,
+            refresh: new_refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.recover(
+            "SELECT grant_data FROM oauth_tokens WHERE access_token = ?",
+            (token,),
+        )
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.recover(
+            "SELECT grant_data FROM oauth_tokens WHERE refresh_token = ?
+                AND (refresh_expires_at IS NULL OR refresh_expires_at >= ?)",
+            (token, self.clock.now().timestamp()),
+        )
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // Both tokens of a pair share one row, so revoking either ends the other as well.
+        self.delete(
+            "DELETE FROM oauth_tokens WHERE ? IN (access_token, refresh_token)",
+            (token,),
+        )
+        .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::prelude::Client;
+    use oxide_auth::primitives::registrar::{RegisteredUrl, Registrar};
+
+    use crate::primitives::db_registrar::DBRegistrar;
+
+    /// The tests need a database, for example from a test container:
+    ///
+    /// `docker run -e MARIADB_ALLOW_EMPTY_ROOT_PASSWORD=1 -e MARIADB_DATABASE=oauth -p 3306:3306 mariadb`
+    /// `OXIDE_AUTH_MYSQL_URL=mysql://root@127.0.0.1/oauth cargo test --features with-mysql`
+    fn repository() -> Option<MySqlClientRepository> {
+        let url = std::env::var("OXIDE_AUTH_MYSQL_URL").ok()?;
+        Some(MySqlClientRepository::new(&url, 4).expect("Could not connect to mysql"))
+    }
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn register_and_check() {
+        let repository = match repository() {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut registrar = DBRegistrar::with_repository(repository);
+        registrar
+            .register_client(Client::confidential(
+                "MySqlClient",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .unwrap();
+        registrar.check("MySqlClient", Some(b"secret")).unwrap();
+        assert!(registrar.check("MySqlClient", Some(b"wrong")).is_err());
+    }
+
+    #[test]
+    fn code_is_single_use() {
+        let repository = match repository() {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut authorizer = MySqlAuthorizer::new(&repository, RandomGenerator::new(16));
+        let grant = grant();
+        let code = authorizer.authorize(grant.clone()).unwrap();
+        assert_eq!(authorizer.extract(&code).unwrap(), Some(grant));
+        assert_eq!(authorizer.extract(&code).unwrap(), None);
+    }
+
+    #[test]
+    fn refresh_and_revoke() {
+        let repository = match repository() {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut issuer = MySqlIssuer::new(&repository, RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let refreshed = issuer.refresh(&refresh, grant()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+
+        let issued = issuer.issue(grant()).unwrap();
+        issuer.revoke(issued.refresh.as_ref().unwrap()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+
+        assert!(issuer.revoke_all_for_owner("Owner").unwrap() >= 1);
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_none());
+    }
+}
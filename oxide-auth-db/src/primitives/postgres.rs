@@ -0,0 +1,435 @@
+//! Authorizer and issuer persisting to a PostgreSQL database.
+//!
+//! Both primitives share the pool and schema of `PgClientRepository` and only ever look up rows
+//! through their primary or an indexed key, so any number of application servers can work on the
+//! same database. The tagger should be random, such as the `RandomGenerator`.
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+use r2d2_postgres::postgres::types::ToSql;
+use r2d2_postgres::postgres::Row;
+
+use crate::db_service::pool::RetryPolicy;
+use crate::db_service::postgres::{PgClientRepository, PgPool};
+use crate::primitives::{system_clock, SharedClock};
+use crate::primitives::stored::StoredGrant;
+
+/// An authorizer keeping its codes in the `oauth_codes` table.
+pub struct PgAuthorizer<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    pool: PgPool,
+    tagger: I,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// An issuer keeping its tokens in the `oauth_tokens` table.
+pub struct PgIssuer<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    pool: PgPool,
+    retry: RetryPolicy,
+    generator: G,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+fn encode_grant(grant: &Grant) -> Result<String, PrimitiveError> {
+    serde_json::to_string(&StoredGrant::from_grant(grant)).map_err(|_| PrimitiveError::Invariant)
+}
+
+/// Decode the grant in the first column of the row, if there is one.
+fn decode_grant(row: Option<Row>) -> Result<Option<Grant>, PrimitiveError> {
+    let row = match row {
+        None => return Ok(None),
+        Some(row) => row,
+    };
+    let data: &str = row.try_get(0).map_err(|_| PrimitiveError::Invariant)?;
+    let stored: StoredGrant = serde_json::from_str(data).map_err(|_| PrimitiveError::Invariant)?;
+    stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant)
+}
+
+impl<I: TagGrant> PgAuthorizer<I> {
+    /// Create an authorizer on the pool of the repository.
+    pub fn new(repository: &PgClientRepository, tagger: I) -> Self {
+        PgAuthorizer {
+            pool: repository.get_pool(),
+            tagger,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Purge expired codes by the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Delete all codes whose grant has expired.
+    pub fn purge_expired(&self) -> anyhow::Result<u64> {
+        let deleted = self.pool.get()?.execute(
+            "DELETE FROM oauth_codes WHERE expires_at < $1",
+            &[&self.clock.now().timestamp()],
+        )?;
+        Ok(deleted)
+    }
+}
+
+impl<I: TagGrant> Authorizer for PgAuthorizer<I> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        self.pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .execute(
+                "INSERT INTO oauth_codes (code, grant_data, expires_at) VALUES ($1, $2, $3)",
+                &[&code, &encode_grant(&grant)?, &grant.until.timestamp()],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        // Deleting and returning in one statement ensures a code can only be redeemed once.
+        let row = self
+            .pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .query_opt(
+                "DELETE FROM oauth_codes WHERE code = $1 RETURNING grant_data",
+                &[&code],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        decode_grant(row)
+    }
+}
+
+impl<G: TagGrant> PgIssuer<G> {
+    /// Create an issuer on the pool of the repository.
+    pub fn new(repository: &PgClientRepository, generator: G) -> Self {
+        PgIssuer {
+            pool: repository.get_pool(),
+            retry: repository.retry_policy(),
+            generator,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Delete all tokens whose grant has expired, unless their refresh token is still valid.
+    pub fn purge_expired(&self) -> anyhow::Result<u64> {
+        let deleted = self.pool.get()?.execute(
+            "DELETE FROM oauth_tokens WHERE expires_at < $1
+                AND (refresh_token IS NULL OR refresh_expires_at < $1)",
+            &[&self.clock.now().timestamp()],
+        )?;
+        Ok(deleted)
+    }
+
+    /// Revoke all access and refresh tokens issued to the resource owner.
+    ///
+    /// Returns the number of revoked token pairs.
+    pub fn revoke_all_for_owner(&self, owner_id: &str) -> anyhow::Result<u64> {
+        let deleted = self
+            .pool
+            .get()?
+            .execute("DELETE FROM oauth_tokens WHERE owner_id = $1", &[&owner_id])?;
+        Ok(deleted)
+    }
+
+    /// Revoke all access and refresh tokens issued to the client.
+    ///
+    /// Returns the number of revoked token pairs.
+    pub fn revoke_all_for_client(&self, client_id: &str) -> anyhow::Result<u64> {
+        let deleted = self
+            .pool
+            .get()?
+            .execute("DELETE FROM oauth_tokens WHERE client_id = $1", &[&client_id])?;
+        Ok(deleted)
+    }
+
+    /// A new access token, with a refresh token unless the grant lacks `offline_access`.
+    fn token_pair(&mut self, grant: &Grant) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+        Ok((access, refresh))
+    }
+
+    fn store(
+        &self, access: &str, refresh: Option<&str>, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>,
+    ) -> Result<(), PrimitiveError> {
+        self.pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .execute(
+                "INSERT INTO oauth_tokens
+                    (access_token, refresh_token, grant_data, expires_at, owner_id, client_id,
+                    refresh_expires_at, session_expires_at)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                &[
+                    &access,
+                    &refresh,
+                    &encode_grant(grant)?,
+                    &grant.until.timestamp(),
+                    &grant.owner_id,
+                    &grant.client_id,
+                    &refresh_until.map(|until| until.timestamp()),
+                    &session_until.map(|until| until.timestamp()),
+                ],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+
+    fn recover(
+        &self, statement: &str, params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Grant>, PrimitiveError> {
+        let row = self.retry.run(|| {
+            self.pool
+                .get()
+                .map_err(|_| PrimitiveError::Unavailable)?
+                .query_opt(statement, params)
+                .map_err(|_| PrimitiveError::Unavailable)
+        })?;
+        decode_grant(row)
+    }
+}
+
+impl<G: TagGrant> Issuer for PgIssuer<G> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.token_pair(&grant)?;
+        self.store(&access, refresh.as_deref(), &grant, refresh_until, session_until)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        // Invalidates both the old refresh token and its access token.
+        let removed = self
+            .pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .query_opt(
+                "DELETE FROM oauth_tokens WHERE refresh_token = $1 RETURNING session_expires_at",
+                &[&refresh],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        // Should only be called on valid refresh tokens.
+        let session_until = removed
+            .ok_or(PrimitiveError::NotFound)?
+            .get::<_, Option<i64>>(0)
+            .and_then(|session| Utc.timestamp_opt(session, 0).single());
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, session_until, now);
+        let (new_access, new_refresh) = self.token_pair(&grant)?;
+        self.store(
+            &new_access,
+            new_refresh.as_deref(),
+            &grant,
+            refresh_until,
+            session_until,
+        )?;
+        Ok(RefreshedToken {
+            token: new_access,
+            refresh: new_refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.recover(
+            "SELECT grant_data FROM oauth_tokens WHERE access_token = $1",
+            &[&token],
+        )
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.recover(
+            "SELECT grant_data FROM oauth_tokens WHERE refresh_token = $1
+                AND (refresh_expires_at IS NULL OR refresh_expires_at >= $2)",
+            &[&token, &self.clock.now().timestamp()],
+        )
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // Both tokens of a pair share one row, so revoking either ends the other as well.
+        self.pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .execute(
+                "DELETE FROM oauth_tokens WHERE $1 IN (access_token, refresh_token)",
+                &[&token],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::prelude::Client;
+    use oxide_auth::primitives::registrar::{RegisteredUrl, Registrar};
+
+    use crate::primitives::db_registrar::DBRegistrar;
+
+    /// The tests need a database, for example from a test container:
+    ///
+    /// `docker run -e POSTGRES_HOST_AUTH_METHOD=trust -p 5432:5432 postgres`
+    /// `OXIDE_AUTH_POSTGRES_URL=postgres://postgres@localhost/postgres cargo test --features with-postgres`
+    fn repository() -> Option<PgClientRepository> {
+        let url = std::env::var("OXIDE_AUTH_POSTGRES_URL").ok()?;
+        Some(PgClientRepository::new(&url, 4).expect("Could not connect to postgres"))
+    }
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn register_and_check() {
+        let repository = match repository() {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut registrar = DBRegistrar::with_repository(repository);
+        registrar
+            .register_client(Client::confidential(
+                "PgClient",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .unwrap();
+        registrar.check("PgClient", Some(b"secret")).unwrap();
+        assert!(registrar.check("PgClient", Some(b"wrong")).is_err());
+    }
+
+    #[test]
+    fn code_is_single_use() {
+        let repository = match repository() {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut authorizer = PgAuthorizer::new(&repository, RandomGenerator::new(16));
+        let grant = grant();
+        let code = authorizer.authorize(grant.clone()).unwrap();
+        assert_eq!(authorizer.extract(&code).unwrap(), Some(grant));
+        assert_eq!(authorizer.extract(&code).unwrap(), None);
+    }
+
+    #[test]
+    fn refresh_and_revoke() {
+        let repository = match repository() {
+            Some(repository) => repository,
+            None => return,
+        };
+
+        let mut issuer = PgIssuer::new(&repository, RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let refreshed = issuer.refresh(&refresh, grant()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+
+        let issued = issuer.issue(grant()).unwrap();
+        issuer.revoke(issued.refresh.as_ref().unwrap()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+
+        assert!(issuer.revoke_all_for_owner("Owner").unwrap() >= 1);
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_none());
+    }
+}
@@ -0,0 +1,441 @@
+//! Authorizer and issuer on a Redis server, sharing the connection pool of `RedisDataSource`.
+//!
+//! Codes and tokens are written with a Redis expiry derived from their grant, so the server drops
+//! them on its own. No state is kept in the process apart from the usage counter of the tagger,
+//! which is why several application servers can share one Redis instance as long as the tagger is
+//! random, such as the `RandomGenerator`.
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+use r2d2_redis::r2d2::{Pool, PooledConnection};
+use r2d2_redis::redis;
+use r2d2_redis::RedisConnectionManager;
+
+use crate::db_service::pool::RetryPolicy;
+use crate::db_service::redis::RedisDataSource;
+use crate::primitives::{system_clock, SharedClock};
+use crate::primitives::stored::{StoredGrant, StoredRefresh, StoredToken};
+
+/// An authorizer keeping its codes as expiring Redis keys.
+pub struct RedisAuthorizer<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    pool: Pool<RedisConnectionManager>,
+    tagger: I,
+    code_prefix: String,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// An issuer keeping its tokens as expiring Redis keys.
+pub struct RedisIssuer<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    pool: Pool<RedisConnectionManager>,
+    retry: RetryPolicy,
+    generator: G,
+    access_prefix: String,
+    refresh_prefix: String,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+type Connection = PooledConnection<RedisConnectionManager>;
+
+fn connection(pool: &Pool<RedisConnectionManager>) -> Result<Connection, PrimitiveError> {
+    pool.get().map_err(|_| PrimitiveError::Unavailable)
+}
+
+/// Seconds until the instant, at least one since Redis rejects non-positive expiries.
+fn seconds_until(until: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    (until - now).num_seconds().max(1)
+}
+
+fn set(
+    connection: &mut Connection, key: &str, value: Vec<u8>, expiry: Option<i64>,
+) -> Result<(), PrimitiveError> {
+    let mut command = redis::cmd("SET");
+    command.arg(key).arg(value);
+    if let Some(seconds) = expiry {
+        command.arg("EX").arg(seconds);
+    }
+    command
+        .query::<()>(&mut **connection)
+        .map_err(|_| PrimitiveError::Unavailable)
+}
+
+/// Atomically read and delete a key.
+fn take(connection: &mut Connection, key: &str) -> Result<Option<Vec<u8>>, PrimitiveError> {
+    // A transaction instead of `GETDEL` keeps this working on servers before Redis 6.2.
+    let (value, _): (Option<Vec<u8>>, i64) = redis::pipe()
+        .atomic()
+        .get(key)
+        .del(key)
+        .query(&mut **connection)
+        .map_err(|_| PrimitiveError::Unavailable)?;
+    Ok(value)
+}
+
+fn decode<T: serde::de::DeserializeOwned>(value: Option<Vec<u8>>) -> Result<Option<T>, PrimitiveError> {
+    match value {
+        None => Ok(None),
+        Some(value) => serde_json::from_slice(&value)
+            .map(Some)
+            .map_err(|_| PrimitiveError::Invariant),
+    }
+}
+
+impl<I: TagGrant> RedisAuthorizer<I> {
+    /// Create an authorizer on the pool of the data source, storing codes under `code:`.
+    pub fn new(source: &RedisDataSource, tagger: I) -> Self {
+        Self::with_prefix(source.get_pool(), tagger, "code:".to_owned())
+    }
+
+    /// Create an authorizer on a pool, storing codes under a custom prefix.
+    pub fn with_prefix(pool: Pool<RedisConnectionManager>, tagger: I, code_prefix: String) -> Self {
+        RedisAuthorizer {
+            pool,
+            tagger,
+            code_prefix,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Compute the expiry of codes from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+}
+
+impl<I: TagGrant> Authorizer for RedisAuthorizer<I> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let now = self.clock.now();
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let value = serde_json::to_vec(&StoredGrant::from_grant(&grant))
+            .map_err(|_| PrimitiveError::Invariant)?;
+        let key = format!("{}{}", self.code_prefix, code);
+        set(
+            &mut connection(&self.pool)?,
+            &key,
+            value,
+            Some(seconds_until(grant.until, now)),
+        )?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.code_prefix, code);
+        let stored: Option<StoredGrant> = decode(take(&mut connection(&self.pool)?, &key)?)?;
+        match stored {
+            None => Ok(None),
+            Some(stored) => stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+}
+
+impl<G: TagGrant> RedisIssuer<G> {
+    /// Create an issuer on the pool of the data source, storing tokens under `token:` and
+    /// `refresh:`.
+    pub fn new(source: &RedisDataSource, generator: G) -> Self {
+        let mut issuer = Self::with_prefixes(
+            source.get_pool(),
+            generator,
+            "token:".to_owned(),
+            "refresh:".to_owned(),
+        );
+        issuer.retry = source.retry_policy();
+        issuer
+    }
+
+    /// Create an issuer on a pool, storing access and refresh tokens under custom prefixes.
+    pub fn with_prefixes(
+        pool: Pool<RedisConnectionManager>, generator: G, access_prefix: String, refresh_prefix: String,
+    ) -> Self {
+        RedisIssuer {
+            pool,
+            retry: RetryPolicy::default(),
+            generator,
+            access_prefix,
+            refresh_prefix,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Change how often token lookups are attempted.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Store a new access token, with a refresh token unless the grant lacks `offline_access`.
+    fn store_pair(
+        &mut self, connection: &mut Connection, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>, now: DateTime<Utc>,
+    ) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+
+        let record = StoredToken {
+            access: access.clone(),
+            refresh: refresh.clone(),
+            grant: StoredGrant::from_grant(grant),
+        };
+        let value = serde_json::to_vec(&record).map_err(|_| PrimitiveError::Invariant)?;
+        let access_key = format!("{}{}", self.access_prefix, access);
+        set(
+            connection,
+            &access_key,
+            value,
+            Some(seconds_until(grant.until, now)),
+        )?;
+
+        if let Some(refresh) = &refresh {
+            let refresh_key = format!("{}{}", self.refresh_prefix, refresh);
+            let refresh_value =
+                serde_json::to_vec(&StoredRefresh::new(record, refresh_until, session_until))
+                    .map_err(|_| PrimitiveError::Invariant)?;
+            set(
+                connection,
+                &refresh_key,
+                refresh_value,
+                refresh_until.map(|until| seconds_until(until, now)),
+            )?;
+        }
+        Ok((access, refresh))
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, PrimitiveError> {
+        self.retry.run(|| {
+            redis::cmd("GET")
+                .arg(key)
+                .query(&mut *connection(&self.pool)?)
+                .map_err(|_| PrimitiveError::Unavailable)
+        })
+    }
+}
+
+impl<G: TagGrant> Issuer for RedisIssuer<G> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let mut connection = connection(&self.pool)?;
+        let (access, refresh) =
+            self.store_pair(&mut connection, &grant, refresh_until, session_until, now)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        let mut connection = connection(&self.pool)?;
+        let refresh_key = format!("{}{}", self.refresh_prefix, refresh);
+        // Should only be called on valid refresh tokens.
+        let old: StoredRefresh =
+            decode(take(&mut connection, &refresh_key)?)?.ok_or(PrimitiveError::NotFound)?;
+        let old_access = format!("{}{}", self.access_prefix, old.token.access);
+        redis::cmd("DEL")
+            .arg(old_access)
+            .query::<()>(&mut *connection)
+            .map_err(|_| PrimitiveError::Unavailable)?;
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, old.session_until, now);
+        let (access, refresh) =
+            self.store_pair(&mut connection, &grant, refresh_until, old.session_until, now)?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.access_prefix, token);
+        match decode::<StoredToken>(self.get(&key)?)? {
+            None => Ok(None),
+            Some(record) => record
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.refresh_prefix, token);
+        match decode::<StoredRefresh>(self.get(&key)?)? {
+            None => Ok(None),
+            Some(entry) => entry
+                .token
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        let mut connection = connection(&self.pool)?;
+        let refresh_key = format!("{}{}", self.refresh_prefix, token);
+        // A refresh token takes its access token with it, but not the other way around.
+        let access = match decode::<StoredRefresh>(take(&mut connection, &refresh_key)?)? {
+            Some(old) => old.token.access,
+            None => token.to_owned(),
+        };
+        redis::cmd("DEL")
+            .arg(format!("{}{}", self.access_prefix, access))
+            .query::<()>(&mut *connection)
+            .map_err(|_| PrimitiveError::Unavailable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+
+    use crate::primitives::kv::{DBAuthorizer, DBIssuer};
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    fn source() -> RedisDataSource {
+        RedisDataSource::new("redis://localhost/3".into(), 4, "client:".into()).unwrap()
+    }
+
+    #[test]
+    fn code_is_single_use() {
+        if crate::requires_redis_and_should_skip() {
+            return;
+        }
+
+        let mut authorizer = RedisAuthorizer::new(&source(), RandomGenerator::new(16));
+        let grant = grant();
+        let code = authorizer.authorize(grant.clone()).unwrap();
+        assert_eq!(authorizer.extract(&code).unwrap(), Some(grant));
+        assert_eq!(authorizer.extract(&code).unwrap(), None);
+    }
+
+    #[test]
+    fn refresh_replaces_pair() {
+        if crate::requires_redis_and_should_skip() {
+            return;
+        }
+
+        let mut issuer = RedisIssuer::new(&source(), RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_some());
+
+        let refreshed = issuer.refresh(&refresh, grant()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+
+        issuer.revoke(refreshed.refresh.as_ref().unwrap()).unwrap();
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_none());
+    }
+
+    #[test]
+    fn generic_primitives() {
+        if crate::requires_redis_and_should_skip() {
+            return;
+        }
+
+        let mut authorizer =
+            DBAuthorizer::with_prefix(source(), RandomGenerator::new(16), "generic-code:".to_owned());
+        let code = authorizer.authorize(grant()).unwrap();
+        assert!(authorizer.extract(&code).unwrap().is_some());
+        assert_eq!(authorizer.extract(&code).unwrap(), None);
+
+        let mut issuer = DBIssuer::new(source(), RandomGenerator::new(16));
+        let issued = issuer.issue(grant()).unwrap();
+        let refreshed = issuer.refresh(&issued.refresh.unwrap(), grant()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+    }
+}
@@ -0,0 +1,346 @@
+//! Configurable hashing of client secrets.
+//!
+//! A [`SecretPolicy`] is a `PasswordPolicy` that hashes new secrets with Argon2id, bcrypt or
+//! PBKDF2 and verifies secrets hashed by any of them, as well as those of the default `Argon2`
+//! policy of `oxide-auth`. Install it with `DBRegistrar::set_secret_policy` so that a client whose
+//! secret was hashed with an outdated algorithm, outdated parameters or without the current pepper
+//! is re-hashed on its next successful authentication:
+//!
+//! ```no_run
+//! # use oxide_auth_db::primitives::db_registrar::DBRegistrar;
+//! # use oxide_auth_db::primitives::secret_policy::SecretPolicy;
+//! # use oxide_auth_db::db_service::kv::{KvClientRepository, MemoryStore};
+//! let mut registrar = DBRegistrar::with_repository(KvClientRepository::new(MemoryStore::new()));
+//! registrar.set_secret_policy(SecretPolicy::bcrypt(12).with_pepper(b"kept outside the database"));
+//! ```
+//!
+//! Argon2id uses the pepper as its secret key. bcrypt and PBKDF2 hash an HMAC-SHA256 of the client
+//! id and secret instead, keyed with the pepper, which also lifts the 72 byte limit of bcrypt.
+//!
+//! [`SecretPolicy`]: struct.SecretPolicy.html
+use argon2::{Config, Variant, Version};
+use hmac::{Hmac, Mac};
+use oxide_auth::primitives::registrar::{ClientType, EncodedClient, PasswordPolicy, RegistrarError};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+
+/// Marks secrets that were hashed with a pepper.
+const PEPPERED: &[u8] = b"peppered:";
+
+/// The algorithm used to hash new secrets, with its parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecretHash {
+    /// Argon2id with the memory cost in KiB, the number of iterations and the parallelism.
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+
+    /// bcrypt with the logarithmic cost.
+    Bcrypt { cost: u32 },
+
+    /// PBKDF2 with HMAC-SHA256 and the number of iterations.
+    Pbkdf2 { iterations: u32 },
+}
+
+/// Hashes client secrets with a configurable algorithm and an optional pepper.
+#[derive(Clone)]
+pub struct SecretPolicy {
+    hash: SecretHash,
+    pepper: Option<Vec<u8>>,
+}
+
+impl SecretPolicy {
+    /// Hash secrets with the algorithm and parameters.
+    pub fn new(hash: SecretHash) -> Self {
+        SecretPolicy { hash, pepper: None }
+    }
+
+    /// Argon2id with 19 MiB of memory, two iterations and no parallelism.
+    pub fn argon2id() -> Self {
+        SecretPolicy::new(SecretHash::Argon2id {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        })
+    }
+
+    /// bcrypt with the cost, between 4 and 31.
+    pub fn bcrypt(cost: u32) -> Self {
+        SecretPolicy::new(SecretHash::Bcrypt { cost })
+    }
+
+    /// PBKDF2-HMAC-SHA256 with the number of iterations.
+    pub fn pbkdf2(iterations: u32) -> Self {
+        SecretPolicy::new(SecretHash::Pbkdf2 { iterations })
+    }
+
+    /// Mix a secret kept outside the database into every hash.
+    ///
+    /// Secrets hashed without a pepper are still accepted, and re-hashed when the registrar does.
+    pub fn with_pepper(mut self, pepper: &[u8]) -> Self {
+        self.pepper = Some(pepper.to_vec());
+        self
+    }
+
+    /// The algorithm used for new secrets.
+    pub fn hash(&self) -> SecretHash {
+        self.hash
+    }
+
+    /// Whether a stored secret was not hashed the way this policy would hash it now.
+    pub fn needs_rehash(&self, stored: &[u8]) -> bool {
+        let (peppered, encoded) = split_pepper(stored);
+        if peppered != self.pepper.is_some() {
+            return true;
+        }
+        let encoded = match std::str::from_utf8(encoded) {
+            Ok(encoded) => encoded,
+            Err(_) => return true,
+        };
+        match self.hash {
+            SecretHash::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => !encoded.starts_with(&format!(
+                "$argon2id$v=19$m={},t={},p={}$",
+                memory_kib, iterations, parallelism
+            )),
+            SecretHash::Bcrypt { cost } => !encoded.starts_with(&format!("$2b${:02}$", cost)),
+            SecretHash::Pbkdf2 { iterations } => {
+                !encoded.starts_with(&format!("$pbkdf2-sha256$i={}$", iterations))
+            }
+        }
+    }
+
+    /// The client with its secret hashed again, if it authenticated with an outdated hash.
+    pub(crate) fn rehashed(
+        &self, client: &EncodedClient, passphrase: Option<&[u8]>,
+    ) -> Option<EncodedClient> {
+        let passphrase = passphrase?;
+        match &client.encoded_client {
+            ClientType::Confidential { passdata } if self.needs_rehash(passdata) => (),
+            _ => return None,
+        }
+        let mut client = client.clone();
+        client.encoded_client = ClientType::Confidential {
+            passdata: self.store(&client.client_id, passphrase),
+        };
+        Some(client)
+    }
+
+    fn pepper(&self, peppered: bool) -> &[u8] {
+        match &self.pepper {
+            Some(pepper) if peppered => pepper,
+            _ => &[],
+        }
+    }
+
+    /// The input of bcrypt and PBKDF2, binding the secret to the client.
+    fn prehash(pepper: &[u8], client_id: &str, passphrase: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(pepper).expect("HMAC takes keys of any size");
+        mac.update(client_id.as_bytes());
+        mac.update(&[0]);
+        mac.update(passphrase);
+        base64::encode(mac.finalize().into_bytes())
+    }
+
+    fn encode(&self, client_id: &str, passphrase: &[u8]) -> Result<String, ()> {
+        let pepper = self.pepper(true);
+        match self.hash {
+            SecretHash::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let config = Config {
+                    variant: Variant::Argon2id,
+                    version: Version::Version13,
+                    mem_cost: memory_kib,
+                    time_cost: iterations,
+                    lanes: parallelism,
+                    secret: pepper,
+                    ad: client_id.as_bytes(),
+                    hash_length: 32,
+                    ..Config::default()
+                };
+                argon2::hash_encoded(passphrase, &salt(), &config).map_err(|_| ())
+            }
+            SecretHash::Bcrypt { cost } => {
+                bcrypt::hash(Self::prehash(pepper, client_id, passphrase), cost).map_err(|_| ())
+            }
+            SecretHash::Pbkdf2 { iterations } => {
+                let salt = salt();
+                let input = Self::prehash(pepper, client_id, passphrase);
+                let mut hash = [0; 32];
+                pbkdf2::pbkdf2_hmac::<Sha256>(input.as_bytes(), &salt, iterations, &mut hash);
+                Ok(format!(
+                    "$pbkdf2-sha256$i={}${}${}",
+                    iterations,
+                    base64::encode_config(salt, base64::STANDARD_NO_PAD),
+                    base64::encode_config(hash, base64::STANDARD_NO_PAD),
+                ))
+            }
+        }
+    }
+
+    fn verify(&self, client_id: &str, passphrase: &[u8], stored: &[u8]) -> Result<bool, ()> {
+        let (peppered, encoded) = split_pepper(stored);
+        if peppered && self.pepper.is_none() {
+            return Err(());
+        }
+        let pepper = self.pepper(peppered);
+        let encoded = std::str::from_utf8(encoded).map_err(|_| ())?;
+
+        if encoded.starts_with("$argon2") {
+            argon2::verify_encoded_ext(encoded, passphrase, pepper, client_id.as_bytes()).map_err(|_| ())
+        } else if encoded.starts_with("$2") {
+            bcrypt::verify(Self::prehash(pepper, client_id, passphrase), encoded).map_err(|_| ())
+        } else if let Some(rest) = encoded.strip_prefix("$pbkdf2-sha256$i=") {
+            let mut parts = rest.split('$');
+            let iterations: u32 = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+            let salt = decode(parts.next())?;
+            let expected = decode(parts.next())?;
+            let input = Self::prehash(pepper, client_id, passphrase);
+            let mut hash = vec![0; expected.len()];
+            pbkdf2::pbkdf2_hmac::<Sha256>(input.as_bytes(), &salt, iterations, &mut hash);
+            Ok(constant_time_eq(&hash, &expected))
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl Default for SecretPolicy {
+    /// Argon2id with its default parameters.
+    fn default() -> Self {
+        SecretPolicy::argon2id()
+    }
+}
+
+impl std::fmt::Debug for SecretPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SecretPolicy")
+            .field("hash", &self.hash)
+            .field("pepper", &self.pepper.as_ref().map(|_| "<hidden>"))
+            .finish()
+    }
+}
+
+impl PasswordPolicy for SecretPolicy {
+    fn store(&self, client_id: &str, passphrase: &[u8]) -> Vec<u8> {
+        let encoded = self
+            .encode(client_id, passphrase)
+            .expect("Failed to hash client secret");
+        match self.pepper {
+            Some(_) => [PEPPERED, encoded.as_bytes()].concat(),
+            None => encoded.into_bytes(),
+        }
+    }
+
+    fn check(&self, client_id: &str, passphrase: &[u8], stored: &[u8]) -> Result<(), RegistrarError> {
+        match self.verify(client_id, passphrase, stored) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(RegistrarError::Unspecified),
+            Err(()) => Err(RegistrarError::PrimitiveError),
+        }
+    }
+}
+
+fn split_pepper(stored: &[u8]) -> (bool, &[u8]) {
+    match stored.strip_prefix(PEPPERED) {
+        Some(encoded) => (true, encoded),
+        None => (false, stored),
+    }
+}
+
+fn salt() -> Vec<u8> {
+    let mut salt = vec![0; 16];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+fn decode(part: Option<&str>) -> Result<Vec<u8>, ()> {
+    base64::decode_config(part.ok_or(())?, base64::STANDARD_NO_PAD).map_err(|_| ())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::registrar::{Argon2, Client, RegisteredUrl, Registrar};
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::primitives::db_registrar::{DBRegistrar, OauthClientDBRepository};
+
+    fn policies() -> Vec<SecretPolicy> {
+        vec![
+            SecretPolicy::new(SecretHash::Argon2id {
+                memory_kib: 64,
+                iterations: 1,
+                parallelism: 1,
+            }),
+            SecretPolicy::bcrypt(4),
+            SecretPolicy::pbkdf2(16),
+        ]
+    }
+
+    #[test]
+    fn verifies_every_algorithm() {
+        for policy in policies() {
+            for policy in [policy.clone(), policy.with_pepper(b"pepper")] {
+                let stored = policy.store("Client", b"secret");
+                assert!(policy.check("Client", b"secret", &stored).is_ok());
+                assert!(policy.check("Client", b"wrong", &stored).is_err());
+                assert!(policy.check("Other", b"secret", &stored).is_err());
+                assert!(!policy.needs_rehash(&stored));
+            }
+        }
+    }
+
+    #[test]
+    fn migrates_old_hashes() {
+        let legacy = Argon2::default().store("Client", b"secret");
+        let bcrypt = SecretPolicy::bcrypt(4).store("Client", b"secret");
+        let current = SecretPolicy::pbkdf2(16).with_pepper(b"pepper");
+
+        for stored in [legacy, bcrypt] {
+            assert!(current.check("Client", b"secret", &stored).is_ok());
+            assert!(current.needs_rehash(&stored));
+        }
+        assert!(SecretPolicy::pbkdf2(32)
+            .with_pepper(b"pepper")
+            .needs_rehash(&current.store("Client", b"secret")));
+    }
+
+    #[test]
+    fn registrar_rehashes_on_check() {
+        let mut registrar = DBRegistrar::with_repository(KvClientRepository::new(MemoryStore::new()));
+        registrar
+            .register_client(Client::confidential(
+                "Client",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .unwrap();
+
+        let policy = SecretPolicy::pbkdf2(16);
+        registrar.set_secret_policy(policy.clone());
+        assert!(registrar.check("Client", Some(b"wrong")).is_err());
+        registrar.check("Client", Some(b"secret")).unwrap();
+
+        let stored = match registrar.repo.find_client_by_id("Client").unwrap().encoded_client {
+            ClientType::Confidential { passdata } => passdata,
+            ClientType::Public => unreachable!(),
+        };
+        assert!(!policy.needs_rehash(&stored));
+        registrar.check("Client", Some(b"secret")).unwrap();
+    }
+}
@@ -0,0 +1,363 @@
+//! Authorizer and issuer on the trees of an embedded sled database.
+//!
+//! Both primitives share the database of `SledClientRepository`. Redeeming a code or a refresh
+//! token removes its entry in one atomic operation, so each can only be used once even when the
+//! primitives are cloned across threads. Expired entries are removed by the compaction of the
+//! repository.
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+use sled::{IVec, Tree};
+
+use crate::db_service::sled::{CodeEntry, SledClientRepository, ACCESS_TOKENS, CODES, REFRESH_TOKENS};
+use crate::primitives::stored::{StoredGrant, StoredRefresh, StoredToken};
+use crate::primitives::{system_clock, SharedClock};
+
+/// An authorizer keeping its codes in the `oauth_codes` tree.
+pub struct SledAuthorizer<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    codes: Tree,
+    tagger: I,
+    usage: u64,
+}
+
+/// An issuer keeping its tokens in the `oauth_access_tokens` and `oauth_refresh_tokens` trees.
+pub struct SledIssuer<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    access: Tree,
+    refresh: Tree,
+    generator: G,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+fn decode<T: serde::de::DeserializeOwned>(value: Option<IVec>) -> Result<Option<T>, PrimitiveError> {
+    match value {
+        None => Ok(None),
+        Some(value) => serde_json::from_slice(&value)
+            .map(Some)
+            .map_err(|_| PrimitiveError::Invariant),
+    }
+}
+
+impl<I: TagGrant> SledAuthorizer<I> {
+    /// Create an authorizer on the database of the repository.
+    pub fn new(repository: &SledClientRepository, tagger: I) -> anyhow::Result<Self> {
+        Ok(SledAuthorizer {
+            codes: repository.get_db().open_tree(CODES)?,
+            tagger,
+            usage: 0,
+        })
+    }
+}
+
+impl<I: TagGrant> Authorizer for SledAuthorizer<I> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let entry: CodeEntry = StoredGrant::from_grant(&grant);
+        let value = serde_json::to_vec(&entry).map_err(|_| PrimitiveError::Invariant)?;
+        self.codes
+            .insert(code.as_bytes(), value)
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let entry: Option<CodeEntry> =
+            decode(self.codes.remove(code).map_err(|_| PrimitiveError::Unavailable)?)?;
+        match entry {
+            None => Ok(None),
+            Some(entry) => entry.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+}
+
+impl<G: TagGrant> SledIssuer<G> {
+    /// Create an issuer on the database of the repository.
+    pub fn new(repository: &SledClientRepository, generator: G) -> anyhow::Result<Self> {
+        let db = repository.get_db();
+        Ok(SledIssuer {
+            access: db.open_tree(ACCESS_TOKENS)?,
+            refresh: db.open_tree(REFRESH_TOKENS)?,
+            generator,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        })
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Store a new access token, with a refresh token unless the grant lacks `offline_access`.
+    fn store_pair(
+        &mut self, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>,
+    ) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+
+        let token = StoredToken {
+            access: access.clone(),
+            refresh: refresh.clone(),
+            grant: StoredGrant::from_grant(grant),
+        };
+        let access_value = serde_json::to_vec(&token).map_err(|_| PrimitiveError::Invariant)?;
+        self.access
+            .insert(access.as_bytes(), access_value)
+            .map_err(|_| PrimitiveError::Unavailable)?;
+
+        if let Some(refresh) = &refresh {
+            let refresh_value =
+                serde_json::to_vec(&StoredRefresh::new(token, refresh_until, session_until))
+                    .map_err(|_| PrimitiveError::Invariant)?;
+            self.refresh
+                .insert(refresh.as_bytes(), refresh_value)
+                .map_err(|_| PrimitiveError::Unavailable)?;
+        }
+        Ok((access, refresh))
+    }
+}
+
+impl<G: TagGrant> Issuer for SledIssuer<G> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, session_until)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        // Should only be called on valid refresh tokens.
+        let old: StoredRefresh = decode(
+            self.refresh
+                .remove(refresh)
+                .map_err(|_| PrimitiveError::Unavailable)?,
+        )?
+        .ok_or(PrimitiveError::NotFound)?;
+        // Invalidates the access token of the old pair as well.
+        self.access
+            .remove(old.token.access.as_bytes())
+            .map_err(|_| PrimitiveError::Unavailable)?;
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, old.session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, old.session_until)?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let stored: Option<StoredToken> =
+            decode(self.access.get(token).map_err(|_| PrimitiveError::Unavailable)?)?;
+        match stored {
+            None => Ok(None),
+            Some(stored) => stored
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let entry: Option<StoredRefresh> =
+            decode(self.refresh.get(token).map_err(|_| PrimitiveError::Unavailable)?)?;
+        match entry {
+            // The flows only check the expiry of the grant, not the one of the refresh token.
+            Some(entry) if entry.is_expired(self.clock.now()) => Ok(None),
+            Some(entry) => entry
+                .token
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+            None => Ok(None),
+        }
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        let refresh: Option<StoredRefresh> = decode(
+            self.refresh
+                .remove(token)
+                .map_err(|_| PrimitiveError::Unavailable)?,
+        )?;
+        // A refresh token takes its access token with it, but not the other way around.
+        let access = refresh.map_or_else(|| token.to_owned(), |refresh| refresh.token.access);
+        self.access
+            .remove(access.as_bytes())
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::prelude::Client;
+    use oxide_auth::primitives::registrar::{RegisteredUrl, Registrar};
+
+    use crate::db_service::sled::purge_expired;
+    use crate::primitives::db_registrar::DBRegistrar;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn register_and_check() {
+        let repository = SledClientRepository::temporary().unwrap();
+        let mut registrar = DBRegistrar::with_repository(repository);
+        registrar
+            .register_client(Client::confidential(
+                "SledClient",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .unwrap();
+        registrar.check("SledClient", Some(b"secret")).unwrap();
+        assert!(registrar.check("SledClient", Some(b"wrong")).is_err());
+    }
+
+    #[test]
+    fn code_is_single_use() {
+        let repository = SledClientRepository::temporary().unwrap();
+        let mut authorizer = SledAuthorizer::new(&repository, RandomGenerator::new(16)).unwrap();
+        let grant = grant();
+        let code = authorizer.authorize(grant.clone()).unwrap();
+        assert_eq!(authorizer.extract(&code).unwrap(), Some(grant));
+        assert_eq!(authorizer.extract(&code).unwrap(), None);
+    }
+
+    #[test]
+    fn refresh_replaces_pair() {
+        let repository = SledClientRepository::temporary().unwrap();
+        let mut issuer = SledIssuer::new(&repository, RandomGenerator::new(16)).unwrap();
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let refreshed = issuer.refresh(&refresh, grant()).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+
+        issuer.revoke(refreshed.refresh.as_ref().unwrap()).unwrap();
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_none());
+    }
+
+    #[test]
+    fn compaction_removes_expired() {
+        let repository = SledClientRepository::temporary().unwrap();
+        let mut authorizer = SledAuthorizer::new(&repository, RandomGenerator::new(16)).unwrap();
+        let mut issuer = SledIssuer::new(&repository, RandomGenerator::new(16)).unwrap();
+        issuer.lifetime_policy(Lifetimes {
+            refresh: Some(Duration::minutes(-1)),
+            ..Lifetimes::default()
+        });
+
+        let mut expired = grant();
+        expired.until = Utc::now() - Duration::minutes(1);
+        let code = authorizer.authorize(expired.clone()).unwrap();
+        let live = authorizer.authorize(grant()).unwrap();
+        let issued = issuer.issue(expired).unwrap();
+        assert!(issuer
+            .recover_refresh(issued.refresh.as_ref().unwrap())
+            .unwrap()
+            .is_none());
+
+        // The code, the access token and the refresh token.
+        assert_eq!(purge_expired(&repository.get_db()).unwrap(), 3);
+        assert_eq!(authorizer.extract(&code).unwrap(), None);
+        assert!(authorizer.extract(&live).unwrap().is_some());
+
+        // Stopping the compaction thread must not block.
+        drop(repository.spawn_compaction(std::time::Duration::from_secs(60)));
+    }
+}
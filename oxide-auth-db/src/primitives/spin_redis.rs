@@ -0,0 +1,367 @@
+//! Authorizer and issuer on a Redis server, reached through the outbound Redis interface of Spin.
+//!
+//! Codes and tokens are written with a Redis expiry derived from their grant, so the server drops
+//! them on its own and no sweeping is required. As with the SQLite primitives, every component
+//! instance starts with a fresh usage counter and the tagger should be random, such as the
+//! `RandomGenerator`.
+//!
+//! With `SpinRedisIssuer::hash_tokens` the issuer keys and stores tokens by their SHA-256 hash, so
+//! reading the server does not allow replaying them.
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+use spin_sdk::redis::{Connection, RedisParameter, RedisResult};
+
+use crate::db_service::health::Health;
+use crate::db_service::spin_redis::health_check;
+use crate::primitives::{system_clock, SharedClock};
+use crate::primitives::stored::{token_hash, StoredGrant, StoredRefresh, StoredToken};
+
+/// An authorizer keeping its codes as expiring Redis keys.
+pub struct SpinRedisAuthorizer<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    connection: Connection,
+    tagger: I,
+    code_prefix: String,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// An issuer keeping its tokens as expiring Redis keys.
+pub struct SpinRedisIssuer<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    connection: Connection,
+    generator: G,
+    access_prefix: String,
+    refresh_prefix: String,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    hash_tokens: bool,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// Seconds until the instant, at least one since Redis rejects non-positive expiries.
+fn seconds_until(until: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    (until - now).num_seconds().max(1)
+}
+
+fn set(
+    connection: &Connection, key: &str, value: Vec<u8>, expiry: Option<i64>,
+) -> Result<(), PrimitiveError> {
+    let mut arguments = vec![
+        RedisParameter::Binary(key.as_bytes().to_vec()),
+        RedisParameter::Binary(value),
+    ];
+    if let Some(seconds) = expiry {
+        arguments.push(RedisParameter::Binary(b"EX".to_vec()));
+        arguments.push(RedisParameter::Int64(seconds));
+    }
+    connection
+        .execute("SET", &arguments)
+        .map_err(|_| PrimitiveError::Unavailable)?;
+    Ok(())
+}
+
+/// Atomically read and delete a key.
+fn take(connection: &Connection, key: &str) -> Result<Option<Vec<u8>>, PrimitiveError> {
+    let result = connection
+        .execute("GETDEL", &[RedisParameter::Binary(key.as_bytes().to_vec())])
+        .map_err(|_| PrimitiveError::Unavailable)?;
+    match result.into_iter().next() {
+        Some(RedisResult::Binary(value)) => Ok(Some(value)),
+        Some(RedisResult::Nil) | None => Ok(None),
+        Some(_) => Err(PrimitiveError::Invariant),
+    }
+}
+
+fn decode<T: serde::de::DeserializeOwned>(value: Option<Vec<u8>>) -> Result<Option<T>, PrimitiveError> {
+    match value {
+        None => Ok(None),
+        Some(value) => serde_json::from_slice(&value)
+            .map(Some)
+            .map_err(|_| PrimitiveError::Invariant),
+    }
+}
+
+impl<I: TagGrant> SpinRedisAuthorizer<I> {
+    /// Create an authorizer storing codes under the `code:` prefix.
+    pub fn new(connection: Connection, tagger: I) -> Self {
+        Self::with_prefix(connection, tagger, "code:".to_owned())
+    }
+
+    /// Create an authorizer storing codes under a custom prefix.
+    pub fn with_prefix(connection: Connection, tagger: I, code_prefix: String) -> Self {
+        SpinRedisAuthorizer {
+            connection,
+            tagger,
+            code_prefix,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Compute the expiry of codes from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Probe the availability of the server.
+    pub fn health_check(&self) -> Health {
+        health_check(&self.connection)
+    }
+}
+
+impl<I: TagGrant> Authorizer for SpinRedisAuthorizer<I> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let now = self.clock.now();
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let value = serde_json::to_vec(&StoredGrant::from_grant(&grant))
+            .map_err(|_| PrimitiveError::Invariant)?;
+        let key = format!("{}{}", self.code_prefix, code);
+        set(
+            &self.connection,
+            &key,
+            value,
+            Some(seconds_until(grant.until, now)),
+        )?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.code_prefix, code);
+        let stored: Option<StoredGrant> = decode(take(&self.connection, &key)?)?;
+        match stored {
+            None => Ok(None),
+            Some(stored) => stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+}
+
+impl<G: TagGrant> SpinRedisIssuer<G> {
+    /// Create an issuer storing tokens under the `token:` and `refresh:` prefixes.
+    pub fn new(connection: Connection, generator: G) -> Self {
+        Self::with_prefixes(connection, generator, "token:".to_owned(), "refresh:".to_owned())
+    }
+
+    /// Create an issuer storing access and refresh tokens under custom prefixes.
+    pub fn with_prefixes(
+        connection: Connection, generator: G, access_prefix: String, refresh_prefix: String,
+    ) -> Self {
+        SpinRedisIssuer {
+            connection,
+            generator,
+            access_prefix,
+            refresh_prefix,
+            lifetimes: Box::new(Lifetimes::default()),
+            hash_tokens: false,
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Probe the availability of the server.
+    pub fn health_check(&self) -> Health {
+        health_check(&self.connection)
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Key and store tokens by their SHA-256 hash instead of the tokens themselves.
+    ///
+    /// Tokens issued before hashing was enabled are no longer found, and the other way around.
+    pub fn hash_tokens(&mut self, enabled: bool) {
+        self.hash_tokens = enabled;
+    }
+
+    /// The form of a token written to the server.
+    fn stored_form(&self, token: &str) -> String {
+        if self.hash_tokens {
+            token_hash(token)
+        } else {
+            token.to_owned()
+        }
+    }
+
+    /// Store a new access token, with a refresh token unless the grant lacks `offline_access`.
+    fn store_pair(
+        &mut self, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>, now: DateTime<Utc>,
+    ) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+
+        let record = StoredToken {
+            access: self.stored_form(&access),
+            refresh: refresh.as_deref().map(|refresh| self.stored_form(refresh)),
+            grant: StoredGrant::from_grant(grant),
+        };
+        let value = serde_json::to_vec(&record).map_err(|_| PrimitiveError::Invariant)?;
+        let access_key = format!("{}{}", self.access_prefix, record.access);
+        set(
+            &self.connection,
+            &access_key,
+            value,
+            Some(seconds_until(grant.until, now)),
+        )?;
+
+        if let Some(stored) = record.refresh.clone() {
+            let refresh_key = format!("{}{}", self.refresh_prefix, stored);
+            let refresh_value =
+                serde_json::to_vec(&StoredRefresh::new(record, refresh_until, session_until))
+                    .map_err(|_| PrimitiveError::Invariant)?;
+            set(
+                &self.connection,
+                &refresh_key,
+                refresh_value,
+                refresh_until.map(|until| seconds_until(until, now)),
+            )?;
+        }
+        Ok((access, refresh))
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, PrimitiveError> {
+        self.connection.get(key).map_err(|_| PrimitiveError::Unavailable)
+    }
+}
+
+impl<G: TagGrant> Issuer for SpinRedisIssuer<G> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, session_until, now)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        let refresh_key = format!("{}{}", self.refresh_prefix, self.stored_form(refresh));
+        // Should only be called on valid refresh tokens.
+        let old: StoredRefresh =
+            decode(take(&self.connection, &refresh_key)?)?.ok_or(PrimitiveError::NotFound)?;
+        let old_access = format!("{}{}", self.access_prefix, old.token.access);
+        self.connection
+            .del(&[old_access])
+            .map_err(|_| PrimitiveError::Unavailable)?;
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, old.session_until, now);
+        let (access, refresh) = self.store_pair(&grant, refresh_until, old.session_until, now)?;
+        Ok(RefreshedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.access_prefix, self.stored_form(token));
+        match decode::<StoredToken>(self.get(&key)?)? {
+            None => Ok(None),
+            Some(record) => record
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = format!("{}{}", self.refresh_prefix, self.stored_form(token));
+        match decode::<StoredRefresh>(self.get(&key)?)? {
+            None => Ok(None),
+            Some(entry) => entry
+                .token
+                .grant
+                .to_grant()
+                .map(Some)
+                .map_err(|_| PrimitiveError::Invariant),
+        }
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        let stored = self.stored_form(token);
+        let refresh_key = format!("{}{}", self.refresh_prefix, stored);
+        // A refresh token takes its access token with it, but not the other way around.
+        let access = match decode::<StoredRefresh>(take(&self.connection, &refresh_key)?)? {
+            Some(old) => old.token.access,
+            None => stored,
+        };
+        self.connection
+            .del(&[format!("{}{}", self.access_prefix, access)])
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
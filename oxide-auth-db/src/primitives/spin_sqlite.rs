@@ -0,0 +1,359 @@
+//! Authorizer and issuer persisting to the SQLite database of a Spin component.
+//!
+//! Both primitives share the schema created by `db_service::spin_sqlite::migrate` and only ever
+//! look up rows through their primary or an indexed key. Every component instance starts with a
+//! fresh usage counter, so the tagger should not be deterministic in it alone; the
+//! `RandomGenerator` is a good choice.
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+use spin_sdk::sqlite::{Connection, QueryResult, Value};
+
+use crate::db_service::health::Health;
+use crate::db_service::spin_sqlite::{health_check, migrate};
+use crate::primitives::{system_clock, SharedClock};
+use crate::primitives::stored::StoredGrant;
+
+/// An authorizer keeping its codes in the `oauth_codes` table.
+pub struct SpinSqliteAuthorizer<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    connection: Connection,
+    tagger: I,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// An issuer keeping its tokens in the `oauth_tokens` table.
+pub struct SpinSqliteIssuer<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    connection: Connection,
+    generator: G,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+fn encode_grant(grant: &Grant) -> Result<Value, PrimitiveError> {
+    serde_json::to_string(&StoredGrant::from_grant(grant))
+        .map(Value::Text)
+        .map_err(|_| PrimitiveError::Invariant)
+}
+
+/// Decode the grant of the first row, if there is one.
+fn first_grant(result: &QueryResult) -> Result<Option<Grant>, PrimitiveError> {
+    let row = match result.rows.first() {
+        None => return Ok(None),
+        Some(row) => row,
+    };
+    let data: &str = row.get(0).ok_or(PrimitiveError::Invariant)?;
+    let stored: StoredGrant = serde_json::from_str(data).map_err(|_| PrimitiveError::Invariant)?;
+    stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant)
+}
+
+impl<I: TagGrant> SpinSqliteAuthorizer<I> {
+    /// Create an authorizer on the connection, applying all pending migrations.
+    pub fn new(connection: Connection, tagger: I) -> anyhow::Result<Self> {
+        migrate(&connection)?;
+        Ok(SpinSqliteAuthorizer {
+            connection,
+            tagger,
+            usage: 0,
+            clock: system_clock(),
+        })
+    }
+
+    /// Purge expired codes by the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Probe the availability of the database.
+    pub fn health_check(&self) -> Health {
+        health_check(&self.connection)
+    }
+
+    /// Delete all codes whose grant has expired.
+    pub fn purge_expired(&self) -> anyhow::Result<()> {
+        self.connection.execute(
+            "DELETE FROM oauth_codes WHERE expires_at < ?",
+            &[Value::Integer(self.clock.now().timestamp())],
+        )?;
+        Ok(())
+    }
+}
+
+impl<I: TagGrant> Authorizer for SpinSqliteAuthorizer<I> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        self.connection
+            .execute(
+                "INSERT INTO oauth_codes (code, grant_data, expires_at) VALUES (?, ?, ?)",
+                &[
+                    Value::Text(code.clone()),
+                    encode_grant(&grant)?,
+                    Value::Integer(grant.until.timestamp()),
+                ],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        // Deleting and returning in one statement ensures a code can only be redeemed once.
+        let result = self
+            .connection
+            .execute(
+                "DELETE FROM oauth_codes WHERE code = ? RETURNING grant_data",
+                &[Value::Text(code.to_owned())],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        first_grant(&result)
+    }
+}
+
+impl<G: TagGrant> SpinSqliteIssuer<G> {
+    /// Create an issuer on the connection, applying all pending migrations.
+    pub fn new(connection: Connection, generator: G) -> anyhow::Result<Self> {
+        migrate(&connection)?;
+        Ok(SpinSqliteIssuer {
+            connection,
+            generator,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        })
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Probe the availability of the database.
+    pub fn health_check(&self) -> Health {
+        health_check(&self.connection)
+    }
+
+    /// Delete all tokens whose grant and refresh token have expired.
+    ///
+    /// Tokens with a refresh token that is kept until used are never deleted, so that expired
+    /// access tokens can still be refreshed.
+    pub fn purge_expired(&self) -> anyhow::Result<()> {
+        let now = self.clock.now().timestamp();
+        self.connection.execute(
+            "DELETE FROM oauth_tokens WHERE expires_at < ?
+                AND (refresh_token IS NULL OR refresh_expires_at < ?)",
+            &[Value::Integer(now), Value::Integer(now)],
+        )?;
+        Ok(())
+    }
+
+    /// Revoke all access and refresh tokens issued to the resource owner.
+    ///
+    /// Returns the number of revoked token pairs. Intended for events such as a password change,
+    /// after which no previously authorized client should retain access. Tokens stored before the
+    /// second schema migration carry no owner and are not affected.
+    pub fn revoke_all_for_owner(&self, owner_id: &str) -> anyhow::Result<usize> {
+        let removed = self.connection.execute(
+            "DELETE FROM oauth_tokens WHERE owner_id = ? RETURNING access_token",
+            &[Value::Text(owner_id.to_owned())],
+        )?;
+        Ok(removed.rows.len())
+    }
+
+    /// Revoke all access and refresh tokens issued to the client.
+    ///
+    /// Returns the number of revoked token pairs, for example after the client was compromised.
+    pub fn revoke_all_for_client(&self, client_id: &str) -> anyhow::Result<usize> {
+        let removed = self.connection.execute(
+            "DELETE FROM oauth_tokens WHERE client_id = ? RETURNING access_token",
+            &[Value::Text(client_id.to_owned())],
+        )?;
+        Ok(removed.rows.len())
+    }
+
+    /// A new access token, with a refresh token unless the grant lacks `offline_access`.
+    fn token_pair(&mut self, grant: &Grant) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+        Ok((access, refresh))
+    }
+
+    fn store(
+        &self, access: &str, refresh: Option<&str>, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>,
+    ) -> Result<(), PrimitiveError> {
+        let timestamp = |until: Option<DateTime<Utc>>| {
+            until.map_or(Value::Null, |until| Value::Integer(until.timestamp()))
+        };
+        self.connection
+            .execute(
+                "INSERT INTO oauth_tokens
+                    (access_token, refresh_token, grant_data, expires_at, owner_id, client_id,
+                    refresh_expires_at, session_expires_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                &[
+                    Value::Text(access.to_owned()),
+                    refresh.map_or(Value::Null, |refresh| Value::Text(refresh.to_owned())),
+                    encode_grant(grant)?,
+                    Value::Integer(grant.until.timestamp()),
+                    Value::Text(grant.owner_id.clone()),
+                    Value::Text(grant.client_id.clone()),
+                    timestamp(refresh_until),
+                    timestamp(session_until),
+                ],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
+
+impl<G: TagGrant> Issuer for SpinSqliteIssuer<G> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.token_pair(&grant)?;
+        self.store(&access, refresh.as_deref(), &grant, refresh_until, session_until)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        // Invalidates both the old refresh token and its access token.
+        let removed = self
+            .connection
+            .execute(
+                "DELETE FROM oauth_tokens WHERE refresh_token = ? RETURNING session_expires_at",
+                &[Value::Text(refresh.to_owned())],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        // Should only be called on valid refresh tokens.
+        let row = removed.rows.first().ok_or(PrimitiveError::NotFound)?;
+        let session_until = row
+            .get::<i64>(0)
+            .and_then(|session| Utc.timestamp_opt(session, 0).single());
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, session_until, now);
+        let (new_access, new_refresh) = self.token_pair(&grant)?;
+        self.store(
+            &new_access,
+            new_refresh.as_deref(),
+            &grant,
+            refresh_until,
+            session_until,
+        )?;
+        Ok(RefreshedToken {
+            token: new_access,
+            refresh: new_refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let result = self
+            .connection
+            .execute(
+                "SELECT grant_data FROM oauth_tokens WHERE access_token = ?",
+                &[Value::Text(token.to_owned())],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        first_grant(&result)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let result = self
+            .connection
+            .execute(
+                "SELECT grant_data FROM oauth_tokens WHERE refresh_token = ?
+                    AND (refresh_expires_at IS NULL OR refresh_expires_at >= ?)",
+                &[
+                    Value::Text(token.to_owned()),
+                    Value::Integer(self.clock.now().timestamp()),
+                ],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        first_grant(&result)
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // Both tokens of a pair share one row, so revoking either ends the other as well.
+        self.connection
+            .execute(
+                "DELETE FROM oauth_tokens WHERE ? IN (access_token, refresh_token)",
+                &[Value::Text(token.to_owned())],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
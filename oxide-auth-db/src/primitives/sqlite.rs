@@ -0,0 +1,573 @@
+//! Authorizer and issuer persisting to an embedded SQLite database.
+//!
+//! Both primitives share the pool and schema of `SqliteClientRepository` and only ever look up
+//! rows through their primary or an indexed key. Codes and tokens survive restarts of the
+//! process, so the tagger should be random, such as the `RandomGenerator`.
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::clock::Clock;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::generator::TagGrant;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{
+    offline_access, IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType,
+};
+use r2d2_sqlite::rusqlite::{params, OptionalExtension, ToSql};
+
+use crate::db_service::pool::RetryPolicy;
+use crate::db_service::sqlite::{SqliteClientRepository, SqlitePool};
+use crate::primitives::{system_clock, SharedClock};
+use crate::primitives::stored::StoredGrant;
+
+/// An authorizer keeping its codes in the `oauth_codes` table.
+pub struct SqliteAuthorizer<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    pool: SqlitePool,
+    tagger: I,
+    usage: u64,
+    clock: SharedClock,
+}
+
+/// An issuer keeping its tokens in the `oauth_tokens` table.
+pub struct SqliteIssuer<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    pool: SqlitePool,
+    retry: RetryPolicy,
+    generator: G,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    offline_access: bool,
+    usage: u64,
+    clock: SharedClock,
+}
+
+fn encode_grant(grant: &Grant) -> Result<String, PrimitiveError> {
+    serde_json::to_string(&StoredGrant::from_grant(grant)).map_err(|_| PrimitiveError::Invariant)
+}
+
+/// Decode the grant data of a row, if there is one.
+fn decode_grant(data: Option<String>) -> Result<Option<Grant>, PrimitiveError> {
+    let data = match data {
+        None => return Ok(None),
+        Some(data) => data,
+    };
+    let stored: StoredGrant = serde_json::from_str(&data).map_err(|_| PrimitiveError::Invariant)?;
+    stored.to_grant().map(Some).map_err(|_| PrimitiveError::Invariant)
+}
+
+impl<I: TagGrant> SqliteAuthorizer<I> {
+    /// Create an authorizer on the pool of the repository.
+    pub fn new(repository: &SqliteClientRepository, tagger: I) -> Self {
+        SqliteAuthorizer {
+            pool: repository.get_pool(),
+            tagger,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Purge expired codes by the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Delete all codes whose grant has expired.
+    pub fn purge_expired(&self) -> anyhow::Result<usize> {
+        let deleted = self.pool.get()?.execute(
+            "DELETE FROM oauth_codes WHERE expires_at < ?1",
+            params![self.clock.now().timestamp()],
+        )?;
+        Ok(deleted)
+    }
+}
+
+impl<I: TagGrant> Authorizer for SqliteAuthorizer<I> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        let next_usage = self.usage.wrapping_add(1);
+        let code = self
+            .tagger
+            .tag(self.usage, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        self.pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .execute(
+                "INSERT INTO oauth_codes (code, grant_data, expires_at) VALUES (?1, ?2, ?3)",
+                params![code, encode_grant(&grant)?, grant.until.timestamp()],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        self.usage = next_usage;
+        Ok(code)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        // Deleting and returning in one statement ensures a code can only be redeemed once.
+        let data = self
+            .pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .query_row(
+                "DELETE FROM oauth_codes WHERE code = ?1 RETURNING grant_data",
+                params![code],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        decode_grant(data)
+    }
+}
+
+impl<G: TagGrant> SqliteIssuer<G> {
+    /// Create an issuer on the pool of the repository.
+    pub fn new(repository: &SqliteClientRepository, generator: G) -> Self {
+        SqliteIssuer {
+            pool: repository.get_pool(),
+            retry: repository.retry_policy(),
+            generator,
+            lifetimes: Box::new(Lifetimes::default()),
+            offline_access: false,
+            usage: 0,
+            clock: system_clock(),
+        }
+    }
+
+    /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
+    }
+
+    /// All grants are valid for their default duration.
+    pub fn valid_for_default(&mut self) {
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Refresh tokens without a lifetime are kept until they are used. The session of a grant is
+    /// stored with its refresh token, so no refreshed token outlives it.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Determine whether refresh tokens require the `offline_access` scope.
+    ///
+    /// By default every grant receives a refresh token. When on, only grants including the
+    /// `offline_access` scope receive one, and a refresh that narrows the scope to exclude it also
+    /// ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
+    /// Compute lifetimes and check the expiry of refresh tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Delete all tokens whose grant has expired, unless their refresh token is still valid.
+    pub fn purge_expired(&self) -> anyhow::Result<usize> {
+        let deleted = self.pool.get()?.execute(
+            "DELETE FROM oauth_tokens WHERE expires_at < ?1
+                AND (refresh_token IS NULL OR refresh_expires_at < ?1)",
+            params![self.clock.now().timestamp()],
+        )?;
+        Ok(deleted)
+    }
+
+    /// Revoke all access and refresh tokens issued to the resource owner.
+    ///
+    /// Returns the number of revoked token pairs.
+    pub fn revoke_all_for_owner(&self, owner_id: &str) -> anyhow::Result<usize> {
+        let deleted = self
+            .pool
+            .get()?
+            .execute("DELETE FROM oauth_tokens WHERE owner_id = ?1", params![owner_id])?;
+        Ok(deleted)
+    }
+
+    /// Revoke all access and refresh tokens issued to the client.
+    ///
+    /// Returns the number of revoked token pairs.
+    pub fn revoke_all_for_client(&self, client_id: &str) -> anyhow::Result<usize> {
+        let deleted = self.pool.get()?.execute(
+            "DELETE FROM oauth_tokens WHERE client_id = ?1",
+            params![client_id],
+        )?;
+        Ok(deleted)
+    }
+
+    /// A new access token, with a refresh token unless the grant lacks `offline_access`.
+    fn token_pair(&mut self, grant: &Grant) -> Result<(String, Option<String>), PrimitiveError> {
+        let access = self
+            .generator
+            .tag(self.usage, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = if !self.offline_access || offline_access(grant) {
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            Some(refresh)
+        } else {
+            None
+        };
+        self.usage = self.usage.wrapping_add(2);
+        Ok((access, refresh))
+    }
+
+    fn store(
+        &self, access: &str, refresh: Option<&str>, grant: &Grant, refresh_until: Option<DateTime<Utc>>,
+        session_until: Option<DateTime<Utc>>,
+    ) -> Result<(), PrimitiveError> {
+        self.pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .execute(
+                "INSERT INTO oauth_tokens
+                    (access_token, refresh_token, grant_data, expires_at, owner_id, client_id,
+                    refresh_expires_at, session_expires_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    access,
+                    refresh,
+                    encode_grant(grant)?,
+                    grant.until.timestamp(),
+                    grant.owner_id,
+                    grant.client_id,
+                    refresh_until.map(|until| until.timestamp()),
+                    session_until.map(|until| until.timestamp()),
+                ],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+
+    fn recover(&self, statement: &str, params: &[&dyn ToSql]) -> Result<Option<Grant>, PrimitiveError> {
+        let data = self.retry.run(|| {
+            self.pool
+                .get()
+                .map_err(|_| PrimitiveError::Unavailable)?
+                .query_row(statement, params, |row| row.get(0))
+                .optional()
+                .map_err(|_| PrimitiveError::Unavailable)
+        })?;
+        decode_grant(data)
+    }
+}
+
+impl<G: TagGrant> Issuer for SqliteIssuer<G> {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
+        let (access, refresh) = self.token_pair(&grant)?;
+        self.store(&access, refresh.as_deref(), &grant, refresh_until, session_until)?;
+        Ok(IssuedToken {
+            token: access,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        // Invalidates both the old refresh token and its access token.
+        let removed = self
+            .pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .query_row(
+                "DELETE FROM oauth_tokens WHERE refresh_token = ?1 RETURNING session_expires_at",
+                params![refresh],
+                |row| row.get::<_, Option<i64>>(0),
+            )
+            .optional()
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        // Should only be called on valid refresh tokens.
+        let session_until = removed
+            .ok_or(PrimitiveError::NotFound)?
+            .and_then(|session| Utc.timestamp_opt(session, 0).single());
+
+        let now = self.clock.now();
+        let refresh_until = self
+            .lifetimes
+            .lifetimes(&grant)
+            .expire(&mut grant, session_until, now);
+        let (new_access, new_refresh) = self.token_pair(&grant)?;
+        self.store(
+            &new_access,
+            new_refresh.as_deref(),
+            &grant,
+            refresh_until,
+            session_until,
+        )?;
+        Ok(RefreshedToken {
+            token: new_access,
+            refresh: new_refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.recover(
+            "SELECT grant_data FROM oauth_tokens WHERE access_token = ?1",
+            params![token],
+        )
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.recover(
+            "SELECT grant_data FROM oauth_tokens WHERE refresh_token = ?1
+                AND (refresh_expires_at IS NULL OR refresh_expires_at >= ?2)",
+            params![token, self.clock.now().timestamp()],
+        )
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // Both tokens of a pair share one row, so revoking either ends the other as well.
+        self.pool
+            .get()
+            .map_err(|_| PrimitiveError::Unavailable)?
+            .execute(
+                "DELETE FROM oauth_tokens WHERE ?1 IN (access_token, refresh_token)",
+                params![token],
+            )
+            .map_err(|_| PrimitiveError::Unavailable)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+    use oxide_auth::primitives::prelude::Client;
+    use oxide_auth::primitives::registrar::{RegisteredUrl, Registrar};
+
+    use crate::db_service::audit::{AuditAction, AuditLog, AuditedRepository};
+    use crate::db_service::consent::{Consent, ConsentStore};
+    use crate::primitives::db_registrar::{DBRegistrar, OauthClientDBRepository};
+    use crate::primitives::metadata::ClientMetadata;
+
+    fn grant(owner_id: &str) -> Grant {
+        Grant {
+            owner_id: owner_id.to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    #[test]
+    fn register_and_check() {
+        let mut registrar = DBRegistrar::with_repository(SqliteClientRepository::in_memory().unwrap());
+        registrar
+            .register_client(Client::confidential(
+                "SqliteClient",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                "default".parse().unwrap(),
+                b"secret",
+            ))
+            .unwrap();
+        registrar.check("SqliteClient", Some(b"secret")).unwrap();
+        assert!(registrar.check("SqliteClient", Some(b"wrong")).is_err());
+        assert!(registrar.check("Unknown", None).is_err());
+    }
+
+    #[test]
+    fn manage_clients() {
+        let mut registrar = DBRegistrar::with_repository(SqliteClientRepository::in_memory().unwrap());
+        let client = |scope: &str| {
+            Client::confidential(
+                "SqliteClient",
+                RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+                scope.parse().unwrap(),
+                b"secret",
+            )
+        };
+        assert!(registrar.update_client(client("default")).is_err());
+        registrar.register_client(client("default")).unwrap();
+        registrar.update_client(client("default extra")).unwrap();
+
+        let secret = registrar.rotate_secret("SqliteClient").unwrap();
+        assert!(registrar.check("SqliteClient", Some(b"secret")).is_err());
+        registrar.check("SqliteClient", Some(secret.as_bytes())).unwrap();
+
+        registrar.disable_client("SqliteClient").unwrap();
+        assert!(registrar.repo.is_client_disabled("SqliteClient").unwrap());
+        assert!(registrar.check("SqliteClient", Some(secret.as_bytes())).is_err());
+        assert_eq!(registrar.repo.list().unwrap().len(), 1);
+        registrar.enable_client("SqliteClient").unwrap();
+        registrar.check("SqliteClient", Some(secret.as_bytes())).unwrap();
+
+        assert_eq!(registrar.client_metadata("SqliteClient").unwrap(), None);
+        let metadata = ClientMetadata {
+            client_name: Some("Example".to_owned()),
+            ..ClientMetadata::default()
+        };
+        registrar.set_client_metadata("SqliteClient", metadata).unwrap();
+        let stored = registrar.client_metadata("SqliteClient").unwrap().unwrap();
+        assert_eq!(stored.client_name.as_deref(), Some("Example"));
+
+        assert!(registrar.delete_client("SqliteClient").unwrap());
+        assert!(!registrar.delete_client("SqliteClient").unwrap());
+        assert!(registrar.disable_client("SqliteClient").is_err());
+    }
+
+    #[test]
+    fn audit_log() {
+        let repository = SqliteClientRepository::in_memory().unwrap();
+        let audited = AuditedRepository::new(repository.clone(), repository.clone()).soft_delete(true);
+        let mut registrar = DBRegistrar::with_repository(audited.acting_as("admin"));
+        let client = Client::public(
+            "SqliteClient",
+            RegisteredUrl::Semantic("https://example.com/endpoint".parse().unwrap()),
+            "default".parse().unwrap(),
+        );
+        registrar.register_client(client).unwrap();
+        registrar.delete_client("SqliteClient").unwrap();
+        assert!(registrar.repo.list().unwrap().is_empty());
+        assert!(repository.is_client_disabled("SqliteClient").unwrap());
+
+        let events = repository.events(Some("SqliteClient")).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].action, AuditAction::SoftDeleted);
+        assert!(repository.events(Some("Other")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn consent_store() {
+        let repository = SqliteClientRepository::in_memory().unwrap();
+        let mut consent = Consent {
+            owner_id: "Owner".to_owned(),
+            client_id: "SqliteClient".to_owned(),
+            scope: "read".parse().unwrap(),
+            approved_at: Utc::now(),
+        };
+        repository.store_consent(&consent).unwrap();
+        consent.scope = "read write".parse().unwrap();
+        repository.store_consent(&consent).unwrap();
+        assert_eq!(
+            repository.find_consent("Owner", "SqliteClient").unwrap(),
+            Some(consent)
+        );
+        assert_eq!(repository.find_consent("Other", "SqliteClient").unwrap(), None);
+        assert!(repository.revoke_consent("Owner", "SqliteClient").unwrap());
+        assert!(!repository.revoke_consent("Owner", "SqliteClient").unwrap());
+    }
+
+    #[test]
+    fn code_is_single_use() {
+        let repository = SqliteClientRepository::in_memory().unwrap();
+        let mut authorizer = SqliteAuthorizer::new(&repository, RandomGenerator::new(16));
+        let grant = grant("Owner");
+        let code = authorizer.authorize(grant.clone()).unwrap();
+        assert_eq!(authorizer.extract(&code).unwrap(), Some(grant));
+        assert_eq!(authorizer.extract(&code).unwrap(), None);
+    }
+
+    #[test]
+    fn refresh_and_revoke() {
+        let repository = SqliteClientRepository::in_memory().unwrap();
+        let mut issuer = SqliteIssuer::new(&repository, RandomGenerator::new(16));
+        let issued = issuer.issue(grant("Owner")).unwrap();
+        let other = issuer.issue(grant("Other")).unwrap();
+        let refresh = issued.refresh.unwrap();
+
+        let refreshed = issuer.refresh(&refresh, grant("Owner")).unwrap();
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+        assert!(issuer.refresh(&refresh, grant("Owner")).is_err());
+
+        let revoked = issuer.issue(grant("Owner")).unwrap();
+        issuer.revoke(revoked.refresh.as_ref().unwrap()).unwrap();
+        assert!(issuer.recover_token(&revoked.token).unwrap().is_none());
+        issuer.revoke(&revoked.token).unwrap();
+
+        assert_eq!(issuer.revoke_all_for_owner("Owner").unwrap(), 1);
+        assert!(issuer.recover_token(&refreshed.token).unwrap().is_none());
+        assert!(issuer.recover_token(&other.token).unwrap().is_some());
+    }
+
+    #[test]
+    fn offline_access_required() {
+        let repository = SqliteClientRepository::in_memory().unwrap();
+        let mut issuer = SqliteIssuer::new(&repository, RandomGenerator::new(16));
+        issuer.require_offline_access(true);
+        // Several tokens without a refresh token do not collide in the unique column.
+        for _ in 0..2 {
+            let issued = issuer.issue(grant("Owner")).unwrap();
+            assert!(issued.refresh.is_none());
+            assert!(issuer.recover_token(&issued.token).unwrap().is_some());
+        }
+
+        let mut offline = grant("Owner");
+        offline.scope = "default offline_access".parse().unwrap();
+        let issued = issuer.issue(offline).unwrap();
+        let refreshed = issuer.refresh(&issued.refresh.unwrap(), grant("Owner")).unwrap();
+        assert!(refreshed.refresh.is_none());
+    }
+
+    #[test]
+    fn lifetime_policy() {
+        let repository = SqliteClientRepository::in_memory().unwrap();
+        let mut issuer = SqliteIssuer::new(&repository, RandomGenerator::new(16));
+        issuer.lifetime_policy(Lifetimes {
+            access: Some(Duration::minutes(-1)),
+            refresh: Some(Duration::days(14)),
+            session: Some(Duration::minutes(5)),
+        });
+        let issued = issuer.issue(grant("Owner")).unwrap();
+        // The refresh token outlives its expired access token.
+        assert_eq!(issuer.purge_expired().unwrap(), 0);
+
+        // Refreshing keeps the session of the first token.
+        issuer.valid_for(Duration::days(1));
+        let refreshed = issuer.refresh(&issued.refresh.unwrap(), grant("Owner")).unwrap();
+        assert!(refreshed.until <= Utc::now() + Duration::minutes(5));
+
+        issuer.lifetime_policy(Lifetimes {
+            access: Some(Duration::minutes(-1)),
+            refresh: Some(Duration::minutes(-1)),
+            session: None,
+        });
+        let expired = issuer.issue(grant("Owner")).unwrap();
+        assert!(issuer
+            .recover_refresh(&expired.refresh.unwrap())
+            .unwrap()
+            .is_none());
+        assert_eq!(issuer.purge_expired().unwrap(), 1);
+    }
+
+    #[test]
+    fn file_database_is_shared() {
+        let path = std::env::temp_dir().join(format!("oxide-auth-db-{}.sqlite", std::process::id()));
+        let repository = SqliteClientRepository::open(&path, 4).unwrap();
+        let mut issuer = SqliteIssuer::new(&repository, RandomGenerator::new(16));
+        let issued = issuer.issue(grant("Owner")).unwrap();
+
+        let reopened = SqliteClientRepository::open(&path, 4).unwrap();
+        let issuer = SqliteIssuer::new(&reopened, RandomGenerator::new(16));
+        assert!(issuer.recover_token(&issued.token).unwrap().is_some());
+        assert!(reopened.health_check().is_healthy());
+        assert!(reopened.migrate().unwrap().is_empty());
+        assert!(reopened.migration_status().unwrap().is_current());
+
+        drop((repository, reopened));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Serializable representations of the grants kept by database backed primitives.
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use oxide_auth::primitives::grant::{Extensions, Grant, Value};
+use oxide_auth::primitives::prelude::Scope;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// A `Grant` in a form that can be written to and read back from a database.
+///
+/// In contrast to self-encoded tokens the record never leaves the server, so private extensions
+/// are stored alongside public ones.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredGrant {
+    /// Identifies the owner of the resource.
+    pub owner_id: String,
+
+    /// Identifies the client to which the grant was issued.
+    pub client_id: String,
+
+    /// The scope granted to the client, in its space separated form.
+    pub scope: String,
+
+    /// The redirection uri under which the client resides.
+    pub redirect_uri: String,
+
+    /// Expiration date of the grant (Utc).
+    pub until: DateTime<Utc>,
+
+    /// The public extensions of the grant.
+    #[serde(default)]
+    pub public_extensions: HashMap<String, Option<String>>,
+
+    /// The private extensions of the grant.
+    #[serde(default)]
+    pub private_extensions: HashMap<String, Option<String>>,
+}
+
+impl StoredGrant {
+    /// Recover the grant from its stored form.
+    pub fn to_grant(&self) -> anyhow::Result<Grant> {
+        let mut extensions = Extensions::new();
+        for (name, content) in &self.public_extensions {
+            extensions.set_raw(name.clone(), Value::public(content.clone()));
+        }
+        for (name, content) in &self.private_extensions {
+            extensions.set_raw(name.clone(), Value::private(content.clone()));
+        }
+
+        Ok(Grant {
+            owner_id: self.owner_id.clone(),
+            client_id: self.client_id.clone(),
+            scope: Scope::from_str(&self.scope).map_err(|e| anyhow::anyhow!("{:?}", e))?,
+            redirect_uri: Url::parse(&self.redirect_uri)?,
+            until: self.until,
+            extensions,
+        })
+    }
+
+    /// Capture a grant for storing.
+    pub fn from_grant(grant: &Grant) -> Self {
+        let own = |(name, content): (&str, Option<&str>)| (name.to_owned(), content.map(str::to_owned));
+        StoredGrant {
+            owner_id: grant.owner_id.clone(),
+            client_id: grant.client_id.clone(),
+            scope: grant.scope.to_string(),
+            redirect_uri: grant.redirect_uri.to_string(),
+            until: grant.until,
+            public_extensions: grant.extensions.public().map(own).collect(),
+            private_extensions: grant.extensions.private().map(own).collect(),
+        }
+    }
+}
+
+/// A token pair as stored by key-value backends under both its access and its refresh key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredToken {
+    /// The access token, or its `token_hash` if the issuer stores hashes.
+    pub access: String,
+
+    /// The refresh token or its `token_hash`, if one was issued.
+    pub refresh: Option<String>,
+
+    /// The grant represented by the token.
+    pub grant: StoredGrant,
+}
+
+/// A refresh token as stored by key-value backends, with an expiry independent of its grant.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredRefresh {
+    /// The token pair the refresh token belongs to.
+    pub token: StoredToken,
+
+    /// When the refresh token expires, it is kept until used if this is `None`.
+    pub until: Option<DateTime<Utc>>,
+
+    /// When the session of the grant ends, refreshing does not extend any token beyond it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_until: Option<DateTime<Utc>>,
+}
+
+impl StoredRefresh {
+    /// A refresh token expiring at `until`, as determined by `Lifetimes::expire`.
+    pub fn new(
+        token: StoredToken, until: Option<DateTime<Utc>>, session_until: Option<DateTime<Utc>>,
+    ) -> Self {
+        StoredRefresh {
+            token,
+            until,
+            session_until,
+        }
+    }
+
+    /// Whether the refresh token expired before `now`.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.until.is_some_and(|until| until < now)
+    }
+}
+
+/// The SHA-256 hash of a token, hex encoded, to store in place of the token itself.
+///
+/// Tokens are random and long enough that an unsalted hash cannot be reversed, while the issuer
+/// still finds the entry of a presented token by hashing it again.
+pub fn token_hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let mut extensions = Extensions::new();
+        extensions.set_raw("pkce".into(), Value::private(Some("S256:abc".into())));
+        extensions.set_raw("flag".into(), Value::public(None));
+        let grant = Grant {
+            owner_id: "Owner".to_string(),
+            client_id: "Client".to_string(),
+            scope: "one two".parse().unwrap(),
+            redirect_uri: "https://example.com/redirect_me".parse().unwrap(),
+            until: Utc::now(),
+            extensions,
+        };
+
+        let json = serde_json::to_string(&StoredGrant::from_grant(&grant)).unwrap();
+        let stored: StoredGrant = serde_json::from_str(&json).unwrap();
+        assert_eq!(stored.to_grant().unwrap(), grant);
+    }
+
+    #[test]
+    fn token_hash_is_hex_sha256() {
+        assert_eq!(
+            token_hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Authorizers and issuers partitioned between tenants.
+//!
+//! The wrapped primitive stores grants with the tenant prefix on their client id, see the
+//! `db_service::tenant` module. Grants of other tenants are not recovered, so a token presented to
+//! the wrong tenant is treated as unknown. Codes can only be checked once extracted, a code
+//! presented to the wrong tenant is consumed and rejected.
+use oxide_auth::primitives::authorizer::Authorizer;
+use oxide_auth::primitives::error::PrimitiveError;
+use oxide_auth::primitives::grant::Grant;
+use oxide_auth::primitives::issuer::{IssuedToken, Issuer, RefreshedToken};
+
+use crate::db_service::tenant::tenant_prefix;
+
+/// Keeps the grants of a tenant apart from other tenants on a shared authorizer.
+pub struct TenantAuthorizer<A: Authorizer> {
+    inner: A,
+    prefix: String,
+}
+
+/// Keeps the grants of a tenant apart from other tenants on a shared issuer.
+pub struct TenantIssuer<I: Issuer> {
+    inner: I,
+    prefix: String,
+}
+
+fn enter(prefix: &str, mut grant: Grant) -> Grant {
+    grant.client_id = format!("{}{}", prefix, grant.client_id);
+    grant
+}
+
+fn leave(prefix: &str, grant: Option<Grant>) -> Option<Grant> {
+    let mut grant = grant?;
+    grant.client_id = grant.client_id.strip_prefix(prefix)?.to_owned();
+    Some(grant)
+}
+
+impl<A: Authorizer> TenantAuthorizer<A> {
+    /// Partition the authorizer for the tenant.
+    pub fn new(inner: A, tenant: &str) -> anyhow::Result<Self> {
+        Ok(TenantAuthorizer {
+            inner,
+            prefix: tenant_prefix(tenant)?,
+        })
+    }
+
+    /// The shared authorizer.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+impl<A: Authorizer> Authorizer for TenantAuthorizer<A> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        self.inner.authorize(enter(&self.prefix, grant))
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        Ok(leave(&self.prefix, self.inner.extract(code)?))
+    }
+}
+
+impl<I: Issuer> TenantIssuer<I> {
+    /// Partition the issuer for the tenant.
+    pub fn new(inner: I, tenant: &str) -> anyhow::Result<Self> {
+        Ok(TenantIssuer {
+            inner,
+            prefix: tenant_prefix(tenant)?,
+        })
+    }
+
+    /// The shared issuer.
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I: Issuer> Issuer for TenantIssuer<I> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        self.inner.issue(enter(&self.prefix, grant))
+    }
+
+    fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        // Never let a tenant replace the refresh token of another one.
+        self.recover_refresh(refresh)?.ok_or(PrimitiveError::NotFound)?;
+        self.inner.refresh(refresh, enter(&self.prefix, grant))
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        Ok(leave(&self.prefix, self.inner.recover_token(token)?))
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        Ok(leave(&self.prefix, self.inner.recover_refresh(token)?))
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // Tokens of other tenants are as unknown as tokens that do not exist.
+        if self.recover_token(token)?.is_none() && self.recover_refresh(token)?.is_none() {
+            return Ok(());
+        }
+        self.inner.revoke(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use oxide_auth::primitives::generator::RandomGenerator;
+    use oxide_auth::primitives::grant::Extensions;
+
+    use crate::db_service::kv::MemoryStore;
+    use crate::primitives::kv::KvIssuer;
+
+    #[test]
+    fn tokens_are_isolated() {
+        let store = MemoryStore::new();
+        let issuer = |tenant: &str| {
+            TenantIssuer::new(KvIssuer::new(store.clone(), RandomGenerator::new(16)), tenant).unwrap()
+        };
+        let grant = Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        };
+
+        let mut acme = issuer("acme");
+        let issued = acme.issue(grant.clone()).unwrap();
+        assert_eq!(acme.recover_token(&issued.token).unwrap(), Some(grant.clone()));
+
+        let mut other = issuer("other");
+        assert_eq!(other.recover_token(&issued.token).unwrap(), None);
+        let refresh = issued.refresh.unwrap();
+        assert!(other.refresh(&refresh, grant.clone()).is_err());
+        assert!(acme.refresh(&refresh, grant).is_ok());
+    }
+}
@@ -0,0 +1,806 @@
+//! Dynamic client registration, as specified in [RFC 7591].
+//!
+//! The [`RegistrationFlow`] validates the metadata of a registration request, persists the new
+//! client through any `OauthClientDBRepository` and answers with the standard client information
+//! response. It does not depend on a web framework, a frontend only has to pass on the JSON body
+//! of the request and send back the status and JSON body returned by [`execute_json`].
+//!
+//! The registration access token of the response is signed with an `Assertion` instead of being
+//! stored. It identifies the client it was issued for and can be checked with
+//! [`check_access_token`], but can not be revoked before it expires other than by changing the
+//! key of the assertion.
+//!
+//! Only metadata the repository can store is registered and returned, that is the redirect uris,
+//! the scope, the authentication method and the descriptive fields of `ClientMetadata`. Registered
+//! clients are allowed all grant types accepted by the flow, since registrars do not record them
+//! per client.
+//!
+//! Registrations can be held for the approval of an administrator with [`require_approval`]. A
+//! pending client is stored disabled, so it can not obtain tokens, until it is [`approve`]d. With
+//! the `jwt` feature, clients of trusted vendors skip the approval by presenting a software
+//! statement signed by one of the publishers registered with [`trust_software_publisher`]. The
+//! metadata of a valid statement takes precedence over the values of the request.
+//!
+//! [RFC 7591]: https://tools.ietf.org/html/rfc7591
+//! [`RegistrationFlow`]: struct.RegistrationFlow.html
+//! [`execute_json`]: struct.RegistrationFlow.html#method.execute_json
+//! [`check_access_token`]: struct.RegistrationFlow.html#method.check_access_token
+//! [`require_approval`]: struct.RegistrationFlow.html#method.require_approval
+//! [`approve`]: struct.RegistrationFlow.html#method.approve
+//! [`trust_software_publisher`]: struct.RegistrationFlow.html#method.trust_software_publisher
+use chrono::{Duration, Utc};
+use oxide_auth::primitives::generator::Assertion;
+#[cfg(feature = "jwt")]
+use oxide_auth::primitives::jwt::{self, JwkSet};
+use oxide_auth::primitives::grant::{Extensions, Grant};
+use oxide_auth::primitives::registrar::{Client, ExactUrl, PasswordPolicy, RegisteredUrl};
+use oxide_auth::primitives::scope::Scope;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::primitives::db_registrar::{OauthClientDBRepository, DEFAULT_PASSWORD_POLICY};
+use crate::primitives::metadata::ClientMetadata;
+
+/// The usage tag of signed registration access tokens.
+const ACCESS_TOKEN_TAG: &str = "registration";
+
+/// The client metadata of a registration request.
+///
+/// Fields not listed here are ignored, as permitted by the specification.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RegistrationRequest {
+    /// Redirection uris for use in redirect-based flows.
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+
+    /// How the client authenticates at the token endpoint, `client_secret_basic` by default.
+    pub token_endpoint_auth_method: Option<String>,
+
+    /// The grant types the client will use, `authorization_code` by default.
+    pub grant_types: Option<Vec<String>>,
+
+    /// The response types the client will use, `code` by default.
+    pub response_types: Option<Vec<String>>,
+
+    /// Space separated scope values the client may request.
+    pub scope: Option<String>,
+
+    /// A JWT asserting metadata of the client on behalf of its vendor.
+    ///
+    /// Ignored unless the flow trusts the publisher of the statement.
+    pub software_statement: Option<String>,
+
+    /// Name, logo, contacts and other descriptive metadata of the client.
+    #[serde(flatten)]
+    pub metadata: ClientMetadata,
+}
+
+/// The client information response of a successful registration.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RegistrationResponse {
+    /// The newly issued client identifier.
+    pub client_id: String,
+
+    /// The client secret, absent for public clients.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+
+    /// Seconds since the epoch at which the client identifier was issued.
+    pub client_id_issued_at: i64,
+
+    /// Seconds since the epoch at which the secret expires, `0` if it never does.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret_expires_at: Option<i64>,
+
+    /// Token for reading and updating the registration.
+    pub registration_access_token: String,
+
+    /// The registered redirection uris.
+    pub redirect_uris: Vec<String>,
+
+    /// The registered authentication method.
+    pub token_endpoint_auth_method: String,
+
+    /// The registered grant types.
+    pub grant_types: Vec<String>,
+
+    /// The registered response types.
+    pub response_types: Vec<String>,
+
+    /// The registered scope.
+    pub scope: String,
+
+    /// The registered descriptive metadata.
+    #[serde(flatten)]
+    pub metadata: ClientMetadata,
+}
+
+/// The error codes of a failed registration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationErrorType {
+    /// A redirection uri is missing or not acceptable.
+    InvalidRedirectUri,
+
+    /// Some other metadata value is invalid or not supported.
+    InvalidClientMetadata,
+
+    /// The software statement is malformed, expired or not signed by its publisher.
+    InvalidSoftwareStatement,
+
+    /// The publisher of the software statement is not trusted.
+    UnapprovedSoftwareStatement,
+
+    /// The client could not be stored.
+    ServerError,
+}
+
+/// The error response of a failed registration.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RegistrationError {
+    /// The error code.
+    pub error: RegistrationErrorType,
+
+    /// A human readable explanation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+}
+
+/// Registers clients in a repository on their own request.
+pub struct RegistrationFlow<R: OauthClientDBRepository> {
+    repository: R,
+    assertion: Assertion,
+    default_scope: Scope,
+    grant_types: Vec<String>,
+    access_token_duration: Duration,
+    password_policy: Option<Box<dyn PasswordPolicy>>,
+    approval: bool,
+    #[cfg(feature = "jwt")]
+    publishers: Vec<(String, JwkSet)>,
+}
+
+/// The claims of a software statement.
+#[cfg(feature = "jwt")]
+#[derive(Deserialize)]
+struct SoftwareStatement {
+    iss: String,
+    exp: Option<i64>,
+    #[serde(flatten)]
+    request: RegistrationRequest,
+}
+
+impl RegistrationError {
+    fn new(error: RegistrationErrorType, description: &str) -> Self {
+        RegistrationError {
+            error,
+            error_description: Some(description.to_owned()),
+        }
+    }
+
+    fn redirect_uri(description: &str) -> Self {
+        Self::new(RegistrationErrorType::InvalidRedirectUri, description)
+    }
+
+    fn metadata(description: &str) -> Self {
+        Self::new(RegistrationErrorType::InvalidClientMetadata, description)
+    }
+
+    #[cfg(feature = "jwt")]
+    fn statement(description: &str) -> Self {
+        Self::new(RegistrationErrorType::InvalidSoftwareStatement, description)
+    }
+
+    fn server(description: &str) -> Self {
+        Self::new(RegistrationErrorType::ServerError, description)
+    }
+
+    /// The http status code to respond with.
+    pub fn status(&self) -> u16 {
+        match self.error {
+            RegistrationErrorType::ServerError => 500,
+            _ => 400,
+        }
+    }
+}
+
+impl RegistrationRequest {
+    /// Replace the values of the request with those set in a software statement.
+    #[cfg(feature = "jwt")]
+    fn overlay(mut self, statement: RegistrationRequest) -> Self {
+        if !statement.redirect_uris.is_empty() {
+            self.redirect_uris = statement.redirect_uris;
+        }
+        self.token_endpoint_auth_method = statement
+            .token_endpoint_auth_method
+            .or(self.token_endpoint_auth_method);
+        self.grant_types = statement.grant_types.or(self.grant_types);
+        self.response_types = statement.response_types.or(self.response_types);
+        self.scope = statement.scope.or(self.scope);
+
+        let (metadata, own) = (statement.metadata, &mut self.metadata);
+        own.client_name = metadata.client_name.or(own.client_name.take());
+        own.logo_uri = metadata.logo_uri.or(own.logo_uri.take());
+        own.policy_uri = metadata.policy_uri.or(own.policy_uri.take());
+        own.tos_uri = metadata.tos_uri.or(own.tos_uri.take());
+        own.software_id = metadata.software_id.or(own.software_id.take());
+        if !metadata.contacts.is_empty() {
+            own.contacts = metadata.contacts;
+        }
+        self
+    }
+}
+
+pub(crate) fn random_string(bytes: usize) -> String {
+    let mut data = vec![0; bytes];
+    OsRng.fill_bytes(&mut data);
+    base64::encode_config(&data, base64::URL_SAFE_NO_PAD)
+}
+
+impl<R: OauthClientDBRepository> RegistrationFlow<R> {
+    /// Register clients in the repository, signing registration access tokens with the assertion.
+    ///
+    /// Clients that do not request a scope get the default scope. The flow accepts the
+    /// `authorization_code` and `refresh_token` grant types, registration access tokens are valid
+    /// for 30 days.
+    pub fn new(repository: R, assertion: Assertion, default_scope: Scope) -> Self {
+        RegistrationFlow {
+            repository,
+            assertion,
+            default_scope,
+            grant_types: vec!["authorization_code".to_owned(), "refresh_token".to_owned()],
+            access_token_duration: Duration::days(30),
+            password_policy: None,
+            approval: false,
+            #[cfg(feature = "jwt")]
+            publishers: Vec::new(),
+        }
+    }
+
+    /// Change the grant types clients may register for.
+    pub fn allow_grant_types(&mut self, grant_types: &[&str]) {
+        self.grant_types = grant_types.iter().map(|&grant| grant.to_owned()).collect();
+    }
+
+    /// Change how long registration access tokens are valid.
+    pub fn access_token_valid_for(&mut self, duration: Duration) {
+        self.access_token_duration = duration;
+    }
+
+    /// Change how client secrets are encoded while stored.
+    ///
+    /// This must be the policy of the registrar which authenticates the clients.
+    pub fn set_password_policy<P: PasswordPolicy + 'static>(&mut self, new_policy: P) {
+        self.password_policy = Some(Box::new(new_policy))
+    }
+
+    /// Hold newly registered clients for the approval of an administrator.
+    ///
+    /// Clients presenting a software statement of a trusted publisher are approved right away.
+    pub fn require_approval(&mut self, required: bool) {
+        self.approval = required;
+    }
+
+    /// Accept software statements issued by `iss` and signed with one of its keys.
+    #[cfg(feature = "jwt")]
+    pub fn trust_software_publisher(&mut self, iss: &str, keys: JwkSet) {
+        self.publishers.retain(|(publisher, _)| publisher != iss);
+        self.publishers.push((iss.to_owned(), keys));
+    }
+
+    /// The repository clients are registered in.
+    pub fn repository(&self) -> &R {
+        &self.repository
+    }
+
+    /// The clients awaiting approval, with their metadata.
+    pub fn pending_clients(&self) -> anyhow::Result<Vec<(String, ClientMetadata)>> {
+        let mut pending = Vec::new();
+        for client in self.repository.list()? {
+            match self.repository.find_client_metadata(&client.client_id)? {
+                Some(metadata) if metadata.pending_approval => {
+                    pending.push((client.client_id, metadata))
+                }
+                _ => (),
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Approve a pending client so that it can obtain tokens.
+    pub fn approve(&mut self, client_id: &str) -> anyhow::Result<()> {
+        let previous = self.pending_metadata(client_id)?;
+        let metadata = ClientMetadata {
+            pending_approval: false,
+            ..previous.clone()
+        }
+        .stamped(Some(&previous));
+        // The mark is cleared first, a failure leaves the client disabled instead of pending.
+        self.repository.store_client_metadata(client_id, &metadata)?;
+        self.repository.set_client_disabled(client_id, false)
+    }
+
+    /// Reject a pending client, deleting its registration.
+    pub fn reject(&mut self, client_id: &str) -> anyhow::Result<()> {
+        self.pending_metadata(client_id)?;
+        self.repository.delete_client(client_id).map(drop)
+    }
+
+    fn pending_metadata(&self, client_id: &str) -> anyhow::Result<ClientMetadata> {
+        match self.repository.find_client_metadata(client_id)? {
+            Some(metadata) if metadata.pending_approval => Ok(metadata),
+            _ => Err(anyhow::anyhow!("Client {} is not awaiting approval", client_id)),
+        }
+    }
+
+    /// Apply a software statement of a trusted publisher to the request.
+    ///
+    /// Returns whether the client is approved by the statement.
+    #[cfg(feature = "jwt")]
+    fn software_statement(
+        &self, request: RegistrationRequest,
+    ) -> Result<(RegistrationRequest, bool), RegistrationError> {
+        let token = match &request.software_statement {
+            Some(token) if !self.publishers.is_empty() => token,
+            _ => return Ok((request, false)),
+        };
+
+        #[derive(Deserialize)]
+        struct Issuer {
+            iss: String,
+        }
+
+        let issuer: Issuer = jwt::unverified_claims(token)
+            .map_err(|_| RegistrationError::statement("Malformed software statement"))?;
+        let keys = self
+            .publishers
+            .iter()
+            .find(|(publisher, _)| *publisher == issuer.iss)
+            .map(|(_, keys)| keys)
+            .ok_or_else(|| {
+                RegistrationError::new(
+                    RegistrationErrorType::UnapprovedSoftwareStatement,
+                    "The publisher of the software statement is not trusted",
+                )
+            })?;
+        let statement: SoftwareStatement = keys.verify(token).map_err(|_| {
+            RegistrationError::statement("The software statement is not signed by its publisher")
+        })?;
+        if statement.iss != issuer.iss {
+            return Err(RegistrationError::statement(
+                "The software statement is not signed by its publisher",
+            ));
+        }
+        if statement.exp.is_some_and(|exp| exp <= Utc::now().timestamp()) {
+            return Err(RegistrationError::statement("The software statement has expired"));
+        }
+
+        Ok((request.overlay(statement.request), true))
+    }
+
+    #[cfg(not(feature = "jwt"))]
+    fn software_statement(
+        &self, request: RegistrationRequest,
+    ) -> Result<(RegistrationRequest, bool), RegistrationError> {
+        Ok((request, false))
+    }
+
+    /// Validate the request and register a new client.
+    pub fn execute(
+        &mut self, request: RegistrationRequest,
+    ) -> Result<RegistrationResponse, RegistrationError> {
+        let (request, approved) = self.software_statement(request)?;
+        let pending = self.approval && !approved;
+
+        let grant_types = request
+            .grant_types
+            .unwrap_or_else(|| vec!["authorization_code".to_owned()]);
+        if let Some(grant) = grant_types.iter().find(|grant| !self.grant_types.contains(grant)) {
+            return Err(RegistrationError::metadata(&format!(
+                "Grant type {} is not supported",
+                grant
+            )));
+        }
+
+        let uses_code = grant_types.iter().any(|grant| grant == "authorization_code");
+        let default_response_types = if uses_code {
+            vec!["code".to_owned()]
+        } else {
+            Vec::new()
+        };
+        let response_types = request.response_types.unwrap_or(default_response_types);
+        if response_types.iter().any(|response| response != "code") {
+            return Err(RegistrationError::metadata(
+                "Only the code response type is supported",
+            ));
+        }
+        if uses_code == response_types.is_empty() {
+            return Err(RegistrationError::metadata(
+                "The code response type and authorization_code grant type must be used together",
+            ));
+        }
+
+        let auth_method = request
+            .token_endpoint_auth_method
+            .unwrap_or_else(|| "client_secret_basic".to_owned());
+        let confidential = match auth_method.as_str() {
+            "none" => false,
+            "client_secret_basic" | "client_secret_post" => true,
+            _ => {
+                return Err(RegistrationError::metadata(
+                    "Unsupported token endpoint authentication method",
+                ))
+            }
+        };
+
+        let scope = match &request.scope {
+            None => self.default_scope.clone(),
+            Some(scope) => scope
+                .parse::<Scope>()
+                .map_err(|_| RegistrationError::metadata("Malformed scope"))?,
+        };
+
+        // Registrars always need a default redirect uri, even for clients without redirects.
+        let mut redirect_uris = Vec::with_capacity(request.redirect_uris.len());
+        for uri in &request.redirect_uris {
+            let url = Url::parse(uri)
+                .map_err(|_| RegistrationError::redirect_uri("Malformed redirect uri"))?;
+            if url.fragment().is_some() {
+                return Err(RegistrationError::redirect_uri(
+                    "Redirect uris must not contain a fragment",
+                ));
+            }
+            let exact = ExactUrl::new(uri.clone())
+                .map_err(|_| RegistrationError::redirect_uri("Malformed redirect uri"))?;
+            redirect_uris.push((url, RegisteredUrl::Exact(exact)));
+        }
+        if redirect_uris.is_empty() {
+            return Err(RegistrationError::redirect_uri(
+                "At least one redirect uri is required",
+            ));
+        }
+
+        let uris = [
+            &request.metadata.logo_uri,
+            &request.metadata.policy_uri,
+            &request.metadata.tos_uri,
+        ];
+        if uris
+            .iter()
+            .filter_map(|uri| uri.as_ref())
+            .any(|uri| Url::parse(uri).is_err())
+        {
+            return Err(RegistrationError::metadata("Malformed metadata uri"));
+        }
+
+        let client_id = random_string(16);
+        let secret = if confidential {
+            Some(random_string(32))
+        } else {
+            None
+        };
+        let (first_url, first) = redirect_uris[0].clone();
+        let additional = redirect_uris[1..].iter().map(|(_, uri)| uri.clone()).collect();
+        let client = match &secret {
+            None => Client::public(&client_id, first, scope.clone()),
+            Some(secret) => Client::confidential(&client_id, first, scope.clone(), secret.as_bytes()),
+        }
+        .with_additional_redirect_uris(additional);
+
+        let policy = self
+            .password_policy
+            .as_deref()
+            .unwrap_or(&*DEFAULT_PASSWORD_POLICY);
+        self.repository
+            .regist_from_encoded_client(client.encode(policy))
+            .map_err(|_| RegistrationError::server("Could not store the client"))?;
+
+        // Timestamps and the approval are for the server to record, not for the client to claim.
+        let mut metadata = request.metadata;
+        metadata.created_at = None;
+        metadata.updated_at = None;
+        metadata.pending_approval = pending;
+        if pending && self.repository.set_client_disabled(&client_id, true).is_err() {
+            self.repository.delete_client(&client_id).ok();
+            return Err(RegistrationError::server(
+                "Could not hold the client for approval",
+            ));
+        }
+        if metadata != ClientMetadata::default() {
+            metadata = metadata.stamped(None);
+            if self
+                .repository
+                .store_client_metadata(&client_id, &metadata)
+                .is_err()
+            {
+                // A pending client must not remain without the mark that lists it for approval.
+                if pending {
+                    self.repository.delete_client(&client_id).ok();
+                }
+                return Err(RegistrationError::server("Could not store the client metadata"));
+            }
+        }
+
+        let now = Utc::now();
+        let registration_access_token = self
+            .assertion
+            .tag(ACCESS_TOKEN_TAG)
+            .sign(
+                0,
+                &Grant {
+                    owner_id: client_id.clone(),
+                    client_id: client_id.clone(),
+                    scope: scope.clone(),
+                    redirect_uri: first_url,
+                    until: now + self.access_token_duration,
+                    extensions: Extensions::new(),
+                },
+            )
+            .map_err(|_| RegistrationError::server("Could not sign the token"))?;
+
+        Ok(RegistrationResponse {
+            client_id,
+            client_secret_expires_at: secret.as_ref().map(|_| 0),
+            client_secret: secret,
+            client_id_issued_at: now.timestamp(),
+            registration_access_token,
+            redirect_uris: request.redirect_uris,
+            token_endpoint_auth_method: auth_method,
+            grant_types,
+            response_types,
+            scope: scope.to_string(),
+            metadata,
+        })
+    }
+
+    /// Register a client from the JSON body of a request.
+    ///
+    /// Returns the http status code and the JSON body of the response, `201` on success.
+    pub fn execute_json(&mut self, body: &[u8]) -> (u16, String) {
+        let result = serde_json::from_slice(body)
+            .map_err(|_| RegistrationError::metadata("Malformed registration request"))
+            .and_then(|request| self.execute(request));
+        let (status, body) = match result {
+            Ok(response) => (201, serde_json::to_string(&response)),
+            Err(error) => (error.status(), serde_json::to_string(&error)),
+        };
+        // Both responses only consist of strings and numbers.
+        (status, body.expect("Registration responses are serializable"))
+    }
+
+    /// Check that the registration access token was issued for the client and has not expired.
+    pub fn check_access_token(&self, client_id: &str, token: &str) -> bool {
+        match self.assertion.tag(ACCESS_TOKEN_TAG).extract(token) {
+            Ok(grant) => grant.client_id == client_id && grant.until > Utc::now(),
+            Err(()) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::primitives::generator::AssertionKind;
+    use oxide_auth::primitives::registrar::Registrar;
+
+    use crate::db_service::kv::{KvClientRepository, MemoryStore};
+    use crate::primitives::db_registrar::DBRegistrar;
+
+    fn flow() -> RegistrationFlow<KvClientRepository<MemoryStore>> {
+        RegistrationFlow::new(
+            KvClientRepository::new(MemoryStore::new()),
+            Assertion::new(AssertionKind::HmacSha256, b"registration key"),
+            "default".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn registers_confidential_client() {
+        let mut flow = flow();
+        let (status, body) = flow.execute_json(
+            br#"{"redirect_uris": ["https://client.example/cb", "https://client.example/other"],
+                "scope": "read write", "client_name": "Example", "logo_uri": "https://client.example/logo.png",
+                "software_statement": "ignored"}"#,
+        );
+        assert_eq!(status, 201);
+
+        let response: RegistrationResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(response.token_endpoint_auth_method, "client_secret_basic");
+        assert_eq!(response.grant_types, vec!["authorization_code"]);
+        assert_eq!(response.client_secret_expires_at, Some(0));
+        assert!(flow.check_access_token(&response.client_id, &response.registration_access_token));
+        assert!(!flow.check_access_token("other", &response.registration_access_token));
+
+        let registrar = DBRegistrar::with_repository(flow.repository().clone());
+        let secret = response.client_secret.unwrap();
+        registrar
+            .check(&response.client_id, Some(secret.as_bytes()))
+            .unwrap();
+        let client = flow.repository().find_client_by_id(&response.client_id).unwrap();
+        assert_eq!(client.additional_redirect_uris.len(), 1);
+        assert_eq!(client.default_scope, "read write".parse().unwrap());
+
+        assert_eq!(response.metadata.client_name.as_deref(), Some("Example"));
+        let metadata = flow
+            .repository()
+            .find_client_metadata(&response.client_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(metadata, response.metadata);
+        assert!(metadata.created_at.is_some());
+    }
+
+    #[test]
+    fn registers_public_client() {
+        let response = flow()
+            .execute(RegistrationRequest {
+                redirect_uris: vec!["http://localhost:8021/endpoint".to_owned()],
+                token_endpoint_auth_method: Some("none".to_owned()),
+                grant_types: Some(vec!["authorization_code".to_owned(), "refresh_token".to_owned()]),
+                ..RegistrationRequest::default()
+            })
+            .unwrap();
+        assert!(response.client_secret.is_none());
+        assert!(response.client_secret_expires_at.is_none());
+        assert_eq!(response.scope, "default");
+    }
+
+    #[test]
+    fn rejects_invalid_metadata() {
+        let mut flow = flow();
+        let cases: &[(&[u8], RegistrationErrorType)] = &[
+            (br#"{}"#, RegistrationErrorType::InvalidRedirectUri),
+            (
+                br#"{"redirect_uris": ["https://client.example/cb#fragment"]}"#,
+                RegistrationErrorType::InvalidRedirectUri,
+            ),
+            (
+                br#"{"redirect_uris": ["not a url"]}"#,
+                RegistrationErrorType::InvalidRedirectUri,
+            ),
+            (
+                br#"{"redirect_uris": ["https://client.example/cb"], "grant_types": ["implicit"]}"#,
+                RegistrationErrorType::InvalidClientMetadata,
+            ),
+            (
+                br#"{"redirect_uris": ["https://client.example/cb"], "response_types": ["token"]}"#,
+                RegistrationErrorType::InvalidClientMetadata,
+            ),
+            (
+                br#"{"redirect_uris": ["https://client.example/cb"],
+                    "token_endpoint_auth_method": "private_key_jwt"}"#,
+                RegistrationErrorType::InvalidClientMetadata,
+            ),
+            (
+                br#"{"redirect_uris": ["https://client.example/cb"], "logo_uri": "logo.png"}"#,
+                RegistrationErrorType::InvalidClientMetadata,
+            ),
+            (br#"not json"#, RegistrationErrorType::InvalidClientMetadata),
+        ];
+
+        for (body, expected) in cases {
+            let (status, body) = flow.execute_json(body);
+            assert_eq!(status, 400);
+            let error: RegistrationError = serde_json::from_str(&body).unwrap();
+            assert_eq!(error.error, *expected);
+        }
+        assert!(flow.repository().list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn approval_required() {
+        let mut flow = flow();
+        flow.require_approval(true);
+        let (status, body) = flow.execute_json(
+            br#"{"redirect_uris": ["https://client.example/cb"], "client_name": "Example",
+                "pending_approval": false}"#,
+        );
+        assert_eq!(status, 201);
+        let response: RegistrationResponse = serde_json::from_str(&body).unwrap();
+        assert!(response.metadata.pending_approval);
+
+        let registrar = DBRegistrar::with_repository(flow.repository().clone());
+        let secret = response.client_secret.unwrap();
+        assert!(registrar
+            .check(&response.client_id, Some(secret.as_bytes()))
+            .is_err());
+        let pending = flow.pending_clients().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].0, response.client_id);
+
+        flow.approve(&response.client_id).unwrap();
+        registrar
+            .check(&response.client_id, Some(secret.as_bytes()))
+            .unwrap();
+        assert!(flow.pending_clients().unwrap().is_empty());
+        assert!(flow.approve(&response.client_id).is_err());
+        assert!(flow.reject(&response.client_id).is_err());
+
+        let response = flow
+            .execute(RegistrationRequest {
+                redirect_uris: vec!["https://other.example/cb".to_owned()],
+                ..RegistrationRequest::default()
+            })
+            .unwrap();
+        flow.reject(&response.client_id).unwrap();
+        assert!(flow.pending_clients().unwrap().is_empty());
+        assert_eq!(flow.repository().list().unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "jwt")]
+    #[test]
+    fn software_statement() {
+        use oxide_auth::primitives::jwt::SigningKey;
+
+        let key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let mut flow = flow();
+        flow.require_approval(true);
+        flow.trust_software_publisher(
+            "https://vendor.example",
+            JwkSet {
+                keys: vec![key.jwk()],
+            },
+        );
+
+        let statement = |claims: serde_json::Value| {
+            let token = key.sign("JWT", &claims).unwrap();
+            let body = serde_json::json!({
+                "redirect_uris": ["https://client.example/cb"],
+                "client_name": "Claimed",
+                "software_statement": token,
+            });
+            serde_json::to_vec(&body).unwrap()
+        };
+
+        let trusted = statement(serde_json::json!({
+            "iss": "https://vendor.example",
+            "client_name": "Vendor App",
+            "software_id": "vendor-app",
+        }));
+        let (status, body) = flow.execute_json(&trusted);
+        assert_eq!(status, 201);
+        let response: RegistrationResponse = serde_json::from_str(&body).unwrap();
+        assert!(!response.metadata.pending_approval);
+        assert_eq!(response.metadata.client_name.as_deref(), Some("Vendor App"));
+        assert_eq!(response.metadata.software_id.as_deref(), Some("vendor-app"));
+        assert_eq!(response.redirect_uris, vec!["https://client.example/cb"]);
+        flow.repository().find_client_by_id(&response.client_id).unwrap();
+
+        let cases = [
+            (
+                statement(serde_json::json!({"iss": "https://other.example"})),
+                RegistrationErrorType::UnapprovedSoftwareStatement,
+            ),
+            (
+                statement(serde_json::json!({"iss": "https://vendor.example", "exp": 1})),
+                RegistrationErrorType::InvalidSoftwareStatement,
+            ),
+            (
+                br#"{"redirect_uris": ["https://client.example/cb"], "software_statement": "not a jwt"}"#
+                    .to_vec(),
+                RegistrationErrorType::InvalidSoftwareStatement,
+            ),
+        ];
+        for (body, expected) in &cases {
+            let (status, body) = flow.execute_json(body);
+            assert_eq!(status, 400);
+            let error: RegistrationError = serde_json::from_str(&body).unwrap();
+            assert_eq!(error.error, *expected);
+        }
+
+        let forged = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let token = forged
+            .sign("JWT", &serde_json::json!({"iss": "https://vendor.example"}))
+            .unwrap();
+        let error = flow
+            .execute(RegistrationRequest {
+                redirect_uris: vec!["https://client.example/cb".to_owned()],
+                software_statement: Some(token),
+                ..RegistrationRequest::default()
+            })
+            .unwrap_err();
+        assert_eq!(error.error, RegistrationErrorType::InvalidSoftwareStatement);
+        assert_eq!(flow.repository().list().unwrap().len(), 1);
+    }
+}
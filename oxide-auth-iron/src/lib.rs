@@ -210,6 +210,7 @@ impl<'a, 'b, 'c: 'b> From<SimpleError<OAuthRequest<'a, 'b, 'c>>> for OAuthError
             EndpointError::BadRequest => Status::BadRequest,
             EndpointError::DenySilently => Status::BadRequest,
             EndpointError::PrimitiveError => Status::InternalServerError,
+            EndpointError::TemporarilyUnavailable => Status::ServiceUnavailable,
         };
 
         OAuthError(IronError::new(as_oauth, status))
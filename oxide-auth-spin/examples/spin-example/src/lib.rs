@@ -0,0 +1,125 @@
+use oxide_auth::{
+    endpoint::{OwnerConsent, QueryParameter, Solicitation},
+    frontends::simple::endpoint::{FnSolicitor, Generic, Vacant},
+    primitives::prelude::{Client, RandomGenerator, Scope},
+    primitives::registrar::RegisteredUrl,
+};
+use oxide_auth_db::{
+    db_service::spin_sqlite::SpinSqliteDataSource,
+    primitives::db_registrar::DBRegistrar,
+    primitives::spin_sqlite::{SpinSqliteAuthorizer, SpinSqliteIssuer},
+};
+use oxide_auth_spin::{authorize, protect, refresh, token, OAuthRequest, OAuthResponse};
+use spin_sdk::{
+    http::{IntoResponse, Method, Request, Response},
+    http_component,
+    sqlite::Connection,
+};
+
+static DENY_TEXT: &str = "<html>
+This page should be accessed via an oauth token from the client in the example. Click
+<a href=\"/authorize?response_type=code&client_id=LocalClient\">
+here</a> to begin the authorization process.
+</html>
+";
+
+type Endpoint<S> = Generic<
+    DBRegistrar<SpinSqliteDataSource>,
+    SpinSqliteAuthorizer<RandomGenerator>,
+    SpinSqliteIssuer<RandomGenerator>,
+    S,
+    Vec<Scope>,
+    Vacant,
+>;
+
+/// Every request runs in a fresh instance, all state lives in the sqlite database.
+fn endpoint<S>(solicitor: S) -> anyhow::Result<Endpoint<S>> {
+    let mut registrar = DBRegistrar::with_repository(SpinSqliteDataSource::open_default()?);
+    // Registering is an upsert, so the example client is simply written on each request.
+    registrar
+        .register_client(Client::public(
+            "LocalClient",
+            RegisteredUrl::Semantic("http://localhost:8021/endpoint".parse()?),
+            "default-scope".parse().unwrap(),
+        ))
+        .map_err(|err| anyhow::anyhow!("{:?}", err))?;
+
+    Ok(Generic {
+        registrar,
+        authorizer: SpinSqliteAuthorizer::new(Connection::open_default()?, RandomGenerator::new(16))?,
+        issuer: SpinSqliteIssuer::new(Connection::open_default()?, RandomGenerator::new(16))?,
+        solicitor,
+        scopes: vec!["default-scope".parse().unwrap()],
+        response: Vacant,
+    })
+}
+
+fn consent_page_html(solicitation: Solicitation) -> String {
+    let grant = solicitation.pre_grant();
+    let mut query = vec![
+        ("response_type", "code"),
+        ("client_id", grant.client_id.as_str()),
+        ("redirect_uri", grant.redirect_uri.as_str()),
+    ];
+    if let Some(state) = solicitation.state() {
+        query.push(("state", state));
+    }
+    let query = serde_urlencoded::to_string(query).unwrap();
+
+    format!(
+        "<html>'{}' (at {}) is requesting permission for '{}'
+<form method=\"post\">
+    <input type=\"submit\" value=\"Accept\" formaction=\"/authorize?{3}&allow=true\">
+    <input type=\"submit\" value=\"Deny\" formaction=\"/authorize?{3}&deny=true\">
+</form>
+</html>",
+        grant.client_id, grant.redirect_uri, grant.scope, query
+    )
+}
+
+/// Show the consent page instead of deciding.
+fn ask_owner(_: &mut OAuthRequest, solicitation: Solicitation) -> OwnerConsent<OAuthResponse> {
+    let page = OAuthResponse::default()
+        .content_type("text/html")
+        .body(&consent_page_html(solicitation));
+    OwnerConsent::InProgress(page)
+}
+
+/// Read the decision submitted through the consent page.
+fn owner_decision(request: &mut OAuthRequest, _: Solicitation) -> OwnerConsent<OAuthResponse> {
+    // Some authentication should be performed here in production cases
+    let allowed = request
+        .query()
+        .and_then(|query| query.unique_value("allow"))
+        .is_some();
+    if allowed {
+        OwnerConsent::Authorized("dummy user".to_owned())
+    } else {
+        OwnerConsent::Denied
+    }
+}
+
+#[http_component]
+fn handle(request: Request) -> anyhow::Result<Response> {
+    let response = match (request.method(), request.path()) {
+        (Method::Get, "/authorize") => authorize(&mut endpoint(FnSolicitor(ask_owner))?, request),
+        (Method::Post, "/authorize") => authorize(&mut endpoint(FnSolicitor(owner_decision))?, request),
+        (Method::Post, "/token") => token(&mut endpoint(Vacant)?, request),
+        (Method::Post, "/refresh") => refresh(&mut endpoint(Vacant)?, request),
+        (Method::Get, "/") => match protect(&mut endpoint(Vacant)?, &request) {
+            Ok(_grant) => Response::new(200, "Hello world!"),
+            Err(denied) => {
+                let mut denied = denied.into_builder();
+                denied.header("content-type", "text/html").body(DENY_TEXT).build()
+            }
+        },
+        (Method::Get, "/healthz") => {
+            let health = SpinSqliteDataSource::open_default()?.health_check();
+            let status = if health.is_healthy() { 200 } else { 503 };
+            Response::new(status, health.error.unwrap_or_else(|| "ok".to_owned()))
+        }
+        _ => Response::new(404, "Not Found"),
+    };
+
+    Ok(response.into_response())
+}
@@ -0,0 +1,107 @@
+use crate::OAuthRequest;
+use oxide_auth::frontends::{dev::OAuthError, simple::endpoint::Error};
+use spin_sdk::http::{IntoResponse, Response};
+
+#[derive(Debug)]
+/// The error type for Oxide Auth operations
+pub enum WebError {
+    /// Errors occuring in Endpoint operations
+    Endpoint(OAuthError),
+
+    /// Request query was absent or could not be parsed
+    Query,
+
+    /// Request body was absent or could not be parsed as a form
+    Body,
+
+    /// Request body was sent with a content type other than `application/x-www-form-urlencoded`
+    UnsupportedContentType(String),
+
+    /// A header required by the component was not present
+    MissingHeader(&'static str),
+
+    /// The Authorization header was invalid
+    Authorization,
+
+    /// A store or other service used by the component failed
+    Internal(String),
+}
+
+impl WebError {
+    /// The http status code with which the error is reported to the client.
+    pub fn status(&self) -> u16 {
+        match self {
+            WebError::Endpoint(OAuthError::PrimitiveError) => 500,
+            WebError::Endpoint(OAuthError::TemporarilyUnavailable) => 503,
+            WebError::Endpoint(OAuthError::DenySilently) => 400,
+            WebError::Endpoint(OAuthError::BadRequest) => 400,
+            WebError::Query => 400,
+            WebError::Body => 400,
+            WebError::UnsupportedContentType(_) => 415,
+            WebError::MissingHeader(_) => 400,
+            WebError::Authorization => 400,
+            WebError::Internal(_) => 500,
+        }
+    }
+}
+
+impl std::fmt::Display for WebError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            WebError::Endpoint(ref e) => write!(f, "Endpoint, {}", e),
+            WebError::Query => write!(f, "No query present"),
+            WebError::Body => write!(f, "No body present"),
+            WebError::UnsupportedContentType(ref ct) => write!(f, "Unsupported content type {}", ct),
+            WebError::MissingHeader(name) => write!(f, "Missing header {}", name),
+            WebError::Authorization => write!(f, "Request has invalid Authorization headers"),
+            WebError::Internal(ref e) => write!(f, "Internal, {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WebError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            WebError::Endpoint(ref e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl IntoResponse for WebError {
+    fn into_response(self) -> Response {
+        // Details of internal failures are not meant for the client.
+        let body = match self {
+            WebError::Endpoint(OAuthError::PrimitiveError) | WebError::Internal(_) => {
+                "Internal server error".to_owned()
+            }
+            ref other => other.to_string(),
+        };
+        Response::builder()
+            .status(self.status())
+            .header("content-type", "text/plain")
+            .body(body)
+            .build()
+    }
+}
+
+impl From<Error<OAuthRequest>> for WebError {
+    fn from(e: Error<OAuthRequest>) -> Self {
+        match e {
+            Error::Web(e) => e,
+            Error::OAuth(e) => e.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for WebError {
+    fn from(e: anyhow::Error) -> Self {
+        WebError::Internal(e.to_string())
+    }
+}
+
+impl From<OAuthError> for WebError {
+    fn from(e: OAuthError) -> Self {
+        WebError::Endpoint(e)
+    }
+}
@@ -0,0 +1,173 @@
+//! Adaptations and integration for Spin components.
+//!
+//! Wraps the `spin_sdk::http` request and response types so that the flows of `oxide-auth` can be
+//! driven from within a Spin http handler.
+#![warn(missing_docs)]
+
+mod error;
+pub use error::WebError;
+
+mod request;
+pub use request::OAuthRequest;
+
+mod response;
+pub use response::OAuthResponse;
+
+mod operations;
+pub use operations::{authorize, protect, refresh, token};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::frontends::dev::{Cow, WebRequest, WebResponse};
+    use spin_sdk::http::{IntoResponse, Method, Request};
+
+    #[test]
+    fn multi_query() {
+        let request = Request::new(Method::Get, "/authorize?fine=val&param=a&param=b");
+        let mut request = OAuthRequest::new(request).unwrap();
+        let query = WebRequest::query(&mut request).unwrap();
+
+        assert_eq!(Some(Cow::Borrowed("val")), query.unique_value("fine"));
+        assert_eq!(None, query.unique_value("param"));
+    }
+
+    #[test]
+    fn form_body_and_auth() {
+        let request = Request::builder()
+            .method(Method::Post)
+            .uri("/token")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .header("authorization", "Basic Zm9vOmJhcg==")
+            .body("grant_type=authorization_code&code=abc")
+            .build();
+        let mut request = OAuthRequest::new(request).unwrap();
+
+        let body = request.urlbody().unwrap();
+        assert_eq!(Some(Cow::Borrowed("abc")), body.unique_value("code"));
+        drop(body);
+        assert_eq!(
+            Some(Cow::Borrowed("Basic Zm9vOmJhcg==")),
+            request.authheader().unwrap()
+        );
+    }
+
+    #[test]
+    fn body_requires_form() {
+        let request = Request::builder()
+            .method(Method::Post)
+            .uri("/token")
+            .header("content-type", "application/json")
+            .body("{}")
+            .build();
+        let mut request = OAuthRequest::new(request).unwrap();
+        match request.urlbody() {
+            Err(WebError::UnsupportedContentType(ct)) => assert_eq!(ct, "application/json"),
+            _ => panic!("Expected the content type to be rejected"),
+        }
+
+        let request = Request::new(Method::Post, "/token");
+        let mut request = OAuthRequest::new(request).unwrap();
+        assert!(matches!(request.urlbody(), Err(WebError::Body)));
+    }
+
+    #[test]
+    fn error_responses() {
+        use oxide_auth::frontends::dev::OAuthError;
+
+        let response = WebError::UnsupportedContentType("text/plain".to_owned()).into_response();
+        assert_eq!(415, *response.status());
+
+        let response = WebError::Endpoint(OAuthError::BadRequest).into_response();
+        assert_eq!(400, *response.status());
+
+        let response = WebError::Internal("connection refused".to_owned()).into_response();
+        assert_eq!(500, *response.status());
+        assert_eq!(b"Internal server error", response.body());
+    }
+
+    #[test]
+    fn redirect_response() {
+        let mut response = OAuthResponse::default();
+        response
+            .redirect("https://client.example/endpoint?code=x".parse().unwrap())
+            .unwrap();
+
+        let response = response.into_response();
+        assert_eq!(302, *response.status());
+        assert_eq!(
+            Some("https://client.example/endpoint?code=x"),
+            response.header("location").and_then(|value| value.as_str())
+        );
+    }
+
+    #[test]
+    fn code_grant_operations() {
+        use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+        use oxide_auth::frontends::simple::endpoint::{FnSolicitor, Generic, Vacant};
+        use oxide_auth::primitives::prelude::*;
+        use oxide_auth::primitives::registrar::RegisteredUrl;
+
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(
+            "LocalClient",
+            RegisteredUrl::Semantic("http://localhost:8021/endpoint".parse().unwrap()),
+            "default-scope".parse().unwrap(),
+        ));
+        let mut endpoint = Generic {
+            registrar,
+            authorizer: AuthMap::new(RandomGenerator::new(16)),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            solicitor: FnSolicitor(|_: &mut OAuthRequest, _: Solicitation| {
+                OwnerConsent::Authorized("dummy user".to_owned())
+            }),
+            scopes: vec!["default-scope".parse::<Scope>().unwrap()],
+            response: Vacant,
+        };
+
+        let request = Request::new(Method::Get, "/authorize?response_type=code&client_id=LocalClient");
+        let response = authorize(&mut endpoint, request);
+        assert_eq!(302, *response.status());
+        let location: url::Url = response
+            .header("location")
+            .and_then(|value| value.as_str())
+            .unwrap()
+            .parse()
+            .unwrap();
+        let code = location
+            .query_pairs()
+            .find(|(key, _)| key == "code")
+            .map(|(_, code)| code.into_owned())
+            .expect("No code in redirect");
+
+        let request = Request::builder()
+            .method(Method::Post)
+            .uri("/token")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(
+                url::form_urlencoded::Serializer::new(String::new())
+                    .append_pair("grant_type", "authorization_code")
+                    .append_pair("client_id", "LocalClient")
+                    .append_pair("code", &code)
+                    .append_pair("redirect_uri", "http://localhost:8021/endpoint")
+                    .finish(),
+            )
+            .build();
+        let response = token(&mut endpoint, request);
+        assert_eq!(200, *response.status());
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let access_token = body["access_token"].as_str().unwrap();
+
+        let request = Request::builder()
+            .method(Method::Get)
+            .uri("/resource")
+            .header("authorization", format!("Bearer {}", access_token))
+            .build();
+        let grant = protect(&mut endpoint, &request).expect("Token was not accepted");
+        assert_eq!(grant.client_id, "LocalClient");
+
+        let request = Request::new(Method::Get, "/resource");
+        let response = protect(&mut endpoint, &request).unwrap_err();
+        assert_eq!(401, *response.status());
+    }
+}
@@ -0,0 +1,65 @@
+//! Run the flows of a Spin component in a single call.
+//!
+//! Each function wraps the incoming request, executes the corresponding flow on the endpoint and
+//! converts the outcome, including errors, into a `spin_sdk::http::Response`.
+use crate::{OAuthRequest, WebError};
+use oxide_auth::{
+    endpoint::{AccessTokenFlow, AuthorizationFlow, Endpoint, RefreshFlow, ResourceFlow},
+    primitives::grant::Grant,
+};
+use spin_sdk::http::{IntoResponse, Request, Response};
+
+/// Handle a request to the authorization endpoint.
+pub fn authorize<E>(endpoint: E, request: Request) -> Response
+where
+    E: Endpoint<OAuthRequest>,
+    WebError: From<E::Error>,
+{
+    let run = || -> Result<_, WebError> {
+        let request = OAuthRequest::new(request)?;
+        Ok(AuthorizationFlow::prepare(endpoint)?.execute(request)?)
+    };
+    run().into_response()
+}
+
+/// Handle a request to the token endpoint, exchanging an authorization code.
+pub fn token<E>(endpoint: E, request: Request) -> Response
+where
+    E: Endpoint<OAuthRequest>,
+    WebError: From<E::Error>,
+{
+    let run = || -> Result<_, WebError> {
+        let request = OAuthRequest::new(request)?;
+        Ok(AccessTokenFlow::prepare(endpoint)?.execute(request)?)
+    };
+    run().into_response()
+}
+
+/// Handle a request to the token endpoint, refreshing an access token.
+pub fn refresh<E>(endpoint: E, request: Request) -> Response
+where
+    E: Endpoint<OAuthRequest>,
+    WebError: From<E::Error>,
+{
+    let run = || -> Result<_, WebError> {
+        let request = OAuthRequest::new(request)?;
+        Ok(RefreshFlow::prepare(endpoint)?.execute(request)?)
+    };
+    run().into_response()
+}
+
+/// Guard a resource, returning the grant of the bearer token on success.
+///
+/// Only the `Authorization` header of the request is inspected, so the request stays available
+/// to the handler. Otherwise the returned response should be sent back to the client as is.
+pub fn protect<E>(endpoint: E, request: &Request) -> Result<Grant, Response>
+where
+    E: Endpoint<OAuthRequest>,
+    WebError: From<E::Error>,
+{
+    let request = OAuthRequest::resource(request).map_err(IntoResponse::into_response)?;
+    ResourceFlow::prepare(endpoint)
+        .map_err(|err| WebError::from(err).into_response())?
+        .execute(request)
+        .map_err(|response| response.map_err(WebError::from).into_response())
+}
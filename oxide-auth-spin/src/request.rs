@@ -0,0 +1,147 @@
+use oxide_auth::frontends::dev::{NormalizedParameter, QueryParameter, WebRequest};
+use spin_sdk::http::{conversions::TryNonRequestFromRequest, Method, Request};
+use crate::{OAuthResponse, WebError};
+use std::borrow::Cow;
+
+/// Type implementing `WebRequest` for a Spin http request.
+///
+/// The query, `application/x-www-form-urlencoded` body and `Authorization` header are parsed when
+/// the request is wrapped. The original request stays accessible so that a component can still
+/// inspect the path or method for routing.
+pub struct OAuthRequest {
+    inner: Request,
+    auth: Option<String>,
+    content_type: Option<String>,
+    query: Option<NormalizedParameter>,
+    body: Option<NormalizedParameter>,
+}
+
+impl OAuthRequest {
+    /// Wrap a Spin request, parsing the parts relevant to OAuth.
+    ///
+    /// Fails only if the `Authorization` header is not valid utf-8. A missing or malformed query
+    /// or body is reported lazily, when a flow actually requires it.
+    pub fn new(request: Request) -> Result<Self, WebError> {
+        let auth = authorization(&request)?;
+
+        let query = serde_urlencoded::from_str(request.query()).ok();
+
+        let content_type = request
+            .header("content-type")
+            .and_then(|value| value.as_str())
+            .map(str::to_owned);
+        let is_form = content_type
+            .as_deref()
+            .is_some_and(|ct| ct.starts_with("application/x-www-form-urlencoded"));
+        let body = if is_form {
+            serde_urlencoded::from_bytes(request.body()).ok()
+        } else {
+            None
+        };
+
+        Ok(OAuthRequest {
+            inner: request,
+            auth,
+            content_type,
+            query,
+            body,
+        })
+    }
+
+    /// Wrap only the `Authorization` header of a request, for guarding resources.
+    ///
+    /// The query and body are left out and the original request is not consumed.
+    pub fn resource(request: &Request) -> Result<Self, WebError> {
+        let auth = authorization(request)?;
+
+        Ok(OAuthRequest {
+            inner: Request::new(Method::Get, request.uri()),
+            auth,
+            content_type: None,
+            query: None,
+            body: None,
+        })
+    }
+
+    /// Fetch the authorization header from the request
+    pub fn authorization_header(&self) -> Option<&str> {
+        self.auth.as_deref()
+    }
+
+    /// Fetch the content type of the request body
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Fetch the query for this request
+    pub fn query(&self) -> Option<&NormalizedParameter> {
+        self.query.as_ref()
+    }
+
+    /// Fetch the query mutably
+    pub fn query_mut(&mut self) -> Option<&mut NormalizedParameter> {
+        self.query.as_mut()
+    }
+
+    /// Fetch the body of the request
+    pub fn body(&self) -> Option<&NormalizedParameter> {
+        self.body.as_ref()
+    }
+
+    /// Reference the wrapped Spin request
+    pub fn inner(&self) -> &Request {
+        &self.inner
+    }
+
+    /// Recover the wrapped Spin request
+    pub fn into_inner(self) -> Request {
+        self.inner
+    }
+}
+
+fn authorization(request: &Request) -> Result<Option<String>, WebError> {
+    match request.header("authorization") {
+        None => Ok(None),
+        Some(value) => Ok(Some(value.as_str().ok_or(WebError::Authorization)?.to_owned())),
+    }
+}
+
+impl WebRequest for OAuthRequest {
+    type Error = WebError;
+    type Response = OAuthResponse;
+
+    fn query(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
+        self.query
+            .as_ref()
+            .map(|q| Cow::Borrowed(q as &dyn QueryParameter))
+            .ok_or(WebError::Query)
+    }
+
+    fn urlbody(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
+        match (&self.body, &self.content_type) {
+            (Some(body), _) => Ok(Cow::Borrowed(body as &dyn QueryParameter)),
+            (None, Some(ct)) if !ct.starts_with("application/x-www-form-urlencoded") => {
+                Err(WebError::UnsupportedContentType(ct.clone()))
+            }
+            (None, _) => Err(WebError::Body),
+        }
+    }
+
+    fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
+        Ok(self.auth.as_deref().map(Cow::Borrowed))
+    }
+}
+
+impl TryNonRequestFromRequest for OAuthRequest {
+    type Error = WebError;
+
+    fn try_from_request(req: Request) -> Result<Self, Self::Error> {
+        OAuthRequest::new(req)
+    }
+}
+
+impl From<OAuthRequest> for Request {
+    fn from(request: OAuthRequest) -> Request {
+        request.inner
+    }
+}
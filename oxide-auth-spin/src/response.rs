@@ -0,0 +1,104 @@
+use crate::WebError;
+use oxide_auth::frontends::dev::{WebResponse, Url};
+use spin_sdk::http::{IntoResponse, Response};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+/// Type implementing `WebResponse` and `IntoResponse` for use in Spin components
+pub struct OAuthResponse {
+    status: u16,
+    headers: HashMap<String, String>,
+    body: Option<String>,
+}
+
+impl OAuthResponse {
+    /// Set the `ContentType` header on a response
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.headers
+            .insert("content-type".to_owned(), content_type.to_owned());
+        self
+    }
+
+    /// Set the body for the response
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_owned());
+        self
+    }
+
+    /// The http status code of the response
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Fetch a header value that was set on the response
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+}
+
+impl Default for OAuthResponse {
+    fn default() -> Self {
+        OAuthResponse {
+            status: 200,
+            headers: HashMap::new(),
+            body: None,
+        }
+    }
+}
+
+impl WebResponse for OAuthResponse {
+    type Error = WebError;
+
+    fn ok(&mut self) -> Result<(), Self::Error> {
+        self.status = 200;
+        Ok(())
+    }
+
+    fn redirect(&mut self, url: Url) -> Result<(), Self::Error> {
+        self.status = 302;
+        self.headers.insert("location".to_owned(), url.into());
+        Ok(())
+    }
+
+    fn client_error(&mut self) -> Result<(), Self::Error> {
+        self.status = 400;
+        Ok(())
+    }
+
+    fn unauthorized(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = 401;
+        self.headers
+            .insert("www-authenticate".to_owned(), kind.to_owned());
+        Ok(())
+    }
+
+    fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        self.body = Some(text.to_owned());
+        self.headers
+            .insert("content-type".to_owned(), "text/plain".to_owned());
+        Ok(())
+    }
+
+    fn body_json(&mut self, json: &str) -> Result<(), Self::Error> {
+        self.body = Some(json.to_owned());
+        self.headers
+            .insert("content-type".to_owned(), "application/json".to_owned());
+        Ok(())
+    }
+}
+
+impl IntoResponse for OAuthResponse {
+    fn into_response(self) -> Response {
+        Response::builder()
+            .status(self.status)
+            .headers(self.headers)
+            .body(self.body)
+            .build()
+    }
+}
+
+impl From<OAuthResponse> for Response {
+    fn from(response: OAuthResponse) -> Response {
+        response.into_response()
+    }
+}
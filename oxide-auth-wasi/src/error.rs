@@ -0,0 +1,79 @@
+use crate::OAuthRequest;
+use oxide_auth::frontends::{dev::OAuthError, simple::endpoint::Error};
+
+#[derive(Debug)]
+/// The error type for Oxide Auth operations
+pub enum WebError {
+    /// Errors occuring in Endpoint operations
+    Endpoint(OAuthError),
+
+    /// Request query was absent or could not be parsed
+    Query,
+
+    /// Request body was absent or could not be parsed as a form
+    Body,
+
+    /// The Authorization header was invalid
+    Authorization,
+
+    /// Reading the request or writing the response through the host failed
+    Host(String),
+}
+
+impl WebError {
+    /// The http status code with which the error is reported to the client.
+    pub fn status(&self) -> u16 {
+        match self {
+            WebError::Endpoint(OAuthError::PrimitiveError) => 500,
+            WebError::Endpoint(OAuthError::TemporarilyUnavailable) => 503,
+            WebError::Endpoint(OAuthError::DenySilently) => 400,
+            WebError::Endpoint(OAuthError::BadRequest) => 400,
+            WebError::Query => 400,
+            WebError::Body => 400,
+            WebError::Authorization => 400,
+            WebError::Host(_) => 500,
+        }
+    }
+}
+
+impl std::fmt::Display for WebError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            WebError::Endpoint(ref e) => write!(f, "Endpoint, {}", e),
+            WebError::Query => write!(f, "No query present"),
+            WebError::Body => write!(f, "No body present"),
+            WebError::Authorization => write!(f, "Request has invalid Authorization headers"),
+            WebError::Host(ref e) => write!(f, "Host, {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WebError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            WebError::Endpoint(ref e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error<OAuthRequest>> for WebError {
+    fn from(e: Error<OAuthRequest>) -> Self {
+        match e {
+            Error::Web(e) => e,
+            Error::OAuth(e) => e.into(),
+        }
+    }
+}
+
+impl From<OAuthError> for WebError {
+    fn from(e: OAuthError) -> Self {
+        WebError::Endpoint(e)
+    }
+}
+
+impl From<std::io::Error> for WebError {
+    fn from(e: std::io::Error) -> Self {
+        WebError::Host(e.to_string())
+    }
+}
@@ -0,0 +1,174 @@
+//! Adaptations and integration for `wasi:http` components.
+//!
+//! Wraps the resources of the `wasi:http/proxy` world, as exposed by the `wasi` crate, so that the
+//! flows of `oxide-auth` can be driven from an exported `incoming-handler` on any WASI 0.2 host.
+//! No runtime specific SDK is required.
+//!
+//! ```no_run
+//! use oxide_auth::frontends::simple::endpoint::Vacant;
+//! use oxide_auth_wasi::{OAuthRequest, OAuthResponse};
+//! use wasi::http::types::{IncomingRequest, ResponseOutparam};
+//!
+//! struct Component;
+//!
+//! impl wasi::exports::http::incoming_handler::Guest for Component {
+//!     fn handle(request: IncomingRequest, outparam: ResponseOutparam) {
+//!         let response = match OAuthRequest::new(&request) {
+//!             // Construct the endpoint and call `oxide_auth_wasi::authorize` and friends here.
+//!             Ok(request) => OAuthResponse::default().body(request.path()),
+//!             Err(err) => OAuthResponse::from(err),
+//!         };
+//!         let _ = response.send(outparam);
+//!     }
+//! }
+//!
+//! wasi::http::proxy::export!(Component);
+//! # fn main() {}
+//! ```
+#![warn(missing_docs)]
+
+mod error;
+pub use error::WebError;
+
+mod request;
+pub use request::OAuthRequest;
+
+mod response;
+pub use response::OAuthResponse;
+
+mod operations;
+pub use operations::{authorize, protect, refresh, token};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxide_auth::frontends::dev::{Cow, WebRequest, WebResponse};
+    use wasi::http::types::Method;
+
+    fn form(path: &str, body: &str) -> OAuthRequest {
+        let headers = [(
+            "Content-Type".to_owned(),
+            b"application/x-www-form-urlencoded".to_vec(),
+        )];
+        OAuthRequest::from_parts(Method::Post, path.to_owned(), &headers, body.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn multi_query() {
+        let path = "/authorize?fine=val&param=a&param=b".to_owned();
+        let mut request = OAuthRequest::from_parts(Method::Get, path, &[], &[]).unwrap();
+        assert_eq!("/authorize", request.path());
+
+        let query = WebRequest::query(&mut request).unwrap();
+        assert_eq!(Some(Cow::Borrowed("val")), query.unique_value("fine"));
+        assert_eq!(None, query.unique_value("param"));
+    }
+
+    #[test]
+    fn form_body_and_auth() {
+        let headers = [
+            (
+                "content-type".to_owned(),
+                b"application/x-www-form-urlencoded".to_vec(),
+            ),
+            ("Authorization".to_owned(), b"Basic Zm9vOmJhcg==".to_vec()),
+        ];
+        let body = b"grant_type=authorization_code&code=abc";
+        let mut request =
+            OAuthRequest::from_parts(Method::Post, "/token".to_owned(), &headers, body).unwrap();
+
+        let body = request.urlbody().unwrap();
+        assert_eq!(Some(Cow::Borrowed("abc")), body.unique_value("code"));
+        drop(body);
+        assert_eq!(
+            Some(Cow::Borrowed("Basic Zm9vOmJhcg==")),
+            request.authheader().unwrap()
+        );
+
+        let headers = [("content-type".to_owned(), b"application/json".to_vec())];
+        let mut request =
+            OAuthRequest::from_parts(Method::Post, "/token".to_owned(), &headers, b"{}").unwrap();
+        assert!(request.urlbody().is_err());
+    }
+
+    #[test]
+    fn error_status() {
+        use oxide_auth::frontends::dev::OAuthError;
+
+        assert_eq!(WebError::Endpoint(OAuthError::PrimitiveError).status(), 500);
+        assert_eq!(
+            WebError::Endpoint(OAuthError::TemporarilyUnavailable).status(),
+            503
+        );
+        assert_eq!(WebError::Query.status(), 400);
+        assert_eq!(WebError::Authorization.status(), 400);
+    }
+
+    #[test]
+    fn code_grant_operations() {
+        use oxide_auth::endpoint::{OwnerConsent, Solicitation};
+        use oxide_auth::frontends::simple::endpoint::{FnSolicitor, Generic, Vacant};
+        use oxide_auth::primitives::prelude::*;
+        use oxide_auth::primitives::registrar::RegisteredUrl;
+
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(
+            "LocalClient",
+            RegisteredUrl::Semantic("http://localhost:8021/endpoint".parse().unwrap()),
+            "default-scope".parse().unwrap(),
+        ));
+        let mut endpoint = Generic {
+            registrar,
+            authorizer: AuthMap::new(RandomGenerator::new(16)),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            solicitor: FnSolicitor(|_: &mut OAuthRequest, _: Solicitation| {
+                OwnerConsent::Authorized("dummy user".to_owned())
+            }),
+            scopes: vec!["default-scope".parse::<Scope>().unwrap()],
+            response: Vacant,
+        };
+
+        let path = "/authorize?response_type=code&client_id=LocalClient".to_owned();
+        let request = OAuthRequest::from_parts(Method::Get, path, &[], &[]).unwrap();
+        let response = authorize(&mut endpoint, request);
+        assert_eq!(302, response.status());
+        let location: oxide_auth::frontends::dev::Url =
+            response.header("Location").unwrap().parse().unwrap();
+        let code = location
+            .query_pairs()
+            .find(|(key, _)| key == "code")
+            .map(|(_, code)| code.into_owned())
+            .expect("No code in redirect");
+
+        let body = serde_urlencoded::to_string([
+            ("grant_type", "authorization_code"),
+            ("client_id", "LocalClient"),
+            ("code", &code),
+            ("redirect_uri", "http://localhost:8021/endpoint"),
+        ])
+        .unwrap();
+        let response = token(&mut endpoint, form("/token", &body));
+        assert_eq!(200, response.status());
+        assert_eq!(Some("application/json"), response.header("content-type"));
+        let body: std::collections::HashMap<String, serde_json::Value> =
+            serde_json::from_str(response.content().unwrap()).unwrap();
+        let access_token = body["access_token"].as_str().unwrap();
+
+        let headers = [(
+            "authorization".to_owned(),
+            format!("Bearer {}", access_token).into_bytes(),
+        )];
+        let request =
+            OAuthRequest::from_parts(Method::Get, "/resource".to_owned(), &headers, &[]).unwrap();
+        let grant = protect(&mut endpoint, &request).expect("Token was not accepted");
+        assert_eq!(grant.client_id, "LocalClient");
+
+        let request = OAuthRequest::from_parts(Method::Get, "/resource".to_owned(), &[], &[]).unwrap();
+        let response = protect(&mut endpoint, &request).unwrap_err();
+        assert_eq!(401, response.status());
+
+        let mut response = OAuthResponse::default();
+        response.unauthorized("Bearer").unwrap();
+        assert_eq!(Some("Bearer"), response.header("WWW-Authenticate"));
+    }
+}
@@ -0,0 +1,54 @@
+//! Run the flows of a `wasi:http` component in a single call.
+//!
+//! Each function executes the corresponding flow on the endpoint and converts the outcome,
+//! including errors, into an `OAuthResponse` ready to be sent.
+use crate::{OAuthRequest, OAuthResponse, WebError};
+use oxide_auth::{
+    endpoint::{AccessTokenFlow, AuthorizationFlow, Endpoint, RefreshFlow, ResourceFlow},
+    primitives::grant::Grant,
+};
+
+/// Handle a request to the authorization endpoint.
+pub fn authorize<E>(endpoint: E, request: OAuthRequest) -> OAuthResponse
+where
+    E: Endpoint<OAuthRequest>,
+    WebError: From<E::Error>,
+{
+    let run = || -> Result<_, WebError> { Ok(AuthorizationFlow::prepare(endpoint)?.execute(request)?) };
+    run().unwrap_or_else(OAuthResponse::from)
+}
+
+/// Handle a request to the token endpoint, exchanging an authorization code.
+pub fn token<E>(endpoint: E, request: OAuthRequest) -> OAuthResponse
+where
+    E: Endpoint<OAuthRequest>,
+    WebError: From<E::Error>,
+{
+    let run = || -> Result<_, WebError> { Ok(AccessTokenFlow::prepare(endpoint)?.execute(request)?) };
+    run().unwrap_or_else(OAuthResponse::from)
+}
+
+/// Handle a request to the token endpoint, refreshing an access token.
+pub fn refresh<E>(endpoint: E, request: OAuthRequest) -> OAuthResponse
+where
+    E: Endpoint<OAuthRequest>,
+    WebError: From<E::Error>,
+{
+    let run = || -> Result<_, WebError> { Ok(RefreshFlow::prepare(endpoint)?.execute(request)?) };
+    run().unwrap_or_else(OAuthResponse::from)
+}
+
+/// Guard a resource, returning the grant of the bearer token on success.
+///
+/// Only the `Authorization` header of the request is inspected. Otherwise the returned response
+/// should be sent back to the client as is.
+pub fn protect<E>(endpoint: E, request: &OAuthRequest) -> Result<Grant, OAuthResponse>
+where
+    E: Endpoint<OAuthRequest>,
+    WebError: From<E::Error>,
+{
+    ResourceFlow::prepare(endpoint)
+        .map_err(|err| OAuthResponse::from(WebError::from(err)))?
+        .execute(request.resource())
+        .map_err(|response| response.unwrap_or_else(|err| WebError::from(err).into()))
+}
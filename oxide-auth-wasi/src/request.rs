@@ -0,0 +1,158 @@
+use oxide_auth::frontends::dev::{NormalizedParameter, QueryParameter, WebRequest};
+use wasi::http::types::{IncomingBody, IncomingRequest, Method};
+use crate::{OAuthResponse, WebError};
+use std::borrow::Cow;
+use std::io::Read;
+
+/// Type implementing `WebRequest` for a `wasi:http` incoming request.
+///
+/// An `incoming-request` is a host resource whose body can only be consumed once, so the request
+/// is read completely when it is wrapped. Method and path are kept for routing.
+pub struct OAuthRequest {
+    method: Method,
+    path_with_query: String,
+    auth: Option<String>,
+    query: Option<NormalizedParameter>,
+    body: Option<NormalizedParameter>,
+}
+
+impl OAuthRequest {
+    /// Read an incoming request from the host, parsing the parts relevant to OAuth.
+    ///
+    /// The body is only consumed for `application/x-www-form-urlencoded` requests.
+    pub fn new(request: &IncomingRequest) -> Result<Self, WebError> {
+        let headers = request.headers().entries();
+        let path_with_query = request.path_with_query().unwrap_or_default();
+
+        let body = if is_form(&headers) {
+            let incoming = request
+                .consume()
+                .map_err(|_| WebError::Host("Request body already consumed".to_owned()))?;
+            let mut stream = incoming
+                .stream()
+                .map_err(|_| WebError::Host("Request body stream unavailable".to_owned()))?;
+            let mut body = Vec::new();
+            stream.read_to_end(&mut body)?;
+            drop(stream);
+            IncomingBody::finish(incoming);
+            body
+        } else {
+            Vec::new()
+        };
+
+        Self::from_parts(request.method(), path_with_query, &headers, &body)
+    }
+
+    /// Assemble a request from its already received parts.
+    ///
+    /// Fails only if the `Authorization` header is not valid utf-8. A missing or malformed query
+    /// or body is reported lazily, when a flow actually requires it.
+    pub fn from_parts(
+        method: Method, path_with_query: String, headers: &[(String, Vec<u8>)], body: &[u8],
+    ) -> Result<Self, WebError> {
+        let auth = match header(headers, "authorization") {
+            None => None,
+            Some(value) => Some(
+                std::str::from_utf8(value)
+                    .map_err(|_| WebError::Authorization)?
+                    .to_owned(),
+            ),
+        };
+
+        let query = path_with_query.split_once('?').map_or("", |(_, query)| query);
+        let query = serde_urlencoded::from_str(query).ok();
+
+        let body = if is_form(headers) {
+            serde_urlencoded::from_bytes(body).ok()
+        } else {
+            None
+        };
+
+        Ok(OAuthRequest {
+            method,
+            path_with_query,
+            auth,
+            query,
+            body,
+        })
+    }
+
+    /// Copy only the `Authorization` header of the request, for guarding resources.
+    pub fn resource(&self) -> Self {
+        OAuthRequest {
+            method: self.method.clone(),
+            path_with_query: self.path_with_query.clone(),
+            auth: self.auth.clone(),
+            query: None,
+            body: None,
+        }
+    }
+
+    /// The method of the request
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The path of the request, without the query
+    pub fn path(&self) -> &str {
+        match self.path_with_query.split_once('?') {
+            Some((path, _)) => path,
+            None => &self.path_with_query,
+        }
+    }
+
+    /// Fetch the authorization header from the request
+    pub fn authorization_header(&self) -> Option<&str> {
+        self.auth.as_deref()
+    }
+
+    /// Fetch the query for this request
+    pub fn query(&self) -> Option<&NormalizedParameter> {
+        self.query.as_ref()
+    }
+
+    /// Fetch the query mutably
+    pub fn query_mut(&mut self) -> Option<&mut NormalizedParameter> {
+        self.query.as_mut()
+    }
+
+    /// Fetch the body of the request
+    pub fn body(&self) -> Option<&NormalizedParameter> {
+        self.body.as_ref()
+    }
+}
+
+fn header<'a>(headers: &'a [(String, Vec<u8>)], name: &str) -> Option<&'a [u8]> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_slice())
+}
+
+fn is_form(headers: &[(String, Vec<u8>)]) -> bool {
+    header(headers, "content-type")
+        .is_some_and(|ct| ct.starts_with(b"application/x-www-form-urlencoded"))
+}
+
+impl WebRequest for OAuthRequest {
+    type Error = WebError;
+    type Response = OAuthResponse;
+
+    fn query(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
+        self.query
+            .as_ref()
+            .map(|q| Cow::Borrowed(q as &dyn QueryParameter))
+            .ok_or(WebError::Query)
+    }
+
+    fn urlbody(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
+        self.body
+            .as_ref()
+            .map(|b| Cow::Borrowed(b as &dyn QueryParameter))
+            .ok_or(WebError::Body)
+    }
+
+    fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
+        Ok(self.auth.as_deref().map(Cow::Borrowed))
+    }
+}
@@ -0,0 +1,138 @@
+use crate::WebError;
+use oxide_auth::frontends::dev::{WebResponse, Url};
+use wasi::http::types::{Fields, OutgoingBody, OutgoingResponse, ResponseOutparam};
+use std::io::Write;
+
+#[derive(Clone, Debug)]
+/// Type implementing `WebResponse`, written to a `wasi:http` response outparam
+pub struct OAuthResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+}
+
+impl OAuthResponse {
+    /// Set the `ContentType` header on a response
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.set_header("content-type", content_type);
+        self
+    }
+
+    /// Set the body for the response
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_owned());
+        self
+    }
+
+    /// The http status code of the response
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Fetch a header value that was set on the response
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body that was set on the response
+    pub fn content(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+
+    /// Hand the response to the host.
+    ///
+    /// Headers and status are sent first, the body is streamed afterwards.
+    pub fn send(self, outparam: ResponseOutparam) -> Result<(), WebError> {
+        let headers: Vec<(String, Vec<u8>)> = self
+            .headers
+            .into_iter()
+            .map(|(key, value)| (key, value.into_bytes()))
+            .collect();
+        let headers = Fields::from_list(&headers).map_err(|e| WebError::Host(format!("{:?}", e)))?;
+
+        let response = OutgoingResponse::new(headers);
+        response
+            .set_status_code(self.status)
+            .map_err(|_| WebError::Host(format!("Invalid status code {}", self.status)))?;
+        let body = response
+            .body()
+            .map_err(|_| WebError::Host("Response body unavailable".to_owned()))?;
+        ResponseOutparam::set(outparam, Ok(response));
+
+        let mut stream = body
+            .write()
+            .map_err(|_| WebError::Host("Response body stream unavailable".to_owned()))?;
+        if let Some(text) = &self.body {
+            Write::write_all(&mut stream, text.as_bytes())?;
+        }
+        Write::flush(&mut stream)?;
+        drop(stream);
+        OutgoingBody::finish(body, None).map_err(|e| WebError::Host(format!("{:?}", e)))
+    }
+
+    fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_owned(), value.to_owned()));
+    }
+}
+
+impl Default for OAuthResponse {
+    fn default() -> Self {
+        OAuthResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+}
+
+impl From<WebError> for OAuthResponse {
+    fn from(error: WebError) -> Self {
+        OAuthResponse {
+            status: error.status(),
+            headers: vec![("content-type".to_owned(), "text/plain".to_owned())],
+            body: Some(error.to_string()),
+        }
+    }
+}
+
+impl WebResponse for OAuthResponse {
+    type Error = WebError;
+
+    fn ok(&mut self) -> Result<(), Self::Error> {
+        self.status = 200;
+        Ok(())
+    }
+
+    fn redirect(&mut self, url: Url) -> Result<(), Self::Error> {
+        self.status = 302;
+        self.set_header("location", url.as_str());
+        Ok(())
+    }
+
+    fn client_error(&mut self) -> Result<(), Self::Error> {
+        self.status = 400;
+        Ok(())
+    }
+
+    fn unauthorized(&mut self, kind: &str) -> Result<(), Self::Error> {
+        self.status = 401;
+        self.set_header("www-authenticate", kind);
+        Ok(())
+    }
+
+    fn body_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        self.body = Some(text.to_owned());
+        self.set_header("content-type", "text/plain");
+        Ok(())
+    }
+
+    fn body_json(&mut self, json: &str) -> Result<(), Self::Error> {
+        self.body = Some(json.to_owned());
+        self.set_header("content-type", "application/json");
+        Ok(())
+    }
+}
@@ -3,15 +3,26 @@ use std::mem;
 use std::borrow::Cow;
 use std::collections::HashMap;
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json;
+use serde_json::{self, Map, Value};
 
+#[cfg(feature = "jwt")]
+use crate::code_grant::assertion::{asserted_client, client_assertion, ClientAssertions};
 use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+#[cfg(feature = "jwt")]
+use crate::code_grant::extensions::{authentication, OPENID_SCOPE};
+use crate::code_grant::extensions::{attach_claims, authorization_details, bound_key, claims};
+use crate::code_grant::extensions::{AuthorizationDetail, DpopProof};
 use crate::primitives::authorizer::Authorizer;
-use crate::primitives::issuer::{IssuedToken, Issuer};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::primitives::error::PrimitiveError as Cause;
+use crate::primitives::issuer::{IssuedToken, Issuer, TokenType};
 use crate::primitives::grant::{Extensions, Grant};
-use crate::primitives::registrar::{Registrar, RegistrarError};
+#[cfg(feature = "jwt")]
+use crate::primitives::jwt::IdTokenSigner;
+use crate::primitives::registrar::{ClientCertificate, Registrar, RegistrarError};
+use crate::primitives::scope::Scope;
 
 /// Token Response
 #[derive(Deserialize, Serialize)]
@@ -39,6 +50,47 @@ pub struct TokenResponse {
     /// Error code
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+
+    /// The authorization details granted to the access token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorization_details: Option<Vec<AuthorizationDetail>>,
+
+    /// The ID token of an OpenID Connect grant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+/// The members of a token response that describe the issued tokens or an error.
+///
+/// A `TokenResponseHook` can not add these, they are only set by the flows.
+const RESERVED_MEMBERS: &[&str] = &[
+    "access_token",
+    "token_type",
+    "expires_in",
+    "refresh_token",
+    "error",
+    "error_description",
+    "error_uri",
+];
+
+/// Adds members to the JSON body of successful token responses.
+///
+/// The members are derived from the grant a token was issued for, for example the `resource` of
+/// the token, an ID token of a custom signer or vendor specific fields. They never replace members
+/// the response already has. Members describing the issued tokens, `access_token`, `token_type`,
+/// `expires_in` and `refresh_token`, as well as error members are ignored.
+///
+/// Closures from a grant to a JSON object are hooks, such as
+/// `|grant: &Grant| json!({ "owner": grant.owner_id }).as_object().cloned().unwrap_or_default()`.
+pub trait TokenResponseHook {
+    /// The additional members of the response for a token issued for the grant.
+    fn members(&self, grant: &Grant) -> Map<String, Value>;
+}
+
+impl<F: Fn(&Grant) -> Map<String, Value>> TokenResponseHook for F {
+    fn members(&self, grant: &Grant) -> Map<String, Value> {
+        self(grant)
+    }
 }
 
 /// Trait based retrieval of parameters necessary for access token request handling.
@@ -67,6 +119,18 @@ pub trait Request {
     /// Retrieve an additional parameter used in an extension
     fn extension(&self, key: &str) -> Option<Cow<str>>;
 
+    /// The DPoP proof sent with the request, if any.
+    fn dpop(&self) -> Option<&DpopProof> {
+        None
+    }
+
+    /// The certificate of the client in a mutual TLS connection, if any.
+    ///
+    /// Clients without other credentials are authenticated with this certificate.
+    fn client_certificate(&self) -> Option<&ClientCertificate> {
+        None
+    }
+
     /// Credentials in body should only be enabled if use of HTTP Basic is not possible.
     ///
     /// Allows the request body to contain the `client_secret` as a form parameter. This is NOT
@@ -116,6 +180,36 @@ pub trait Endpoint {
     ///
     /// It is possible to use `&mut ()`.
     fn extension(&mut self) -> &mut dyn Extension;
+
+    /// The signer of ID tokens for grants with the `openid` scope.
+    ///
+    /// Returning `None` is the default implementation, no ID tokens are issued then.
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        None
+    }
+
+    /// The verifier of clients authenticating with a JWT assertion.
+    ///
+    /// Returning `None` is the default implementation, client assertions are not accepted then.
+    #[cfg(feature = "jwt")]
+    fn client_assertions(&self) -> Option<&ClientAssertions> {
+        None
+    }
+
+    /// The clock deciding whether the authorization code has expired.
+    ///
+    /// The system clock is the default implementation.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+
+    /// Adds members to the token response.
+    ///
+    /// Returning `None` is the default implementation, the response has only the standard members.
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        None
+    }
 }
 
 enum Credentials<'a> {
@@ -166,6 +260,7 @@ enum Credentials<'a> {
 /// 3. Query the backend for a new (bearer) token
 pub struct AccessToken {
     state: AccessTokenState,
+    now: DateTime<Utc>,
 }
 
 /// Inner state machine for access token
@@ -265,9 +360,16 @@ impl AccessToken {
     pub fn new(request: &dyn Request) -> Self {
         AccessToken {
             state: Self::validate(request).unwrap_or_else(AccessTokenState::Err),
+            now: Utc::now(),
         }
     }
 
+    /// Check the expiry of the authorization code against the time `now`.
+    pub fn at(mut self, now: DateTime<Utc>) -> Self {
+        self.now = now;
+        self
+    }
+
     /// Go to next state
     pub fn advance(&mut self, input: Input) -> Output<'_> {
         self.state = match (self.take(), input) {
@@ -286,7 +388,8 @@ impl AccessToken {
                     client, redirect_uri, ..
                 },
                 Input::Recovered(grant),
-            ) => Self::recovered(client, redirect_uri, grant).unwrap_or_else(AccessTokenState::Err),
+            ) => Self::recovered(client, redirect_uri, grant, self.now)
+                .unwrap_or_else(AccessTokenState::Err),
             (AccessTokenState::Extend { saved_params, .. }, Input::Extended { access_extensions }) => {
                 Self::issue(saved_params, access_extensions)
             }
@@ -334,7 +437,11 @@ impl AccessToken {
             credentials.authenticate(client_id.as_ref(), auth.as_ref());
         }
 
-        if let Some(client_id) = &client_id {
+        // The assertion is verified when authenticating, like a passphrase.
+        let asserted = asserted_client_id(request)?;
+        if let Some(client_id) = &asserted {
+            credentials.unauthenticated(client_id);
+        } else if let Some(client_id) = &client_id {
             match &client_secret {
                 Some(auth) if request.allow_credentials_in_body() => {
                     credentials.authenticate(client_id.as_ref(), auth.as_ref().as_bytes())
@@ -377,7 +484,7 @@ impl AccessToken {
     }
 
     fn recovered(
-        client_id: String, redirect_uri: url::Url, grant: Option<Box<Grant>>,
+        client_id: String, redirect_uri: url::Url, grant: Option<Box<Grant>>, now: DateTime<Utc>,
     ) -> Result<AccessTokenState> {
         let mut saved_params = match grant {
             None => return Err(Error::invalid()),
@@ -388,25 +495,32 @@ impl AccessToken {
             return Err(Error::invalid_with(AccessTokenErrorType::InvalidGrant));
         }
 
-        if saved_params.until < Utc::now() {
+        if saved_params.until < now {
             return Err(Error::invalid_with(AccessTokenErrorType::InvalidGrant));
         }
 
+        // Custom claims stay with the grant, the extensions only decide on the rest.
         let extensions = mem::take(&mut saved_params.extensions);
+        attach_claims(&mut saved_params.extensions, claims(&extensions));
         Ok(AccessTokenState::Extend {
             saved_params,
             extensions,
         })
     }
 
-    fn issue(grant: Box<Grant>, extensions: Extensions) -> AccessTokenState {
+    fn issue(grant: Box<Grant>, mut extensions: Extensions) -> AccessTokenState {
+        attach_claims(&mut extensions, claims(&grant.extensions));
         AccessTokenState::Issue {
             grant: Box::new(Grant { extensions, ..*grant }),
         }
     }
 
-    fn finish(grant: Box<Grant>, token: IssuedToken) -> BearerToken {
-        BearerToken(token, grant.scope.to_string())
+    fn finish(grant: Box<Grant>, mut token: IssuedToken) -> BearerToken {
+        if bound_key(&grant.extensions).is_some() {
+            token.token_type = TokenType::DPoP;
+        }
+        let details = authorization_details(&grant.extensions);
+        BearerToken(token, grant.scope.to_string(), details, None, Map::new())
     }
 }
 
@@ -428,32 +542,42 @@ pub fn access_token(handler: &mut dyn Endpoint, request: &dyn Request) -> Result
         },
     }
 
-    let mut access_token = AccessToken::new(request);
+    let mut access_token = AccessToken::new(request).at(handler.clock().now());
     let mut requested = Requested::None;
+    let mut id_token = None;
+    let mut members = Map::new();
 
     loop {
         let input = match requested {
             Requested::None => Input::None,
             Requested::Authenticate { client, passdata } => {
-                handler
-                    .registrar()
-                    .check(client, passdata)
-                    .map_err(|err| match err {
-                        RegistrarError::Unspecified => Error::unauthorized("basic"),
-                        RegistrarError::PrimitiveError => Error::Primitive(Box::new(PrimitiveError {
-                            grant: None,
-                            extensions: None,
-                        })),
-                    })?;
+                let registrar = handler.registrar();
+                match (passdata, request.client_certificate()) {
+                    (None, _) if has_client_assertion(request) => {
+                        authenticate_assertion(handler, request, client)
+                    }
+                    (None, Some(certificate)) => registrar.check_certificate(client, certificate),
+                    (passdata, _) => registrar.check(client, passdata),
+                }
+                .map_err(|err| match err {
+                    RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                        Error::unauthorized("basic")
+                    }
+                    RegistrarError::PrimitiveError => {
+                        Error::Primitive(Box::new(PrimitiveError::caused_by(Cause::Invariant)))
+                    }
+                })?;
                 Input::Authenticated
             }
             Requested::Recover(code) => {
-                let opt_grant = handler.authorizer().extract(code).map_err(|_| {
-                    Error::Primitive(Box::new(PrimitiveError {
-                        grant: None,
-                        extensions: None,
-                    }))
-                })?;
+                let opt_grant = match handler.authorizer().extract(code) {
+                    // A code that vanished in the meantime is as invalid as an unknown one.
+                    Err(Cause::NotFound) => None,
+                    Err(cause) => {
+                        return Err(Error::Primitive(Box::new(PrimitiveError::caused_by(cause))))
+                    }
+                    Ok(grant) => grant,
+                };
                 Input::Recovered(opt_grant.map(Box::new))
             }
             Requested::Extend { extensions } => {
@@ -464,13 +588,18 @@ pub fn access_token(handler: &mut dyn Endpoint, request: &dyn Request) -> Result
                 Input::Extended { access_extensions }
             }
             Requested::Issue { grant } => {
-                let token = handler.issuer().issue(grant.clone()).map_err(|_| {
+                id_token = issue_id_token(handler, grant).map_err(|()| {
                     Error::Primitive(Box::new(PrimitiveError {
-                        // FIXME: endpoint should get and handle these.
-                        grant: None,
+                        grant: Some(grant.clone()),
                         extensions: None,
+                        cause: Cause::Invariant,
                     }))
                 })?;
+                let token = handler.issuer().issue(grant.clone()).map_err(|cause| {
+                    // FIXME: endpoint should get and handle the grant and extensions.
+                    Error::Primitive(Box::new(PrimitiveError::caused_by(cause)))
+                })?;
+                members = response_members(handler.token_response_hook(), grant);
                 Input::Issued(token)
             }
         };
@@ -480,12 +609,90 @@ pub fn access_token(handler: &mut dyn Endpoint, request: &dyn Request) -> Result
             Output::Recover { code } => Requested::Recover(code),
             Output::Extend { extensions } => Requested::Extend { extensions },
             Output::Issue { grant } => Requested::Issue { grant },
-            Output::Ok(token) => return Ok(token),
+            Output::Ok(mut token) => {
+                token.3 = id_token;
+                token.4 = members;
+                return Ok(token);
+            }
             Output::Err(e) => return Err(*e),
         };
     }
 }
 
+/// Sign the ID token of a grant with the `openid` scope, if the endpoint has a signer.
+#[cfg(feature = "jwt")]
+fn issue_id_token(handler: &dyn Endpoint, grant: &Grant) -> std::result::Result<Option<String>, ()> {
+    let signer = match handler.id_token_signer() {
+        Some(signer) if grant.scope.iter().any(|scope| scope == OPENID_SCOPE) => signer,
+        _ => return Ok(None),
+    };
+
+    let mut claims = signer.claims(grant)?;
+    if let Some(authentication) = authentication(&grant.extensions) {
+        claims.nonce = authentication.nonce;
+        claims.auth_time = Some(authentication.auth_time);
+        claims.acr = authentication.acr;
+    }
+    signer.sign(&claims).map(Some)
+}
+
+/// The client of a request with a client assertion, named by the request or else the assertion.
+#[cfg(feature = "jwt")]
+fn asserted_client_id(request: &dyn Request) -> Result<Option<String>> {
+    let assertion = match client_assertion(|key| request.extension(key)) {
+        None => return Ok(None),
+        Some(assertion) => assertion,
+    };
+
+    match request.client_id() {
+        Some(client_id) => Ok(Some(client_id.into_owned())),
+        None => asserted_client(&assertion)
+            .map(Some)
+            .ok_or_else(|| Error::unauthorized("basic")),
+    }
+}
+
+#[cfg(not(feature = "jwt"))]
+fn asserted_client_id(_: &dyn Request) -> Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(feature = "jwt")]
+fn has_client_assertion(request: &dyn Request) -> bool {
+    client_assertion(|key| request.extension(key)).is_some()
+}
+
+#[cfg(not(feature = "jwt"))]
+fn has_client_assertion(_: &dyn Request) -> bool {
+    false
+}
+
+/// Verify the client assertion of the request, rejecting it if assertions are not accepted.
+#[cfg(feature = "jwt")]
+fn authenticate_assertion(
+    handler: &dyn Endpoint, request: &dyn Request, client: &str,
+) -> std::result::Result<(), RegistrarError> {
+    let assertion = client_assertion(|key| request.extension(key)).ok_or(RegistrarError::Unspecified)?;
+    match handler.client_assertions() {
+        Some(assertions) => {
+            assertions.authenticate(handler.registrar(), client, &assertion, handler.clock().now())
+        }
+        None => Err(RegistrarError::Unspecified),
+    }
+}
+
+#[cfg(not(feature = "jwt"))]
+fn authenticate_assertion(
+    _: &dyn Endpoint, _: &dyn Request, _: &str,
+) -> std::result::Result<(), RegistrarError> {
+    Err(RegistrarError::Unspecified)
+}
+
+#[cfg(not(feature = "jwt"))]
+fn issue_id_token(_: &dyn Endpoint, _: &Grant) -> std::result::Result<Option<String>, ()> {
+    Ok(None)
+}
+
 impl<'a> Credentials<'a> {
     pub fn authenticate(&mut self, client_id: &'a str, passphrase: &'a [u8]) {
         self.add(Credentials::Authenticated {
@@ -557,6 +764,9 @@ pub struct PrimitiveError {
 
     /// The extensions that were computed.
     pub extensions: Option<Extensions>,
+
+    /// The class of the failure, deciding on the response to the client.
+    pub cause: Cause,
 }
 
 /// Simple wrapper around AccessTokenError to imbue the type with addtional json functionality. In
@@ -568,8 +778,38 @@ pub struct ErrorDescription {
 
 type Result<T> = std::result::Result<T, Error>;
 
-/// Represents an access token, a refresh token and the associated scope for serialization.
-pub struct BearerToken(pub(crate) IssuedToken, pub(crate) String);
+/// Represents an access token, a refresh token and the associated scope, authorization details,
+/// ID token and additional members for serialization.
+pub struct BearerToken(
+    pub(crate) IssuedToken,
+    pub(crate) String,
+    pub(crate) Option<Vec<AuthorizationDetail>>,
+    pub(crate) Option<String>,
+    pub(crate) Map<String, Value>,
+);
+
+/// The members a hook adds to the response of a token issued for the grant.
+pub(crate) fn response_members(
+    hook: Option<&dyn TokenResponseHook>, grant: &Grant,
+) -> Map<String, Value> {
+    match hook {
+        None => Map::new(),
+        Some(hook) => hook.members(grant),
+    }
+}
+
+/// Serialize a token response with additional members that do not replace its own.
+pub(crate) fn response_json(response: &TokenResponse, members: &Map<String, Value>) -> String {
+    let mut json = serde_json::to_value(response).unwrap();
+    if let Value::Object(json) = &mut json {
+        for (name, value) in members {
+            if !RESERVED_MEMBERS.contains(&name.as_str()) && !json.contains_key(name) {
+                json.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    serde_json::to_string(&json).unwrap()
+}
 
 impl Error {
     /// Create invalid error type
@@ -618,9 +858,14 @@ impl Error {
 
 impl PrimitiveError {
     pub(crate) fn empty() -> Self {
+        PrimitiveError::caused_by(Cause::Invariant)
+    }
+
+    pub(crate) fn caused_by(cause: Cause) -> Self {
         PrimitiveError {
             grant: None,
             extensions: None,
+            cause,
         }
     }
 }
@@ -644,6 +889,11 @@ impl ErrorDescription {
 }
 
 impl BearerToken {
+    /// Create the response for an issued token with the scope of its grant.
+    pub fn new(token: IssuedToken, scope: &Scope) -> Self {
+        BearerToken(token, scope.to_string(), None, None, Map::new())
+    }
+
     /// Convert the token into a json string, viable for being sent over a network with
     /// `application/json` encoding.
     pub fn to_json(&self) -> String {
@@ -651,13 +901,15 @@ impl BearerToken {
         let token_response = TokenResponse {
             access_token: Some(self.0.token.clone()),
             refresh_token: self.0.refresh.clone(),
-            token_type: Some("bearer".to_owned()),
+            token_type: Some(self.0.token_type.name().to_owned()),
             expires_in: Some(remaining.num_seconds()),
             scope: Some(self.1.clone()),
             error: None,
+            authorization_details: self.2.clone(),
+            id_token: self.3.clone(),
         };
 
-        serde_json::to_string(&token_response).unwrap()
+        response_json(&token_response, &self.4)
     }
 }
 
@@ -676,6 +928,9 @@ mod tests {
                 token_type: TokenType::Bearer,
             },
             "scope".into(),
+            None,
+            None,
+            Map::new(),
         );
 
         let json = token.to_json();
@@ -693,6 +948,9 @@ mod tests {
         let token = BearerToken(
             IssuedToken::without_refresh("access".into(), Utc::now()),
             "scope".into(),
+            None,
+            None,
+            Map::new(),
         );
 
         let json = token.to_json();
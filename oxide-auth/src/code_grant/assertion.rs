@@ -0,0 +1,353 @@
+//! Provides JWT assertions as grants and as client credentials.
+//!
+//! [RFC 7523] profiles the assertion framework of [RFC 7521] for JSON Web Tokens. A signed JWT
+//! can be used in two ways:
+//!
+//! * As an authorization grant, `grant_type=urn:ietf:params:oauth:grant-type:jwt-bearer`, where
+//!   the `sub` of an assertion from a trusted issuer is the resource owner. The [`JwtBearer`]
+//!   handler implements this grant and is registered like other custom grants.
+//! * As client authentication, `client_assertion_type` of
+//!   `urn:ietf:params:oauth:client-assertion-type:jwt-bearer`. The client signs the assertion with
+//!   the private key of its registered `jwks`, `private_key_jwt`, or with a shared secret,
+//!   `client_secret_jwt`. [`ClientAssertions`] verifies them for the token endpoint flows.
+//!
+//! Assertions must name the authorization server in their `aud` claim and be unexpired.
+//!
+//! Only available with the `jwt` feature.
+//!
+//! [RFC 7521]: https://tools.ietf.org/html/rfc7521
+//! [RFC 7523]: https://tools.ietf.org/html/rfc7523
+//! [`JwtBearer`]: struct.JwtBearer.html
+//! [`ClientAssertions`]: struct.ClientAssertions.html
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::code_grant::accesstoken::BearerToken;
+use crate::code_grant::custom_grant::{self, Endpoint, Error, GrantHandler, Request, Result};
+use crate::code_grant::error::AccessTokenErrorType;
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::jwt::{self, JwkSet, HMAC_ALGORITHM};
+use crate::primitives::registrar::{ClientUrl, Registrar, RegistrarError};
+
+/// The `grant_type` of token requests with a JWT assertion as the grant.
+pub const JWT_BEARER_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+
+/// The `client_assertion_type` of clients authenticating with a JWT assertion.
+pub const JWT_BEARER_CLIENT_ASSERTION_TYPE: &str =
+    "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// The claims of a JWT assertion.
+///
+/// See [RFC 7523, Section 3](https://tools.ietf.org/html/rfc7523#section-3).
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AssertionClaims {
+    /// The party that created and signed the assertion.
+    pub iss: String,
+
+    /// The resource owner of a grant or the client of a client assertion.
+    pub sub: String,
+
+    /// The authorization server the assertion is intended for.
+    pub aud: Audience,
+
+    /// The expiry as seconds since the unix epoch.
+    pub exp: i64,
+
+    /// The time before which the assertion must not be accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<i64>,
+
+    /// The time of issuance as seconds since the unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+
+    /// A unique identifier of the assertion.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+}
+
+/// The `aud` claim, a single audience or a list of them.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Audience {
+    /// The assertion is intended for exactly one party.
+    One(String),
+
+    /// The assertion is intended for any of the parties.
+    Many(Vec<String>),
+}
+
+/// Verifies the JWT assertions with which clients authenticate.
+///
+/// Assertions signed with `HS256` are checked with the [`jwt_secret`] of the client, all others with
+/// the keys of its [`jwks`]. The `iss` and `sub` of the assertion must both be the id of the client.
+///
+/// [`jwt_secret`]: ../../primitives/registrar/trait.Registrar.html#method.jwt_secret
+/// [`jwks`]: ../../primitives/registrar/trait.Registrar.html#method.jwks
+#[derive(Clone, Debug)]
+pub struct ClientAssertions {
+    audience: Vec<String>,
+    leeway: Duration,
+}
+
+/// Handles the JWT bearer grant of [RFC 7523, Section 2.1].
+///
+/// The assertion is verified with the keys of its issuer, either one trusted with [`trust`] or a
+/// registered client asserting on its own behalf with its `jwks`. Its `sub` becomes the owner of
+/// the grant. Clients authenticate with their credentials, a client assertion or, for public
+/// clients, their id. A client issuing the grant assertion itself need not authenticate otherwise.
+///
+/// [RFC 7523, Section 2.1]: https://tools.ietf.org/html/rfc7523#section-2.1
+/// [`trust`]: #method.trust
+pub struct JwtBearer {
+    assertions: ClientAssertions,
+    issuers: HashMap<String, JwkSet>,
+}
+
+/// Get the client assertion of a request, if it is of the JWT bearer type.
+pub(crate) fn client_assertion<'r, F>(extension: F) -> Option<Cow<'r, str>>
+where
+    F: Fn(&str) -> Option<Cow<'r, str>>,
+{
+    match extension("client_assertion_type") {
+        Some(ref kind) if kind == JWT_BEARER_CLIENT_ASSERTION_TYPE => extension("client_assertion"),
+        _ => None,
+    }
+}
+
+/// The client asserted by a client assertion, without verifying it.
+///
+/// Used when the request does not name its client with the `client_id` parameter.
+pub(crate) fn asserted_client(assertion: &str) -> Option<String> {
+    jwt::unverified_claims::<AssertionClaims>(assertion)
+        .ok()
+        .map(|claims| claims.sub)
+}
+
+impl Audience {
+    /// Check that one of the audiences is among the accepted ones.
+    pub fn intended_for(&self, accepted: &[String]) -> bool {
+        match self {
+            Audience::One(aud) => accepted.contains(aud),
+            Audience::Many(auds) => auds.iter().any(|aud| accepted.contains(aud)),
+        }
+    }
+}
+
+impl AssertionClaims {
+    /// Check that the assertion is intended for the audience and valid at the time `now`.
+    ///
+    /// Expiry and not-before are compared with the leeway to allow for skewed clocks.
+    pub fn validate(&self, audience: &[String], leeway: Duration, now: DateTime<Utc>) -> bool {
+        let intended = self.aud.intended_for(audience);
+
+        let now = now.timestamp();
+        let leeway = leeway.num_seconds();
+        let started = match self.nbf {
+            Some(nbf) => nbf - leeway <= now,
+            None => true,
+        };
+        intended && started && self.exp + leeway > now
+    }
+}
+
+impl ClientAssertions {
+    /// Accept assertions intended for the audience.
+    ///
+    /// This should be the URL of the token endpoint or the issuer identifier of the server.
+    pub fn new(audience: &str) -> Self {
+        ClientAssertions {
+            audience: vec![audience.to_owned()],
+            leeway: Duration::seconds(60),
+        }
+    }
+
+    /// Also accept assertions intended for another audience.
+    pub fn audience(&mut self, audience: &str) {
+        self.audience.push(audience.to_owned());
+    }
+
+    /// Set the allowed clock skew for expiry checks, one minute by default.
+    pub fn leeway(&mut self, leeway: Duration) {
+        self.leeway = leeway;
+    }
+
+    /// Authenticate the client with its assertion, checking its expiry at the time `now`.
+    pub fn authenticate(
+        &self, registrar: &dyn Registrar, client_id: &str, assertion: &str, now: DateTime<Utc>,
+    ) -> std::result::Result<(), RegistrarError> {
+        let claims = self.verify(registrar, client_id, assertion, now)?;
+        if claims.iss != client_id || claims.sub != client_id {
+            return Err(RegistrarError::Unspecified);
+        }
+
+        Ok(())
+    }
+
+    /// Verify an assertion signed by the client and check its audience and expiry.
+    fn verify(
+        &self, registrar: &dyn Registrar, client_id: &str, assertion: &str, now: DateTime<Utc>,
+    ) -> std::result::Result<AssertionClaims, RegistrarError> {
+        let header = jwt::header(assertion).map_err(|()| RegistrarError::Unspecified)?;
+        let claims: AssertionClaims = if header.alg == HMAC_ALGORITHM {
+            let secret = registrar
+                .jwt_secret(client_id)?
+                .ok_or(RegistrarError::Unspecified)?;
+            jwt::verify_hmac(&secret, assertion)
+        } else {
+            let jwks = registrar.jwks(client_id)?.ok_or(RegistrarError::Unspecified)?;
+            JwkSet::from_json(&jwks).and_then(|jwks| jwks.verify(assertion))
+        }
+        .map_err(|()| RegistrarError::Unspecified)?;
+
+        if !claims.validate(&self.audience, self.leeway, now) {
+            return Err(RegistrarError::Unspecified);
+        }
+
+        Ok(claims)
+    }
+}
+
+impl JwtBearer {
+    /// Handle grant assertions intended for the audience.
+    ///
+    /// Client assertions of the requests are verified for the same audience.
+    pub fn new(assertions: ClientAssertions) -> Self {
+        JwtBearer {
+            assertions,
+            issuers: HashMap::new(),
+        }
+    }
+
+    /// Accept assertions of the issuer, verified with its keys.
+    pub fn trust(&mut self, iss: &str, jwks: JwkSet) {
+        self.issuers.insert(iss.to_owned(), jwks);
+    }
+
+    /// Verify the grant assertion, returning its claims.
+    fn verify(
+        &self, registrar: &dyn Registrar, assertion: &str, now: DateTime<Utc>,
+    ) -> Result<AssertionClaims> {
+        let invalid = || Error::invalid(AccessTokenErrorType::InvalidGrant);
+        let iss = jwt::unverified_claims::<AssertionClaims>(assertion)
+            .map_err(|()| invalid())?
+            .iss;
+
+        let claims: AssertionClaims = match self.issuers.get(&iss) {
+            Some(jwks) => jwks.verify(assertion).map_err(|()| invalid())?,
+            None => {
+                self.assertions
+                    .verify(registrar, &iss, assertion, now)
+                    .map_err(|err| match err {
+                        RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+                        RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => invalid(),
+                    })?
+            }
+        };
+
+        if !claims.validate(&self.assertions.audience, self.assertions.leeway, now) {
+            return Err(invalid());
+        }
+
+        Ok(claims)
+    }
+
+    /// Authenticate the client of the request, if it offered any credentials.
+    fn authenticate(
+        &self, registrar: &dyn Registrar, request: &dyn Request, now: DateTime<Utc>,
+    ) -> Result<Option<String>> {
+        if let Some(assertion) = client_assertion(|key| request.extension(key)) {
+            if request.authorization().is_some() {
+                return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+            }
+
+            let client_id = request
+                .client_id()
+                .map(Cow::into_owned)
+                .or_else(|| asserted_client(&assertion))
+                .ok_or_else(|| Error::unauthorized("basic"))?;
+            self.assertions
+                .authenticate(registrar, &client_id, &assertion, now)
+                .map_err(|err| match err {
+                    RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+                    RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                        Error::unauthorized("basic")
+                    }
+                })?;
+            return Ok(Some(client_id));
+        }
+
+        if request.authorization().is_none() && request.client_id().is_none() {
+            return Ok(None);
+        }
+
+        custom_grant::authenticate(registrar, request).map(Some)
+    }
+}
+
+impl GrantHandler for JwtBearer {
+    fn grant(&mut self, request: &dyn Request, endpoint: &mut dyn Endpoint) -> Result<BearerToken> {
+        let assertion = request
+            .extension("assertion")
+            .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidRequest))?;
+        let scope = match request.scope().map(|scope| scope.as_ref().parse()) {
+            None => None,
+            Some(Err(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidScope)),
+            Some(Ok(scope)) => Some(scope),
+        };
+
+        let now = endpoint.clock().now();
+        let registrar = endpoint.registrar();
+        let authenticated = self.authenticate(registrar, request, now)?;
+        let claims = self.verify(registrar, &assertion, now)?;
+
+        // A client asserting on its own behalf is its own issuer.
+        let self_issued = !self.issuers.contains_key(&claims.iss);
+        let client_id = match authenticated {
+            Some(client_id) if self_issued && client_id != claims.iss => {
+                return Err(Error::invalid(AccessTokenErrorType::InvalidGrant))
+            }
+            Some(client_id) => client_id,
+            None if self_issued => claims.iss.clone(),
+            None => return Err(Error::unauthorized("basic")),
+        };
+
+        let bound_client = registrar
+            .bound_redirect(ClientUrl {
+                client_id: Cow::Owned(client_id),
+                redirect_uri: None,
+            })
+            .map_err(|err| match err {
+                RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+                RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                    Error::unauthorized("basic")
+                }
+            })?;
+        let pre_grant = registrar
+            .negotiate(bound_client, scope)
+            .map_err(|err| match err {
+                RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+                RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                    Error::invalid(AccessTokenErrorType::InvalidScope)
+                }
+            })?;
+
+        let token = endpoint
+            .issuer()
+            .issue(Grant {
+                owner_id: claims.sub,
+                client_id: pre_grant.client_id,
+                scope: pre_grant.scope.clone(),
+                redirect_uri: pre_grant.redirect_uri.into_url(),
+                until: now + Duration::minutes(10),
+                extensions: Extensions::new(),
+            })
+            .map_err(Error::Primitive)?;
+
+        Ok(BearerToken::new(token, &pre_grant.scope))
+    }
+}
@@ -2,12 +2,18 @@
 use std::borrow::Cow;
 use std::result::Result as StdResult;
 
+use serde_json::{Map, Value};
 use url::Url;
-use chrono::{Duration, Utc};
+use chrono::Duration;
 
 use crate::code_grant::error::{AuthorizationError, AuthorizationErrorType};
+use crate::code_grant::extensions::{attach_claims, enrich, AuthenticationRequest, ClaimsEnricher};
 use crate::primitives::authorizer::Authorizer;
-use crate::primitives::registrar::{ClientUrl, ExactUrl, Registrar, RegistrarError, PreGrant};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::registrar::{
+    ClientUrl, ExactUrl, RedirectMismatch, Registrar, RegistrarError, PreGrant,
+};
 use crate::primitives::grant::{Extensions, Grant};
 use crate::{endpoint::Scope, endpoint::Solicitation, primitives::registrar::BoundClient};
 
@@ -68,6 +74,20 @@ pub trait Endpoint {
     ///
     /// It is possible to use `&mut ()`.
     fn extension(&mut self) -> &mut dyn Extension;
+
+    /// The clock from which the lifetime of authorization codes is computed.
+    ///
+    /// The system clock is the default implementation.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+
+    /// Attaches custom claims to the grant of the owner.
+    ///
+    /// Returning `None` is the default implementation.
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        None
+    }
 }
 
 /// The result will indicate wether the authorization succeed or not.
@@ -189,7 +209,7 @@ impl Authorization {
                 self.negotiated(state, pre_grant)
             }
             (AuthorizationState::Err(err), _) => AuthorizationState::Err(err),
-            (_, _) => AuthorizationState::Err(Error::PrimitiveError),
+            (_, _) => AuthorizationState::Err(Error::PrimitiveError(PrimitiveError::Invariant)),
         };
 
         self.output()
@@ -275,7 +295,10 @@ impl Authorization {
     }
 
     fn take(&mut self) -> AuthorizationState {
-        std::mem::replace(&mut self.state, AuthorizationState::Err(Error::PrimitiveError))
+        std::mem::replace(
+            &mut self.state,
+            AuthorizationState::Err(Error::PrimitiveError(PrimitiveError::Invariant)),
+        )
     }
 
     fn validate(request: &dyn Request) -> Result<AuthorizationState> {
@@ -341,7 +364,12 @@ pub fn authorization_code(handler: &mut dyn Endpoint, request: &dyn Request) ->
                 };
                 let bound_client = match handler.registrar().bound_redirect(client_url) {
                     Err(RegistrarError::Unspecified) => return Err(Error::Ignore),
-                    Err(RegistrarError::PrimitiveError) => return Err(Error::PrimitiveError),
+                    Err(RegistrarError::RedirectMismatch(mismatch)) => {
+                        return Err(Error::RedirectMismatch(mismatch))
+                    }
+                    Err(RegistrarError::PrimitiveError) => {
+                        return Err(Error::PrimitiveError(PrimitiveError::Invariant))
+                    }
                     Ok(pre_grant) => pre_grant,
                 };
                 the_redirect_uri = Some(bound_client.redirect_uri.clone().into_owned());
@@ -377,8 +405,10 @@ pub fn authorization_code(handler: &mut dyn Endpoint, request: &dyn Request) ->
                     .registrar()
                     .negotiate(bound_client, scope)
                     .map_err(|err| match err {
-                        RegistrarError::PrimitiveError => Error::PrimitiveError,
-                        RegistrarError::Unspecified => {
+                        RegistrarError::PrimitiveError => {
+                            Error::PrimitiveError(PrimitiveError::Invariant)
+                        }
+                        RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
                             let prepared_error = ErrorUrl::with_request(
                                 request,
                                 redirect_uri,
@@ -413,11 +443,26 @@ pub fn authorization_code(handler: &mut dyn Endpoint, request: &dyn Request) ->
                 state,
                 extensions,
             } => {
+                let authentication = AuthenticationRequest::parse(
+                    request.extension("prompt").as_deref(),
+                    request.extension("max_age").as_deref(),
+                    request.extension("login_hint").as_deref(),
+                    request.extension("id_token_hint").as_deref(),
+                )
+                .map_err(|()| {
+                    Error::Redirect(ErrorUrl::with_request(
+                        request,
+                        pre_grant.redirect_uri.to_url(),
+                        AuthorizationErrorType::InvalidRequest,
+                    ))
+                })?;
+
                 return Ok(Pending {
                     pre_grant,
                     state,
                     extensions,
-                })
+                    authentication,
+                });
             }
             Output::Err(e) => return Err(e),
         };
@@ -433,6 +478,7 @@ pub struct Pending {
     pre_grant: PreGrant,
     state: Option<String>,
     extensions: Extensions,
+    authentication: AuthenticationRequest,
 }
 
 impl Pending {
@@ -441,14 +487,30 @@ impl Pending {
         Solicitation {
             grant: Cow::Borrowed(&self.pre_grant),
             state: self.state.as_ref().map(|s| Cow::Borrowed(&**s)),
+            extensions: Some(Cow::Borrowed(&self.extensions)),
+            authentication: Some(Cow::Borrowed(&self.authentication)),
         }
     }
 
+    /// The parameters of the request about the authentication of the owner.
+    pub fn authentication_request(&self) -> &AuthenticationRequest {
+        &self.authentication
+    }
+
     /// Denies the request, which redirects to the client for which the request originated.
     pub fn deny(self) -> Result<Url> {
+        self.deny_with(AuthorizationErrorType::AccessDenied)
+    }
+
+    /// Fails the request with an error, which redirects to the client for which the request
+    /// originated.
+    ///
+    /// This answers requests that can not be decided without interaction with the owner, such as
+    /// those with `prompt=none` when the owner is not logged in.
+    pub fn deny_with(self, kind: AuthorizationErrorType) -> Result<Url> {
         let url = self.pre_grant.redirect_uri;
         let mut error = AuthorizationError::default();
-        error.set_type(AuthorizationErrorType::AccessDenied);
+        error.set_type(kind);
         let error = ErrorUrl::new_generic(url.into_url(), self.state, error);
         Err(Error::Redirect(error))
     }
@@ -458,19 +520,32 @@ impl Pending {
     /// Use negotiated parameters to authorize a client for an owner. The endpoint SHOULD be the
     /// same endpoint as was used to create the pending request.
     pub fn authorize(self, handler: &mut dyn Endpoint, owner_id: Cow<str>) -> Result<Url> {
+        self.authorize_with_claims(handler, owner_id, Map::new())
+    }
+
+    /// Inform the backend about consent from a resource owner, with custom claims for the grant.
+    ///
+    /// The claims of the `ClaimsEnricher` of the endpoint are added, without replacing any.
+    pub fn authorize_with_claims(
+        self, handler: &mut dyn Endpoint, owner_id: Cow<str>, claims: Map<String, Value>,
+    ) -> Result<Url> {
         let mut url = self.pre_grant.redirect_uri.to_url();
 
+        let mut grant = Grant {
+            owner_id: owner_id.into_owned(),
+            client_id: self.pre_grant.client_id,
+            redirect_uri: self.pre_grant.redirect_uri.into_url(),
+            scope: self.pre_grant.scope,
+            until: handler.clock().now() + Duration::minutes(10),
+            extensions: self.extensions,
+        };
+        attach_claims(&mut grant.extensions, claims);
+        enrich(handler.claims_enricher(), &mut grant);
+
         let grant = handler
             .authorizer()
-            .authorize(Grant {
-                owner_id: owner_id.into_owned(),
-                client_id: self.pre_grant.client_id,
-                redirect_uri: self.pre_grant.redirect_uri.into_url(),
-                scope: self.pre_grant.scope,
-                until: Utc::now() + Duration::minutes(10),
-                extensions: self.extensions,
-            })
-            .map_err(|()| Error::PrimitiveError)?;
+            .authorize(grant)
+            .map_err(Error::PrimitiveError)?;
 
         url.query_pairs_mut()
             .append_pair("code", grant.as_str())
@@ -498,10 +573,17 @@ pub enum Error {
     /// Redirect to the given url
     Redirect(ErrorUrl),
 
+    /// The client is known but the requested redirect url was not accepted.
+    ///
+    /// The user agent must not be redirected, the reason should be shown to the resource owner
+    /// instead.
+    RedirectMismatch(RedirectMismatch),
+
     /// Something happened in one of the primitives.
     ///
-    /// The endpoint should decide how to handle this and if this is temporary.
-    PrimitiveError,
+    /// The endpoint should decide how to handle this, the class of the failure tells if this is
+    /// temporary.
+    PrimitiveError(PrimitiveError),
 }
 
 /// Encapsulates a redirect to a valid redirect_uri with an error response. The implementation
@@ -511,6 +593,7 @@ pub enum Error {
 #[derive(Clone)]
 pub struct ErrorUrl {
     base_uri: Url,
+    state: Option<String>,
     error: AuthorizationError,
 }
 
@@ -518,13 +601,15 @@ type Result<T> = StdResult<T, Error>;
 
 impl ErrorUrl {
     /// Construct a new error, already fixing the state parameter if it exists.
-    fn new_generic<S>(mut url: Url, state: Option<S>, error: AuthorizationError) -> ErrorUrl
+    fn new_generic<S>(url: Url, state: Option<S>, error: AuthorizationError) -> ErrorUrl
     where
         S: AsRef<str>,
     {
-        url.query_pairs_mut()
-            .extend_pairs(state.as_ref().map(|st| ("state", st.as_ref())));
-        ErrorUrl { base_uri: url, error }
+        ErrorUrl {
+            base_uri: url,
+            state: state.map(|st| st.as_ref().to_owned()),
+            error,
+        }
     }
 
     /// Construct a new error, already fixing the state parameter if it exists.
@@ -549,6 +634,11 @@ impl ErrorUrl {
     pub fn description(&mut self) -> &mut AuthorizationError {
         &mut self.error
     }
+
+    /// The redirect uri of the client, before the error is added to it.
+    pub fn redirect_uri(&self) -> &Url {
+        &self.base_uri
+    }
 }
 
 impl Error {
@@ -560,7 +650,8 @@ impl Error {
         match self {
             Error::Ignore => None,
             Error::Redirect(inner) => Some(inner.description()),
-            Error::PrimitiveError => None,
+            Error::RedirectMismatch(_) => None,
+            Error::PrimitiveError(_) => None,
         }
     }
 }
@@ -569,7 +660,9 @@ impl Into<Url> for ErrorUrl {
     /// Finalize the error url by saving its parameters in the query part of the redirect_uri
     fn into(self) -> Url {
         let mut url = self.base_uri;
-        url.query_pairs_mut().extend_pairs(self.error.into_iter());
+        url.query_pairs_mut()
+            .extend_pairs(self.state.map(|st| ("state", st)))
+            .extend_pairs(self.error.into_iter());
         url
     }
 }
@@ -0,0 +1,346 @@
+//! Provides the handling for Client-Initiated Backchannel Authentication.
+//!
+//! The flow of [CIBA] consists of two requests of the client, neither of which passes through the
+//! browser of the owner. The client starts an authentication with a backchannel authentication
+//! request naming the owner in a `login_hint` and receives an `auth_req_id`. The server pushes the
+//! request to the owner, who approves or denies it on their own device. The client polls the
+//! token endpoint with its `auth_req_id` until it receives a token or an error other than
+//! `authorization_pending` and `slow_down`. In the ping mode the server first notifies the client
+//! that the owner decided.
+//!
+//! [CIBA]: https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html
+use std::borrow::Cow;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::code_grant::accesstoken::{response_members, BearerToken, ErrorDescription, TokenResponseHook};
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::code_grant::extensions::{authorization_details, enrich, ClaimsEnricher, OPENID_SCOPE};
+use crate::primitives::ciba::{
+    BackchannelAuthorization, BackchannelRequest, BackchannelStore, NotifyOwner, TokenDelivery,
+};
+use crate::primitives::device::DevicePoll;
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::issuer::Issuer;
+use crate::primitives::registrar::{ClientUrl, Registrar, RegistrarError};
+use crate::primitives::scope::Scope;
+
+/// The `grant_type` of token requests polling with an `auth_req_id`.
+pub const CIBA_GRANT_TYPE: &str = "urn:openid:params:grant-type:ciba";
+
+/// Required content of a backchannel authentication or token request.
+pub trait Request {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+
+    /// The client_id, which must match the authenticated client when present.
+    fn client_id(&self) -> Option<Cow<str>>;
+
+    /// The requested scope, which must contain `openid`, only in authentication requests.
+    fn scope(&self) -> Option<Cow<str>>;
+
+    /// The hint identifying the owner, only in authentication requests.
+    fn login_hint(&self) -> Option<Cow<str>>;
+
+    /// A message to show on the devices of the owner, only in authentication requests.
+    fn binding_message(&self) -> Option<Cow<str>>;
+
+    /// The bearer token of notifications to clients in the ping mode.
+    fn client_notification_token(&self) -> Option<Cow<str>>;
+
+    /// Valid token requests have this set to `CIBA_GRANT_TYPE`.
+    fn grant_type(&self) -> Option<Cow<str>>;
+
+    /// The id of the request the client is polling for, only in token requests.
+    fn auth_req_id(&self) -> Option<Cow<str>>;
+
+    /// Retrieve an additional parameter used in an extension
+    fn extension(&self, key: &str) -> Option<Cow<str>>;
+}
+
+/// Required functionality to respond to backchannel requests.
+pub trait Endpoint {
+    /// Get the client corresponding to some id.
+    fn registrar(&self) -> &dyn Registrar;
+
+    /// Return the issuer instance to create the access token.
+    fn issuer(&mut self) -> &mut dyn Issuer;
+
+    /// The store of pending backchannel requests.
+    fn backchannel(&mut self) -> &mut dyn BackchannelStore;
+
+    /// Identifies owners and pushes requests to them.
+    fn notifier(&self) -> &dyn NotifyOwner;
+
+    /// The client notification endpoint of clients registered for the ping mode.
+    ///
+    /// All other clients poll for their tokens.
+    fn notification_endpoint(&self, client_id: &str) -> Option<Url>;
+
+    /// Adds members to the token response.
+    ///
+    /// Returning `None` is the default implementation, the response has only the standard members.
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        None
+    }
+
+    /// Attaches custom claims to the grant of the token.
+    ///
+    /// Returning `None` is the default implementation.
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        None
+    }
+}
+
+/// The response to a successful backchannel authentication request.
+///
+/// See [CIBA, Section 7.3](https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#rfc.section.7.3).
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BackchannelAuthenticationResponse {
+    /// The id with which the client polls the token endpoint.
+    pub auth_req_id: String,
+
+    /// The lifetime in seconds of the request.
+    pub expires_in: i64,
+
+    /// The minimum number of seconds the client should wait between polling requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<i64>,
+}
+
+/// A started backchannel authentication, to be sent to the client.
+pub struct Started(BackchannelAuthorization, TokenDelivery);
+
+/// Defines actions for the response to a backchannel request.
+#[derive(Clone)]
+pub enum Error {
+    /// The request was invalid or the authentication is not decided.
+    Invalid(ErrorDescription),
+
+    /// The client did not properly authorize itself.
+    Unauthorized(ErrorDescription, String),
+
+    /// An underlying primitive operation did not complete successfully.
+    Primitive(PrimitiveError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Start the authentication of an owner for the requesting client.
+///
+/// The owner is identified by the `login_hint` and notified of the stored request.
+pub fn backchannel_authentication(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<Started> {
+    if !request.valid() {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    let scope: Scope = match request.scope().map(|scope| scope.as_ref().parse()) {
+        Some(Ok(scope)) => scope,
+        _ => return Err(Error::invalid(AccessTokenErrorType::InvalidScope)),
+    };
+    if !scope.iter().any(|scope| scope == OPENID_SCOPE) {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidScope));
+    }
+
+    // Only one hint may be given, and only the plain login hint is understood.
+    if request.extension("login_hint_token").is_some() || request.extension("id_token_hint").is_some() {
+        return Err(Error::explained(
+            AccessTokenErrorType::InvalidRequest,
+            "Only the login_hint is supported",
+        ));
+    }
+    let login_hint = request
+        .login_hint()
+        .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidRequest))?;
+
+    let client_id = authenticate(handler, request)?;
+    let delivery = match handler.notification_endpoint(&client_id) {
+        None => TokenDelivery::Poll,
+        Some(endpoint) => {
+            let token = request
+                .client_notification_token()
+                .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidRequest))?;
+            TokenDelivery::Ping {
+                endpoint,
+                token: token.into_owned(),
+            }
+        }
+    };
+
+    let bound_client = handler
+        .registrar()
+        .bound_redirect(ClientUrl {
+            client_id: Cow::Owned(client_id),
+            redirect_uri: None,
+        })
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    let pre_grant =
+        handler
+            .registrar()
+            .negotiate(bound_client, Some(scope))
+            .map_err(|err| match err {
+                RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+                RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                    Error::invalid(AccessTokenErrorType::InvalidScope)
+                }
+            })?;
+
+    let owner_id = handler
+        .notifier()
+        .identify(&login_hint)
+        .map_err(|()| Error::Primitive(PrimitiveError::Invariant))?
+        .ok_or_else(|| Error::invalid(AccessTokenErrorType::UnknownUserId))?;
+
+    let backchannel = BackchannelRequest {
+        grant: pre_grant,
+        owner_id,
+        binding_message: request.binding_message().map(Cow::into_owned),
+        delivery: delivery.clone(),
+    };
+    let started = handler
+        .backchannel()
+        .start(backchannel.clone())
+        .map_err(|()| Error::Primitive(PrimitiveError::Invariant))?;
+    handler
+        .notifier()
+        .notify(&started.auth_req_id, &backchannel)
+        .map_err(|()| Error::Primitive(PrimitiveError::Invariant))?;
+
+    Ok(Started(started, delivery))
+}
+
+/// Poll for the token of a backchannel authentication.
+pub fn backchannel_token(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<BearerToken> {
+    if !request.valid() {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    match request.grant_type() {
+        Some(ref cow) if cow == CIBA_GRANT_TYPE => (),
+        None => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        Some(_) => return Err(Error::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+    }
+
+    let auth_req_id = request
+        .auth_req_id()
+        .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidRequest))?;
+    let client_id = authenticate(handler, request)?;
+
+    let polled = handler
+        .backchannel()
+        .poll(&client_id, &auth_req_id)
+        .map_err(|()| Error::Primitive(PrimitiveError::Invariant))?;
+    let mut grant = match polled {
+        DevicePoll::Approved(grant) => grant,
+        DevicePoll::Pending => return Err(Error::invalid(AccessTokenErrorType::AuthorizationPending)),
+        DevicePoll::SlowDown => return Err(Error::invalid(AccessTokenErrorType::SlowDown)),
+        DevicePoll::Denied => return Err(Error::invalid(AccessTokenErrorType::AccessDenied)),
+        DevicePoll::Expired => return Err(Error::invalid(AccessTokenErrorType::ExpiredToken)),
+        DevicePoll::Unknown => return Err(Error::invalid(AccessTokenErrorType::InvalidGrant)),
+    };
+
+    enrich(handler.claims_enricher(), &mut grant);
+    let scope = grant.scope.to_string();
+    let details = authorization_details(&grant.extensions);
+    let members = response_members(handler.token_response_hook(), &grant);
+    let token = handler.issuer().issue(grant).map_err(Error::Primitive)?;
+    Ok(BearerToken(token, scope, details, None, members))
+}
+
+/// Authenticate the client with its credentials, backchannel requests require confidential clients.
+fn authenticate(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<String> {
+    let (client_id, passphrase) = match request.authorization() {
+        Some(credentials) => credentials,
+        None => return Err(Error::unauthorized("basic")),
+    };
+
+    // The client may still name itself, but not as another client.
+    if request.client_id().is_some_and(|named| named != client_id) {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    handler
+        .registrar()
+        .check(&client_id, Some(&passphrase))
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    Ok(client_id.into_owned())
+}
+
+impl Started {
+    /// The started authentication.
+    pub fn authorization(&self) -> &BackchannelAuthorization {
+        &self.0
+    }
+
+    /// How the client learns about the decision.
+    pub fn delivery(&self) -> &TokenDelivery {
+        &self.1
+    }
+
+    /// The response to the client.
+    ///
+    /// The polling interval is only included for clients in the poll mode.
+    pub fn response(&self) -> BackchannelAuthenticationResponse {
+        let interval = match self.1 {
+            TokenDelivery::Poll => Some(self.0.interval.num_seconds()),
+            TokenDelivery::Ping { .. } => None,
+        };
+
+        BackchannelAuthenticationResponse {
+            auth_req_id: self.0.auth_req_id.clone(),
+            expires_in: self.0.until.signed_duration_since(Utc::now()).num_seconds(),
+            interval,
+        }
+    }
+}
+
+impl Error {
+    fn invalid(kind: AccessTokenErrorType) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(kind);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    fn explained(kind: AccessTokenErrorType, description: &'static str) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(kind);
+        error.explain(description);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    fn unauthorized(authtype: &str) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidClient);
+        Error::Unauthorized(ErrorDescription { error }, authtype.to_string())
+    }
+
+    /// Get a handle to the description the client will receive.
+    ///
+    /// Some types of this error don't return any description which is represented by a `None`
+    /// result.
+    pub fn description(&mut self) -> Option<&mut AccessTokenError> {
+        match self {
+            Error::Invalid(description) => Some(description.description()),
+            Error::Unauthorized(description, _) => Some(description.description()),
+            Error::Primitive(_) => None,
+        }
+    }
+}
@@ -2,11 +2,16 @@
 use std::mem;
 use std::borrow::Cow;
 
-use chrono::{Utc, Duration};
+use chrono::Duration;
+use serde_json::{Map, Value};
 
-use crate::code_grant::accesstoken::BearerToken;
+use crate::code_grant::accesstoken::{response_members, BearerToken, TokenResponseHook};
+#[cfg(feature = "jwt")]
+use crate::code_grant::assertion::{asserted_client, client_assertion, ClientAssertions};
 use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::code_grant::extensions::{attach_claims, authorization_details, enrich, ClaimsEnricher};
 use crate::endpoint::{Scope, Solicitation};
+use crate::primitives::clock::{Clock, SystemClock};
 use crate::primitives::issuer::Issuer;
 use crate::primitives::grant::{Extensions, Grant};
 use crate::primitives::registrar::{Registrar, RegistrarError, BoundClient, PreGrant, ClientUrl};
@@ -89,6 +94,35 @@ pub trait Endpoint {
     ///
     /// It is possible to use `&mut ()`.
     fn extension(&mut self) -> &mut dyn Extension;
+
+    /// The verifier of clients authenticating with a JWT assertion.
+    ///
+    /// Returning `None` is the default implementation, client assertions are not accepted then.
+    #[cfg(feature = "jwt")]
+    fn client_assertions(&self) -> Option<&ClientAssertions> {
+        None
+    }
+
+    /// The clock of client assertions and of the lifetime of the grant.
+    ///
+    /// The system clock is the default implementation.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+
+    /// Adds members to the token response.
+    ///
+    /// Returning `None` is the default implementation, the response has only the standard members.
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        None
+    }
+
+    /// Attaches custom claims to the grant of the token.
+    ///
+    /// Returning `None` is the default implementation.
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        None
+    }
 }
 
 enum Credentials<'a> {
@@ -104,6 +138,8 @@ enum Credentials<'a> {
     /// As the client credentials may not be used for public clients, this is
     /// actually an error.
     Unauthenticated,
+    /// A client assertion was offered, verified in place of a passphrase.
+    Asserted { client_id: &'a str },
     /// Multiple possible credentials were offered.
     ///
     /// This is a security issue, only one attempt must be made per request.
@@ -315,11 +351,14 @@ impl ClientCredentials {
             credentials.authenticate(client_id.as_ref(), auth.as_ref());
         }
 
-        match (&client_id, &client_secret) {
-            (Some(client_id), Some(client_secret)) if request.allow_credentials_in_body() => {
+        let asserted = asserted_client_id(request)?;
+        match (&asserted, &client_id, &client_secret) {
+            (Some(asserted), _, None) => credentials.asserted(asserted),
+            (Some(_), _, Some(_)) => credentials.unauthenticated(),
+            (None, Some(client_id), Some(client_secret)) if request.allow_credentials_in_body() => {
                 credentials.authenticate(client_id.as_ref(), client_secret.as_ref().as_bytes())
             }
-            (None, None) => {}
+            (None, None, None) => {}
             _ => credentials.unauthenticated(),
         }
 
@@ -388,6 +427,8 @@ impl Pending {
         Solicitation {
             grant: Cow::Borrowed(&self.pre_grant),
             state: None,
+            extensions: Some(Cow::Borrowed(&self.extensions)),
+            authentication: None,
         }
     }
 
@@ -398,23 +439,44 @@ impl Pending {
     pub fn issue(
         self, handler: &mut dyn Endpoint, owner_id: String, allow_refresh_token: bool,
     ) -> Result<BearerToken> {
+        self.issue_with_claims(handler, owner_id, Map::new(), allow_refresh_token)
+    }
+
+    /// Inform the backend about consent from a resource owner, with custom claims for the grant.
+    ///
+    /// The claims of the `ClaimsEnricher` of the endpoint are added, without replacing any.
+    pub fn issue_with_claims(
+        self, handler: &mut dyn Endpoint, owner_id: String, claims: Map<String, Value>,
+        allow_refresh_token: bool,
+    ) -> Result<BearerToken> {
+        let details = authorization_details(&self.extensions);
+        let mut grant = Grant {
+            owner_id,
+            client_id: self.pre_grant.client_id,
+            redirect_uri: self.pre_grant.redirect_uri.into_url(),
+            scope: self.pre_grant.scope.clone(),
+            until: handler.clock().now() + Duration::minutes(10),
+            extensions: self.extensions,
+        };
+        attach_claims(&mut grant.extensions, claims);
+        enrich(handler.claims_enricher(), &mut grant);
+        let members = response_members(handler.token_response_hook(), &grant);
         let mut token = handler
             .issuer()
-            .issue(Grant {
-                owner_id,
-                client_id: self.pre_grant.client_id,
-                redirect_uri: self.pre_grant.redirect_uri.into_url(),
-                scope: self.pre_grant.scope.clone(),
-                until: Utc::now() + Duration::minutes(10),
-                extensions: self.extensions,
-            })
-            .map_err(|()| Error::Primitive(Box::new(PrimitiveError::empty())))?;
+            .issue(grant)
+            .map_err(|cause| Error::Primitive(Box::new(PrimitiveError::caused_by(cause))))?;
 
         if !allow_refresh_token {
             token.refresh = None;
         }
 
-        Ok(BearerToken(token, self.pre_grant.scope.to_string()))
+        Ok(BearerToken(
+            token,
+            self.pre_grant.scope.to_string(),
+            details,
+            None,
+            members,
+        ))
     }
 }
 
@@ -444,16 +506,19 @@ pub fn client_credentials(handler: &mut dyn Endpoint, request: &dyn Request) ->
         let input = match requested {
             Requested::None => Input::None,
             Requested::Authenticate { client, passdata } => {
-                handler
-                    .registrar()
-                    .check(&client, Some(passdata.as_slice()))
-                    .map_err(|err| match err {
-                        RegistrarError::Unspecified => Error::unauthorized("basic"),
-                        RegistrarError::PrimitiveError => Error::Primitive(Box::new(PrimitiveError {
-                            grant: None,
-                            extensions: None,
-                        })),
-                    })?;
+                if has_client_assertion(request) {
+                    authenticate_assertion(handler, request, &client)
+                } else {
+                    handler.registrar().check(&client, Some(passdata.as_slice()))
+                }
+                .map_err(|err| match err {
+                    RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                        Error::unauthorized("basic")
+                    }
+                    RegistrarError::PrimitiveError => {
+                        Error::Primitive(Box::new(PrimitiveError::empty()))
+                    }
+                })?;
                 Input::Authenticated
             }
             Requested::Bind { client_id } => {
@@ -462,12 +527,11 @@ pub fn client_credentials(handler: &mut dyn Endpoint, request: &dyn Request) ->
                     redirect_uri: None,
                 };
                 let bound_client = match handler.registrar().bound_redirect(client_url) {
-                    Err(RegistrarError::Unspecified) => return Err(Error::Ignore),
+                    Err(RegistrarError::Unspecified) | Err(RegistrarError::RedirectMismatch(_)) => {
+                        return Err(Error::Ignore)
+                    }
                     Err(RegistrarError::PrimitiveError) => {
-                        return Err(Error::Primitive(Box::new(PrimitiveError {
-                            grant: None,
-                            extensions: None,
-                        })));
+                        return Err(Error::Primitive(Box::new(PrimitiveError::empty())));
                     }
                     Ok(pre_grant) => pre_grant,
                 };
@@ -485,11 +549,12 @@ pub fn client_credentials(handler: &mut dyn Endpoint, request: &dyn Request) ->
                     .registrar()
                     .negotiate(bound_client.clone(), scope.clone())
                     .map_err(|err| match err {
-                        RegistrarError::PrimitiveError => Error::Primitive(Box::new(PrimitiveError {
-                            grant: None,
-                            extensions: None,
-                        })),
-                        RegistrarError::Unspecified => Error::Ignore,
+                        RegistrarError::PrimitiveError => {
+                            Error::Primitive(Box::new(PrimitiveError::empty()))
+                        }
+                        RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                            Error::Ignore
+                        }
                     })?;
                 Input::Negotiated { pre_grant }
             }
@@ -522,6 +587,58 @@ pub fn client_credentials(handler: &mut dyn Endpoint, request: &dyn Request) ->
     }
 }
 
+/// The client of a request with a client assertion, named by the request or else the assertion.
+#[cfg(feature = "jwt")]
+fn asserted_client_id(request: &dyn Request) -> Result<Option<String>> {
+    let assertion = match client_assertion(|key| request.extension(key)) {
+        None => return Ok(None),
+        Some(assertion) => assertion,
+    };
+
+    match request.extension("client_id") {
+        Some(client_id) => Ok(Some(client_id.into_owned())),
+        None => asserted_client(&assertion)
+            .map(Some)
+            .ok_or_else(|| Error::unauthorized("basic")),
+    }
+}
+
+#[cfg(not(feature = "jwt"))]
+fn asserted_client_id(_: &dyn Request) -> Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(feature = "jwt")]
+fn has_client_assertion(request: &dyn Request) -> bool {
+    client_assertion(|key| request.extension(key)).is_some()
+}
+
+#[cfg(not(feature = "jwt"))]
+fn has_client_assertion(_: &dyn Request) -> bool {
+    false
+}
+
+/// Verify the client assertion of the request, rejecting it if assertions are not accepted.
+#[cfg(feature = "jwt")]
+fn authenticate_assertion(
+    handler: &dyn Endpoint, request: &dyn Request, client: &str,
+) -> std::result::Result<(), RegistrarError> {
+    let assertion = client_assertion(|key| request.extension(key)).ok_or(RegistrarError::Unspecified)?;
+    match handler.client_assertions() {
+        Some(assertions) => {
+            assertions.authenticate(handler.registrar(), client, &assertion, handler.clock().now())
+        }
+        None => Err(RegistrarError::Unspecified),
+    }
+}
+
+#[cfg(not(feature = "jwt"))]
+fn authenticate_assertion(
+    _: &dyn Endpoint, _: &dyn Request, _: &str,
+) -> std::result::Result<(), RegistrarError> {
+    Err(RegistrarError::Unspecified)
+}
+
 impl<'a> Credentials<'a> {
     pub fn authenticate(&mut self, client_id: &'a str, passphrase: &'a [u8]) {
         self.add(Credentials::Authenticated {
@@ -534,12 +651,17 @@ impl<'a> Credentials<'a> {
         self.add(Credentials::Unauthenticated)
     }
 
+    pub fn asserted(&mut self, client_id: &'a str) {
+        self.add(Credentials::Asserted { client_id })
+    }
+
     pub fn into_client(self) -> Option<(&'a str, &'a [u8])> {
         match self {
             Credentials::Authenticated {
                 client_id,
                 passphrase,
             } => Some((client_id, passphrase)),
+            Credentials::Asserted { client_id } => Some((client_id, &[])),
             Credentials::Unauthenticated { .. } => None,
             _ => None,
         }
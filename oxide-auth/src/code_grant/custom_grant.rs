@@ -0,0 +1,211 @@
+//! Provides the handling for token requests of custom grant types.
+//!
+//! The token endpoint may support extension grants beyond those specified in RFC 6749, identified
+//! by an absolute URI as their `grant_type` (see [RFC 6749, Section 4.5]). Examples are SAML
+//! assertions of [RFC 7522] or grants internal to a deployment. A [`GrantHandler`] implements one
+//! such grant and is registered for its type in [`GrantTypes`], which dispatches requests by their
+//! `grant_type` parameter.
+//!
+//! [RFC 6749, Section 4.5]: https://tools.ietf.org/html/rfc6749#section-4.5
+//! [RFC 7522]: https://tools.ietf.org/html/rfc7522
+//! [`GrantHandler`]: trait.GrantHandler.html
+//! [`GrantTypes`]: struct.GrantTypes.html
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::code_grant::accesstoken::{BearerToken, ErrorDescription};
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::issuer::Issuer;
+use crate::primitives::registrar::{Registrar, RegistrarError};
+
+/// Required content of a token request of a custom grant type.
+pub trait Request {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+
+    /// The client_id, required for public clients.
+    fn client_id(&self) -> Option<Cow<str>>;
+
+    /// The grant type, selecting the handler of the request.
+    fn grant_type(&self) -> Option<Cow<str>>;
+
+    /// Optionally specifies the requested scope.
+    fn scope(&self) -> Option<Cow<str>>;
+
+    /// Retrieve any other parameter of the request, as defined by the grant type.
+    fn extension(&self, key: &str) -> Option<Cow<str>>;
+}
+
+/// Required functionality to respond to token requests of custom grant types.
+pub trait Endpoint {
+    /// The registrar, with which handlers authenticate clients and negotiate scopes.
+    fn registrar(&self) -> &dyn Registrar;
+
+    /// The issuer of the tokens.
+    fn issuer(&mut self) -> &mut dyn Issuer;
+
+    /// The clock with which handlers check the expiry of grants and compute their lifetime.
+    ///
+    /// The system clock is the default implementation.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+}
+
+/// Implements a custom grant type.
+///
+/// The handler validates the parameters of the grant, authenticates the client if necessary, for
+/// example with [`authenticate`], and issues the token. Requests are only passed to the handler
+/// once they were found to be well-formed.
+///
+/// [`authenticate`]: fn.authenticate.html
+pub trait GrantHandler {
+    /// Issue a token for the request with the primitives of the endpoint, or explain why it is
+    /// rejected.
+    fn grant(&mut self, request: &dyn Request, endpoint: &mut dyn Endpoint) -> Result<BearerToken>;
+}
+
+/// A registry of handlers for custom grant types.
+///
+/// Requests are dispatched to the handler registered for their `grant_type`, all other grant types
+/// are rejected as `unsupported_grant_type`.
+#[derive(Default)]
+pub struct GrantTypes {
+    handlers: HashMap<String, Box<dyn GrantHandler + Send>>,
+}
+
+/// Defines actions for the response to a token request of a custom grant type.
+#[derive(Clone)]
+pub enum Error {
+    /// The request or the grant was invalid.
+    Invalid(ErrorDescription),
+
+    /// The client did not properly authorize itself.
+    Unauthorized(ErrorDescription, String),
+
+    /// An underlying primitive operation did not complete successfully.
+    Primitive(PrimitiveError),
+}
+
+/// The result of handling a request of a custom grant type.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Handle a token request of a custom grant type.
+pub fn custom_grant(
+    endpoint: &mut dyn Endpoint, handler: &mut dyn GrantHandler, request: &dyn Request,
+) -> Result<BearerToken> {
+    if !request.valid() || request.grant_type().is_none() {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    handler.grant(request, endpoint)
+}
+
+/// Authenticate the client with its credentials or, for public clients, its id.
+///
+/// Returns the id of the client, for use by grant handlers.
+pub fn authenticate(registrar: &dyn Registrar, request: &dyn Request) -> Result<String> {
+    let (client_id, passphrase) = match (request.authorization(), request.client_id()) {
+        // An authenticated client may still name itself, but not as another client.
+        (Some((client_id, passphrase)), Some(named)) if named == client_id => {
+            (client_id, Some(passphrase))
+        }
+        (Some(_), Some(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        (Some((client_id, passphrase)), None) => (client_id, Some(passphrase)),
+        (None, Some(client_id)) => (client_id, None),
+        (None, None) => return Err(Error::unauthorized("basic")),
+    };
+
+    registrar
+        .check(&client_id, passphrase.as_deref())
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    Ok(client_id.into_owned())
+}
+
+impl GrantTypes {
+    /// A registry without any grant types.
+    pub fn new() -> Self {
+        GrantTypes::default()
+    }
+
+    /// Handle requests of the grant type with the handler.
+    ///
+    /// Replaces any handler previously registered for the same grant type.
+    pub fn register<H>(&mut self, grant_type: &str, handler: H)
+    where
+        H: GrantHandler + Send + 'static,
+    {
+        self.handlers.insert(grant_type.to_owned(), Box::new(handler));
+    }
+
+    /// Check if a handler is registered for the grant type.
+    ///
+    /// Useful to decide which flow should handle a request to the token endpoint.
+    pub fn supports(&self, grant_type: &str) -> bool {
+        self.handlers.contains_key(grant_type)
+    }
+
+    /// The registered grant types, for example to announce them in the server metadata.
+    pub fn grant_types(&self) -> impl Iterator<Item = &str> {
+        self.handlers.keys().map(String::as_str)
+    }
+}
+
+impl GrantHandler for GrantTypes {
+    fn grant(&mut self, request: &dyn Request, endpoint: &mut dyn Endpoint) -> Result<BearerToken> {
+        let grant_type = request
+            .grant_type()
+            .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidRequest))?;
+        match self.handlers.get_mut(grant_type.as_ref()) {
+            Some(handler) => handler.grant(request, endpoint),
+            None => Err(Error::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+        }
+    }
+}
+
+impl<H: GrantHandler + ?Sized> GrantHandler for &mut H {
+    fn grant(&mut self, request: &dyn Request, endpoint: &mut dyn Endpoint) -> Result<BearerToken> {
+        (**self).grant(request, endpoint)
+    }
+}
+
+impl Error {
+    /// Reject the request with an error of the kind.
+    pub fn invalid(kind: AccessTokenErrorType) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(kind);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    /// Reject the request as the client could not be authenticated.
+    pub fn unauthorized(authtype: &str) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidClient);
+        Error::Unauthorized(ErrorDescription { error }, authtype.to_string())
+    }
+
+    /// Get a handle to the description the client will receive.
+    ///
+    /// Some types of this error don't return any description which is represented by a `None`
+    /// result.
+    pub fn description(&mut self) -> Option<&mut AccessTokenError> {
+        match self {
+            Error::Invalid(description) => Some(description.description()),
+            Error::Unauthorized(description, _) => Some(description.description()),
+            Error::Primitive(_) => None,
+        }
+    }
+}
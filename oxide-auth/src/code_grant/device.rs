@@ -0,0 +1,278 @@
+//! Provides the handling for the Device Authorization Grant.
+//!
+//! The grant of [RFC 8628] consists of three requests. The device starts an authorization with a
+//! device authorization request and receives a `device_code` and a `user_code`. The user enters
+//! the user code on the verification page and approves the request through the usual solicitor.
+//! The device polls the token endpoint with its device code until it receives a token or an error
+//! other than `authorization_pending` and `slow_down`.
+//!
+//! [RFC 8628]: https://tools.ietf.org/html/rfc8628
+use std::borrow::Cow;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::code_grant::accesstoken::{response_members, BearerToken, ErrorDescription, TokenResponseHook};
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::code_grant::extensions::{authorization_details, enrich, ClaimsEnricher};
+use crate::primitives::device::{DeviceAuthorization, DeviceCodeStore, DevicePoll};
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::issuer::Issuer;
+use crate::primitives::registrar::{ClientUrl, Registrar, RegistrarError};
+
+/// The `grant_type` of token requests polling with a device code.
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Required content of a device authorization or device token request.
+pub trait Request {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+
+    /// The client_id, required for public clients.
+    fn client_id(&self) -> Option<Cow<str>>;
+
+    /// Optionally specifies the requested scope, only in device authorization requests.
+    fn scope(&self) -> Option<Cow<str>>;
+
+    /// Valid token requests have this set to `DEVICE_CODE_GRANT_TYPE`.
+    fn grant_type(&self) -> Option<Cow<str>>;
+
+    /// The device code the device is polling with, only in token requests.
+    fn device_code(&self) -> Option<Cow<str>>;
+
+    /// Retrieve an additional parameter used in an extension
+    fn extension(&self, key: &str) -> Option<Cow<str>>;
+}
+
+/// Required functionality to respond to device requests.
+pub trait Endpoint {
+    /// Get the client corresponding to some id.
+    fn registrar(&self) -> &dyn Registrar;
+
+    /// Return the issuer instance to create the access token.
+    fn issuer(&mut self) -> &mut dyn Issuer;
+
+    /// The store of pending device authorizations.
+    fn device_codes(&mut self) -> &mut dyn DeviceCodeStore;
+
+    /// Adds members to the token response.
+    ///
+    /// Returning `None` is the default implementation, the response has only the standard members.
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        None
+    }
+
+    /// Attaches custom claims to the grant of the token.
+    ///
+    /// Returning `None` is the default implementation.
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        None
+    }
+}
+
+/// The response to a successful device authorization request.
+///
+/// See [RFC 8628, Section 3.2](https://tools.ietf.org/html/rfc8628#section-3.2).
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DeviceAuthorizationResponse {
+    /// The code with which the device polls the token endpoint.
+    pub device_code: String,
+
+    /// The code the user enters on the verification page.
+    pub user_code: String,
+
+    /// The verification page on the authorization server.
+    pub verification_uri: String,
+
+    /// The verification page with the user code already filled in, for example as a QR code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_uri_complete: Option<String>,
+
+    /// The lifetime in seconds of both codes.
+    pub expires_in: i64,
+
+    /// The minimum number of seconds the device should wait between polling requests.
+    pub interval: i64,
+}
+
+/// A started device authorization, to be sent to the device.
+pub struct DeviceCodes(DeviceAuthorization);
+
+/// Defines actions for the response to a device request.
+#[derive(Clone)]
+pub enum Error {
+    /// The request was invalid or the authorization is not ready.
+    Invalid(ErrorDescription),
+
+    /// The client did not properly authorize itself.
+    Unauthorized(ErrorDescription, String),
+
+    /// An underlying primitive operation did not complete successfully.
+    Primitive(PrimitiveError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Start the authorization of a device for the requesting client.
+pub fn device_authorization(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<DeviceCodes> {
+    if !request.valid() {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    let scope = match request.scope().map(|scope| scope.as_ref().parse()) {
+        None => None,
+        Some(Err(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidScope)),
+        Some(Ok(scope)) => Some(scope),
+    };
+
+    let client_id = authenticate(handler, request)?;
+    let bound_client = handler
+        .registrar()
+        .bound_redirect(ClientUrl {
+            client_id: Cow::Owned(client_id),
+            redirect_uri: None,
+        })
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    let pre_grant = handler
+        .registrar()
+        .negotiate(bound_client, scope)
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::invalid(AccessTokenErrorType::InvalidScope)
+            }
+        })?;
+
+    let started = handler
+        .device_codes()
+        .start(pre_grant)
+        .map_err(|()| Error::Primitive(PrimitiveError::Invariant))?;
+    Ok(DeviceCodes(started))
+}
+
+/// Poll for the token of a device authorization.
+pub fn device_token(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<BearerToken> {
+    if !request.valid() {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    match request.grant_type() {
+        Some(ref cow) if cow == DEVICE_CODE_GRANT_TYPE => (),
+        None => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        Some(_) => return Err(Error::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+    }
+
+    let device_code = request
+        .device_code()
+        .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidRequest))?;
+    let client_id = authenticate(handler, request)?;
+
+    let polled = handler
+        .device_codes()
+        .poll(&client_id, &device_code)
+        .map_err(|()| Error::Primitive(PrimitiveError::Invariant))?;
+    let mut grant = match polled {
+        DevicePoll::Approved(grant) => grant,
+        DevicePoll::Pending => return Err(Error::invalid(AccessTokenErrorType::AuthorizationPending)),
+        DevicePoll::SlowDown => return Err(Error::invalid(AccessTokenErrorType::SlowDown)),
+        DevicePoll::Denied => return Err(Error::invalid(AccessTokenErrorType::AccessDenied)),
+        DevicePoll::Expired => return Err(Error::invalid(AccessTokenErrorType::ExpiredToken)),
+        DevicePoll::Unknown => return Err(Error::invalid(AccessTokenErrorType::InvalidGrant)),
+    };
+
+    enrich(handler.claims_enricher(), &mut grant);
+    let scope = grant.scope.to_string();
+    let details = authorization_details(&grant.extensions);
+    let members = response_members(handler.token_response_hook(), &grant);
+    let token = handler.issuer().issue(grant).map_err(Error::Primitive)?;
+    Ok(BearerToken(token, scope, details, None, members))
+}
+
+/// Authenticate the client with its credentials or, for public clients, its id.
+fn authenticate(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<String> {
+    let (client_id, passphrase) = match (request.authorization(), request.client_id()) {
+        // An authenticated client may still name itself, but not as another client.
+        (Some((client_id, passphrase)), Some(named)) if named == client_id => {
+            (client_id, Some(passphrase))
+        }
+        (Some(_), Some(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        (Some((client_id, passphrase)), None) => (client_id, Some(passphrase)),
+        (None, Some(client_id)) => (client_id, None),
+        (None, None) => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+    };
+
+    handler
+        .registrar()
+        .check(&client_id, passphrase.as_deref())
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    Ok(client_id.into_owned())
+}
+
+impl DeviceCodes {
+    /// The started authorization.
+    pub fn authorization(&self) -> &DeviceAuthorization {
+        &self.0
+    }
+
+    /// The response to the device, pointing the user to the verification page.
+    ///
+    /// The complete verification uri carries the user code in the `user_code` query parameter.
+    pub fn response(&self, verification_uri: &Url) -> DeviceAuthorizationResponse {
+        let mut complete = verification_uri.clone();
+        complete
+            .query_pairs_mut()
+            .append_pair("user_code", &self.0.user_code);
+
+        DeviceAuthorizationResponse {
+            device_code: self.0.device_code.clone(),
+            user_code: self.0.user_code.clone(),
+            verification_uri: verification_uri.to_string(),
+            verification_uri_complete: Some(complete.to_string()),
+            expires_in: self.0.until.signed_duration_since(Utc::now()).num_seconds(),
+            interval: self.0.interval.num_seconds(),
+        }
+    }
+}
+
+impl Error {
+    fn invalid(kind: AccessTokenErrorType) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(kind);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    fn unauthorized(authtype: &str) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidClient);
+        Error::Unauthorized(ErrorDescription { error }, authtype.to_string())
+    }
+
+    /// Get a handle to the description the client will receive.
+    ///
+    /// Some types of this error don't return any description which is represented by a `None`
+    /// result.
+    pub fn description(&mut self) -> Option<&mut AccessTokenError> {
+        match self {
+            Error::Invalid(description) => Some(description.description()),
+            Error::Unauthorized(description, _) => Some(description.description()),
+            Error::Primitive(_) => None,
+        }
+    }
+}
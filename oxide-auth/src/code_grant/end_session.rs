@@ -0,0 +1,177 @@
+//! Provides the handling for logout requests of OpenID Connect clients.
+//!
+//! A client sends the owner to the end session endpoint of the authorization server to log out of
+//! the server as well, as specified in [RP-Initiated Logout]. The request carries a previously
+//! issued ID token as `id_token_hint`, which identifies the owner and the client. The session named
+//! by the `sid` claim of the token ends, or without such a claim all sessions of the owner in which
+//! the client participates. Afterwards the owner is sent to the `post_logout_redirect_uri` of the
+//! request, which must be registered for the client.
+//!
+//! Requests without an `id_token_hint` are rejected, since the owner could not be identified
+//! without asking. The hint may have expired, only its signature and issuer are verified.
+//!
+//! [RP-Initiated Logout]: https://openid.net/specs/openid-connect-rpinitiated-1_0.html
+use std::borrow::Cow;
+
+use url::Url;
+
+use crate::code_grant::accesstoken::ErrorDescription;
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::primitives::jwt::{IdTokenClaims, IdTokenSigner};
+use crate::primitives::registrar::{ClientUrl, ExactUrl, Registrar, RegistrarError};
+use crate::primitives::session::{Session, SessionRegistry};
+
+/// Required content of a logout request.
+pub trait Request {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+
+    /// An ID token previously issued to the client.
+    fn id_token_hint(&self) -> Option<Cow<str>>;
+
+    /// The client_id, which must be the audience of the hint.
+    fn client_id(&self) -> Option<Cow<str>>;
+
+    /// Where to send the owner after the logout.
+    fn post_logout_redirect_uri(&self) -> Option<Cow<str>>;
+
+    /// The state to pass back to the client with the redirect.
+    fn state(&self) -> Option<Cow<str>>;
+
+    /// Retrieve an additional parameter used in an extension
+    fn extension(&self, key: &str) -> Option<Cow<str>>;
+}
+
+/// Required functionality to respond to logout requests.
+pub trait Endpoint {
+    /// Validate the redirect uri of the client.
+    fn registrar(&self) -> &dyn Registrar;
+
+    /// Verify the ID token hints.
+    fn id_token_signer(&self) -> &IdTokenSigner;
+
+    /// The sessions to end.
+    fn sessions(&mut self) -> &mut dyn SessionRegistry;
+}
+
+/// The result of a successful logout.
+#[derive(Clone, Debug)]
+pub struct Logout {
+    /// The sessions that were ended.
+    pub sessions: Vec<Session>,
+
+    /// Where to send the owner, including the state of the request.
+    pub redirect_uri: Option<Url>,
+}
+
+/// Defines actions for the response to a logout request.
+#[derive(Clone)]
+pub enum Error {
+    /// The request was malformed, its hint invalid or its redirect uri not registered.
+    Invalid(ErrorDescription),
+
+    /// An underlying primitive operation did not complete successfully.
+    Primitive,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// End the sessions of the owner identified by the hint of the request.
+pub fn end_session(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<Logout> {
+    if !request.valid() {
+        return Err(Error::invalid());
+    }
+
+    let hint = request.id_token_hint().ok_or_else(Error::invalid)?;
+    let signer = handler.id_token_signer();
+    let claims: IdTokenClaims = signer.keys().verify(&hint).map_err(|()| Error::invalid())?;
+    if claims.iss != signer.issuer() {
+        return Err(Error::invalid());
+    }
+
+    let client_id = match request.client_id() {
+        Some(client_id) if client_id != claims.aud => return Err(Error::invalid()),
+        _ => claims.aud.as_str(),
+    };
+
+    let redirect_uri = match request.post_logout_redirect_uri() {
+        None => None,
+        Some(uri) => Some(bind_redirect(handler.registrar(), client_id, &uri)?),
+    };
+
+    // The session of a pairwise subject is only found by its `sid`.
+    let ended = match claims.claims.get("sid").and_then(|sid| sid.as_str()) {
+        Some(sid) => match handler.sessions().session(sid).map_err(|()| Error::Primitive)? {
+            Some(session)
+                if handler.id_token_signer().subject(&session.owner_id, client_id) == claims.sub =>
+            {
+                vec![session.sid]
+            }
+            _ => Vec::new(),
+        },
+        None => handler
+            .sessions()
+            .sessions_of(&claims.sub)
+            .map_err(|()| Error::Primitive)?
+            .into_iter()
+            .filter(|session| session.clients.iter().any(|client| client == client_id))
+            .map(|session| session.sid)
+            .collect(),
+    };
+
+    let sessions = handler.sessions();
+    let mut logout = Logout {
+        sessions: Vec::new(),
+        redirect_uri,
+    };
+    for sid in ended {
+        if let Some(session) = sessions.end(&sid).map_err(|()| Error::Primitive)? {
+            logout.sessions.push(session);
+        }
+    }
+
+    if let (Some(uri), Some(state)) = (logout.redirect_uri.as_mut(), request.state()) {
+        uri.query_pairs_mut().append_pair("state", &state);
+    }
+
+    Ok(logout)
+}
+
+/// Check that the uri is registered for the client.
+///
+/// Clients register no separate logout uris, any of their redirect uris is accepted.
+fn bind_redirect(registrar: &dyn Registrar, client_id: &str, uri: &str) -> Result<Url> {
+    let exact: ExactUrl = uri.parse().map_err(|_| Error::invalid())?;
+    let bound = registrar
+        .bound_redirect(ClientUrl {
+            client_id: Cow::Borrowed(client_id),
+            redirect_uri: Some(Cow::Owned(exact)),
+        })
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive,
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => Error::invalid(),
+        })?;
+    Ok(bound.redirect_uri.to_url())
+}
+
+impl Error {
+    fn invalid() -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidRequest);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    /// Get a handle to the description the client will receive.
+    ///
+    /// Some types of this error don't return any description which is represented by a `None`
+    /// result.
+    pub fn description(&mut self) -> Option<&mut AccessTokenError> {
+        match self {
+            Error::Invalid(description) => Some(description.description()),
+            Error::Primitive => None,
+        }
+    }
+}
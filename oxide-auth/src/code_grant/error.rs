@@ -35,6 +35,22 @@ pub enum AuthorizationErrorType {
     /// overloading or maintenance of the server.  (This error code is needed because a 503 Service
     /// Unavailable HTTP status code cannot be returned to the client via an HTTP redirect.)
     TemporarilyUnavailable,
+
+    /// The authorization server requires interaction with the end-user, but the request forbade
+    /// any user interface with `prompt=none`.
+    InteractionRequired,
+
+    /// The authorization server requires the end-user to log in, but the request forbade any user
+    /// interface with `prompt=none`.
+    LoginRequired,
+
+    /// The end-user must select one of their accounts, but the request forbade any user interface
+    /// with `prompt=none`.
+    AccountSelectionRequired,
+
+    /// The authorization server requires the consent of the end-user, but the request forbade any
+    /// user interface with `prompt=none`.
+    ConsentRequired,
 }
 
 impl AuthorizationErrorType {
@@ -47,6 +63,10 @@ impl AuthorizationErrorType {
             AuthorizationErrorType::InvalidScope => "invalid_scope",
             AuthorizationErrorType::ServerError => "server_error",
             AuthorizationErrorType::TemporarilyUnavailable => "temporarily_unavailable",
+            AuthorizationErrorType::InteractionRequired => "interaction_required",
+            AuthorizationErrorType::LoginRequired => "login_required",
+            AuthorizationErrorType::AccountSelectionRequired => "account_selection_required",
+            AuthorizationErrorType::ConsentRequired => "consent_required",
         }
     }
 }
@@ -59,6 +79,7 @@ pub struct AuthorizationError {
     error: AuthorizationErrorType,
     description: Option<Cow<'static, str>>,
     uri: Option<Cow<'static, str>>,
+    trace_id: Option<Cow<'static, str>>,
 }
 
 impl AuthorizationError {
@@ -68,6 +89,7 @@ impl AuthorizationError {
             error,
             description: None,
             uri: None,
+            trace_id: None,
         }
     }
 
@@ -93,6 +115,28 @@ impl AuthorizationError {
         self.uri = Some(String::from(uri).into())
     }
 
+    /// The short text explanation of the error, if any.
+    pub fn explanation(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The uri explaining the error, if any.
+    pub fn uri(&self) -> Option<&str> {
+        self.uri.as_deref()
+    }
+
+    /// Attach an identifier correlating the response with the logs of the server.
+    ///
+    /// It is sent as the `trace_id` parameter of the error.
+    pub fn trace<D: Into<Cow<'static, str>>>(&mut self, trace_id: D) {
+        self.trace_id = Some(trace_id.into())
+    }
+
+    /// The identifier correlating the response with the logs of the server, if any.
+    pub fn trace_id(&self) -> Option<&str> {
+        self.trace_id.as_deref()
+    }
+
     /// Iterate over the key value pairs that describe this error.
     ///
     /// These pairs must be added to the detailed description of an error. To this end the pairs
@@ -136,6 +180,27 @@ pub enum AccessTokenErrorType {
     /// The requested scope is invalid, unknown, malformed, or exceeds the scope granted by the
     /// resource owner.
     InvalidScope,
+
+    /// The user has not yet decided on a pending device authorization, the device should keep
+    /// polling. See [RFC 8628, Section 3.5](https://tools.ietf.org/html/rfc8628#section-3.5).
+    AuthorizationPending,
+
+    /// A variant of `AuthorizationPending` asking the device to poll less frequently.
+    SlowDown,
+
+    /// The user denied the device authorization.
+    AccessDenied,
+
+    /// The device code expired before the user decided.
+    ExpiredToken,
+
+    /// The requested audience of an exchanged token is unknown or not allowed for the client. See
+    /// [RFC 8693, Section 2.2.2](https://tools.ietf.org/html/rfc8693#section-2.2.2).
+    InvalidTarget,
+
+    /// The hint of a backchannel authentication request does not identify a known owner. See
+    /// [CIBA, Section 13](https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#rfc.section.13).
+    UnknownUserId,
 }
 
 impl AccessTokenErrorType {
@@ -147,6 +212,12 @@ impl AccessTokenErrorType {
             AccessTokenErrorType::UnauthorizedClient => "unauthorized_client",
             AccessTokenErrorType::UnsupportedGrantType => "unsupported_grant_type",
             AccessTokenErrorType::InvalidScope => "invalid_scope",
+            AccessTokenErrorType::AuthorizationPending => "authorization_pending",
+            AccessTokenErrorType::SlowDown => "slow_down",
+            AccessTokenErrorType::AccessDenied => "access_denied",
+            AccessTokenErrorType::ExpiredToken => "expired_token",
+            AccessTokenErrorType::InvalidTarget => "invalid_target",
+            AccessTokenErrorType::UnknownUserId => "unknown_user_id",
         }
     }
 }
@@ -163,6 +234,7 @@ pub struct AccessTokenError {
     error: AccessTokenErrorType,
     description: Option<Cow<'static, str>>,
     uri: Option<Cow<'static, str>>,
+    trace_id: Option<Cow<'static, str>>,
 }
 
 impl AccessTokenError {
@@ -171,6 +243,7 @@ impl AccessTokenError {
             error,
             description: None,
             uri: None,
+            trace_id: None,
         }
     }
 
@@ -195,6 +268,28 @@ impl AccessTokenError {
         self.uri = Some(String::from(uri).into())
     }
 
+    /// The short text explanation of the error, if any.
+    pub fn explanation(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The uri explaining the error, if any.
+    pub fn uri(&self) -> Option<&str> {
+        self.uri.as_deref()
+    }
+
+    /// Attach an identifier correlating the response with the logs of the server.
+    ///
+    /// It is sent as the `trace_id` parameter of the error.
+    pub fn trace<D: Into<Cow<'static, str>>>(&mut self, trace_id: D) {
+        self.trace_id = Some(trace_id.into())
+    }
+
+    /// The identifier correlating the response with the logs of the server, if any.
+    pub fn trace_id(&self) -> Option<&str> {
+        self.trace_id.as_deref()
+    }
+
     /// Iterate over the key value pairs that describe this error.
     ///
     /// These pairs must be added to the detailed description of an error. The pairs will be
@@ -214,6 +309,7 @@ impl Default for AuthorizationError {
             error: AuthorizationErrorType::InvalidRequest,
             description: None,
             uri: None,
+            trace_id: None,
         }
     }
 }
@@ -240,6 +336,7 @@ impl Default for AccessTokenError {
             error: AccessTokenErrorType::InvalidRequest,
             description: None,
             uri: None,
+            trace_id: None,
         }
     }
 }
@@ -264,10 +361,13 @@ impl IntoIterator for AuthorizationError {
     fn into_iter(self) -> Self::IntoIter {
         let mut vec = vec![("error", Cow::Borrowed(self.error.description()))];
         if let Some(description) = self.description {
-            vec.push(("description", description));
+            vec.push(("error_description", description));
         }
         if let Some(uri) = self.uri {
-            vec.push(("uri", uri));
+            vec.push(("error_uri", uri));
+        }
+        if let Some(trace_id) = self.trace_id {
+            vec.push(("trace_id", trace_id));
         }
         vec.into_iter()
     }
@@ -280,10 +380,13 @@ impl IntoIterator for &'_ AuthorizationError {
     fn into_iter(self) -> Self::IntoIter {
         let mut vec = vec![("error", Cow::Borrowed(self.error.description()))];
         if let Some(description) = &self.description {
-            vec.push(("description", description.clone().to_owned()));
+            vec.push(("error_description", description.clone().to_owned()));
         }
         if let Some(uri) = &self.uri {
-            vec.push(("uri", uri.clone().to_owned()));
+            vec.push(("error_uri", uri.clone().to_owned()));
+        }
+        if let Some(trace_id) = &self.trace_id {
+            vec.push(("trace_id", trace_id.clone()));
         }
         vec.into_iter()
     }
@@ -297,10 +400,13 @@ impl IntoIterator for AccessTokenError {
     fn into_iter(self) -> Self::IntoIter {
         let mut vec = vec![("error", Cow::Borrowed(self.error.description()))];
         if let Some(description) = self.description {
-            vec.push(("description", description));
+            vec.push(("error_description", description));
         }
         if let Some(uri) = self.uri {
-            vec.push(("uri", uri));
+            vec.push(("error_uri", uri));
+        }
+        if let Some(trace_id) = self.trace_id {
+            vec.push(("trace_id", trace_id));
         }
         vec.into_iter()
     }
@@ -313,10 +419,13 @@ impl IntoIterator for &'_ AccessTokenError {
     fn into_iter(self) -> Self::IntoIter {
         let mut vec = vec![("error", Cow::Borrowed(self.error.description()))];
         if let Some(description) = &self.description {
-            vec.push(("description", description.clone().to_owned()));
+            vec.push(("error_description", description.clone().to_owned()));
         }
         if let Some(uri) = &self.uri {
-            vec.push(("uri", uri.clone().to_owned()));
+            vec.push(("error_uri", uri.clone().to_owned()));
+        }
+        if let Some(trace_id) = &self.trace_id {
+            vec.push(("trace_id", trace_id.clone()));
         }
         vec.into_iter()
     }
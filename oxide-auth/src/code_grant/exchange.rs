@@ -0,0 +1,370 @@
+//! Provides the handling for Token Exchange requests.
+//!
+//! A client holding a token of some subject trades it for a new access token, as specified in
+//! [RFC 8693]. Typically a service receiving a user's token requests a token for a downstream
+//! service on behalf of that user, optionally proving its own identity with an actor token. Which
+//! tokens are accepted, for which audiences and with which scopes, is decided by an
+//! [`ExchangePolicy`].
+//!
+//! [RFC 8693]: https://tools.ietf.org/html/rfc8693
+//! [`ExchangePolicy`]: trait.ExchangePolicy.html
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::code_grant::accesstoken::ErrorDescription;
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::primitives::grant::{Grant, Value};
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::issuer::{IssuedToken, Issuer};
+use crate::primitives::registrar::{Registrar, RegistrarError};
+use crate::primitives::scope::Scope;
+
+/// The `grant_type` of token exchange requests.
+pub const TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+/// The token type identifier of access tokens.
+pub const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// The token type identifier of refresh tokens.
+pub const REFRESH_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:refresh_token";
+
+/// The grant extension recording the requested audience of an exchanged token.
+pub const AUDIENCE_EXTENSION: &str = "audience";
+
+/// The grant extension recording the owner of the actor token of an exchanged token.
+pub const ACTOR_EXTENSION: &str = "act";
+
+/// Required content of a token exchange request.
+pub trait Request {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+
+    /// The client_id, required for public clients.
+    fn client_id(&self) -> Option<Cow<str>>;
+
+    /// Valid requests have this set to `TOKEN_EXCHANGE_GRANT_TYPE`.
+    fn grant_type(&self) -> Option<Cow<str>>;
+
+    /// The token representing the subject on whose behalf the request is made.
+    fn subject_token(&self) -> Option<Cow<str>>;
+
+    /// The type identifier of the subject token.
+    fn subject_token_type(&self) -> Option<Cow<str>>;
+
+    /// Optionally, a token representing the acting party.
+    fn actor_token(&self) -> Option<Cow<str>>;
+
+    /// The type identifier of the actor token, required with an actor token.
+    fn actor_token_type(&self) -> Option<Cow<str>>;
+
+    /// Optionally, the service the new token is intended for.
+    fn audience(&self) -> Option<Cow<str>>;
+
+    /// Optionally specifies the requested scope.
+    fn scope(&self) -> Option<Cow<str>>;
+
+    /// Retrieve an additional parameter used in an extension
+    fn extension(&self, key: &str) -> Option<Cow<str>>;
+}
+
+/// Required functionality to respond to token exchange requests.
+pub trait Endpoint {
+    /// Authenticate the requesting client.
+    fn registrar(&self) -> &dyn Registrar;
+
+    /// The issuer recovering the presented tokens and issuing the new access token, with the
+    /// policy deciding on the presented tokens, the audience and the scope.
+    fn issuer_and_policy(&mut self) -> (&mut dyn Issuer, &mut dyn ExchangePolicy);
+
+    /// The clock deciding whether the presented tokens have expired.
+    ///
+    /// The system clock is the default implementation.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+}
+
+/// Decides which exchanges are allowed.
+///
+/// The default implementations accept valid access tokens of the issuer as subject and actor
+/// tokens, permit any audience and only narrow the scope of the subject token.
+pub trait ExchangePolicy {
+    /// Validate a subject token and return the grant it represents.
+    ///
+    /// Returning `Ok(None)` rejects the token.
+    fn subject(
+        &mut self, issuer: &dyn Issuer, token: &str, token_type: &str,
+    ) -> std::result::Result<Option<Grant>, PrimitiveError> {
+        recover_access_token(issuer, token, token_type)
+    }
+
+    /// Validate an actor token and return the grant it represents.
+    ///
+    /// Returning `Ok(None)` rejects the token.
+    fn actor(
+        &mut self, issuer: &dyn Issuer, token: &str, token_type: &str,
+    ) -> std::result::Result<Option<Grant>, PrimitiveError> {
+        recover_access_token(issuer, token, token_type)
+    }
+
+    /// Check if the client may request a token for the audience, `None` if it named none.
+    fn audience(&mut self, _client_id: &str, _audience: Option<&str>) -> bool {
+        true
+    }
+
+    /// Determine the scope of the new token, or `None` to reject the requested scope.
+    fn scope(&mut self, subject: &Scope, requested: Option<Scope>) -> Option<Scope> {
+        match requested {
+            None => Some(subject.clone()),
+            Some(requested) if requested <= *subject => Some(requested),
+            Some(_) => None,
+        }
+    }
+}
+
+/// A policy with allowed audiences for each client.
+///
+/// Clients may only name audiences that have been allowed for them, while requests without an
+/// audience are always allowed. Tokens and scopes are handled as by the default implementation.
+#[derive(Clone, Debug, Default)]
+pub struct AudiencePolicy {
+    audiences: HashMap<String, HashSet<String>>,
+}
+
+/// The response to a successful token exchange.
+///
+/// See [RFC 8693, Section 2.2.1](https://tools.ietf.org/html/rfc8693#section-2.2.1). No refresh
+/// token is issued for exchanged tokens.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ExchangeResponse {
+    /// The new access token.
+    pub access_token: String,
+
+    /// The type identifier of the issued token, always `ACCESS_TOKEN_TYPE`.
+    pub issued_token_type: String,
+
+    /// How the token is used, always `bearer`.
+    pub token_type: String,
+
+    /// The lifetime in seconds of the token.
+    pub expires_in: i64,
+
+    /// The space separated scopes of the token.
+    pub scope: String,
+}
+
+/// Defines actions for the response to a token exchange request.
+#[derive(Clone)]
+pub enum Error {
+    /// The request or one of its tokens was invalid.
+    Invalid(ErrorDescription),
+
+    /// The client did not properly authorize itself.
+    Unauthorized(ErrorDescription, String),
+
+    /// An underlying primitive operation did not complete successfully.
+    Primitive(PrimitiveError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Exchange the subject token of the request for a new access token.
+///
+/// The new token belongs to the owner of the subject token, is issued to the requesting client and
+/// its grant carries the expiry of the subject token. The audience and the owner of the actor token
+/// are kept in the `AUDIENCE_EXTENSION` and `ACTOR_EXTENSION` of the grant.
+pub fn exchange(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<ExchangeResponse> {
+    if !request.valid() {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    match request.grant_type() {
+        Some(ref cow) if cow == TOKEN_EXCHANGE_GRANT_TYPE => (),
+        None => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        Some(_) => return Err(Error::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+    }
+
+    let client_id = authenticate(handler, request)?;
+    let (subject_token, subject_type) = match (request.subject_token(), request.subject_token_type()) {
+        (Some(token), Some(token_type)) => (token, token_type),
+        _ => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+    };
+    let actor = match (request.actor_token(), request.actor_token_type()) {
+        (None, None) => None,
+        (Some(token), Some(token_type)) => Some((token, token_type)),
+        _ => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+    };
+    let requested_scope = match request.scope().map(|scope| scope.as_ref().parse()) {
+        None => None,
+        Some(Err(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidScope)),
+        Some(Ok(scope)) => Some(scope),
+    };
+    let audience = request.audience();
+
+    let now = handler.clock().now();
+    let (issuer, policy) = handler.issuer_and_policy();
+    let subject = recovered(policy.subject(issuer, &subject_token, &subject_type))?
+        .filter(|grant| grant.until > now)
+        .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidGrant))?;
+    let actor = match actor {
+        None => None,
+        Some((token, token_type)) => Some(
+            recovered(policy.actor(issuer, &token, &token_type))?
+                .filter(|grant| grant.until > now)
+                .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidGrant))?,
+        ),
+    };
+
+    if !policy.audience(&client_id, audience.as_deref()) {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidTarget));
+    }
+    let scope = policy
+        .scope(&subject.scope, requested_scope)
+        .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidScope))?;
+
+    let mut grant = Grant {
+        owner_id: subject.owner_id,
+        client_id,
+        scope,
+        redirect_uri: subject.redirect_uri,
+        until: subject.until,
+        extensions: subject.extensions,
+    };
+    if let Some(audience) = audience {
+        let audience = Value::public(Some(audience.into_owned()));
+        grant.extensions.set_raw(AUDIENCE_EXTENSION.to_owned(), audience);
+    }
+    if let Some(actor) = actor {
+        let act = Value::public(Some(actor.owner_id));
+        grant.extensions.set_raw(ACTOR_EXTENSION.to_owned(), act);
+    }
+
+    let scope = grant.scope.to_string();
+    let issued = issuer.issue(grant).map_err(Error::Primitive)?;
+    Ok(ExchangeResponse::new(issued, scope, now))
+}
+
+/// Recover a valid access token of the issuer.
+fn recover_access_token(
+    issuer: &dyn Issuer, token: &str, token_type: &str,
+) -> std::result::Result<Option<Grant>, PrimitiveError> {
+    if token_type != ACCESS_TOKEN_TYPE {
+        return Ok(None);
+    }
+    issuer.recover_token(token)
+}
+
+/// The grant of a recovered token, where a token that vanished in the meantime is rejected.
+fn recovered(recovered: std::result::Result<Option<Grant>, PrimitiveError>) -> Result<Option<Grant>> {
+    match recovered {
+        Ok(grant) => Ok(grant),
+        Err(PrimitiveError::NotFound) => Ok(None),
+        Err(err) => Err(Error::Primitive(err)),
+    }
+}
+
+/// Authenticate the client with its credentials or, for public clients, its id.
+fn authenticate(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<String> {
+    let (client_id, passphrase) = match (request.authorization(), request.client_id()) {
+        // An authenticated client may still name itself, but not as another client.
+        (Some((client_id, passphrase)), Some(named)) if named == client_id => {
+            (client_id, Some(passphrase))
+        }
+        (Some(_), Some(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        (Some((client_id, passphrase)), None) => (client_id, Some(passphrase)),
+        (None, Some(client_id)) => (client_id, None),
+        (None, None) => return Err(Error::unauthorized("basic")),
+    };
+
+    handler
+        .registrar()
+        .check(&client_id, passphrase.as_deref())
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    Ok(client_id.into_owned())
+}
+
+impl ExchangePolicy for AudiencePolicy {
+    fn audience(&mut self, client_id: &str, audience: Option<&str>) -> bool {
+        match audience {
+            None => true,
+            Some(audience) => self
+                .audiences
+                .get(client_id)
+                .is_some_and(|allowed| allowed.contains(audience)),
+        }
+    }
+}
+
+impl AudiencePolicy {
+    /// A policy allowing no audiences.
+    pub fn new() -> Self {
+        AudiencePolicy::default()
+    }
+
+    /// Allow the client to request tokens for the audience.
+    pub fn allow(&mut self, client_id: &str, audience: &str) {
+        self.audiences
+            .entry(client_id.to_owned())
+            .or_default()
+            .insert(audience.to_owned());
+    }
+}
+
+impl ExchangeResponse {
+    fn new(issued: IssuedToken, scope: String, now: DateTime<Utc>) -> Self {
+        ExchangeResponse {
+            access_token: issued.token,
+            issued_token_type: ACCESS_TOKEN_TYPE.to_owned(),
+            token_type: "bearer".to_owned(),
+            expires_in: issued.until.signed_duration_since(now).num_seconds(),
+            scope,
+        }
+    }
+
+    /// Convert the response into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl Error {
+    fn invalid(kind: AccessTokenErrorType) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(kind);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    fn unauthorized(authtype: &str) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidClient);
+        Error::Unauthorized(ErrorDescription { error }, authtype.to_string())
+    }
+
+    /// Get a handle to the description the client will receive.
+    ///
+    /// Some types of this error don't return any description which is represented by a `None`
+    /// result.
+    pub fn description(&mut self) -> Option<&mut AccessTokenError> {
+        match self {
+            Error::Invalid(description) => Some(description.description()),
+            Error::Unauthorized(description, _) => Some(description.description()),
+            Error::Primitive(_) => None,
+        }
+    }
+}
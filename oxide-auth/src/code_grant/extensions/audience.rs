@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use url::Url;
+
+use crate::code_grant::exchange::AUDIENCE_EXTENSION;
+use crate::primitives::grant::{Extensions, Grant, GrantExtension};
+
+/// The audiences a grant was issued for, if it is restricted to any.
+pub fn audience(extensions: &Extensions) -> Option<Vec<&str>> {
+    extensions
+        .public()
+        .find(|(identifier, _)| *identifier == AUDIENCE_EXTENSION)
+        .and_then(|(_, audience)| audience)
+        .map(|audience| audience.split(' ').filter(|aud| !aud.is_empty()).collect())
+}
+
+/// Resource Indicators of [RFC 8707] at the token endpoint.
+///
+/// A token request names the resource server its token is meant for with the `resource`
+/// parameter, an absolute uri without a fragment. This resource is recorded as the audience of the
+/// grant. Clients may also be configured with audiences, which their tokens receive when the
+/// request names no resource and which restrict the resources they may name.
+///
+/// [RFC 8707]: https://tools.ietf.org/html/rfc8707
+#[derive(Clone, Debug, Default)]
+pub struct ResourceIndicators {
+    clients: HashMap<String, Vec<String>>,
+}
+
+/// Restricts a resource server to the tokens issued for it.
+///
+/// Tokens whose grant has an audience are only accepted when this resource is part of it. Tokens
+/// without any audience are accepted unless an audience is required.
+#[derive(Clone, Debug)]
+pub struct AudienceRestriction {
+    resource: String,
+    required: bool,
+}
+
+impl ResourceIndicators {
+    /// Accept any resource and give tokens without a resource no audience.
+    pub fn new() -> Self {
+        ResourceIndicators::default()
+    }
+
+    /// Configure the audiences of a client.
+    pub fn client<I, S>(&mut self, client_id: &str, audiences: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let audiences = audiences.into_iter().map(Into::into).collect();
+        self.clients.insert(client_id.to_owned(), audiences);
+        self
+    }
+
+    /// The audience of a token the client requested for the resource.
+    ///
+    /// The resource must be an absolute uri without a fragment and, if the client is configured
+    /// with audiences, one of them.
+    pub fn audience(
+        &self, client_id: Option<&str>, resource: Option<&str>,
+    ) -> Result<Option<String>, ()> {
+        let configured = client_id.and_then(|client_id| self.clients.get(client_id));
+        let resource = match resource {
+            None => return Ok(configured.map(|audiences| audiences.join(" "))),
+            Some(resource) => resource,
+        };
+
+        let uri = Url::parse(resource).map_err(|_| ())?;
+        if uri.fragment().is_some() {
+            return Err(());
+        }
+
+        match configured {
+            Some(audiences) if !audiences.iter().any(|audience| audience == resource) => Err(()),
+            _ => Ok(Some(resource.to_owned())),
+        }
+    }
+}
+
+impl AudienceRestriction {
+    /// Only accept tokens issued for the resource.
+    pub fn required(resource: &str) -> Self {
+        AudienceRestriction {
+            resource: resource.to_owned(),
+            required: true,
+        }
+    }
+
+    /// Accept tokens issued for the resource and tokens without any audience.
+    pub fn optional(resource: &str) -> Self {
+        AudienceRestriction {
+            resource: resource.to_owned(),
+            required: false,
+        }
+    }
+
+    /// Check the access of the resource with the token of the grant.
+    pub fn protect(&self, grant: &Grant) -> Result<(), ()> {
+        match audience(&grant.extensions) {
+            None if self.required => Err(()),
+            None => Ok(()),
+            Some(audience) if audience.contains(&self.resource.as_str()) => Ok(()),
+            Some(_) => Err(()),
+        }
+    }
+}
+
+impl GrantExtension for ResourceIndicators {
+    fn identifier(&self) -> &'static str {
+        AUDIENCE_EXTENSION
+    }
+}
+
+impl GrantExtension for AudienceRestriction {
+    fn identifier(&self) -> &'static str {
+        AUDIENCE_EXTENSION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::grant::Value;
+    use chrono::Utc;
+
+    const API: &str = "https://api.example.com/";
+
+    #[test]
+    fn resource_audience() {
+        let mut indicators = ResourceIndicators::new();
+        assert_eq!(indicators.audience(Some("Client"), None), Ok(None));
+        assert_eq!(
+            indicators.audience(Some("Client"), Some(API)),
+            Ok(Some(API.to_owned()))
+        );
+        assert!(indicators.audience(None, Some("/relative")).is_err());
+        assert!(indicators
+            .audience(None, Some("https://api.example.com/#part"))
+            .is_err());
+
+        indicators.client("Client", vec![API, "https://other.example.com/"]);
+        assert_eq!(
+            indicators.audience(Some("Client"), None),
+            Ok(Some(
+                "https://api.example.com/ https://other.example.com/".to_owned()
+            ))
+        );
+        assert!(indicators
+            .audience(Some("Client"), Some("https://third.example.com/"))
+            .is_err());
+    }
+
+    #[test]
+    fn restricted_access() {
+        let mut grant = Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com".parse().unwrap(),
+            until: Utc::now(),
+            extensions: Extensions::new(),
+        };
+        assert!(AudienceRestriction::optional(API).protect(&grant).is_ok());
+        assert!(AudienceRestriction::required(API).protect(&grant).is_err());
+
+        let audience = Value::public(Some(format!("{} https://other.example.com/", API)));
+        grant.extensions.set_raw(AUDIENCE_EXTENSION.to_owned(), audience);
+        assert!(AudienceRestriction::required(API).protect(&grant).is_ok());
+        let elsewhere = AudienceRestriction::optional("https://third.example.com/");
+        assert!(elsewhere.protect(&grant).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use serde_json::{Map, Value as JsonValue};
+
+use crate::primitives::grant::{Extensions, Grant, Value};
+
+/// The identifier of the extension data holding the custom claims of a grant.
+pub const CLAIMS_EXTENSION: &str = "claims";
+
+/// Provides the custom claims of new grants, such as the roles of the owner or their tenant.
+///
+/// The enricher is asked for the claims of each grant once its owner consented, or once it is
+/// issued directly as for client credentials and passwords. The claims are stored with the grant,
+/// so that authorizers and issuers persist them, and become additional claims of JWT access tokens
+/// and members of introspection responses.
+pub trait ClaimsEnricher {
+    /// The claims to attach to the grant.
+    ///
+    /// Claims that the owner solicitor already attached at consent take precedence over those of
+    /// the same name.
+    fn claims(&self, grant: &Grant) -> Map<String, JsonValue>;
+}
+
+impl<F> ClaimsEnricher for F
+where
+    F: Fn(&Grant) -> Map<String, JsonValue>,
+{
+    fn claims(&self, grant: &Grant) -> Map<String, JsonValue> {
+        self(grant)
+    }
+}
+
+/// The custom claims of a grant, empty if it has none.
+pub fn claims(extensions: &Extensions) -> Map<String, JsonValue> {
+    extensions
+        .public()
+        .find(|(identifier, _)| *identifier == CLAIMS_EXTENSION)
+        .and_then(|(_, claims)| claims)
+        .and_then(|claims| serde_json::from_str(claims).ok())
+        .unwrap_or_default()
+}
+
+/// Attach custom claims to a grant, replacing those of the same name it already has.
+pub fn attach_claims(extensions: &mut Extensions, claims: Map<String, JsonValue>) {
+    if claims.is_empty() {
+        return;
+    }
+
+    let mut attached = self::claims(extensions);
+    attached.extend(claims);
+    let encoded = serde_json::to_string(&attached).unwrap();
+    extensions.set_raw(CLAIMS_EXTENSION.to_owned(), Value::public(Some(encoded)));
+}
+
+/// Attach the claims of the enricher to a new grant, keeping those it already has.
+pub(crate) fn enrich(enricher: Option<&dyn ClaimsEnricher>, grant: &mut Grant) {
+    let enricher = match enricher {
+        None => return,
+        Some(enricher) => enricher,
+    };
+
+    let mut enriched = enricher.claims(grant);
+    enriched.extend(claims(&grant.extensions));
+    attach_claims(&mut grant.extensions, enriched);
+}
+
+/// The custom claims of a grant, without those named like one of the reserved names.
+pub(crate) fn unreserved(extensions: &Extensions, reserved: &[&str]) -> Map<String, JsonValue> {
+    let mut claims = claims(extensions);
+    for name in reserved {
+        claims.remove(*name);
+    }
+    claims
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "read".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now(),
+            extensions: Extensions::new(),
+        }
+    }
+
+    fn object(value: JsonValue) -> Map<String, JsonValue> {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn attached_and_enriched() {
+        let mut grant = grant();
+        assert!(claims(&grant.extensions).is_empty());
+
+        attach_claims(&mut grant.extensions, object(json!({ "roles": ["admin"] })));
+        let enricher =
+            |grant: &Grant| object(json!({ "roles": [], "tenant": 7, "owner": grant.owner_id }));
+        enrich(Some(&enricher), &mut grant);
+
+        assert_eq!(
+            JsonValue::Object(claims(&grant.extensions)),
+            json!({ "roles": ["admin"], "tenant": 7, "owner": "Owner" })
+        );
+        assert_eq!(
+            JsonValue::Object(unreserved(&grant.extensions, &["owner", "roles"])),
+            json!({ "tenant": 7 })
+        );
+    }
+}
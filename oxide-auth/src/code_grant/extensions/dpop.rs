@@ -0,0 +1,235 @@
+use crate::primitives::grant::Extensions;
+
+#[cfg(feature = "jwt")]
+use std::collections::HashMap;
+#[cfg(feature = "jwt")]
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(feature = "jwt")]
+use chrono::{DateTime, Duration, TimeZone, Utc};
+#[cfg(feature = "jwt")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "jwt")]
+use sha2::{Digest, Sha256};
+#[cfg(feature = "jwt")]
+use url::Url;
+
+#[cfg(feature = "jwt")]
+use crate::primitives::clock::{self, Clock, SharedClock};
+#[cfg(feature = "jwt")]
+use crate::primitives::grant::{Grant, GrantExtension};
+#[cfg(feature = "jwt")]
+use crate::primitives::jwt;
+
+/// The identifier of the extension data binding a grant to the thumbprint of a key.
+pub const DPOP_EXTENSION: &str = "dpop";
+
+/// The media type of DPoP proofs.
+pub const DPOP_PROOF_TYPE: &str = "dpop+jwt";
+
+/// A `DPoP` header together with the request it was sent with.
+///
+/// The method and uri are those of the received request, against which the `htm` and `htu` claims
+/// of the proof are checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DpopProof {
+    /// The compact JWS of the `DPoP` header.
+    pub proof: String,
+
+    /// The http method of the request, such as `POST`.
+    pub method: String,
+
+    /// The absolute uri of the request.
+    pub uri: String,
+}
+
+/// The thumbprint of the key a grant is bound to, if it is bound to one.
+pub fn bound_key(extensions: &Extensions) -> Option<&str> {
+    extensions
+        .public()
+        .find(|(identifier, _)| *identifier == DPOP_EXTENSION)
+        .and_then(|(_, thumbprint)| thumbprint)
+}
+
+/// Demonstrating Proof of Possession of [RFC 9449].
+///
+/// Clients send a proof, a JWT signed with a key of their choice and carrying the public key in
+/// its header, with their token request. The issued token is bound to the thumbprint of that key
+/// and is then only accepted by resources together with a fresh proof signed by the same key. A
+/// stolen token is thus useless without the private key of the client.
+///
+/// The same extension validates proofs at the token endpoint and guards resources, as an addon of
+/// both flows. Tokens bound to a key are only rejected without proof by resources guarded with
+/// this extension.
+///
+/// The identifier of each proof is remembered until the proof becomes too old, to reject replayed
+/// proofs. This memory is not shared between instances, so the extension should be shared between
+/// all flows of a server, for example in an `Arc`.
+///
+/// Only available with the `jwt` feature.
+///
+/// [RFC 9449]: https://tools.ietf.org/html/rfc9449
+#[cfg(feature = "jwt")]
+pub struct Dpop {
+    required: bool,
+    max_age: Duration,
+    seen: Mutex<HashMap<String, DateTime<Utc>>>,
+    clock: SharedClock,
+}
+
+/// The claims of a DPoP proof.
+///
+/// See [RFC 9449, Section 4.2](https://tools.ietf.org/html/rfc9449#section-4.2).
+#[cfg(feature = "jwt")]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ProofClaims {
+    /// A unique identifier of the proof.
+    pub jti: String,
+
+    /// The http method of the request.
+    pub htm: String,
+
+    /// The uri of the request, without query and fragment.
+    pub htu: String,
+
+    /// The time of creation as seconds since the unix epoch.
+    pub iat: i64,
+
+    /// The base64url encoded SHA-256 hash of the access token sent along to resources.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ath: Option<String>,
+}
+
+#[cfg(feature = "jwt")]
+impl Dpop {
+    /// A DPoP extension which requires proofs in all token requests.
+    pub fn required() -> Dpop {
+        Dpop {
+            required: true,
+            max_age: Duration::seconds(60),
+            seen: Mutex::new(HashMap::new()),
+            clock: clock::system(),
+        }
+    }
+
+    /// DPoP extension binding tokens only when the client sends a proof.
+    pub fn optional() -> Dpop {
+        Dpop {
+            required: false,
+            ..Dpop::required()
+        }
+    }
+
+    /// Only accept proofs created within this duration of the current time, 60 seconds by default.
+    pub fn max_age(&mut self, max_age: Duration) {
+        self.max_age = max_age;
+    }
+
+    /// Check the age of proofs against the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
+    }
+
+    /// Validate the proof of a token request and return the thumbprint of its key.
+    ///
+    /// A missing proof is only an error when proofs are required.
+    pub fn verify(&self, proof: Option<&DpopProof>) -> Result<Option<String>, ()> {
+        match proof {
+            None if self.required => Err(()),
+            None => Ok(None),
+            Some(proof) => self.validate(proof, None).map(Some),
+        }
+    }
+
+    /// Check the access of a resource with the token of the grant.
+    ///
+    /// The `authorization` is the complete `Authorization` header. Tokens bound to a key must be
+    /// sent with the `DPoP` scheme and a proof for the token, signed by that key. Other tokens must
+    /// not be sent with the `DPoP` scheme.
+    pub fn protect(
+        &self, authorization: &str, proof: Option<&DpopProof>, grant: &Grant,
+    ) -> Result<(), ()> {
+        let token = match authorization.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case("DPoP") => Some(token),
+            _ => None,
+        };
+
+        match (bound_key(&grant.extensions), token, proof) {
+            (None, None, _) => Ok(()),
+            (Some(bound), Some(token), Some(proof)) => {
+                let thumbprint = self.validate(proof, Some(token))?;
+                if thumbprint == bound {
+                    Ok(())
+                } else {
+                    Err(())
+                }
+            }
+            _ => Err(()),
+        }
+    }
+
+    fn validate(&self, proof: &DpopProof, access_token: Option<&str>) -> Result<String, ()> {
+        let header = jwt::header(&proof.proof)?;
+        if header.typ.as_deref() != Some(DPOP_PROOF_TYPE) {
+            return Err(());
+        }
+
+        let jwk = header.jwk.ok_or(())?;
+        let claims: ProofClaims = jwk.verify(&proof.proof)?;
+        if claims.htm != proof.method || without_query(&claims.htu)? != without_query(&proof.uri)? {
+            return Err(());
+        }
+
+        let ath = access_token.map(|token| b64encode(&Sha256::digest(token.as_bytes())));
+        if claims.ath != ath {
+            return Err(());
+        }
+
+        let issued = Utc.timestamp_opt(claims.iat, 0).single().ok_or(())?;
+        let now = self.clock.now();
+        if issued < now - self.max_age || issued > now + self.max_age {
+            return Err(());
+        }
+
+        if !self.remember(claims.jti, issued + self.max_age, now) {
+            return Err(());
+        }
+
+        jwk.thumbprint()
+    }
+
+    /// Remember the identifier of a proof, false if it was already used.
+    fn remember(&self, jti: String, until: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        seen.retain(|_, expiry| *expiry > now);
+
+        if seen.contains_key(&jti) {
+            return false;
+        }
+
+        seen.insert(jti, until);
+        true
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl GrantExtension for Dpop {
+    fn identifier(&self) -> &'static str {
+        DPOP_EXTENSION
+    }
+}
+
+/// The uri compared with the `htu` claim, ignoring query and fragment.
+#[cfg(feature = "jwt")]
+fn without_query(uri: &str) -> Result<Url, ()> {
+    let mut uri: Url = uri.parse().map_err(|_| ())?;
+    uri.set_query(None);
+    uri.set_fragment(None);
+    Ok(uri)
+}
+
+/// Base 64 encoding without padding
+#[cfg(feature = "jwt")]
+fn b64encode(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
@@ -1,4 +1,24 @@
 //! Provides standard extensions to the OAuth process.
+mod audience;
+mod claims;
+mod dpop;
+mod mtls;
+mod oidc;
 mod pkce;
+mod rar;
 
+pub use self::audience::{audience, AudienceRestriction, ResourceIndicators};
+pub use self::claims::{attach_claims, claims, ClaimsEnricher, CLAIMS_EXTENSION};
+pub(crate) use self::claims::{enrich, unreserved};
+pub use self::dpop::{bound_key, DpopProof, DPOP_EXTENSION, DPOP_PROOF_TYPE};
+#[cfg(feature = "jwt")]
+pub use self::dpop::{Dpop, ProofClaims};
+pub use self::mtls::{bound_certificate, CertificateBinding, MTLS_EXTENSION};
+pub use self::oidc::{
+    authentication, Authentication, AuthenticationRequest, OpenIdConnect, Prompt, StepUp,
+    OFFLINE_ACCESS_SCOPE, OPENID_EXTENSION, OPENID_SCOPE,
+};
 pub use self::pkce::Pkce;
+pub use self::rar::{
+    authorization_details, AuthorizationDetail, RichAuthorization, AUTHORIZATION_DETAILS_EXTENSION,
+};
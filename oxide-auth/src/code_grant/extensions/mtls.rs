@@ -0,0 +1,67 @@
+use crate::primitives::grant::{Extensions, Grant, GrantExtension};
+use crate::primitives::registrar::ClientCertificate;
+
+/// The identifier of the extension data binding a grant to the thumbprint of a certificate.
+pub const MTLS_EXTENSION: &str = "mtls";
+
+/// The thumbprint of the certificate a grant is bound to, if it is bound to one.
+pub fn bound_certificate(extensions: &Extensions) -> Option<&str> {
+    extensions
+        .public()
+        .find(|(identifier, _)| *identifier == MTLS_EXTENSION)
+        .and_then(|(_, thumbprint)| thumbprint)
+}
+
+/// Certificate-bound access tokens of [RFC 8705].
+///
+/// Tokens requested over a mutual TLS connection are bound to the `x5t#S256` thumbprint of the
+/// client certificate. Resources guarded with this extension then only accept such a token over a
+/// connection with the same certificate. Unlike DPoP, the token type stays `bearer`.
+///
+/// The certificate is not required to authenticate the client, a public client may bind its tokens
+/// to a self-signed certificate as well.
+///
+/// [RFC 8705]: https://tools.ietf.org/html/rfc8705
+pub struct CertificateBinding {
+    required: bool,
+}
+
+impl CertificateBinding {
+    /// Bind all tokens, rejecting token requests without a client certificate.
+    pub fn required() -> CertificateBinding {
+        CertificateBinding { required: true }
+    }
+
+    /// Bind tokens only when the client presents a certificate.
+    pub fn optional() -> CertificateBinding {
+        CertificateBinding { required: false }
+    }
+
+    /// The thumbprint to bind the token of a token request to.
+    ///
+    /// A missing certificate is only an error when binding is required.
+    pub fn bind(&self, certificate: Option<&ClientCertificate>) -> Result<Option<String>, ()> {
+        match certificate {
+            None if self.required => Err(()),
+            None => Ok(None),
+            Some(certificate) => Ok(Some(certificate.thumbprint())),
+        }
+    }
+
+    /// Check the access of a resource with the token of the grant.
+    ///
+    /// Tokens bound to a certificate must be presented over a connection with that certificate.
+    pub fn protect(&self, certificate: Option<&ClientCertificate>, grant: &Grant) -> Result<(), ()> {
+        match (bound_certificate(&grant.extensions), certificate) {
+            (None, _) => Ok(()),
+            (Some(bound), Some(certificate)) if certificate.thumbprint() == bound => Ok(()),
+            _ => Err(()),
+        }
+    }
+}
+
+impl GrantExtension for CertificateBinding {
+    fn identifier(&self) -> &'static str {
+        MTLS_EXTENSION
+    }
+}
@@ -0,0 +1,388 @@
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::primitives::grant::{Extensions, Grant, GrantExtension};
+
+pub use crate::primitives::issuer::OFFLINE_ACCESS_SCOPE;
+
+/// The identifier of the extension data holding the authentication of an OpenID Connect grant.
+pub const OPENID_EXTENSION: &str = "openid";
+
+/// The scope value requesting an OpenID Connect authentication.
+pub const OPENID_SCOPE: &str = "openid";
+
+/// The authentication of the end-user, as requested in an OpenID Connect authorization request.
+///
+/// These values become the respective claims of the ID token issued for the grant.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Authentication {
+    /// The `nonce` of the request, echoed to the client in the ID token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+
+    /// The time of the authentication as seconds since the unix epoch.
+    pub auth_time: i64,
+
+    /// The authentication context class, the first of the requested `acr_values`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<String>,
+
+    /// All requested `acr_values`, in the order of preference of the client.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acr_values: Vec<String>,
+
+    /// The requested `max_age` in seconds of the authentication.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<i64>,
+}
+
+/// A value of the `prompt` parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prompt {
+    /// No user interface may be displayed, `none`.
+    None,
+
+    /// The end-user must authenticate again, `login`.
+    Login,
+
+    /// The end-user must be asked for consent again, `consent`.
+    Consent,
+
+    /// The end-user must select one of their accounts, `select_account`.
+    SelectAccount,
+}
+
+/// The parameters of an authorization request about the authentication of the end-user.
+///
+/// These are the `prompt`, `max_age`, `login_hint` and `id_token_hint` parameters of [OpenID
+/// Connect Core, Section 3.1.2.1]. They are parsed for every authorization request and presented
+/// to the owner solicitor, which decides whether a login or consent page must be shown.
+///
+/// [OpenID Connect Core, Section 3.1.2.1]: https://openid.net/specs/openid-connect-core-1_0.html#AuthRequest
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AuthenticationRequest {
+    /// The requested prompts, empty if the server may decide.
+    pub prompt: Vec<Prompt>,
+
+    /// The maximum age in seconds of the authentication of the end-user.
+    pub max_age: Option<i64>,
+
+    /// A hint to the identifier the end-user might use to log in.
+    pub login_hint: Option<String>,
+
+    /// An ID token previously issued to the client, hinting at the current session.
+    ///
+    /// The token is passed on as it was received, its signature has not been verified.
+    pub id_token_hint: Option<String>,
+}
+
+/// The authentication of an OpenID Connect grant, if there was one.
+///
+/// Returns `None` as well when the stored authentication can not be decoded.
+pub fn authentication(extensions: &Extensions) -> Option<Authentication> {
+    extensions
+        .public()
+        .find(|(identifier, _)| *identifier == OPENID_EXTENSION)
+        .and_then(|(_, authentication)| authentication)
+        .and_then(|authentication| serde_json::from_str(authentication).ok())
+}
+
+/// Recognizes OpenID Connect authentication requests.
+///
+/// Requests with the `openid` scope are authentication requests of [OpenID Connect Core]. Their
+/// `nonce`, the time of the authorization, the requested `acr_values` and `max_age` are stored in
+/// the grant. An endpoint with an `IdTokenSigner` then issues an ID token alongside the access
+/// token of the grant, carrying these values as its claims.
+///
+/// [OpenID Connect Core]: https://openid.net/specs/openid-connect-core-1_0.html
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OpenIdConnect;
+
+impl OpenIdConnect {
+    /// Create the extension.
+    pub fn new() -> Self {
+        OpenIdConnect
+    }
+
+    /// Collect the authentication of a request with the given parameters.
+    ///
+    /// Requests without the `openid` scope are plain OAuth requests and have no authentication.
+    /// A `max_age` that is not a non-negative number is ignored.
+    pub fn authenticate(
+        &self, scope: Option<&str>, nonce: Option<&str>, acr_values: Option<&str>, max_age: Option<&str>,
+    ) -> Option<Authentication> {
+        let mut scopes = scope.into_iter().flat_map(|scope| scope.split(' '));
+        if !scopes.any(|scope| scope == OPENID_SCOPE) {
+            return None;
+        }
+
+        let acr_values: Vec<String> = acr_values
+            .into_iter()
+            .flat_map(|values| values.split(' '))
+            .filter(|value| !value.is_empty())
+            .map(str::to_owned)
+            .collect();
+        Some(Authentication {
+            nonce: nonce.map(str::to_owned),
+            auth_time: Utc::now().timestamp(),
+            acr: acr_values.first().cloned(),
+            acr_values,
+            max_age: max_age
+                .and_then(|max_age| max_age.parse().ok())
+                .filter(|max_age| *max_age >= 0),
+        })
+    }
+
+    /// Encode the authentication for storage in a grant.
+    pub fn encode(authentication: &Authentication) -> String {
+        serde_json::to_string(authentication).unwrap()
+    }
+}
+
+impl Prompt {
+    /// Parse a single value of the parameter.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "none" => Some(Prompt::None),
+            "login" => Some(Prompt::Login),
+            "consent" => Some(Prompt::Consent),
+            "select_account" => Some(Prompt::SelectAccount),
+            _ => None,
+        }
+    }
+}
+
+impl AuthenticationRequest {
+    /// Parse the parameters of a request.
+    ///
+    /// Unknown prompts are ignored. The prompt `none` can not be combined with other prompts and
+    /// `max_age` must be a non-negative number, otherwise the request is invalid.
+    pub fn parse(
+        prompt: Option<&str>, max_age: Option<&str>, login_hint: Option<&str>,
+        id_token_hint: Option<&str>,
+    ) -> Result<Self, ()> {
+        let prompt: Vec<_> = prompt
+            .into_iter()
+            .flat_map(|prompt| prompt.split(' '))
+            .filter_map(Prompt::parse)
+            .collect();
+        if prompt.contains(&Prompt::None) && prompt.len() > 1 {
+            return Err(());
+        }
+
+        let max_age = match max_age.map(str::parse::<i64>) {
+            None => None,
+            Some(Ok(max_age)) if max_age >= 0 => Some(max_age),
+            Some(_) => return Err(()),
+        };
+
+        Ok(AuthenticationRequest {
+            prompt,
+            max_age,
+            login_hint: login_hint.map(str::to_owned),
+            id_token_hint: id_token_hint.map(str::to_owned),
+        })
+    }
+
+    /// Whether the client requested the prompt.
+    pub fn prompts(&self, prompt: Prompt) -> bool {
+        self.prompt.contains(&prompt)
+    }
+
+    /// Whether no user interface may be shown, for `prompt=none`.
+    ///
+    /// When the owner would need to log in or consent, the request must fail with an error such as
+    /// `login_required` or `consent_required` instead.
+    pub fn is_silent(&self) -> bool {
+        self.prompts(Prompt::None)
+    }
+
+    /// Whether the end-user, who last authenticated at `auth_time`, must log in again.
+    ///
+    /// This is the case for `prompt=login` and for authentications older than `max_age`.
+    pub fn requires_login(&self, auth_time: i64) -> bool {
+        let expired = match self.max_age {
+            Some(max_age) => Utc::now().timestamp() - auth_time > max_age,
+            None => false,
+        };
+        self.prompts(Prompt::Login) || expired
+    }
+}
+
+/// Demands a minimum authentication context class of the owner for accessing a resource.
+///
+/// The known classes are ordered from the weakest to the strongest, any class at least as strong
+/// as the minimum is accepted. Tokens whose grant has no authentication, a weaker or an unknown
+/// class, or an authentication older than the maximum age are refused with the error
+/// `insufficient_user_authentication` of [RFC 9470]. The client can then ask the owner to
+/// authenticate again with the `acr_values` and `max_age` of the challenge.
+///
+/// [RFC 9470]: https://tools.ietf.org/html/rfc9470
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepUp {
+    accepted: Vec<String>,
+    max_age: Option<Duration>,
+}
+
+impl StepUp {
+    /// Accept the `minimum` and all stronger classes of the ordered `levels`.
+    ///
+    /// When the minimum is not one of the levels, no class is accepted.
+    pub fn new<I, S>(levels: I, minimum: &str) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        StepUp {
+            accepted: levels
+                .into_iter()
+                .map(Into::into)
+                .skip_while(|level| level != minimum)
+                .collect(),
+            max_age: None,
+        }
+    }
+
+    /// Additionally require the authentication to be no older than `max_age`.
+    pub fn max_age(&mut self, max_age: Duration) {
+        self.max_age = Some(max_age);
+    }
+
+    /// Whether the authentication of the grant satisfies the requirement.
+    pub fn satisfied_by(&self, grant: &Grant) -> bool {
+        let authentication = match authentication(&grant.extensions) {
+            Some(authentication) => authentication,
+            None => return false,
+        };
+
+        let recent = match self.max_age {
+            Some(max_age) => Utc::now().timestamp() - authentication.auth_time <= max_age.num_seconds(),
+            None => true,
+        };
+        let strong = authentication.acr.is_some_and(|acr| self.accepted.contains(&acr));
+        recent && strong
+    }
+
+    /// The accepted classes as a space separated list, for the `acr_values` of a challenge.
+    pub fn acr_values(&self) -> String {
+        self.accepted.join(" ")
+    }
+
+    /// The required maximum age in seconds, for the `max_age` of a challenge.
+    pub fn max_age_seconds(&self) -> Option<i64> {
+        self.max_age.map(|max_age| max_age.num_seconds())
+    }
+}
+
+impl GrantExtension for OpenIdConnect {
+    fn identifier(&self) -> &'static str {
+        OPENID_EXTENSION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::grant::Value;
+
+    #[test]
+    fn authentication_requests() {
+        let oidc = OpenIdConnect::new();
+        assert_eq!(oidc.authenticate(None, Some("n-0S6"), None, None), None);
+        assert_eq!(
+            oidc.authenticate(Some("profile email"), Some("n-0S6"), None, None),
+            None
+        );
+
+        let authentication = oidc
+            .authenticate(
+                Some("openid profile"),
+                Some("n-0S6"),
+                Some("urn:mace:incommon:iap:silver loa-1"),
+                Some("300"),
+            )
+            .unwrap();
+        assert_eq!(authentication.nonce.as_deref(), Some("n-0S6"));
+        assert_eq!(
+            authentication.acr.as_deref(),
+            Some("urn:mace:incommon:iap:silver")
+        );
+        assert_eq!(
+            authentication.acr_values,
+            vec!["urn:mace:incommon:iap:silver", "loa-1"]
+        );
+        assert_eq!(authentication.max_age, Some(300));
+
+        let encoded = OpenIdConnect::encode(&authentication);
+        assert_eq!(
+            serde_json::from_str::<Authentication>(&encoded).unwrap(),
+            authentication
+        );
+    }
+
+    #[test]
+    fn authentication_parameters() {
+        let request = AuthenticationRequest::parse(
+            Some("login consent unknown"),
+            Some("300"),
+            Some("user@example.com"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(request.prompt, vec![Prompt::Login, Prompt::Consent]);
+        assert_eq!(request.login_hint.as_deref(), Some("user@example.com"));
+        assert!(!request.is_silent());
+        assert!(request.requires_login(Utc::now().timestamp()));
+
+        let request = AuthenticationRequest::parse(Some("none"), Some("300"), None, None).unwrap();
+        assert!(request.is_silent());
+        assert!(!request.requires_login(Utc::now().timestamp()));
+        assert!(request.requires_login(Utc::now().timestamp() - 600));
+
+        assert_eq!(
+            AuthenticationRequest::parse(None, None, None, None),
+            Ok(AuthenticationRequest::default())
+        );
+        assert!(AuthenticationRequest::parse(Some("none login"), None, None, None).is_err());
+        assert!(AuthenticationRequest::parse(None, Some("-1"), None, None).is_err());
+        assert!(AuthenticationRequest::parse(None, Some("soon"), None, None).is_err());
+    }
+
+    #[test]
+    fn step_up() {
+        let grant = |authentication: Option<Authentication>| {
+            let mut extensions = Extensions::new();
+            if let Some(authentication) = authentication {
+                let encoded = OpenIdConnect::encode(&authentication);
+                extensions.set(&OpenIdConnect, Value::public(Some(encoded)));
+            }
+            Grant {
+                owner_id: "Owner".to_owned(),
+                client_id: "Client".to_owned(),
+                scope: "openid".parse().unwrap(),
+                redirect_uri: "https://example.com".parse().unwrap(),
+                until: Utc::now(),
+                extensions,
+            }
+        };
+        let authenticated = |acr: &str, age: i64| {
+            let mut authentication = OpenIdConnect::new()
+                .authenticate(Some("openid"), None, Some(acr), None)
+                .unwrap();
+            authentication.auth_time -= age;
+            grant(Some(authentication))
+        };
+
+        let mut step_up = StepUp::new(vec!["pwd", "otp", "hwk"], "otp");
+        assert_eq!(step_up.acr_values(), "otp hwk");
+        assert!(step_up.satisfied_by(&authenticated("hwk", 600)));
+        assert!(!step_up.satisfied_by(&authenticated("pwd", 0)));
+        assert!(!step_up.satisfied_by(&authenticated("unknown", 0)));
+        assert!(!step_up.satisfied_by(&grant(None)));
+
+        step_up.max_age(Duration::minutes(5));
+        assert_eq!(step_up.max_age_seconds(), Some(300));
+        assert!(step_up.satisfied_by(&authenticated("otp", 0)));
+        assert!(!step_up.satisfied_by(&authenticated("otp", 600)));
+    }
+}
@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 
 use crate::primitives::grant::{GrantExtension, Value};
 
@@ -27,12 +28,20 @@ use subtle::ConstantTimeEq;
 ///
 /// Support for the `plain` method is OPTIONAL and must be turned on explicitely.
 ///
+/// Besides the endpoint wide requirement, PKCE can be required from or waived for individual
+/// clients, for example to require it from all public clients as recommended by OAuth 2.1. Clients
+/// required to use PKCE in this way must use the `S256` method, even when `plain` is allowed.
+///
 /// [RFC 7636]: https://tools.ietf.org/html/rfc7636
 pub struct Pkce {
     required: bool,
     allow_plain: bool,
+    clients: HashMap<String, bool>,
+    required_for: Option<ClientPredicate>,
 }
 
+type ClientPredicate = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
 enum Method {
     Plain(String),
     Sha256(String),
@@ -44,6 +53,8 @@ impl Pkce {
         Pkce {
             required: true,
             allow_plain: false,
+            clients: HashMap::new(),
+            required_for: None,
         }
     }
 
@@ -52,6 +63,8 @@ impl Pkce {
         Pkce {
             required: false,
             allow_plain: false,
+            clients: HashMap::new(),
+            required_for: None,
         }
     }
 
@@ -61,6 +74,56 @@ impl Pkce {
         self.allow_plain = true;
     }
 
+    /// Require the client to use PKCE with the `S256` method, regardless of the endpoint policy.
+    pub fn require_client(&mut self, client_id: &str) {
+        self.clients.insert(client_id.to_string(), true);
+    }
+
+    /// Do not require PKCE from the client, even when it is otherwise required.
+    ///
+    /// A challenge the client makes anyways is still checked.
+    pub fn exempt_client(&mut self, client_id: &str) {
+        self.clients.insert(client_id.to_string(), false);
+    }
+
+    /// Require PKCE with the `S256` method from all clients for which the predicate holds.
+    ///
+    /// This is meant to identify public clients, for example by looking them up in the registrar.
+    /// Clients required or exempted individually are not checked against the predicate.
+    pub fn require_for<F>(&mut self, predicate: F)
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.required_for = Some(Box::new(predicate));
+    }
+
+    /// The code challenge methods this extension accepts, `S256` and possibly `plain`.
+    pub fn code_challenge_methods(&self) -> Vec<&'static str> {
+        if self.allow_plain {
+            vec!["S256", "plain"]
+        } else {
+            vec!["S256"]
+        }
+    }
+
+    /// Whether the client must use PKCE and whether it may use the `plain` method.
+    fn policy_for(&self, client_id: Option<&str>) -> (bool, bool) {
+        let client_required = client_id.and_then(|client_id| {
+            self.clients.get(client_id).cloned().or_else(|| {
+                self.required_for
+                    .as_ref()
+                    .filter(|predicate| predicate(client_id))
+                    .map(|_| true)
+            })
+        });
+
+        match client_required {
+            Some(true) => (true, false),
+            Some(false) => (false, self.allow_plain),
+            None => (self.required, self.allow_plain),
+        }
+    }
+
     /// Create the encoded method for proposed method and challenge.
     ///
     /// The method defaults to `plain` when none is given, effectively offering increased
@@ -73,17 +136,33 @@ impl Pkce {
     /// a SHA256 methods the results would not be quite as severe but still bad practice.
     pub fn challenge(
         &self, method: Option<Cow<str>>, challenge: Option<Cow<str>>,
+    ) -> Result<Option<Value>, ()> {
+        self.challenge_with(self.policy_for(None), method, challenge)
+    }
+
+    /// Create the encoded method for a challenge of a specific client.
+    ///
+    /// Like `Pkce::challenge` but applies the requirement configured for the client, if any.
+    pub fn client_challenge(
+        &self, client_id: &str, method: Option<Cow<str>>, challenge: Option<Cow<str>>,
+    ) -> Result<Option<Value>, ()> {
+        self.challenge_with(self.policy_for(Some(client_id)), method, challenge)
+    }
+
+    fn challenge_with(
+        &self, (required, allow_plain): (bool, bool), method: Option<Cow<str>>,
+        challenge: Option<Cow<str>>,
     ) -> Result<Option<Value>, ()> {
         let method = method.unwrap_or(Cow::Borrowed("plain"));
 
         let challenge = match challenge {
-            None if self.required => return Err(()),
+            None if required => return Err(()),
             None => return Ok(None),
             Some(challenge) => challenge,
         };
 
         let method = Method::from_parameter(method, challenge)?;
-        let method = method.assert_supported_method(self.allow_plain)?;
+        let method = method.assert_supported_method(allow_plain)?;
 
         Ok(Some(Value::private(Some(method.encode()))))
     }
@@ -97,8 +176,23 @@ impl Pkce {
     /// When a challenge was agreed upon but no verifier is present, this method will return an
     /// error.
     pub fn verify(&self, method: Option<Value>, verifier: Option<Cow<str>>) -> Result<(), ()> {
+        self.verify_with(self.policy_for(None).0, method, verifier)
+    }
+
+    /// Verify against the encoded challenge of a specific client.
+    ///
+    /// Like `Pkce::verify` but applies the requirement configured for the client, if any.
+    pub fn client_verify(
+        &self, client_id: &str, method: Option<Value>, verifier: Option<Cow<str>>,
+    ) -> Result<(), ()> {
+        self.verify_with(self.policy_for(Some(client_id)).0, method, verifier)
+    }
+
+    fn verify_with(
+        &self, required: bool, method: Option<Value>, verifier: Option<Cow<str>>,
+    ) -> Result<(), ()> {
         let (method, verifier) = match (method, verifier) {
-            (None, _) if self.required => return Err(()),
+            (None, _) if required => return Err(()),
             (None, _) => return Ok(()),
             // An internal saved method but no verifier
             (Some(_), None) => return Err(()),
@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::primitives::grant::{Extensions, GrantExtension};
+
+/// The identifier of the extension data holding the authorization details of a grant.
+pub const AUTHORIZATION_DETAILS_EXTENSION: &str = "authorization_details";
+
+/// A single entry of the `authorization_details` parameter.
+///
+/// Only the `type` is required, the meaning of all other members depends on it. See
+/// [RFC 9396, Section 2](https://tools.ietf.org/html/rfc9396#section-2).
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AuthorizationDetail {
+    /// The type of authorization detail, such as `payment_initiation`.
+    #[serde(rename = "type")]
+    pub detail_type: String,
+
+    /// The locations of the resources or resource servers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locations: Vec<String>,
+
+    /// The kinds of actions to take at the resource.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<String>,
+
+    /// The kinds of data being requested from the resource.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub datatypes: Vec<String>,
+
+    /// A specific resource available at the api.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+
+    /// The types or levels of privilege being requested at the resource.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub privileges: Vec<String>,
+
+    /// All other members, as defined by the type.
+    #[serde(flatten)]
+    pub fields: Map<String, JsonValue>,
+}
+
+/// The authorization details of a grant, if the client requested any.
+///
+/// Returns `None` as well when the stored details can not be decoded.
+pub fn authorization_details(extensions: &Extensions) -> Option<Vec<AuthorizationDetail>> {
+    extensions
+        .public()
+        .find(|(identifier, _)| *identifier == AUTHORIZATION_DETAILS_EXTENSION)
+        .and_then(|(_, details)| details)
+        .and_then(|details| serde_json::from_str(details).ok())
+}
+
+/// Rich Authorization Requests of [RFC 9396].
+///
+/// Clients describe fine-grained permissions in the json array of the `authorization_details`
+/// parameter of their authorization request. The details are validated, presented to the owner
+/// along with the scope and stored in the grant, from which they are echoed in token and
+/// introspection responses. A token request may name a subset of the granted details to narrow
+/// down the details of its token.
+///
+/// Only the types of details the server understands are accepted.
+///
+/// [RFC 9396]: https://tools.ietf.org/html/rfc9396
+pub struct RichAuthorization {
+    types: HashSet<String>,
+}
+
+impl RichAuthorization {
+    /// Accept authorization details of the given types.
+    pub fn new<I, S>(types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        RichAuthorization {
+            types: types.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Parse and validate the `authorization_details` parameter of a request.
+    ///
+    /// The parameter must be a non-empty json array of objects whose types are all accepted.
+    pub fn parse(&self, parameter: Option<&str>) -> Result<Option<Vec<AuthorizationDetail>>, ()> {
+        let parameter = match parameter {
+            None => return Ok(None),
+            Some(parameter) => parameter,
+        };
+
+        let details: Vec<AuthorizationDetail> = serde_json::from_str(parameter).map_err(|_| ())?;
+        if details.is_empty()
+            || details
+                .iter()
+                .any(|detail| !self.types.contains(&detail.detail_type))
+        {
+            return Err(());
+        }
+
+        Ok(Some(details))
+    }
+
+    /// Choose the details of a token from those of its grant.
+    ///
+    /// Without requested details the token receives all granted details. Otherwise each requested
+    /// detail must have been granted exactly as requested.
+    pub fn narrow(
+        &self, granted: Option<Vec<AuthorizationDetail>>, requested: Option<&str>,
+    ) -> Result<Option<Vec<AuthorizationDetail>>, ()> {
+        let requested = match self.parse(requested)? {
+            None => return Ok(granted),
+            Some(requested) => requested,
+        };
+
+        let granted = granted.ok_or(())?;
+        if requested.iter().all(|detail| granted.contains(detail)) {
+            Ok(Some(requested))
+        } else {
+            Err(())
+        }
+    }
+
+    /// Encode details for storage in a grant.
+    pub fn encode(details: &[AuthorizationDetail]) -> String {
+        serde_json::to_string(details).unwrap()
+    }
+}
+
+impl GrantExtension for RichAuthorization {
+    fn identifier(&self) -> &'static str {
+        AUTHORIZATION_DETAILS_EXTENSION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DETAILS: &str = r#"[{
+        "type": "payment_initiation",
+        "actions": ["initiate"],
+        "locations": ["https://example.com/payments"],
+        "instructedAmount": {"currency": "EUR", "amount": "123.50"}
+    }]"#;
+
+    #[test]
+    fn parse_details() {
+        let rar = RichAuthorization::new(vec!["payment_initiation"]);
+        let details = rar.parse(Some(DETAILS)).unwrap().unwrap();
+        assert_eq!(details[0].detail_type, "payment_initiation");
+        assert_eq!(details[0].actions, vec!["initiate".to_owned()]);
+        assert!(details[0].fields.contains_key("instructedAmount"));
+
+        let encoded = RichAuthorization::encode(&details);
+        assert_eq!(rar.parse(Some(&encoded)), Ok(Some(details)));
+        assert_eq!(rar.parse(None), Ok(None));
+    }
+
+    #[test]
+    fn reject_details() {
+        let rar = RichAuthorization::new(vec!["account_information"]);
+        assert!(rar.parse(Some(DETAILS)).is_err());
+        assert!(rar.parse(Some("[]")).is_err());
+        assert!(rar.parse(Some(r#"{"type": "account_information"}"#)).is_err());
+        assert!(rar.parse(Some(r#"[{"actions": ["read"]}]"#)).is_err());
+        assert!(rar.parse(Some("not json")).is_err());
+    }
+
+    #[test]
+    fn narrow_details() {
+        let rar = RichAuthorization::new(vec!["payment_initiation", "account_information"]);
+        let granted = rar.parse(Some(DETAILS)).unwrap();
+        assert_eq!(rar.narrow(granted.clone(), None), Ok(granted.clone()));
+        assert_eq!(rar.narrow(granted.clone(), Some(DETAILS)), Ok(granted.clone()));
+        let other = r#"[{"type": "account_information"}]"#;
+        assert!(rar.narrow(granted, Some(other)).is_err());
+        assert!(rar.narrow(None, Some(DETAILS)).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! Provides the handling for Token Introspection requests.
+//!
+//! Resource servers that can not validate opaque tokens on their own ask the authorization server
+//! about them, as specified in [RFC 7662]. The resource server authenticates either as a client
+//! with its credentials or with a bearer token of its own. The response describes the grant of an
+//! active token and only states that any other token is inactive.
+//!
+//! Since any token can be introspected, only resource servers may do so. A client authenticating
+//! with its credentials must be one of them, and a bearer token must carry the introspection scope.
+//! Ordinary clients are refused, they could otherwise scan for valid tokens.
+//!
+//! [RFC 7662]: https://tools.ietf.org/html/rfc7662
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::code_grant::accesstoken::ErrorDescription;
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::code_grant::extensions::{authentication, authorization_details, unreserved, AuthorizationDetail};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::primitives::grant::Grant;
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::issuer::Issuer;
+use crate::primitives::registrar::{Registrar, RegistrarError};
+use crate::primitives::scope::Scope;
+
+/// The scope a bearer token needs to introspect tokens, unless the endpoint requires another.
+pub const INTROSPECTION_SCOPE: &str = "introspection";
+
+/// Required content of an introspection request.
+pub trait Request {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+
+    /// The token of a bearer authorization header.
+    fn bearer(&self) -> Option<Cow<str>>;
+
+    /// The token to introspect.
+    fn token(&self) -> Option<Cow<str>>;
+
+    /// Optionally hints at the kind of token, `access_token` or `refresh_token`.
+    fn token_type_hint(&self) -> Option<Cow<str>>;
+
+    /// Retrieve an additional parameter used in an extension
+    fn extension(&self, key: &str) -> Option<Cow<str>>;
+}
+
+/// Required functionality to respond to introspection requests.
+pub trait Endpoint {
+    /// Authenticate resource servers using client credentials.
+    fn registrar(&self) -> &dyn Registrar;
+
+    /// Recover the introspected token and bearer tokens of resource servers.
+    fn issuer(&mut self) -> &mut dyn Issuer;
+
+    /// Whether the client may introspect tokens after authenticating with its credentials.
+    ///
+    /// The default implementation allows no client.
+    fn resource_server(&self, _client_id: &str) -> bool {
+        false
+    }
+
+    /// The scope the bearer token of a resource server must include.
+    ///
+    /// The default implementation requires the `introspection` scope.
+    fn introspection_scope(&self) -> Scope {
+        INTROSPECTION_SCOPE.parse().unwrap()
+    }
+
+    /// The clock deciding whether a token has expired.
+    ///
+    /// The system clock is the default implementation.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+}
+
+/// The description of an introspected token.
+///
+/// See [RFC 7662, Section 2.2](https://tools.ietf.org/html/rfc7662#section-2.2). All other members
+/// are omitted for inactive tokens.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct IntrospectionResponse {
+    /// If the token is currently valid.
+    pub active: bool,
+
+    /// The space separated scopes of the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+
+    /// The client the token was issued to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+
+    /// The resource owner who authorized the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+
+    /// The expiry as seconds since the unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+
+    /// The type of the token, `bearer` for access tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+
+    /// The authorization details granted to the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorization_details: Option<Vec<AuthorizationDetail>>,
+
+    /// The authentication context class of the authentication of the owner.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<String>,
+
+    /// The time of the authentication of the owner as seconds since the unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>,
+
+    /// The custom claims of the grant, such as the roles of the owner.
+    #[serde(flatten)]
+    pub claims: Map<String, Value>,
+}
+
+/// The members of a response that custom claims of a grant can not replace.
+const RESERVED_MEMBERS: &[&str] = &[
+    "active",
+    "scope",
+    "client_id",
+    "username",
+    "token_type",
+    "exp",
+    "iat",
+    "nbf",
+    "sub",
+    "aud",
+    "iss",
+    "jti",
+    "authorization_details",
+    "acr",
+    "auth_time",
+];
+
+/// Defines actions for the response to an introspection request.
+#[derive(Clone)]
+pub enum Error {
+    /// The request did not name a token or was otherwise malformed.
+    Invalid(ErrorDescription),
+
+    /// The resource server did not properly authorize itself.
+    Unauthorized(ErrorDescription, String),
+
+    /// An underlying primitive operation did not complete successfully.
+    Primitive(PrimitiveError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Describe the token of the request to the authenticated resource server.
+pub fn introspect(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<IntrospectionResponse> {
+    if !request.valid() {
+        return Err(Error::invalid());
+    }
+
+    authenticate(handler, request)?;
+    let token = request.token().ok_or_else(Error::invalid)?;
+
+    let now = handler.clock().now();
+    let issuer = handler.issuer();
+    let refresh_first = request.token_type_hint().as_deref() == Some("refresh_token");
+    // The hint only decides the order of lookups, any token is still found.
+    let recovered = if refresh_first {
+        match issuer.recover_refresh(&token) {
+            Ok(None) => issuer.recover_token(&token).map(|grant| grant.map(|g| (g, true))),
+            found => found.map(|grant| grant.map(|g| (g, false))),
+        }
+    } else {
+        match issuer.recover_token(&token) {
+            Ok(None) => issuer
+                .recover_refresh(&token)
+                .map(|grant| grant.map(|g| (g, false))),
+            found => found.map(|grant| grant.map(|g| (g, true))),
+        }
+    };
+
+    match recovered {
+        Ok(Some((grant, access))) if grant.until > now => {
+            Ok(IntrospectionResponse::active(grant, access))
+        }
+        // A token that vanished in the meantime is no longer active either.
+        Ok(_) | Err(PrimitiveError::NotFound) => Ok(IntrospectionResponse::inactive()),
+        Err(err) => Err(Error::Primitive(err)),
+    }
+}
+
+/// Authenticate the resource server with client credentials or with an active bearer token.
+fn authenticate(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<()> {
+    if let Some((client_id, passphrase)) = request.authorization() {
+        handler
+            .registrar()
+            .check(&client_id, Some(&passphrase))
+            .map_err(|err| match err {
+                RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+                RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                    Error::unauthorized("basic")
+                }
+            })?;
+        // Valid credentials of an ordinary client do not suffice.
+        if !handler.resource_server(&client_id) {
+            return Err(Error::unauthorized("basic"));
+        }
+        return Ok(());
+    }
+
+    let bearer = request.bearer().ok_or_else(|| Error::unauthorized("basic"))?;
+    let now = handler.clock().now();
+    let scope = handler.introspection_scope();
+    match handler.issuer().recover_token(&bearer) {
+        Ok(Some(grant)) if grant.until > now && scope.allow_access(&grant.scope) => Ok(()),
+        Ok(_) | Err(PrimitiveError::NotFound) => Err(Error::unauthorized("Bearer")),
+        Err(err) => Err(Error::Primitive(err)),
+    }
+}
+
+impl IntrospectionResponse {
+    /// The response for any token that is not currently valid.
+    pub fn inactive() -> Self {
+        IntrospectionResponse::default()
+    }
+
+    /// Describe the grant of a valid access or refresh token.
+    pub fn active(grant: Grant, access_token: bool) -> Self {
+        let authentication = authentication(&grant.extensions);
+        let claims = unreserved(&grant.extensions, RESERVED_MEMBERS);
+        IntrospectionResponse {
+            active: true,
+            scope: Some(grant.scope.to_string()),
+            client_id: Some(grant.client_id),
+            sub: Some(grant.owner_id),
+            exp: Some(grant.until.timestamp()),
+            token_type: if access_token {
+                Some("bearer".to_owned())
+            } else {
+                None
+            },
+            authorization_details: authorization_details(&grant.extensions),
+            acr: authentication.as_ref().and_then(|auth| auth.acr.clone()),
+            auth_time: authentication.map(|auth| auth.auth_time),
+            claims,
+        }
+    }
+
+    /// Convert the response into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl Error {
+    /// Create an error for a malformed request.
+    pub fn invalid() -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidRequest);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    /// Create an error for a resource server that did not authenticate with the scheme.
+    pub fn unauthorized(authtype: &str) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidClient);
+        Error::Unauthorized(ErrorDescription { error }, authtype.to_string())
+    }
+
+    /// Get a handle to the description the client will receive.
+    ///
+    /// Some types of this error don't return any description which is represented by a `None`
+    /// result.
+    pub fn description(&mut self) -> Option<&mut AccessTokenError> {
+        match self {
+            Error::Invalid(description) => Some(description.description()),
+            Error::Unauthorized(description, _) => Some(description.description()),
+            Error::Primitive(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, Utc};
+
+    use crate::primitives::grant::Extensions;
+
+    #[test]
+    fn inactive_is_terse() {
+        let json = IntrospectionResponse::inactive().to_json();
+        assert_eq!(json, r#"{"active":false}"#);
+
+        let grant = Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        };
+        let active = IntrospectionResponse::active(grant, false);
+        assert_eq!(active.sub.as_deref(), Some("Owner"));
+        assert_eq!(active.token_type, None);
+    }
+}
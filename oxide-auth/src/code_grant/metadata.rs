@@ -0,0 +1,598 @@
+//! Provides the metadata document of an authorization server.
+//!
+//! Clients discover the endpoints and capabilities of an authorization server from the document
+//! served at `/.well-known/oauth-authorization-server`, as specified in [RFC 8414]. The document is
+//! assembled once with a [`MetadataBuilder`] from the configuration of the server:
+//!
+//! ```
+//! # use oxide_auth::code_grant::extensions::Pkce;
+//! use oxide_auth::code_grant::metadata::MetadataBuilder;
+//!
+//! let metadata = MetadataBuilder::new("https://auth.example.com")
+//!     .authorization_endpoint("/authorize")
+//!     .token_endpoint("/token")
+//!     .grant_types(vec!["authorization_code", "refresh_token"])
+//!     .pkce(&Pkce::required())
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(metadata.token_endpoint.as_deref(), Some("https://auth.example.com/token"));
+//! ```
+//!
+//! OpenID Providers serve the same kind of document at `/.well-known/openid-configuration`, as
+//! specified in [OpenID Connect Discovery]. Such a document is started with
+//! [`MetadataBuilder::openid`] and additionally describes the ID tokens and claims of the provider.
+//!
+//! [RFC 8414]: https://tools.ietf.org/html/rfc8414
+//! [OpenID Connect Discovery]: https://openid.net/specs/openid-connect-discovery-1_0.html
+//! [`MetadataBuilder`]: struct.MetadataBuilder.html
+//! [`MetadataBuilder::openid`]: struct.MetadataBuilder.html#method.openid
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use url::{ParseError, Url};
+
+use crate::code_grant::extensions::{Pkce, OPENID_SCOPE};
+#[cfg(feature = "jwt")]
+use crate::primitives::jwt::IdTokenSigner;
+
+/// The path below the issuer at which the metadata document is served.
+pub const WELL_KNOWN_PATH: &str = "/.well-known/oauth-authorization-server";
+
+/// The path below the issuer at which the configuration of an OpenID Provider is served.
+pub const OPENID_CONFIGURATION_PATH: &str = "/.well-known/openid-configuration";
+
+/// The metadata of an authorization server.
+///
+/// See [RFC 8414, Section 2](https://tools.ietf.org/html/rfc8414#section-2) and the registry of
+/// metadata established there for the members of later specifications.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ServerMetadata {
+    /// The issuer identifier of the server, an https url without query or fragment.
+    pub issuer: String,
+
+    /// The url of the authorization endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization_endpoint: Option<String>,
+
+    /// The url of the token endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_endpoint: Option<String>,
+
+    /// The url of the JSON Web Key Set of the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks_uri: Option<String>,
+
+    /// The url of the dynamic client registration endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_endpoint: Option<String>,
+
+    /// The scopes clients may request.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes_supported: Vec<String>,
+
+    /// The values of the `response_type` parameter the server supports.
+    pub response_types_supported: Vec<String>,
+
+    /// The values of the `response_mode` parameter the server supports.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub response_modes_supported: Vec<String>,
+
+    /// The grant types the server supports.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grant_types_supported: Vec<String>,
+
+    /// The methods of client authentication at the token endpoint.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+
+    /// The url of human-readable documentation of the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_documentation: Option<String>,
+
+    /// The url of the revocation endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation_endpoint: Option<String>,
+
+    /// The url of the introspection endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub introspection_endpoint: Option<String>,
+
+    /// The PKCE code challenge methods the server supports.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub code_challenge_methods_supported: Vec<String>,
+
+    /// The url of the device authorization endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_authorization_endpoint: Option<String>,
+
+    /// The url of the pushed authorization request endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pushed_authorization_request_endpoint: Option<String>,
+
+    /// Whether the server only accepts authorization requests that were pushed.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub require_pushed_authorization_requests: bool,
+
+    /// The signature algorithms accepted for request objects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub request_object_signing_alg_values_supported: Vec<String>,
+
+    /// The signature algorithms of signed authorization responses.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authorization_signing_alg_values_supported: Vec<String>,
+
+    /// The signature algorithms accepted for DPoP proofs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dpop_signing_alg_values_supported: Vec<String>,
+
+    /// Whether the server binds access tokens to the certificates of mutual TLS connections.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub tls_client_certificate_bound_access_tokens: bool,
+
+    /// The types of authorization details the server accepts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authorization_details_types_supported: Vec<String>,
+
+    /// The url of the UserInfo endpoint of an OpenID Provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub userinfo_endpoint: Option<String>,
+
+    /// The url at which clients of an OpenID Provider log owners out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_session_endpoint: Option<String>,
+
+    /// Whether an OpenID Provider notifies clients of logouts with back-channel logout tokens.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub backchannel_logout_supported: bool,
+
+    /// Whether the back-channel logout tokens of an OpenID Provider include the session id.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub backchannel_logout_session_supported: bool,
+
+    /// The url of the backchannel authentication endpoint of an OpenID Provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backchannel_authentication_endpoint: Option<String>,
+
+    /// The modes, `poll` or `ping`, in which clients of backchannel authentication receive tokens.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backchannel_token_delivery_modes_supported: Vec<String>,
+
+    /// The authentication context classes an OpenID Provider supports.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acr_values_supported: Vec<String>,
+
+    /// The types of subject identifiers, `public` or `pairwise`, of an OpenID Provider.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subject_types_supported: Vec<String>,
+
+    /// The signature algorithms of ID tokens.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub id_token_signing_alg_values_supported: Vec<String>,
+
+    /// The claims about end-users an OpenID Provider may be able to supply.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub claims_supported: Vec<String>,
+
+    /// All other members of the document.
+    #[serde(flatten)]
+    pub additional: Map<String, Value>,
+}
+
+/// Assembles the metadata document of a server.
+///
+/// Endpoints are given as urls relative to the issuer, like links, so that an absolute path such
+/// as `/token` names the endpoint on the host of the issuer. The only response type is `code`
+/// unless configured otherwise.
+pub struct MetadataBuilder {
+    metadata: ServerMetadata,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn strings<I, S>(values: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    values.into_iter().map(Into::into).collect()
+}
+
+impl ServerMetadata {
+    /// Convert the document into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl MetadataBuilder {
+    /// Start the document of the server with the issuer identifier.
+    pub fn new(issuer: &str) -> Self {
+        MetadataBuilder {
+            metadata: ServerMetadata {
+                issuer: issuer.to_owned(),
+                response_types_supported: vec!["code".to_owned()],
+                ..ServerMetadata::default()
+            },
+        }
+    }
+
+    /// Start the configuration of an OpenID Provider with the issuer identifier.
+    ///
+    /// The provider supports the `openid` scope and `public` subject identifiers unless configured
+    /// otherwise. The signature algorithm of its ID tokens is required by OpenID Connect Discovery
+    /// and should be set with `id_token_signing_algs`.
+    pub fn openid(issuer: &str) -> Self {
+        MetadataBuilder::new(issuer)
+            .scopes(vec![OPENID_SCOPE])
+            .subject_types(vec!["public"])
+    }
+
+    /// Set the authorization endpoint.
+    pub fn authorization_endpoint(mut self, url: &str) -> Self {
+        self.metadata.authorization_endpoint = Some(url.to_owned());
+        self
+    }
+
+    /// Set the token endpoint.
+    pub fn token_endpoint(mut self, url: &str) -> Self {
+        self.metadata.token_endpoint = Some(url.to_owned());
+        self
+    }
+
+    /// Set the location of the JSON Web Key Set of the server.
+    pub fn jwks_uri(mut self, url: &str) -> Self {
+        self.metadata.jwks_uri = Some(url.to_owned());
+        self
+    }
+
+    /// Set the dynamic client registration endpoint.
+    pub fn registration_endpoint(mut self, url: &str) -> Self {
+        self.metadata.registration_endpoint = Some(url.to_owned());
+        self
+    }
+
+    /// Set the revocation endpoint.
+    pub fn revocation_endpoint(mut self, url: &str) -> Self {
+        self.metadata.revocation_endpoint = Some(url.to_owned());
+        self
+    }
+
+    /// Set the introspection endpoint.
+    pub fn introspection_endpoint(mut self, url: &str) -> Self {
+        self.metadata.introspection_endpoint = Some(url.to_owned());
+        self
+    }
+
+    /// Set the device authorization endpoint.
+    pub fn device_authorization_endpoint(mut self, url: &str) -> Self {
+        self.metadata.device_authorization_endpoint = Some(url.to_owned());
+        self
+    }
+
+    /// Set the pushed authorization request endpoint.
+    ///
+    /// When `required`, clients are told that authorization requests must be pushed.
+    pub fn pushed_authorization_request_endpoint(mut self, url: &str, required: bool) -> Self {
+        self.metadata.pushed_authorization_request_endpoint = Some(url.to_owned());
+        self.metadata.require_pushed_authorization_requests = required;
+        self
+    }
+
+    /// Set the location of the documentation of the server.
+    pub fn service_documentation(mut self, url: &str) -> Self {
+        self.metadata.service_documentation = Some(url.to_owned());
+        self
+    }
+
+    /// Set the scopes clients may request.
+    pub fn scopes<I, S>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.scopes_supported = strings(scopes);
+        self
+    }
+
+    /// Set the response types, replacing the default `code`.
+    pub fn response_types<I, S>(mut self, response_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.response_types_supported = strings(response_types);
+        self
+    }
+
+    /// Set the response modes.
+    pub fn response_modes<I, S>(mut self, response_modes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.response_modes_supported = strings(response_modes);
+        self
+    }
+
+    /// Set the grant types, such as `authorization_code` and `refresh_token`.
+    pub fn grant_types<I, S>(mut self, grant_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.grant_types_supported = strings(grant_types);
+        self
+    }
+
+    /// Set the client authentication methods of the token endpoint.
+    pub fn token_endpoint_auth_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.token_endpoint_auth_methods_supported = strings(methods);
+        self
+    }
+
+    /// Set the code challenge methods accepted by the PKCE extension.
+    pub fn pkce(mut self, pkce: &Pkce) -> Self {
+        self.metadata.code_challenge_methods_supported = strings(pkce.code_challenge_methods());
+        self
+    }
+
+    /// Set the signature algorithms accepted for request objects.
+    pub fn request_object_signing_algs<I, S>(mut self, algorithms: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.request_object_signing_alg_values_supported = strings(algorithms);
+        self
+    }
+
+    /// Set the signature algorithms of signed authorization responses.
+    pub fn authorization_signing_algs<I, S>(mut self, algorithms: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.authorization_signing_alg_values_supported = strings(algorithms);
+        self
+    }
+
+    /// Set the signature algorithms accepted for DPoP proofs.
+    pub fn dpop_signing_algs<I, S>(mut self, algorithms: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.dpop_signing_alg_values_supported = strings(algorithms);
+        self
+    }
+
+    /// Announce that access tokens are bound to the certificates of mutual TLS connections.
+    pub fn certificate_bound_tokens(mut self) -> Self {
+        self.metadata.tls_client_certificate_bound_access_tokens = true;
+        self
+    }
+
+    /// Set the types of authorization details the server accepts.
+    pub fn authorization_details_types<I, S>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.authorization_details_types_supported = strings(types);
+        self
+    }
+
+    /// Set the UserInfo endpoint.
+    pub fn userinfo_endpoint(mut self, url: &str) -> Self {
+        self.metadata.userinfo_endpoint = Some(url.to_owned());
+        self
+    }
+
+    /// Set the end session endpoint.
+    pub fn end_session_endpoint(mut self, url: &str) -> Self {
+        self.metadata.end_session_endpoint = Some(url.to_owned());
+        self
+    }
+
+    /// Announce back-channel logout tokens, which include the session id.
+    pub fn back_channel_logout(mut self) -> Self {
+        self.metadata.backchannel_logout_supported = true;
+        self.metadata.backchannel_logout_session_supported = true;
+        self
+    }
+
+    /// Set the backchannel authentication endpoint and the supported token delivery modes.
+    pub fn backchannel_authentication_endpoint<I, S>(mut self, url: &str, modes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.backchannel_authentication_endpoint = Some(url.to_owned());
+        self.metadata.backchannel_token_delivery_modes_supported = strings(modes);
+        self
+    }
+
+    /// Set the authentication context classes.
+    pub fn acr_values<I, S>(mut self, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.acr_values_supported = strings(values);
+        self
+    }
+
+    /// Set the types of subject identifiers.
+    pub fn subject_types<I, S>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.subject_types_supported = strings(types);
+        self
+    }
+
+    /// Set the signature algorithms of ID tokens.
+    pub fn id_token_signing_algs<I, S>(mut self, algorithms: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.id_token_signing_alg_values_supported = strings(algorithms);
+        self
+    }
+
+    /// Set the signature algorithm of ID tokens to that of the signer.
+    #[cfg(feature = "jwt")]
+    pub fn id_token_signer(self, signer: &IdTokenSigner) -> Self {
+        let key = signer.signing_key();
+        self.id_token_signing_algs(key.map(|key| key.algorithm().name()))
+    }
+
+    /// Set the claims about end-users.
+    pub fn claims<I, S>(mut self, claims: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.claims_supported = strings(claims);
+        self
+    }
+
+    /// Add another member to the document.
+    pub fn field(mut self, name: &str, value: Value) -> Self {
+        self.metadata.additional.insert(name.to_owned(), value);
+        self
+    }
+
+    /// Finish the document, resolving all endpoints against the issuer.
+    pub fn build(mut self) -> Result<ServerMetadata, ParseError> {
+        let issuer: Url = self.metadata.issuer.parse()?;
+        let metadata = &mut self.metadata;
+        for url in [
+            &mut metadata.authorization_endpoint,
+            &mut metadata.token_endpoint,
+            &mut metadata.jwks_uri,
+            &mut metadata.registration_endpoint,
+            &mut metadata.service_documentation,
+            &mut metadata.revocation_endpoint,
+            &mut metadata.introspection_endpoint,
+            &mut metadata.device_authorization_endpoint,
+            &mut metadata.pushed_authorization_request_endpoint,
+            &mut metadata.userinfo_endpoint,
+            &mut metadata.end_session_endpoint,
+            &mut metadata.backchannel_authentication_endpoint,
+        ]
+        .iter_mut()
+        .filter_map(|url| url.as_mut())
+        {
+            *url = issuer.join(url)?.into();
+        }
+
+        Ok(self.metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_resolved() {
+        let metadata = MetadataBuilder::new("https://auth.example.com/tenant/")
+            .authorization_endpoint("authorize")
+            .token_endpoint("/token")
+            .jwks_uri("https://keys.example.com/jwks.json")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            metadata.authorization_endpoint.as_deref(),
+            Some("https://auth.example.com/tenant/authorize")
+        );
+        assert_eq!(
+            metadata.token_endpoint.as_deref(),
+            Some("https://auth.example.com/token")
+        );
+        assert_eq!(
+            metadata.jwks_uri.as_deref(),
+            Some("https://keys.example.com/jwks.json")
+        );
+        assert!(MetadataBuilder::new("not an issuer").build().is_err());
+    }
+
+    #[test]
+    fn document_members() {
+        let mut pkce = Pkce::optional();
+        pkce.allow_plain();
+        let metadata = MetadataBuilder::new("https://auth.example.com")
+            .pkce(&pkce)
+            .pushed_authorization_request_endpoint("/par", true)
+            .field("op_policy_uri", "https://example.com/policy".into())
+            .build()
+            .unwrap();
+
+        let json: Value = serde_json::from_str(&metadata.to_json()).unwrap();
+        assert_eq!(json["issuer"], "https://auth.example.com");
+        assert_eq!(json["response_types_supported"], serde_json::json!(["code"]));
+        assert_eq!(
+            json["code_challenge_methods_supported"],
+            serde_json::json!(["S256", "plain"])
+        );
+        assert_eq!(json["require_pushed_authorization_requests"], true);
+        assert_eq!(json["op_policy_uri"], "https://example.com/policy");
+        // Empty and unset members are left out.
+        assert!(json.get("grant_types_supported").is_none());
+        assert!(json.get("tls_client_certificate_bound_access_tokens").is_none());
+
+        let parsed: ServerMetadata = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, metadata);
+    }
+
+    #[test]
+    fn openid_configuration() {
+        let metadata = MetadataBuilder::openid("https://auth.example.com")
+            .userinfo_endpoint("/userinfo")
+            .end_session_endpoint("/logout")
+            .back_channel_logout()
+            .backchannel_authentication_endpoint("/bc-authorize", vec!["poll", "ping"])
+            .id_token_signing_algs(vec!["EdDSA"])
+            .claims(vec!["sub", "name"])
+            .build()
+            .unwrap();
+
+        assert_eq!(metadata.scopes_supported, vec!["openid".to_owned()]);
+        assert_eq!(metadata.subject_types_supported, vec!["public".to_owned()]);
+        assert_eq!(
+            metadata.userinfo_endpoint.as_deref(),
+            Some("https://auth.example.com/userinfo")
+        );
+        assert_eq!(
+            metadata.end_session_endpoint.as_deref(),
+            Some("https://auth.example.com/logout")
+        );
+
+        let json: Value = serde_json::from_str(&metadata.to_json()).unwrap();
+        assert_eq!(
+            json["id_token_signing_alg_values_supported"],
+            serde_json::json!(["EdDSA"])
+        );
+        assert_eq!(json["claims_supported"], serde_json::json!(["sub", "name"]));
+        assert_eq!(json["backchannel_logout_session_supported"], true);
+        assert_eq!(
+            json["backchannel_authentication_endpoint"],
+            "https://auth.example.com/bc-authorize"
+        );
+        assert_eq!(
+            json["backchannel_token_delivery_modes_supported"],
+            serde_json::json!(["poll", "ping"])
+        );
+        assert!(json.get("acr_values_supported").is_none());
+    }
+}
@@ -28,9 +28,23 @@
 //! [`Endpoint`]: ../endpoint/trait.Endpoint.html
 
 pub mod accesstoken;
+#[cfg(feature = "jwt")]
+pub mod assertion;
 pub mod authorization;
+pub mod ciba;
 pub mod client_credentials;
+pub mod custom_grant;
+pub mod device;
+#[cfg(feature = "jwt")]
+pub mod end_session;
 pub mod error;
+pub mod exchange;
 pub mod extensions;
+pub mod introspection;
+pub mod metadata;
+pub mod par;
+#[cfg(feature = "password")]
+pub mod password;
 pub mod refresh;
 pub mod resource;
+pub mod revocation;
@@ -0,0 +1,203 @@
+//! Provides the handling for Pushed Authorization Requests.
+//!
+//! Clients post the parameters of an authorization request to the authorization server before
+//! redirecting the user-agent, as specified in [RFC 9126]. The request is checked like at the
+//! token endpoint and stored in a [`RequestUriStore`], and the client receives a `request_uri`
+//! which it then passes to the authorization endpoint in place of the parameters.
+//!
+//! [RFC 9126]: https://tools.ietf.org/html/rfc9126
+//! [`RequestUriStore`]: ../../primitives/request_uri/trait.RequestUriStore.html
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::code_grant::accesstoken::ErrorDescription;
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::primitives::registrar::{ClientUrl, ExactUrl, Registrar, RegistrarError};
+use crate::primitives::request_uri::{PushedRequest, RequestUriStore};
+
+/// Required content of a pushed authorization request.
+pub trait Request {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+
+    /// The client_id, required for public clients.
+    fn client_id(&self) -> Option<Cow<str>>;
+
+    /// The redirect uri of the authorization request.
+    fn redirect_uri(&self) -> Option<Cow<str>>;
+
+    /// Optionally specifies the requested scope.
+    fn scope(&self) -> Option<Cow<str>>;
+
+    /// All parameters of the request, which are stored for the authorization endpoint.
+    fn parameters(&self) -> HashMap<String, String>;
+}
+
+/// Required functionality to respond to pushed authorization requests.
+pub trait Endpoint {
+    /// Authenticate the requesting client and check its redirect uri and scope.
+    fn registrar(&self) -> &dyn Registrar;
+
+    /// The store keeping the pushed parameters.
+    fn request_uris(&mut self) -> &mut dyn RequestUriStore;
+}
+
+/// The response to a successful pushed authorization request.
+///
+/// See [RFC 9126, Section 2.2](https://tools.ietf.org/html/rfc9126#section-2.2).
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PushedResponse {
+    /// The uri to pass to the authorization endpoint.
+    pub request_uri: String,
+
+    /// The lifetime in seconds of the uri.
+    pub expires_in: i64,
+}
+
+/// Defines actions for the response to a pushed authorization request.
+#[derive(Clone)]
+pub enum Error {
+    /// The pushed parameters were invalid.
+    Invalid(ErrorDescription),
+
+    /// The client did not properly authorize itself.
+    Unauthorized(ErrorDescription, String),
+
+    /// An underlying primitive operation did not complete successfully.
+    Primitive,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Store the parameters of an authorization request and create a `request_uri` for them.
+///
+/// The client is authenticated and its redirect uri and scope are checked with the registrar
+/// before the request is stored. Client credentials in the body are not stored, and the
+/// `client_id` parameter always names the authenticated client.
+pub fn push_authorization(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<PushedResponse> {
+    if !request.valid() {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    let client_id = authenticate(handler, request)?;
+    let mut parameters = request.parameters();
+    // A pushed request can not itself refer to another request.
+    if parameters.contains_key("request_uri") {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    let redirect_uri = match request.redirect_uri() {
+        None => None,
+        Some(uri) => match uri.parse::<ExactUrl>() {
+            Ok(uri) => Some(Cow::Owned(uri)),
+            Err(_) => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        },
+    };
+    let scope = match request.scope().map(|scope| scope.as_ref().parse()) {
+        None => None,
+        Some(Err(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidScope)),
+        Some(Ok(scope)) => Some(scope),
+    };
+
+    let registrar = handler.registrar();
+    let bound = registrar
+        .bound_redirect(ClientUrl {
+            client_id: Cow::Borrowed(&client_id),
+            redirect_uri,
+        })
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive,
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::invalid(AccessTokenErrorType::InvalidRequest)
+            }
+        })?;
+    registrar.negotiate(bound, scope).map_err(|err| match err {
+        RegistrarError::PrimitiveError => Error::Primitive,
+        RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+            Error::invalid(AccessTokenErrorType::InvalidScope)
+        }
+    })?;
+
+    parameters.remove("client_secret");
+    parameters.insert("client_id".to_owned(), client_id.clone());
+    let pushed = handler
+        .request_uris()
+        .push(PushedRequest {
+            client_id,
+            parameters,
+        })
+        .map_err(|()| Error::Primitive)?;
+
+    Ok(PushedResponse {
+        request_uri: pushed.request_uri,
+        expires_in: pushed.until.signed_duration_since(Utc::now()).num_seconds(),
+    })
+}
+
+/// Authenticate the client with its credentials or, for public clients, its id.
+fn authenticate(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<String> {
+    let (client_id, passphrase) = match (request.authorization(), request.client_id()) {
+        // An authenticated client may still name itself, but not as another client.
+        (Some((client_id, passphrase)), Some(named)) if named == client_id => {
+            (client_id, Some(passphrase))
+        }
+        (Some(_), Some(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        (Some((client_id, passphrase)), None) => (client_id, Some(passphrase)),
+        (None, Some(client_id)) => (client_id, None),
+        (None, None) => return Err(Error::unauthorized("basic")),
+    };
+
+    handler
+        .registrar()
+        .check(&client_id, passphrase.as_deref())
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive,
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    Ok(client_id.into_owned())
+}
+
+impl PushedResponse {
+    /// Convert the response into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl Error {
+    fn invalid(kind: AccessTokenErrorType) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(kind);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    fn unauthorized(authtype: &str) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidClient);
+        Error::Unauthorized(ErrorDescription { error }, authtype.to_string())
+    }
+
+    /// Get a handle to the description the client will receive.
+    ///
+    /// Some types of this error don't return any description which is represented by a `None`
+    /// result.
+    pub fn description(&mut self) -> Option<&mut AccessTokenError> {
+        match self {
+            Error::Invalid(description) => Some(description.description()),
+            Error::Unauthorized(description, _) => Some(description.description()),
+            Error::Primitive => None,
+        }
+    }
+}
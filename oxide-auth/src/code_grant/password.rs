@@ -0,0 +1,369 @@
+//! Provides the handling for Resource Owner Password Credentials requests.
+//!
+//! The client collects the username and password of the resource owner and trades them for an
+//! access token directly, as specified in [RFC 6749, Section 4.3]. This grant is only meant for
+//! legacy first-party clients that can not perform redirects and has been removed in OAuth 2.1,
+//! hence it is only available with the `password` feature. The credentials are checked by a
+//! [`CredentialValidator`] of the integrator and repeated failures for a username are throttled.
+//!
+//! [RFC 6749, Section 4.3]: https://tools.ietf.org/html/rfc6749#section-4.3
+//! [`CredentialValidator`]: trait.CredentialValidator.html
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::code_grant::accesstoken::{response_members, BearerToken, ErrorDescription, TokenResponseHook};
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::code_grant::extensions::{enrich, ClaimsEnricher};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::issuer::Issuer;
+use crate::primitives::registrar::{ClientUrl, Registrar, RegistrarError};
+
+/// The `grant_type` of password requests.
+pub const PASSWORD_GRANT_TYPE: &str = "password";
+
+/// Required content of a password request.
+pub trait Request {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+
+    /// The client_id, required for public clients.
+    fn client_id(&self) -> Option<Cow<str>>;
+
+    /// Valid requests have this set to `PASSWORD_GRANT_TYPE`.
+    fn grant_type(&self) -> Option<Cow<str>>;
+
+    /// The username of the resource owner.
+    fn username(&self) -> Option<Cow<str>>;
+
+    /// The password of the resource owner.
+    fn password(&self) -> Option<Cow<str>>;
+
+    /// Optionally specifies the requested scope.
+    fn scope(&self) -> Option<Cow<str>>;
+
+    /// Retrieve an additional parameter used in an extension
+    fn extension(&self, key: &str) -> Option<Cow<str>>;
+}
+
+/// Required functionality to respond to password requests.
+pub trait Endpoint {
+    /// Authenticate the requesting client and negotiate the scope.
+    fn registrar(&self) -> &dyn Registrar;
+
+    /// The issuer of the access token.
+    fn issuer(&mut self) -> &mut dyn Issuer;
+
+    /// The validator of the owner credentials.
+    fn validator(&mut self) -> &mut dyn CredentialValidator;
+
+    /// The throttle of failed attempts.
+    fn throttle(&self) -> &Throttle;
+
+    /// The clock of the lockout and of the lifetime of the grant.
+    ///
+    /// The system clock is the default implementation.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+
+    /// Adds members to the token response.
+    ///
+    /// Returning `None` is the default implementation, the response has only the standard members.
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        None
+    }
+
+    /// Attaches custom claims to the grant of the token.
+    ///
+    /// Returning `None` is the default implementation.
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        None
+    }
+}
+
+/// Checks the credentials of resource owners.
+///
+/// Implement this against the user store of the application, such as a database or an LDAP
+/// directory. Closures taking the username and the password can be used directly.
+pub trait CredentialValidator {
+    /// Check the password of a user, returning the id of the owner if it is correct.
+    ///
+    /// Returning `Ok(None)` rejects the credentials, an error is an internal failure.
+    fn validate(&mut self, username: &str, password: &str) -> std::result::Result<Option<String>, ()>;
+}
+
+/// Limits the failed attempts for each username.
+///
+/// After `max_failures` consecutive wrong passwords, the username is locked out for the duration
+/// of the lockout, counted from the last failure. Requests for a locked username are rejected
+/// without consulting the validator. A correct password resets the count.
+///
+/// Clones share their record of failures, so that a throttle can be handed to each new flow.
+#[derive(Clone, Debug)]
+pub struct Throttle {
+    max_failures: u32,
+    lockout: Duration,
+    failures: Arc<Mutex<HashMap<String, Failures>>>,
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: DateTime<Utc>,
+}
+
+/// Defines actions for the response to a password request.
+#[derive(Clone)]
+pub enum Error {
+    /// The request or the owner credentials were invalid.
+    Invalid(ErrorDescription),
+
+    /// The client did not properly authorize itself.
+    Unauthorized(ErrorDescription, String),
+
+    /// An underlying primitive operation did not complete successfully.
+    Primitive(PrimitiveError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Issue a token for the owner whose credentials are presented.
+///
+/// The client is authenticated with its credentials or, for public clients, with its id. The scope
+/// is negotiated with the registrar as for other grants. A refresh token is included if the issuer
+/// creates one.
+pub fn password(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<BearerToken> {
+    if !request.valid() {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    match request.grant_type() {
+        Some(ref cow) if cow == PASSWORD_GRANT_TYPE => (),
+        None => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        Some(_) => return Err(Error::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+    }
+
+    let client_id = authenticate(handler, request)?;
+    let (username, password) = match (request.username(), request.password()) {
+        (Some(username), Some(password)) => (username, password),
+        _ => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+    };
+    let scope = match request.scope().map(|scope| scope.as_ref().parse()) {
+        None => None,
+        Some(Err(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidScope)),
+        Some(Ok(scope)) => Some(scope),
+    };
+
+    let registrar = handler.registrar();
+    let bound_client = registrar
+        .bound_redirect(ClientUrl {
+            client_id: Cow::Borrowed(&client_id),
+            redirect_uri: None,
+        })
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    let pre_grant = registrar
+        .negotiate(bound_client, scope)
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::invalid(AccessTokenErrorType::InvalidScope)
+            }
+        })?;
+
+    let now = handler.clock().now();
+    if handler.throttle().is_locked(&username, now) {
+        let mut error = Error::invalid(AccessTokenErrorType::InvalidGrant);
+        if let Some(description) = error.description() {
+            description.explain("Too many failed attempts, try again later");
+        }
+        return Err(error);
+    }
+
+    let owner_id = match handler.validator().validate(&username, &password) {
+        Err(()) => return Err(Error::Primitive(PrimitiveError::Invariant)),
+        Ok(None) => {
+            handler.throttle().fail(&username, now);
+            return Err(Error::invalid(AccessTokenErrorType::InvalidGrant));
+        }
+        Ok(Some(owner_id)) => {
+            handler.throttle().succeed(&username);
+            owner_id
+        }
+    };
+
+    let scope = pre_grant.scope.to_string();
+    let mut grant = Grant {
+        owner_id,
+        client_id: pre_grant.client_id,
+        scope: pre_grant.scope,
+        redirect_uri: pre_grant.redirect_uri.into_url(),
+        until: now + Duration::minutes(10),
+        extensions: Extensions::new(),
+    };
+    enrich(handler.claims_enricher(), &mut grant);
+    let members = response_members(handler.token_response_hook(), &grant);
+    let token = handler.issuer().issue(grant).map_err(Error::Primitive)?;
+
+    Ok(BearerToken(token, scope, None, None, members))
+}
+
+/// Authenticate the client with its credentials or, for public clients, its id.
+fn authenticate(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<String> {
+    let (client_id, passphrase) = match (request.authorization(), request.client_id()) {
+        // An authenticated client may still name itself, but not as another client.
+        (Some((client_id, passphrase)), Some(named)) if named == client_id => {
+            (client_id, Some(passphrase))
+        }
+        (Some(_), Some(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        (Some((client_id, passphrase)), None) => (client_id, Some(passphrase)),
+        (None, Some(client_id)) => (client_id, None),
+        (None, None) => return Err(Error::unauthorized("basic")),
+    };
+
+    handler
+        .registrar()
+        .check(&client_id, passphrase.as_deref())
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    Ok(client_id.into_owned())
+}
+
+impl<F> CredentialValidator for F
+where
+    F: FnMut(&str, &str) -> Option<String>,
+{
+    fn validate(&mut self, username: &str, password: &str) -> std::result::Result<Option<String>, ()> {
+        Ok(self(username, password))
+    }
+}
+
+impl Throttle {
+    /// Lock out usernames for `lockout` after `max_failures` consecutive failures.
+    pub fn new(max_failures: u32, lockout: Duration) -> Self {
+        Throttle {
+            max_failures,
+            lockout,
+            failures: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Check if further attempts for the username are rejected at the time `now`.
+    pub fn is_locked(&self, username: &str, now: DateTime<Utc>) -> bool {
+        match self.failures.lock().unwrap().get(username) {
+            None => false,
+            Some(failures) => failures.count >= self.max_failures && failures.last + self.lockout > now,
+        }
+    }
+
+    /// Record a failed attempt for the username at the time `now`.
+    ///
+    /// Failures older than the lockout are forgotten.
+    pub fn fail(&self, username: &str, now: DateTime<Utc>) {
+        let mut failures = self.failures.lock().unwrap();
+        let failures = failures
+            .entry(username.to_owned())
+            .or_insert(Failures { count: 0, last: now });
+        if failures.last + self.lockout <= now {
+            failures.count = 0;
+        }
+        failures.count += 1;
+        failures.last = now;
+    }
+
+    /// Record a successful attempt, resetting the failures of the username.
+    pub fn succeed(&self, username: &str) {
+        self.failures.lock().unwrap().remove(username);
+    }
+}
+
+impl Default for Throttle {
+    /// Allow five consecutive failures before locking out a username for fifteen minutes.
+    fn default() -> Self {
+        Throttle::new(5, Duration::minutes(15))
+    }
+}
+
+impl Error {
+    fn invalid(kind: AccessTokenErrorType) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(kind);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    fn unauthorized(authtype: &str) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidClient);
+        Error::Unauthorized(ErrorDescription { error }, authtype.to_string())
+    }
+
+    /// Get a handle to the description the client will receive.
+    ///
+    /// Some types of this error don't return any description which is represented by a `None`
+    /// result.
+    pub fn description(&mut self) -> Option<&mut AccessTokenError> {
+        match self {
+            Error::Invalid(description) => Some(description.description()),
+            Error::Unauthorized(description, _) => Some(description.description()),
+            Error::Primitive(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::clock::ManualClock;
+
+    #[test]
+    fn throttle_failures() {
+        let now = Utc::now();
+        let throttle = Throttle::new(2, Duration::minutes(5));
+        throttle.fail("alice", now);
+        assert!(!throttle.is_locked("alice", now));
+        throttle.clone().fail("alice", now);
+        assert!(throttle.is_locked("alice", now));
+        assert!(!throttle.is_locked("bob", now));
+
+        throttle.succeed("alice");
+        assert!(!throttle.is_locked("alice", now));
+
+        // Without a lockout, failures are forgotten immediately.
+        let throttle = Throttle::new(1, Duration::zero());
+        throttle.fail("alice", now);
+        throttle.fail("alice", now);
+        assert!(!throttle.is_locked("alice", now));
+    }
+
+    #[test]
+    fn throttle_lockout_ends() {
+        let clock = ManualClock::new(Utc::now());
+        let throttle = Throttle::new(1, Duration::minutes(5));
+        throttle.fail("alice", clock.now());
+        assert!(throttle.is_locked("alice", clock.now()));
+
+        clock.advance(Duration::minutes(4));
+        assert!(throttle.is_locked("alice", clock.now()));
+        clock.advance(Duration::minutes(1));
+        assert!(!throttle.is_locked("alice", clock.now()));
+    }
+}
@@ -2,15 +2,19 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Map;
 
 use crate::code_grant::{
-    accesstoken::TokenResponse,
+    accesstoken::{response_json, response_members, TokenResponse, TokenResponseHook},
     error::{AccessTokenError, AccessTokenErrorType},
+    extensions::{authorization_details, bound_key, AuthorizationDetail},
 };
+use crate::primitives::clock::{Clock, SystemClock};
 use crate::primitives::grant::Grant;
-use crate::primitives::issuer::{RefreshedToken, Issuer};
-use crate::primitives::registrar::{Registrar, RegistrarError};
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::issuer::{RefreshedToken, Issuer, TokenType};
+use crate::primitives::registrar::{ClientCertificate, Registrar, RegistrarError};
 
 /// Required content of a refresh request.
 ///
@@ -38,6 +42,13 @@ pub trait Request {
 
     /// Retrieve an additional parameter used in an extension
     fn extension(&self, key: &str) -> Option<Cow<str>>;
+
+    /// The certificate of the client in a mutual TLS connection, if any.
+    ///
+    /// Clients without other credentials are authenticated with this certificate.
+    fn client_certificate(&self) -> Option<&ClientCertificate> {
+        None
+    }
 }
 
 /// The specific endpoint trait for refreshing.
@@ -57,11 +68,31 @@ pub trait Endpoint {
 
     /// Recover and test the provided refresh token then issue new tokens.
     fn issuer(&mut self) -> &mut dyn Issuer;
+
+    /// The clock deciding whether the refresh token has expired.
+    ///
+    /// The system clock is the default implementation.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+
+    /// Adds members to the token response.
+    ///
+    /// Returning `None` is the default implementation, the response has only the standard members.
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        None
+    }
 }
 
-/// Represents a bearer token, optional refresh token and the associated scope for serialization.
+/// Represents a bearer token, optional refresh token and the associated scope, authorization
+/// details and additional members for serialization.
 #[derive(Debug)]
-pub struct BearerToken(RefreshedToken, String);
+pub struct BearerToken(
+    RefreshedToken,
+    String,
+    Option<Vec<AuthorizationDetail>>,
+    Map<String, serde_json::Value>,
+);
 
 /// An ongoing refresh request.
 ///
@@ -89,6 +120,7 @@ pub struct BearerToken(RefreshedToken, String);
 #[derive(Debug)]
 pub struct Refresh {
     state: RefreshState,
+    now: DateTime<Utc>,
 }
 
 /// Inner state machine for refreshing.
@@ -208,7 +240,7 @@ pub enum Error {
     ///
     /// This is expected to occur with some endpoints. See `PrimitiveError` for
     /// more details on when this is returned.
-    Primitive,
+    Primitive(PrimitiveError),
 }
 
 /// Simple wrapper around RefreshError.
@@ -230,9 +262,16 @@ impl Refresh {
     pub fn new(request: &dyn Request) -> Self {
         Refresh {
             state: initialize(request).unwrap_or_else(RefreshState::Err),
+            now: Utc::now(),
         }
     }
 
+    /// Check the expiry of the refresh token against the time `now`.
+    pub fn at(mut self, now: DateTime<Utc>) -> Self {
+        self.now = now;
+        self
+    }
+
     /// Advance the state machine.
     ///
     /// The provided `Input` needs to fulfill the *previous* `Output` request. See their
@@ -257,17 +296,18 @@ impl Refresh {
                 self.output()
             }
             (RefreshState::Recovering { authenticated, token }, Input::Recovered { scope, grant }) => {
-                self.state = recovered_refresh(scope, authenticated, grant, token)
+                self.state = recovered_refresh(scope, authenticated, grant, token, self.now)
                     .unwrap_or_else(RefreshState::Err);
                 self.output()
             }
             (RefreshState::CoAuthenticating { grant, token }, Input::Authenticated { scope }) => {
-                self.state = co_authenticated(scope, grant, token).unwrap_or_else(RefreshState::Err);
+                self.state =
+                    co_authenticated(scope, grant, token, self.now).unwrap_or_else(RefreshState::Err);
                 self.output()
             }
             (RefreshState::Issuing { grant, token: _ }, Input::Refreshed(token)) => {
                 // Ensure that this result is not duplicated.
-                self.state = RefreshState::Err(Error::Primitive);
+                self.state = RefreshState::Err(Error::Primitive(PrimitiveError::Invariant));
                 Output::Ok(issued(grant, token))
             }
             (current, Input::None) => {
@@ -281,14 +321,17 @@ impl Refresh {
                 self.output()
             }
             (_, _) => {
-                self.state = RefreshState::Err(Error::Primitive);
+                self.state = RefreshState::Err(Error::Primitive(PrimitiveError::Invariant));
                 self.output()
             }
         }
     }
 
     fn take(&mut self) -> RefreshState {
-        core::mem::replace(&mut self.state, RefreshState::Err(Error::Primitive))
+        core::mem::replace(
+            &mut self.state,
+            RefreshState::Err(Error::Primitive(PrimitiveError::Invariant)),
+        )
     }
 
     fn output(&self) -> Output<'_> {
@@ -335,37 +378,46 @@ pub fn refresh(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<Bear
         RecoverRefresh { token: String },
         Authenticate { client: String, pass: Option<Vec<u8>> },
     }
-    let mut refresh = Refresh::new(request);
+    let mut refresh = Refresh::new(request).at(handler.clock().now());
     let mut requested = Requested::None;
+    let mut members = Map::new();
     loop {
         let input = match requested {
             Requested::None => Input::None,
             Requested::Refresh { token, grant } => {
+                members = response_members(handler.token_response_hook(), &grant);
                 let refreshed = handler
                     .issuer()
                     .refresh(&token, *grant)
-                    .map_err(|()| Error::Primitive)?;
+                    .map_err(|err| match err {
+                        // The token was revoked or used concurrently since it was recovered.
+                        PrimitiveError::NotFound => Error::invalid(AccessTokenErrorType::InvalidGrant),
+                        err => Error::Primitive(err),
+                    })?;
                 Input::Refreshed(refreshed)
             }
             Requested::RecoverRefresh { token } => {
-                let recovered = handler
-                    .issuer()
-                    .recover_refresh(&token)
-                    .map_err(|()| Error::Primitive)?;
+                let recovered = match handler.issuer().recover_refresh(&token) {
+                    Err(PrimitiveError::NotFound) => None,
+                    recovered => recovered.map_err(Error::Primitive)?,
+                };
                 Input::Recovered {
                     scope: request.scope(),
                     grant: recovered.map(Box::new),
                 }
             }
             Requested::Authenticate { client, pass } => {
-                let _: () =
-                    handler
-                        .registrar()
-                        .check(&client, pass.as_deref())
-                        .map_err(|err| match err {
-                            RegistrarError::PrimitiveError => Error::Primitive,
-                            RegistrarError::Unspecified => Error::unauthorized("basic"),
-                        })?;
+                let registrar = handler.registrar();
+                let _: () = match (pass, request.client_certificate()) {
+                    (None, Some(certificate)) => registrar.check_certificate(&client, certificate),
+                    (pass, _) => registrar.check(&client, pass.as_deref()),
+                }
+                .map_err(|err| match err {
+                    RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+                    RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                        Error::unauthorized("basic")
+                    }
+                })?;
                 Input::Authenticated {
                     scope: request.scope(),
                 }
@@ -374,7 +426,10 @@ pub fn refresh(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<Bear
 
         requested = match refresh.advance(input) {
             Output::Err(error) => return Err(error),
-            Output::Ok(token) => return Ok(token),
+            Output::Ok(mut token) => {
+                token.3 = members;
+                return Ok(token);
+            }
             Output::Refresh { token, grant } => Requested::Refresh {
                 token: token.to_string(),
                 grant,
@@ -429,6 +484,7 @@ fn authenticated(client: String, token: String) -> RefreshState {
 
 fn recovered_refresh(
     scope: Option<Cow<str>>, authenticated: Option<String>, grant: Option<Box<Grant>>, token: String,
+    now: DateTime<Utc>,
 ) -> Result<RefreshState> {
     let grant = grant
         // ... is invalid, ... (Section 5.2)
@@ -443,7 +499,7 @@ fn recovered_refresh(
                 // Unauthorized but with BadRequest.
                 Err(Error::invalid(AccessTokenErrorType::InvalidGrant))
             } else {
-                validate(scope, grant, token)
+                validate(scope, grant, token, now)
             }
         }
 
@@ -455,14 +511,22 @@ fn recovered_refresh(
     }
 }
 
-fn co_authenticated(scope: Option<Cow<str>>, grant: Box<Grant>, token: String) -> Result<RefreshState> {
-    validate(scope, grant, token)
+fn co_authenticated(
+    scope: Option<Cow<str>>, grant: Box<Grant>, token: String, now: DateTime<Utc>,
+) -> Result<RefreshState> {
+    validate(scope, grant, token, now)
 }
 
-fn validate(scope: Option<Cow<str>>, grant: Box<Grant>, token: String) -> Result<RefreshState> {
+fn validate(
+    scope: Option<Cow<str>>, grant: Box<Grant>, token: String, now: DateTime<Utc>,
+) -> Result<RefreshState> {
     // .. is expired, revoked, ... (Section 5.2)
-    if grant.until <= Utc::now() {
-        return Err(Error::invalid(AccessTokenErrorType::InvalidGrant));
+    if grant.until <= now {
+        let mut error = Error::invalid(AccessTokenErrorType::InvalidGrant);
+        if let Some(description) = error.description() {
+            description.explain("The refresh token has expired");
+        }
+        return Err(error);
     }
 
     let scope = match scope {
@@ -491,17 +555,22 @@ fn validate(scope: Option<Cow<str>>, grant: Box<Grant>, token: String) -> Result
     // Update the grant with the derived data.
     let mut grant = grant;
     grant.scope = scope;
-    grant.until = Utc::now() + Duration::hours(1);
+    grant.until = now + Duration::hours(1);
 
     Ok(RefreshState::Issuing { grant, token })
 }
 
-fn issued(grant: Box<Grant>, token: RefreshedToken) -> BearerToken {
-    BearerToken(token, grant.scope.to_string())
+fn issued(grant: Box<Grant>, mut token: RefreshedToken) -> BearerToken {
+    if bound_key(&grant.extensions).is_some() {
+        token.token_type = TokenType::DPoP;
+    }
+    let details = authorization_details(&grant.extensions);
+    BearerToken(token, grant.scope.to_string(), details, Map::new())
 }
 
 impl Error {
-    fn invalid(kind: AccessTokenErrorType) -> Self {
+    /// Create invalid error type of the given kind
+    pub fn invalid(kind: AccessTokenErrorType) -> Self {
         Error::Invalid(ErrorDescription {
             error: AccessTokenError::new(kind),
         })
@@ -526,7 +595,7 @@ impl Error {
         match self {
             Error::Invalid(description) => Some(description.description()),
             Error::Unauthorized(description, _) => Some(description.description()),
-            Error::Primitive => None,
+            Error::Primitive(_) => None,
         }
     }
 }
@@ -558,12 +627,14 @@ impl BearerToken {
         let token_response = TokenResponse {
             access_token: Some(self.0.token.clone()),
             refresh_token: self.0.refresh.clone(),
-            token_type: Some("bearer".to_owned()),
+            token_type: Some(self.0.token_type.name().to_owned()),
             expires_in: Some(remaining.num_seconds()),
             scope: Some(self.1.clone()),
             error: None,
+            authorization_details: self.2.clone(),
+            id_token: None,
         };
 
-        serde_json::to_string(&token_response).unwrap()
+        response_json(&token_response, &self.3)
     }
 }
@@ -2,10 +2,14 @@
 use std::{fmt, mem};
 use std::borrow::Cow;
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 
+use crate::code_grant::extensions::{DpopProof, StepUp};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::primitives::error::PrimitiveError;
 use crate::primitives::issuer::Issuer;
 use crate::primitives::grant::Grant;
+use crate::primitives::registrar::ClientCertificate;
 use crate::primitives::scope::Scope;
 
 /// Gives additional information about the reason for an access failure.
@@ -31,6 +35,11 @@ pub enum ErrorCode {
 
     /// The token is expired, revoked, malformed or otherwise does not meet expectations.
     InvalidToken,
+
+    /// The authentication of the owner is too weak or too old, see [RFC 9470].
+    ///
+    /// [RFC 9470]: https://tools.ietf.org/html/rfc9470
+    InsufficientUserAuthentication,
 }
 
 /// Additional information provided for the WWW-Authenticate header.
@@ -41,6 +50,12 @@ pub struct Authenticate {
 
     /// The required scope to access the resource.
     pub scope: Option<Scope>,
+
+    /// The authentication context classes of which the owner must authenticate with one.
+    pub acr_values: Option<String>,
+
+    /// The maximum age in seconds of the authentication of the owner.
+    pub max_age: Option<i64>,
 }
 
 /// An error signalling the resource access was not permitted.
@@ -68,10 +83,11 @@ pub enum Error {
     },
 
     /// Some part of the endpoint failed, defer to endpoint for handling.
-    PrimitiveError,
+    PrimitiveError(PrimitiveError),
 }
 
 const BEARER_START: &str = "Bearer ";
+const DPOP_START: &str = "DPoP ";
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -85,10 +101,32 @@ pub trait Request {
 
     /// The authorization used in the request.
     ///
-    /// Expects the complete `Authorization` HTTP-header, including the qualification as `Bearer`.
-    /// In case the client included multiple forms of authorization, this method MUST return None
-    /// and the request SHOULD be marked as invalid.
+    /// Expects the complete `Authorization` HTTP-header, including the qualification as `Bearer`
+    /// or `DPoP`. In case the client included multiple forms of authorization, this method MUST
+    /// return None and the request SHOULD be marked as invalid.
     fn token(&self) -> Option<Cow<str>>;
+
+    /// The DPoP proof sent with the request, if any.
+    fn dpop(&self) -> Option<&DpopProof> {
+        None
+    }
+
+    /// The certificate of the client in a mutual TLS connection, if any.
+    fn client_certificate(&self) -> Option<&ClientCertificate> {
+        None
+    }
+}
+
+/// An extension checking the access to a resource with a valid token.
+pub trait Extension {
+    /// Inspect the request and the grant of its token, an error denies access.
+    fn check(&mut self, request: &dyn Request, grant: &Grant) -> std::result::Result<(), ()>;
+}
+
+impl Extension for () {
+    fn check(&mut self, _: &dyn Request, _: &Grant) -> std::result::Result<(), ()> {
+        Ok(())
+    }
 }
 
 /// Required functionality to respond to resource requests.
@@ -102,11 +140,27 @@ pub trait Endpoint {
 
     /// Issuer which provides the tokens used for authorization by the client.
     fn issuer(&mut self) -> &dyn Issuer;
+
+    /// The system of used extension, checking the access with the recovered grant.
+    fn extension(&mut self) -> &mut dyn Extension;
+
+    /// The authentication of the owner required by the resource, if any.
+    fn step_up(&mut self) -> Option<&StepUp> {
+        None
+    }
+
+    /// The clock deciding whether the token has expired.
+    ///
+    /// The system clock is the default implementation.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
 }
 
 /// The result will indicate whether the resource access should be allowed or not.
 pub struct Resource {
     state: ResourceState,
+    now: DateTime<Utc>,
 }
 
 enum ResourceState {
@@ -176,8 +230,14 @@ pub enum Output<'machine> {
 impl Resource {
     /// Create a Resource state machine at `ResourceState::New` state
     pub fn new() -> Self {
+        Resource::at(Utc::now())
+    }
+
+    /// Create a Resource state machine checking the expiry of the token against the time `now`.
+    pub fn at(now: DateTime<Utc>) -> Self {
         Resource {
             state: ResourceState::New,
+            now,
         }
     }
 
@@ -190,12 +250,12 @@ impl Resource {
             }
             (ResourceState::Internalized { token }, Input::Scopes(scopes)) => get_scopes(token, scopes),
             (ResourceState::Recovering { token: _, scopes }, Input::Recovered(grant)) => {
-                match recovered(grant, scopes) {
+                match recovered(grant, scopes, self.now) {
                     Ok(grant) => return Output::Ok(Box::new(grant)),
                     Err(err) => ResourceState::Err(err),
                 }
             }
-            _ => return Output::Err(Error::PrimitiveError),
+            _ => return Output::Err(Error::PrimitiveError(PrimitiveError::Invariant)),
         };
 
         self.output()
@@ -211,7 +271,10 @@ impl Resource {
     }
 
     fn take(&mut self) -> ResourceState {
-        mem::replace(&mut self.state, ResourceState::Err(Error::PrimitiveError))
+        mem::replace(
+            &mut self.state,
+            ResourceState::Err(Error::PrimitiveError(PrimitiveError::Invariant)),
+        )
     }
 }
 
@@ -224,7 +287,7 @@ pub fn protect(handler: &mut dyn Endpoint, req: &dyn Request) -> Result<Grant> {
         Grant(String),
     }
 
-    let mut resource = Resource::new();
+    let mut resource = Resource::at(handler.clock().now());
     let mut requested = Requested::None;
     loop {
         let input = match requested {
@@ -232,17 +295,18 @@ pub fn protect(handler: &mut dyn Endpoint, req: &dyn Request) -> Result<Grant> {
             Requested::Request => Input::Request { request: req },
             Requested::Scopes => Input::Scopes(handler.scopes()),
             Requested::Grant(token) => {
-                let grant = handler
-                    .issuer()
-                    .recover_token(&token)
-                    .map_err(|_| Error::PrimitiveError)?;
+                let grant = match handler.issuer().recover_token(&token) {
+                    // A token that vanished in the meantime is as invalid as an unknown one.
+                    Err(PrimitiveError::NotFound) => None,
+                    grant => grant.map_err(Error::PrimitiveError)?,
+                };
                 Input::Recovered(grant)
             }
         };
 
         requested = match resource.advance(input) {
             Output::Err(error) => return Err(error),
-            Output::Ok(grant) => return Ok(*grant),
+            Output::Ok(grant) => return extended(handler, req, *grant),
             Output::GetRequest => Requested::Request,
             Output::DetermineScopes => Requested::Scopes,
             Output::Recover { token } => Requested::Grant(token.to_string()),
@@ -250,6 +314,32 @@ pub fn protect(handler: &mut dyn Endpoint, req: &dyn Request) -> Result<Grant> {
     }
 }
 
+/// Let the extension check the access with an otherwise valid token.
+fn extended(handler: &mut dyn Endpoint, req: &dyn Request, grant: Grant) -> Result<Grant> {
+    if handler.extension().check(req, &grant).is_err() {
+        return Err(Error::AccessDenied {
+            failure: AccessFailure {
+                code: Some(ErrorCode::InvalidToken),
+            },
+            authenticate: Authenticate::empty(),
+        });
+    }
+
+    match handler.step_up() {
+        Some(step_up) if !step_up.satisfied_by(&grant) => Err(Error::AccessDenied {
+            failure: AccessFailure {
+                code: Some(ErrorCode::InsufficientUserAuthentication),
+            },
+            authenticate: Authenticate {
+                acr_values: Some(step_up.acr_values()),
+                max_age: step_up.max_age_seconds(),
+                ..Authenticate::empty()
+            },
+        }),
+        _ => Ok(grant),
+    }
+}
+
 fn validate(request: &'_ dyn Request) -> Result<ResourceState> {
     if !request.valid() {
         return Err(Error::InvalidRequest {
@@ -266,18 +356,23 @@ fn validate(request: &'_ dyn Request) -> Result<ResourceState> {
         }
     };
 
-    if !client_token
-        .to_uppercase()
-        .starts_with(&BEARER_START.to_uppercase())
-    {
-        return Err(Error::InvalidRequest {
-            authenticate: Authenticate::empty(),
-        });
-    }
+    // Tokens bound with DPoP use their own scheme, the proof is checked by an extension.
+    let uppercase = client_token.to_uppercase();
+    let scheme = [BEARER_START, DPOP_START]
+        .iter()
+        .find(|scheme| uppercase.starts_with(&scheme.to_uppercase()));
+    let scheme = match scheme {
+        Some(scheme) => scheme,
+        None => {
+            return Err(Error::InvalidRequest {
+                authenticate: Authenticate::empty(),
+            })
+        }
+    };
 
     let token = match client_token {
-        Cow::Borrowed(token) => token[BEARER_START.len()..].to_string(),
-        Cow::Owned(mut token) => token.split_off(BEARER_START.len()),
+        Cow::Borrowed(token) => token[scheme.len()..].to_string(),
+        Cow::Owned(mut token) => token.split_off(scheme.len()),
     };
 
     Ok(ResourceState::Internalized { token })
@@ -290,7 +385,7 @@ fn get_scopes(token: String, scopes: &'_ [Scope]) -> ResourceState {
     }
 }
 
-fn recovered(grant: Option<Grant>, mut scopes: Vec<Scope>) -> Result<Grant> {
+fn recovered(grant: Option<Grant>, mut scopes: Vec<Scope>, now: DateTime<Utc>) -> Result<Grant> {
     let grant = match grant {
         Some(grant) => grant,
         None => {
@@ -299,15 +394,15 @@ fn recovered(grant: Option<Grant>, mut scopes: Vec<Scope>) -> Result<Grant> {
                     code: Some(ErrorCode::InvalidRequest),
                 },
                 authenticate: Authenticate {
-                    realm: None,
                     // TODO. Don't drop the other scopes?
                     scope: scopes.drain(..).next(),
+                    ..Authenticate::empty()
                 },
             });
         }
     };
 
-    if grant.until < Utc::now() {
+    if grant.until < now {
         return Err(Error::AccessDenied {
             failure: AccessFailure {
                 code: Some(ErrorCode::InvalidToken),
@@ -326,8 +421,8 @@ fn recovered(grant: Option<Grant>, mut scopes: Vec<Scope>) -> Result<Grant> {
                 code: Some(ErrorCode::InsufficientScope),
             },
             authenticate: Authenticate {
-                realm: None,
                 scope: scopes.drain(..).next(),
+                ..Authenticate::empty()
             },
         });
     }
@@ -342,6 +437,7 @@ impl ErrorCode {
             ErrorCode::InvalidRequest => "invalid_request",
             ErrorCode::InsufficientScope => "insufficient_scope",
             ErrorCode::InvalidToken => "invalid_token",
+            ErrorCode::InsufficientUserAuthentication => "insufficient_user_authentication",
         }
     }
 }
@@ -384,12 +480,16 @@ impl Authenticate {
         Authenticate {
             realm: None,
             scope: None,
+            acr_values: None,
+            max_age: None,
         }
     }
 
     fn extend_header(self, header: &mut BearerHeader) {
         header.add_kvp("realm", self.realm);
         header.add_kvp("scope", self.scope);
+        header.add_kvp("acr_values", self.acr_values);
+        header.add_kvp("max_age", self.max_age);
     }
 }
 
@@ -417,7 +517,7 @@ impl Error {
             Error::InvalidRequest { authenticate } => {
                 authenticate.extend_header(&mut header);
             }
-            Error::PrimitiveError => (),
+            Error::PrimitiveError(_) => (),
         }
         header.finalize()
     }
@@ -0,0 +1,158 @@
+//! Provides the handling for Token Revocation requests.
+//!
+//! Clients notify the authorization server that a token is no longer needed, as specified in
+//! [RFC 7009]. Revoking a refresh token also revokes the access token issued with it. Since an
+//! unknown or already revoked token poses no risk, its revocation succeeds all the same.
+//!
+//! [RFC 7009]: https://tools.ietf.org/html/rfc7009
+use std::borrow::Cow;
+
+use crate::code_grant::accesstoken::ErrorDescription;
+use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::issuer::Issuer;
+use crate::primitives::registrar::{Registrar, RegistrarError};
+
+/// Required content of a revocation request.
+pub trait Request {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+
+    /// The client_id, required for public clients.
+    fn client_id(&self) -> Option<Cow<str>>;
+
+    /// The token to revoke.
+    fn token(&self) -> Option<Cow<str>>;
+
+    /// Optionally hints at the kind of token, `access_token` or `refresh_token`.
+    fn token_type_hint(&self) -> Option<Cow<str>>;
+
+    /// Retrieve an additional parameter used in an extension
+    fn extension(&self, key: &str) -> Option<Cow<str>>;
+}
+
+/// Required functionality to respond to revocation requests.
+pub trait Endpoint {
+    /// Authenticate the requesting client.
+    fn registrar(&self) -> &dyn Registrar;
+
+    /// Recover and revoke the token.
+    fn issuer(&mut self) -> &mut dyn Issuer;
+}
+
+/// Defines actions for the response to a revocation request.
+#[derive(Clone)]
+pub enum Error {
+    /// The request was malformed or the token was issued to another client.
+    Invalid(ErrorDescription),
+
+    /// The client did not properly authorize itself.
+    Unauthorized(ErrorDescription, String),
+
+    /// An underlying primitive operation did not complete successfully.
+    ///
+    /// This includes issuers that are unable to revoke tokens.
+    Primitive(PrimitiveError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Revoke the token of the request on behalf of the authenticated client.
+pub fn revoke(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<()> {
+    if !request.valid() {
+        return Err(Error::invalid(AccessTokenErrorType::InvalidRequest));
+    }
+
+    let client_id = authenticate(handler, request)?;
+    let token = request
+        .token()
+        .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidRequest))?;
+
+    let issuer = handler.issuer();
+    // The hint only decides the order of lookups, any token is still found.
+    let recovered = if request.token_type_hint().as_deref() == Some("refresh_token") {
+        match issuer.recover_refresh(&token) {
+            Ok(None) => issuer.recover_token(&token),
+            found => found,
+        }
+    } else {
+        match issuer.recover_token(&token) {
+            Ok(None) => issuer.recover_refresh(&token),
+            found => found,
+        }
+    };
+
+    let revoked = match recovered {
+        // Tokens that vanished in the meantime need not be revoked.
+        Ok(None) | Err(PrimitiveError::NotFound) => return Ok(()),
+        Ok(Some(grant)) if grant.client_id != client_id => {
+            return Err(Error::invalid(AccessTokenErrorType::UnauthorizedClient))
+        }
+        Ok(Some(_)) => issuer.revoke(&token),
+        Err(err) => Err(err),
+    };
+
+    match revoked {
+        Ok(()) | Err(PrimitiveError::NotFound) => Ok(()),
+        Err(err) => Err(Error::Primitive(err)),
+    }
+}
+
+/// Authenticate the client with its credentials or, for public clients, its id.
+fn authenticate(handler: &mut dyn Endpoint, request: &dyn Request) -> Result<String> {
+    let (client_id, passphrase) = match (request.authorization(), request.client_id()) {
+        // An authenticated client may still name itself, but not as another client.
+        (Some((client_id, passphrase)), Some(named)) if named == client_id => {
+            (client_id, Some(passphrase))
+        }
+        (Some(_), Some(_)) => return Err(Error::invalid(AccessTokenErrorType::InvalidRequest)),
+        (Some((client_id, passphrase)), None) => (client_id, Some(passphrase)),
+        (None, Some(client_id)) => (client_id, None),
+        (None, None) => return Err(Error::unauthorized("basic")),
+    };
+
+    handler
+        .registrar()
+        .check(&client_id, passphrase.as_deref())
+        .map_err(|err| match err {
+            RegistrarError::PrimitiveError => Error::Primitive(PrimitiveError::Invariant),
+            RegistrarError::Unspecified | RegistrarError::RedirectMismatch(_) => {
+                Error::unauthorized("basic")
+            }
+        })?;
+    Ok(client_id.into_owned())
+}
+
+impl Error {
+    /// Create an error for an invalid request of the kind.
+    pub fn invalid(kind: AccessTokenErrorType) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(kind);
+        Error::Invalid(ErrorDescription { error })
+    }
+
+    /// Create an error for a client that did not authenticate with the scheme.
+    pub fn unauthorized(authtype: &str) -> Self {
+        let mut error = AccessTokenError::default();
+        error.set_type(AccessTokenErrorType::InvalidClient);
+        Error::Unauthorized(ErrorDescription { error }, authtype.to_string())
+    }
+
+    /// Get a handle to the description the client will receive.
+    ///
+    /// Some types of this error don't return any description which is represented by a `None`
+    /// result.
+    pub fn description(&mut self) -> Option<&mut AccessTokenError> {
+        match self {
+            Error::Invalid(description) => Some(description.description()),
+            Error::Unauthorized(description, _) => Some(description.description()),
+            Error::Primitive(_) => None,
+        }
+    }
+}
@@ -5,10 +5,16 @@ use std::marker::PhantomData;
 use crate::code_grant::accesstoken::{
     access_token, Error as TokenError, Extension, Endpoint as TokenEndpoint, Request as TokenRequest,
 };
+#[cfg(feature = "jwt")]
+use crate::code_grant::assertion::ClientAssertions;
 use crate::primitives::{authorizer::Authorizer, registrar::Registrar, issuer::Issuer};
+use crate::primitives::clock::{Clock, SystemClock};
+#[cfg(feature = "jwt")]
+use crate::primitives::jwt::IdTokenSigner;
+use crate::code_grant::accesstoken::TokenResponseHook;
 use super::{
-    Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
-    is_authorization_method,
+    ClientCertificate, DpopProof, Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest,
+    WebResponse, is_authorization_method,
 };
 
 /// Offers access tokens to authenticated third parties.
@@ -35,6 +41,8 @@ where
 struct WrappedToken<E: Endpoint<R>, R: WebRequest> {
     inner: E,
     extension_fallback: (),
+    #[cfg(feature = "jwt")]
+    client_assertions: Option<ClientAssertions>,
     r_type: PhantomData<R>,
 }
 
@@ -53,6 +61,12 @@ struct WrappedRequest<'a, R: WebRequest + 'a> {
 
     /// The credentials-in-body flag from the flow.
     allow_credentials_in_body: bool,
+
+    /// The DPoP proof of the request.
+    dpop: Option<DpopProof>,
+
+    /// The certificate of the client in a mutual TLS connection.
+    client_certificate: Option<ClientCertificate>,
 }
 
 struct Invalid;
@@ -96,6 +110,8 @@ where
             endpoint: WrappedToken {
                 inner: endpoint,
                 extension_fallback: (),
+                #[cfg(feature = "jwt")]
+                client_assertions: None,
                 r_type: PhantomData,
             },
             allow_credentials_in_body: false,
@@ -113,6 +129,15 @@ where
         self.allow_credentials_in_body = allow;
     }
 
+    /// Accept clients authenticating with a JWT assertion, `private_key_jwt` or `client_secret_jwt`.
+    ///
+    /// Such clients send the assertion in the `client_assertion` parameter instead of a passphrase.
+    /// Without a verifier, requests with a client assertion are rejected.
+    #[cfg(feature = "jwt")]
+    pub fn client_assertions(&mut self, assertions: ClientAssertions) {
+        self.endpoint.client_assertions = Some(assertions);
+    }
+
     /// Use the checked endpoint to check for authorization for a resource.
     ///
     /// ## Panics
@@ -176,9 +201,9 @@ fn token_error<E: Endpoint<R>, R: WebRequest>(
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
-        TokenError::Primitive(_) => {
+        TokenError::Primitive(err) => {
             // FIXME: give the context for restoration.
-            return Err(endpoint.error(OAuthError::PrimitiveError));
+            return Err(endpoint.error(err.cause.into()));
         }
     })
 }
@@ -202,6 +227,24 @@ impl<E: Endpoint<R>, R: WebRequest> TokenEndpoint for WrappedToken<E, R> {
             .and_then(super::Extension::access_token)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.inner.id_token_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn client_assertions(&self) -> Option<&ClientAssertions> {
+        self.client_assertions.as_ref()
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.inner.token_response_hook()
+    }
 }
 
 impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
@@ -219,6 +262,8 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
 
         Ok(WrappedRequest {
             request: PhantomData,
+            dpop: request.dpop().map_err(FailParse::Err)?,
+            client_certificate: request.client_certificate().map_err(FailParse::Err)?,
             body: request.urlbody().map_err(FailParse::Err)?,
             authorization,
             error: None,
@@ -233,6 +278,8 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
             authorization: None,
             error: Some(err),
             allow_credentials_in_body: false,
+            dpop: None,
+            client_certificate: None,
         }
     }
 
@@ -301,6 +348,14 @@ impl<'a, R: WebRequest> TokenRequest for WrappedRequest<'a, R> {
         self.body.unique_value(key)
     }
 
+    fn dpop(&self) -> Option<&DpopProof> {
+        self.dpop.as_ref()
+    }
+
+    fn client_certificate(&self) -> Option<&ClientCertificate> {
+        self.client_certificate.as_ref()
+    }
+
     fn allow_credentials_in_body(&self) -> bool {
         self.allow_credentials_in_body
     }
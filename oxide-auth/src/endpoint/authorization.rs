@@ -1,11 +1,40 @@
+#[cfg(feature = "jwt")]
+use std::collections::HashMap;
+
+#[cfg(feature = "jwt")]
+use chrono::{DateTime, Utc};
+
 use crate::code_grant::authorization::{
     authorization_code, Error as AuthorizationError, Extension, Endpoint as AuthorizationEndpoint,
     Request as AuthorizationRequest, Pending,
 };
+use crate::code_grant::error::AuthorizationError as ErrorDescription;
+use crate::primitives::clock::SystemClock;
+#[cfg(feature = "jwt")]
+use crate::primitives::jwt::JwkSet;
+#[cfg(feature = "jwt")]
+use crate::primitives::registrar::RegistrarError;
 
 use super::*;
 
+/// The registered claims of a request object, which are no authorization parameters.
+#[cfg(feature = "jwt")]
+const OBJECT_CLAIMS: [&str; 6] = ["iss", "aud", "exp", "iat", "nbf", "jti"];
+
 /// All relevant methods for handling authorization code requests.
+///
+/// When the endpoint has a `RequestUriStore`, a request with a `request_uri` is replaced by the
+/// parameters the client pushed beforehand.
+///
+/// With the `jwt` feature, a `request` parameter passes the parameters in a request object of
+/// [RFC 9101] instead, signed with a key from the `jwks` the registrar has for the client.
+///
+/// A client may also request the response in a JWT secured response mode of [JARM], `jwt`,
+/// `query.jwt`, `fragment.jwt` or `form_post.jwt`. If the endpoint has a `ResponseSigner`, the
+/// response parameters are then signed and returned in the single `response` parameter.
+///
+/// [RFC 9101]: https://tools.ietf.org/html/rfc9101
+/// [JARM]: https://openid.net/specs/oauth-v2-jarm.html
 pub struct AuthorizationFlow<E, R>
 where
     E: Endpoint<R>,
@@ -17,9 +46,34 @@ where
 struct WrappedAuthorization<E: Endpoint<R>, R: WebRequest> {
     inner: E,
     extension_fallback: (),
+    /// The signed response requested by the current request.
+    #[cfg(feature = "jwt")]
+    jarm: Option<Jarm>,
     r_type: PhantomData<R>,
 }
 
+/// A request for a JWT secured authorization response.
+#[cfg(feature = "jwt")]
+struct Jarm {
+    client_id: String,
+    mode: JarmMode,
+}
+
+/// How a signed response is returned to the client.
+#[cfg(feature = "jwt")]
+enum JarmMode {
+    Query,
+    Fragment,
+    FormPost,
+}
+
+/// The signed response, as a redirect or a form posted by the user-agent.
+#[cfg(feature = "jwt")]
+enum Secured {
+    Redirect(Url),
+    FormPost(String),
+}
+
 struct WrappedRequest<'a, R: WebRequest + 'a> {
     /// Original request.
     request: PhantomData<R>,
@@ -28,7 +82,12 @@ struct WrappedRequest<'a, R: WebRequest + 'a> {
     query: Cow<'a, dyn QueryParameter + 'static>,
 
     /// An error if one occurred.
-    error: Option<R::Error>,
+    error: Option<InitError<R::Error>>,
+}
+
+enum InitError<E> {
+    Malformed,
+    Internal(E),
 }
 
 struct AuthorizationPending<'a, E: 'a, R: 'a>
@@ -115,6 +174,8 @@ where
             endpoint: WrappedAuthorization {
                 inner: endpoint,
                 extension_fallback: (),
+                #[cfg(feature = "jwt")]
+                jarm: None,
                 r_type: PhantomData,
             },
         })
@@ -130,10 +191,27 @@ where
     /// When the registrar or the authorizer returned by the endpoint is suddenly `None` when
     /// previously it was `Some(_)`.
     pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
-        let negotiated = authorization_code(&mut self.endpoint, &WrappedRequest::new(&mut request));
+        let mut wrapped = WrappedRequest::new(&mut request);
+        if let Some(request_uris) = self.endpoint.inner.request_uris_mut() {
+            if wrapped.resolve(request_uris).is_err() {
+                return Err(self.endpoint.inner.error(OAuthError::PrimitiveError));
+            }
+        }
+
+        #[cfg(feature = "jwt")]
+        {
+            let registrar = self.endpoint.inner.registrar().unwrap();
+            let now = self.endpoint.inner.clock().unwrap_or(&SystemClock).now();
+            if wrapped.unpack(registrar, now).is_err() {
+                return Err(self.endpoint.inner.error(OAuthError::PrimitiveError));
+            }
+            self.endpoint.jarm = wrapped.jarm();
+        }
+
+        let negotiated = authorization_code(&mut self.endpoint, &wrapped);
 
         let inner = match negotiated {
-            Err(err) => match authorization_error(&mut self.endpoint.inner, &mut request, err) {
+            Err(err) => match authorization_error(&mut self.endpoint, &mut request, err) {
                 Ok(response) => AuthorizationPartialInner::Failed { request, response },
                 Err(error) => AuthorizationPartialInner::Error { request, error },
             },
@@ -169,24 +247,42 @@ impl<'a, E: Endpoint<R>, R: WebRequest> AuthorizationPartial<'a, E, R> {
 }
 
 fn authorization_error<E: Endpoint<R>, R: WebRequest>(
-    endpoint: &mut E, request: &mut R, error: AuthorizationError,
+    endpoint: &mut WrappedAuthorization<E, R>, request: &mut R, error: AuthorizationError,
 ) -> Result<R::Response, E::Error> {
     match error {
-        AuthorizationError::Ignore => Err(endpoint.error(OAuthError::DenySilently)),
+        AuthorizationError::Ignore => Err(endpoint.inner.error(OAuthError::DenySilently)),
         AuthorizationError::Redirect(mut target) => {
-            let mut response = endpoint.response(
+            let mut response = endpoint.inner.response(
                 request,
                 InnerTemplate::Redirect {
                     authorization_error: Some(target.description()),
                 }
                 .into(),
             )?;
+            let base = target.redirect_uri().clone();
+            endpoint.redirect(&mut response, &base, target.into())?;
+            Ok(response)
+        }
+        AuthorizationError::RedirectMismatch(mismatch) => {
+            let mut description = ErrorDescription::default();
+            description.explain(mismatch.description());
+            let mut response = endpoint.inner.response(
+                request,
+                InnerTemplate::InvalidRedirect {
+                    authorization_error: Some(&mut description),
+                }
+                .into(),
+            )?;
             response
-                .redirect(target.into())
-                .map_err(|err| endpoint.web_error(err))?;
+                .client_error()
+                .map_err(|err| endpoint.inner.web_error(err))?;
+            let text = description.explanation().unwrap_or(mismatch.description());
+            response
+                .body_text(text)
+                .map_err(|err| endpoint.inner.web_error(err))?;
             Ok(response)
         }
-        AuthorizationError::PrimitiveError => Err(endpoint.error(OAuthError::PrimitiveError)),
+        AuthorizationError::PrimitiveError(err) => Err(endpoint.inner.error(err.into())),
     }
 }
 
@@ -200,9 +296,15 @@ impl<'a, E: Endpoint<R>, R: WebRequest> AuthorizationPending<'a, E, R> {
 
         match checked {
             OwnerConsent::Denied => self.deny(),
+            // No page may be shown for `prompt=none`, the client is told to ask interactively.
+            OwnerConsent::InProgress(_) if self.pending.authentication_request().is_silent() => {
+                self.fail(AuthorizationErrorType::InteractionRequired)
+            }
             OwnerConsent::InProgress(resp) => self.in_progress(resp),
-            OwnerConsent::Authorized(who) => self.authorize(who),
+            OwnerConsent::Authorized(who) => self.authorize(who, Map::new()),
+            OwnerConsent::AuthorizedWithClaims(who, claims) => self.authorize(who, claims),
             OwnerConsent::Error(err) => (self.request, Err(self.endpoint.inner.web_error(err))),
+            OwnerConsent::Failed(kind) => self.fail(kind),
         }
     }
 
@@ -217,34 +319,46 @@ impl<'a, E: Endpoint<R>, R: WebRequest> AuthorizationPending<'a, E, R> {
     }
 
     /// Denies the request, the client is not allowed access.
-    fn deny(mut self) -> (R, Result<R::Response, E::Error>) {
-        let result = self.pending.deny();
-        let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request);
+    fn deny(self) -> (R, Result<R::Response, E::Error>) {
+        self.fail(AuthorizationErrorType::AccessDenied)
+    }
+
+    /// Fails the request with an error for the client.
+    fn fail(mut self, kind: AuthorizationErrorType) -> (R, Result<R::Response, E::Error>) {
+        let base = self.pending.pre_grant().redirect_uri.to_url();
+        let result = self.pending.deny_with(kind);
+        let result = Self::convert_result(result, &base, self.endpoint, &mut self.request);
 
         (self.request, result)
     }
 
     /// Tells the system that the resource owner with the given id has approved the grant.
-    fn authorize(mut self, who: String) -> (R, Result<R::Response, E::Error>) {
-        let result = self.pending.authorize(self.endpoint, who.into());
-        let result = Self::convert_result(result, &mut self.endpoint.inner, &mut self.request);
+    fn authorize(
+        mut self, who: String, claims: Map<String, Value>,
+    ) -> (R, Result<R::Response, E::Error>) {
+        let base = self.pending.pre_grant().redirect_uri.to_url();
+        let result = self
+            .pending
+            .authorize_with_claims(self.endpoint, who.into(), claims);
+        let result = Self::convert_result(result, &base, self.endpoint, &mut self.request);
 
         (self.request, result)
     }
 
     fn convert_result(
-        result: Result<Url, AuthorizationError>, endpoint: &mut E, request: &mut R,
+        result: Result<Url, AuthorizationError>, base: &Url, endpoint: &mut WrappedAuthorization<E, R>,
+        request: &mut R,
     ) -> Result<R::Response, E::Error> {
         match result {
             Ok(url) => {
-                let mut response = endpoint.response(
+                let mut response = endpoint.inner.response(
                     request,
                     InnerTemplate::Redirect {
                         authorization_error: None,
                     }
                     .into(),
                 )?;
-                response.redirect(url).map_err(|err| endpoint.web_error(err))?;
+                endpoint.redirect(&mut response, base, url)?;
                 Ok(response)
             }
             Err(err) => authorization_error(endpoint, request, err),
@@ -256,6 +370,73 @@ impl<E: Endpoint<R>, R: WebRequest> WrappedAuthorization<E, R> {
     fn owner_solicitor(&mut self) -> &mut dyn OwnerSolicitor<R> {
         self.inner.owner_solicitor().unwrap()
     }
+
+    /// Redirect to the url, which adds the response parameters to the redirect uri `base`.
+    #[cfg_attr(not(feature = "jwt"), allow(unused_variables))]
+    fn redirect(&mut self, response: &mut R::Response, base: &Url, url: Url) -> Result<(), E::Error> {
+        #[cfg(feature = "jwt")]
+        let url = match self.secure(base, url) {
+            Err(()) => return Err(self.inner.error(OAuthError::PrimitiveError)),
+            Ok(Secured::Redirect(url)) => url,
+            Ok(Secured::FormPost(page)) => {
+                response.ok().map_err(|err| self.inner.web_error(err))?;
+                return response.body_html(&page).map_err(|err| self.inner.web_error(err));
+            }
+        };
+
+        response.redirect(url).map_err(|err| self.inner.web_error(err))
+    }
+
+    /// Sign the response parameters if the request asked for it.
+    #[cfg(feature = "jwt")]
+    fn secure(&self, base: &Url, url: Url) -> Result<Secured, ()> {
+        let (jarm, signer) = match (&self.jarm, self.inner.response_signer()) {
+            (Some(jarm), Some(signer)) => (jarm, signer),
+            _ => return Ok(Secured::Redirect(url)),
+        };
+
+        let parameters: Vec<_> = url
+            .query_pairs()
+            .skip(base.query_pairs().count())
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        let signed = signer.sign(&jarm.client_id, &parameters)?;
+
+        let mut target = base.clone();
+        Ok(match jarm.mode {
+            JarmMode::Query => {
+                target.query_pairs_mut().append_pair("response", &signed);
+                Secured::Redirect(target)
+            }
+            JarmMode::Fragment => {
+                target.set_fragment(Some(&format!("response={}", signed)));
+                Secured::Redirect(target)
+            }
+            JarmMode::FormPost => Secured::FormPost(form_post(&target, &signed)),
+        })
+    }
+}
+
+/// An html page whose form posts the signed `response` to the client.
+#[cfg(feature = "jwt")]
+fn form_post(target: &Url, response: &str) -> String {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('"', "&quot;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    format!(
+        "<!DOCTYPE html><html><head><title>Submit This Form</title></head>\
+         <body onload=\"document.forms[0].submit()\">\
+         <form method=\"post\" action=\"{}\">\
+         <input type=\"hidden\" name=\"response\" value=\"{}\"/>\
+         <noscript><input type=\"submit\" value=\"Continue\"/></noscript>\
+         </form></body></html>",
+        escape(target.as_str()),
+        escape(response)
+    )
 }
 
 impl<E: Endpoint<R>, R: WebRequest> AuthorizationEndpoint for WrappedAuthorization<E, R> {
@@ -273,6 +454,14 @@ impl<E: Endpoint<R>, R: WebRequest> AuthorizationEndpoint for WrappedAuthorizati
             .and_then(super::Extension::authorization)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.inner.claims_enricher()
+    }
 }
 
 impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
@@ -292,9 +481,145 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
         WrappedRequest {
             request: PhantomData,
             query: Cow::Owned(Default::default()),
-            error: Some(err),
+            error: Some(InitError::Internal(err)),
+        }
+    }
+
+    /// Replace the query with the pushed parameters its `request_uri` refers to.
+    ///
+    /// A `request_uri` which does not resolve for the named client makes the request invalid.
+    fn resolve(&mut self, request_uris: &mut dyn RequestUriStore) -> Result<(), ()> {
+        if self.error.is_some() {
+            return Ok(());
+        }
+
+        let request_uri = match self.query.unique_value("request_uri") {
+            None => return Ok(()),
+            Some(request_uri) => request_uri.into_owned(),
+        };
+
+        let pushed = match self.query.unique_value("client_id") {
+            None => None,
+            Some(client_id) => request_uris.pull(&client_id, &request_uri)?,
+        };
+
+        match pushed {
+            None => self.error = Some(InitError::Malformed),
+            Some(pushed) => self.query = Cow::Owned(pushed.parameters.into_iter().collect()),
+        }
+
+        Ok(())
+    }
+
+    /// The JWT secured response mode requested by the client, if any.
+    #[cfg(feature = "jwt")]
+    fn jarm(&self) -> Option<Jarm> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        let mode = match self.query.unique_value("response_mode")?.as_ref() {
+            // The default of the `code` response type is the query.
+            "jwt" | "query.jwt" => JarmMode::Query,
+            "fragment.jwt" => JarmMode::Fragment,
+            "form_post.jwt" => JarmMode::FormPost,
+            _ => return None,
+        };
+
+        Some(Jarm {
+            client_id: self.query.unique_value("client_id")?.into_owned(),
+            mode,
+        })
+    }
+
+    /// Replace the query with the parameters of its `request` object.
+    ///
+    /// Only the parameters of the object are used, parameters of the query are ignored except for
+    /// the `client_id` the object is verified for. An object that is not signed by one of the keys
+    /// of this client makes the request invalid.
+    #[cfg(feature = "jwt")]
+    fn unpack(&mut self, registrar: &dyn Registrar, now: DateTime<Utc>) -> Result<(), ()> {
+        if self.error.is_some() {
+            return Ok(());
+        }
+
+        let object = match self.query.unique_value("request") {
+            None => return Ok(()),
+            Some(object) => object.into_owned(),
+        };
+
+        let client_id = match self.query.unique_value("client_id") {
+            // The parameters are passed either by value or by reference, never both.
+            Some(client_id) if self.query.unique_value("request_uri").is_none() => {
+                client_id.into_owned()
+            }
+            _ => {
+                self.error = Some(InitError::Malformed);
+                return Ok(());
+            }
+        };
+
+        let jwks = match registrar.jwks(&client_id) {
+            Err(RegistrarError::PrimitiveError) => return Err(()),
+            Err(RegistrarError::Unspecified) | Err(RegistrarError::RedirectMismatch(_)) => None,
+            Ok(jwks) => jwks,
+        };
+
+        let parameters = jwks
+            .and_then(|jwks| JwkSet::from_json(&jwks).ok())
+            .and_then(|keys| keys.verify(&object).ok())
+            .and_then(|claims| request_parameters(&client_id, claims, now));
+
+        match parameters {
+            None => self.error = Some(InitError::Malformed),
+            Some(parameters) => self.query = Cow::Owned(parameters.into_iter().collect()),
         }
+
+        Ok(())
+    }
+}
+
+/// The authorization parameters in the claims of a request object.
+///
+/// The object may only name the client as its `client_id` and `iss`, and must not have expired at
+/// `now`.
+/// Parameters whose values are not strings, such as `authorization_details`, keep their json
+/// encoding.
+#[cfg(feature = "jwt")]
+fn request_parameters(
+    client_id: &str, claims: serde_json::Map<String, serde_json::Value>, now: DateTime<Utc>,
+) -> Option<HashMap<String, String>> {
+    use serde_json::Value;
+
+    let names_client = |claim| match claims.get(claim) {
+        None => true,
+        Some(value) => value.as_str() == Some(client_id),
+    };
+    if !names_client("client_id") || !names_client("iss") {
+        return None;
+    }
+
+    if let Some(exp) = claims.get("exp") {
+        if exp.as_i64()? <= now.timestamp() {
+            return None;
+        }
+    }
+
+    // An object can not refer to yet another request.
+    if claims.contains_key("request") || claims.contains_key("request_uri") {
+        return None;
     }
+
+    let mut parameters: HashMap<_, _> = claims
+        .into_iter()
+        .filter(|(claim, _)| !OBJECT_CLAIMS.contains(&claim.as_str()))
+        .map(|(claim, value)| match value {
+            Value::String(value) => (claim, value),
+            value => (claim, value.to_string()),
+        })
+        .collect();
+    parameters.insert("client_id".to_owned(), client_id.to_owned());
+    Some(parameters)
 }
 
 impl<'a, R: WebRequest + 'a> AuthorizationRequest for WrappedRequest<'a, R> {
@@ -0,0 +1,373 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::str::from_utf8;
+
+use url::Url;
+
+use crate::code_grant::ciba::{
+    backchannel_authentication, backchannel_token, Endpoint as BackchannelEndpoint, Error, Request,
+};
+use crate::primitives::ciba::{BackchannelRequest, BackchannelStore, NotifyOwner};
+use crate::primitives::{registrar::Registrar, issuer::Issuer};
+use crate::code_grant::accesstoken::TokenResponseHook;
+use crate::code_grant::extensions::ClaimsEnricher;
+use super::{
+    Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
+    is_authorization_method,
+};
+
+/// Starts backchannel authentications of owners identified by a client.
+///
+/// This is the backchannel authentication endpoint of [CIBA]. The owner named in the `login_hint`
+/// is identified and notified by the `NotifyOwner` given on construction, while the request waits
+/// in the `BackchannelStore` for their decision. Clients registered with `ping_client` are called
+/// at their notification endpoint once the owner decided, if the store is wrapped in `WithPing`,
+/// and all other clients poll the `BackchannelTokenFlow`.
+///
+/// [CIBA]: https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html
+pub struct BackchannelAuthenticationFlow<E, R, S, N>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: BackchannelStore,
+    N: NotifyOwner,
+{
+    endpoint: WrappedBackchannel<E, R, S, N>,
+}
+
+/// Issues tokens to clients polling with the `auth_req_id` of a backchannel authentication.
+///
+/// Until the owner decided, the client receives an `authorization_pending` error or, when it polls
+/// too frequently, a `slow_down` error.
+pub struct BackchannelTokenFlow<E, R, S>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: BackchannelStore,
+{
+    endpoint: WrappedBackchannel<E, R, S, Polling>,
+}
+
+struct WrappedBackchannel<E: Endpoint<R>, R: WebRequest, S, N> {
+    inner: E,
+    store: S,
+    notifier: N,
+    ping_clients: HashMap<String, Url>,
+    r_type: PhantomData<R>,
+}
+
+/// The notifier of token requests, which never start authentications.
+struct Polling;
+
+struct WrappedRequest<'a, R: WebRequest + 'a> {
+    /// Original request.
+    request: PhantomData<R>,
+
+    /// The query in the body.
+    body: Cow<'a, dyn QueryParameter + 'static>,
+
+    /// The authorization token.
+    authorization: Option<Authorization>,
+
+    /// An error if one occurred.
+    error: Option<InitError<R::Error>>,
+}
+
+enum InitError<E> {
+    Malformed,
+    Internal(E),
+}
+
+struct Authorization(String, Vec<u8>);
+
+fn prepare<E, R, S, N>(
+    mut endpoint: E, store: S, notifier: N,
+) -> Result<WrappedBackchannel<E, R, S, N>, E::Error>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    if endpoint.registrar().is_none() {
+        return Err(endpoint.error(OAuthError::PrimitiveError));
+    }
+
+    if endpoint.issuer_mut().is_none() {
+        return Err(endpoint.error(OAuthError::PrimitiveError));
+    }
+
+    Ok(WrappedBackchannel {
+        inner: endpoint,
+        store,
+        notifier,
+        ping_clients: HashMap::new(),
+        r_type: PhantomData,
+    })
+}
+
+impl<E, R, S, N> BackchannelAuthenticationFlow<E, R, S, N>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: BackchannelStore,
+    N: NotifyOwner,
+{
+    /// Wrap the endpoint if it supports handling backchannel authentication requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    pub fn prepare(endpoint: E, store: S, notifier: N) -> Result<Self, E::Error> {
+        Ok(BackchannelAuthenticationFlow {
+            endpoint: prepare(endpoint, store, notifier)?,
+        })
+    }
+
+    /// Register the client for the ping mode with its client notification endpoint.
+    ///
+    /// Its requests must then include a `client_notification_token`.
+    pub fn ping_client(&mut self, client_id: &str, endpoint: Url) {
+        self.endpoint.ping_clients.insert(client_id.to_owned(), endpoint);
+    }
+
+    /// Use the checked endpoint to start a backchannel authentication.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar or issuer returned by the endpoint is suddenly `None` when previously
+    /// it was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let started = backchannel_authentication(&mut self.endpoint, &WrappedRequest::new(&mut request));
+
+        let started = match started {
+            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(started) => started,
+        };
+
+        let json = serde_json::to_string(&started.response()).unwrap();
+        let mut response = self
+            .endpoint
+            .inner
+            .response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_json(&json)
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        Ok(response)
+    }
+}
+
+impl<E, R, S> BackchannelTokenFlow<E, R, S>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: BackchannelStore,
+{
+    /// Wrap the endpoint if it supports handling backchannel token requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    pub fn prepare(endpoint: E, store: S) -> Result<Self, E::Error> {
+        Ok(BackchannelTokenFlow {
+            endpoint: prepare(endpoint, store, Polling)?,
+        })
+    }
+
+    /// Use the checked endpoint to poll for a token.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar or issuer returned by the endpoint is suddenly `None` when previously
+    /// it was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let issued = backchannel_token(&mut self.endpoint, &WrappedRequest::new(&mut request));
+
+        let token = match issued {
+            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(token) => token,
+        };
+
+        let mut response = self
+            .endpoint
+            .inner
+            .response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_json(&token.to_json())
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        Ok(response)
+    }
+}
+
+fn token_error<E: Endpoint<R>, R: WebRequest>(
+    endpoint: &mut E, request: &mut R, error: Error,
+) -> Result<R::Response, E::Error> {
+    Ok(match error {
+        Error::Invalid(mut json) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::BadRequest {
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Unauthorized(mut json, scheme) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::Unauthorized {
+                    error: None,
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response
+                .unauthorized(&scheme)
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Primitive(err) => return Err(endpoint.error(err.into())),
+    })
+}
+
+impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
+    pub fn new(request: &'a mut R) -> Self {
+        Self::new_or_fail(request).unwrap_or_else(Self::from_err)
+    }
+
+    fn new_or_fail(request: &'a mut R) -> Result<Self, InitError<R::Error>> {
+        // If there is a header, it must parse correctly.
+        let authorization = match request.authheader() {
+            Err(err) => return Err(InitError::Internal(err)),
+            Ok(Some(header)) => Self::parse_header(header).map(Some)?,
+            Ok(None) => None,
+        };
+
+        Ok(WrappedRequest {
+            request: PhantomData,
+            body: request.urlbody().map_err(InitError::Internal)?,
+            authorization,
+            error: None,
+        })
+    }
+
+    fn from_err(err: InitError<R::Error>) -> Self {
+        WrappedRequest {
+            request: PhantomData,
+            body: Cow::Owned(Default::default()),
+            authorization: None,
+            error: Some(err),
+        }
+    }
+
+    fn parse_header(header: Cow<str>) -> Result<Authorization, InitError<R::Error>> {
+        let auth_data = is_authorization_method(&header, "Basic ").ok_or(InitError::Malformed)?;
+        let combined = base64::decode(auth_data).map_err(|_| InitError::Malformed)?;
+
+        let mut split = combined.splitn(2, |&c| c == b':');
+        let client_bin = split.next().ok_or(InitError::Malformed)?;
+        let passwd = split.next().ok_or(InitError::Malformed)?;
+        let client = from_utf8(client_bin).map_err(|_| InitError::Malformed)?;
+
+        Ok(Authorization(client.to_string(), passwd.to_vec()))
+    }
+}
+
+impl NotifyOwner for Polling {
+    fn identify(&self, _: &str) -> Result<Option<String>, ()> {
+        Err(())
+    }
+
+    fn notify(&self, _: &str, _: &BackchannelRequest) -> Result<(), ()> {
+        Err(())
+    }
+}
+
+impl<E, R, S, N> BackchannelEndpoint for WrappedBackchannel<E, R, S, N>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: BackchannelStore,
+    N: NotifyOwner,
+{
+    fn registrar(&self) -> &dyn Registrar {
+        self.inner.registrar().unwrap()
+    }
+
+    fn issuer(&mut self) -> &mut dyn Issuer {
+        self.inner.issuer_mut().unwrap()
+    }
+
+    fn backchannel(&mut self) -> &mut dyn BackchannelStore {
+        &mut self.store
+    }
+
+    fn notifier(&self) -> &dyn NotifyOwner {
+        &self.notifier
+    }
+
+    fn notification_endpoint(&self, client_id: &str) -> Option<Url> {
+        self.ping_clients.get(client_id).cloned()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.inner.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.inner.claims_enricher()
+    }
+}
+
+impl<'a, R: WebRequest> Request for WrappedRequest<'a, R> {
+    fn valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        self.authorization
+            .as_ref()
+            .map(|auth| (auth.0.as_str().into(), auth.1.as_slice().into()))
+    }
+
+    fn client_id(&self) -> Option<Cow<str>> {
+        self.body.unique_value("client_id")
+    }
+
+    fn scope(&self) -> Option<Cow<str>> {
+        self.body.unique_value("scope")
+    }
+
+    fn login_hint(&self) -> Option<Cow<str>> {
+        self.body.unique_value("login_hint")
+    }
+
+    fn binding_message(&self) -> Option<Cow<str>> {
+        self.body.unique_value("binding_message")
+    }
+
+    fn client_notification_token(&self) -> Option<Cow<str>> {
+        self.body.unique_value("client_notification_token")
+    }
+
+    fn grant_type(&self) -> Option<Cow<str>> {
+        self.body.unique_value("grant_type")
+    }
+
+    fn auth_req_id(&self) -> Option<Cow<str>> {
+        self.body.unique_value("auth_req_id")
+    }
+
+    fn extension(&self, key: &str) -> Option<Cow<str>> {
+        self.body.unique_value(key)
+    }
+}
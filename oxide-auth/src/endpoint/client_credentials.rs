@@ -2,13 +2,20 @@ use std::borrow::Cow;
 use std::str::from_utf8;
 use std::marker::PhantomData;
 
+use serde_json::Map;
+
 use crate::code_grant::client_credentials::{
     client_credentials, Error as ClientCredentialsError, Extension,
     Endpoint as ClientCredentialsEndpoint, Request as ClientCredentialsRequest,
 };
+#[cfg(feature = "jwt")]
+use crate::code_grant::assertion::ClientAssertions;
 use crate::code_grant::error::{AccessTokenError, AccessTokenErrorType};
 use crate::code_grant::refresh::ErrorDescription;
 use crate::primitives::{registrar::Registrar, issuer::Issuer};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::code_grant::accesstoken::TokenResponseHook;
+use crate::code_grant::extensions::ClaimsEnricher;
 use super::{
     Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
     is_authorization_method, OwnerConsent,
@@ -36,6 +43,8 @@ where
 struct WrappedToken<E: Endpoint<R>, R: WebRequest> {
     inner: E,
     extension_fallback: (),
+    #[cfg(feature = "jwt")]
+    client_assertions: Option<ClientAssertions>,
     r_type: PhantomData<R>,
 }
 
@@ -93,6 +102,8 @@ where
             endpoint: WrappedToken {
                 inner: endpoint,
                 extension_fallback: (),
+                #[cfg(feature = "jwt")]
+                client_assertions: None,
                 r_type: PhantomData,
             },
             allow_credentials_in_body: false,
@@ -111,6 +122,15 @@ where
         self.allow_credentials_in_body = allow;
     }
 
+    /// Accept clients authenticating with a JWT assertion, `private_key_jwt` or `client_secret_jwt`.
+    ///
+    /// Such clients send the assertion in the `client_assertion` parameter instead of a passphrase.
+    /// Without a verifier, requests with a client assertion are rejected.
+    #[cfg(feature = "jwt")]
+    pub fn client_assertions(&mut self, assertions: ClientAssertions) {
+        self.endpoint.client_assertions = Some(assertions);
+    }
+
     /// Allow the refresh token to be included in the response.
     ///
     /// According to [RFC-6749 Section 4.4.3][4.4.3] "A refresh token SHOULD NOT be included" in
@@ -150,15 +170,16 @@ where
             .unwrap()
             .check_consent(&mut request, pending.as_solicitation());
 
-        let owner_id = match consent {
-            OwnerConsent::Authorized(owner_id) => owner_id,
+        let (owner_id, claims) = match consent {
+            OwnerConsent::Authorized(owner_id) => (owner_id, Map::new()),
+            OwnerConsent::AuthorizedWithClaims(owner_id, claims) => (owner_id, claims),
             OwnerConsent::Error(error) => return Err(self.endpoint.inner.web_error(error)),
             OwnerConsent::InProgress(..) => {
                 // User interaction is not permitted in the client credentials flow, so
                 // an InProgress response is invalid.
                 return Err(self.endpoint.inner.error(OAuthError::PrimitiveError));
             }
-            OwnerConsent::Denied => {
+            OwnerConsent::Denied | OwnerConsent::Failed(_) => {
                 let mut error = AccessTokenError::default();
                 error.set_type(AccessTokenErrorType::InvalidClient);
                 let mut json = ErrorDescription { error };
@@ -180,7 +201,9 @@ where
             }
         };
 
-        let token = match pending.issue(&mut self.endpoint, owner_id, self.allow_refresh_token) {
+        let issued =
+            pending.issue_with_claims(&mut self.endpoint, owner_id, claims, self.allow_refresh_token);
+        let token = match issued {
             Err(error) => {
                 return client_credentials_error(&mut self.endpoint.inner, &mut request, error)
             }
@@ -234,9 +257,9 @@ fn client_credentials_error<E: Endpoint<R>, R: WebRequest>(
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
-        ClientCredentialsError::Primitive(_) => {
+        ClientCredentialsError::Primitive(err) => {
             // FIXME: give the context for restoration.
-            return Err(endpoint.error(OAuthError::PrimitiveError));
+            return Err(endpoint.error(err.cause.into()));
         }
     })
 }
@@ -256,6 +279,23 @@ impl<E: Endpoint<R>, R: WebRequest> ClientCredentialsEndpoint for WrappedToken<E
             .and_then(super::Extension::client_credentials)
             .unwrap_or(&mut self.extension_fallback)
     }
+
+    #[cfg(feature = "jwt")]
+    fn client_assertions(&self) -> Option<&ClientAssertions> {
+        self.client_assertions.as_ref()
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.inner.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.inner.claims_enricher()
+    }
 }
 
 impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
@@ -0,0 +1,240 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::str::from_utf8;
+
+use crate::code_grant::custom_grant::{
+    custom_grant, Endpoint as CustomGrantEndpoint, Error, GrantHandler, Request,
+};
+use crate::primitives::{registrar::Registrar, issuer::Issuer};
+use crate::primitives::clock::{Clock, SystemClock};
+use super::{
+    Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
+    is_authorization_method,
+};
+
+/// Offers access tokens for requests of custom grant types.
+///
+/// The request is passed to the `GrantHandler` of the flow, usually a `GrantTypes` registry with a
+/// handler for each supported extension grant. Together with the registrar and the issuer of the
+/// endpoint, the handler decides on the token.
+pub struct CustomGrantFlow<E, R, G>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    G: GrantHandler,
+{
+    endpoint: WrappedEndpoint<E, R>,
+    handler: G,
+}
+
+struct WrappedEndpoint<E: Endpoint<R>, R: WebRequest> {
+    inner: E,
+    r_type: PhantomData<R>,
+}
+
+struct WrappedRequest<'a, R: WebRequest + 'a> {
+    /// Original request.
+    request: PhantomData<R>,
+
+    /// The query in the body.
+    body: Cow<'a, dyn QueryParameter + 'static>,
+
+    /// The authorization token.
+    authorization: Option<Authorization>,
+
+    /// An error if one occurred.
+    error: Option<InitError<R::Error>>,
+}
+
+enum InitError<E> {
+    Malformed,
+    Internal(E),
+}
+
+struct Authorization(String, Vec<u8>);
+
+impl<E, R, G> CustomGrantFlow<E, R, G>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    G: GrantHandler,
+{
+    /// Wrap the endpoint if it supports handling requests of custom grant types.
+    ///
+    /// Also binds the endpoint to the particular `WebRequest` type through the type system. The
+    /// endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    ///
+    /// Requests are handled by the `handler`.
+    pub fn prepare(mut endpoint: E, handler: G) -> Result<Self, E::Error> {
+        if endpoint.registrar().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(CustomGrantFlow {
+            endpoint: WrappedEndpoint {
+                inner: endpoint,
+                r_type: PhantomData,
+            },
+            handler,
+        })
+    }
+
+    /// Use the checked endpoint to handle a request of a custom grant type.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar or issuer returned by the endpoint is suddenly `None` when previously it
+    /// was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let issued = custom_grant(
+            &mut self.endpoint,
+            &mut self.handler,
+            &WrappedRequest::new(&mut request),
+        );
+
+        let token = match issued {
+            Err(error) => return custom_grant_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(token) => token,
+        };
+
+        let mut response = self
+            .endpoint
+            .inner
+            .response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_json(&token.to_json())
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        Ok(response)
+    }
+}
+
+fn custom_grant_error<E: Endpoint<R>, R: WebRequest>(
+    endpoint: &mut E, request: &mut R, error: Error,
+) -> Result<R::Response, E::Error> {
+    Ok(match error {
+        Error::Invalid(mut json) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::BadRequest {
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Unauthorized(mut json, scheme) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::Unauthorized {
+                    error: None,
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response
+                .unauthorized(&scheme)
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Primitive(err) => return Err(endpoint.error(err.into())),
+    })
+}
+
+impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
+    pub fn new(request: &'a mut R) -> Self {
+        Self::new_or_fail(request).unwrap_or_else(Self::from_err)
+    }
+
+    fn new_or_fail(request: &'a mut R) -> Result<Self, InitError<R::Error>> {
+        // If there is a header, it must parse correctly.
+        let authorization = match request.authheader() {
+            Err(err) => return Err(InitError::Internal(err)),
+            Ok(Some(header)) => Self::parse_header(header).map(Some)?,
+            Ok(None) => None,
+        };
+
+        Ok(WrappedRequest {
+            request: PhantomData,
+            body: request.urlbody().map_err(InitError::Internal)?,
+            authorization,
+            error: None,
+        })
+    }
+
+    fn from_err(err: InitError<R::Error>) -> Self {
+        WrappedRequest {
+            request: PhantomData,
+            body: Cow::Owned(Default::default()),
+            authorization: None,
+            error: Some(err),
+        }
+    }
+
+    fn parse_header(header: Cow<str>) -> Result<Authorization, InitError<R::Error>> {
+        let auth_data = is_authorization_method(&header, "Basic ").ok_or(InitError::Malformed)?;
+        let combined = base64::decode(auth_data).map_err(|_| InitError::Malformed)?;
+
+        let mut split = combined.splitn(2, |&c| c == b':');
+        let client_bin = split.next().ok_or(InitError::Malformed)?;
+        let passwd = split.next().ok_or(InitError::Malformed)?;
+        let client = from_utf8(client_bin).map_err(|_| InitError::Malformed)?;
+
+        Ok(Authorization(client.to_string(), passwd.to_vec()))
+    }
+}
+
+impl<E: Endpoint<R>, R: WebRequest> CustomGrantEndpoint for WrappedEndpoint<E, R> {
+    fn registrar(&self) -> &dyn Registrar {
+        self.inner.registrar().unwrap()
+    }
+
+    fn issuer(&mut self) -> &mut dyn Issuer {
+        self.inner.issuer_mut().unwrap()
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
+}
+
+impl<'a, R: WebRequest> Request for WrappedRequest<'a, R> {
+    fn valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        self.authorization
+            .as_ref()
+            .map(|auth| (auth.0.as_str().into(), auth.1.as_slice().into()))
+    }
+
+    fn client_id(&self) -> Option<Cow<str>> {
+        self.body.unique_value("client_id")
+    }
+
+    fn grant_type(&self) -> Option<Cow<str>> {
+        self.body.unique_value("grant_type")
+    }
+
+    fn scope(&self) -> Option<Cow<str>> {
+        self.body.unique_value("scope")
+    }
+
+    fn extension(&self, key: &str) -> Option<Cow<str>> {
+        self.body.unique_value(key)
+    }
+}
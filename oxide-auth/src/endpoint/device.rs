@@ -0,0 +1,456 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::str::from_utf8;
+
+use url::Url;
+
+use crate::code_grant::device::{
+    device_authorization, device_token, Endpoint as DeviceEndpoint, Error, Request,
+};
+use crate::primitives::device::DeviceCodeStore;
+use crate::primitives::{registrar::Registrar, issuer::Issuer};
+use crate::code_grant::accesstoken::TokenResponseHook;
+use crate::code_grant::extensions::ClaimsEnricher;
+use super::{
+    Endpoint, InnerTemplate, OAuthError, OwnerConsent, QueryParameter, Solicitation, WebRequest,
+    WebResponse, is_authorization_method,
+};
+
+/// Starts device authorizations for devices with limited input capabilities.
+///
+/// This is the device authorization endpoint of [RFC 8628]. The response points the user to the
+/// verification page, where a `DeviceVerificationFlow` asks them to approve the device.
+///
+/// [RFC 8628]: https://tools.ietf.org/html/rfc8628
+pub struct DeviceAuthorizationFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: WrappedDevice<E, R>,
+    verification_uri: Url,
+    complete_uri: bool,
+}
+
+/// Lets users approve device authorizations by entering the user code shown on the device.
+///
+/// The user code is read from the `user_code` parameter of the query or of the body. The request
+/// is presented to the owner solicitor of the endpoint like an authorization code request, and
+/// its decision is recorded for the device to pick up.
+pub struct DeviceVerificationFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: E,
+    r_type: PhantomData<R>,
+}
+
+/// Issues tokens to devices polling with their device code.
+///
+/// Until the user decided, the device receives an `authorization_pending` error or, when it polls
+/// too frequently, a `slow_down` error.
+pub struct DeviceTokenFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: WrappedDevice<E, R>,
+}
+
+struct WrappedDevice<E: Endpoint<R>, R: WebRequest> {
+    inner: E,
+    r_type: PhantomData<R>,
+}
+
+struct WrappedRequest<'a, R: WebRequest + 'a> {
+    /// Original request.
+    request: PhantomData<R>,
+
+    /// The query in the body.
+    body: Cow<'a, dyn QueryParameter + 'static>,
+
+    /// The authorization token.
+    authorization: Option<Authorization>,
+
+    /// An error if one occurred.
+    error: Option<InitError<R::Error>>,
+}
+
+enum InitError<E> {
+    Malformed,
+    Internal(E),
+}
+
+struct Authorization(String, Vec<u8>);
+
+fn prepare<E: Endpoint<R>, R: WebRequest>(mut endpoint: E) -> Result<WrappedDevice<E, R>, E::Error> {
+    if endpoint.registrar().is_none() {
+        return Err(endpoint.error(OAuthError::PrimitiveError));
+    }
+
+    if endpoint.issuer_mut().is_none() {
+        return Err(endpoint.error(OAuthError::PrimitiveError));
+    }
+
+    if endpoint.device_codes_mut().is_none() {
+        return Err(endpoint.error(OAuthError::PrimitiveError));
+    }
+
+    Ok(WrappedDevice {
+        inner: endpoint,
+        r_type: PhantomData,
+    })
+}
+
+impl<E, R> DeviceAuthorizationFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    /// Wrap the endpoint if it supports handling device authorization requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    /// * a `DeviceCodeStore` from `device_codes_mut`
+    ///
+    /// Users are sent to the `verification_uri` to enter their user code.
+    pub fn prepare(endpoint: E, verification_uri: Url) -> Result<Self, E::Error> {
+        Ok(DeviceAuthorizationFlow {
+            endpoint: prepare(endpoint)?,
+            verification_uri,
+            complete_uri: true,
+        })
+    }
+
+    /// Whether responses contain the `verification_uri_complete`, on by default.
+    ///
+    /// The complete uri carries the user code, so devices can show it as a QR code or link. Turn
+    /// it off if the verification page should not accept codes from its query.
+    pub fn complete_uri(&mut self, include: bool) {
+        self.complete_uri = include;
+    }
+
+    /// Use the checked endpoint to start a device authorization.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar, issuer or device code store returned by the endpoint is suddenly
+    /// `None` when previously it was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let started = device_authorization(&mut self.endpoint, &WrappedRequest::new(&mut request));
+
+        let codes = match started {
+            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(codes) => codes,
+        };
+
+        let mut codes = codes.response(&self.verification_uri);
+        if !self.complete_uri {
+            codes.verification_uri_complete = None;
+        }
+
+        let json = serde_json::to_string(&codes).unwrap();
+        let mut response = self
+            .endpoint
+            .inner
+            .response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_json(&json)
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        Ok(response)
+    }
+}
+
+impl<E, R> DeviceVerificationFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    /// Wrap the endpoint if it supports handling device verification requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * an `OwnerSolicitor` from `owner_solicitor`
+    /// * a `DeviceCodeStore` from `device_codes_mut`
+    pub fn prepare(mut endpoint: E) -> Result<Self, E::Error> {
+        if endpoint.owner_solicitor().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.device_codes_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(DeviceVerificationFlow {
+            endpoint,
+            r_type: PhantomData,
+        })
+    }
+
+    /// Ask the owner to decide on the device authorization of the user code.
+    ///
+    /// ## Panics
+    ///
+    /// When the owner solicitor or device code store returned by the endpoint is suddenly `None`
+    /// when previously it was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let user_code = match Self::user_code(&mut request) {
+            Some(user_code) => user_code,
+            None => return self.unknown_code(&mut request),
+        };
+
+        let pending = self.endpoint.device_codes_mut().unwrap().pending(&user_code);
+        let pre_grant = match pending {
+            Err(()) => return Err(self.endpoint.error(OAuthError::PrimitiveError)),
+            Ok(None) => return self.unknown_code(&mut request),
+            Ok(Some(pre_grant)) => pre_grant,
+        };
+
+        let consent = self
+            .endpoint
+            .owner_solicitor()
+            .unwrap()
+            .check_consent(&mut request, Solicitation::new(&pre_grant));
+
+        let decided = match consent {
+            OwnerConsent::InProgress(response) => return Ok(response),
+            OwnerConsent::Error(error) => return Err(self.endpoint.web_error(error)),
+            OwnerConsent::Authorized(owner_id) => self
+                .endpoint
+                .device_codes_mut()
+                .unwrap()
+                .approve(&user_code, owner_id)
+                .map(|_| "The device has been authorized."),
+            OwnerConsent::AuthorizedWithClaims(owner_id, claims) => self
+                .endpoint
+                .device_codes_mut()
+                .unwrap()
+                .approve_with_claims(&user_code, owner_id, claims)
+                .map(|_| "The device has been authorized."),
+            OwnerConsent::Denied | OwnerConsent::Failed(_) => self
+                .endpoint
+                .device_codes_mut()
+                .unwrap()
+                .deny(&user_code)
+                .map(|_| "The device has been denied access."),
+        };
+
+        let message = decided.map_err(|()| self.endpoint.error(OAuthError::PrimitiveError))?;
+        let mut response = self.endpoint.response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_text(message)
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+
+    fn user_code(request: &mut R) -> Option<String> {
+        if let Ok(query) = request.query() {
+            if let Some(user_code) = query.unique_value("user_code") {
+                return Some(user_code.into_owned());
+            }
+        }
+
+        let body = request.urlbody().ok()?;
+        let user_code = body.unique_value("user_code")?;
+        Some(user_code.into_owned())
+    }
+
+    fn unknown_code(&mut self, request: &mut R) -> Result<R::Response, E::Error> {
+        let mut response = self.endpoint.response(
+            request,
+            InnerTemplate::BadRequest {
+                access_token_error: None,
+            }
+            .into(),
+        )?;
+        response
+            .client_error()
+            .map_err(|err| self.endpoint.web_error(err))?;
+        response
+            .body_text("The code is unknown or has expired.")
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+}
+
+impl<E, R> DeviceTokenFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    /// Wrap the endpoint if it supports handling device token requests.
+    ///
+    /// The endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    /// * a `DeviceCodeStore` from `device_codes_mut`
+    pub fn prepare(endpoint: E) -> Result<Self, E::Error> {
+        Ok(DeviceTokenFlow {
+            endpoint: prepare(endpoint)?,
+        })
+    }
+
+    /// Use the checked endpoint to poll for a device token.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar, issuer or device code store returned by the endpoint is suddenly
+    /// `None` when previously it was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let issued = device_token(&mut self.endpoint, &WrappedRequest::new(&mut request));
+
+        let token = match issued {
+            Err(error) => return token_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(token) => token,
+        };
+
+        let mut response = self
+            .endpoint
+            .inner
+            .response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_json(&token.to_json())
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        Ok(response)
+    }
+}
+
+fn token_error<E: Endpoint<R>, R: WebRequest>(
+    endpoint: &mut E, request: &mut R, error: Error,
+) -> Result<R::Response, E::Error> {
+    Ok(match error {
+        Error::Invalid(mut json) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::BadRequest {
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Unauthorized(mut json, scheme) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::Unauthorized {
+                    error: None,
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response
+                .unauthorized(&scheme)
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Primitive(err) => return Err(endpoint.error(err.into())),
+    })
+}
+
+impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
+    pub fn new(request: &'a mut R) -> Self {
+        Self::new_or_fail(request).unwrap_or_else(Self::from_err)
+    }
+
+    fn new_or_fail(request: &'a mut R) -> Result<Self, InitError<R::Error>> {
+        // If there is a header, it must parse correctly.
+        let authorization = match request.authheader() {
+            Err(err) => return Err(InitError::Internal(err)),
+            Ok(Some(header)) => Self::parse_header(header).map(Some)?,
+            Ok(None) => None,
+        };
+
+        Ok(WrappedRequest {
+            request: PhantomData,
+            body: request.urlbody().map_err(InitError::Internal)?,
+            authorization,
+            error: None,
+        })
+    }
+
+    fn from_err(err: InitError<R::Error>) -> Self {
+        WrappedRequest {
+            request: PhantomData,
+            body: Cow::Owned(Default::default()),
+            authorization: None,
+            error: Some(err),
+        }
+    }
+
+    fn parse_header(header: Cow<str>) -> Result<Authorization, InitError<R::Error>> {
+        let auth_data = is_authorization_method(&header, "Basic ").ok_or(InitError::Malformed)?;
+        let combined = base64::decode(auth_data).map_err(|_| InitError::Malformed)?;
+
+        let mut split = combined.splitn(2, |&c| c == b':');
+        let client_bin = split.next().ok_or(InitError::Malformed)?;
+        let passwd = split.next().ok_or(InitError::Malformed)?;
+        let client = from_utf8(client_bin).map_err(|_| InitError::Malformed)?;
+
+        Ok(Authorization(client.to_string(), passwd.to_vec()))
+    }
+}
+
+impl<E: Endpoint<R>, R: WebRequest> DeviceEndpoint for WrappedDevice<E, R> {
+    fn registrar(&self) -> &dyn Registrar {
+        self.inner.registrar().unwrap()
+    }
+
+    fn issuer(&mut self) -> &mut dyn Issuer {
+        self.inner.issuer_mut().unwrap()
+    }
+
+    fn device_codes(&mut self) -> &mut dyn DeviceCodeStore {
+        self.inner.device_codes_mut().unwrap()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.inner.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.inner.claims_enricher()
+    }
+}
+
+impl<'a, R: WebRequest> Request for WrappedRequest<'a, R> {
+    fn valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        self.authorization
+            .as_ref()
+            .map(|auth| (auth.0.as_str().into(), auth.1.as_slice().into()))
+    }
+
+    fn client_id(&self) -> Option<Cow<str>> {
+        self.body.unique_value("client_id")
+    }
+
+    fn scope(&self) -> Option<Cow<str>> {
+        self.body.unique_value("scope")
+    }
+
+    fn grant_type(&self) -> Option<Cow<str>> {
+        self.body.unique_value("grant_type")
+    }
+
+    fn device_code(&self) -> Option<Cow<str>> {
+        self.body.unique_value("device_code")
+    }
+
+    fn extension(&self, key: &str) -> Option<Cow<str>> {
+        self.body.unique_value(key)
+    }
+}
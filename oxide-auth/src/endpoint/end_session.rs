@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+use crate::code_grant::end_session::{end_session, Endpoint as EndSessionEndpoint, Error, Logout, Request};
+use crate::primitives::jwt::IdTokenSigner;
+use crate::primitives::registrar::Registrar;
+use crate::primitives::session::SessionRegistry;
+use super::{Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse};
+
+/// Logs owners out on request of an OpenID Connect client.
+///
+/// This is the end session endpoint of [RP-Initiated Logout]. The parameters are read from the
+/// query or, for posted forms, from the body. The sessions identified by the `id_token_hint` are
+/// ended in the `SessionRegistry` and the owner is redirected to the `post_logout_redirect_uri`,
+/// or receives an empty page when the request named none. The server should also clear its own
+/// session cookie when it responds to a successful request.
+///
+/// [RP-Initiated Logout]: https://openid.net/specs/openid-connect-rpinitiated-1_0.html
+pub struct EndSessionFlow<E, R, S>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: SessionRegistry,
+{
+    endpoint: WrappedEndSession<E, R, S>,
+}
+
+struct WrappedEndSession<E: Endpoint<R>, R: WebRequest, S> {
+    inner: E,
+    sessions: S,
+    r_type: PhantomData<R>,
+}
+
+struct WrappedRequest<'a, R: WebRequest + 'a> {
+    /// Original request.
+    request: PhantomData<R>,
+
+    /// The query of the request.
+    query: Cow<'a, dyn QueryParameter + 'static>,
+
+    /// The body of posted requests.
+    body: Option<Cow<'a, dyn QueryParameter + 'static>>,
+
+    /// An error if one occurred.
+    error: Option<R::Error>,
+}
+
+impl<E, R, S> EndSessionFlow<E, R, S>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    S: SessionRegistry,
+{
+    /// Wrap the endpoint if it supports handling logout requests.
+    ///
+    /// Also binds the endpoint to the particular `WebRequest` type through the type system. The
+    /// endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `IdTokenSigner` from `id_token_signer`
+    pub fn prepare(mut endpoint: E, sessions: S) -> Result<Self, E::Error> {
+        if endpoint.registrar().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.id_token_signer().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(EndSessionFlow {
+            endpoint: WrappedEndSession {
+                inner: endpoint,
+                sessions,
+                r_type: PhantomData,
+            },
+        })
+    }
+
+    /// Use the checked endpoint to log the owner out.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar or signer returned by the endpoint is suddenly `None` when previously it
+    /// was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let logout = end_session(&mut self.endpoint, &WrappedRequest::new(&mut request));
+
+        match logout {
+            Ok(Logout {
+                redirect_uri: Some(uri),
+                ..
+            }) => {
+                let mut response = self.endpoint.inner.response(
+                    &mut request,
+                    InnerTemplate::Redirect {
+                        authorization_error: None,
+                    }
+                    .into(),
+                )?;
+                response
+                    .redirect(uri)
+                    .map_err(|err| self.endpoint.inner.web_error(err))?;
+                Ok(response)
+            }
+            Ok(Logout {
+                redirect_uri: None, ..
+            }) => self
+                .endpoint
+                .inner
+                .response(&mut request, InnerTemplate::Ok.into()),
+            Err(error) => end_session_error(&mut self.endpoint.inner, &mut request, error),
+        }
+    }
+}
+
+fn end_session_error<E: Endpoint<R>, R: WebRequest>(
+    endpoint: &mut E, request: &mut R, error: Error,
+) -> Result<R::Response, E::Error> {
+    Ok(match error {
+        Error::Invalid(mut json) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::BadRequest {
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Primitive => return Err(endpoint.error(OAuthError::PrimitiveError)),
+    })
+}
+
+impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
+    pub fn new(request: &'a mut R) -> Self {
+        // Only posted forms have a body, its absence is no error.
+        let body = request.urlbody().ok().map(|body| Cow::Owned(body.normalize()));
+        match request.query() {
+            Ok(query) => WrappedRequest {
+                request: PhantomData,
+                query,
+                body,
+                error: None,
+            },
+            Err(err) => WrappedRequest {
+                request: PhantomData,
+                query: Cow::Owned(Default::default()),
+                body: None,
+                error: Some(err),
+            },
+        }
+    }
+
+    fn parameter(&self, key: &str) -> Option<Cow<str>> {
+        self.query
+            .unique_value(key)
+            .or_else(|| self.body.as_ref()?.unique_value(key))
+    }
+}
+
+impl<E: Endpoint<R>, R: WebRequest, S: SessionRegistry> EndSessionEndpoint
+    for WrappedEndSession<E, R, S>
+{
+    fn registrar(&self) -> &dyn Registrar {
+        self.inner.registrar().unwrap()
+    }
+
+    fn id_token_signer(&self) -> &IdTokenSigner {
+        self.inner.id_token_signer().unwrap()
+    }
+
+    fn sessions(&mut self) -> &mut dyn SessionRegistry {
+        &mut self.sessions
+    }
+}
+
+impl<'a, R: WebRequest> Request for WrappedRequest<'a, R> {
+    fn valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn id_token_hint(&self) -> Option<Cow<str>> {
+        self.parameter("id_token_hint")
+    }
+
+    fn client_id(&self) -> Option<Cow<str>> {
+        self.parameter("client_id")
+    }
+
+    fn post_logout_redirect_uri(&self) -> Option<Cow<str>> {
+        self.parameter("post_logout_redirect_uri")
+    }
+
+    fn state(&self) -> Option<Cow<str>> {
+        self.parameter("state")
+    }
+
+    fn extension(&self, key: &str) -> Option<Cow<str>> {
+        self.parameter(key)
+    }
+}
@@ -1,6 +1,8 @@
 use std::error;
 use std::fmt;
 
+use crate::primitives::error::PrimitiveError;
+
 /// Errors which should not or need not be communicated to the requesting party but which are of
 /// interest to the server. See the documentation for each enum variant for more documentation on
 /// each as some may have an expected response. These include badly formatted headers or url encoded
@@ -19,6 +21,12 @@ pub enum OAuthError {
     /// implementation of the primitive underlying those two.
     PrimitiveError,
 
+    /// One of the primitives used to complete the operation failed temporarily.
+    ///
+    /// Retrying the request later may succeed. Typically, this should be represented as a
+    /// `503–Service Unavailable`.
+    TemporarilyUnavailable,
+
     /// The incoming request was malformed.
     ///
     /// This implies that it did not change any internal state. Note that this differs from an
@@ -34,9 +42,21 @@ impl fmt::Display for OAuthError {
         match self {
             OAuthError::DenySilently => fmt.write_str("OAuthError: Request should be silently denied"),
             OAuthError::PrimitiveError => fmt.write_str("OAuthError: Server component failed"),
+            OAuthError::TemporarilyUnavailable => {
+                fmt.write_str("OAuthError: Server component temporarily unavailable")
+            }
             OAuthError::BadRequest => fmt.write_str("OAuthError: Bad request"),
         }
     }
 }
 
 impl error::Error for OAuthError {}
+
+impl From<PrimitiveError> for OAuthError {
+    fn from(err: PrimitiveError) -> Self {
+        match err {
+            PrimitiveError::Unavailable => OAuthError::TemporarilyUnavailable,
+            PrimitiveError::NotFound | PrimitiveError::Invariant => OAuthError::PrimitiveError,
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::str::from_utf8;
+
+use crate::code_grant::exchange::{exchange, Endpoint as ExchangeEndpoint, Error, ExchangePolicy, Request};
+use crate::primitives::{registrar::Registrar, issuer::Issuer};
+use crate::primitives::clock::{Clock, SystemClock};
+use super::{
+    Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
+    is_authorization_method,
+};
+
+/// Exchanges tokens of a subject for new access tokens of the requesting client.
+///
+/// This is the token exchange grant of [RFC 8693]. Which subject and actor tokens, audiences and
+/// scopes are accepted is decided by the `ExchangePolicy` of the flow.
+///
+/// [RFC 8693]: https://tools.ietf.org/html/rfc8693
+pub struct TokenExchangeFlow<E, R, P>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    P: ExchangePolicy,
+{
+    endpoint: WrappedExchange<E, R, P>,
+}
+
+struct WrappedExchange<E: Endpoint<R>, R: WebRequest, P: ExchangePolicy> {
+    inner: E,
+    policy: P,
+    r_type: PhantomData<R>,
+}
+
+struct WrappedRequest<'a, R: WebRequest + 'a> {
+    /// Original request.
+    request: PhantomData<R>,
+
+    /// The query in the body.
+    body: Cow<'a, dyn QueryParameter + 'static>,
+
+    /// The authorization token.
+    authorization: Option<Authorization>,
+
+    /// An error if one occurred.
+    error: Option<InitError<R::Error>>,
+}
+
+enum InitError<E> {
+    Malformed,
+    Internal(E),
+}
+
+struct Authorization(String, Vec<u8>);
+
+impl<E, R, P> TokenExchangeFlow<E, R, P>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+    P: ExchangePolicy,
+{
+    /// Wrap the endpoint if it supports handling token exchange requests.
+    ///
+    /// Also binds the endpoint to the particular `WebRequest` type through the type system. The
+    /// endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    ///
+    /// Exchanges are decided by the `policy`.
+    pub fn prepare(mut endpoint: E, policy: P) -> Result<Self, E::Error> {
+        if endpoint.registrar().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(TokenExchangeFlow {
+            endpoint: WrappedExchange {
+                inner: endpoint,
+                policy,
+                r_type: PhantomData,
+            },
+        })
+    }
+
+    /// Use the checked endpoint to exchange a token.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar or issuer returned by the endpoint is suddenly `None` when previously it
+    /// was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let exchanged = exchange(&mut self.endpoint, &WrappedRequest::new(&mut request));
+
+        let token = match exchanged {
+            Err(error) => return exchange_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(token) => token,
+        };
+
+        let mut response = self
+            .endpoint
+            .inner
+            .response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_json(&token.to_json())
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        Ok(response)
+    }
+}
+
+fn exchange_error<E: Endpoint<R>, R: WebRequest>(
+    endpoint: &mut E, request: &mut R, error: Error,
+) -> Result<R::Response, E::Error> {
+    Ok(match error {
+        Error::Invalid(mut json) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::BadRequest {
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Unauthorized(mut json, scheme) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::Unauthorized {
+                    error: None,
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response
+                .unauthorized(&scheme)
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Primitive(err) => return Err(endpoint.error(err.into())),
+    })
+}
+
+impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
+    pub fn new(request: &'a mut R) -> Self {
+        Self::new_or_fail(request).unwrap_or_else(Self::from_err)
+    }
+
+    fn new_or_fail(request: &'a mut R) -> Result<Self, InitError<R::Error>> {
+        // If there is a header, it must parse correctly.
+        let authorization = match request.authheader() {
+            Err(err) => return Err(InitError::Internal(err)),
+            Ok(Some(header)) => Self::parse_header(header).map(Some)?,
+            Ok(None) => None,
+        };
+
+        Ok(WrappedRequest {
+            request: PhantomData,
+            body: request.urlbody().map_err(InitError::Internal)?,
+            authorization,
+            error: None,
+        })
+    }
+
+    fn from_err(err: InitError<R::Error>) -> Self {
+        WrappedRequest {
+            request: PhantomData,
+            body: Cow::Owned(Default::default()),
+            authorization: None,
+            error: Some(err),
+        }
+    }
+
+    fn parse_header(header: Cow<str>) -> Result<Authorization, InitError<R::Error>> {
+        let auth_data = is_authorization_method(&header, "Basic ").ok_or(InitError::Malformed)?;
+        let combined = base64::decode(auth_data).map_err(|_| InitError::Malformed)?;
+
+        let mut split = combined.splitn(2, |&c| c == b':');
+        let client_bin = split.next().ok_or(InitError::Malformed)?;
+        let passwd = split.next().ok_or(InitError::Malformed)?;
+        let client = from_utf8(client_bin).map_err(|_| InitError::Malformed)?;
+
+        Ok(Authorization(client.to_string(), passwd.to_vec()))
+    }
+}
+
+impl<E: Endpoint<R>, R: WebRequest, P: ExchangePolicy> ExchangeEndpoint for WrappedExchange<E, R, P> {
+    fn registrar(&self) -> &dyn Registrar {
+        self.inner.registrar().unwrap()
+    }
+
+    fn issuer_and_policy(&mut self) -> (&mut dyn Issuer, &mut dyn ExchangePolicy) {
+        (self.inner.issuer_mut().unwrap(), &mut self.policy)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
+}
+
+impl<'a, R: WebRequest> Request for WrappedRequest<'a, R> {
+    fn valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        self.authorization
+            .as_ref()
+            .map(|auth| (auth.0.as_str().into(), auth.1.as_slice().into()))
+    }
+
+    fn client_id(&self) -> Option<Cow<str>> {
+        self.body.unique_value("client_id")
+    }
+
+    fn grant_type(&self) -> Option<Cow<str>> {
+        self.body.unique_value("grant_type")
+    }
+
+    fn subject_token(&self) -> Option<Cow<str>> {
+        self.body.unique_value("subject_token")
+    }
+
+    fn subject_token_type(&self) -> Option<Cow<str>> {
+        self.body.unique_value("subject_token_type")
+    }
+
+    fn actor_token(&self) -> Option<Cow<str>> {
+        self.body.unique_value("actor_token")
+    }
+
+    fn actor_token_type(&self) -> Option<Cow<str>> {
+        self.body.unique_value("actor_token_type")
+    }
+
+    fn audience(&self) -> Option<Cow<str>> {
+        self.body.unique_value("audience")
+    }
+
+    fn scope(&self) -> Option<Cow<str>> {
+        self.body.unique_value("scope")
+    }
+
+    fn extension(&self, key: &str) -> Option<Cow<str>> {
+        self.body.unique_value(key)
+    }
+}
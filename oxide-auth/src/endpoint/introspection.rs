@@ -0,0 +1,271 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::str::from_utf8;
+
+use crate::code_grant::introspection::{
+    introspect, Endpoint as IntrospectionEndpoint, Error, Request, INTROSPECTION_SCOPE,
+};
+use crate::primitives::{registrar::Registrar, issuer::Issuer};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::primitives::scope::Scope;
+use super::{
+    Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
+    is_authorization_method,
+};
+
+/// Answers resource servers asking about the state of a token.
+///
+/// This is the introspection endpoint of [RFC 7662]. Resource servers authenticate with client
+/// credentials in a basic authorization header or with a valid bearer token of their own. Only
+/// the clients named with `resource_server` and bearer tokens with the `introspection_scope` are
+/// accepted.
+///
+/// [RFC 7662]: https://tools.ietf.org/html/rfc7662
+pub struct IntrospectionFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: WrappedIntrospection<E, R>,
+}
+
+struct WrappedIntrospection<E: Endpoint<R>, R: WebRequest> {
+    inner: E,
+    r_type: PhantomData<R>,
+    resource_servers: Vec<String>,
+    scope: Scope,
+}
+
+struct WrappedRequest<'a, R: WebRequest + 'a> {
+    /// Original request.
+    request: PhantomData<R>,
+
+    /// The query in the body.
+    body: Cow<'a, dyn QueryParameter + 'static>,
+
+    /// The authorization of the resource server.
+    authorization: Option<Authorization>,
+
+    /// An error if one occurred.
+    error: Option<InitError<R::Error>>,
+}
+
+enum InitError<E> {
+    Malformed,
+    Internal(E),
+}
+
+enum Authorization {
+    Basic(String, Vec<u8>),
+    Bearer(String),
+}
+
+impl<E, R> IntrospectionFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    /// Wrap the endpoint if it supports handling introspection requests.
+    ///
+    /// Also binds the endpoint to the particular `WebRequest` type through the type system. The
+    /// endpoint needs to provide (return `Some`):
+    ///
+    /// * a `Registrar` from `registrar`
+    /// * an `Issuer` from `issuer_mut`
+    pub fn prepare(mut endpoint: E) -> Result<Self, E::Error> {
+        if endpoint.registrar().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        if endpoint.issuer_mut().is_none() {
+            return Err(endpoint.error(OAuthError::PrimitiveError));
+        }
+
+        Ok(IntrospectionFlow {
+            endpoint: WrappedIntrospection {
+                inner: endpoint,
+                r_type: PhantomData,
+                resource_servers: Vec::new(),
+                scope: INTROSPECTION_SCOPE.parse().unwrap(),
+            },
+        })
+    }
+
+    /// Allow the client to introspect tokens with its credentials.
+    ///
+    /// No client may do so by default, since introspection describes the grant of any token.
+    pub fn resource_server(&mut self, client_id: &str) {
+        self.endpoint.resource_servers.push(client_id.to_owned());
+    }
+
+    /// The scope a bearer token must include to introspect tokens, `introspection` by default.
+    pub fn introspection_scope(&mut self, scope: Scope) {
+        self.endpoint.scope = scope;
+    }
+
+    /// Use the checked endpoint to introspect a token.
+    ///
+    /// ## Panics
+    ///
+    /// When the registrar or issuer returned by the endpoint is suddenly `None` when previously it
+    /// was `Some(_)`.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let introspected = introspect(&mut self.endpoint, &WrappedRequest::new(&mut request));
+
+        let description = match introspected {
+            Err(error) => return introspection_error(&mut self.endpoint.inner, &mut request, error),
+            Ok(description) => description,
+        };
+
+        let mut response = self
+            .endpoint
+            .inner
+            .response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_json(&description.to_json())
+            .map_err(|err| self.endpoint.inner.web_error(err))?;
+        Ok(response)
+    }
+}
+
+fn introspection_error<E: Endpoint<R>, R: WebRequest>(
+    endpoint: &mut E, request: &mut R, error: Error,
+) -> Result<R::Response, E::Error> {
+    Ok(match error {
+        Error::Invalid(mut json) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::BadRequest {
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response.client_error().map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Unauthorized(mut json, scheme) => {
+            let mut response = endpoint.response(
+                request,
+                InnerTemplate::Unauthorized {
+                    error: None,
+                    access_token_error: Some(json.description()),
+                }
+                .into(),
+            )?;
+            response
+                .unauthorized(&scheme)
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+                .body_json(&json.to_json())
+                .map_err(|err| endpoint.web_error(err))?;
+            response
+        }
+        Error::Primitive(err) => return Err(endpoint.error(err.into())),
+    })
+}
+
+impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
+    pub fn new(request: &'a mut R) -> Self {
+        Self::new_or_fail(request).unwrap_or_else(Self::from_err)
+    }
+
+    fn new_or_fail(request: &'a mut R) -> Result<Self, InitError<R::Error>> {
+        // If there is a header, it must parse correctly.
+        let authorization = match request.authheader() {
+            Err(err) => return Err(InitError::Internal(err)),
+            Ok(Some(header)) => Self::parse_header(header).map(Some)?,
+            Ok(None) => None,
+        };
+
+        Ok(WrappedRequest {
+            request: PhantomData,
+            body: request.urlbody().map_err(InitError::Internal)?,
+            authorization,
+            error: None,
+        })
+    }
+
+    fn from_err(err: InitError<R::Error>) -> Self {
+        WrappedRequest {
+            request: PhantomData,
+            body: Cow::Owned(Default::default()),
+            authorization: None,
+            error: Some(err),
+        }
+    }
+
+    fn parse_header(header: Cow<str>) -> Result<Authorization, InitError<R::Error>> {
+        if let Some(token) = is_authorization_method(&header, "Bearer ") {
+            return Ok(Authorization::Bearer(token.to_string()));
+        }
+
+        let auth_data = is_authorization_method(&header, "Basic ").ok_or(InitError::Malformed)?;
+        let combined = base64::decode(auth_data).map_err(|_| InitError::Malformed)?;
+
+        let mut split = combined.splitn(2, |&c| c == b':');
+        let client_bin = split.next().ok_or(InitError::Malformed)?;
+        let passwd = split.next().ok_or(InitError::Malformed)?;
+        let client = from_utf8(client_bin).map_err(|_| InitError::Malformed)?;
+
+        Ok(Authorization::Basic(client.to_string(), passwd.to_vec()))
+    }
+}
+
+impl<E: Endpoint<R>, R: WebRequest> IntrospectionEndpoint for WrappedIntrospection<E, R> {
+    fn registrar(&self) -> &dyn Registrar {
+        self.inner.registrar().unwrap()
+    }
+
+    fn issuer(&mut self) -> &mut dyn Issuer {
+        self.inner.issuer_mut().unwrap()
+    }
+
+    fn resource_server(&self, client_id: &str) -> bool {
+        self.resource_servers.iter().any(|allowed| allowed == client_id)
+    }
+
+    fn introspection_scope(&self) -> Scope {
+        self.scope.clone()
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
+}
+
+impl<'a, R: WebRequest> Request for WrappedRequest<'a, R> {
+    fn valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        match &self.authorization {
+            Some(Authorization::Basic(client, passwd)) => {
+                Some((client.as_str().into(), passwd.as_slice().into()))
+            }
+            _ => None,
+        }
+    }
+
+    fn bearer(&self) -> Option<Cow<str>> {
+        match &self.authorization {
+            Some(Authorization::Bearer(token)) => Some(token.as_str().into()),
+            _ => None,
+        }
+    }
+
+    fn token(&self) -> Option<Cow<str>> {
+        self.body.unique_value("token")
+    }
+
+    fn token_type_hint(&self) -> Option<Cow<str>> {
+        self.body.unique_value("token_type_hint")
+    }
+
+    fn extension(&self, key: &str) -> Option<Cow<str>> {
+        self.body.unique_value(key)
+    }
+}
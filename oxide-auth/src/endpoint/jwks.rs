@@ -0,0 +1,49 @@
+use std::marker::PhantomData;
+
+use crate::primitives::keystore::KeyStore;
+use super::{Endpoint, InnerTemplate, WebRequest, WebResponse};
+
+/// Serves the public keys of a `KeyStore` as a JSON Web Key Set.
+///
+/// Clients and resource servers fetch this document from the `jwks_uri` of the server metadata to
+/// verify access tokens, ID tokens and signed responses. The set is read from the store for every
+/// request, so that rotated keys are served immediately. The path of the document is up to the
+/// routing of the server, `/.well-known/jwks.json` by convention. The endpoint is only used to
+/// create the responses.
+pub struct JwksFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: E,
+    keys: KeyStore,
+    r_type: PhantomData<R>,
+}
+
+impl<E, R> JwksFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    /// Serve the public keys of the store with the endpoint.
+    ///
+    /// Also binds the endpoint to the particular `WebRequest` type through the type system. No
+    /// primitives of the endpoint are required.
+    pub fn prepare(endpoint: E, keys: KeyStore) -> Result<Self, E::Error> {
+        Ok(JwksFlow {
+            endpoint,
+            keys,
+            r_type: PhantomData,
+        })
+    }
+
+    /// Respond with the current key set.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let document = serde_json::to_string(&self.keys.jwks()).unwrap_or_default();
+        let mut response = self.endpoint.response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_json(&document)
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+}
@@ -0,0 +1,50 @@
+use std::marker::PhantomData;
+
+use crate::code_grant::metadata::ServerMetadata;
+use super::{Endpoint, InnerTemplate, WebRequest, WebResponse};
+
+/// Serves the metadata document of the authorization server.
+///
+/// This answers requests to `/.well-known/oauth-authorization-server` as in [RFC 8414], or to
+/// `/.well-known/openid-configuration` of an OpenID Provider as in [OpenID Connect Discovery]. The
+/// document is created once with a `MetadataBuilder` and then returned unchanged for every
+/// request, the endpoint is only used to create the responses.
+///
+/// [RFC 8414]: https://tools.ietf.org/html/rfc8414
+/// [OpenID Connect Discovery]: https://openid.net/specs/openid-connect-discovery-1_0.html
+pub struct MetadataFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    endpoint: E,
+    document: String,
+    r_type: PhantomData<R>,
+}
+
+impl<E, R> MetadataFlow<E, R>
+where
+    E: Endpoint<R>,
+    R: WebRequest,
+{
+    /// Serve the document with the endpoint.
+    ///
+    /// Also binds the endpoint to the particular `WebRequest` type through the type system. No
+    /// primitives of the endpoint are required.
+    pub fn prepare(endpoint: E, metadata: &ServerMetadata) -> Result<Self, E::Error> {
+        Ok(MetadataFlow {
+            endpoint,
+            document: metadata.to_json(),
+            r_type: PhantomData,
+        })
+    }
+
+    /// Respond with the metadata document.
+    pub fn execute(&mut self, mut request: R) -> Result<R::Response, E::Error> {
+        let mut response = self.endpoint.response(&mut request, InnerTemplate::Ok.into())?;
+        response
+            .body_json(&self.document)
+            .map_err(|err| self.endpoint.web_error(err))?;
+        Ok(response)
+    }
+}
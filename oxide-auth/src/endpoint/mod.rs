@@ -30,10 +30,24 @@
 //! [`Registrar`]: ../../primitives/registrar/trait.Registrar.html
 mod authorization;
 mod accesstoken;
+mod ciba;
 mod client_credentials;
+mod custom_grant;
+mod device;
+#[cfg(feature = "jwt")]
+mod end_session;
 mod error;
+mod exchange;
+mod introspection;
+#[cfg(feature = "jwt")]
+mod jwks;
+mod metadata;
+mod par;
+#[cfg(feature = "password")]
+mod password;
 mod refresh;
 mod resource;
+mod revocation;
 mod query;
 
 #[cfg(test)]
@@ -43,27 +57,54 @@ use std::borrow::Cow;
 use std::marker::PhantomData;
 
 pub use crate::primitives::authorizer::Authorizer;
+pub use crate::primitives::clock::Clock;
+pub use crate::primitives::device::DeviceCodeStore;
 pub use crate::primitives::issuer::Issuer;
-pub use crate::primitives::registrar::Registrar;
+#[cfg(feature = "jwt")]
+pub use crate::primitives::jwt::{IdTokenSigner, ResponseSigner};
+pub use crate::primitives::registrar::{ClientCertificate, Registrar};
+pub use crate::primitives::request_uri::RequestUriStore;
 pub use crate::primitives::scope::Scope;
 
 use crate::code_grant::resource::{Error as ResourceError};
-use crate::code_grant::error::{AuthorizationError, AccessTokenError};
+use crate::code_grant::error::{AuthorizationError, AuthorizationErrorType, AccessTokenError};
+use crate::code_grant::extensions::authorization_details;
+use crate::primitives::grant::Extensions;
 
+use serde_json::{Map, Value};
 use url::Url;
 
 // Re-export the extension traits under prefixed names.
 pub use crate::code_grant::authorization::Extension as AuthorizationExtension;
 pub use crate::code_grant::accesstoken::Extension as AccessTokenExtension;
+pub use crate::code_grant::accesstoken::TokenResponseHook;
 pub use crate::code_grant::client_credentials::Extension as ClientCredentialsExtension;
+pub use crate::code_grant::resource::Extension as ResourceExtension;
+pub use crate::code_grant::extensions::{
+    AuthenticationRequest, AuthorizationDetail, ClaimsEnricher, DpopProof, Prompt, StepUp,
+};
 
 pub use crate::primitives::registrar::PreGrant;
 pub use self::authorization::*;
 pub use self::accesstoken::*;
+pub use self::ciba::{BackchannelAuthenticationFlow, BackchannelTokenFlow};
 pub use self::client_credentials::ClientCredentialsFlow;
+pub use self::custom_grant::CustomGrantFlow;
+pub use self::device::{DeviceAuthorizationFlow, DeviceTokenFlow, DeviceVerificationFlow};
+#[cfg(feature = "jwt")]
+pub use self::end_session::EndSessionFlow;
 pub use self::error::OAuthError;
+pub use self::exchange::TokenExchangeFlow;
+pub use self::introspection::IntrospectionFlow;
+#[cfg(feature = "jwt")]
+pub use self::jwks::JwksFlow;
+pub use self::metadata::MetadataFlow;
+pub use self::par::PushedAuthorizationFlow;
+#[cfg(feature = "password")]
+pub use self::password::PasswordFlow;
 pub use self::refresh::RefreshFlow;
 pub use self::resource::*;
+pub use self::revocation::RevocationFlow;
 pub use self::query::*;
 
 /// Answer from OwnerAuthorizer to indicate the owners choice.
@@ -77,8 +118,19 @@ pub enum OwnerConsent<Response: WebResponse> {
     /// Authorization was granted by the specified user.
     Authorized(String),
 
+    /// Authorization was granted by the specified user, with custom claims for the grant.
+    ///
+    /// Claims of the `ClaimsEnricher` of the endpoint are added to these, without replacing any.
+    AuthorizedWithClaims(String, Map<String, Value>),
+
     /// An error occurred while checking authorization.
     Error(Response::Error),
+
+    /// The request can not be decided without interacting with the owner, which the client forbade.
+    ///
+    /// The client receives the error, such as `LoginRequired` or `ConsentRequired` for requests
+    /// with `prompt=none`. Flows without a redirect to the client treat this as a denial.
+    Failed(AuthorizationErrorType),
 }
 
 /// Modifiable reason for creating a response to the client.
@@ -163,6 +215,18 @@ enum InnerTemplate<'a> {
         access_token_error: Option<&'a mut AccessTokenError>,
     },
 
+    /// The redirect url of an authorization request was not accepted for the client.
+    ///
+    /// The user agent must not be redirected to that url, the response is shown to the resource
+    /// owner instead. Its body contains the explanation of the error as text.
+    InvalidRedirect {
+        /// Information on the rejected redirect url.
+        ///
+        /// Endpoints may modify this description, for example to replace the explanation of the
+        /// body with a reference to a human readable page.
+        authorization_error: Option<&'a mut AuthorizationError>,
+    },
+
     /// An expected, normal response.
     ///
     /// The content of the response may require precise semantics to be standard compliant,
@@ -180,6 +244,8 @@ enum InnerTemplate<'a> {
 pub struct Solicitation<'flow> {
     pub(crate) grant: Cow<'flow, PreGrant>,
     pub(crate) state: Option<Cow<'flow, str>>,
+    pub(crate) extensions: Option<Cow<'flow, Extensions>>,
+    pub(crate) authentication: Option<Cow<'flow, AuthenticationRequest>>,
 }
 
 impl<'flow> Solicitation<'flow> {
@@ -190,6 +256,12 @@ impl<'flow> Solicitation<'flow> {
         Solicitation {
             grant: Cow::Owned(self.grant.into_owned()),
             state: self.state.map(|state| Cow::Owned(state.into_owned())),
+            extensions: self
+                .extensions
+                .map(|extensions| Cow::Owned(extensions.into_owned())),
+            authentication: self
+                .authentication
+                .map(|authentication| Cow::Owned(authentication.into_owned())),
         }
     }
 
@@ -213,6 +285,24 @@ impl<'flow> Solicitation<'flow> {
         }
     }
 
+    /// The authorization details requested by the client, to be presented to the owner.
+    ///
+    /// These are only available when the endpoint validated them with a `RichAuthorization`
+    /// extension. The owner consents to these details in addition to the scope of the grant.
+    pub fn authorization_details(&self) -> Option<Vec<AuthorizationDetail>> {
+        self.extensions.as_deref().and_then(authorization_details)
+    }
+
+    /// The parameters of the request about the authentication of the owner.
+    ///
+    /// These are available in the authorization flow, which parses `prompt`, `max_age`,
+    /// `login_hint` and `id_token_hint` of every request. A solicitor should show a login page
+    /// when `requires_login` and must not show any page when the request `is_silent`, answering
+    /// with `OwnerConsent::Failed` instead.
+    pub fn authentication_request(&self) -> Option<&AuthenticationRequest> {
+        self.authentication.as_deref()
+    }
+
     /// Create a new solicitation request from a pre grant.
     ///
     /// You usually wouldn't need to call this manually as it is called by the endpoint's flow and
@@ -221,6 +311,8 @@ impl<'flow> Solicitation<'flow> {
         Solicitation {
             grant: Cow::Borrowed(grant),
             state: None,
+            extensions: None,
+            authentication: None,
         }
     }
 
@@ -311,6 +403,23 @@ pub trait WebRequest {
     /// Contents of the authorization header or none if none exists. An Err value indicates a
     /// malformed header or request.
     fn authheader(&mut self) -> Result<Option<Cow<str>>, Self::Error>;
+
+    /// Contents of the `DPoP` header with the method and uri of the request, or none if there is
+    /// no such header.
+    ///
+    /// Only needed for DPoP bound tokens, requests are treated as having no proof by default.
+    fn dpop(&mut self) -> Result<Option<DpopProof>, Self::Error> {
+        Ok(None)
+    }
+
+    /// The certificate the client presented in a mutual TLS connection, or none if it presented
+    /// no certificate.
+    ///
+    /// Only needed for clients authenticating with mutual TLS and certificate-bound tokens,
+    /// requests are treated as having no certificate by default.
+    fn client_certificate(&mut self) -> Result<Option<ClientCertificate>, Self::Error> {
+        Ok(None)
+    }
 }
 
 /// Response representation into which the Request is transformed by the code_grant types.
@@ -324,6 +433,13 @@ pub trait WebResponse {
     /// Set the response status to 200.
     fn ok(&mut self) -> Result<(), Self::Error>;
 
+    /// Set the response status to 201.
+    ///
+    /// Falls back to `ok` by default, for responses without a dedicated status.
+    fn created(&mut self) -> Result<(), Self::Error> {
+        self.ok()
+    }
+
     /// A response which will redirect the user-agent to which the response is issued.
     fn redirect(&mut self, url: Url) -> Result<(), Self::Error>;
 
@@ -338,6 +454,13 @@ pub trait WebResponse {
 
     /// Json repsonse data, with media type `aplication/json.
     fn body_json(&mut self, data: &str) -> Result<(), Self::Error>;
+
+    /// An html page, with media type `text/html`.
+    ///
+    /// Falls back to `body_text` by default, for responses without a dedicated media type.
+    fn body_html(&mut self, html: &str) -> Result<(), Self::Error> {
+        self.body_text(html)
+    }
 }
 
 /// Intermediate trait to flow specific extensions.
@@ -362,6 +485,11 @@ pub trait Extension {
     fn client_credentials(&mut self) -> Option<&mut dyn ClientCredentialsExtension> {
         None
     }
+
+    /// The handler for resource extensions.
+    fn resource(&mut self) -> Option<&mut dyn ResourceExtension> {
+        None
+    }
 }
 
 /// Fuses requests and primitives into a coherent system to give a response.
@@ -436,6 +564,66 @@ pub trait Endpoint<Request: WebRequest> {
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         None
     }
+
+    /// A store of pending device authorizations if this endpoint can access one.
+    ///
+    /// Returning `None` is the default implementation and will implicate failing the device
+    /// flows but does not have any effect on other flows.
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        None
+    }
+
+    /// A store of pushed authorization requests if this endpoint can access one.
+    ///
+    /// Returning `None` is the default implementation and will implicate failing the pushed
+    /// authorization flow. The authorization flow then does not resolve any `request_uri`.
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        None
+    }
+
+    /// The signer of authorization responses in the JWT secured response modes.
+    ///
+    /// Returning `None` is the default implementation. The authorization flow then responds to
+    /// requests for a signed response with the plain parameters in the query.
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        None
+    }
+
+    /// The signer of ID tokens for OpenID Connect grants.
+    ///
+    /// Returning `None` is the default implementation. The access token flow then issues no ID
+    /// tokens, also for grants with the `openid` scope.
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        None
+    }
+    /// The clock deciding the expiry of codes and tokens in the flows.
+    ///
+    /// Returning `None` is the default implementation and uses the system clock.
+    fn clock(&self) -> Option<&dyn Clock> {
+        None
+    }
+
+    /// Adds members to the successful responses of the token flows.
+    ///
+    /// Returning `None` is the default implementation, responses then have only the members of
+    /// the flows. The hook applies to the authorization code, refresh, client credentials,
+    /// password, device and backchannel flows. Responses of custom grants are built by their
+    /// handlers instead.
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        None
+    }
+
+    /// Attaches custom claims to new grants.
+    ///
+    /// Returning `None` is the default implementation, grants then only have the claims that the
+    /// owner solicitor attached with `OwnerConsent::AuthorizedWithClaims`. The enricher applies to
+    /// the grants of the authorization code, client credentials, password, device and
+    /// backchannel flows.
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        None
+    }
 }
 
 impl<'a> Template<'a> {
@@ -465,12 +653,18 @@ impl<'a> Template<'a> {
         InnerTemplate::Redirect { authorization_error }.into()
     }
 
+    /// Create a template for an authorization request with a rejected redirect url
+    pub fn new_invalid_redirect(authorization_error: Option<&'a mut AuthorizationError>) -> Self {
+        InnerTemplate::InvalidRedirect { authorization_error }.into()
+    }
+
     /// The corresponding status code.
     pub fn status(&self) -> ResponseStatus {
         match self.inner {
             InnerTemplate::Unauthorized { .. } => ResponseStatus::Unauthorized,
             InnerTemplate::Redirect { .. } => ResponseStatus::Redirect,
             InnerTemplate::BadRequest { .. } => ResponseStatus::BadRequest,
+            InnerTemplate::InvalidRedirect { .. } => ResponseStatus::BadRequest,
             InnerTemplate::Ok => ResponseStatus::Ok,
         }
     }
@@ -496,6 +690,9 @@ impl<'a> Template<'a> {
             InnerTemplate::Redirect {
                 authorization_error, ..
             } => reborrow(authorization_error),
+            InnerTemplate::InvalidRedirect {
+                authorization_error, ..
+            } => reborrow(authorization_error),
             _ => None,
         }
     }
@@ -595,6 +792,35 @@ impl<'a, R: WebRequest, E: Endpoint<R>> Endpoint<R> for &'a mut E {
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         (**self).extension()
     }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        (**self).device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        (**self).request_uris_mut()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        (**self).response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        (**self).id_token_signer()
+    }
+    fn clock(&self) -> Option<&dyn Clock> {
+        (**self).clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        (**self).token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        (**self).claims_enricher()
+    }
 }
 
 impl<'a, R: WebRequest, E: Endpoint<R> + 'a> Endpoint<R> for Box<E> {
@@ -635,6 +861,35 @@ impl<'a, R: WebRequest, E: Endpoint<R> + 'a> Endpoint<R> for Box<E> {
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         (**self).extension()
     }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        (**self).device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        (**self).request_uris_mut()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        (**self).response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        (**self).id_token_signer()
+    }
+    fn clock(&self) -> Option<&dyn Clock> {
+        (**self).clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        (**self).token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        (**self).claims_enricher()
+    }
 }
 
 impl Extension for () {}
@@ -82,6 +82,15 @@ impl NormalizedParameter {
             .and_modify(|val| *val = None)
             .or_insert(unique_val);
     }
+
+    /// Iterate over all keys and their unique values.
+    ///
+    /// The value is `None` for keys that appeared more than once.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.inner
+            .iter()
+            .map(|(key, val)| (key.as_ref(), val.as_ref().map(Cow::as_ref)))
+    }
 }
 
 impl Borrow<dyn QueryParameter> for NormalizedParameter {
@@ -4,8 +4,10 @@ use std::str::from_utf8;
 
 use crate::code_grant::refresh::{refresh, Error, Endpoint as RefreshEndpoint, Request};
 use crate::primitives::{registrar::Registrar, issuer::Issuer};
+use crate::primitives::clock::{Clock, SystemClock};
+use crate::code_grant::accesstoken::TokenResponseHook;
 use super::{
-    Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
+    ClientCertificate, Endpoint, InnerTemplate, OAuthError, QueryParameter, WebRequest, WebResponse,
     is_authorization_method,
 };
 
@@ -33,6 +35,9 @@ struct WrappedRequest<'a, R: WebRequest + 'a> {
     /// The authorization token.
     authorization: Option<Authorization>,
 
+    /// The certificate of the client in a mutual TLS connection.
+    client_certificate: Option<ClientCertificate>,
+
     /// An error if one occurred.
     error: Option<InitError<R::Error>>,
 }
@@ -139,9 +144,9 @@ fn token_error<E: Endpoint<R>, R: WebRequest>(
                 .map_err(|err| endpoint.web_error(err))?;
             response
         }
-        Error::Primitive => {
+        Error::Primitive(err) => {
             // FIXME: give the context for restoration.
-            return Err(endpoint.error(OAuthError::PrimitiveError));
+            return Err(endpoint.error(err.into()));
         }
     })
 }
@@ -161,6 +166,7 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
 
         Ok(WrappedRequest {
             request: PhantomData,
+            client_certificate: request.client_certificate().map_err(InitError::Internal)?,
             body: request.urlbody().map_err(InitError::Internal)?,
             authorization,
             error: None,
@@ -172,6 +178,7 @@ impl<'a, R: WebRequest + 'a> WrappedRequest<'a, R> {
             request: PhantomData,
             body: Cow::Owned(Default::default()),
             authorization: None,
+            client_certificate: None,
             error: Some(err),
         }
     }
@@ -218,6 +225,14 @@ impl<E: Endpoint<R>, R: WebRequest> RefreshEndpoint for WrappedRefresh<E, R> {
     fn issuer(&mut self) -> &mut dyn Issuer {
         self.inner.issuer_mut().unwrap()
     }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock().unwrap_or(&SystemClock)
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.inner.token_response_hook()
+    }
 }
 
 impl<'a, R: WebRequest> Request for WrappedRequest<'a, R> {
@@ -246,4 +261,8 @@ impl<'a, R: WebRequest> Request for WrappedRequest<'a, R> {
     fn extension(&self, key: &str) -> Option<Cow<str>> {
         self.body.unique_value(key)
     }
+
+    fn client_certificate(&self) -> Option<&ClientCertificate> {
+        self.client_certificate.as_ref()
+    }
 }
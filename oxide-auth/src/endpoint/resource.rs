@@ -4,6 +4,7 @@ use crate::code_grant::resource::{
     protect, Error as ResourceError, Endpoint as ResourceEndpoint, Request as ResourceRequest,
 };
 use crate::primitives::grant::Grant;
+use crate::primitives::clock::SystemClock;
 
 use super::*;
 
@@ -14,6 +15,7 @@ where
     R: WebRequest,
 {
     endpoint: WrappedResource<E, R>,
+    step_up: Option<StepUp>,
 }
 
 struct WrappedResource<E: Endpoint<R>, R: WebRequest>(E, PhantomData<R>);
@@ -25,6 +27,12 @@ struct WrappedRequest<R: WebRequest> {
     /// The authorization token.
     authorization: Option<String>,
 
+    /// The DPoP proof of the request.
+    dpop: Option<DpopProof>,
+
+    /// The certificate of the client in a mutual TLS connection.
+    client_certificate: Option<ClientCertificate>,
+
     /// An error if one occurred.
     ///
     /// Actual parsing of the authorization header is done in the lower level.
@@ -34,6 +42,8 @@ struct WrappedRequest<R: WebRequest> {
 struct Scoped<'a, E: 'a, R: 'a> {
     request: &'a mut R,
     endpoint: &'a mut E,
+    extension_fallback: (),
+    step_up: Option<&'a StepUp>,
 }
 
 impl<E, R> ResourceFlow<E, R>
@@ -62,9 +72,19 @@ where
 
         Ok(ResourceFlow {
             endpoint: WrappedResource(endpoint, PhantomData),
+            step_up: None,
         })
     }
 
+    /// Require a sufficiently strong and recent authentication of the owner.
+    ///
+    /// Tokens of weaker or older authentications are refused with an
+    /// `insufficient_user_authentication` challenge, with which the client can request a new
+    /// authorization from the owner.
+    pub fn require_authentication(&mut self, step_up: StepUp) {
+        self.step_up = Some(step_up);
+    }
+
     /// Use the checked endpoint to check for authorization for a resource.
     ///
     /// ## Panics
@@ -78,6 +98,8 @@ where
             let mut scoped = Scoped {
                 request: &mut request,
                 endpoint: &mut self.endpoint.0,
+                extension_fallback: (),
+                step_up: self.step_up.as_ref(),
             };
 
             protect(&mut scoped, &wrapped)
@@ -99,9 +121,7 @@ where
             ResourceError::InvalidRequest { .. } => InnerTemplate::BadRequest {
                 access_token_error: None,
             },
-            ResourceError::PrimitiveError => {
-                return Err(self.endpoint.0.error(OAuthError::PrimitiveError))
-            }
+            ResourceError::PrimitiveError(err) => return Err(self.endpoint.0.error((*err).into())),
         };
 
         let mut response = self.endpoint.0.response(request, template.into())?;
@@ -122,9 +142,21 @@ impl<R: WebRequest> WrappedRequest<R> {
             Err(error) => return Self::from_error(error),
         };
 
+        let dpop = match request.dpop() {
+            Ok(dpop) => dpop,
+            Err(error) => return Self::from_error(error),
+        };
+
+        let client_certificate = match request.client_certificate() {
+            Ok(certificate) => certificate,
+            Err(error) => return Self::from_error(error),
+        };
+
         WrappedRequest {
             request: PhantomData,
             authorization: token,
+            dpop,
+            client_certificate,
             error: None,
         }
     }
@@ -133,6 +165,8 @@ impl<R: WebRequest> WrappedRequest<R> {
         WrappedRequest {
             request: PhantomData,
             authorization: None,
+            dpop: None,
+            client_certificate: None,
             error: Some(error),
         }
     }
@@ -146,6 +180,21 @@ impl<'a, E: Endpoint<R> + 'a, R: WebRequest + 'a> ResourceEndpoint for Scoped<'a
     fn issuer(&mut self) -> &dyn Issuer {
         self.endpoint.issuer_mut().unwrap()
     }
+
+    fn extension(&mut self) -> &mut dyn ResourceExtension {
+        self.endpoint
+            .extension()
+            .and_then(super::Extension::resource)
+            .unwrap_or(&mut self.extension_fallback)
+    }
+
+    fn step_up(&mut self) -> Option<&StepUp> {
+        self.step_up
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.endpoint.clock().unwrap_or(&SystemClock)
+    }
 }
 
 impl<R: WebRequest> ResourceRequest for WrappedRequest<R> {
@@ -156,4 +205,12 @@ impl<R: WebRequest> ResourceRequest for WrappedRequest<R> {
     fn token(&self) -> Option<Cow<str>> {
         self.authorization.as_deref().map(Cow::Borrowed)
     }
+
+    fn dpop(&self) -> Option<&DpopProof> {
+        self.dpop.as_ref()
+    }
+
+    fn client_certificate(&self) -> Option<&ClientCertificate> {
+        self.client_certificate.as_ref()
+    }
 }
@@ -0,0 +1,232 @@
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::code_grant::assertion::{
+    AssertionClaims, Audience, ClientAssertions, JwtBearer, JWT_BEARER_CLIENT_ASSERTION_TYPE,
+    JWT_BEARER_GRANT_TYPE,
+};
+use crate::code_grant::custom_grant::GrantTypes;
+use crate::frontends::simple::endpoint::{client_credentials_flow, custom_grant_flow, ClientOwner};
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::issuer::{Issuer, TokenMap};
+use crate::primitives::jwt::{self, JwkSet, SigningKey};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+const TOKEN_ENDPOINT: &str = "https://auth.example.com/token";
+const EXAMPLE_SECRET: &[u8] = b"a secret shared with the client of at least 32 bytes";
+const TRUSTED_ISSUER: &str = "https://idp.example.com";
+
+struct AssertionSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+    client_key: SigningKey,
+    issuer_key: SigningKey,
+}
+
+impl AssertionSetup {
+    fn new() -> AssertionSetup {
+        let client_key = SigningKey::es256(&SigningKey::generate_es256().unwrap())
+            .unwrap()
+            .with_kid("client");
+        let issuer_key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let jwks = JwkSet {
+            keys: vec![client_key.jwk()],
+        };
+
+        let mut registrar = ClientMap::new();
+        registrar.register_client(
+            Client::public(
+                EXAMPLE_CLIENT_ID,
+                RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+                EXAMPLE_SCOPE.parse().unwrap(),
+            )
+            .with_jwks(&serde_json::to_string(&jwks).unwrap())
+            .with_jwt_secret(EXAMPLE_SECRET),
+        );
+
+        AssertionSetup {
+            registrar,
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            client_key,
+            issuer_key,
+        }
+    }
+
+    fn claims(iss: &str, sub: &str, aud: &str, lifetime: Duration) -> AssertionClaims {
+        let now = Utc::now();
+        AssertionClaims {
+            iss: iss.to_owned(),
+            sub: sub.to_owned(),
+            aud: Audience::One(aud.to_owned()),
+            exp: (now + lifetime).timestamp(),
+            nbf: None,
+            iat: Some(now.timestamp()),
+            jti: Some("assertion".to_owned()),
+        }
+    }
+
+    fn client_assertion(&self, aud: &str, lifetime: Duration) -> String {
+        let claims = Self::claims(EXAMPLE_CLIENT_ID, EXAMPLE_CLIENT_ID, aud, lifetime);
+        self.client_key.sign("JWT", &claims).unwrap()
+    }
+
+    fn client_credentials(&mut self, client_assertion: &str) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("grant_type", "client_credentials"),
+                    ("client_assertion_type", JWT_BEARER_CLIENT_ASSERTION_TYPE),
+                    ("client_assertion", client_assertion),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth: None,
+        };
+
+        let mut owner = ClientOwner;
+        let mut flow = client_credentials_flow(&self.registrar, &mut self.issuer, &mut owner);
+        flow.client_assertions(ClientAssertions::new(TOKEN_ENDPOINT));
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn jwt_bearer(&mut self, assertion: &str, client_assertion: Option<&str>) -> CraftedResponse {
+        let mut body = vec![("grant_type", JWT_BEARER_GRANT_TYPE), ("assertion", assertion)];
+        if let Some(client_assertion) = client_assertion {
+            body.push(("client_assertion_type", JWT_BEARER_CLIENT_ASSERTION_TYPE));
+            body.push(("client_assertion", client_assertion));
+        }
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(body.iter().to_single_value_query()),
+            auth: None,
+        };
+
+        let mut handler = JwtBearer::new(ClientAssertions::new(TOKEN_ENDPOINT));
+        handler.trust(
+            TRUSTED_ISSUER,
+            JwkSet {
+                keys: vec![self.issuer_key.jwk()],
+            },
+        );
+        let mut grant_types = GrantTypes::new();
+        grant_types.register(JWT_BEARER_GRANT_TYPE, handler);
+
+        let mut flow = custom_grant_flow(&self.registrar, &mut self.issuer, &mut grant_types);
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn owner(&self, response: CraftedResponse) -> String {
+        assert_eq!(response.status, Status::Ok);
+        let token: TokenResponse = match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+            other => panic!("Expected json body, got {:?}", other),
+        };
+        let grant = self
+            .issuer
+            .recover_token(&token.access_token.unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(grant.client_id, EXAMPLE_CLIENT_ID);
+        grant.owner_id
+    }
+}
+
+#[test]
+fn private_key_jwt() {
+    let mut setup = AssertionSetup::new();
+    let assertion = setup.client_assertion(TOKEN_ENDPOINT, Duration::minutes(5));
+    let response = setup.client_credentials(&assertion);
+    assert_eq!(setup.owner(response), EXAMPLE_CLIENT_ID);
+
+    // Assertions for another server or past their expiry are not accepted.
+    let assertion = setup.client_assertion("https://other.example.com/token", Duration::minutes(5));
+    let response = setup.client_credentials(&assertion);
+    assert_eq!(response.status, Status::Unauthorized);
+
+    let assertion = setup.client_assertion(TOKEN_ENDPOINT, Duration::minutes(-5));
+    let response = setup.client_credentials(&assertion);
+    assert_eq!(response.status, Status::Unauthorized);
+}
+
+#[test]
+fn client_secret_jwt() {
+    let mut setup = AssertionSetup::new();
+    let claims = AssertionSetup::claims(
+        EXAMPLE_CLIENT_ID,
+        EXAMPLE_CLIENT_ID,
+        TOKEN_ENDPOINT,
+        Duration::minutes(5),
+    );
+
+    let assertion = jwt::sign_hmac(EXAMPLE_SECRET, "JWT", &claims).unwrap();
+    let response = setup.client_credentials(&assertion);
+    assert_eq!(setup.owner(response), EXAMPLE_CLIENT_ID);
+
+    let assertion = jwt::sign_hmac(b"not the secret of the client", "JWT", &claims).unwrap();
+    let response = setup.client_credentials(&assertion);
+    assert_eq!(response.status, Status::Unauthorized);
+}
+
+#[test]
+fn jwt_bearer_grant() {
+    let mut setup = AssertionSetup::new();
+    let claims = AssertionSetup::claims(
+        TRUSTED_ISSUER,
+        EXAMPLE_OWNER_ID,
+        TOKEN_ENDPOINT,
+        Duration::minutes(5),
+    );
+    let assertion = setup.issuer_key.sign("JWT", &claims).unwrap();
+    let client_assertion = setup.client_assertion(TOKEN_ENDPOINT, Duration::minutes(5));
+
+    let response = setup.jwt_bearer(&assertion, Some(&client_assertion));
+    assert_eq!(setup.owner(response), EXAMPLE_OWNER_ID);
+
+    // The grant of a trusted issuer does not identify the client.
+    let response = setup.jwt_bearer(&assertion, None);
+    assert_eq!(response.status, Status::Unauthorized);
+
+    // A client may assert on its own behalf.
+    let claims = AssertionSetup::claims(
+        EXAMPLE_CLIENT_ID,
+        EXAMPLE_OWNER_ID,
+        TOKEN_ENDPOINT,
+        Duration::minutes(5),
+    );
+    let assertion = setup.client_key.sign("JWT", &claims).unwrap();
+    let response = setup.jwt_bearer(&assertion, None);
+    assert_eq!(setup.owner(response), EXAMPLE_OWNER_ID);
+}
+
+#[test]
+fn jwt_bearer_grant_rejected() {
+    let mut setup = AssertionSetup::new();
+    let client_assertion = setup.client_assertion(TOKEN_ENDPOINT, Duration::minutes(5));
+
+    let expired = AssertionSetup::claims(
+        TRUSTED_ISSUER,
+        EXAMPLE_OWNER_ID,
+        TOKEN_ENDPOINT,
+        Duration::minutes(-5),
+    );
+    let assertion = setup.issuer_key.sign("JWT", &expired).unwrap();
+    let response = setup.jwt_bearer(&assertion, Some(&client_assertion));
+    assert_eq!(response.status, Status::BadRequest);
+
+    // Signed by a key not belonging to the claimed issuer.
+    let forged = AssertionSetup::claims(
+        TRUSTED_ISSUER,
+        EXAMPLE_OWNER_ID,
+        TOKEN_ENDPOINT,
+        Duration::minutes(5),
+    );
+    let assertion = setup.client_key.sign("JWT", &forged).unwrap();
+    let response = setup.jwt_bearer(&assertion, Some(&client_assertion));
+    assert_eq!(response.status, Status::BadRequest);
+}
@@ -0,0 +1,156 @@
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::endpoint::{AccessTokenFlow, Endpoint, ResourceFlow};
+use crate::frontends::simple::endpoint::{Error, Generic, Vacant};
+use crate::frontends::simple::extensions::{AddonList, AudienceRestriction, Extended, ResourceIndicators};
+use crate::primitives::authorizer::{AuthMap, Authorizer};
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::scope::Scope;
+
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+const API: &str = "https://api.example.com/";
+const OTHER_API: &str = "https://other.example.com/";
+
+struct AudienceSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+    resource_scope: [Scope; 1],
+    indicators: Arc<ResourceIndicators>,
+}
+
+impl AudienceSetup {
+    fn new(indicators: ResourceIndicators) -> AudienceSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+        ));
+
+        AudienceSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthorizationCode".to_owned())),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            resource_scope: [EXAMPLE_SCOPE.parse().unwrap()],
+            indicators: Arc::new(indicators),
+        }
+    }
+
+    fn endpoint(
+        &mut self, restriction: AudienceRestriction,
+    ) -> impl Endpoint<CraftedRequest, Error = Error<CraftedRequest>> + '_ {
+        let mut extensions = AddonList::new();
+        extensions.push_access_token(self.indicators.clone());
+        extensions.push_resource(Arc::new(restriction));
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            scopes: &self.resource_scope[..],
+            solicitor: Vacant,
+            response: Vacant,
+        };
+
+        Extended::extend_with(endpoint, extensions)
+    }
+
+    fn token(&mut self, resource: Option<&str>) -> CraftedResponse {
+        let code = self
+            .authorizer
+            .authorize(Grant {
+                owner_id: EXAMPLE_OWNER_ID.to_owned(),
+                client_id: EXAMPLE_CLIENT_ID.to_owned(),
+                scope: EXAMPLE_SCOPE.parse().unwrap(),
+                redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+                until: Utc::now() + Duration::minutes(10),
+                extensions: Extensions::new(),
+            })
+            .unwrap();
+
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("client_id", EXAMPLE_CLIENT_ID),
+            ("code", &code),
+            ("redirect_uri", EXAMPLE_REDIRECT_URI),
+        ];
+        params.extend(resource.map(|resource| ("resource", resource)));
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(params.iter().to_single_value_query()),
+            auth: None,
+        };
+
+        let mut endpoint = self.endpoint(AudienceRestriction::optional(API));
+        let mut flow = AccessTokenFlow::prepare(&mut endpoint)
+            .unwrap_or_else(|_| panic!("Not violating any requirements on access token flow."));
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn access_token(&mut self, resource: Option<&str>) -> String {
+        let response = self.token(resource);
+        assert_eq!(response.status, Status::Ok);
+        let response: TokenResponse = match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).unwrap(),
+            other => panic!("Expected json body, got {:?}", other),
+        };
+        response.access_token.unwrap()
+    }
+
+    fn access(&mut self, token: &str, restriction: AudienceRestriction) -> bool {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: None,
+            auth: Some(format!("Bearer {}", token)),
+        };
+
+        let mut endpoint = self.endpoint(restriction);
+        let mut flow = ResourceFlow::prepare(&mut endpoint)
+            .unwrap_or_else(|_| panic!("Not violating any requirements on resource flow."));
+        match flow.execute(request) {
+            Ok(_) => true,
+            Err(Ok(response)) => {
+                assert_eq!(response.status, Status::Unauthorized);
+                false
+            }
+            Err(Err(_)) => panic!("Expected non-error response"),
+        }
+    }
+}
+
+#[test]
+fn audience_from_resource() {
+    let mut setup = AudienceSetup::new(ResourceIndicators::new());
+    let token = setup.access_token(Some(API));
+    assert!(setup.access(&token, AudienceRestriction::required(API)));
+    assert!(!setup.access(&token, AudienceRestriction::optional(OTHER_API)));
+
+    // Tokens without an audience are only accepted where none is required.
+    let token = setup.access_token(None);
+    assert!(setup.access(&token, AudienceRestriction::optional(API)));
+    assert!(!setup.access(&token, AudienceRestriction::required(API)));
+}
+
+#[test]
+fn audience_from_client() {
+    let mut indicators = ResourceIndicators::new();
+    indicators.client(EXAMPLE_CLIENT_ID, vec![API]);
+    let mut setup = AudienceSetup::new(indicators);
+
+    let token = setup.access_token(None);
+    assert!(setup.access(&token, AudienceRestriction::required(API)));
+    assert!(!setup.access(&token, AudienceRestriction::required(OTHER_API)));
+
+    assert_eq!(setup.token(Some(OTHER_API)).status, Status::BadRequest);
+    assert_eq!(setup.token(Some("not a uri")).status, Status::BadRequest);
+}
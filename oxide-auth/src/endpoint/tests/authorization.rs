@@ -1,21 +1,39 @@
 use std::collections::HashMap;
 
 use crate::primitives::authorizer::AuthMap;
-use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::registrar::{Client, ClientMap, RedirectMismatch, RegisteredUrl};
 
-use crate::endpoint::{OwnerSolicitor};
+use crate::code_grant::error::AuthorizationErrorType;
+use crate::endpoint::{AuthenticationRequest, OwnerConsent, OwnerSolicitor, Prompt, Solicitation};
 
 use crate::frontends::simple::endpoint::authorization_flow;
 
-use super::{CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
+use super::{Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
 use super::{Allow, Deny};
 use super::defaults::*;
 
+/// Shows a login page, unless the request forbids it.
+struct LoginPage(Option<AuthenticationRequest>);
+
 struct AuthorizationSetup {
     registrar: ClientMap,
     authorizer: AuthMap<TestGenerator>,
 }
 
+impl OwnerSolicitor<CraftedRequest> for LoginPage {
+    fn check_consent(
+        &mut self, _: &mut CraftedRequest, solicitation: Solicitation,
+    ) -> OwnerConsent<CraftedResponse> {
+        self.0 = solicitation.authentication_request().cloned();
+        match &self.0 {
+            Some(request) if request.is_silent() => {
+                OwnerConsent::Failed(AuthorizationErrorType::LoginRequired)
+            }
+            _ => OwnerConsent::InProgress(CraftedResponse::default()),
+        }
+    }
+}
+
 impl AuthorizationSetup {
     fn new() -> AuthorizationSetup {
         let mut registrar = ClientMap::new();
@@ -65,6 +83,27 @@ impl AuthorizationSetup {
         }
     }
 
+    fn test_invalid_redirect(&mut self, request: CraftedRequest, mismatch: RedirectMismatch) {
+        let response = authorization_flow(
+            &self.registrar,
+            &mut self.authorizer,
+            &mut Allow(EXAMPLE_OWNER_ID.to_string()),
+        )
+        .execute(request)
+        .expect("Expected the mismatch to be shown");
+
+        assert_eq!(response.status, Status::BadRequest);
+        assert!(
+            response.location.is_none(),
+            "Redirect to rejected url {:?}",
+            response
+        );
+        match response.body {
+            Some(Body::Text(ref text)) if text == mismatch.description() => (),
+            other => panic!("Expected explanation of {:?}: {:?}", mismatch, other),
+        }
+    }
+
     fn test_error_redirect<P>(&mut self, request: CraftedRequest, mut pagehandler: P)
     where
         P: OwnerSolicitor<CraftedRequest>,
@@ -90,6 +129,40 @@ impl AuthorizationSetup {
             other => panic!("Expected location with error set description: {:?}", other),
         }
     }
+
+    fn test_error_type<P>(&mut self, request: CraftedRequest, mut pagehandler: P, error: &str)
+    where
+        P: OwnerSolicitor<CraftedRequest>,
+    {
+        let response = authorization_flow(&mut self.registrar, &mut self.authorizer, &mut pagehandler)
+            .execute(request)
+            .expect("Expected redirect with error set");
+
+        match response.location {
+            Some(ref url)
+                if url
+                    .query_pairs()
+                    .any(|(key, value)| key == "error" && value == error) =>
+            {
+                ()
+            }
+            other => panic!("Expected location with error {}: {:?}", error, other),
+        }
+    }
+}
+
+fn prompt_request(parameters: &[(&str, &str)]) -> CraftedRequest {
+    let mut query = vec![
+        ("response_type", "code"),
+        ("client_id", EXAMPLE_CLIENT_ID),
+        ("redirect_uri", EXAMPLE_REDIRECT_URI),
+    ];
+    query.extend_from_slice(parameters);
+    CraftedRequest {
+        query: Some(query.iter().to_single_value_query()),
+        urlbody: None,
+        auth: None,
+    }
 }
 
 #[test]
@@ -143,8 +216,8 @@ fn auth_request_silent_unknown_client() {
 }
 
 #[test]
-fn auth_request_silent_mismatching_redirect() {
-    // The redirect_uri does not match
+fn auth_request_mismatching_redirect() {
+    // The redirect_uri does not match, the reason is shown instead of redirecting
     let mismatching_redirect = CraftedRequest {
         query: Some(
             vec![
@@ -159,14 +232,34 @@ fn auth_request_silent_mismatching_redirect() {
         auth: None,
     };
 
-    AuthorizationSetup::new().test_silent_error(mismatching_redirect);
+    AuthorizationSetup::new()
+        .test_invalid_redirect(mismatching_redirect, RedirectMismatch::Unregistered);
+}
+
+#[test]
+fn auth_request_fragment_redirect() {
+    let fragment_redirect = CraftedRequest {
+        query: Some(
+            vec![
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", "https://client.example/endpoint#fragment"),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: None,
+    };
+
+    AuthorizationSetup::new().test_invalid_redirect(fragment_redirect, RedirectMismatch::Fragment);
 }
 
 #[test]
-fn auth_request_silent_mismatching_literal_redirect() {
+fn auth_request_mismatching_literal_redirect() {
     // The redirect_uri does not match if stringly matched.
     let mut setup = AuthorizationSetup::new();
-    const UNIQUE_CLIENT: &'static str = "client_auth_request_silent_mismatching_literal_redirect";
+    const UNIQUE_CLIENT: &'static str = "client_auth_request_mismatching_literal_redirect";
     const REGISTERED_URL: &'static str = "https://right.client.example/endpoint";
     const TRIED_URL: &'static str = "https://right.client.example/endpoint/";
 
@@ -192,7 +285,7 @@ fn auth_request_silent_mismatching_literal_redirect() {
         auth: None,
     };
 
-    setup.test_silent_error(mismatching_redirect);
+    setup.test_invalid_redirect(mismatching_redirect, RedirectMismatch::Unregistered);
 
     let valid_redirect = CraftedRequest {
         query: Some(
@@ -292,3 +385,71 @@ fn auth_request_error_malformed_scope() {
 
     AuthorizationSetup::new().test_error_redirect(malformed_scope, Allow(EXAMPLE_OWNER_ID.to_string()));
 }
+
+#[test]
+fn auth_request_authentication_parameters() {
+    let request = prompt_request(&[
+        ("prompt", "login consent"),
+        ("max_age", "600"),
+        ("login_hint", "alice@example.com"),
+        ("id_token_hint", "eyJhbGciOiJub25lIn0.e30."),
+    ]);
+
+    let mut page = LoginPage(None);
+    let mut setup = AuthorizationSetup::new();
+    let response = authorization_flow(&mut setup.registrar, &mut setup.authorizer, &mut page)
+        .execute(request)
+        .expect("Should not error");
+    assert_eq!(response.status, Status::Ok);
+
+    let authentication = page.0.expect("Expected the authentication request");
+    assert_eq!(authentication.prompt, vec![Prompt::Login, Prompt::Consent]);
+    assert_eq!(authentication.max_age, Some(600));
+    assert_eq!(authentication.login_hint.as_deref(), Some("alice@example.com"));
+    assert_eq!(
+        authentication.id_token_hint.as_deref(),
+        Some("eyJhbGciOiJub25lIn0.e30.")
+    );
+}
+
+#[test]
+fn auth_request_silent_authentication() {
+    let mut setup = AuthorizationSetup::new();
+    // The solicitor decides on the error.
+    setup.test_error_type(
+        prompt_request(&[("prompt", "none")]),
+        LoginPage(None),
+        "login_required",
+    );
+
+    // A solicitor showing a page anyways is overruled.
+    struct AnyPage;
+    impl OwnerSolicitor<CraftedRequest> for AnyPage {
+        fn check_consent(
+            &mut self, _: &mut CraftedRequest, _: Solicitation,
+        ) -> OwnerConsent<CraftedResponse> {
+            OwnerConsent::InProgress(CraftedResponse::default())
+        }
+    }
+    setup.test_error_type(
+        prompt_request(&[("prompt", "none")]),
+        AnyPage,
+        "interaction_required",
+    );
+
+    // A logged in owner still authorizes silently.
+    setup.test_success(prompt_request(&[("prompt", "none")]));
+}
+
+#[test]
+fn auth_request_error_authentication_parameters() {
+    let mut setup = AuthorizationSetup::new();
+    for parameters in &[
+        [("prompt", "none login")],
+        [("max_age", "-1")],
+        [("max_age", "never")],
+    ] {
+        let allow = Allow(EXAMPLE_OWNER_ID.to_string());
+        setup.test_error_type(prompt_request(parameters), allow, "invalid_request");
+    }
+}
@@ -0,0 +1,227 @@
+use crate::code_grant::ciba::{BackchannelAuthenticationResponse, CIBA_GRANT_TYPE};
+use crate::endpoint::{BackchannelAuthenticationFlow, BackchannelTokenFlow};
+use crate::frontends::simple::endpoint::{Generic, Vacant};
+use crate::primitives::ciba::{BackchannelMap, BackchannelRequest, BackchannelStore, NotifyOwner, WithPing};
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use serde_json;
+use url::Url;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+const LOGIN_HINT: &str = "owner@example.com";
+const NOTIFICATION_ENDPOINT: &str = "https://client.example/ciba";
+
+struct CibaSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<TestGenerator>,
+    owners: Owners,
+    basic_authorization: String,
+}
+
+/// Knows a single owner and remembers the requests pushed to them.
+struct Owners {
+    notified: RefCell<Vec<BackchannelRequest>>,
+}
+
+impl NotifyOwner for Owners {
+    fn identify(&self, login_hint: &str) -> Result<Option<String>, ()> {
+        Ok(Some(EXAMPLE_OWNER_ID.to_owned()).filter(|_| login_hint == LOGIN_HINT))
+    }
+
+    fn notify(&self, _: &str, request: &BackchannelRequest) -> Result<(), ()> {
+        self.notified.borrow_mut().push(request.clone());
+        Ok(())
+    }
+}
+
+impl CibaSetup {
+    fn new() -> CibaSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            "openid example".parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        let basic_authorization =
+            base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+        CibaSetup {
+            registrar,
+            issuer: TokenMap::new(TestGenerator("AccessToken".to_owned())),
+            owners: Owners {
+                notified: RefCell::new(Vec::new()),
+            },
+            basic_authorization,
+        }
+    }
+
+    fn authenticate<S: BackchannelStore>(
+        &mut self, store: S, params: &[(&str, &str)], ping: bool,
+    ) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(params.iter().to_single_value_query()),
+            auth: Some(format!("Basic {}", self.basic_authorization)),
+        };
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: Vacant,
+            issuer: &mut self.issuer,
+            scopes: Vacant,
+            solicitor: Vacant,
+            response: Vacant,
+        };
+        let mut flow = BackchannelAuthenticationFlow::prepare(endpoint, store, &self.owners)
+            .unwrap_or_else(|_| panic!("Not violating any requirements on backchannel flow."));
+        if ping {
+            flow.ping_client(EXAMPLE_CLIENT_ID, NOTIFICATION_ENDPOINT.parse().unwrap());
+        }
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn start<S: BackchannelStore>(&mut self, store: S) -> BackchannelAuthenticationResponse {
+        let params = [("scope", "openid"), ("login_hint", LOGIN_HINT)];
+        let response = self.authenticate(store, &params, false);
+        assert_eq!(response.status, Status::Ok);
+        serde_json::from_str(&Self::json_body(response)).expect("Expected valid json body")
+    }
+
+    fn poll<S: BackchannelStore>(&mut self, store: S, auth_req_id: &str) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [("grant_type", CIBA_GRANT_TYPE), ("auth_req_id", auth_req_id)]
+                    .iter()
+                    .to_single_value_query(),
+            ),
+            auth: Some(format!("Basic {}", self.basic_authorization)),
+        };
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: Vacant,
+            issuer: &mut self.issuer,
+            scopes: Vacant,
+            solicitor: Vacant,
+            response: Vacant,
+        };
+        let mut flow = BackchannelTokenFlow::prepare(endpoint, store)
+            .unwrap_or_else(|_| panic!("Not violating any requirements on backchannel token flow."));
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn assert_error(response: CraftedResponse, error: &str) {
+        assert_eq!(response.status, Status::BadRequest);
+        let content: HashMap<String, String> = serde_json::from_str(&Self::json_body(response)).unwrap();
+        assert_eq!(content.get("error").map(String::as_str), Some(error));
+    }
+
+    fn json_body(response: CraftedResponse) -> String {
+        match response.body {
+            Some(Body::Json(json)) => json,
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn ciba_poll_approved() {
+    let mut setup = CibaSetup::new();
+    let mut store = BackchannelMap::new();
+    let started = setup.start(&mut store);
+    assert_eq!(started.interval, Some(5));
+
+    let notified = setup.owners.notified.borrow()[0].clone();
+    assert_eq!(notified.owner_id, EXAMPLE_OWNER_ID);
+    assert_eq!(notified.grant.client_id, EXAMPLE_CLIENT_ID);
+
+    CibaSetup::assert_error(
+        setup.poll(&mut store, &started.auth_req_id),
+        "authorization_pending",
+    );
+    CibaSetup::assert_error(setup.poll(&mut store, &started.auth_req_id), "slow_down");
+
+    assert_eq!(store.approve(&started.auth_req_id), Ok(true));
+    let response = setup.poll(&mut store, &started.auth_req_id);
+    assert_eq!(response.status, Status::Ok);
+    let token: HashMap<String, serde_json::Value> =
+        serde_json::from_str(&CibaSetup::json_body(response)).unwrap();
+    assert_eq!(token.get("access_token"), Some(&"AccessToken".into()));
+
+    CibaSetup::assert_error(setup.poll(&mut store, &started.auth_req_id), "invalid_grant");
+}
+
+#[test]
+fn ciba_ping_denied() {
+    let mut setup = CibaSetup::new();
+    let pinged = RefCell::new(Vec::new());
+    let mut store = WithPing {
+        inner: BackchannelMap::new(),
+        ping: |endpoint: &Url, token: &str, _: &str| {
+            pinged.borrow_mut().push((endpoint.to_string(), token.to_owned()));
+            Ok(())
+        },
+    };
+
+    let params = [("scope", "openid"), ("login_hint", LOGIN_HINT)];
+    CibaSetup::assert_error(setup.authenticate(&mut store, &params, true), "invalid_request");
+
+    let params = [
+        ("scope", "openid"),
+        ("login_hint", LOGIN_HINT),
+        ("client_notification_token", "NotificationToken"),
+        ("binding_message", "W4SCT"),
+    ];
+    let response = setup.authenticate(&mut store, &params, true);
+    assert_eq!(response.status, Status::Ok);
+    let started: BackchannelAuthenticationResponse =
+        serde_json::from_str(&CibaSetup::json_body(response)).unwrap();
+    assert_eq!(started.interval, None);
+    assert_eq!(
+        setup.owners.notified.borrow()[0].binding_message.as_deref(),
+        Some("W4SCT")
+    );
+
+    assert_eq!(store.deny(&started.auth_req_id), Ok(true));
+    assert_eq!(
+        *pinged.borrow(),
+        vec![(NOTIFICATION_ENDPOINT.to_owned(), "NotificationToken".to_owned())]
+    );
+    CibaSetup::assert_error(setup.poll(&mut store, &started.auth_req_id), "access_denied");
+}
+
+#[test]
+fn ciba_rejected() {
+    let mut setup = CibaSetup::new();
+    let mut store = BackchannelMap::new();
+
+    let unknown = [("scope", "openid"), ("login_hint", "other@example.com")];
+    CibaSetup::assert_error(setup.authenticate(&mut store, &unknown, false), "unknown_user_id");
+
+    let no_openid = [("scope", "example"), ("login_hint", LOGIN_HINT)];
+    CibaSetup::assert_error(setup.authenticate(&mut store, &no_openid, false), "invalid_scope");
+
+    let other_hint = [
+        ("scope", "openid"),
+        ("login_hint", LOGIN_HINT),
+        ("id_token_hint", "eyJ"),
+    ];
+    CibaSetup::assert_error(
+        setup.authenticate(&mut store, &other_hint, false),
+        "invalid_request",
+    );
+
+    setup.basic_authorization = base64::encode(format!("{}:wrong", EXAMPLE_CLIENT_ID));
+    let valid = [("scope", "openid"), ("login_hint", LOGIN_HINT)];
+    let response = setup.authenticate(&mut store, &valid, false);
+    assert_eq!(response.status, Status::Unauthorized);
+    assert!(setup.owners.notified.borrow().is_empty());
+}
@@ -0,0 +1,132 @@
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::code_grant::introspection::IntrospectionResponse;
+use crate::endpoint::{AccessTokenFlow, AuthorizationFlow, OwnerConsent, OwnerSolicitor, Solicitation};
+use crate::frontends::simple::endpoint::{introspection_flow, Generic, Vacant, WithClaimsEnricher};
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::Grant;
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use serde_json::{json, Map, Value};
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+/// Approves all requests, assigning the owner a role.
+struct RoleConsent;
+
+impl OwnerSolicitor<CraftedRequest> for RoleConsent {
+    fn check_consent(
+        &mut self, _: &mut CraftedRequest, _: Solicitation,
+    ) -> OwnerConsent<CraftedResponse> {
+        let claims = json!({ "roles": ["admin"], "scope": "everything" });
+        OwnerConsent::AuthorizedWithClaims(EXAMPLE_OWNER_ID.to_owned(), object(claims))
+    }
+}
+
+fn object(value: Value) -> Map<String, Value> {
+    value.as_object().cloned().unwrap()
+}
+
+fn tenant(grant: &Grant) -> Map<String, Value> {
+    object(json!({ "roles": [], "tenant": format!("{}-tenant", grant.owner_id) }))
+}
+
+fn json_body<T: serde::de::DeserializeOwned>(response: CraftedResponse) -> T {
+    match response.body {
+        Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+        other => panic!("Expected json body, got {:?}", other),
+    }
+}
+
+#[test]
+fn claims_of_consent_and_enricher() {
+    let mut registrar = ClientMap::new();
+    registrar.register_client(Client::confidential(
+        EXAMPLE_CLIENT_ID,
+        RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+        EXAMPLE_SCOPE.parse().unwrap(),
+        EXAMPLE_PASSPHRASE.as_bytes(),
+    ));
+    let mut authorizer = AuthMap::new(TestGenerator("AuthorizationCode".to_owned()));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+
+    let request = CraftedRequest {
+        query: Some(
+            [
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ("response_type", "code"),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: None,
+    };
+    let endpoint = Generic {
+        registrar: &registrar,
+        authorizer: &mut authorizer,
+        issuer: Vacant,
+        scopes: Vacant,
+        solicitor: RoleConsent,
+        response: Vacant,
+    };
+    let response = AuthorizationFlow::prepare(WithClaimsEnricher::new(endpoint, tenant))
+        .unwrap_or_else(|_| panic!("Not violating any requirements on authorization flow."))
+        .execute(request)
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Redirect);
+
+    let authorization =
+        "Basic ".to_owned() + &base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "authorization_code"),
+                ("code", "AuthorizationCode"),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: Some(authorization.clone()),
+    };
+    let endpoint = Generic {
+        registrar: &registrar,
+        authorizer: &mut authorizer,
+        issuer: &mut issuer,
+        scopes: Vacant,
+        solicitor: Vacant,
+        response: Vacant,
+    };
+    let response = AccessTokenFlow::prepare(endpoint)
+        .unwrap_or_else(|_| panic!("Not violating any requirements on access token flow."))
+        .execute(request)
+        .expect("Expected non-error response");
+    let token: TokenResponse = json_body(response);
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [("token", token.access_token.unwrap().as_str())]
+                .iter()
+                .to_single_value_query(),
+        ),
+        auth: Some(authorization),
+    };
+    let mut flow = introspection_flow(&registrar, &mut issuer);
+    flow.resource_server(EXAMPLE_CLIENT_ID);
+    let response = flow.execute(request).expect("Expected non-error response");
+    let description: IntrospectionResponse = json_body(response);
+
+    // The claims of the solicitor take precedence, reserved members are never replaced.
+    assert!(description.active);
+    assert_eq!(description.scope.as_deref(), token.scope.as_deref());
+    assert_eq!(
+        Value::Object(description.claims),
+        json!({ "roles": ["admin"], "tenant": "Owner-tenant" })
+    );
+}
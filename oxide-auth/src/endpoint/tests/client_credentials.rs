@@ -3,9 +3,12 @@ use crate::primitives::issuer::TokenMap;
 
 use crate::endpoint::{OwnerSolicitor};
 
-use crate::frontends::simple::endpoint::client_credentials_flow;
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::frontends::simple::endpoint::{client_credentials_flow, ClientOwner, Generic, Vacant};
+use crate::primitives::issuer::Issuer;
+use crate::primitives::scope::Scope;
 
-use super::{CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::{Body, CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
 use super::{Allow, Deny};
 use super::defaults::*;
 
@@ -359,3 +362,46 @@ fn client_credentials_request_error_malformed_scope() {
 
     setup.test_bad_request(malformed_scope, Allow(EXAMPLE_OWNER_ID.to_owned()));
 }
+
+#[test]
+fn client_credentials_client_owner() {
+    let mut setup = ClientCredentialsSetup::new();
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [("grant_type", "client_credentials"), ("scope", "other")]
+                .iter()
+                .to_single_value_query(),
+        ),
+        auth: Some(format!("Basic {}", setup.basic_authorization)),
+    };
+
+    let response = Generic {
+        registrar: &setup.registrar,
+        authorizer: Vacant,
+        issuer: &mut setup.issuer,
+        solicitor: ClientOwner,
+        scopes: Vacant,
+        response: Vacant,
+    }
+    .client_credentials_flow()
+    .execute(request)
+    .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Ok);
+
+    let token: TokenResponse = match response.body {
+        Some(Body::Json(json)) => serde_json::from_str(&json).unwrap(),
+        other => panic!("Expected json body, got {:?}", other),
+    };
+    // The registered default scope is granted and no refresh token is issued.
+    let scope: Scope = token.scope.unwrap().parse().unwrap();
+    assert_eq!(scope, EXAMPLE_SCOPE.parse().unwrap());
+    assert_eq!(token.refresh_token, None);
+
+    let grant = setup
+        .issuer
+        .recover_token(&token.access_token.unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(grant.owner_id, EXAMPLE_CLIENT_ID);
+}
@@ -0,0 +1,118 @@
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::endpoint::Solicitation;
+use crate::frontends::simple::consent::{ClientDisplay, ConsentPage};
+use crate::frontends::simple::endpoint::authorization_flow;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+/// Identifies the owner by the authorization header, standing in for a session cookie.
+fn session(request: &mut CraftedRequest) -> Option<String> {
+    request.auth.clone()
+}
+
+struct ConsentSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    consent: ConsentPage<fn(&mut CraftedRequest) -> Option<String>>,
+}
+
+impl ConsentSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+        ));
+
+        let mut consent = ConsentPage::new(session as fn(&mut CraftedRequest) -> Option<String>);
+        consent.describe_scope("example", "See <your> examples");
+        consent.describe_client(EXAMPLE_CLIENT_ID, ClientDisplay::new("Example & Co"));
+
+        ConsentSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthToken".to_owned())),
+            consent,
+        }
+    }
+
+    fn execute(&mut self, decision: Option<&str>, owner: Option<&str>) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: Some(
+                [
+                    ("response_type", "code"),
+                    ("client_id", EXAMPLE_CLIENT_ID),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                    ("state", "xyz"),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            urlbody: decision.map(|decision| [("consent", decision)].iter().to_single_value_query()),
+            auth: owner.map(str::to_owned),
+        };
+
+        authorization_flow(&self.registrar, &mut self.authorizer, &mut self.consent)
+            .execute(request)
+            .expect("Should not error")
+    }
+}
+
+#[test]
+fn consent_page() {
+    let mut setup = ConsentSetup::new();
+
+    let response = setup.execute(None, Some(EXAMPLE_OWNER_ID));
+    assert_eq!(response.status, Status::Ok);
+    let page = match response.body {
+        Some(Body::Html(page)) => page,
+        other => panic!("Expected a consent page: {:?}", other),
+    };
+    assert!(page.contains("<title>Authorize Example &amp; Co</title>"));
+    assert!(page.contains("<li>See &lt;your&gt; examples</li>\n"));
+    assert!(page.contains("<li>default</li>\n"));
+    assert!(page.contains("state=xyz"));
+    assert!(page.contains("response_type=code"));
+}
+
+#[test]
+fn consent_decision() {
+    let mut setup = ConsentSetup::new();
+
+    let response = setup.execute(Some("allow"), Some(EXAMPLE_OWNER_ID));
+    assert_eq!(response.status, Status::Redirect);
+    let location = response.location.unwrap();
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "code" && value == "AuthToken"));
+
+    let response = setup.execute(Some("deny"), Some(EXAMPLE_OWNER_ID));
+    let location = response.location.unwrap();
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "error" && value == "access_denied"));
+
+    // Without a logged in owner even an approval is denied.
+    let response = setup.execute(Some("allow"), None);
+    let location = response.location.unwrap();
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "error" && value == "access_denied"));
+}
+
+#[test]
+fn consent_template() {
+    let grant = crate::primitives::registrar::PreGrant {
+        client_id: "<Client>".to_owned(),
+        redirect_uri: RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+        scope: "read".parse().unwrap(),
+    };
+    let mut consent = ConsentPage::new(session);
+    consent.template("{{ {client} wants {scope}, {unknown} {action} }}");
+
+    let page = consent.render(&Solicitation::new(&grant), "a=b&c=d");
+    assert_eq!(page, "{ &lt;Client&gt; wants read, {unknown} ?a=b&amp;c=d }");
+}
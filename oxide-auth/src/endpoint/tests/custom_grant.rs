@@ -0,0 +1,144 @@
+use crate::code_grant::accesstoken::{BearerToken, TokenResponse};
+use crate::code_grant::custom_grant::{authenticate, Endpoint, Error, GrantHandler, GrantTypes, Request};
+use crate::code_grant::error::AccessTokenErrorType;
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::{Issuer, TokenMap};
+use crate::primitives::registrar::{Client, ClientMap, ClientUrl, RegisteredUrl};
+
+use crate::frontends::simple::endpoint::custom_grant_flow;
+
+use std::borrow::Cow;
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+const INTERNAL_GRANT_TYPE: &str = "urn:example:params:oauth:grant-type:internal";
+
+/// Trusts assertions of the form `trusted:<owner>`.
+struct InternalAssertion;
+
+struct CustomGrantSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+    grant_types: GrantTypes,
+    basic_authorization: String,
+}
+
+impl GrantHandler for InternalAssertion {
+    fn grant(
+        &mut self, request: &dyn Request, endpoint: &mut dyn Endpoint,
+    ) -> Result<BearerToken, Error> {
+        let client_id = authenticate(endpoint.registrar(), request)?;
+        let owner_id = request
+            .extension("assertion")
+            .and_then(|assertion| assertion.strip_prefix("trusted:").map(str::to_owned))
+            .ok_or_else(|| Error::invalid(AccessTokenErrorType::InvalidGrant))?;
+
+        let registrar = endpoint.registrar();
+        let bound = registrar
+            .bound_redirect(ClientUrl {
+                client_id: Cow::Owned(client_id),
+                redirect_uri: None,
+            })
+            .map_err(|_| Error::Primitive(PrimitiveError::Invariant))?;
+        let pre_grant = registrar
+            .negotiate(bound, None)
+            .map_err(|_| Error::invalid(AccessTokenErrorType::InvalidScope))?;
+
+        let token = endpoint
+            .issuer()
+            .issue(Grant {
+                owner_id,
+                client_id: pre_grant.client_id,
+                scope: pre_grant.scope.clone(),
+                redirect_uri: pre_grant.redirect_uri.into_url(),
+                until: Utc::now() + Duration::minutes(10),
+                extensions: Extensions::new(),
+            })
+            .map_err(Error::Primitive)?;
+        Ok(BearerToken::new(token, &pre_grant.scope))
+    }
+}
+
+impl CustomGrantSetup {
+    fn new() -> CustomGrantSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        let mut grant_types = GrantTypes::new();
+        grant_types.register(INTERNAL_GRANT_TYPE, InternalAssertion);
+
+        CustomGrantSetup {
+            registrar,
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            grant_types,
+            basic_authorization: base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE)),
+        }
+    }
+
+    fn request(&mut self, grant_type: &str, assertion: &str, auth: bool) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [("grant_type", grant_type), ("assertion", assertion)]
+                    .iter()
+                    .to_single_value_query(),
+            ),
+            auth: Some(format!("Basic {}", self.basic_authorization)).filter(|_| auth),
+        };
+
+        let mut flow = custom_grant_flow(&self.registrar, &mut self.issuer, &mut self.grant_types);
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn json_body(response: CraftedResponse) -> TokenResponse {
+        match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn custom_grant_issued() {
+    let mut setup = CustomGrantSetup::new();
+    assert!(setup.grant_types.supports(INTERNAL_GRANT_TYPE));
+
+    let response = setup.request(INTERNAL_GRANT_TYPE, "trusted:Owner", true);
+    assert_eq!(response.status, Status::Ok);
+    let token = CustomGrantSetup::json_body(response);
+    let grant = setup
+        .issuer
+        .recover_token(&token.access_token.unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(grant.owner_id, "Owner");
+    assert_eq!(grant.client_id, EXAMPLE_CLIENT_ID);
+}
+
+#[test]
+fn custom_grant_rejected() {
+    let mut setup = CustomGrantSetup::new();
+    let response = setup.request(INTERNAL_GRANT_TYPE, "untrusted", true);
+    assert_eq!(response.status, Status::BadRequest);
+    let token = CustomGrantSetup::json_body(response);
+    assert_eq!(token.error.as_deref(), Some("invalid_grant"));
+
+    let response = setup.request("urn:example:params:oauth:grant-type:other", "trusted:Owner", true);
+    assert_eq!(response.status, Status::BadRequest);
+    let token = CustomGrantSetup::json_body(response);
+    assert_eq!(token.error.as_deref(), Some("unsupported_grant_type"));
+
+    let response = setup.request(INTERNAL_GRANT_TYPE, "trusted:Owner", false);
+    assert_eq!(response.status, Status::Unauthorized);
+}
@@ -0,0 +1,189 @@
+use crate::code_grant::device::{DeviceAuthorizationResponse, DEVICE_CODE_GRANT_TYPE};
+use crate::endpoint::{DeviceAuthorizationFlow, DeviceTokenFlow, DeviceVerificationFlow, Endpoint};
+use crate::endpoint::OwnerSolicitor;
+use crate::frontends::simple::endpoint::{Error, Generic, Vacant, WithDeviceCodes};
+use crate::primitives::device::DeviceCodeMap;
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use std::collections::HashMap;
+
+use serde_json;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::{Allow, Deny};
+use super::defaults::*;
+
+const VERIFICATION_URI: &str = "https://example.com/device";
+
+struct DeviceSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<TestGenerator>,
+    device_codes: DeviceCodeMap<TestGenerator>,
+}
+
+impl DeviceSetup {
+    fn new() -> DeviceSetup {
+        let mut registrar = ClientMap::new();
+        let issuer = TokenMap::new(TestGenerator("AccessToken".to_owned()));
+        let device_codes = DeviceCodeMap::new(TestGenerator("DeviceCode".to_owned()));
+
+        let client = Client::public(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+        );
+        registrar.register_client(client);
+        DeviceSetup {
+            registrar,
+            issuer,
+            device_codes,
+        }
+    }
+
+    fn endpoint<S>(
+        &mut self, solicitor: S,
+    ) -> impl Endpoint<CraftedRequest, Error = Error<CraftedRequest>> + '_
+    where
+        S: OwnerSolicitor<CraftedRequest> + 'static,
+    {
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: Vacant,
+            issuer: &mut self.issuer,
+            scopes: Vacant,
+            solicitor,
+            response: Vacant,
+        };
+
+        WithDeviceCodes::new(endpoint, &mut self.device_codes)
+    }
+
+    fn start(&mut self) -> DeviceAuthorizationResponse {
+        self.start_with(true)
+    }
+
+    fn start_with(&mut self, complete_uri: bool) -> DeviceAuthorizationResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some([("client_id", EXAMPLE_CLIENT_ID)].iter().to_single_value_query()),
+            auth: None,
+        };
+
+        let mut flow =
+            DeviceAuthorizationFlow::prepare(self.endpoint(Vacant), VERIFICATION_URI.parse().unwrap())
+                .unwrap_or_else(|_| {
+                    panic!("Not violating any requirements on device authorization flow.")
+                });
+        flow.complete_uri(complete_uri);
+        let response = flow.execute(request).expect("Expected non-error response");
+
+        assert_eq!(response.status, Status::Ok);
+        serde_json::from_str(&Self::json_body(response)).expect("Expected valid json body")
+    }
+
+    fn verify<S>(&mut self, user_code: &str, solicitor: S) -> CraftedResponse
+    where
+        S: OwnerSolicitor<CraftedRequest> + 'static,
+    {
+        let request = CraftedRequest {
+            query: Some([("user_code", user_code)].iter().to_single_value_query()),
+            urlbody: None,
+            auth: None,
+        };
+
+        let mut flow = DeviceVerificationFlow::prepare(self.endpoint(solicitor))
+            .unwrap_or_else(|_| panic!("Not violating any requirements on device verification flow."));
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn poll(&mut self, device_code: &str) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("grant_type", DEVICE_CODE_GRANT_TYPE),
+                    ("device_code", device_code),
+                    ("client_id", EXAMPLE_CLIENT_ID),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth: None,
+        };
+
+        let mut flow = DeviceTokenFlow::prepare(self.endpoint(Vacant))
+            .unwrap_or_else(|_| panic!("Not violating any requirements on device token flow."));
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn assert_error(response: CraftedResponse, error: &str) {
+        assert_eq!(response.status, Status::BadRequest);
+        let content: HashMap<String, String> = serde_json::from_str(&Self::json_body(response)).unwrap();
+        assert_eq!(content.get("error").map(String::as_str), Some(error));
+    }
+
+    fn json_body(response: CraftedResponse) -> String {
+        match response.body {
+            Some(Body::Json(json)) => json,
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn device_approved() {
+    let mut setup = DeviceSetup::new();
+    let started = setup.start();
+    assert_eq!(started.verification_uri, VERIFICATION_URI);
+    assert_eq!(started.interval, 5);
+    assert!(started.verification_uri_complete.is_some());
+
+    DeviceSetup::assert_error(setup.poll(&started.device_code), "authorization_pending");
+    DeviceSetup::assert_error(setup.poll(&started.device_code), "slow_down");
+
+    let verified = setup.verify(&started.user_code, Allow(EXAMPLE_OWNER_ID.to_owned()));
+    assert_eq!(verified.status, Status::Ok);
+
+    let response = setup.poll(&started.device_code);
+    assert_eq!(response.status, Status::Ok);
+    let content: HashMap<String, serde_json::Value> =
+        serde_json::from_str(&DeviceSetup::json_body(response)).unwrap();
+    assert_eq!(content.get("access_token"), Some(&"AccessToken".into()));
+
+    // The device code is redeemed exactly once.
+    DeviceSetup::assert_error(setup.poll(&started.device_code), "invalid_grant");
+}
+
+#[test]
+fn device_denied() {
+    let mut setup = DeviceSetup::new();
+    let started = setup.start();
+
+    let verified = setup.verify(&started.user_code, Deny);
+    assert_eq!(verified.status, Status::Ok);
+    // A decided user code can not be entered again.
+    let again = setup.verify(&started.user_code, Allow(EXAMPLE_OWNER_ID.to_owned()));
+    assert_eq!(again.status, Status::BadRequest);
+
+    DeviceSetup::assert_error(setup.poll(&started.device_code), "access_denied");
+}
+
+#[test]
+fn device_unknown_user_code() {
+    let mut setup = DeviceSetup::new();
+    setup.start();
+
+    let verified = setup.verify("BCDF-GHJK", Allow(EXAMPLE_OWNER_ID.to_owned()));
+    assert_eq!(verified.status, Status::BadRequest);
+}
+
+#[test]
+fn device_without_complete_uri() {
+    let mut setup = DeviceSetup::new();
+    let started = setup.start_with(false);
+    assert!(started.verification_uri_complete.is_none());
+
+    let verified = setup.verify(&started.user_code, Allow(EXAMPLE_OWNER_ID.to_owned()));
+    assert_eq!(verified.status, Status::Ok);
+}
@@ -0,0 +1,233 @@
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::code_grant::extensions::{ProofClaims, DPOP_PROOF_TYPE};
+use crate::endpoint::{AccessTokenFlow, DpopProof, Endpoint, QueryParameter, ResourceFlow, WebRequest};
+use crate::frontends::simple::endpoint::{Error, Generic, Vacant};
+use crate::frontends::simple::extensions::{AddonList, Dpop, Extended};
+use crate::primitives::authorizer::{AuthMap, Authorizer};
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::jwt::SigningKey;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::scope::Scope;
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use base64;
+use chrono::{Duration, Utc};
+use serde_json;
+use sha2::{Digest, Sha256};
+
+use super::{Body, CraftedError, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+const TOKEN_URI: &str = "https://example.com/token";
+const RESOURCE_URI: &str = "https://example.com/resource";
+
+/// A crafted request with a DPoP header.
+#[derive(Debug)]
+struct ProofRequest {
+    request: CraftedRequest,
+    dpop: Option<DpopProof>,
+}
+
+struct DpopSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+    resource_scope: [Scope; 1],
+    dpop: Arc<Dpop>,
+    key: SigningKey,
+}
+
+impl WebRequest for ProofRequest {
+    type Response = CraftedResponse;
+    type Error = CraftedError;
+
+    fn query(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+        self.request.query()
+    }
+
+    fn urlbody(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+        self.request.urlbody()
+    }
+
+    fn authheader(&mut self) -> Result<Option<Cow<str>>, Self::Error> {
+        self.request.authheader()
+    }
+
+    fn dpop(&mut self) -> Result<Option<DpopProof>, Self::Error> {
+        Ok(self.dpop.clone())
+    }
+}
+
+impl DpopSetup {
+    fn new(dpop: Dpop) -> DpopSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+        ));
+
+        DpopSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthorizationCode".to_owned())),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            resource_scope: ["example".parse().unwrap()],
+            dpop: Arc::new(dpop),
+            key: SigningKey::es256(&SigningKey::generate_es256().unwrap()).unwrap(),
+        }
+    }
+
+    fn endpoint(&mut self) -> impl Endpoint<ProofRequest, Error = Error<ProofRequest>> + '_ {
+        let mut extensions = AddonList::new();
+        extensions.push_access_token(self.dpop.clone());
+        extensions.push_resource(self.dpop.clone());
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            scopes: &self.resource_scope[..],
+            solicitor: Vacant,
+            response: Vacant,
+        };
+
+        Extended::extend_with(endpoint, extensions)
+    }
+
+    fn proof(key: &SigningKey, method: &str, uri: &str, token: Option<&str>) -> DpopProof {
+        let claims = ProofClaims {
+            jti: base64::encode(rand::random::<[u8; 16]>()),
+            htm: method.to_owned(),
+            htu: uri.to_owned(),
+            iat: Utc::now().timestamp(),
+            ath: token.map(|token| {
+                base64::encode_config(Sha256::digest(token.as_bytes()), base64::URL_SAFE_NO_PAD)
+            }),
+        };
+
+        DpopProof {
+            proof: key.sign_with_jwk(DPOP_PROOF_TYPE, &claims).unwrap(),
+            method: method.to_owned(),
+            uri: uri.to_owned(),
+        }
+    }
+
+    fn token(&mut self, dpop: Option<DpopProof>) -> TokenResponse {
+        let code = self
+            .authorizer
+            .authorize(Grant {
+                owner_id: EXAMPLE_OWNER_ID.to_owned(),
+                client_id: EXAMPLE_CLIENT_ID.to_owned(),
+                scope: EXAMPLE_SCOPE.parse().unwrap(),
+                redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+                until: Utc::now() + Duration::minutes(10),
+                extensions: Extensions::new(),
+            })
+            .unwrap();
+
+        let request = ProofRequest {
+            request: CraftedRequest {
+                query: None,
+                urlbody: Some(
+                    [
+                        ("grant_type", "authorization_code"),
+                        ("client_id", EXAMPLE_CLIENT_ID),
+                        ("code", &code),
+                        ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                    ]
+                    .iter()
+                    .to_single_value_query(),
+                ),
+                auth: None,
+            },
+            dpop,
+        };
+
+        let mut endpoint = self.endpoint();
+        let mut flow = AccessTokenFlow::prepare(&mut endpoint)
+            .unwrap_or_else(|_| panic!("Not violating any requirements on access token flow."));
+        let response = flow.execute(request).expect("Expected non-error response");
+        match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).unwrap(),
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+
+    fn access(&mut self, authorization: String, dpop: Option<DpopProof>) -> bool {
+        let request = ProofRequest {
+            request: CraftedRequest {
+                query: None,
+                urlbody: None,
+                auth: Some(authorization),
+            },
+            dpop,
+        };
+
+        let mut endpoint = self.endpoint();
+        let mut flow = ResourceFlow::prepare(&mut endpoint)
+            .unwrap_or_else(|_| panic!("Not violating any requirements on resource flow."));
+        match flow.execute(request) {
+            Ok(_) => true,
+            Err(Ok(response)) => {
+                assert_eq!(response.status, Status::Unauthorized);
+                false
+            }
+            Err(Err(_)) => panic!("Expected non-error response"),
+        }
+    }
+}
+
+#[test]
+fn dpop_bound_token() {
+    let mut setup = DpopSetup::new(Dpop::required());
+    let proof = DpopSetup::proof(&setup.key, "POST", TOKEN_URI, None);
+    let response = setup.token(Some(proof));
+    assert_eq!(response.token_type.as_deref(), Some("DPoP"));
+    let token = response.access_token.unwrap();
+
+    let proof = DpopSetup::proof(&setup.key, "GET", RESOURCE_URI, Some(&token));
+    assert!(setup.access(format!("DPoP {}", token), Some(proof.clone())));
+    // A proof is only accepted once.
+    assert!(!setup.access(format!("DPoP {}", token), Some(proof)));
+
+    // The token is useless without a proof of the key it is bound to.
+    assert!(!setup.access(format!("Bearer {}", token), None));
+    let other = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+    let proof = DpopSetup::proof(&other, "GET", RESOURCE_URI, Some(&token));
+    assert!(!setup.access(format!("DPoP {}", token), Some(proof)));
+    let proof = DpopSetup::proof(&setup.key, "GET", RESOURCE_URI, Some("OtherToken"));
+    assert!(!setup.access(format!("DPoP {}", token), Some(proof)));
+    // The proof was made for another request than the one it is sent with.
+    let mut proof = DpopSetup::proof(&setup.key, "POST", RESOURCE_URI, Some(&token));
+    proof.method = "GET".to_owned();
+    assert!(!setup.access(format!("DPoP {}", token), Some(proof)));
+}
+
+#[test]
+fn dpop_required() {
+    let mut setup = DpopSetup::new(Dpop::required());
+    let response = setup.token(None);
+    assert_eq!(response.error.as_deref(), Some("invalid_request"));
+
+    // Proofs for another endpoint are rejected.
+    let mut proof = DpopSetup::proof(&setup.key, "POST", RESOURCE_URI, None);
+    proof.uri = TOKEN_URI.to_owned();
+    let response = setup.token(Some(proof));
+    assert_eq!(response.error.as_deref(), Some("invalid_request"));
+}
+
+#[test]
+fn dpop_optional() {
+    let mut setup = DpopSetup::new(Dpop::optional());
+    let response = setup.token(None);
+    assert_eq!(response.token_type.as_deref(), Some("bearer"));
+    let token = response.access_token.unwrap();
+
+    assert!(setup.access(format!("Bearer {}", token), None));
+    let proof = DpopSetup::proof(&setup.key, "GET", RESOURCE_URI, Some(&token));
+    assert!(!setup.access(format!("DPoP {}", token), Some(proof)));
+}
@@ -0,0 +1,165 @@
+use crate::endpoint::EndSessionFlow;
+use crate::frontends::simple::endpoint::{Generic, Vacant, WithIdTokenSigner};
+use crate::primitives::jwt::{IdTokenClaims, IdTokenSigner, Pairwise, SigningKey};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::session::{SessionMap, SessionRegistry};
+
+use chrono::Utc;
+use serde_json::Map;
+
+use super::{CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+const ISSUER: &str = "https://auth.example.com";
+
+struct EndSessionSetup {
+    registrar: ClientMap,
+    signer: IdTokenSigner,
+    sessions: SessionMap,
+}
+
+impl EndSessionSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+        ));
+
+        let key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        EndSessionSetup {
+            registrar,
+            signer: IdTokenSigner::new(key, ISSUER),
+            sessions: SessionMap::new(),
+        }
+    }
+
+    /// An expired ID token of the owner, with a session id.
+    fn hint(&self, iss: &str, sid: Option<&str>) -> String {
+        let mut claims = Map::new();
+        if let Some(sid) = sid {
+            claims.insert("sid".to_owned(), sid.into());
+        }
+        let now = Utc::now().timestamp();
+        self.signer
+            .sign(&IdTokenClaims {
+                iss: iss.to_owned(),
+                sub: self.signer.subject(EXAMPLE_OWNER_ID, EXAMPLE_CLIENT_ID),
+                aud: EXAMPLE_CLIENT_ID.to_owned(),
+                exp: now - 60,
+                iat: now - 3600,
+                auth_time: None,
+                nonce: None,
+                acr: None,
+                claims,
+            })
+            .unwrap()
+    }
+
+    fn execute(&mut self, parameters: &[(&str, &str)]) -> CraftedResponse {
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: Vacant,
+            issuer: Vacant,
+            scopes: Vacant,
+            solicitor: Vacant,
+            response: Vacant,
+        };
+        let request = CraftedRequest {
+            query: Some(parameters.iter().to_single_value_query()),
+            urlbody: None,
+            auth: None,
+        };
+
+        EndSessionFlow::prepare(WithIdTokenSigner::new(endpoint, &self.signer), &mut self.sessions)
+            .unwrap_or_else(|_| panic!("Should prepare"))
+            .execute(request)
+            .unwrap_or_else(|_| panic!("Should not error"))
+    }
+}
+
+#[test]
+fn end_session_redirects() {
+    let mut setup = EndSessionSetup::new();
+    let sid = setup.sessions.start(EXAMPLE_OWNER_ID).unwrap();
+    setup.sessions.join(&sid, EXAMPLE_CLIENT_ID).unwrap();
+    let other = setup.sessions.start(EXAMPLE_OWNER_ID).unwrap();
+
+    let hint = setup.hint(ISSUER, None);
+    let response = setup.execute(&[
+        ("id_token_hint", &hint),
+        ("post_logout_redirect_uri", EXAMPLE_REDIRECT_URI),
+        ("state", "xyz"),
+    ]);
+    assert_eq!(response.status, Status::Redirect);
+    let location = response.location.unwrap();
+    assert!(location.as_str().starts_with(EXAMPLE_REDIRECT_URI));
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "state" && value == "xyz"));
+
+    // Only the session in which the client participated has ended.
+    assert_eq!(setup.sessions.session(&sid), Ok(None));
+    assert!(setup.sessions.session(&other).unwrap().is_some());
+}
+
+#[test]
+fn end_session_by_sid() {
+    let mut setup = EndSessionSetup::new();
+    let sid = setup.sessions.start(EXAMPLE_OWNER_ID).unwrap();
+    let other = setup.sessions.start(EXAMPLE_OWNER_ID).unwrap();
+    setup.sessions.join(&other, EXAMPLE_CLIENT_ID).unwrap();
+
+    let hint = setup.hint(ISSUER, Some(&sid));
+    let response = setup.execute(&[("id_token_hint", &hint), ("client_id", EXAMPLE_CLIENT_ID)]);
+    assert_eq!(response.status, Status::Ok);
+    assert!(response.location.is_none());
+    assert_eq!(setup.sessions.session(&sid), Ok(None));
+    assert!(setup.sessions.session(&other).unwrap().is_some());
+}
+
+#[test]
+fn end_session_pairwise() {
+    let mut setup = EndSessionSetup::new();
+    setup.signer.pairwise(Pairwise::new(b"salt"));
+    let sid = setup.sessions.start(EXAMPLE_OWNER_ID).unwrap();
+    setup.sessions.join(&sid, EXAMPLE_CLIENT_ID).unwrap();
+
+    // The pairwise subject does not name the owner, only the session is found.
+    let hint = setup.hint(ISSUER, None);
+    assert_eq!(setup.execute(&[("id_token_hint", &hint)]).status, Status::Ok);
+    assert!(setup.sessions.session(&sid).unwrap().is_some());
+
+    let hint = setup.hint(ISSUER, Some(&sid));
+    assert_eq!(setup.execute(&[("id_token_hint", &hint)]).status, Status::Ok);
+    assert_eq!(setup.sessions.session(&sid), Ok(None));
+}
+
+#[test]
+fn end_session_invalid() {
+    let mut setup = EndSessionSetup::new();
+    let sid = setup.sessions.start(EXAMPLE_OWNER_ID).unwrap();
+    setup.sessions.join(&sid, EXAMPLE_CLIENT_ID).unwrap();
+    let hint = setup.hint(ISSUER, None);
+
+    let invalid: &[&[(&str, &str)]] = &[
+        &[("client_id", EXAMPLE_CLIENT_ID)],
+        &[("id_token_hint", "eyJhbGciOiJub25lIn0.e30.")],
+        &[("id_token_hint", &hint), ("client_id", "OtherClient")],
+        &[
+            ("id_token_hint", &hint),
+            ("post_logout_redirect_uri", "https://evil.example/logout"),
+        ],
+    ];
+    for parameters in invalid {
+        let response = setup.execute(parameters);
+        assert_eq!(response.status, Status::BadRequest, "{:?}", parameters);
+    }
+
+    let foreign = setup.hint("https://other.example.com", None);
+    let response = setup.execute(&[("id_token_hint", &foreign)]);
+    assert_eq!(response.status, Status::BadRequest);
+
+    assert!(setup.sessions.session(&sid).unwrap().is_some());
+}
@@ -0,0 +1,116 @@
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::endpoint::{AuthorizationFlow, RefreshFlow};
+use crate::frontends::simple::endpoint::{Generic, Vacant, WithErrorReporting};
+
+use std::collections::HashMap;
+
+use serde_json;
+use url::Url;
+
+use super::{Body, CraftedRequest, Deny, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+const ERROR_URI: &str = "https://auth.example.com/errors/";
+
+fn registrar() -> ClientMap {
+    let mut registrar = ClientMap::new();
+    registrar.register_client(Client::confidential(
+        EXAMPLE_CLIENT_ID,
+        RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+        EXAMPLE_SCOPE.parse().unwrap(),
+        EXAMPLE_PASSPHRASE.as_bytes(),
+    ));
+    registrar
+}
+
+#[test]
+fn authorization_error_reported() {
+    let registrar = registrar();
+    let mut authorizer = AuthMap::new(TestGenerator("AuthToken".to_string()));
+    let endpoint = Generic {
+        registrar: &registrar,
+        authorizer: &mut authorizer,
+        issuer: Vacant,
+        solicitor: Deny,
+        scopes: Vacant,
+        response: Vacant,
+    };
+
+    let request = CraftedRequest {
+        query: Some(
+            [
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: None,
+    };
+
+    let error_uri = Url::parse(ERROR_URI).unwrap();
+    let response = AuthorizationFlow::prepare(WithErrorReporting::new(endpoint, Some(error_uri)))
+        .unwrap()
+        .execute(request)
+        .expect("Expected a redirect to the client");
+
+    assert_eq!(response.status, Status::Redirect);
+    let location = response.location.expect("Expected a redirect location");
+    let query: HashMap<_, _> = location.query_pairs().into_owned().collect();
+    assert_eq!(query.get("error").map(String::as_str), Some("access_denied"));
+    assert_eq!(
+        query.get("error_uri").map(String::as_str),
+        Some("https://auth.example.com/errors/access_denied")
+    );
+    assert!(query.get("trace_id").is_some_and(|id| id.len() == 16));
+}
+
+#[test]
+fn access_token_error_reported() {
+    let registrar = registrar();
+    let mut issuer = TokenMap::new(RandomGenerator::new(16));
+    let endpoint = Generic {
+        registrar: &registrar,
+        authorizer: Vacant,
+        issuer: &mut issuer,
+        solicitor: Vacant,
+        scopes: Vacant,
+        response: Vacant,
+    };
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "refresh_token"),
+                ("refresh_token", "not a refresh token"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("client_secret", EXAMPLE_PASSPHRASE),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: None,
+    };
+
+    // Without a base, errors only receive their trace id.
+    let response = RefreshFlow::prepare(WithErrorReporting::new(endpoint, None))
+        .unwrap()
+        .execute(request)
+        .expect("Expected an error response");
+
+    assert_eq!(response.status, Status::BadRequest);
+    let body: HashMap<String, String> = match response.body {
+        Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+        other => panic!("Expected json body, got {:?}", other),
+    };
+    assert_eq!(body.get("error").map(String::as_str), Some("invalid_grant"));
+    assert!(!body.contains_key("error_uri"));
+    assert!(body.get("trace_id").is_some_and(|id| id.len() == 16));
+}
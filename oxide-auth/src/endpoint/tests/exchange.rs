@@ -0,0 +1,214 @@
+use crate::code_grant::exchange::{
+    AudiencePolicy, ExchangePolicy, ExchangeResponse, ACCESS_TOKEN_TYPE, ACTOR_EXTENSION,
+    AUDIENCE_EXTENSION, TOKEN_EXCHANGE_GRANT_TYPE,
+};
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::{IssuedToken, Issuer, TokenMap};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::frontends::simple::endpoint::token_exchange_flow;
+
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+const SERVICE_CLIENT_ID: &str = "Service";
+const SERVICE_AUDIENCE: &str = "https://api.example.com";
+
+struct ExchangeSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+    basic_authorization: String,
+}
+
+impl ExchangeSetup {
+    fn new() -> ExchangeSetup {
+        let mut registrar = ClientMap::new();
+        let issuer = TokenMap::new(RandomGenerator::new(16));
+
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        let basic_authorization =
+            base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+        ExchangeSetup {
+            registrar,
+            issuer,
+            basic_authorization,
+        }
+    }
+
+    fn issue(&mut self, owner_id: &str) -> IssuedToken {
+        self.issuer
+            .issue(Grant {
+                client_id: SERVICE_CLIENT_ID.to_string(),
+                owner_id: owner_id.to_string(),
+                redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+                scope: EXAMPLE_SCOPE.parse().unwrap(),
+                until: Utc::now() + Duration::hours(1),
+                extensions: Extensions::new(),
+            })
+            .unwrap()
+    }
+
+    fn request(&self, subject_token: &str, params: &[(&str, &str)]) -> CraftedRequest {
+        let mut body = vec![
+            ("grant_type", TOKEN_EXCHANGE_GRANT_TYPE),
+            ("subject_token", subject_token),
+            ("subject_token_type", ACCESS_TOKEN_TYPE),
+        ];
+        body.extend_from_slice(params);
+        CraftedRequest {
+            query: None,
+            urlbody: Some(body.iter().to_single_value_query()),
+            auth: Some(format!("Basic {}", self.basic_authorization)),
+        }
+    }
+
+    fn execute<P: ExchangePolicy>(&mut self, request: CraftedRequest, policy: P) -> CraftedResponse {
+        let mut flow = token_exchange_flow(&self.registrar, &mut self.issuer, policy);
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn assert_error(response: CraftedResponse, error: &str) {
+        assert_eq!(response.status, Status::BadRequest);
+        let content: HashMap<String, String> = serde_json::from_str(&Self::json_body(response)).unwrap();
+        assert_eq!(content.get("error").map(String::as_str), Some(error));
+    }
+
+    fn json_body(response: CraftedResponse) -> String {
+        match response.body {
+            Some(Body::Json(json)) => json,
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+}
+
+struct DefaultPolicy;
+
+impl ExchangePolicy for DefaultPolicy {}
+
+#[test]
+fn exchange_narrowed_scope() {
+    let mut setup = ExchangeSetup::new();
+    let subject = setup.issue(EXAMPLE_OWNER_ID);
+
+    let request = setup.request(&subject.token, &[("scope", "example")]);
+    let response = setup.execute(request, DefaultPolicy);
+    assert_eq!(response.status, Status::Ok);
+
+    let exchanged: ExchangeResponse = serde_json::from_str(&ExchangeSetup::json_body(response)).unwrap();
+    assert_eq!(exchanged.issued_token_type, ACCESS_TOKEN_TYPE);
+    assert_eq!(exchanged.scope, "example");
+
+    let grant = setup
+        .issuer
+        .recover_token(&exchanged.access_token)
+        .unwrap()
+        .unwrap();
+    assert_eq!(grant.owner_id, EXAMPLE_OWNER_ID);
+    assert_eq!(grant.client_id, EXAMPLE_CLIENT_ID);
+    assert!(grant.extensions.public().next().is_none());
+}
+
+#[test]
+fn exchange_widened_scope() {
+    let mut setup = ExchangeSetup::new();
+    let subject = setup.issue(EXAMPLE_OWNER_ID);
+
+    let request = setup.request(&subject.token, &[("scope", "example default admin")]);
+    ExchangeSetup::assert_error(setup.execute(request, DefaultPolicy), "invalid_scope");
+}
+
+#[test]
+fn exchange_audience() {
+    let mut setup = ExchangeSetup::new();
+    let subject = setup.issue(EXAMPLE_OWNER_ID);
+
+    let request = setup.request(&subject.token, &[("audience", SERVICE_AUDIENCE)]);
+    ExchangeSetup::assert_error(setup.execute(request, AudiencePolicy::new()), "invalid_target");
+
+    let mut policy = AudiencePolicy::new();
+    policy.allow(EXAMPLE_CLIENT_ID, SERVICE_AUDIENCE);
+    let request = setup.request(&subject.token, &[("audience", SERVICE_AUDIENCE)]);
+    let response = setup.execute(request, policy);
+    assert_eq!(response.status, Status::Ok);
+
+    let exchanged: ExchangeResponse = serde_json::from_str(&ExchangeSetup::json_body(response)).unwrap();
+    let grant = setup
+        .issuer
+        .recover_token(&exchanged.access_token)
+        .unwrap()
+        .unwrap();
+    let audience = grant
+        .extensions
+        .public()
+        .find(|(name, _)| *name == AUDIENCE_EXTENSION);
+    assert_eq!(audience, Some((AUDIENCE_EXTENSION, Some(SERVICE_AUDIENCE))));
+}
+
+#[test]
+fn exchange_actor() {
+    let mut setup = ExchangeSetup::new();
+    let subject = setup.issue(EXAMPLE_OWNER_ID);
+    let actor = setup.issue("Actor");
+
+    let request = setup.request(
+        &subject.token,
+        &[
+            ("actor_token", &actor.token),
+            ("actor_token_type", ACCESS_TOKEN_TYPE),
+        ],
+    );
+    let response = setup.execute(request, DefaultPolicy);
+    assert_eq!(response.status, Status::Ok);
+
+    let exchanged: ExchangeResponse = serde_json::from_str(&ExchangeSetup::json_body(response)).unwrap();
+    let grant = setup
+        .issuer
+        .recover_token(&exchanged.access_token)
+        .unwrap()
+        .unwrap();
+    let act = grant
+        .extensions
+        .public()
+        .find(|(name, _)| *name == ACTOR_EXTENSION);
+    assert_eq!(act, Some((ACTOR_EXTENSION, Some("Actor"))));
+
+    // An actor token type without a token is malformed.
+    let request = setup.request(&subject.token, &[("actor_token_type", ACCESS_TOKEN_TYPE)]);
+    ExchangeSetup::assert_error(setup.execute(request, DefaultPolicy), "invalid_request");
+}
+
+#[test]
+fn exchange_invalid_subject() {
+    let mut setup = ExchangeSetup::new();
+    let subject = setup.issue(EXAMPLE_OWNER_ID);
+
+    let request = setup.request("NotAToken", &[]);
+    ExchangeSetup::assert_error(setup.execute(request, DefaultPolicy), "invalid_grant");
+
+    // Refresh tokens are not accepted as subject tokens by default.
+    let refresh = subject.refresh.unwrap();
+    let request = setup.request(&refresh, &[]);
+    ExchangeSetup::assert_error(setup.execute(request, DefaultPolicy), "invalid_grant");
+}
+
+#[test]
+fn exchange_unauthorized() {
+    let mut setup = ExchangeSetup::new();
+    let subject = setup.issue(EXAMPLE_OWNER_ID);
+
+    let mut request = setup.request(&subject.token, &[]);
+    request.auth = None;
+    assert_eq!(setup.execute(request, DefaultPolicy).status, Status::Unauthorized);
+}
@@ -0,0 +1,177 @@
+use crate::code_grant::introspection::IntrospectionResponse;
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::{Issuer, TokenMap};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::frontends::simple::endpoint::introspection_flow;
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+struct IntrospectionSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+    resource_server: Option<&'static str>,
+    basic_authorization: String,
+    access_token: String,
+    refresh_token: String,
+}
+
+fn grant(scope: &str) -> Grant {
+    Grant {
+        client_id: EXAMPLE_CLIENT_ID.to_string(),
+        owner_id: EXAMPLE_OWNER_ID.to_string(),
+        redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+        scope: scope.parse().unwrap(),
+        until: Utc::now() + Duration::hours(1),
+        extensions: Extensions::new(),
+    }
+}
+
+impl IntrospectionSetup {
+    fn new() -> IntrospectionSetup {
+        let mut registrar = ClientMap::new();
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+
+        let client = Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        );
+        registrar.register_client(client);
+
+        let issued = issuer.issue(grant(EXAMPLE_SCOPE)).unwrap();
+
+        let basic_authorization =
+            base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+        IntrospectionSetup {
+            registrar,
+            issuer,
+            resource_server: Some(EXAMPLE_CLIENT_ID),
+            basic_authorization,
+            access_token: issued.token,
+            refresh_token: issued.refresh.unwrap(),
+        }
+    }
+
+    fn request(&self, auth: Option<String>, params: &[(&str, &str)]) -> CraftedRequest {
+        CraftedRequest {
+            query: None,
+            urlbody: Some(params.iter().to_single_value_query()),
+            auth,
+        }
+    }
+
+    fn basic(&self, params: &[(&str, &str)]) -> CraftedRequest {
+        self.request(Some(format!("Basic {}", self.basic_authorization)), params)
+    }
+
+    fn execute(&mut self, request: CraftedRequest) -> CraftedResponse {
+        let mut flow = introspection_flow(&self.registrar, &mut self.issuer);
+        if let Some(client_id) = self.resource_server {
+            flow.resource_server(client_id);
+        }
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn introspect(&mut self, request: CraftedRequest) -> IntrospectionResponse {
+        let response = self.execute(request);
+        assert_eq!(response.status, Status::Ok);
+        match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn introspect_active_tokens() {
+    let mut setup = IntrospectionSetup::new();
+
+    let access_token = setup.access_token.clone();
+    let description = setup.introspect(setup.basic(&[("token", &access_token)]));
+    assert!(description.active);
+    assert_eq!(description.client_id.as_deref(), Some(EXAMPLE_CLIENT_ID));
+    assert_eq!(description.sub.as_deref(), Some(EXAMPLE_OWNER_ID));
+    assert_eq!(description.token_type.as_deref(), Some("bearer"));
+    assert!(description.exp.is_some());
+
+    let refresh_token = setup.refresh_token.clone();
+    let hinted = setup.basic(&[("token", &refresh_token), ("token_type_hint", "refresh_token")]);
+    let description = setup.introspect(hinted);
+    assert!(description.active);
+    assert_eq!(description.token_type, None);
+
+    // A wrong hint does not hide the token.
+    let misleading = setup.basic(&[("token", &refresh_token), ("token_type_hint", "access_token")]);
+    assert!(setup.introspect(misleading).active);
+}
+
+#[test]
+fn introspect_inactive_token() {
+    let mut setup = IntrospectionSetup::new();
+    let description = setup.introspect(setup.basic(&[("token", "NotAToken")]));
+    assert_eq!(description, IntrospectionResponse::inactive());
+
+    let access_token = setup.access_token.clone();
+    setup.issuer.revoke(&access_token).unwrap();
+    let description = setup.introspect(setup.basic(&[("token", &access_token)]));
+    assert!(!description.active);
+}
+
+#[test]
+fn introspect_with_bearer() {
+    let mut setup = IntrospectionSetup::new();
+    let introspection = setup.issuer.issue(grant("introspection")).unwrap();
+    let bearer = Some(format!("Bearer {}", introspection.token));
+    let refresh_token = setup.refresh_token.clone();
+    let description = setup.introspect(setup.request(bearer, &[("token", &refresh_token)]));
+    assert!(description.active);
+
+    let wrong = setup.request(Some("Bearer NotAToken".to_owned()), &[("token", &refresh_token)]);
+    assert_eq!(setup.execute(wrong).status, Status::Unauthorized);
+}
+
+#[test]
+fn introspect_unauthorized() {
+    let mut setup = IntrospectionSetup::new();
+    let access_token = setup.access_token.clone();
+
+    let anonymous = setup.request(None, &[("token", &access_token)]);
+    assert_eq!(setup.execute(anonymous).status, Status::Unauthorized);
+
+    let wrong_password = base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, "wrong"));
+    let wrong = setup.request(
+        Some(format!("Basic {}", wrong_password)),
+        &[("token", &access_token)],
+    );
+    assert_eq!(setup.execute(wrong).status, Status::Unauthorized);
+}
+
+#[test]
+fn introspect_as_client() {
+    let mut setup = IntrospectionSetup::new();
+    let access_token = setup.access_token.clone();
+
+    // The token of an ordinary client lacks the introspection scope.
+    let bearer = Some(format!("Bearer {}", access_token));
+    let scanning = setup.request(bearer, &[("token", &access_token)]);
+    assert_eq!(setup.execute(scanning).status, Status::Unauthorized);
+
+    // Valid credentials are not enough either, unless the client is a resource server.
+    setup.resource_server = None;
+    let scanning = setup.basic(&[("token", &access_token)]);
+    assert_eq!(setup.execute(scanning).status, Status::Unauthorized);
+}
+
+#[test]
+fn introspect_without_token() {
+    let mut setup = IntrospectionSetup::new();
+    let response = setup.execute(setup.basic(&[]));
+    assert_eq!(response.status, Status::BadRequest);
+}
@@ -0,0 +1,149 @@
+use crate::endpoint::{AuthorizationFlow, Endpoint};
+use crate::frontends::simple::endpoint::{Error, Generic, Vacant};
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::jwt::{JwkSet, SigningKey};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use chrono::{Duration, Utc};
+use serde_json::{self, json, Value};
+
+use super::{Allow, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+struct JarSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    key: SigningKey,
+}
+
+impl JarSetup {
+    fn new() -> JarSetup {
+        let key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap())
+            .unwrap()
+            .with_kid("request");
+        let jwks = JwkSet {
+            keys: vec![key.jwk()],
+        };
+
+        let mut registrar = ClientMap::new();
+        registrar.register_client(
+            Client::public(
+                EXAMPLE_CLIENT_ID,
+                RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+                EXAMPLE_SCOPE.parse().unwrap(),
+            )
+            .with_jwks(&serde_json::to_string(&jwks).unwrap()),
+        );
+        registrar.register_client(Client::public(
+            "ClientWithoutKeys",
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+        ));
+
+        JarSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthorizationCode".to_owned())),
+            key,
+        }
+    }
+
+    fn endpoint(&mut self) -> impl Endpoint<CraftedRequest, Error = Error<CraftedRequest>> + '_ {
+        Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: Vacant,
+            scopes: Vacant,
+            solicitor: Allow(EXAMPLE_OWNER_ID.to_owned()),
+            response: Vacant,
+        }
+    }
+
+    fn claims() -> Value {
+        json!({
+            "iss": EXAMPLE_CLIENT_ID,
+            "aud": "https://example.com",
+            "exp": (Utc::now() + Duration::minutes(5)).timestamp(),
+            "client_id": EXAMPLE_CLIENT_ID,
+            "response_type": "code",
+            "redirect_uri": EXAMPLE_REDIRECT_URI,
+            "state": "signed",
+        })
+    }
+
+    fn authorize(&mut self, client_id: &str, object: &str) -> Option<CraftedResponse> {
+        let request = CraftedRequest {
+            query: Some(
+                [
+                    ("client_id", client_id),
+                    ("response_type", "code"),
+                    ("state", "unsigned"),
+                    ("request", object),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            urlbody: None,
+            auth: None,
+        };
+
+        let mut flow = AuthorizationFlow::prepare(self.endpoint())
+            .unwrap_or_else(|_| panic!("Not violating any requirements on authorization flow."));
+        flow.execute(request).ok()
+    }
+}
+
+#[test]
+fn request_object() {
+    let mut setup = JarSetup::new();
+    let object = setup
+        .key
+        .sign("oauth-authz-req+jwt", &JarSetup::claims())
+        .unwrap();
+
+    let response = setup
+        .authorize(EXAMPLE_CLIENT_ID, &object)
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Redirect);
+    let location = response.location.unwrap();
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "code" && value == "AuthorizationCode"));
+    // Parameters of the object take precedence over those of the query.
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "state" && value == "signed"));
+}
+
+#[test]
+fn request_object_unverified() {
+    let mut setup = JarSetup::new();
+    let object = setup
+        .key
+        .sign("oauth-authz-req+jwt", &JarSetup::claims())
+        .unwrap();
+    assert!(setup.authorize("ClientWithoutKeys", &object).is_none());
+
+    let other = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap())
+        .unwrap()
+        .with_kid("request");
+    let forged = other.sign("oauth-authz-req+jwt", &JarSetup::claims()).unwrap();
+    assert!(setup.authorize(EXAMPLE_CLIENT_ID, &forged).is_none());
+}
+
+#[test]
+fn request_object_invalid() {
+    let mut setup = JarSetup::new();
+    let invalid = [
+        ("client_id", json!("SomeOtherClient")),
+        ("iss", json!("SomeOtherClient")),
+        ("exp", json!((Utc::now() - Duration::minutes(5)).timestamp())),
+        ("request_uri", json!("urn:ietf:params:oauth:request_uri:nested")),
+    ];
+
+    for (claim, value) in invalid.iter() {
+        let mut claims = JarSetup::claims();
+        claims[claim] = value.clone();
+        let object = setup.key.sign("oauth-authz-req+jwt", &claims).unwrap();
+        assert!(setup.authorize(EXAMPLE_CLIENT_ID, &object).is_none(), "{}", claim);
+    }
+}
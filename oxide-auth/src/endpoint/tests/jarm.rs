@@ -0,0 +1,167 @@
+use crate::endpoint::{AuthorizationFlow, OwnerSolicitor};
+use crate::frontends::simple::endpoint::{Generic, Vacant, WithResponseSigner};
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::jwt::{self, Algorithm, ResponseSigner, SigningKey};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use serde_json::Value;
+
+use super::{Allow, Body, CraftedRequest, CraftedResponse, Deny, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+const ISSUER: &str = "https://auth.example.com";
+
+struct JarmSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    signer: ResponseSigner,
+    public_key: Vec<u8>,
+}
+
+impl JarmSetup {
+    fn new() -> JarmSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+        ));
+
+        let key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let public_key = key.public_key().to_vec();
+
+        JarmSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthorizationCode".to_owned())),
+            signer: ResponseSigner::new(key, ISSUER),
+            public_key,
+        }
+    }
+
+    fn request(mode: &str) -> CraftedRequest {
+        CraftedRequest {
+            query: Some(
+                [
+                    ("client_id", EXAMPLE_CLIENT_ID),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                    ("response_type", "code"),
+                    ("response_mode", mode),
+                    ("state", "jarm"),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            urlbody: None,
+            auth: None,
+        }
+    }
+
+    fn authorize<S>(&mut self, mode: &str, solicitor: S) -> CraftedResponse
+    where
+        S: OwnerSolicitor<CraftedRequest>,
+    {
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: Vacant,
+            scopes: Vacant,
+            solicitor,
+            response: Vacant,
+        };
+
+        AuthorizationFlow::prepare(WithResponseSigner::new(endpoint, &self.signer))
+            .unwrap_or_else(|_| panic!("Not violating any requirements on authorization flow."))
+            .execute(Self::request(mode))
+            .expect("Expected non-error response")
+    }
+
+    fn claims(&self, response: &str) -> Value {
+        let claims: Value = jwt::verify(Algorithm::EdDSA, &self.public_key, response).unwrap();
+        assert_eq!(claims["iss"], ISSUER);
+        assert_eq!(claims["aud"], EXAMPLE_CLIENT_ID);
+        assert_eq!(claims["state"], "jarm");
+        claims
+    }
+}
+
+#[test]
+fn signed_query_response() {
+    let mut setup = JarmSetup::new();
+    for mode in &["jwt", "query.jwt"] {
+        let response = setup.authorize(mode, Allow(EXAMPLE_OWNER_ID.to_owned()));
+        assert_eq!(response.status, Status::Redirect);
+        let location = response.location.unwrap();
+        assert!(location.as_str().starts_with(EXAMPLE_REDIRECT_URI));
+
+        let pairs: Vec<_> = location.query_pairs().collect();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].0, "response");
+        let claims = setup.claims(&pairs[0].1);
+        assert_eq!(claims["code"], "AuthorizationCode");
+    }
+}
+
+#[test]
+fn signed_fragment_response() {
+    let mut setup = JarmSetup::new();
+    let response = setup.authorize("fragment.jwt", Allow(EXAMPLE_OWNER_ID.to_owned()));
+    assert_eq!(response.status, Status::Redirect);
+    let location = response.location.unwrap();
+    assert_eq!(location.query(), None);
+
+    let fragment = location.fragment().unwrap();
+    let signed = fragment.strip_prefix("response=").unwrap();
+    assert_eq!(setup.claims(signed)["code"], "AuthorizationCode");
+}
+
+#[test]
+fn signed_form_post_response() {
+    let mut setup = JarmSetup::new();
+    let response = setup.authorize("form_post.jwt", Allow(EXAMPLE_OWNER_ID.to_owned()));
+    assert_eq!(response.status, Status::Ok);
+    assert!(response.location.is_none());
+
+    let page = match response.body {
+        Some(Body::Html(page)) => page,
+        other => panic!("Expected html body, got {:?}", other),
+    };
+    assert!(page.contains(&format!("action=\"{}\"", EXAMPLE_REDIRECT_URI)));
+    let value = page.split("name=\"response\" value=\"").nth(1).unwrap();
+    let signed = &value[..value.find('"').unwrap()];
+    assert_eq!(setup.claims(signed)["code"], "AuthorizationCode");
+}
+
+#[test]
+fn signed_error_response() {
+    let mut setup = JarmSetup::new();
+    let response = setup.authorize("query.jwt", Deny);
+    assert_eq!(response.status, Status::Redirect);
+    let location = response.location.unwrap();
+    let (_, signed) = location.query_pairs().find(|(key, _)| key == "response").unwrap();
+
+    let claims = setup.claims(&signed);
+    assert_eq!(claims["error"], "access_denied");
+    assert!(claims.get("code").is_none());
+}
+
+#[test]
+fn unsigned_without_signer() {
+    let mut setup = JarmSetup::new();
+    let endpoint = Generic {
+        registrar: &setup.registrar,
+        authorizer: &mut setup.authorizer,
+        issuer: Vacant,
+        scopes: Vacant,
+        solicitor: Allow(EXAMPLE_OWNER_ID.to_owned()),
+        response: Vacant,
+    };
+
+    let response = AuthorizationFlow::prepare(endpoint)
+        .unwrap_or_else(|_| panic!("Not violating any requirements on authorization flow."))
+        .execute(JarmSetup::request("query.jwt"))
+        .expect("Expected non-error response");
+    let location = response.location.unwrap();
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "code" && value == "AuthorizationCode"));
+}
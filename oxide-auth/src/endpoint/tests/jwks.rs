@@ -0,0 +1,69 @@
+use crate::frontends::simple::endpoint::jwks_flow;
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::{Issuer, TokenMap};
+use crate::primitives::jwt::{AccessTokenClaims, Algorithm, JwkSet, JwtIssuer};
+use crate::primitives::keystore::KeyStore;
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use super::{Body, CraftedRequest, Status};
+use super::defaults::*;
+
+fn serve(keys: &KeyStore) -> JwkSet {
+    let request = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: None,
+    };
+
+    let response = jwks_flow(keys)
+        .execute(request)
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Ok);
+
+    match response.body {
+        Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+        other => panic!("Expected json body, got {:?}", other),
+    }
+}
+
+fn grant() -> Grant {
+    Grant {
+        client_id: EXAMPLE_CLIENT_ID.to_string(),
+        owner_id: EXAMPLE_OWNER_ID.to_string(),
+        redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+        scope: EXAMPLE_SCOPE.parse().unwrap(),
+        until: Utc::now() + Duration::hours(1),
+        extensions: Extensions::new(),
+    }
+}
+
+#[test]
+fn served_keys_follow_rotation() {
+    let keys = KeyStore::new();
+    let first = keys.generate(Algorithm::ES256).unwrap();
+    let mut issuer = JwtIssuer::with_keys(
+        keys.clone(),
+        "https://auth.example.com",
+        TokenMap::new(RandomGenerator::new(16)),
+    );
+
+    let before = issuer.issue(grant()).unwrap().token;
+    let jwks = serve(&keys);
+    assert_eq!(jwks.keys.len(), 1);
+    assert_eq!(jwks.keys[0].kid.as_deref(), Some(first.as_str()));
+    assert!(jwks.verify::<AccessTokenClaims>(&before).is_ok());
+
+    // The rotated key signs new tokens, while the previous one is still published.
+    let second = keys.rotate().unwrap();
+    let after = issuer.issue(grant()).unwrap().token;
+    let jwks = serve(&keys);
+    assert_eq!(jwks.keys.len(), 2);
+    assert_eq!(jwks.keys[0].kid.as_deref(), Some(second.as_str()));
+    assert!(jwks.verify::<AccessTokenClaims>(&before).is_ok());
+    assert!(jwks.verify::<AccessTokenClaims>(&after).is_ok());
+    assert!(issuer.recover_token(&before).unwrap().is_some());
+    assert!(issuer.recover_token(&after).unwrap().is_some());
+}
@@ -0,0 +1,66 @@
+use crate::code_grant::extensions::Pkce;
+use crate::code_grant::metadata::{MetadataBuilder, ServerMetadata};
+use crate::frontends::simple::endpoint::metadata_flow;
+
+use serde_json;
+
+use super::{Body, CraftedRequest, Status};
+
+fn serve(metadata: &ServerMetadata) -> ServerMetadata {
+    let request = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: None,
+    };
+
+    let response = metadata_flow(metadata)
+        .execute(request)
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Ok);
+
+    match response.body {
+        Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+        other => panic!("Expected json body, got {:?}", other),
+    }
+}
+
+#[test]
+fn metadata_document() {
+    let metadata = MetadataBuilder::new("https://auth.example.com")
+        .authorization_endpoint("/authorize")
+        .token_endpoint("/token")
+        .grant_types(vec!["authorization_code", "refresh_token"])
+        .pkce(&Pkce::required())
+        .build()
+        .unwrap();
+
+    let served = serve(&metadata);
+    assert_eq!(served, metadata);
+    assert_eq!(served.code_challenge_methods_supported, vec!["S256".to_owned()]);
+}
+
+#[test]
+fn openid_configuration() {
+    let metadata = MetadataBuilder::openid("https://auth.example.com")
+        .authorization_endpoint("/authorize")
+        .token_endpoint("/token")
+        .jwks_uri("/jwks.json")
+        .userinfo_endpoint("/userinfo")
+        .scopes(vec!["openid", "profile", "email"])
+        .id_token_signing_algs(vec!["RS256"])
+        .claims(vec!["sub", "name", "email"])
+        .build()
+        .unwrap();
+
+    let served = serve(&metadata);
+    assert_eq!(served, metadata);
+    assert_eq!(served.subject_types_supported, vec!["public".to_owned()]);
+    assert_eq!(
+        served.id_token_signing_alg_values_supported,
+        vec!["RS256".to_owned()]
+    );
+    assert_eq!(
+        served.jwks_uri.as_deref(),
+        Some("https://auth.example.com/jwks.json")
+    );
+}
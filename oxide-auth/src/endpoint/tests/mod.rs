@@ -66,6 +66,9 @@ enum Body {
 
     /// A json encoded body, `application/json`.
     Json(String),
+
+    /// An html page, `text/html`.
+    Html(String),
 }
 
 #[derive(Debug)]
@@ -141,6 +144,12 @@ impl WebResponse for CraftedResponse {
         self.body = Some(Body::Json(data.to_owned()));
         Ok(())
     }
+
+    /// An html page, with media type `text/html`.
+    fn body_html(&mut self, html: &str) -> Result<(), Self::Error> {
+        self.body = Some(Body::Html(html.to_owned()));
+        Ok(())
+    }
 }
 
 struct TestGenerator(String);
@@ -217,8 +226,39 @@ pub mod defaults {
 }
 
 mod authorization;
+mod consent;
 mod access_token;
+mod ciba;
 mod client_credentials;
+mod custom_grant;
 mod resource;
 mod refresh;
+mod error;
 mod pkce;
+mod device;
+mod introspection;
+mod metadata;
+mod revocation;
+mod exchange;
+mod mtls;
+mod par;
+#[cfg(feature = "password")]
+mod password;
+mod rar;
+mod steps;
+mod audience;
+mod claims;
+#[cfg(feature = "jwt")]
+mod assertion;
+#[cfg(feature = "jwt")]
+mod dpop;
+#[cfg(feature = "jwt")]
+mod end_session;
+#[cfg(feature = "jwt")]
+mod jar;
+#[cfg(feature = "jwt")]
+mod jarm;
+#[cfg(feature = "jwt")]
+mod jwks;
+#[cfg(feature = "jwt")]
+mod oidc;
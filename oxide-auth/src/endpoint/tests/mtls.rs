@@ -0,0 +1,200 @@
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::endpoint::{
+    AccessTokenFlow, ClientCertificate, Endpoint, QueryParameter, ResourceFlow, WebRequest,
+};
+use crate::frontends::simple::endpoint::{Error, Generic, Vacant};
+use crate::frontends::simple::extensions::{AddonList, CertificateBinding, Extended};
+use crate::primitives::authorizer::{AuthMap, Authorizer};
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl, TlsClientAuth};
+use crate::primitives::scope::Scope;
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use super::{Body, CraftedError, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+/// A crafted request over a mutual TLS connection.
+#[derive(Debug)]
+struct TlsRequest {
+    request: CraftedRequest,
+    certificate: Option<ClientCertificate>,
+}
+
+struct MtlsSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+    resource_scope: [Scope; 1],
+    binding: Arc<CertificateBinding>,
+    certificate: ClientCertificate,
+}
+
+impl WebRequest for TlsRequest {
+    type Response = CraftedResponse;
+    type Error = CraftedError;
+
+    fn query(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+        self.request.query()
+    }
+
+    fn urlbody(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+        self.request.urlbody()
+    }
+
+    fn authheader(&mut self) -> Result<Option<Cow<str>>, Self::Error> {
+        self.request.authheader()
+    }
+
+    fn client_certificate(&mut self) -> Result<Option<ClientCertificate>, Self::Error> {
+        Ok(self.certificate.clone())
+    }
+}
+
+impl MtlsSetup {
+    fn new(binding: CertificateBinding) -> MtlsSetup {
+        let certificate = MtlsSetup::certificate("Certificate");
+        let mut registrar = ClientMap::new();
+        registrar.register_client(
+            Client::public(
+                EXAMPLE_CLIENT_ID,
+                RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+                EXAMPLE_SCOPE.parse().unwrap(),
+            )
+            .with_tls_client_auth(TlsClientAuth::SelfSigned(vec![certificate.thumbprint()])),
+        );
+
+        MtlsSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthorizationCode".to_owned())),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            resource_scope: ["example".parse().unwrap()],
+            binding: Arc::new(binding),
+            certificate,
+        }
+    }
+
+    fn certificate(der: &str) -> ClientCertificate {
+        ClientCertificate {
+            der: der.as_bytes().to_vec(),
+            subject_dn: None,
+        }
+    }
+
+    fn endpoint(&mut self) -> impl Endpoint<TlsRequest, Error = Error<TlsRequest>> + '_ {
+        let mut extensions = AddonList::new();
+        extensions.push_access_token(self.binding.clone());
+        extensions.push_resource(self.binding.clone());
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            scopes: &self.resource_scope[..],
+            solicitor: Vacant,
+            response: Vacant,
+        };
+
+        Extended::extend_with(endpoint, extensions)
+    }
+
+    fn token(&mut self, certificate: Option<ClientCertificate>) -> CraftedResponse {
+        let code = self
+            .authorizer
+            .authorize(Grant {
+                owner_id: EXAMPLE_OWNER_ID.to_owned(),
+                client_id: EXAMPLE_CLIENT_ID.to_owned(),
+                scope: EXAMPLE_SCOPE.parse().unwrap(),
+                redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+                until: Utc::now() + Duration::minutes(10),
+                extensions: Extensions::new(),
+            })
+            .unwrap();
+
+        let request = TlsRequest {
+            request: CraftedRequest {
+                query: None,
+                urlbody: Some(
+                    [
+                        ("grant_type", "authorization_code"),
+                        ("client_id", EXAMPLE_CLIENT_ID),
+                        ("code", &code),
+                        ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                    ]
+                    .iter()
+                    .to_single_value_query(),
+                ),
+                auth: None,
+            },
+            certificate,
+        };
+
+        let mut endpoint = self.endpoint();
+        let mut flow = AccessTokenFlow::prepare(&mut endpoint)
+            .unwrap_or_else(|_| panic!("Not violating any requirements on access token flow."));
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn access(&mut self, token: &str, certificate: Option<ClientCertificate>) -> bool {
+        let request = TlsRequest {
+            request: CraftedRequest {
+                query: None,
+                urlbody: None,
+                auth: Some(format!("Bearer {}", token)),
+            },
+            certificate,
+        };
+
+        let mut endpoint = self.endpoint();
+        let mut flow = ResourceFlow::prepare(&mut endpoint)
+            .unwrap_or_else(|_| panic!("Not violating any requirements on resource flow."));
+        match flow.execute(request) {
+            Ok(_) => true,
+            Err(Ok(response)) => {
+                assert_eq!(response.status, Status::Unauthorized);
+                false
+            }
+            Err(Err(_)) => panic!("Expected non-error response"),
+        }
+    }
+
+    fn json_body(response: CraftedResponse) -> TokenResponse {
+        match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).unwrap(),
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn mtls_client_authentication() {
+    let mut setup = MtlsSetup::new(CertificateBinding::optional());
+    let certificate = setup.certificate.clone();
+    let response = setup.token(Some(certificate));
+    assert_eq!(response.status, Status::Ok);
+
+    // The client is not public, it must present its certificate.
+    assert_eq!(setup.token(None).status, Status::Unauthorized);
+    let other = MtlsSetup::certificate("Other certificate");
+    assert_eq!(setup.token(Some(other)).status, Status::Unauthorized);
+}
+
+#[test]
+fn certificate_bound_token() {
+    let mut setup = MtlsSetup::new(CertificateBinding::required());
+    let certificate = setup.certificate.clone();
+    let response = MtlsSetup::json_body(setup.token(Some(certificate.clone())));
+    assert_eq!(response.token_type.as_deref(), Some("bearer"));
+    let token = response.access_token.unwrap();
+
+    assert!(setup.access(&token, Some(certificate)));
+    assert!(!setup.access(&token, None));
+    let other = MtlsSetup::certificate("Other certificate");
+    assert!(!setup.access(&token, Some(other)));
+}
@@ -0,0 +1,171 @@
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::endpoint::{AccessTokenFlow, AuthorizationFlow, Endpoint, Scope};
+use crate::frontends::simple::endpoint::{Error, Generic, Vacant, WithIdTokenSigner};
+use crate::frontends::simple::extensions::{AddonList, Extended, OpenIdConnect};
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::jwt::{ClaimsProvider, IdTokenClaims, IdTokenSigner, SigningKey};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde_json::{Map, Value};
+
+use super::{Allow, Body, CraftedRequest, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+const ISSUER: &str = "https://auth.example.com";
+
+/// Knows the name of every owner, if the client may see profiles.
+struct Profiles;
+
+struct OidcSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+    signer: IdTokenSigner,
+    oidc: Arc<OpenIdConnect>,
+}
+
+impl ClaimsProvider for Profiles {
+    fn claims(&self, owner_id: &str, _: &str, scope: &Scope) -> Result<Map<String, Value>, ()> {
+        let mut claims = Map::new();
+        if scope.iter().any(|scope| scope == "profile") {
+            claims.insert("name".to_owned(), owner_id.into());
+        }
+        Ok(claims)
+    }
+}
+
+impl OidcSetup {
+    /// Registers a client whose default scope is also requested in authorization requests.
+    fn new(scope: &str) -> OidcSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            scope.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        let key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let mut signer = IdTokenSigner::new(key, ISSUER);
+        signer.claims_provider(Profiles);
+
+        OidcSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthorizationCode".to_owned())),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            signer,
+            oidc: Arc::new(OpenIdConnect::new()),
+        }
+    }
+
+    fn endpoint(&mut self) -> impl Endpoint<CraftedRequest, Error = Error<CraftedRequest>> + '_ {
+        let mut extensions = AddonList::new();
+        extensions.push_code(self.oidc.clone());
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            scopes: Vacant,
+            solicitor: Allow(EXAMPLE_OWNER_ID.to_owned()),
+            response: Vacant,
+        };
+
+        WithIdTokenSigner::new(Extended::extend_with(endpoint, extensions), &self.signer)
+    }
+
+    fn authorize(&mut self, scope: &str) {
+        let request = CraftedRequest {
+            query: Some(
+                [
+                    ("client_id", EXAMPLE_CLIENT_ID),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                    ("response_type", "code"),
+                    ("scope", scope),
+                    ("nonce", "n-0S6_WzA2Mj"),
+                    ("acr_values", "urn:example:loa:2"),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            urlbody: None,
+            auth: None,
+        };
+
+        let response = AuthorizationFlow::prepare(self.endpoint())
+            .unwrap_or_else(|_| panic!("Not violating any requirements on authorization flow."))
+            .execute(request)
+            .expect("Expected non-error response");
+        assert_eq!(response.status, Status::Redirect);
+    }
+
+    fn token(&mut self) -> TokenResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("grant_type", "authorization_code"),
+                    ("code", "AuthorizationCode"),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth: Some(
+                "Basic ".to_owned()
+                    + &base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE)),
+            ),
+        };
+
+        let response = AccessTokenFlow::prepare(self.endpoint())
+            .unwrap_or_else(|_| panic!("Not violating any requirements on access token flow."))
+            .execute(request)
+            .expect("Expected non-error response");
+        match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn id_token_issued() {
+    let mut setup = OidcSetup::new("openid profile");
+    setup.authorize("openid profile");
+    let token = setup.token();
+    assert!(token.access_token.is_some());
+
+    let id_token = token.id_token.expect("Expected an id token for the openid scope");
+    let claims: IdTokenClaims = setup.signer.keys().verify(&id_token).unwrap();
+    assert_eq!(claims.iss, ISSUER);
+    assert_eq!(claims.sub, EXAMPLE_OWNER_ID);
+    assert_eq!(claims.aud, EXAMPLE_CLIENT_ID);
+    assert_eq!(claims.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+    assert_eq!(claims.acr.as_deref(), Some("urn:example:loa:2"));
+    assert!(claims.auth_time.unwrap() <= Utc::now().timestamp());
+    assert!(claims.exp > Utc::now().timestamp());
+    assert_eq!(claims.claims["name"], EXAMPLE_OWNER_ID);
+}
+
+#[test]
+fn id_token_claims_by_scope() {
+    let mut setup = OidcSetup::new("openid");
+    setup.authorize("openid");
+    let id_token = setup.token().id_token.unwrap();
+    let claims: IdTokenClaims = setup.signer.keys().verify(&id_token).unwrap();
+    assert!(!claims.claims.contains_key("name"));
+}
+
+#[test]
+fn no_id_token_without_openid() {
+    let mut setup = OidcSetup::new("profile");
+    setup.authorize("profile");
+    let token = setup.token();
+    assert!(token.access_token.is_some());
+    assert!(token.id_token.is_none());
+}
@@ -0,0 +1,154 @@
+use crate::code_grant::par::PushedResponse;
+use crate::endpoint::{AuthorizationFlow, Endpoint, PushedAuthorizationFlow};
+use crate::frontends::simple::endpoint::{Error, Generic, Vacant, WithRequestUris};
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::request_uri::RequestUriMap;
+
+use chrono::Duration;
+use serde_json;
+
+use super::{Allow, Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+struct ParSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    request_uris: RequestUriMap,
+    basic_authorization: String,
+}
+
+impl ParSetup {
+    fn new() -> ParSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        ParSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthorizationCode".to_owned())),
+            request_uris: RequestUriMap::new(),
+            basic_authorization: base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE)),
+        }
+    }
+
+    fn endpoint(&mut self) -> impl Endpoint<CraftedRequest, Error = Error<CraftedRequest>> + '_ {
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: Vacant,
+            scopes: Vacant,
+            solicitor: Allow(EXAMPLE_OWNER_ID.to_owned()),
+            response: Vacant,
+        };
+
+        WithRequestUris::new(endpoint, &mut self.request_uris)
+    }
+
+    fn push(&mut self, redirect_uri: &str, auth: Option<String>) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("response_type", "code"),
+                    ("redirect_uri", redirect_uri),
+                    ("state", "pushed"),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth,
+        };
+
+        let mut flow = PushedAuthorizationFlow::prepare(self.endpoint())
+            .unwrap_or_else(|_| panic!("Not violating any requirements on pushed authorization flow."));
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn push_valid(&mut self) -> PushedResponse {
+        let auth = Some("Basic ".to_owned() + &self.basic_authorization);
+        let response = self.push(EXAMPLE_REDIRECT_URI, auth);
+        assert_eq!(response.status, Status::Ok);
+        match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+
+    fn authorize(&mut self, client_id: &str, request_uri: &str) -> Option<CraftedResponse> {
+        let request = CraftedRequest {
+            query: Some(
+                [("client_id", client_id), ("request_uri", request_uri)]
+                    .iter()
+                    .to_single_value_query(),
+            ),
+            urlbody: None,
+            auth: None,
+        };
+
+        let mut flow = AuthorizationFlow::prepare(self.endpoint())
+            .unwrap_or_else(|_| panic!("Not violating any requirements on authorization flow."));
+        flow.execute(request).ok()
+    }
+}
+
+#[test]
+fn pushed_request() {
+    let mut setup = ParSetup::new();
+    let pushed = setup.push_valid();
+    assert!(pushed.expires_in > 0);
+
+    // Another client can not use the pushed request.
+    assert!(setup.authorize("SomeOtherClient", &pushed.request_uri).is_none());
+
+    let response = setup
+        .authorize(EXAMPLE_CLIENT_ID, &pushed.request_uri)
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Redirect);
+    let location = response.location.unwrap();
+    assert!(location.as_str().starts_with(EXAMPLE_REDIRECT_URI));
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "code" && value == "AuthorizationCode"));
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "state" && value == "pushed"));
+}
+
+#[test]
+fn pushed_request_single_use() {
+    let mut setup = ParSetup::new();
+    let pushed = setup.push_valid();
+    assert!(setup.authorize(EXAMPLE_CLIENT_ID, &pushed.request_uri).is_some());
+    assert!(setup.authorize(EXAMPLE_CLIENT_ID, &pushed.request_uri).is_none());
+    assert!(setup
+        .authorize(EXAMPLE_CLIENT_ID, "urn:ietf:params:oauth:request_uri:unknown")
+        .is_none());
+}
+
+#[test]
+fn pushed_request_expired() {
+    let mut setup = ParSetup::new();
+    setup.request_uris.valid_for(Duration::seconds(-1));
+    let pushed = setup.push_valid();
+    assert!(setup.authorize(EXAMPLE_CLIENT_ID, &pushed.request_uri).is_none());
+}
+
+#[test]
+fn pushed_request_rejected() {
+    let mut setup = ParSetup::new();
+    let auth = Some("Basic ".to_owned() + &setup.basic_authorization);
+    let response = setup.push("https://other.example/endpoint", auth);
+    assert_eq!(response.status, Status::BadRequest);
+
+    let response = setup.push(EXAMPLE_REDIRECT_URI, None);
+    assert_eq!(response.status, Status::Unauthorized);
+
+    let wrong = base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, "WrongPassphrase"));
+    let response = setup.push(EXAMPLE_REDIRECT_URI, Some("Basic ".to_owned() + &wrong));
+    assert_eq!(response.status, Status::Unauthorized);
+}
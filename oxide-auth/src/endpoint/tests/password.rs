@@ -0,0 +1,114 @@
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::code_grant::password::{Throttle, PASSWORD_GRANT_TYPE};
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::issuer::{Issuer, TokenMap};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::frontends::simple::endpoint::password_flow;
+
+use chrono::Duration;
+use serde_json;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+const USERNAME: &str = "alice";
+const PASSWORD: &str = "correct horse battery staple";
+
+struct PasswordSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+    throttle: Throttle,
+    basic_authorization: String,
+}
+
+impl PasswordSetup {
+    fn new() -> PasswordSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        PasswordSetup {
+            registrar,
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            throttle: Throttle::new(2, Duration::minutes(5)),
+            basic_authorization: base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE)),
+        }
+    }
+
+    fn request(&mut self, password: &str, auth: bool) -> CraftedResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(
+                [
+                    ("grant_type", PASSWORD_GRANT_TYPE),
+                    ("username", USERNAME),
+                    ("password", password),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            auth: Some(format!("Basic {}", self.basic_authorization)).filter(|_| auth),
+        };
+
+        let validator = |username: &str, password: &str| {
+            Some(EXAMPLE_OWNER_ID.to_owned()).filter(|_| username == USERNAME && password == PASSWORD)
+        };
+        let mut flow = password_flow(&self.registrar, &mut self.issuer, validator, &self.throttle);
+        flow.execute(request).expect("Expected non-error response")
+    }
+
+    fn json_body(response: CraftedResponse) -> TokenResponse {
+        match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn password_grant() {
+    let mut setup = PasswordSetup::new();
+    let response = setup.request(PASSWORD, true);
+    assert_eq!(response.status, Status::Ok);
+
+    let token = PasswordSetup::json_body(response);
+    assert!(token.refresh_token.is_some());
+    let grant = setup
+        .issuer
+        .recover_token(&token.access_token.unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(grant.owner_id, EXAMPLE_OWNER_ID);
+    assert_eq!(grant.client_id, EXAMPLE_CLIENT_ID);
+}
+
+#[test]
+fn password_grant_rejected() {
+    let mut setup = PasswordSetup::new();
+    let response = setup.request("wrong", true);
+    assert_eq!(response.status, Status::BadRequest);
+    let token = PasswordSetup::json_body(response);
+    assert_eq!(token.error.as_deref(), Some("invalid_grant"));
+
+    // The confidential client must authenticate.
+    let response = setup.request(PASSWORD, false);
+    assert_eq!(response.status, Status::Unauthorized);
+}
+
+#[test]
+fn password_grant_throttled() {
+    let mut setup = PasswordSetup::new();
+    assert_eq!(setup.request("wrong", true).status, Status::BadRequest);
+    assert_eq!(setup.request("wrong", true).status, Status::BadRequest);
+
+    // Even the correct password is rejected while the username is locked out.
+    let response = setup.request(PASSWORD, true);
+    assert_eq!(response.status, Status::BadRequest);
+    let token = PasswordSetup::json_body(response);
+    assert_eq!(token.error.as_deref(), Some("invalid_grant"));
+}
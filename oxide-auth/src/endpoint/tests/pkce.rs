@@ -51,8 +51,12 @@ impl PkceSetup {
     fn allowing_endpoint(
         &mut self,
     ) -> impl Endpoint<CraftedRequest, Error = Error<CraftedRequest>> + '_ {
-        let pkce_extension = Pkce::required();
+        self.endpoint_with(Pkce::required())
+    }
 
+    fn endpoint_with(
+        &mut self, pkce_extension: Pkce,
+    ) -> impl Endpoint<CraftedRequest, Error = Error<CraftedRequest>> + '_ {
         let mut extensions = AddonList::new();
         extensions.push_code(pkce_extension);
 
@@ -123,6 +127,21 @@ impl PkceSetup {
         }
     }
 
+    fn test_refused_authorization(&mut self, pkce: Pkce, auth_request: CraftedRequest) {
+        let mut endpoint = self.endpoint_with(pkce);
+        let mut flow = AuthorizationFlow::prepare(&mut endpoint)
+            .unwrap_or_else(|_| panic!("Not violating any requirements on authorization flow."));
+        let response = flow
+            .execute(auth_request)
+            .expect("Expected no flow execution error");
+        assert_eq!(response.status, Status::Redirect, "Expected redirect to client");
+        assert!(response
+            .location
+            .unwrap()
+            .as_str()
+            .contains("error=invalid_request"));
+    }
+
     fn assert_nonerror_redirect(response: CraftedResponse) {
         assert_eq!(response.status, Status::Redirect, "Expected redirect to client");
         assert!(response.location.unwrap().as_str().find("error").is_none());
@@ -215,3 +234,72 @@ fn pkce_failed_verifier() {
 
     setup.test_failed_verification(correct_authorization, correct_access);
 }
+
+fn authorization_request(challenge: Option<(&str, &str)>) -> CraftedRequest {
+    let mut query = vec![
+        ("client_id", EXAMPLE_CLIENT_ID),
+        ("redirect_uri", EXAMPLE_REDIRECT_URI),
+        ("response_type", "code"),
+    ];
+    if let Some((challenge, method)) = challenge {
+        query.push(("code_challenge", challenge));
+        query.push(("code_challenge_method", method));
+    }
+
+    CraftedRequest {
+        query: Some(query.iter().to_single_value_query()),
+        urlbody: None,
+        auth: None,
+    }
+}
+
+#[test]
+fn pkce_required_for_client() {
+    let mut setup = PkceSetup::new();
+
+    let mut pkce = Pkce::optional();
+    pkce.require_for(|client_id| client_id == EXAMPLE_CLIENT_ID);
+    setup.test_refused_authorization(pkce, authorization_request(None));
+
+    // Clients required to use PKCE may not use the plain method.
+    let mut pkce = Pkce::optional();
+    pkce.allow_plain();
+    pkce.require_client(EXAMPLE_CLIENT_ID);
+    let plain = setup.verifier.clone();
+    setup.test_refused_authorization(pkce, authorization_request(Some((&plain, "plain"))));
+}
+
+#[test]
+fn pkce_exempt_client() {
+    let mut setup = PkceSetup::new();
+
+    let mut pkce = Pkce::required();
+    pkce.exempt_client(EXAMPLE_CLIENT_ID);
+    let access = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "authorization_code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("code", &setup.auth_token),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: None,
+    };
+
+    let mut endpoint = setup.endpoint_with(pkce);
+    let response = AuthorizationFlow::prepare(&mut endpoint)
+        .unwrap_or_else(|_| panic!("Not violating any requirements on authorization flow."))
+        .execute(authorization_request(None))
+        .expect("Expected no flow execution error");
+    PkceSetup::assert_nonerror_redirect(response);
+
+    let response = AccessTokenFlow::prepare(&mut endpoint)
+        .unwrap_or_else(|_| panic!("Not violating any requirements on access token flow."))
+        .execute(access)
+        .expect("Expected no flow execution error");
+    assert_eq!(response.status, Status::Ok, "Expected access token in response");
+}
@@ -0,0 +1,189 @@
+use crate::code_grant::accesstoken::TokenResponse;
+use crate::code_grant::introspection::IntrospectionResponse;
+use crate::endpoint::{
+    AccessTokenFlow, AuthorizationDetail, AuthorizationFlow, Endpoint, OwnerConsent, OwnerSolicitor,
+    Solicitation,
+};
+use crate::frontends::simple::endpoint::{introspection_flow, Error, Generic, Vacant};
+use crate::frontends::simple::extensions::{AddonList, Extended, RichAuthorization};
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::issuer::TokenMap;
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use std::sync::Arc;
+
+use serde_json;
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+const DETAILS: &str = r#"[{"type":"account_information","actions":["read"],"identifier":"account-1"}]"#;
+
+/// Approves requests only after having seen their authorization details.
+struct DetailsConsent(Option<Vec<AuthorizationDetail>>);
+
+struct RarSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+    rar: Arc<RichAuthorization>,
+}
+
+impl OwnerSolicitor<CraftedRequest> for DetailsConsent {
+    fn check_consent(
+        &mut self, _: &mut CraftedRequest, solicitation: Solicitation,
+    ) -> OwnerConsent<CraftedResponse> {
+        self.0 = solicitation.authorization_details();
+        OwnerConsent::Authorized(EXAMPLE_OWNER_ID.to_owned())
+    }
+}
+
+impl RarSetup {
+    fn new() -> RarSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+
+        RarSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthorizationCode".to_owned())),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            rar: Arc::new(RichAuthorization::new(vec!["account_information"])),
+        }
+    }
+
+    fn endpoint<'a>(
+        &'a mut self, consent: &'a mut DetailsConsent,
+    ) -> impl Endpoint<CraftedRequest, Error = Error<CraftedRequest>> + 'a {
+        let mut extensions = AddonList::new();
+        extensions.push_code(self.rar.clone());
+
+        let endpoint = Generic {
+            registrar: &self.registrar,
+            authorizer: &mut self.authorizer,
+            issuer: &mut self.issuer,
+            scopes: Vacant,
+            solicitor: consent,
+            response: Vacant,
+        };
+
+        Extended::extend_with(endpoint, extensions)
+    }
+
+    fn authorize(&mut self, details: &str) -> (CraftedResponse, Option<Vec<AuthorizationDetail>>) {
+        let request = CraftedRequest {
+            query: Some(
+                [
+                    ("client_id", EXAMPLE_CLIENT_ID),
+                    ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                    ("response_type", "code"),
+                    ("authorization_details", details),
+                ]
+                .iter()
+                .to_single_value_query(),
+            ),
+            urlbody: None,
+            auth: None,
+        };
+
+        let mut consent = DetailsConsent(None);
+        let response = AuthorizationFlow::prepare(self.endpoint(&mut consent))
+            .unwrap_or_else(|_| panic!("Not violating any requirements on authorization flow."))
+            .execute(request)
+            .expect("Expected non-error response");
+        assert_eq!(response.status, Status::Redirect);
+        (response, consent.0)
+    }
+
+    fn token(&mut self, details: Option<&str>) -> TokenResponse {
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", "AuthorizationCode"),
+            ("redirect_uri", EXAMPLE_REDIRECT_URI),
+        ];
+        params.extend(details.map(|details| ("authorization_details", details)));
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some(params.iter().to_single_value_query()),
+            auth: Some(
+                "Basic ".to_owned()
+                    + &base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE)),
+            ),
+        };
+
+        let mut consent = DetailsConsent(None);
+        let response = AccessTokenFlow::prepare(self.endpoint(&mut consent))
+            .unwrap_or_else(|_| panic!("Not violating any requirements on access token flow."))
+            .execute(request)
+            .expect("Expected non-error response");
+        Self::json_body(response)
+    }
+
+    fn introspect(&mut self, token: &str) -> IntrospectionResponse {
+        let request = CraftedRequest {
+            query: None,
+            urlbody: Some([("token", token)].iter().to_single_value_query()),
+            auth: Some(
+                "Basic ".to_owned()
+                    + &base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE)),
+            ),
+        };
+
+        let mut flow = introspection_flow(&self.registrar, &mut self.issuer);
+        flow.resource_server(EXAMPLE_CLIENT_ID);
+        let response = flow.execute(request).expect("Expected non-error response");
+        Self::json_body(response)
+    }
+
+    fn json_body<T: serde::de::DeserializeOwned>(response: CraftedResponse) -> T {
+        match response.body {
+            Some(Body::Json(json)) => serde_json::from_str(&json).expect("Expected valid json body"),
+            other => panic!("Expected json body, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn authorization_details_granted() {
+    let mut setup = RarSetup::new();
+    let details: Vec<AuthorizationDetail> = serde_json::from_str(DETAILS).unwrap();
+
+    let (_, consented) = setup.authorize(DETAILS);
+    assert_eq!(consented.as_ref(), Some(&details));
+
+    let token = setup.token(None);
+    assert_eq!(token.authorization_details.as_ref(), Some(&details));
+
+    let description = setup.introspect(&token.access_token.unwrap());
+    assert!(description.active);
+    assert_eq!(description.authorization_details, Some(details));
+}
+
+#[test]
+fn authorization_details_narrowed() {
+    let mut setup = RarSetup::new();
+    setup.authorize(DETAILS);
+    let other = r#"[{"type":"account_information","actions":["write"]}]"#;
+    let token = setup.token(Some(other));
+    assert_eq!(token.error.as_deref(), Some("invalid_request"));
+
+    setup.authorize(DETAILS);
+    let token = setup.token(Some(DETAILS));
+    assert!(token.authorization_details.is_some());
+}
+
+#[test]
+fn authorization_details_invalid() {
+    let mut setup = RarSetup::new();
+    for details in &[r#"[{"type":"payment_initiation"}]"#, "[]", "{}"] {
+        let (response, consented) = setup.authorize(details);
+        assert!(consented.is_none());
+        let location = response.location.unwrap();
+        assert!(location.as_str().contains("error=invalid_request"));
+    }
+}
@@ -1,7 +1,9 @@
-use crate::primitives::issuer::{Issuer, IssuedToken, RefreshedToken, TokenMap, TokenType};
+use crate::primitives::error::PrimitiveError;
+use crate::primitives::issuer::{Issuer, IssuedToken, Lifetimes, RefreshedToken, TokenMap, TokenType};
 use crate::primitives::generator::RandomGenerator;
 use crate::primitives::grant::{Grant, Extensions};
 use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+use crate::primitives::scope::Scope;
 
 use std::collections::HashMap;
 
@@ -12,7 +14,10 @@ use serde_json;
 use super::{Body, CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
 use super::defaults::*;
 use crate::code_grant::accesstoken::TokenResponse;
-use crate::frontends::simple::endpoint::{refresh_flow, resource_flow};
+use crate::endpoint::{OAuthError, RefreshFlow};
+use crate::frontends::simple::endpoint::{
+    refresh_flow, resource_flow, Error, Generic, Vacant, WithTokenResponseHook,
+};
 
 struct RefreshTokenSetup {
     registrar: ClientMap,
@@ -26,6 +31,27 @@ struct RefreshTokenSetup {
     basic_authorization: String,
 }
 
+/// Recovers grants of the inner issuer but fails to refresh them.
+struct FailingRefresh<'a>(&'a mut TokenMap<RandomGenerator>, PrimitiveError);
+
+impl Issuer for FailingRefresh<'_> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        self.0.issue(grant)
+    }
+
+    fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        Err(self.1)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.0.recover_token(token)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.0.recover_refresh(token)
+    }
+}
+
 impl RefreshTokenSetup {
     fn private_client() -> Self {
         let mut registrar = ClientMap::new();
@@ -404,3 +430,128 @@ fn private_invalid_token() {
 
     setup.assert_invalid_grant(valid_private);
 }
+
+#[test]
+fn private_refresh_expired() {
+    let mut setup = RefreshTokenSetup::private_client();
+    setup.issuer.lifetime_policy(Lifetimes {
+        refresh: Some(Duration::minutes(-1)),
+        ..Lifetimes::default()
+    });
+    let grant = setup
+        .issuer
+        .recover_refresh(&setup.refresh_token)
+        .unwrap()
+        .unwrap();
+    let expired = setup.issuer.issue(grant).unwrap().refresh.unwrap();
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            vec![("grant_type", "refresh_token"), ("refresh_token", &expired)]
+                .iter()
+                .to_single_value_query(),
+        ),
+        auth: Some(setup.basic_authorization.clone()),
+    };
+
+    let response = refresh_flow(&setup.registrar, &mut setup.issuer)
+        .execute(request)
+        .expect("Expected non-failed reponse");
+    assert_eq!(response.status, Status::BadRequest);
+    let body = setup.assert_json_body(&response);
+    assert_eq!(body.get("error").map(String::as_str), Some("invalid_grant"));
+    assert_eq!(
+        body.get("error_description").map(String::as_str),
+        Some("The refresh token has expired")
+    );
+}
+
+#[test]
+fn response_hook_members() {
+    let mut setup = RefreshTokenSetup::public_client();
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            vec![
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &setup.refresh_token),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: None,
+    };
+
+    let hook = |grant: &Grant| {
+        let mut members = serde_json::Map::new();
+        members.insert("owner".into(), grant.owner_id.clone().into());
+        members.insert("access_token".into(), "forged".into());
+        members.insert("scope".into(), "admin".into());
+        members
+    };
+
+    let endpoint = WithTokenResponseHook::new(
+        Generic {
+            registrar: &setup.registrar,
+            authorizer: Vacant,
+            issuer: &mut setup.issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        },
+        hook,
+    );
+
+    let response = RefreshFlow::prepare(endpoint)
+        .unwrap()
+        .execute(request)
+        .expect("Expected non-failed reponse");
+    assert_eq!(response.status, Status::Ok);
+    let body = match response.body {
+        Some(Body::Json(body)) => body,
+        _ => panic!("Expect json body"),
+    };
+
+    let body: HashMap<String, serde_json::Value> =
+        serde_json::from_str(&body).expect("Expected valid json body");
+    assert_eq!(body.get("owner").and_then(|v| v.as_str()), Some(EXAMPLE_OWNER_ID));
+    assert_ne!(body.get("access_token").and_then(|v| v.as_str()), Some("forged"));
+    let scope = body.get("scope").and_then(|v| v.as_str()).unwrap();
+    assert_eq!(scope.parse::<Scope>().unwrap(), EXAMPLE_SCOPE.parse().unwrap());
+}
+
+#[test]
+fn refresh_failures_classified() {
+    let mut setup = RefreshTokenSetup::private_client();
+    let request = || CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("grant_type", "refresh_token"),
+                ("refresh_token", setup.refresh_token.as_str()),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: Some(setup.basic_authorization.clone()),
+    };
+    let (valid_vanished, valid_unavailable) = (request(), request());
+
+    // A token revoked concurrently is as invalid as an unknown one.
+    let mut issuer = FailingRefresh(&mut setup.issuer, PrimitiveError::NotFound);
+    let response = refresh_flow(&setup.registrar, &mut issuer)
+        .execute(valid_vanished)
+        .expect("Expected non-failed reponse");
+    assert_eq!(response.status, Status::BadRequest);
+    let body = setup.assert_json_body(&response);
+    assert_eq!(body.get("error").map(String::as_str), Some("invalid_grant"));
+
+    let mut issuer = FailingRefresh(&mut setup.issuer, PrimitiveError::Unavailable);
+    let response = refresh_flow(&setup.registrar, &mut issuer).execute(valid_unavailable);
+    assert!(matches!(
+        response,
+        Err(Error::OAuth(OAuthError::TemporarilyUnavailable))
+    ));
+}
@@ -176,3 +176,89 @@ fn resource_wrong_scope() {
 
     setup.test_access_error(wrong_scope);
 }
+
+#[test]
+fn resource_step_up() {
+    use crate::code_grant::extensions::OpenIdConnect;
+    use crate::endpoint::StepUp;
+    use crate::primitives::grant::Value;
+    use crate::primitives::issuer::Issuer;
+
+    let mut setup = ResourceSetup::new();
+    let mut authenticated = |acr: &str| {
+        let authentication = OpenIdConnect::new()
+            .authenticate(Some("openid"), None, Some(acr), None)
+            .unwrap();
+        let mut extensions = Extensions::new();
+        let encoded = OpenIdConnect::encode(&authentication);
+        extensions.set(&OpenIdConnect, Value::public(Some(encoded)));
+        let token = setup
+            .issuer
+            .issue(Grant {
+                client_id: EXAMPLE_CLIENT_ID.to_string(),
+                owner_id: EXAMPLE_OWNER_ID.to_string(),
+                redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+                scope: "legit needed".parse().unwrap(),
+                until: Utc::now() + Duration::hours(1),
+                extensions,
+            })
+            .unwrap();
+        CraftedRequest {
+            query: None,
+            urlbody: None,
+            auth: Some("Bearer ".to_string() + &token.token),
+        }
+    };
+    let strong = authenticated("otp");
+    let weak = authenticated("pwd");
+    let unauthenticated = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &setup.authtoken),
+    };
+
+    let mut flow = resource_flow(&mut setup.issuer, &setup.resource_scope);
+    flow.require_authentication(StepUp::new(vec!["pwd", "otp"], "otp"));
+    assert!(flow.execute(strong).is_ok());
+
+    for denied in [weak, unauthenticated] {
+        let response = match flow.execute(denied) {
+            Ok(grant) => panic!("Expected an error instead of {:?}", grant),
+            Err(response) => response.unwrap(),
+        };
+        let challenge = response.www_authenticate.unwrap();
+        assert!(challenge.contains("error=\"insufficient_user_authentication\""));
+        assert!(challenge.contains("acr_values=\"otp\""));
+    }
+}
+
+#[test]
+fn resource_clock() {
+    use crate::frontends::simple::endpoint::{Generic, Vacant, WithClock};
+    use crate::endpoint::ResourceFlow;
+    use crate::primitives::clock::ManualClock;
+
+    let mut setup = ResourceSetup::new();
+    let clock = ManualClock::new(Utc::now());
+    let token = setup.authtoken.clone();
+    let authorized = || CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &token),
+    };
+
+    let endpoint = Generic {
+        registrar: Vacant,
+        authorizer: Vacant,
+        issuer: &mut setup.issuer,
+        solicitor: Vacant,
+        scopes: &setup.resource_scope[..],
+        response: Vacant,
+    };
+    let mut flow = ResourceFlow::prepare(WithClock::new(endpoint, &clock)).unwrap();
+    assert!(flow.execute(authorized()).is_ok());
+
+    // The token expires by the clock of the endpoint, not that of the system.
+    clock.advance(Duration::hours(2));
+    assert!(flow.execute(authorized()).is_err());
+}
@@ -0,0 +1,172 @@
+use crate::primitives::generator::RandomGenerator;
+use crate::primitives::grant::{Extensions, Grant};
+use crate::primitives::issuer::{IssuedToken, Issuer, TokenMap};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::frontends::simple::endpoint::revocation_flow;
+
+use chrono::{Duration, Utc};
+
+use super::{CraftedRequest, CraftedResponse, Status, ToSingleValueQuery};
+use super::defaults::*;
+
+const PUBLIC_CLIENT_ID: &str = "PublicClient";
+
+struct RevocationSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+    basic_authorization: String,
+}
+
+impl RevocationSetup {
+    fn new() -> RevocationSetup {
+        let mut registrar = ClientMap::new();
+        let issuer = TokenMap::new(RandomGenerator::new(16));
+
+        registrar.register_client(Client::confidential(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes(),
+        ));
+        registrar.register_client(Client::public(
+            PUBLIC_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+        ));
+
+        let basic_authorization =
+            base64::encode(format!("{}:{}", EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+        RevocationSetup {
+            registrar,
+            issuer,
+            basic_authorization,
+        }
+    }
+
+    fn issue(&mut self, client_id: &str) -> IssuedToken {
+        self.issuer
+            .issue(Grant {
+                client_id: client_id.to_string(),
+                owner_id: EXAMPLE_OWNER_ID.to_string(),
+                redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+                scope: EXAMPLE_SCOPE.parse().unwrap(),
+                until: Utc::now() + Duration::hours(1),
+                extensions: Extensions::new(),
+            })
+            .unwrap()
+    }
+
+    fn basic(&self, params: &[(&str, &str)]) -> CraftedRequest {
+        CraftedRequest {
+            query: None,
+            urlbody: Some(params.iter().to_single_value_query()),
+            auth: Some(format!("Basic {}", self.basic_authorization)),
+        }
+    }
+
+    fn execute(&mut self, request: CraftedRequest) -> CraftedResponse {
+        let mut flow = revocation_flow(&self.registrar, &mut self.issuer);
+        flow.execute(request).expect("Expected non-error response")
+    }
+}
+
+#[test]
+fn revoke_access_token() {
+    let mut setup = RevocationSetup::new();
+    let issued = setup.issue(EXAMPLE_CLIENT_ID);
+
+    let response = setup.execute(setup.basic(&[("token", &issued.token)]));
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(setup.issuer.recover_token(&issued.token), Ok(None));
+    assert!(setup
+        .issuer
+        .recover_refresh(issued.refresh.as_ref().unwrap())
+        .unwrap()
+        .is_some());
+
+    // Revoking again is just as successful.
+    let response = setup.execute(setup.basic(&[("token", &issued.token)]));
+    assert_eq!(response.status, Status::Ok);
+}
+
+#[test]
+fn revoke_refresh_token() {
+    let mut setup = RevocationSetup::new();
+    let issued = setup.issue(PUBLIC_CLIENT_ID);
+    let refresh = issued.refresh.unwrap();
+
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(
+            [
+                ("token", refresh.as_str()),
+                ("token_type_hint", "refresh_token"),
+                ("client_id", PUBLIC_CLIENT_ID),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        auth: None,
+    };
+    let response = setup.execute(request);
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(setup.issuer.recover_refresh(&refresh), Ok(None));
+    assert_eq!(setup.issuer.recover_token(&issued.token), Ok(None));
+}
+
+#[test]
+fn revoke_foreign_token() {
+    let mut setup = RevocationSetup::new();
+    let issued = setup.issue(PUBLIC_CLIENT_ID);
+
+    let response = setup.execute(setup.basic(&[("token", &issued.token)]));
+    assert_eq!(response.status, Status::BadRequest);
+    assert!(setup.issuer.recover_token(&issued.token).unwrap().is_some());
+}
+
+#[test]
+fn revoke_unauthorized() {
+    let mut setup = RevocationSetup::new();
+    let issued = setup.issue(EXAMPLE_CLIENT_ID);
+
+    let anonymous = CraftedRequest {
+        query: None,
+        urlbody: Some([("token", issued.token.as_str())].iter().to_single_value_query()),
+        auth: None,
+    };
+    assert_eq!(setup.execute(anonymous).status, Status::Unauthorized);
+
+    let missing_token = setup.basic(&[]);
+    assert_eq!(setup.execute(missing_token).status, Status::BadRequest);
+    assert!(setup.issuer.recover_token(&issued.token).unwrap().is_some());
+}
+
+#[cfg(feature = "jwt")]
+#[test]
+fn revoke_stateless_token() {
+    use crate::primitives::jwt::{Algorithm, StatelessIssuer};
+    use crate::primitives::keystore::KeyStore;
+
+    let setup = RevocationSetup::new();
+    let keys = KeyStore::new();
+    keys.generate(Algorithm::EdDSA).unwrap();
+    let mut issuer = StatelessIssuer::new(keys, "https://auth.example.com");
+    let issued = issuer
+        .issue(Grant {
+            client_id: EXAMPLE_CLIENT_ID.to_string(),
+            owner_id: EXAMPLE_OWNER_ID.to_string(),
+            redirect_uri: EXAMPLE_REDIRECT_URI.parse().unwrap(),
+            scope: EXAMPLE_SCOPE.parse().unwrap(),
+            until: Utc::now() + Duration::hours(1),
+            extensions: Extensions::new(),
+        })
+        .unwrap();
+
+    // A self-contained token can not be revoked, but that is no failure of the server.
+    let mut flow = revocation_flow(&setup.registrar, &mut issuer);
+    let response = flow
+        .execute(setup.basic(&[("token", &issued.token)]))
+        .expect("Expected non-error response");
+    assert_eq!(response.status, Status::Ok);
+}
@@ -0,0 +1,188 @@
+use crate::primitives::authorizer::AuthMap;
+use crate::primitives::pending::{PendingMap, PendingStore};
+use crate::primitives::registrar::{Client, ClientMap, RegisteredUrl};
+
+use crate::endpoint::{Solicitation, WebRequest};
+use crate::frontends::simple::endpoint::authorization_flow;
+use crate::frontends::simple::steps::{MultiStep, Progress, ResumedRequest, StepResult};
+
+use super::{Body, CraftedRequest, CraftedResponse, Status, TestGenerator, ToSingleValueQuery};
+use super::defaults::*;
+
+type Resumed = ResumedRequest<CraftedRequest>;
+
+/// Shows a page named after the step until its field is posted with the expected value.
+fn page(
+    name: &'static str, expected: &'static str,
+) -> impl FnMut(&mut Resumed, &Solicitation, &mut Progress) -> StepResult<CraftedResponse> {
+    move |request: &mut Resumed, _: &Solicitation, progress: &mut Progress| {
+        let submitted = match request.urlbody() {
+            Ok(body) => body.unique_value(name).map(|value| value.into_owned()),
+            Err(_) => None,
+        };
+
+        match submitted.as_deref() {
+            Some(value) if value == expected => {
+                progress.pending.state.insert(name.to_owned(), value.to_owned());
+                StepResult::Complete
+            }
+            Some(_) => StepResult::Denied,
+            None => StepResult::Page(CraftedResponse {
+                body: Some(Body::Text(format!("{} {}", name, progress.handle))),
+                ..CraftedResponse::default()
+            }),
+        }
+    }
+}
+
+struct StepSetup {
+    registrar: ClientMap,
+    authorizer: AuthMap<TestGenerator>,
+    solicitor: MultiStep<Resumed, PendingMap>,
+}
+
+impl StepSetup {
+    fn new() -> Self {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(
+            EXAMPLE_CLIENT_ID,
+            RegisteredUrl::Semantic(EXAMPLE_REDIRECT_URI.parse().unwrap()),
+            EXAMPLE_SCOPE.parse().unwrap(),
+        ));
+
+        let mut solicitor = MultiStep::new(PendingMap::new());
+        solicitor.push_step(
+            |request: &mut Resumed, _: &Solicitation, progress: &mut Progress| {
+                let owner = request
+                    .authheader()
+                    .ok()
+                    .flatten()
+                    .map(|owner| owner.into_owned());
+                match owner {
+                    Some(owner) => {
+                        progress.pending.owner = Some(owner);
+                        StepResult::Complete
+                    }
+                    None => StepResult::Page(CraftedResponse {
+                        body: Some(Body::Text(format!("login {}", progress.handle))),
+                        ..CraftedResponse::default()
+                    }),
+                }
+            },
+        );
+        solicitor.push_step(page("otp", "123456"));
+        solicitor.push_step(page("consent", "allow"));
+
+        StepSetup {
+            registrar,
+            authorizer: AuthMap::new(TestGenerator("AuthToken".to_owned())),
+            solicitor,
+        }
+    }
+
+    fn execute(&mut self, request: CraftedRequest) -> CraftedResponse {
+        let request = ResumedRequest::new(request, self.solicitor.store());
+        authorization_flow(&self.registrar, &mut self.authorizer, &mut self.solicitor)
+            .execute(request)
+            .expect("Should not error")
+    }
+
+    /// Continue with the handle, posting a field and authenticated as the owner.
+    fn resume(
+        &mut self, handle: &str, field: Option<(&str, &str)>, owner: Option<&str>,
+    ) -> CraftedResponse {
+        self.execute(CraftedRequest {
+            query: Some([("pending", handle)].iter().to_single_value_query()),
+            urlbody: field.map(|field| [field].iter().to_single_value_query()),
+            auth: owner.map(str::to_owned),
+        })
+    }
+}
+
+fn original() -> CraftedRequest {
+    CraftedRequest {
+        query: Some(
+            [
+                ("response_type", "code"),
+                ("client_id", EXAMPLE_CLIENT_ID),
+                ("redirect_uri", EXAMPLE_REDIRECT_URI),
+                ("state", "xyz"),
+            ]
+            .iter()
+            .to_single_value_query(),
+        ),
+        urlbody: None,
+        auth: None,
+    }
+}
+
+/// The name of the shown page and the handle it continues with.
+fn shown(response: CraftedResponse) -> (String, String) {
+    assert_eq!(response.status, Status::Ok);
+    match response.body {
+        Some(Body::Text(text)) => {
+            let (name, handle) = text.split_once(' ').unwrap();
+            (name.to_owned(), handle.to_owned())
+        }
+        other => panic!("Expected a page: {:?}", other),
+    }
+}
+
+#[test]
+fn steps_resume() {
+    let mut setup = StepSetup::new();
+
+    let (name, handle) = shown(setup.execute(original()));
+    assert_eq!(name, "login");
+
+    let (name, next) = shown(setup.resume(&handle, None, Some(EXAMPLE_OWNER_ID)));
+    assert_eq!((name.as_str(), next.as_str()), ("otp", handle.as_str()));
+    let (name, _) = shown(setup.resume(&handle, Some(("otp", "123456")), None));
+    assert_eq!(name, "consent");
+    let pending = setup.solicitor.store().get(&handle).unwrap().unwrap();
+    assert_eq!(pending.step, 2);
+    assert_eq!(pending.owner.as_deref(), Some(EXAMPLE_OWNER_ID));
+
+    let response = setup.resume(&handle, Some(("consent", "allow")), None);
+    assert_eq!(response.status, Status::Redirect);
+    let location = response.location.unwrap();
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "code" && value == "AuthToken"));
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "state" && value == "xyz"));
+
+    // The authorization has been decided, its handle no longer restores the request.
+    assert!(setup.solicitor.store().get(&handle).unwrap().is_none());
+    let request = ResumedRequest::new(
+        CraftedRequest {
+            query: Some([("pending", handle.as_str())].iter().to_single_value_query()),
+            urlbody: None,
+            auth: None,
+        },
+        setup.solicitor.store(),
+    );
+    assert!(
+        authorization_flow(&setup.registrar, &mut setup.authorizer, &mut setup.solicitor)
+            .execute(request)
+            .is_err()
+    );
+}
+
+#[test]
+fn steps_denied() {
+    let mut setup = StepSetup::new();
+
+    let mut request = original();
+    request.auth = Some(EXAMPLE_OWNER_ID.to_owned());
+    let (name, handle) = shown(setup.execute(request));
+    assert_eq!(name, "otp");
+
+    let response = setup.resume(&handle, Some(("otp", "000000")), None);
+    let location = response.location.unwrap();
+    assert!(location
+        .query_pairs()
+        .any(|(key, value)| key == "error" && value == "access_denied"));
+    assert!(setup.solicitor.store().get(&handle).unwrap().is_none());
+}
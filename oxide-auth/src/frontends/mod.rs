@@ -173,6 +173,8 @@
 //! [`code_grant::endpoint::{AuthorizationFlow, GrantFlow, AccessFlow}`]: ../code_grant/endpoint/index.html
 //!
 
+#[cfg(feature = "qr")]
+pub mod qr;
 pub mod simple;
 
 /// Simply a prelude useful for writing front-ends.
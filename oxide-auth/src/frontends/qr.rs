@@ -0,0 +1,560 @@
+//! QR codes of the complete verification uri of device authorizations.
+//!
+//! Devices with a screen can show the `verification_uri_complete` of a device authorization as a
+//! QR code, so users only scan it with their phone instead of typing the uri and the user code.
+//! The encoder is intentionally small: it supports the byte mode with error correction level M,
+//! enough for any uri a verification page would use, and renders the code as an SVG image.
+//!
+//! ```
+//! # use oxide_auth::code_grant::device::DeviceAuthorizationResponse;
+//! use oxide_auth::frontends::qr::QrCode;
+//!
+//! # let response = DeviceAuthorizationResponse {
+//! #     verification_uri_complete: Some("https://example.com/device?user_code=WDJB-MJHT".into()),
+//! #     ..DeviceAuthorizationResponse::default()
+//! # };
+//! let qr = QrCode::verification_uri_complete(&response).unwrap();
+//! let svg = qr.to_svg();
+//! assert!(svg.starts_with("<svg"));
+//! ```
+use crate::code_grant::device::DeviceAuthorizationResponse;
+
+/// Format bits of error correction level M.
+const ECC_FORMAT_BITS: usize = 0;
+
+/// Error correction codewords per block of level M, by version.
+const ECC_CODEWORDS_PER_BLOCK: [usize; 41] = [
+    0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+];
+
+/// Error correction blocks of level M, by version.
+const ECC_BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26,
+    28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+];
+
+/// A QR code, a square of dark and light modules.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QrCode {
+    size: usize,
+    modules: Vec<bool>,
+}
+
+/// Builds the modules of a code, remembering which ones belong to function patterns.
+struct Canvas {
+    size: usize,
+    modules: Vec<bool>,
+    function: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encode the data in the smallest version that fits.
+    ///
+    /// Fails if the data exceeds the capacity of the largest version, 2331 bytes.
+    pub fn encode(data: &[u8]) -> Result<Self, ()> {
+        let version = (1..=40)
+            .find(|&version| data_bits(data, version) <= 8 * data_codewords(version))
+            .ok_or(())?;
+        let codewords = add_error_correction(&data_codewords_of(data, version), version);
+
+        let mut canvas = Canvas::new(version);
+        canvas.draw_codewords(&codewords);
+        let best = (0..8)
+            .min_by_key(|&mask| {
+                canvas.apply_mask(mask);
+                canvas.draw_format_bits(mask);
+                let penalty = canvas.penalty();
+                canvas.apply_mask(mask);
+                penalty
+            })
+            .unwrap();
+        canvas.apply_mask(best);
+        canvas.draw_format_bits(best);
+
+        Ok(QrCode {
+            size: canvas.size,
+            modules: canvas.modules,
+        })
+    }
+
+    /// The code of the complete verification uri of a device authorization, if it has one.
+    pub fn verification_uri_complete(response: &DeviceAuthorizationResponse) -> Option<Self> {
+        let uri = response.verification_uri_complete.as_ref()?;
+        QrCode::encode(uri.as_bytes()).ok()
+    }
+
+    /// The number of modules on each side, without a border.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the module in column `x` and row `y` is dark.
+    ///
+    /// Modules outside of the code are light.
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[y * self.size + x]
+    }
+
+    /// Render the code as an SVG image, with the required border of four modules.
+    ///
+    /// Each module is one unit of the view box, the image scales to any size it is displayed at.
+    pub fn to_svg(&self) -> String {
+        let border = 4;
+        let mut path = String::new();
+        for y in 0..self.size {
+            for x in 0..self.size {
+                if self.is_dark(x, y) {
+                    path.push_str(&format!("M{},{}h1v1h-1z", x + border, y + border));
+                }
+            }
+        }
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {0} {0}" "#,
+                r#"shape-rendering="crispEdges"><rect width="{0}" height="{0}" fill="white"/>"#,
+                r#"<path d="{1}" fill="black"/></svg>"#
+            ),
+            self.size + 2 * border,
+            path
+        )
+    }
+}
+
+impl Canvas {
+    /// An empty code of the version with all function patterns drawn.
+    fn new(version: usize) -> Self {
+        let size = 4 * version + 17;
+        let mut canvas = Canvas {
+            size,
+            modules: vec![false; size * size],
+            function: vec![false; size * size],
+        };
+
+        for i in 0..size {
+            canvas.set_function(6, i, i % 2 == 0);
+            canvas.set_function(i, 6, i % 2 == 0);
+        }
+        canvas.draw_finder(3, 3);
+        canvas.draw_finder(size - 4, 3);
+        canvas.draw_finder(3, size - 4);
+
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // Alignment patterns would overlap the finder patterns in three corners.
+                if (i, j) != (0, 0) && (i, j) != (0, last) && (i, j) != (last, 0) {
+                    canvas.draw_alignment(x, y);
+                }
+            }
+        }
+
+        // Reserve the format area, drawn for real once the mask is chosen.
+        canvas.draw_format_bits(0);
+        if version >= 7 {
+            canvas.draw_version(version);
+        }
+        canvas
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    fn draw_finder(&mut self, x: usize, y: usize) {
+        for dy in -4isize..=4 {
+            for dx in -4isize..=4 {
+                let (fx, fy) = (x as isize + dx, y as isize + dy);
+                if fx < 0 || fy < 0 || fx >= self.size as isize || fy >= self.size as isize {
+                    continue;
+                }
+                let distance = dx.abs().max(dy.abs());
+                self.set_function(fx as usize, fy as usize, distance != 2 && distance != 4);
+            }
+        }
+    }
+
+    fn draw_alignment(&mut self, x: usize, y: usize) {
+        for dy in -2isize..=2 {
+            for dx in -2isize..=2 {
+                let dark = dx.abs().max(dy.abs()) != 1;
+                self.set_function((x as isize + dx) as usize, (y as isize + dy) as usize, dark);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: usize) {
+        let data = ECC_FORMAT_BITS << 3 | mask;
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (data << 10 | remainder) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+        let size = self.size;
+
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    fn draw_version(&mut self, version: usize) {
+        let mut remainder = version;
+        for _ in 0..12 {
+            remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+        }
+        let bits = version << 12 | remainder;
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /// Place the codewords in the zigzag of two module wide columns, from the bottom right.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size as isize;
+        let mut index = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            // The vertical timing pattern is skipped entirely.
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..size {
+                for column in 0..2 {
+                    let x = (right - column) as usize;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vertical } else { vertical } as usize;
+                    if !self.function[y * self.size + x] && index < codewords.len() * 8 {
+                        self.modules[y * self.size + x] =
+                            (codewords[index >> 3] >> (7 - (index & 7))) & 1 != 0;
+                        index += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    /// Toggle the data modules of the mask, applying it twice restores the modules.
+    fn apply_mask(&mut self, mask: usize) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                if invert && !self.function[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    /// The penalty of the current modules, lower values are easier to scan.
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let at = |x: usize, y: usize| self.modules[y * size + x];
+        let mut penalty = 0;
+
+        // Rows and columns are scored alike, by reading columns as rows of the transposed code.
+        for transposed in [false, true] {
+            let module = |a: usize, b: usize| if transposed { at(b, a) } else { at(a, b) };
+            for b in 0..size {
+                let line: Vec<bool> = (0..size).map(|a| module(a, b)).collect();
+                let mut run = 1;
+                for a in 1..=size {
+                    if a < size && line[a] == line[a - 1] {
+                        run += 1;
+                        continue;
+                    }
+                    if run >= 5 {
+                        penalty += run - 2;
+                    }
+                    run = 1;
+                }
+
+                // Patterns resembling a finder, with four light modules on one side.
+                const FINDER: [bool; 7] = [true, false, true, true, true, false, true];
+                for a in 0..size.saturating_sub(6) {
+                    if line[a..a + 7] != FINDER {
+                        continue;
+                    }
+                    let light = |from: isize| {
+                        (from..from + 4).all(|i| i < 0 || i >= size as isize || !line[i as usize])
+                    };
+                    if light(a as isize - 4) || light(a as isize + 7) {
+                        penalty += 40;
+                    }
+                }
+            }
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let dark = at(x, y);
+                if dark == at(x + 1, y) && dark == at(x, y + 1) && dark == at(x + 1, y + 1) {
+                    penalty += 3;
+                }
+            }
+        }
+
+        let dark = self.modules.iter().filter(|&&dark| dark).count();
+        let total = size * size;
+        // The deviation of the dark ratio from one half, in steps of five percent.
+        let deviation = (dark * 20).max(total * 10) - (dark * 20).min(total * 10);
+        penalty + deviation / total * 10
+    }
+}
+
+/// The centers of alignment patterns in each dimension.
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let step = (version * 8 + count * 3 + 5) / (count * 4 - 4) * 2;
+    let size = 4 * version + 17;
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// The number of modules available for codewords, after all function patterns.
+fn raw_data_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignment = version / 7 + 2;
+        modules -= (25 * alignment - 10) * alignment - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8 - ECC_CODEWORDS_PER_BLOCK[version] * ECC_BLOCKS[version]
+}
+
+fn count_bits(version: usize) -> usize {
+    if version < 10 {
+        8
+    } else {
+        16
+    }
+}
+
+fn data_bits(data: &[u8], version: usize) -> usize {
+    if data.len() >= 1 << count_bits(version) {
+        return usize::MAX;
+    }
+    4 + count_bits(version) + 8 * data.len()
+}
+
+/// The data codewords of a byte mode segment, with terminator and padding.
+fn data_codewords_of(data: &[u8], version: usize) -> Vec<u8> {
+    let mut bits = Vec::new();
+    let mut push = |value: usize, count: usize| {
+        for i in (0..count).rev() {
+            bits.push((value >> i) & 1 != 0);
+        }
+    };
+    push(0b0100, 4);
+    push(data.len(), count_bits(version));
+    for &byte in data {
+        push(usize::from(byte), 8);
+    }
+
+    let capacity = 8 * data_codewords(version);
+    let terminated = bits.len() + (capacity - bits.len()).min(4);
+    bits.resize(terminated.div_ceil(8) * 8, false);
+
+    let mut codewords: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+        .collect();
+    for &pad in [0xEC, 0x11].iter().cycle() {
+        if codewords.len() == data_codewords(version) {
+            break;
+        }
+        codewords.push(pad);
+    }
+    codewords
+}
+
+/// Split the data into blocks, append their error correction and interleave the result.
+fn add_error_correction(data: &[u8], version: usize) -> Vec<u8> {
+    let blocks = ECC_BLOCKS[version];
+    let ecc = ECC_CODEWORDS_PER_BLOCK[version];
+    let raw = raw_data_modules(version) / 8;
+    let short_blocks = blocks - raw % blocks;
+    let short_length = raw / blocks;
+    let divisor = reed_solomon_divisor(ecc);
+
+    let mut encoded = Vec::with_capacity(blocks);
+    let mut rest = data;
+    for i in 0..blocks {
+        let length = short_length - ecc + usize::from(i >= short_blocks);
+        let (block, remaining) = rest.split_at(length);
+        rest = remaining;
+        let mut block = block.to_vec();
+        let remainder = reed_solomon_remainder(&block, &divisor);
+        // Short blocks are padded to align the interleaving, the padding is skipped below.
+        if i < short_blocks {
+            block.push(0);
+        }
+        block.extend(remainder);
+        encoded.push(block);
+    }
+
+    let mut result = Vec::with_capacity(raw);
+    for i in 0..=short_length {
+        for (j, block) in encoded.iter().enumerate() {
+            if i != short_length - ecc || j >= short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = reed_solomon_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = reed_solomon_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (value, &coefficient) in result.iter_mut().zip(divisor) {
+            *value ^= reed_solomon_multiply(coefficient, factor);
+        }
+    }
+    result
+}
+
+/// Multiplication in GF(2^8) modulo the polynomial of QR codes, `x^8 + x^4 + x^3 + x^2 + 1`.
+fn reed_solomon_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1D);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_correction() {
+        // The data codewords of `HELLO WORLD` at version 1-M, with their known error correction.
+        let data = [
+            32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        ];
+        let ecc = reed_solomon_remainder(&data, &reed_solomon_divisor(10));
+        assert_eq!(ecc, [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+        assert_eq!(add_error_correction(&data, 1)[16..], ecc[..]);
+    }
+
+    #[test]
+    fn capacity() {
+        assert_eq!(data_codewords(1), 16);
+        assert_eq!(data_codewords(10), 216);
+        assert_eq!(data_codewords(40), 2334);
+        assert_eq!(alignment_positions(7), [6, 22, 38]);
+        assert_eq!(alignment_positions(32), [6, 34, 60, 86, 112, 138]);
+
+        assert_eq!(QrCode::encode(&[b'a'; 14]).unwrap().size(), 21);
+        assert_eq!(QrCode::encode(&[b'a'; 15]).unwrap().size(), 25);
+        assert_eq!(QrCode::encode(&[b'a'; 2331]).unwrap().size(), 177);
+        assert!(QrCode::encode(&[b'a'; 2332]).is_err());
+    }
+
+    #[test]
+    fn format_and_finders() {
+        let qr = QrCode::encode(b"https://example.com/device?user_code=WDJB-MJHT").unwrap();
+        let size = qr.size();
+        for &(x, y) in &[(0, 0), (size - 7, 0), (0, size - 7)] {
+            assert!((0..7).all(|i| qr.is_dark(x + i, y) && qr.is_dark(x, y + i)));
+            assert!(!qr.is_dark(x + 1, y + 1));
+            assert!(qr.is_dark(x + 3, y + 3));
+        }
+        assert!(qr.is_dark(8, size - 8));
+
+        // Both copies of the format information agree and decode to level M.
+        let first: usize = (0..15).fold(0, |bits, i| {
+            let (x, y) = match i {
+                0..=5 => (8, i),
+                6 => (8, 7),
+                7 => (8, 8),
+                8 => (7, 8),
+                _ => (14 - i, 8),
+            };
+            bits | usize::from(qr.is_dark(x, y)) << i
+        });
+        let second: usize = (0..15).fold(0, |bits, i| {
+            let (x, y) = if i < 8 {
+                (size - 1 - i, 8)
+            } else {
+                (8, size - 15 + i)
+            };
+            bits | usize::from(qr.is_dark(x, y)) << i
+        });
+        assert_eq!(first, second);
+        assert_eq!((first ^ 0x5412) >> 13, ECC_FORMAT_BITS);
+
+        let svg = qr.to_svg();
+        assert!(svg.contains(&format!("viewBox=\"0 0 {0} {0}\"", size + 8)));
+    }
+
+    #[test]
+    fn device_response() {
+        let mut response = DeviceAuthorizationResponse::default();
+        assert!(QrCode::verification_uri_complete(&response).is_none());
+        response.verification_uri_complete =
+            Some("https://example.com/device?user_code=WDJB-MJHT".into());
+        assert!(QrCode::verification_uri_complete(&response).is_some());
+    }
+}
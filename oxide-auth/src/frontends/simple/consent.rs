@@ -0,0 +1,289 @@
+//! A solicitor rendering a consent page from a template.
+//!
+//! Most servers ask the owner for consent with a simple html form listing the client and the
+//! requested scopes. The [`ConsentPage`] renders such a form from a customizable template and
+//! parses the decision that the owner posts back to the authorization endpoint. Only identifying
+//! the owner, usually from a session cookie, is left to the server:
+//!
+//! ```
+//! use oxide_auth::frontends::simple::consent::{ClientDisplay, ConsentPage};
+//! use oxide_auth::frontends::simple::request::Request;
+//!
+//! let mut consent = ConsentPage::new(|request: &mut Request| {
+//!     // Look up the owner of the session.
+//! #   let _ = request;
+//!     Some("alice".to_owned())
+//! });
+//! consent.describe_scope("read", "Read your documents");
+//! consent.describe_client("LocalClient", ClientDisplay::new("Document Viewer"));
+//! ```
+//!
+//! The form is posted to the url of the page itself, repeating the query of the original request,
+//! so that the authorization flow sees the same request again. Its body only carries the decision
+//! of the owner in the `consent` parameter, either `allow` or `deny`.
+//!
+//! [`ConsentPage`]: struct.ConsentPage.html
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use url::{form_urlencoded, Url};
+
+use crate::endpoint::{OwnerConsent, OwnerSolicitor, Solicitation, WebRequest, WebResponse};
+use crate::primitives::registrar::PreGrant;
+
+/// The template used when none was configured.
+///
+/// It demonstrates all placeholders of the template language, see [`ConsentPage::template`].
+///
+/// [`ConsentPage::template`]: struct.ConsentPage.html#method.template
+pub const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Authorize {client}</title></head>
+<body>
+<p><a href="{client_uri}">{client}</a> is requesting access to your account.</p>
+<p>It will be allowed to:</p>
+<ul>
+{scopes}</ul>
+<p>Afterwards you will be returned to {redirect_uri}.</p>
+<form method="post" action="{action}">
+    <button type="submit" name="consent" value="allow">Allow</button>
+    <button type="submit" name="consent" value="deny">Deny</button>
+</form>
+</body>
+</html>
+"#;
+
+/// Asks the owner for consent with an html form.
+///
+/// The owner is identified by the function given on construction, which returns `None` when no
+/// owner is logged in. Such requests are denied, the server should route them through its login
+/// page before the authorization endpoint. Requests posting a decision are answered directly while
+/// all others receive the consent page.
+///
+/// The form itself is not protected against cross-site request forgery. Servers should either
+/// verify the `Origin` of posted decisions or add a token to the template and check it in the
+/// function identifying the owner.
+pub struct ConsentPage<F> {
+    owner: F,
+    template: Cow<'static, str>,
+    scopes: HashMap<String, String>,
+    clients: HashMap<String, ClientDisplay>,
+}
+
+/// How a client is presented to the owner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientDisplay {
+    /// The human readable name of the client.
+    pub name: String,
+
+    /// The homepage of the client.
+    pub uri: Option<Url>,
+
+    /// An image identifying the client.
+    pub logo_uri: Option<Url>,
+}
+
+impl<F> ConsentPage<F> {
+    /// Ask for consent on behalf of the owner identified by the function.
+    pub fn new(owner: F) -> Self {
+        ConsentPage {
+            owner,
+            template: Cow::Borrowed(DEFAULT_TEMPLATE),
+            scopes: HashMap::new(),
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Replace the template of the page.
+    ///
+    /// Placeholders in braces are substituted with html escaped values:
+    ///
+    /// * `{client}` the name of the client, or its id when it has not been described,
+    /// * `{client_id}` the id of the client,
+    /// * `{client_uri}` and `{logo_uri}` the urls of the client description, or empty,
+    /// * `{redirect_uri}` where the owner will be redirected afterwards,
+    /// * `{scope}` the requested scope as a space separated list,
+    /// * `{scopes}` one `<li>` element for each requested scope, with its description,
+    /// * `{state}` the state of the client, or empty,
+    /// * `{action}` the target of the form, which must be posted with a `consent` parameter.
+    ///
+    /// Use `{{` and `}}` for literal braces. Unknown placeholders are kept as they are.
+    pub fn template<T: Into<Cow<'static, str>>>(&mut self, template: T) {
+        self.template = template.into();
+    }
+
+    /// Describe the meaning of a scope token to the owner.
+    ///
+    /// Scopes without description are listed with their token.
+    pub fn describe_scope(&mut self, scope: &str, description: &str) {
+        self.scopes.insert(scope.to_owned(), description.to_owned());
+    }
+
+    /// Describe the client with the id to the owner.
+    pub fn describe_client(&mut self, client_id: &str, display: ClientDisplay) {
+        self.clients.insert(client_id.to_owned(), display);
+    }
+
+    /// Render the page for a solicitation.
+    ///
+    /// The `query` is the query of the authorization request, which the form repeats.
+    pub fn render(&self, solicitation: &Solicitation, query: &str) -> String {
+        let grant = solicitation.pre_grant();
+        let client = self.clients.get(&grant.client_id);
+
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest: &str = &self.template;
+        while let Some(start) = rest.find(['{', '}']) {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                rendered.push_str(&rest[..1]);
+                rest = &rest[2..];
+                continue;
+            }
+
+            let end = match rest.find('}') {
+                Some(end) if rest.starts_with('{') => end,
+                _ => {
+                    rendered.push_str(&rest[..1]);
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+
+            match self.placeholder(&rest[1..end], grant, client, solicitation.state(), query) {
+                Some(value) => rendered.push_str(&value),
+                None => rendered.push_str(&rest[..=end]),
+            }
+            rest = &rest[end + 1..];
+        }
+
+        rendered.push_str(rest);
+        rendered
+    }
+
+    fn placeholder(
+        &self, name: &str, grant: &PreGrant, client: Option<&ClientDisplay>, state: Option<&str>,
+        query: &str,
+    ) -> Option<String> {
+        let url = |url: Option<&Url>| escape(url.map(Url::as_str).unwrap_or(""));
+        let value = match name {
+            "client" => escape(
+                client
+                    .map(|client| client.name.as_str())
+                    .unwrap_or(&grant.client_id),
+            ),
+            "client_id" => escape(&grant.client_id),
+            "client_uri" => url(client.and_then(|client| client.uri.as_ref())),
+            "logo_uri" => url(client.and_then(|client| client.logo_uri.as_ref())),
+            "redirect_uri" => escape(grant.redirect_uri.as_str()),
+            "scope" => escape(&grant.scope.to_string()),
+            "scopes" => {
+                let mut tokens: Vec<_> = grant.scope.iter().collect();
+                tokens.sort_unstable();
+                tokens
+                    .into_iter()
+                    .map(|token| {
+                        let description = self.scopes.get(token).map(String::as_str);
+                        format!("<li>{}</li>\n", escape(description.unwrap_or(token)))
+                    })
+                    .collect()
+            }
+            "state" => escape(state.unwrap_or("")),
+            "action" => escape(&format!("?{}", query)),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+impl ClientDisplay {
+    /// A client shown with its name only.
+    pub fn new(name: &str) -> Self {
+        ClientDisplay {
+            name: name.to_owned(),
+            uri: None,
+            logo_uri: None,
+        }
+    }
+}
+
+impl<W, F> OwnerSolicitor<W> for ConsentPage<F>
+where
+    W: WebRequest,
+    W::Response: Default,
+    F: FnMut(&mut W) -> Option<String>,
+{
+    fn check_consent(
+        &mut self, request: &mut W, solicitation: Solicitation,
+    ) -> OwnerConsent<W::Response> {
+        let owner = match (self.owner)(request) {
+            Some(owner) => owner,
+            None => return OwnerConsent::Denied,
+        };
+
+        let decision = request
+            .urlbody()
+            .ok()
+            .and_then(|body| body.unique_value("consent").map(Cow::into_owned));
+        match decision.as_deref() {
+            Some("allow") => return OwnerConsent::Authorized(owner),
+            Some("deny") => return OwnerConsent::Denied,
+            _ => (),
+        }
+
+        let query = match request.query() {
+            Ok(query) => encode(query.normalize().iter()),
+            // Repeat at least the parameters of the validated request.
+            Err(_) => {
+                let grant = solicitation.pre_grant();
+                let scope = grant.scope.to_string();
+                let parameters = [
+                    ("response_type", Some("code")),
+                    ("client_id", Some(grant.client_id.as_str())),
+                    ("redirect_uri", Some(grant.redirect_uri.as_str())),
+                    ("scope", Some(scope.as_str())),
+                    ("state", solicitation.state()),
+                ];
+                encode(parameters.iter().cloned())
+            }
+        };
+
+        let page = self.render(&solicitation, &query);
+        let mut response = W::Response::default();
+        match response.ok().and_then(|()| response.body_html(&page)) {
+            Ok(()) => OwnerConsent::InProgress(response),
+            Err(err) => OwnerConsent::Error(err),
+        }
+    }
+}
+
+/// Urlencode the parameters, skipping those that appeared several times.
+fn encode<'a, I>(parameters: I) -> String
+where
+    I: Iterator<Item = (&'a str, Option<&'a str>)>,
+{
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    for (key, value) in parameters {
+        if let Some(value) = value {
+            serializer.append_pair(key, value);
+        }
+    }
+    serializer.finish()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
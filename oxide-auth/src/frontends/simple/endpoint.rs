@@ -8,17 +8,42 @@
 //! [`Endpoint`]: ../../endpoint/trait.Endpoint.html
 
 use crate::primitives::authorizer::Authorizer;
+use crate::primitives::clock::Clock;
+use crate::primitives::device::DeviceCodeStore;
 use crate::primitives::issuer::Issuer;
+#[cfg(feature = "jwt")]
+use crate::primitives::jwt::{IdTokenSigner, ResponseSigner};
+#[cfg(feature = "jwt")]
+use crate::primitives::keystore::KeyStore;
 use crate::primitives::registrar::Registrar;
+use crate::primitives::request_uri::RequestUriStore;
 use crate::primitives::scope::Scope;
 
 use crate::endpoint::{AccessTokenFlow, AuthorizationFlow, ResourceFlow, RefreshFlow, ClientCredentialsFlow};
-use crate::endpoint::{Endpoint, Extension, OAuthError, PreGrant, Template, Scopes};
+use crate::code_grant::custom_grant::GrantHandler;
+use crate::code_grant::exchange::ExchangePolicy;
+use crate::code_grant::metadata::ServerMetadata;
+#[cfg(feature = "password")]
+use crate::code_grant::password::{CredentialValidator, Throttle};
+use crate::endpoint::{IntrospectionFlow, MetadataFlow, PushedAuthorizationFlow, RevocationFlow};
+use crate::endpoint::{CustomGrantFlow, TokenExchangeFlow};
+#[cfg(feature = "jwt")]
+use crate::endpoint::JwksFlow;
+#[cfg(feature = "password")]
+use crate::endpoint::PasswordFlow;
+use crate::endpoint::{
+    ClaimsEnricher, Endpoint, Extension, OAuthError, PreGrant, Template, Scopes, TokenResponseHook,
+};
 use crate::endpoint::{OwnerConsent, OwnerSolicitor, Solicitation};
 use crate::endpoint::WebRequest;
 
+#[cfg(feature = "jwt")]
+use std::borrow::Borrow;
 use std::marker::PhantomData;
 
+use rand::Rng;
+use url::Url;
+
 /// Errors either caused by the underlying web types or the library.
 #[derive(Debug)]
 pub enum Error<W: WebRequest> {
@@ -125,6 +150,206 @@ impl<E, Error> ErrorInto<E, Error> {
     }
 }
 
+/// Adds a store of device authorizations to an endpoint, enabling the device flows.
+///
+/// All other primitives are those of the wrapped endpoint.
+pub struct WithDeviceCodes<E, D> {
+    /// The wrapped endpoint.
+    pub endpoint: E,
+
+    /// The store of pending device authorizations.
+    pub device_codes: D,
+}
+
+impl<E, D> WithDeviceCodes<E, D> {
+    /// Wrap the endpoint, using the store for device authorizations.
+    pub fn new(endpoint: E, device_codes: D) -> Self {
+        WithDeviceCodes {
+            endpoint,
+            device_codes,
+        }
+    }
+}
+
+/// Adds a store of pushed authorization requests to an endpoint.
+///
+/// This enables the pushed authorization flow, and the authorization flow then resolves the
+/// `request_uri` of requests. All other primitives are those of the wrapped endpoint.
+pub struct WithRequestUris<E, S> {
+    /// The wrapped endpoint.
+    pub endpoint: E,
+
+    /// The store of pushed requests.
+    pub request_uris: S,
+}
+
+impl<E, S> WithRequestUris<E, S> {
+    /// Wrap the endpoint, using the store for pushed requests.
+    pub fn new(endpoint: E, request_uris: S) -> Self {
+        WithRequestUris {
+            endpoint,
+            request_uris,
+        }
+    }
+}
+
+/// Adds a signer of authorization responses to an endpoint.
+///
+/// The authorization flow then answers requests for the JWT secured response modes with signed
+/// responses. All other primitives are those of the wrapped endpoint. Only available with the
+/// `jwt` feature.
+#[cfg(feature = "jwt")]
+pub struct WithResponseSigner<E, S> {
+    /// The wrapped endpoint.
+    pub endpoint: E,
+
+    /// The signer of authorization responses.
+    pub signer: S,
+}
+
+#[cfg(feature = "jwt")]
+impl<E, S> WithResponseSigner<E, S> {
+    /// Wrap the endpoint, signing responses with the signer.
+    pub fn new(endpoint: E, signer: S) -> Self {
+        WithResponseSigner { endpoint, signer }
+    }
+}
+
+/// Adds a signer of ID tokens to an endpoint.
+///
+/// The access token flow then issues an ID token alongside the access token of grants with the
+/// `openid` scope. All other primitives are those of the wrapped endpoint. Only available with the
+/// `jwt` feature.
+#[cfg(feature = "jwt")]
+pub struct WithIdTokenSigner<E, S> {
+    /// The wrapped endpoint.
+    pub endpoint: E,
+
+    /// The signer of ID tokens.
+    pub signer: S,
+}
+
+#[cfg(feature = "jwt")]
+impl<E, S> WithIdTokenSigner<E, S> {
+    /// Wrap the endpoint, signing ID tokens with the signer.
+    pub fn new(endpoint: E, signer: S) -> Self {
+        WithIdTokenSigner { endpoint, signer }
+    }
+}
+
+/// Sets the clock deciding the expiry of codes and tokens in the flows of an endpoint.
+///
+/// The primitives of the endpoint keep their own clock, which should usually be the same one.
+/// All other primitives are those of the wrapped endpoint.
+pub struct WithClock<E, C> {
+    /// The wrapped endpoint.
+    pub endpoint: E,
+
+    /// The clock of the flows.
+    pub clock: C,
+}
+
+impl<E, C> WithClock<E, C> {
+    /// Wrap the endpoint, checking expiry against the time of the clock.
+    pub fn new(endpoint: E, clock: C) -> Self {
+        WithClock { endpoint, clock }
+    }
+}
+
+/// Adds members to the successful responses of the token flows of an endpoint.
+///
+/// The hook computes the members from the grant of each issued token. All other primitives are
+/// those of the wrapped endpoint.
+pub struct WithTokenResponseHook<E, H> {
+    /// The wrapped endpoint.
+    pub endpoint: E,
+
+    /// The hook adding members to token responses.
+    pub hook: H,
+}
+
+impl<E, H> WithTokenResponseHook<E, H> {
+    /// Wrap the endpoint, adding the members of the hook to its token responses.
+    pub fn new(endpoint: E, hook: H) -> Self {
+        WithTokenResponseHook { endpoint, hook }
+    }
+}
+
+/// Attaches custom claims to the new grants of an endpoint.
+///
+/// The enricher computes the claims of each grant when it is authorized or issued. All other
+/// primitives are those of the wrapped endpoint.
+pub struct WithClaimsEnricher<E, C> {
+    /// The wrapped endpoint.
+    pub endpoint: E,
+
+    /// The enricher attaching claims to grants.
+    pub enricher: C,
+}
+
+impl<E, C> WithClaimsEnricher<E, C> {
+    /// Wrap the endpoint, attaching the claims of the enricher to its grants.
+    pub fn new(endpoint: E, enricher: C) -> Self {
+        WithClaimsEnricher { endpoint, enricher }
+    }
+}
+
+/// Completes and logs the error responses of an endpoint.
+///
+/// Every error of the authorization and access token flows receives a random `trace_id` that is
+/// also logged together with the error, so that a report of a client can be correlated with the
+/// logs of the server. When an error uri base is configured, errors without an `error_uri` link to
+/// the page named after their error code below that base. All other primitives are those of the
+/// wrapped endpoint.
+pub struct WithErrorReporting<E> {
+    /// The wrapped endpoint.
+    pub endpoint: E,
+
+    /// The base of the pages documenting each error code.
+    pub error_uri: Option<Url>,
+}
+
+impl<E> WithErrorReporting<E> {
+    /// Wrap the endpoint, linking errors to pages below `error_uri` if provided.
+    pub fn new(endpoint: E, error_uri: Option<Url>) -> Self {
+        WithErrorReporting { endpoint, error_uri }
+    }
+
+    fn report(&self, kind: &mut Template) {
+        let trace_id = format!("{:016x}", rand::thread_rng().gen::<u64>());
+        if let Some(error) = kind.authorization_error() {
+            let code = error.kind();
+            if let (None, Some(uri)) = (error.uri(), self.error_page(code.as_ref())) {
+                error.explain_uri(uri);
+            }
+            error.trace(trace_id.clone());
+            log::warn!(
+                "authorization error {} [trace_id={}]: {}",
+                code,
+                trace_id,
+                error.explanation().unwrap_or("")
+            );
+        }
+        if let Some(error) = kind.access_token_error() {
+            let code = error.kind();
+            if let (None, Some(uri)) = (error.uri(), self.error_page(code.as_ref())) {
+                error.explain_uri(uri);
+            }
+            error.trace(trace_id.clone());
+            log::warn!(
+                "access token error {} [trace_id={}]: {}",
+                code,
+                trace_id,
+                error.explanation().unwrap_or("")
+            );
+        }
+    }
+
+    fn error_page(&self, code: &str) -> Option<Url> {
+        self.error_uri.as_ref()?.join(code).ok()
+    }
+}
+
 /// Marker struct if some primitive is not provided.
 ///
 /// Used in place of other primitives when those are not provided. The exact semantics depend on
@@ -161,6 +386,13 @@ pub struct Vacant;
 /// A simple wrapper for functions and lambdas to be used as solicitors.
 pub struct FnSolicitor<F>(pub F);
 
+/// Authorize client credentials requests on behalf of the requesting client itself.
+///
+/// In the client credentials grant the client accesses resources under its own control, there is
+/// no separate resource owner whose consent could be asked. This solicitor approves every such
+/// request and makes the authenticated client the owner of the issued token.
+pub struct ClientOwner;
+
 /// Use a predetermined grant and owner as solicitor.
 ///
 /// Convenience wrapper when the owner and her/his consent to a grant can be identified without
@@ -270,7 +502,23 @@ type ClientCredentials<'a, W> = Generic<
 >;
 type Refresh<'a> =
     Generic<&'a (dyn Registrar + 'a), Vacant, &'a mut (dyn Issuer + 'a), Vacant, Vacant, Vacant>;
+type Introspection<'a> =
+    Generic<&'a (dyn Registrar + 'a), Vacant, &'a mut (dyn Issuer + 'a), Vacant, Vacant, Vacant>;
+type Revocation<'a> =
+    Generic<&'a (dyn Registrar + 'a), Vacant, &'a mut (dyn Issuer + 'a), Vacant, Vacant, Vacant>;
+type Exchange<'a> =
+    Generic<&'a (dyn Registrar + 'a), Vacant, &'a mut (dyn Issuer + 'a), Vacant, Vacant, Vacant>;
+type CustomGrant<'a> =
+    Generic<&'a (dyn Registrar + 'a), Vacant, &'a mut (dyn Issuer + 'a), Vacant, Vacant, Vacant>;
+#[cfg(feature = "password")]
+type Password<'a> =
+    Generic<&'a (dyn Registrar + 'a), Vacant, &'a mut (dyn Issuer + 'a), Vacant, Vacant, Vacant>;
 type Resource<'a> = Generic<Vacant, Vacant, &'a mut (dyn Issuer + 'a), Vacant, &'a [Scope], Vacant>;
+type Metadata = Generic<Vacant, Vacant, Vacant, Vacant, Vacant, Vacant>;
+type Par<'a> = WithRequestUris<
+    Generic<&'a (dyn Registrar + 'a), Vacant, Vacant, Vacant, Vacant, Vacant>,
+    &'a mut (dyn RequestUriStore + 'a),
+>;
 
 /// Create an ad-hoc authorization flow.
 ///
@@ -338,6 +586,11 @@ where
 /// Since all necessary primitives are expected in the function syntax, this is guaranteed to never
 /// fail or panic, compared to preparing one with `ClientCredentialsFlow`.
 ///
+/// Only confidential clients can authenticate for this grant. The scope of the token is negotiated
+/// by the registrar, so clients without a requested scope receive their registered default scope,
+/// and refresh tokens are discarded unless explicitly allowed on the flow. Pass a `ClientOwner`
+/// as the solicitor to issue tokens owned by the client itself.
+///
 /// But this is not as versatile and extensible, so it should be used with care.  The fact that it
 /// only takes references is a conscious choice to maintain forwards portability while encouraging
 /// the transition to custom `Endpoint` implementations instead.
@@ -423,6 +676,260 @@ where
     }
 }
 
+/// Create an ad-hoc introspection flow.
+///
+/// Since all necessary primitives are expected in the function syntax, this is guaranteed to never
+/// fail or panic, compared to preparing one with `IntrospectionFlow`.
+///
+/// But this is not as versatile and extensible, so it should be used with care.  The fact that it
+/// only takes references is a conscious choice to maintain forwards portability while encouraging
+/// the transition to custom `Endpoint` implementations instead.
+pub fn introspection_flow<'a, W>(
+    registrar: &'a dyn Registrar, issuer: &'a mut dyn Issuer,
+) -> IntrospectionFlow<Introspection<'a>, W>
+where
+    W: WebRequest,
+    W::Response: Default,
+{
+    let flow = IntrospectionFlow::prepare(Generic {
+        registrar,
+        authorizer: Vacant,
+        issuer,
+        solicitor: Vacant,
+        scopes: Vacant,
+        response: Vacant,
+    });
+
+    match flow {
+        Err(_) => unreachable!(),
+        Ok(flow) => flow,
+    }
+}
+
+/// Create an ad-hoc flow serving the metadata document.
+///
+/// The document is serialized once, so later changes to `metadata` are not served.
+pub fn metadata_flow<W>(metadata: &ServerMetadata) -> MetadataFlow<Metadata, W>
+where
+    W: WebRequest,
+    W::Response: Default,
+{
+    let flow = MetadataFlow::prepare(
+        Generic {
+            registrar: Vacant,
+            authorizer: Vacant,
+            issuer: Vacant,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        },
+        metadata,
+    );
+
+    match flow {
+        Err(_) => unreachable!(),
+        Ok(flow) => flow,
+    }
+}
+
+/// Create an ad-hoc flow serving the public keys of the store.
+///
+/// Only available with the `jwt` feature.
+#[cfg(feature = "jwt")]
+pub fn jwks_flow<W>(keys: &KeyStore) -> JwksFlow<Metadata, W>
+where
+    W: WebRequest,
+    W::Response: Default,
+{
+    let flow = JwksFlow::prepare(
+        Generic {
+            registrar: Vacant,
+            authorizer: Vacant,
+            issuer: Vacant,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        },
+        keys.clone(),
+    );
+
+    match flow {
+        Err(_) => unreachable!(),
+        Ok(flow) => flow,
+    }
+}
+
+/// Create an ad-hoc revocation flow.
+///
+/// Since all necessary primitives are expected in the function syntax, this is guaranteed to never
+/// fail or panic, compared to preparing one with `RevocationFlow`.
+///
+/// But this is not as versatile and extensible, so it should be used with care.  The fact that it
+/// only takes references is a conscious choice to maintain forwards portability while encouraging
+/// the transition to custom `Endpoint` implementations instead.
+pub fn revocation_flow<'a, W>(
+    registrar: &'a dyn Registrar, issuer: &'a mut dyn Issuer,
+) -> RevocationFlow<Revocation<'a>, W>
+where
+    W: WebRequest,
+    W::Response: Default,
+{
+    let flow = RevocationFlow::prepare(Generic {
+        registrar,
+        authorizer: Vacant,
+        issuer,
+        solicitor: Vacant,
+        scopes: Vacant,
+        response: Vacant,
+    });
+
+    match flow {
+        Err(_) => unreachable!(),
+        Ok(flow) => flow,
+    }
+}
+
+/// Create an ad-hoc token exchange flow.
+///
+/// Since all necessary primitives are expected in the function syntax, this is guaranteed to never
+/// fail or panic, compared to preparing one with `TokenExchangeFlow`.
+///
+/// But this is not as versatile and extensible, so it should be used with care.  The fact that it
+/// only takes references is a conscious choice to maintain forwards portability while encouraging
+/// the transition to custom `Endpoint` implementations instead.
+pub fn token_exchange_flow<'a, W, P>(
+    registrar: &'a dyn Registrar, issuer: &'a mut dyn Issuer, policy: P,
+) -> TokenExchangeFlow<Exchange<'a>, W, P>
+where
+    W: WebRequest,
+    W::Response: Default,
+    P: ExchangePolicy,
+{
+    let flow = TokenExchangeFlow::prepare(
+        Generic {
+            registrar,
+            authorizer: Vacant,
+            issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        },
+        policy,
+    );
+
+    match flow {
+        Err(_) => unreachable!(),
+        Ok(flow) => flow,
+    }
+}
+
+/// Create an ad-hoc flow for requests of custom grant types.
+///
+/// Since all necessary primitives are expected in the function syntax, this is guaranteed to never
+/// fail or panic, compared to preparing one with `CustomGrantFlow`. Pass a `&mut GrantTypes` as the
+/// handler to keep the registry outside of the flow.
+///
+/// But this is not as versatile and extensible, so it should be used with care.  The fact that it
+/// only takes references is a conscious choice to maintain forwards portability while encouraging
+/// the transition to custom `Endpoint` implementations instead.
+pub fn custom_grant_flow<'a, W, G>(
+    registrar: &'a dyn Registrar, issuer: &'a mut dyn Issuer, handler: G,
+) -> CustomGrantFlow<CustomGrant<'a>, W, G>
+where
+    W: WebRequest,
+    W::Response: Default,
+    G: GrantHandler,
+{
+    let flow = CustomGrantFlow::prepare(
+        Generic {
+            registrar,
+            authorizer: Vacant,
+            issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        },
+        handler,
+    );
+
+    match flow {
+        Err(_) => unreachable!(),
+        Ok(flow) => flow,
+    }
+}
+
+/// Create an ad-hoc resource owner password credentials flow.
+///
+/// Since all necessary primitives are expected in the function syntax, this is guaranteed to never
+/// fail or panic, compared to preparing one with `PasswordFlow`.
+///
+/// The flow uses a clone of the `throttle`, which shares the failed attempts with all other flows
+/// created with the same throttle.
+///
+/// But this is not as versatile and extensible, so it should be used with care.  The fact that it
+/// only takes references is a conscious choice to maintain forwards portability while encouraging
+/// the transition to custom `Endpoint` implementations instead.
+#[cfg(feature = "password")]
+pub fn password_flow<'a, W, V>(
+    registrar: &'a dyn Registrar, issuer: &'a mut dyn Issuer, validator: V, throttle: &Throttle,
+) -> PasswordFlow<Password<'a>, W, V>
+where
+    W: WebRequest,
+    W::Response: Default,
+    V: CredentialValidator,
+{
+    let flow = PasswordFlow::prepare(
+        Generic {
+            registrar,
+            authorizer: Vacant,
+            issuer,
+            solicitor: Vacant,
+            scopes: Vacant,
+            response: Vacant,
+        },
+        validator,
+    );
+
+    match flow {
+        Err(_) => unreachable!(),
+        Ok(mut flow) => {
+            flow.throttle(throttle.clone());
+            flow
+        }
+    }
+}
+
+/// Create an ad-hoc pushed authorization request flow.
+///
+/// Since all necessary primitives are expected in the function syntax, this is guaranteed to never
+/// fail or panic, compared to preparing one with `PushedAuthorizationFlow`.
+///
+/// But this is not as versatile and extensible, so it should be used with care.  The fact that it
+/// only takes references is a conscious choice to maintain forwards portability while encouraging
+/// the transition to custom `Endpoint` implementations instead.
+pub fn par_flow<'a, W>(
+    registrar: &'a dyn Registrar, request_uris: &'a mut dyn RequestUriStore,
+) -> PushedAuthorizationFlow<Par<'a>, W>
+where
+    W: WebRequest,
+    W::Response: Default,
+{
+    let generic = Generic {
+        registrar,
+        authorizer: Vacant,
+        issuer: Vacant,
+        solicitor: Vacant,
+        scopes: Vacant,
+        response: Vacant,
+    };
+    let flow = PushedAuthorizationFlow::prepare(WithRequestUris::new(generic, request_uris));
+
+    match flow {
+        Err(_) => unreachable!(),
+        Ok(flow) => flow,
+    }
+}
+
 impl<R, A, I, O, C, L> Generic<R, A, I, O, C, L> {
     /// Change the used solicitor.
     pub fn with_solicitor<N>(self, new_solicitor: N) -> Generic<R, A, I, N, C, L> {
@@ -481,6 +988,22 @@ impl<R, A, I, O, C, L> Generic<R, A, I, O, C, L> {
         }
     }
 
+    /// Create a client credentials flow.
+    ///
+    /// Opposed to `ClientCredentialsFlow::prepare` this statically ensures that the construction
+    /// succeeds.
+    pub fn client_credentials_flow<W: WebRequest>(self) -> ClientCredentialsFlow<Self, W>
+    where
+        Self: Endpoint<W>,
+        R: Registrar,
+        I: Issuer,
+    {
+        match ClientCredentialsFlow::prepare(self) {
+            Ok(flow) => flow,
+            Err(_) => unreachable!(),
+        }
+    }
+
     /// Create a token refresh flow.
     ///
     /// Opposed to `RefreshFlow::prepare` this statically ensures that the construction succeeds.
@@ -496,38 +1019,88 @@ impl<R, A, I, O, C, L> Generic<R, A, I, O, C, L> {
         }
     }
 
-    /// Create a resource access flow.
+    /// Create a token introspection flow.
     ///
-    /// Opposed to `ResourceFlow::prepare` this statically ensures that the construction succeeds.
-    pub fn resource_flow<W: WebRequest>(self) -> ResourceFlow<Self, W>
+    /// Opposed to `IntrospectionFlow::prepare` this statically ensures that the construction
+    /// succeeds.
+    pub fn introspection_flow<W: WebRequest>(self) -> IntrospectionFlow<Self, W>
     where
         Self: Endpoint<W>,
+        R: Registrar,
         I: Issuer,
     {
-        match ResourceFlow::prepare(self) {
+        match IntrospectionFlow::prepare(self) {
             Ok(flow) => flow,
             Err(_) => unreachable!(),
         }
     }
 
-    /// Check, statically, that this is an endpoint for some request.
+    /// Create a token revocation flow.
     ///
-    /// This is mainly a utility method intended for compilation and integration tests.
-    pub fn assert<W: WebRequest>(self) -> Self
+    /// Opposed to `RevocationFlow::prepare` this statically ensures that the construction
+    /// succeeds.
+    pub fn revocation_flow<W: WebRequest>(self) -> RevocationFlow<Self, W>
     where
         Self: Endpoint<W>,
+        R: Registrar,
+        I: Issuer,
     {
-        self
+        match RevocationFlow::prepare(self) {
+            Ok(flow) => flow,
+            Err(_) => unreachable!(),
+        }
     }
-}
 
-impl<W: WebRequest> Error<W> {
-    /// Convert into a single error type.
+    /// Create a token exchange flow.
     ///
-    /// Note that the additional information whether the error occurred in the web components or
-    /// during the flow needs to be implicitely contained in the types. Otherwise, this information
-    /// is lost and you should use or provide a `From<Error<W>>` implementation instead. This
-    /// method is still useful for frontends providing a standard error type that interacts with
+    /// Opposed to `TokenExchangeFlow::prepare` this statically ensures that the construction
+    /// succeeds.
+    pub fn token_exchange_flow<W: WebRequest, P: ExchangePolicy>(
+        self, policy: P,
+    ) -> TokenExchangeFlow<Self, W, P>
+    where
+        Self: Endpoint<W>,
+        R: Registrar,
+        I: Issuer,
+    {
+        match TokenExchangeFlow::prepare(self, policy) {
+            Ok(flow) => flow,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Create a resource access flow.
+    ///
+    /// Opposed to `ResourceFlow::prepare` this statically ensures that the construction succeeds.
+    pub fn resource_flow<W: WebRequest>(self) -> ResourceFlow<Self, W>
+    where
+        Self: Endpoint<W>,
+        I: Issuer,
+    {
+        match ResourceFlow::prepare(self) {
+            Ok(flow) => flow,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Check, statically, that this is an endpoint for some request.
+    ///
+    /// This is mainly a utility method intended for compilation and integration tests.
+    pub fn assert<W: WebRequest>(self) -> Self
+    where
+        Self: Endpoint<W>,
+    {
+        self
+    }
+}
+
+impl<W: WebRequest> Error<W> {
+    /// Convert into a single error type.
+    ///
+    /// Note that the additional information whether the error occurred in the web components or
+    /// during the flow needs to be implicitely contained in the types. Otherwise, this information
+    /// is lost and you should use or provide a `From<Error<W>>` implementation instead. This
+    /// method is still useful for frontends providing a standard error type that interacts with
     /// their web server library.
     pub fn pack<P>(self) -> P
     where
@@ -584,6 +1157,626 @@ where
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         self.0.extension()
     }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        self.0.device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        self.0.request_uris_mut()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        self.0.response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.0.id_token_signer()
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.0.clock()
+    }
+}
+
+impl<E, D, W> Endpoint<W> for WithDeviceCodes<E, D>
+where
+    E: Endpoint<W>,
+    D: DeviceCodeStore,
+    W: WebRequest,
+{
+    type Error = E::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.endpoint.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.endpoint.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.endpoint.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.endpoint.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.endpoint.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.endpoint.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.endpoint.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.endpoint.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.endpoint.extension()
+    }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        Some(&mut self.device_codes)
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        self.endpoint.request_uris_mut()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        self.endpoint.response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.endpoint.id_token_signer()
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.endpoint.clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.endpoint.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.endpoint.claims_enricher()
+    }
+}
+
+impl<E, S, W> Endpoint<W> for WithRequestUris<E, S>
+where
+    E: Endpoint<W>,
+    S: RequestUriStore,
+    W: WebRequest,
+{
+    type Error = E::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.endpoint.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.endpoint.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.endpoint.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.endpoint.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.endpoint.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.endpoint.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.endpoint.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.endpoint.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.endpoint.extension()
+    }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        self.endpoint.device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        Some(&mut self.request_uris)
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        self.endpoint.response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.endpoint.id_token_signer()
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.endpoint.clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.endpoint.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.endpoint.claims_enricher()
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl<E, S, W> Endpoint<W> for WithResponseSigner<E, S>
+where
+    E: Endpoint<W>,
+    S: Borrow<ResponseSigner>,
+    W: WebRequest,
+{
+    type Error = E::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.endpoint.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.endpoint.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.endpoint.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.endpoint.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.endpoint.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.endpoint.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.endpoint.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.endpoint.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.endpoint.extension()
+    }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        self.endpoint.device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        self.endpoint.request_uris_mut()
+    }
+
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        Some(self.signer.borrow())
+    }
+
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.endpoint.id_token_signer()
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.endpoint.clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.endpoint.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.endpoint.claims_enricher()
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl<E, S, W> Endpoint<W> for WithIdTokenSigner<E, S>
+where
+    E: Endpoint<W>,
+    S: Borrow<IdTokenSigner>,
+    W: WebRequest,
+{
+    type Error = E::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.endpoint.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.endpoint.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.endpoint.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.endpoint.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.endpoint.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.endpoint.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.endpoint.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.endpoint.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.endpoint.extension()
+    }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        self.endpoint.device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        self.endpoint.request_uris_mut()
+    }
+
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        self.endpoint.response_signer()
+    }
+
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        Some(self.signer.borrow())
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.endpoint.clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.endpoint.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.endpoint.claims_enricher()
+    }
+}
+
+impl<E, W> Endpoint<W> for WithErrorReporting<E>
+where
+    E: Endpoint<W>,
+    W: WebRequest,
+{
+    type Error = E::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.endpoint.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.endpoint.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.endpoint.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.endpoint.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.endpoint.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, mut kind: Template) -> Result<W::Response, Self::Error> {
+        self.report(&mut kind);
+        self.endpoint.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.endpoint.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.endpoint.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.endpoint.extension()
+    }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        self.endpoint.device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        self.endpoint.request_uris_mut()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        self.endpoint.response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.endpoint.id_token_signer()
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.endpoint.clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.endpoint.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.endpoint.claims_enricher()
+    }
+}
+
+impl<E, C, W> Endpoint<W> for WithClock<E, C>
+where
+    E: Endpoint<W>,
+    C: Clock,
+    W: WebRequest,
+{
+    type Error = E::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.endpoint.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.endpoint.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.endpoint.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.endpoint.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.endpoint.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.endpoint.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.endpoint.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.endpoint.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.endpoint.extension()
+    }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        self.endpoint.device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        self.endpoint.request_uris_mut()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        self.endpoint.response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.endpoint.id_token_signer()
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        Some(&self.clock)
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.endpoint.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.endpoint.claims_enricher()
+    }
+}
+
+impl<E, H, W> Endpoint<W> for WithTokenResponseHook<E, H>
+where
+    E: Endpoint<W>,
+    H: TokenResponseHook,
+    W: WebRequest,
+{
+    type Error = E::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.endpoint.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.endpoint.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.endpoint.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.endpoint.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.endpoint.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.endpoint.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.endpoint.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.endpoint.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.endpoint.extension()
+    }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        self.endpoint.device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        self.endpoint.request_uris_mut()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        self.endpoint.response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.endpoint.id_token_signer()
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.endpoint.clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        Some(&self.hook)
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.endpoint.claims_enricher()
+    }
+}
+
+impl<E, C, W> Endpoint<W> for WithClaimsEnricher<E, C>
+where
+    E: Endpoint<W>,
+    C: ClaimsEnricher,
+    W: WebRequest,
+{
+    type Error = E::Error;
+
+    fn registrar(&self) -> Option<&dyn Registrar> {
+        self.endpoint.registrar()
+    }
+
+    fn authorizer_mut(&mut self) -> Option<&mut dyn Authorizer> {
+        self.endpoint.authorizer_mut()
+    }
+
+    fn issuer_mut(&mut self) -> Option<&mut dyn Issuer> {
+        self.endpoint.issuer_mut()
+    }
+
+    fn owner_solicitor(&mut self) -> Option<&mut dyn OwnerSolicitor<W>> {
+        self.endpoint.owner_solicitor()
+    }
+
+    fn scopes(&mut self) -> Option<&mut dyn Scopes<W>> {
+        self.endpoint.scopes()
+    }
+
+    fn response(&mut self, request: &mut W, kind: Template) -> Result<W::Response, Self::Error> {
+        self.endpoint.response(request, kind)
+    }
+
+    fn error(&mut self, err: OAuthError) -> Self::Error {
+        self.endpoint.error(err)
+    }
+
+    fn web_error(&mut self, err: W::Error) -> Self::Error {
+        self.endpoint.web_error(err)
+    }
+
+    fn extension(&mut self) -> Option<&mut dyn Extension> {
+        self.endpoint.extension()
+    }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        self.endpoint.device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        self.endpoint.request_uris_mut()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        self.endpoint.response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.endpoint.id_token_signer()
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.endpoint.clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.endpoint.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        Some(&self.enricher)
+    }
 }
 
 impl<W, R, A, I, O, C, L> Endpoint<W> for Generic<R, A, I, O, C, L>
@@ -692,6 +1885,12 @@ where
     }
 }
 
+impl<W: WebRequest> OwnerSolicitor<W> for ClientOwner {
+    fn check_consent(&mut self, _: &mut W, solicitation: Solicitation) -> OwnerConsent<W::Response> {
+        OwnerConsent::Authorized(solicitation.pre_grant().client_id.clone())
+    }
+}
+
 impl<W: WebRequest> OwnerSolicitor<W> for ApprovedGrant {
     /// Approve if the grant matches *exactly*.
     ///
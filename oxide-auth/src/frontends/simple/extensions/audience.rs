@@ -0,0 +1,29 @@
+use super::{AccessTokenAddon, AccessTokenRequest, ResourceAddon, ResourceRequest};
+use super::{AddonResult, Grant, Value};
+
+pub use crate::code_grant::extensions::{AudienceRestriction, ResourceIndicators};
+
+impl AccessTokenAddon for ResourceIndicators {
+    fn execute(&self, request: &dyn AccessTokenRequest, _: Option<Value>) -> AddonResult {
+        let client_id = match request.authorization() {
+            Some((client_id, _)) => Some(client_id),
+            None => request.client_id(),
+        };
+
+        let resource = request.extension("resource");
+        match self.audience(client_id.as_deref(), resource.as_deref()) {
+            Err(()) => AddonResult::Err,
+            Ok(None) => AddonResult::Ok,
+            Ok(Some(audience)) => AddonResult::Data(Value::public(Some(audience))),
+        }
+    }
+}
+
+impl ResourceAddon for AudienceRestriction {
+    fn execute(&self, _: &dyn ResourceRequest, grant: &Grant) -> AddonResult {
+        match self.protect(grant) {
+            Ok(()) => AddonResult::Ok,
+            Err(()) => AddonResult::Err,
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use super::{AccessTokenAddon, AccessTokenRequest, ResourceAddon, ResourceRequest};
+use super::{AddonResult, Grant, Value};
+
+pub use crate::code_grant::extensions::Dpop;
+
+impl AccessTokenAddon for Dpop {
+    fn execute(&self, request: &dyn AccessTokenRequest, _: Option<Value>) -> AddonResult {
+        match self.verify(request.dpop()) {
+            Err(()) => AddonResult::Err,
+            Ok(None) => AddonResult::Ok,
+            Ok(Some(thumbprint)) => AddonResult::Data(Value::public(Some(thumbprint))),
+        }
+    }
+}
+
+impl ResourceAddon for Dpop {
+    fn execute(&self, request: &dyn ResourceRequest, grant: &Grant) -> AddonResult {
+        let authorization = match request.token() {
+            Some(authorization) => authorization,
+            None => return AddonResult::Err,
+        };
+
+        match self.protect(&authorization, request.dpop(), grant) {
+            Ok(()) => AddonResult::Ok,
+            Err(()) => AddonResult::Err,
+        }
+    }
+}
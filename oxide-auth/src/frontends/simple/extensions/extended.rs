@@ -1,7 +1,15 @@
-use crate::endpoint::{Endpoint, Extension, OAuthError, OwnerSolicitor, Scopes, Template, WebRequest};
+use crate::endpoint::{
+    ClaimsEnricher, Endpoint, Extension, OAuthError, OwnerSolicitor, Scopes, Template,
+    TokenResponseHook, WebRequest,
+};
 use crate::primitives::authorizer::Authorizer;
+use crate::primitives::clock::Clock;
+use crate::primitives::device::DeviceCodeStore;
 use crate::primitives::issuer::Issuer;
+#[cfg(feature = "jwt")]
+use crate::primitives::jwt::{IdTokenSigner, ResponseSigner};
 use crate::primitives::registrar::Registrar;
+use crate::primitives::request_uri::RequestUriStore;
 
 use super::AddonList;
 
@@ -90,4 +98,34 @@ where
     fn extension(&mut self) -> Option<&mut dyn Extension> {
         Some(&mut self.addons)
     }
+
+    fn device_codes_mut(&mut self) -> Option<&mut dyn DeviceCodeStore> {
+        self.inner.device_codes_mut()
+    }
+
+    fn request_uris_mut(&mut self) -> Option<&mut dyn RequestUriStore> {
+        self.inner.request_uris_mut()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn response_signer(&self) -> Option<&ResponseSigner> {
+        self.inner.response_signer()
+    }
+
+    #[cfg(feature = "jwt")]
+    fn id_token_signer(&self) -> Option<&IdTokenSigner> {
+        self.inner.id_token_signer()
+    }
+
+    fn clock(&self) -> Option<&dyn Clock> {
+        self.inner.clock()
+    }
+
+    fn token_response_hook(&self) -> Option<&dyn TokenResponseHook> {
+        self.inner.token_response_hook()
+    }
+
+    fn claims_enricher(&self) -> Option<&dyn ClaimsEnricher> {
+        self.inner.claims_enricher()
+    }
 }
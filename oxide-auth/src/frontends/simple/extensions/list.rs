@@ -1,11 +1,12 @@
 use std::fmt;
 use std::sync::Arc;
 
-use super::{AuthorizationAddon, AccessTokenAddon, AddonResult};
+use super::{AuthorizationAddon, AccessTokenAddon, AddonResult, ResourceAddon};
 use crate::code_grant::accesstoken::{Extension as AccessTokenExtension, Request};
 use crate::code_grant::authorization::{Extension as AuthorizationExtension, Request as AuthRequest};
+use crate::code_grant::resource::{Extension as ResourceExtension, Request as ResourceRequest};
 use crate::endpoint::Extension;
-use crate::primitives::grant::{Extensions, GrantExtension};
+use crate::primitives::grant::{Extensions, Grant, GrantExtension};
 
 /// A simple list of loosly related authorization and access addons.
 ///
@@ -14,6 +15,7 @@ use crate::primitives::grant::{Extensions, GrantExtension};
 pub struct AddonList {
     authorization: Vec<Arc<dyn AuthorizationAddon + Send + Sync + 'static>>,
     access_token: Vec<Arc<dyn AccessTokenAddon + Send + Sync + 'static>>,
+    resource: Vec<Arc<dyn ResourceAddon + Send + Sync + 'static>>,
 }
 
 impl AddonList {
@@ -22,6 +24,7 @@ impl AddonList {
         AddonList {
             authorization: vec![],
             access_token: vec![],
+            resource: vec![],
         }
     }
 
@@ -41,6 +44,14 @@ impl AddonList {
         self.access_token.push(Arc::new(addon))
     }
 
+    /// Add an addon that only applies to resource access.
+    pub fn push_resource<A>(&mut self, addon: A)
+    where
+        A: ResourceAddon + Send + Sync + 'static,
+    {
+        self.resource.push(Arc::new(addon))
+    }
+
     /// Add an addon that applies to the whole code grant flow.
     ///
     /// The addon gets added both the authorization and access token addons.
@@ -68,6 +79,10 @@ impl Extension for AddonList {
     fn access_token(&mut self) -> Option<&mut dyn AccessTokenExtension> {
         Some(self)
     }
+
+    fn resource(&mut self) -> Option<&mut dyn ResourceExtension> {
+        Some(self)
+    }
 }
 
 impl AccessTokenExtension for AddonList {
@@ -109,6 +124,18 @@ impl AuthorizationExtension for AddonList {
     }
 }
 
+impl ResourceExtension for AddonList {
+    fn check(&mut self, request: &dyn ResourceRequest, grant: &Grant) -> Result<(), ()> {
+        for ext in self.resource.iter() {
+            if let AddonResult::Err = ext.execute(request, grant) {
+                return Err(());
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl fmt::Debug for AddonList {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use std::slice::Iter;
@@ -123,6 +150,7 @@ impl fmt::Debug for AddonList {
         f.debug_struct("AddonList")
             .field("authorization", &ExtIter(self.authorization.iter()))
             .field("access_token", &ExtIter(self.access_token.iter()))
+            .field("resource", &ExtIter(self.resource.iter()))
             .finish()
     }
 }
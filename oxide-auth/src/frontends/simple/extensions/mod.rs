@@ -3,19 +3,32 @@
 //! Note that extensions will probably return in `v0.4` but not its preview versions.
 pub use crate::code_grant::authorization::Request as AuthorizationRequest;
 pub use crate::code_grant::accesstoken::Request as AccessTokenRequest;
+pub use crate::code_grant::resource::Request as ResourceRequest;
 
+mod audience;
+#[cfg(feature = "jwt")]
+mod dpop;
 mod extended;
+mod mtls;
+mod oidc;
 mod pkce;
+mod rar;
 mod list;
 
 use std::borrow::{Cow, ToOwned};
 use std::rc::Rc;
 use std::sync::Arc;
 
+pub use self::audience::{AudienceRestriction, ResourceIndicators};
+#[cfg(feature = "jwt")]
+pub use self::dpop::Dpop;
 pub use self::extended::Extended;
+pub use self::mtls::CertificateBinding;
+pub use self::oidc::OpenIdConnect;
 pub use self::pkce::Pkce;
+pub use self::rar::RichAuthorization;
 pub use self::list::AddonList;
-use crate::primitives::grant::{GrantExtension, Value};
+use crate::primitives::grant::{Grant, GrantExtension, Value};
 
 /// Result of extension processing.
 #[must_use = "This type is similar to std::result::Result and should not be ignored."]
@@ -55,6 +68,14 @@ pub trait AccessTokenAddon: GrantExtension {
     fn execute(&self, request: &dyn AccessTokenRequest, code_data: Option<Value>) -> AddonResult;
 }
 
+/// An extension reacting to the access of a resource with a valid token.
+pub trait ResourceAddon: GrantExtension {
+    /// Check the access with the grant of the token, using the data stored by this extension.
+    ///
+    /// Any data returned is ignored, only an error denies the access.
+    fn execute(&self, request: &dyn ResourceRequest, grant: &Grant) -> AddonResult;
+}
+
 impl<'a, T: AuthorizationAddon + ?Sized> AuthorizationAddon for &'a T {
     fn execute(&self, request: &dyn AuthorizationRequest) -> AddonResult {
         (**self).execute(request)
@@ -120,3 +141,36 @@ impl<T: AccessTokenAddon + ?Sized> AccessTokenAddon for Rc<T> {
         (**self).execute(request, data)
     }
 }
+
+impl<'a, T: ResourceAddon + ?Sized> ResourceAddon for &'a T {
+    fn execute(&self, request: &dyn ResourceRequest, grant: &Grant) -> AddonResult {
+        (**self).execute(request, grant)
+    }
+}
+
+impl<'a, T: ResourceAddon + ?Sized> ResourceAddon for Cow<'a, T>
+where
+    T: Clone + ToOwned,
+{
+    fn execute(&self, request: &dyn ResourceRequest, grant: &Grant) -> AddonResult {
+        self.as_ref().execute(request, grant)
+    }
+}
+
+impl<T: ResourceAddon + ?Sized> ResourceAddon for Box<T> {
+    fn execute(&self, request: &dyn ResourceRequest, grant: &Grant) -> AddonResult {
+        (**self).execute(request, grant)
+    }
+}
+
+impl<T: ResourceAddon + ?Sized> ResourceAddon for Arc<T> {
+    fn execute(&self, request: &dyn ResourceRequest, grant: &Grant) -> AddonResult {
+        (**self).execute(request, grant)
+    }
+}
+
+impl<T: ResourceAddon + ?Sized> ResourceAddon for Rc<T> {
+    fn execute(&self, request: &dyn ResourceRequest, grant: &Grant) -> AddonResult {
+        (**self).execute(request, grant)
+    }
+}
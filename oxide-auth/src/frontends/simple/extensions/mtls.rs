@@ -0,0 +1,23 @@
+use super::{AccessTokenAddon, AccessTokenRequest, ResourceAddon, ResourceRequest};
+use super::{AddonResult, Grant, Value};
+
+pub use crate::code_grant::extensions::CertificateBinding;
+
+impl AccessTokenAddon for CertificateBinding {
+    fn execute(&self, request: &dyn AccessTokenRequest, _: Option<Value>) -> AddonResult {
+        match self.bind(request.client_certificate()) {
+            Err(()) => AddonResult::Err,
+            Ok(None) => AddonResult::Ok,
+            Ok(Some(thumbprint)) => AddonResult::Data(Value::public(Some(thumbprint))),
+        }
+    }
+}
+
+impl ResourceAddon for CertificateBinding {
+    fn execute(&self, request: &dyn ResourceRequest, grant: &Grant) -> AddonResult {
+        match self.protect(request.client_certificate(), grant) {
+            Ok(()) => AddonResult::Ok,
+            Err(()) => AddonResult::Err,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use super::{AuthorizationAddon, AuthorizationRequest, AccessTokenAddon, AccessTokenRequest};
+use super::{AddonResult, Value};
+
+pub use crate::code_grant::extensions::OpenIdConnect;
+
+impl AuthorizationAddon for OpenIdConnect {
+    fn execute(&self, request: &dyn AuthorizationRequest) -> AddonResult {
+        let scope = request.scope();
+        let nonce = request.extension("nonce");
+        let acr_values = request.extension("acr_values");
+        let max_age = request.extension("max_age");
+        let authentication = self.authenticate(
+            scope.as_deref(),
+            nonce.as_deref(),
+            acr_values.as_deref(),
+            max_age.as_deref(),
+        );
+        match authentication {
+            None => AddonResult::Ok,
+            Some(authentication) => {
+                AddonResult::Data(Value::public(Some(Self::encode(&authentication))))
+            }
+        }
+    }
+}
+
+impl AccessTokenAddon for OpenIdConnect {
+    fn execute(&self, _: &dyn AccessTokenRequest, data: Option<Value>) -> AddonResult {
+        // The authentication is kept for the ID token of the grant.
+        match data {
+            None => AddonResult::Ok,
+            Some(data) => AddonResult::Data(data),
+        }
+    }
+}
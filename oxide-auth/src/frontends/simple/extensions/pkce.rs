@@ -8,7 +8,12 @@ impl AuthorizationAddon for Pkce {
         let method = request.extension("code_challenge_method");
         let challenge = request.extension("code_challenge");
 
-        let encoded = match self.challenge(method, challenge) {
+        let encoded = match request.client_id() {
+            Some(client_id) => self.client_challenge(&client_id, method, challenge),
+            None => self.challenge(method, challenge),
+        };
+
+        let encoded = match encoded {
             Err(()) => return AddonResult::Err,
             Ok(None) => return AddonResult::Ok,
             Ok(Some(encoded)) => encoded,
@@ -22,7 +27,16 @@ impl AccessTokenAddon for Pkce {
     fn execute(&self, request: &dyn AccessTokenRequest, data: Option<Value>) -> AddonResult {
         let verifier = request.extension("code_verifier");
 
-        match self.verify(data, verifier) {
+        // Confidential clients may only identify themselves through their authorization.
+        let client_id = request
+            .client_id()
+            .or_else(|| request.authorization().map(|(client_id, _)| client_id));
+        let verified = match client_id {
+            Some(client_id) => self.client_verify(&client_id, data, verifier),
+            None => self.verify(data, verifier),
+        };
+
+        match verified {
             Ok(_) => AddonResult::Ok,
             Err(_) => AddonResult::Err,
         }
@@ -0,0 +1,35 @@
+use super::{AuthorizationAddon, AuthorizationRequest, AccessTokenAddon, AccessTokenRequest};
+use super::{AddonResult, Value};
+
+pub use crate::code_grant::extensions::RichAuthorization;
+
+impl AuthorizationAddon for RichAuthorization {
+    fn execute(&self, request: &dyn AuthorizationRequest) -> AddonResult {
+        let details = request.extension("authorization_details");
+        match self.parse(details.as_deref()) {
+            Err(()) => AddonResult::Err,
+            Ok(None) => AddonResult::Ok,
+            Ok(Some(details)) => AddonResult::Data(Value::public(Some(Self::encode(&details)))),
+        }
+    }
+}
+
+impl AccessTokenAddon for RichAuthorization {
+    fn execute(&self, request: &dyn AccessTokenRequest, data: Option<Value>) -> AddonResult {
+        let granted = match data.map(Value::into_public_value) {
+            None | Some(Ok(None)) => None,
+            Some(Ok(Some(granted))) => match serde_json::from_str(&granted) {
+                Ok(granted) => Some(granted),
+                Err(_) => return AddonResult::Err,
+            },
+            Some(Err(())) => return AddonResult::Err,
+        };
+
+        let requested = request.extension("authorization_details");
+        match self.narrow(granted, requested.as_deref()) {
+            Err(()) => AddonResult::Err,
+            Ok(None) => AddonResult::Ok,
+            Ok(Some(details)) => AddonResult::Data(Value::public(Some(Self::encode(&details)))),
+        }
+    }
+}
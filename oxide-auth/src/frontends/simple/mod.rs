@@ -7,8 +7,12 @@
 //!
 //! [`Endpoint`]: ../../endpoint/trait.Endpoint.html
 //! [`WebRequest`]: ../../endpoint/trait.Endpoint.html
+pub mod consent;
+
 pub mod endpoint;
 
 pub mod extensions;
 
 pub mod request;
+
+pub mod steps;
@@ -0,0 +1,274 @@
+//! A solicitor taking the owner through several pages.
+//!
+//! The [`MultiStep`] solicitor runs a sequence of steps, for example a login, a second factor and a
+//! consent page. Each step either completes or shows a page to the owner, after which the request
+//! is suspended in a [`PendingStore`]. The pages send the owner back to the authorization endpoint
+//! with only the `pending` handle in the query. Wrapping such requests in a [`ResumedRequest`]
+//! restores the parameters of the original request, and the solicitor continues with the step that
+//! showed the page:
+//!
+//! ```
+//! use oxide_auth::endpoint::{QueryParameter, Solicitation, WebRequest, WebResponse};
+//! use oxide_auth::frontends::simple::request::{Request, Response};
+//! use oxide_auth::frontends::simple::steps::{MultiStep, Progress, ResumedRequest, StepResult};
+//! use oxide_auth::primitives::pending::PendingMap;
+//!
+//! let mut solicitor = MultiStep::new(PendingMap::new());
+//! solicitor.push_step(|request: &mut ResumedRequest<Request>, _: &Solicitation, progress: &mut Progress| {
+//!     let body = request.urlbody().unwrap();
+//!     match body.unique_value("username") {
+//!         // Verify the password, then continue with the next step.
+//!         Some(username) => {
+//!             progress.pending.owner = Some(username.into_owned());
+//!             StepResult::Complete
+//!         }
+//!         None => {
+//!             let mut page = Response::default();
+//!             let form = format!("<form method=post action='?pending={}'>..</form>", progress.handle);
+//!             page.body_html(&form).unwrap();
+//!             StepResult::Page(page)
+//!         }
+//!     }
+//! });
+//!
+//! // For every request to the authorization endpoint:
+//! # let request = Request::default();
+//! let request = ResumedRequest::new(request, solicitor.store());
+//! ```
+//!
+//! [`MultiStep`]: struct.MultiStep.html
+//! [`PendingStore`]: ../../../primitives/pending/trait.PendingStore.html
+//! [`ResumedRequest`]: struct.ResumedRequest.html
+use std::borrow::Cow;
+
+use crate::code_grant::error::AuthorizationErrorType;
+use crate::endpoint::{
+    ClientCertificate, DpopProof, NormalizedParameter, OwnerConsent, OwnerSolicitor, QueryParameter,
+    Solicitation, WebRequest, WebResponse,
+};
+use crate::primitives::pending::{PendingAuthorization, PendingStore, PENDING_PARAMETER};
+
+/// One page through which the owner is taken before an authorization is decided.
+pub trait Step<W: WebRequest> {
+    /// Complete the step with the request, or show its page to the owner.
+    ///
+    /// A step is repeated until it completes, so it should check whether the request submits its
+    /// page, usually by the parameters of the body. Steps authenticating the owner record the owner
+    /// in the progress.
+    fn step(
+        &mut self, request: &mut W, solicitation: &Solicitation, progress: &mut Progress,
+    ) -> StepResult<W::Response>;
+}
+
+/// The outcome of a single step.
+pub enum StepResult<Response: WebResponse> {
+    /// Continue with the next step in the same request.
+    Complete,
+
+    /// Show the page to the owner, which must return with the handle of the progress.
+    Page(Response),
+
+    /// The owner denied the request, or failed the step.
+    Denied,
+
+    /// An error occurred while creating the page.
+    Error(Response::Error),
+}
+
+/// The progress of a pending authorization, as seen by its steps.
+pub struct Progress<'a> {
+    /// The handle to include in the `pending` parameter of requests continuing the authorization.
+    pub handle: &'a str,
+
+    /// The pending authorization, which is stored with any modifications.
+    pub pending: &'a mut PendingAuthorization,
+}
+
+/// Takes the owner through a sequence of steps before approving a request.
+///
+/// Requests without a `pending` handle, or with an unknown one, begin at the first step. The
+/// request is authorized on behalf of the owner recorded by the steps when all steps have
+/// completed, and denied if none was recorded. Failing to store the progress makes the request fail
+/// with a `server_error`.
+///
+/// The handle alone continues the authorization. Steps after the login should therefore bind the
+/// progress to the session of the owner, for example by recording and comparing a session id.
+pub struct MultiStep<W: WebRequest, S> {
+    store: S,
+    steps: Vec<Box<dyn Step<W>>>,
+}
+
+/// A request continuing a pending authorization.
+///
+/// Requests whose `pending` parameter references a known authorization are given the parameters of
+/// the original request as their query, in addition to the handle itself. All other requests are
+/// left unchanged.
+#[derive(Clone, Debug)]
+pub struct ResumedRequest<W> {
+    request: W,
+    query: Option<NormalizedParameter>,
+}
+
+impl<W: WebRequest, S: PendingStore> MultiStep<W, S> {
+    /// A solicitor without steps, storing its progress in the store.
+    pub fn new(store: S) -> Self {
+        MultiStep {
+            store,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Append a step to the sequence.
+    pub fn push_step<T: Step<W> + 'static>(&mut self, step: T) {
+        self.steps.push(Box::new(step));
+    }
+
+    /// The store of pending authorizations, for resuming requests.
+    pub fn store(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    fn resume(
+        &mut self, request: &mut W, client_id: &str,
+    ) -> Result<(String, PendingAuthorization), ()> {
+        let query = request.query().map_err(|_| ())?.normalize();
+
+        if let Some(handle) = query.unique_value(PENDING_PARAMETER) {
+            if let Some(pending) = self.store.get(&handle)? {
+                // Handles are only valid for the request that began them.
+                if pending.parameters.get("client_id").map(String::as_str) == Some(client_id) {
+                    return Ok((handle.into_owned(), pending));
+                }
+            }
+        }
+
+        let parameters = query
+            .iter()
+            .filter(|(key, _)| *key != PENDING_PARAMETER)
+            .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+            .collect();
+        let pending = PendingAuthorization {
+            parameters,
+            ..PendingAuthorization::default()
+        };
+        let handle = self.store.begin(pending.clone())?;
+        Ok((handle, pending))
+    }
+}
+
+impl<W: WebRequest> ResumedRequest<W> {
+    /// Restore the original parameters of a request continuing a pending authorization.
+    pub fn new<S: PendingStore + ?Sized>(mut request: W, store: &mut S) -> Self {
+        let handle = match request.query() {
+            Ok(query) => query.unique_value(PENDING_PARAMETER).map(Cow::into_owned),
+            Err(_) => None,
+        };
+
+        let pending = match handle.as_ref().map(|handle| store.get(handle)) {
+            Some(Ok(Some(pending))) => pending,
+            _ => return ResumedRequest { request, query: None },
+        };
+
+        let mut query: NormalizedParameter = pending.parameters.into_iter().collect();
+        query.insert_or_poison(PENDING_PARAMETER.into(), handle.unwrap().into());
+        ResumedRequest {
+            request,
+            query: Some(query),
+        }
+    }
+
+    /// Recover the wrapped request.
+    pub fn into_inner(self) -> W {
+        self.request
+    }
+}
+
+impl<W, S> OwnerSolicitor<W> for MultiStep<W, S>
+where
+    W: WebRequest,
+    S: PendingStore,
+{
+    fn check_consent(
+        &mut self, request: &mut W, solicitation: Solicitation,
+    ) -> OwnerConsent<W::Response> {
+        let client_id = solicitation.pre_grant().client_id.clone();
+        let (handle, mut pending) = match self.resume(request, &client_id) {
+            Ok(resumed) => resumed,
+            Err(()) => return OwnerConsent::Failed(AuthorizationErrorType::ServerError),
+        };
+
+        while let Some(step) = self.steps.get_mut(pending.step) {
+            let mut progress = Progress {
+                handle: &handle,
+                pending: &mut pending,
+            };
+
+            match step.step(request, &solicitation, &mut progress) {
+                StepResult::Complete => pending.step += 1,
+                StepResult::Page(page) => {
+                    return match self.store.update(&handle, pending) {
+                        Ok(()) => OwnerConsent::InProgress(page),
+                        Err(()) => OwnerConsent::Failed(AuthorizationErrorType::ServerError),
+                    }
+                }
+                StepResult::Denied => {
+                    let _ = self.store.finish(&handle);
+                    return OwnerConsent::Denied;
+                }
+                StepResult::Error(err) => return OwnerConsent::Error(err),
+            }
+        }
+
+        // Each authorization is decided only once.
+        match self.store.finish(&handle) {
+            Ok(Some(_)) => (),
+            Ok(None) => return OwnerConsent::Denied,
+            Err(()) => return OwnerConsent::Failed(AuthorizationErrorType::ServerError),
+        }
+
+        match pending.owner {
+            Some(owner) => OwnerConsent::Authorized(owner),
+            None => OwnerConsent::Denied,
+        }
+    }
+}
+
+impl<W, F> Step<W> for F
+where
+    W: WebRequest,
+    F: FnMut(&mut W, &Solicitation, &mut Progress) -> StepResult<W::Response>,
+{
+    fn step(
+        &mut self, request: &mut W, solicitation: &Solicitation, progress: &mut Progress,
+    ) -> StepResult<W::Response> {
+        self(request, solicitation, progress)
+    }
+}
+
+impl<W: WebRequest> WebRequest for ResumedRequest<W> {
+    type Error = W::Error;
+    type Response = W::Response;
+
+    fn query(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+        match &self.query {
+            Some(query) => Ok(Cow::Borrowed(query as &dyn QueryParameter)),
+            None => self.request.query(),
+        }
+    }
+
+    fn urlbody(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+        self.request.urlbody()
+    }
+
+    fn authheader(&mut self) -> Result<Option<Cow<str>>, Self::Error> {
+        self.request.authheader()
+    }
+
+    fn dpop(&mut self) -> Result<Option<DpopProof>, Self::Error> {
+        self.request.dpop()
+    }
+
+    fn client_certificate(&mut self) -> Result<Option<ClientCertificate>, Self::Error> {
+        self.request.client_certificate()
+    }
+}
@@ -8,20 +8,40 @@
 use std::collections::HashMap;
 use std::sync::{MutexGuard, RwLockWriteGuard};
 
+#[cfg(feature = "sealed")]
+use chrono::{TimeZone, Utc};
+#[cfg(feature = "sealed")]
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+#[cfg(feature = "sealed")]
+use ring::rand::{SecureRandom, SystemRandom};
+#[cfg(feature = "sealed")]
+use serde::{Deserialize, Serialize};
+
+use super::error::PrimitiveError;
 use super::grant::Grant;
 use super::generator::TagGrant;
+#[cfg(feature = "sealed")]
+use super::clock::{self, Clock, SharedClock};
+#[cfg(feature = "sealed")]
+use super::grant::{Extensions, Value};
+#[cfg(feature = "sealed")]
+use super::Time;
 
 /// Authorizers create and manage authorization codes.
 ///
 /// The authorization code can be traded for a bearer token at the token endpoint.
 pub trait Authorizer {
     /// Create a code which allows retrieval of a bearer token at a later time.
-    fn authorize(&mut self, _: Grant) -> Result<String, ()>;
+    ///
+    /// Fails with `PrimitiveError::Unavailable` when storing the code failed temporarily.
+    fn authorize(&mut self, _: Grant) -> Result<String, PrimitiveError>;
 
     /// Retrieve the parameters associated with a token, invalidating the code in the process. In
-    /// particular, a code should not be usable twice (there is no stateless implementation of an
-    /// authorizer for this reason).
-    fn extract(&mut self, token: &str) -> Result<Option<Grant>, ()>;
+    /// particular, a code should not be usable twice (even the `SealedAuthorizer`, which stores
+    /// nothing when issuing codes, remembers redeemed codes for this reason).
+    ///
+    /// Unknown codes are `Ok(None)` rather than an error.
+    fn extract(&mut self, token: &str) -> Result<Option<Grant>, PrimitiveError>;
 }
 
 /// An in-memory hash map.
@@ -51,64 +71,280 @@ impl<I: TagGrant> AuthMap<I> {
     }
 }
 
+/// Encrypts the grant into the authorization code itself.
+///
+/// Issuing a code requires no storage at all, which suits serverless deployments where a write on
+/// every authorization request is expensive. Each code is sealed with AES-256-GCM under the secret
+/// key, so that its content including private extensions stays confidential and can not be
+/// altered, and carries a random identifier. Redeeming a code records that identifier in a
+/// `Redeemed` store until the code expires, which rejects a second use of the same code. Expired
+/// codes are never extracted.
+///
+/// All instances of a deployment must share the key and, to reliably reject replayed codes, the
+/// store of redeemed codes. Only available with the `sealed` feature.
+#[cfg(feature = "sealed")]
+pub struct SealedAuthorizer<R: Redeemed = RedeemedCodes> {
+    key: LessSafeKey,
+    rng: SystemRandom,
+    redeemed: R,
+    clock: SharedClock,
+}
+
+/// Remembers the identifiers of redeemed authorization codes.
+///
+/// Only available with the `sealed` feature.
+#[cfg(feature = "sealed")]
+pub trait Redeemed {
+    /// Record the identifier of a code valid until `until`, at the time `now` of the authorizer.
+    ///
+    /// Returns `false` if the code was already redeemed. Records may be forgotten once the code
+    /// has expired at `now`.
+    fn redeem(&mut self, id: &str, until: Time, now: Time) -> Result<bool, PrimitiveError>;
+}
+
+/// An in-memory store of redeemed codes, forgetting them once they expire.
+///
+/// Only available with the `sealed` feature.
+#[cfg(feature = "sealed")]
+#[derive(Clone, Debug, Default)]
+pub struct RedeemedCodes {
+    codes: HashMap<String, Time>,
+}
+
+#[cfg(feature = "sealed")]
+#[derive(Deserialize, Serialize)]
+struct SealedGrant {
+    id: String,
+    owner_id: String,
+    client_id: String,
+    scope: String,
+    redirect_uri: String,
+    until: i64,
+    /// Each extension with whether it is private and its content.
+    extensions: Vec<(String, bool, Option<String>)>,
+}
+
+#[cfg(feature = "sealed")]
+const SEALED_AAD: &[u8] = b"oxide-auth authorization code";
+
+#[cfg(feature = "sealed")]
+impl SealedAuthorizer {
+    /// Seal codes with the 32 byte secret key, remembering redeemed codes in memory.
+    ///
+    /// Generate the key with a utility such as `openssl rand` and store it securely, never derive
+    /// it from a password alone.
+    pub fn new(key: &[u8]) -> Result<Self, ()> {
+        SealedAuthorizer::with_redeemed(key, RedeemedCodes::default())
+    }
+
+    /// Seal codes with a random key, such that codes are only valid for the program execution.
+    pub fn ephemeral() -> Self {
+        let mut key = [0; 32];
+        SystemRandom::new()
+            .fill(&mut key)
+            .expect("Failed to generate random key");
+        SealedAuthorizer::new(&key).expect("A 32 byte key is valid")
+    }
+}
+
+#[cfg(feature = "sealed")]
+impl<R: Redeemed> SealedAuthorizer<R> {
+    /// Seal codes with the 32 byte secret key, remembering redeemed codes in `redeemed`.
+    pub fn with_redeemed(key: &[u8], redeemed: R) -> Result<Self, ()> {
+        let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| ())?;
+        Ok(SealedAuthorizer {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+            redeemed,
+            clock: clock::system(),
+        })
+    }
+
+    /// Check the expiry of codes against the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
+    }
+
+    fn seal(&self, grant: &Grant) -> Result<String, PrimitiveError> {
+        let mut id = [0; 16];
+        self.rng.fill(&mut id).map_err(|_| PrimitiveError::Invariant)?;
+        let public = grant
+            .extensions
+            .public()
+            .map(|(name, content)| (name, false, content));
+        let private = grant
+            .extensions
+            .private()
+            .map(|(name, content)| (name, true, content));
+        let sealed = SealedGrant {
+            id: base64::encode_config(id, base64::URL_SAFE_NO_PAD),
+            owner_id: grant.owner_id.clone(),
+            client_id: grant.client_id.clone(),
+            scope: grant.scope.to_string(),
+            redirect_uri: grant.redirect_uri.to_string(),
+            until: grant.until.timestamp(),
+            extensions: public
+                .chain(private)
+                .map(|(name, private, content)| (name.to_owned(), private, content.map(str::to_owned)))
+                .collect(),
+        };
+
+        let mut nonce = [0; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| PrimitiveError::Invariant)?;
+        let mut data = rmp_serde::to_vec(&sealed).map_err(|_| PrimitiveError::Invariant)?;
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(SEALED_AAD),
+                &mut data,
+            )
+            .map_err(|_| PrimitiveError::Invariant)?;
+
+        let mut code = nonce.to_vec();
+        code.extend(data);
+        Ok(base64::encode_config(code, base64::URL_SAFE_NO_PAD))
+    }
+
+    fn open(&self, code: &str) -> Option<(String, Grant)> {
+        let mut data = base64::decode_config(code, base64::URL_SAFE_NO_PAD).ok()?;
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+        let mut sealed = data.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&data).ok()?;
+        let plain = self
+            .key
+            .open_in_place(nonce, Aad::from(SEALED_AAD), &mut sealed)
+            .ok()?;
+        let sealed: SealedGrant = rmp_serde::from_slice(plain).ok()?;
+
+        let mut extensions = Extensions::new();
+        for (name, private, content) in sealed.extensions {
+            let value = if private {
+                Value::private(content)
+            } else {
+                Value::public(content)
+            };
+            extensions.set_raw(name, value);
+        }
+        let grant = Grant {
+            owner_id: sealed.owner_id,
+            client_id: sealed.client_id,
+            scope: sealed.scope.parse().ok()?,
+            redirect_uri: sealed.redirect_uri.parse().ok()?,
+            until: Utc.timestamp_opt(sealed.until, 0).single()?,
+            extensions,
+        };
+        Some((sealed.id, grant))
+    }
+}
+
+#[cfg(feature = "sealed")]
+impl Redeemed for RedeemedCodes {
+    fn redeem(&mut self, id: &str, until: Time, now: Time) -> Result<bool, PrimitiveError> {
+        self.codes.retain(|_, expiry| *expiry > now);
+        if self.codes.contains_key(id) {
+            return Ok(false);
+        }
+        self.codes.insert(id.to_owned(), until);
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "sealed")]
+impl<R: Redeemed + ?Sized> Redeemed for &mut R {
+    fn redeem(&mut self, id: &str, until: Time, now: Time) -> Result<bool, PrimitiveError> {
+        (**self).redeem(id, until, now)
+    }
+}
+
+#[cfg(feature = "sealed")]
+impl<R: Redeemed + ?Sized> Redeemed for Box<R> {
+    fn redeem(&mut self, id: &str, until: Time, now: Time) -> Result<bool, PrimitiveError> {
+        (**self).redeem(id, until, now)
+    }
+}
+
 impl<'a, A: Authorizer + ?Sized> Authorizer for &'a mut A {
-    fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
         (**self).authorize(grant)
     }
 
-    fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).extract(code)
     }
 }
 
 impl<A: Authorizer + ?Sized> Authorizer for Box<A> {
-    fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
         (**self).authorize(grant)
     }
 
-    fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).extract(code)
     }
 }
 
 impl<'a, A: Authorizer + ?Sized> Authorizer for MutexGuard<'a, A> {
-    fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
         (**self).authorize(grant)
     }
 
-    fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).extract(code)
     }
 }
 
 impl<'a, A: Authorizer + ?Sized> Authorizer for RwLockWriteGuard<'a, A> {
-    fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
         (**self).authorize(grant)
     }
 
-    fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).extract(code)
     }
 }
 
 impl<I: TagGrant> Authorizer for AuthMap<I> {
-    fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
         // The (usage, grant) tuple needs to be unique. Since this wraps after 2^64 operations, we
         // expect the validity time of the grant to have changed by then. This works when you don't
         // set your system time forward/backward ~20billion seconds, assuming ~10^9 operations per
         // second.
         let next_usage = self.usage.wrapping_add(1);
-        let token = self.tagger.tag(next_usage - 1, &grant)?;
+        let token = self
+            .tagger
+            .tag(next_usage - 1, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
         self.tokens.insert(token.clone(), grant);
         self.usage = next_usage;
         Ok(token)
     }
 
-    fn extract<'a>(&mut self, grant: &'a str) -> Result<Option<Grant>, ()> {
+    fn extract<'a>(&mut self, grant: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         Ok(self.tokens.remove(grant))
     }
 }
 
+#[cfg(feature = "sealed")]
+impl<R: Redeemed> Authorizer for SealedAuthorizer<R> {
+    fn authorize(&mut self, grant: Grant) -> Result<String, PrimitiveError> {
+        self.seal(&grant)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let (id, grant) = match self.open(code) {
+            Some(sealed) => sealed,
+            None => return Ok(None),
+        };
+        let now = self.clock.now();
+        if grant.until <= now || !self.redeemed.redeem(&id, grant.until, now)? {
+            return Ok(None);
+        }
+        Ok(Some(grant))
+    }
+}
+
 #[cfg(test)]
 /// Tests for authorizer implementations, including those provided here.
 pub mod tests {
@@ -183,4 +419,51 @@ pub mod tests {
         let mut storage = AuthMap::new(BadGenerator);
         simple_test_suite(&mut storage);
     }
+
+    #[test]
+    #[cfg(feature = "sealed")]
+    fn sealed_codes() {
+        use chrono::Duration;
+        use crate::primitives::grant::Value;
+
+        let mut extensions = Extensions::new();
+        extensions.set_raw("secret".to_string(), Value::private(Some("hidden".to_string())));
+        let grant = Grant {
+            owner_id: "Owner".to_string(),
+            client_id: "Client".to_string(),
+            scope: "One two three scopes".parse().unwrap(),
+            redirect_uri: "https://example.com/redirect_me".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions,
+        };
+
+        let mut authorizer = SealedAuthorizer::new(&[7; 32]).unwrap();
+        let code = authorizer.authorize(grant.clone()).unwrap();
+        assert!(!code.contains("hidden"));
+        assert_ne!(code, authorizer.authorize(grant.clone()).unwrap());
+
+        // Another instance with the same key redeems the code, but only once.
+        let mut other = SealedAuthorizer::new(&[7; 32]).unwrap();
+        let extracted = other.extract(&code).unwrap().expect("Sealed code was not opened");
+        assert_eq!(extracted.owner_id, grant.owner_id);
+        assert_eq!(extracted.scope, grant.scope);
+        assert_eq!(extracted.until.timestamp(), grant.until.timestamp());
+        assert_eq!(extracted.extensions, grant.extensions);
+        assert!(other.extract(&code).unwrap().is_none());
+
+        // Codes of another key, altered codes and expired codes are rejected.
+        assert!(SealedAuthorizer::ephemeral().extract(&code).unwrap().is_none());
+        let mut altered = code.clone().into_bytes();
+        altered[20] = if altered[20] == b'A' { b'B' } else { b'A' };
+        let altered = String::from_utf8(altered).unwrap();
+        assert!(authorizer.extract(&altered).unwrap().is_none());
+
+        let expired = authorizer
+            .authorize(Grant {
+                until: Utc::now() - Duration::minutes(1),
+                ..grant
+            })
+            .unwrap();
+        assert!(authorizer.extract(&expired).unwrap().is_none());
+    }
 }
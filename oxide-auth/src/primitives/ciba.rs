@@ -0,0 +1,534 @@
+//! Backchannel requests track authentications initiated by clients.
+//!
+//! In [Client-Initiated Backchannel Authentication], a client such as a call center application
+//! asks the server to authenticate an owner it can only identify by a hint, for example an email
+//! address or a phone number. The server pushes the request to a device of the owner, who approves
+//! or denies it there. Meanwhile the client polls the token endpoint with the `auth_req_id` of the
+//! request or, in the ping mode, waits until the server calls its notification endpoint.
+//!
+//! A [`BackchannelStore`] keeps these pending requests, records the decision of the owner and
+//! enforces the polling interval of clients. The server reaches owners through a [`NotifyOwner`]
+//! and clients in the ping mode through a [`PingClient`].
+//!
+//! [Client-Initiated Backchannel Authentication]: https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html
+//! [`BackchannelStore`]: trait.BackchannelStore.html
+//! [`NotifyOwner`]: trait.NotifyOwner.html
+//! [`PingClient`]: trait.PingClient.html
+use std::collections::HashMap;
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use chrono::Duration;
+use rand::{thread_rng, RngCore};
+use url::Url;
+
+use super::clock::{self, Clock, SharedClock};
+use super::device::DevicePoll;
+use super::grant::{Extensions, Grant};
+use super::registrar::PreGrant;
+use super::Time;
+
+/// How the client learns that the owner has decided.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TokenDelivery {
+    /// The client polls the token endpoint until the owner decided.
+    Poll,
+
+    /// The server notifies the client, which then fetches the token from the token endpoint.
+    Ping {
+        /// The client notification endpoint registered for the client.
+        endpoint: Url,
+
+        /// The bearer token with which the client authenticates notifications.
+        token: String,
+    },
+}
+
+/// A backchannel authentication request of a client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackchannelRequest {
+    /// The negotiated parameters of the request.
+    pub grant: PreGrant,
+
+    /// The owner identified by the hint of the client.
+    pub owner_id: String,
+
+    /// A short message shown on both the consumption and the authentication device, to let the
+    /// owner recognize the request.
+    pub binding_message: Option<String>,
+
+    /// How the client is told about the decision.
+    pub delivery: TokenDelivery,
+}
+
+/// A newly started backchannel authentication.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackchannelAuthorization {
+    /// The identifier with which the client polls for its token.
+    pub auth_req_id: String,
+
+    /// Expiration timestamp of the request (Utc).
+    pub until: Time,
+
+    /// The minimum time the client should wait between polling requests.
+    pub interval: Duration,
+}
+
+/// Stores the pending backchannel authentication requests.
+///
+/// Polling reuses the states of device authorizations, see [`DevicePoll`].
+///
+/// [`DevicePoll`]: ../device/enum.DevicePoll.html
+pub trait BackchannelStore {
+    /// Start an authentication request.
+    fn start(&mut self, request: BackchannelRequest) -> Result<BackchannelAuthorization, ()>;
+
+    /// The pending request with the id.
+    ///
+    /// Returns `None` for unknown or expired requests and for requests that were already decided.
+    fn pending(&mut self, auth_req_id: &str) -> Result<Option<BackchannelRequest>, ()>;
+
+    /// Record the approval of the owner, returning whether the request was pending.
+    fn approve(&mut self, auth_req_id: &str) -> Result<bool, ()>;
+
+    /// Record that the owner denied the request, returning whether it was pending.
+    fn deny(&mut self, auth_req_id: &str) -> Result<bool, ()>;
+
+    /// Poll the request of the client with the id.
+    fn poll(&mut self, client_id: &str, auth_req_id: &str) -> Result<DevicePoll, ()>;
+}
+
+/// Pushes authentication requests to the owner.
+pub trait NotifyOwner {
+    /// Identify the owner from the `login_hint` of the client.
+    ///
+    /// Returns `None` when the hint does not identify a known owner.
+    fn identify(&self, login_hint: &str) -> Result<Option<String>, ()>;
+
+    /// Ask the owner to approve a started request, for example with a push message to their phone.
+    ///
+    /// The decision is recorded with the `approve` or `deny` of the store.
+    fn notify(&self, auth_req_id: &str, request: &BackchannelRequest) -> Result<(), ()>;
+}
+
+/// Calls the client notification endpoint of clients in the ping mode.
+///
+/// Implemented for functions taking the endpoint, the bearer token and the `auth_req_id`. The
+/// notification is a `POST` of the json object `{"auth_req_id": ..}` with the token in an
+/// `Authorization: Bearer` header.
+pub trait PingClient {
+    /// Notify the client that the request with the id was decided.
+    fn ping(&self, endpoint: &Url, token: &str, auth_req_id: &str) -> Result<(), ()>;
+}
+
+/// An in-memory hash map of backchannel requests.
+///
+/// Request ids are 16 random bytes in url-safe base64. Requests are valid for ten minutes and
+/// clients are asked to poll at most every five seconds by default.
+pub struct BackchannelMap {
+    duration: Duration,
+    interval: Duration,
+    requests: HashMap<String, Backchannel>,
+    clock: SharedClock,
+}
+
+struct Backchannel {
+    request: BackchannelRequest,
+    until: Time,
+    interval: Duration,
+    last_poll: Option<Time>,
+    decision: Option<bool>,
+}
+
+/// A store calling the notification endpoint of ping mode clients when the owner decided.
+///
+/// A failed notification is logged but does not undo the decision, the client can still poll.
+pub struct WithPing<S, P> {
+    /// The wrapped store.
+    pub inner: S,
+
+    /// The transport of notifications.
+    pub ping: P,
+}
+
+impl BackchannelMap {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        BackchannelMap {
+            duration: Duration::minutes(10),
+            interval: Duration::seconds(5),
+            requests: HashMap::new(),
+            clock: clock::system(),
+        }
+    }
+
+    /// Set the validity of requests started after this call.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    /// Set the polling interval of requests started after this call.
+    pub fn poll_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Compute the expiry of requests and the polling intervals from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
+    }
+
+    fn decide(&mut self, auth_req_id: &str, decision: bool) -> bool {
+        let now = self.clock.now();
+        match self.requests.get_mut(auth_req_id) {
+            Some(pending) if pending.decision.is_none() && pending.until > now => {
+                pending.decision = Some(decision);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Default for BackchannelMap {
+    fn default() -> Self {
+        BackchannelMap::new()
+    }
+}
+
+impl<S: BackchannelStore, P: PingClient> WithPing<S, P> {
+    fn notify(
+        &mut self, auth_req_id: &str, decide: fn(&mut S, &str) -> Result<bool, ()>,
+    ) -> Result<bool, ()> {
+        let pending = self.inner.pending(auth_req_id)?;
+        if !decide(&mut self.inner, auth_req_id)? {
+            return Ok(false);
+        }
+
+        if let Some(BackchannelRequest {
+            delivery: TokenDelivery::Ping { endpoint, token },
+            ..
+        }) = pending
+        {
+            if self.ping.ping(&endpoint, &token, auth_req_id).is_err() {
+                log::warn!("Failed to notify the client at {}", endpoint);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<N: NotifyOwner + ?Sized> NotifyOwner for &N {
+    fn identify(&self, login_hint: &str) -> Result<Option<String>, ()> {
+        (**self).identify(login_hint)
+    }
+
+    fn notify(&self, auth_req_id: &str, request: &BackchannelRequest) -> Result<(), ()> {
+        (**self).notify(auth_req_id, request)
+    }
+}
+
+impl<F> PingClient for F
+where
+    F: Fn(&Url, &str, &str) -> Result<(), ()>,
+{
+    fn ping(&self, endpoint: &Url, token: &str, auth_req_id: &str) -> Result<(), ()> {
+        self(endpoint, token, auth_req_id)
+    }
+}
+
+impl<S: BackchannelStore + ?Sized> BackchannelStore for &mut S {
+    fn start(&mut self, request: BackchannelRequest) -> Result<BackchannelAuthorization, ()> {
+        (**self).start(request)
+    }
+
+    fn pending(&mut self, auth_req_id: &str) -> Result<Option<BackchannelRequest>, ()> {
+        (**self).pending(auth_req_id)
+    }
+
+    fn approve(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        (**self).approve(auth_req_id)
+    }
+
+    fn deny(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        (**self).deny(auth_req_id)
+    }
+
+    fn poll(&mut self, client_id: &str, auth_req_id: &str) -> Result<DevicePoll, ()> {
+        (**self).poll(client_id, auth_req_id)
+    }
+}
+
+impl<S: BackchannelStore + ?Sized> BackchannelStore for Box<S> {
+    fn start(&mut self, request: BackchannelRequest) -> Result<BackchannelAuthorization, ()> {
+        (**self).start(request)
+    }
+
+    fn pending(&mut self, auth_req_id: &str) -> Result<Option<BackchannelRequest>, ()> {
+        (**self).pending(auth_req_id)
+    }
+
+    fn approve(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        (**self).approve(auth_req_id)
+    }
+
+    fn deny(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        (**self).deny(auth_req_id)
+    }
+
+    fn poll(&mut self, client_id: &str, auth_req_id: &str) -> Result<DevicePoll, ()> {
+        (**self).poll(client_id, auth_req_id)
+    }
+}
+
+impl<'a, S: BackchannelStore + ?Sized> BackchannelStore for MutexGuard<'a, S> {
+    fn start(&mut self, request: BackchannelRequest) -> Result<BackchannelAuthorization, ()> {
+        (**self).start(request)
+    }
+
+    fn pending(&mut self, auth_req_id: &str) -> Result<Option<BackchannelRequest>, ()> {
+        (**self).pending(auth_req_id)
+    }
+
+    fn approve(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        (**self).approve(auth_req_id)
+    }
+
+    fn deny(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        (**self).deny(auth_req_id)
+    }
+
+    fn poll(&mut self, client_id: &str, auth_req_id: &str) -> Result<DevicePoll, ()> {
+        (**self).poll(client_id, auth_req_id)
+    }
+}
+
+impl<'a, S: BackchannelStore + ?Sized> BackchannelStore for RwLockWriteGuard<'a, S> {
+    fn start(&mut self, request: BackchannelRequest) -> Result<BackchannelAuthorization, ()> {
+        (**self).start(request)
+    }
+
+    fn pending(&mut self, auth_req_id: &str) -> Result<Option<BackchannelRequest>, ()> {
+        (**self).pending(auth_req_id)
+    }
+
+    fn approve(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        (**self).approve(auth_req_id)
+    }
+
+    fn deny(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        (**self).deny(auth_req_id)
+    }
+
+    fn poll(&mut self, client_id: &str, auth_req_id: &str) -> Result<DevicePoll, ()> {
+        (**self).poll(client_id, auth_req_id)
+    }
+}
+
+impl<S: BackchannelStore, P: PingClient> BackchannelStore for WithPing<S, P> {
+    fn start(&mut self, request: BackchannelRequest) -> Result<BackchannelAuthorization, ()> {
+        self.inner.start(request)
+    }
+
+    fn pending(&mut self, auth_req_id: &str) -> Result<Option<BackchannelRequest>, ()> {
+        self.inner.pending(auth_req_id)
+    }
+
+    fn approve(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        self.notify(auth_req_id, S::approve)
+    }
+
+    fn deny(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        self.notify(auth_req_id, S::deny)
+    }
+
+    fn poll(&mut self, client_id: &str, auth_req_id: &str) -> Result<DevicePoll, ()> {
+        self.inner.poll(client_id, auth_req_id)
+    }
+}
+
+impl BackchannelStore for BackchannelMap {
+    fn start(&mut self, request: BackchannelRequest) -> Result<BackchannelAuthorization, ()> {
+        let now = self.clock.now();
+        self.requests.retain(|_, pending| pending.until > now);
+
+        let mut random = [0; 16];
+        thread_rng().try_fill_bytes(&mut random).map_err(|_| ())?;
+        let auth_req_id = encode_config(random, URL_SAFE_NO_PAD);
+
+        let until = now + self.duration;
+        self.requests.insert(
+            auth_req_id.clone(),
+            Backchannel {
+                request,
+                until,
+                interval: self.interval,
+                last_poll: None,
+                decision: None,
+            },
+        );
+
+        Ok(BackchannelAuthorization {
+            auth_req_id,
+            until,
+            interval: self.interval,
+        })
+    }
+
+    fn pending(&mut self, auth_req_id: &str) -> Result<Option<BackchannelRequest>, ()> {
+        let now = self.clock.now();
+        let pending = self
+            .requests
+            .get(auth_req_id)
+            .filter(|pending| pending.decision.is_none() && pending.until > now)
+            .map(|pending| pending.request.clone());
+        Ok(pending)
+    }
+
+    fn approve(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        Ok(self.decide(auth_req_id, true))
+    }
+
+    fn deny(&mut self, auth_req_id: &str) -> Result<bool, ()> {
+        Ok(self.decide(auth_req_id, false))
+    }
+
+    fn poll(&mut self, client_id: &str, auth_req_id: &str) -> Result<DevicePoll, ()> {
+        let now = self.clock.now();
+        let pending = match self.requests.get_mut(auth_req_id) {
+            Some(pending) if pending.request.grant.client_id == client_id => pending,
+            _ => return Ok(DevicePoll::Unknown),
+        };
+
+        if pending.until <= now {
+            self.requests.remove(auth_req_id);
+            return Ok(DevicePoll::Expired);
+        }
+
+        match pending.decision {
+            None => {
+                let too_early = pending
+                    .last_poll
+                    .is_some_and(|last_poll| now - last_poll < pending.interval);
+                pending.last_poll = Some(now);
+                if too_early {
+                    pending.interval += Duration::seconds(5);
+                    Ok(DevicePoll::SlowDown)
+                } else {
+                    Ok(DevicePoll::Pending)
+                }
+            }
+            Some(false) => {
+                self.requests.remove(auth_req_id);
+                Ok(DevicePoll::Denied)
+            }
+            Some(true) => {
+                let request = self.requests.remove(auth_req_id).unwrap().request;
+                Ok(DevicePoll::Approved(Grant {
+                    owner_id: request.owner_id,
+                    client_id: request.grant.client_id,
+                    scope: request.grant.scope,
+                    redirect_uri: request.grant.redirect_uri.into_url(),
+                    until: now + Duration::minutes(10),
+                    extensions: Extensions::new(),
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn request(delivery: TokenDelivery) -> BackchannelRequest {
+        BackchannelRequest {
+            grant: PreGrant {
+                client_id: "Client".to_string(),
+                redirect_uri: "https://example.com/redirect_me"
+                    .parse::<url::Url>()
+                    .unwrap()
+                    .into(),
+                scope: "openid".parse().unwrap(),
+            },
+            owner_id: "Owner".to_string(),
+            binding_message: None,
+            delivery,
+        }
+    }
+
+    #[test]
+    fn approve_and_poll() {
+        let mut store = BackchannelMap::new();
+        store.poll_interval(Duration::seconds(1));
+        let started = store.start(request(TokenDelivery::Poll)).unwrap();
+        let id = &started.auth_req_id;
+
+        assert_eq!(store.poll("Other", id), Ok(DevicePoll::Unknown));
+        assert_eq!(store.poll("Client", id), Ok(DevicePoll::Pending));
+        assert_eq!(store.poll("Client", id), Ok(DevicePoll::SlowDown));
+
+        assert_eq!(store.pending(id), Ok(Some(request(TokenDelivery::Poll))));
+        assert_eq!(store.approve(id), Ok(true));
+        assert_eq!(store.deny(id), Ok(false));
+        assert_eq!(store.pending(id), Ok(None));
+
+        match store.poll("Client", id) {
+            Ok(DevicePoll::Approved(grant)) => assert_eq!(grant.owner_id, "Owner"),
+            other => panic!("Expected an approved grant, got {:?}", other),
+        }
+        assert_eq!(store.poll("Client", id), Ok(DevicePoll::Unknown));
+    }
+
+    #[test]
+    fn deny_and_expire() {
+        let mut store = BackchannelMap::new();
+        let denied = store.start(request(TokenDelivery::Poll)).unwrap();
+        assert_eq!(store.deny(&denied.auth_req_id), Ok(true));
+        assert_eq!(store.poll("Client", &denied.auth_req_id), Ok(DevicePoll::Denied));
+
+        store.valid_for(Duration::seconds(-1));
+        let expired = store.start(request(TokenDelivery::Poll)).unwrap();
+        assert_eq!(store.approve(&expired.auth_req_id), Ok(false));
+        assert_eq!(
+            store.poll("Client", &expired.auth_req_id),
+            Ok(DevicePoll::Expired)
+        );
+    }
+
+    #[test]
+    fn pings_client() {
+        let pinged = RefCell::new(Vec::new());
+        let ping = |endpoint: &Url, token: &str, auth_req_id: &str| {
+            pinged
+                .borrow_mut()
+                .push((endpoint.to_string(), token.to_owned(), auth_req_id.to_owned()));
+            Ok(())
+        };
+        let mut store = WithPing {
+            inner: BackchannelMap::new(),
+            ping,
+        };
+
+        let polled = store.start(request(TokenDelivery::Poll)).unwrap();
+        assert_eq!(store.approve(&polled.auth_req_id), Ok(true));
+        assert!(pinged.borrow().is_empty());
+
+        let delivery = TokenDelivery::Ping {
+            endpoint: "https://client.example.com/cb".parse().unwrap(),
+            token: "8d67dc78".to_string(),
+        };
+        let started = store.start(request(delivery)).unwrap();
+        assert_eq!(store.deny(&started.auth_req_id), Ok(true));
+        assert_eq!(store.deny(&started.auth_req_id), Ok(false));
+        assert_eq!(
+            *pinged.borrow(),
+            vec![(
+                "https://client.example.com/cb".to_string(),
+                "8d67dc78".to_string(),
+                started.auth_req_id.clone()
+            )]
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! The current time as seen by primitives and flows.
+//!
+//! Expiry checks and lifetimes are computed from a [`Clock`] instead of the system time directly.
+//! This makes them testable and lets hosts without a reliable system clock, such as some WASM
+//! runtimes, provide the time themselves. The default everywhere is the [`SystemClock`]. A single
+//! clock can be shared between primitives and the endpoint by wrapping it in an `Arc`:
+//!
+//! ```
+//! use std::sync::Arc;
+//! use chrono::{Duration, Utc};
+//! use oxide_auth::primitives::clock::ManualClock;
+//! use oxide_auth::primitives::generator::RandomGenerator;
+//! use oxide_auth::primitives::issuer::TokenMap;
+//! use oxide_auth::frontends::simple::endpoint::{Generic, Vacant, WithClock};
+//!
+//! let clock = Arc::new(ManualClock::new(Utc::now()));
+//! let mut issuer = TokenMap::new(RandomGenerator::new(16));
+//! issuer.clock(clock.clone());
+//! # let scopes: [oxide_auth::primitives::scope::Scope; 1] = ["read".parse().unwrap()];
+//! let endpoint = WithClock::new(
+//!     Generic {
+//!         registrar: Vacant,
+//!         authorizer: Vacant,
+//!         issuer,
+//!         solicitor: Vacant,
+//!         scopes: &scopes[..],
+//!         response: Vacant,
+//!     },
+//!     clock.clone(),
+//! );
+//!
+//! // Tokens issued before now expire in the flows of the endpoint after their lifetime passed.
+//! clock.advance(Duration::hours(2));
+//! ```
+//!
+//! [`Clock`]: trait.Clock.html
+//! [`SystemClock`]: struct.SystemClock.html
+use std::sync::{Arc, Mutex, PoisonError};
+
+use chrono::{Duration, Utc};
+
+use super::Time;
+
+/// A source of the current time.
+pub trait Clock {
+    /// The current time.
+    fn now(&self) -> Time;
+}
+
+/// The clock of the system, `Utc::now()`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+/// A clock that only moves when told to, for tests and hosts that provide their own time.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<Time>,
+}
+
+/// A clock shared by a primitive.
+pub(crate) type SharedClock = Arc<dyn Clock + Send + Sync>;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Time {
+        Utc::now()
+    }
+}
+
+impl ManualClock {
+    /// A clock standing at the time.
+    pub fn new(now: Time) -> Self {
+        ManualClock { now: Mutex::new(now) }
+    }
+
+    /// Set the current time.
+    pub fn set(&self, now: Time) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) = now;
+    }
+
+    /// Move the current time forward, or backward for a negative duration.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(PoisonError::into_inner);
+        *now += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Time {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Time {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> Time {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Time {
+        (**self).now()
+    }
+}
+
+/// The system clock, shared.
+pub(crate) fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// Share the clock of a primitive.
+pub(crate) fn shared<C: Clock + Send + Sync + 'static>(clock: C) -> SharedClock {
+    Arc::new(clock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock() {
+        let start = Utc::now();
+        let clock = Arc::new(ManualClock::new(start));
+        let shared = shared(clock.clone());
+        assert_eq!(shared.now(), start);
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(shared.now(), start + Duration::minutes(5));
+        clock.set(start - Duration::hours(1));
+        assert_eq!(shared.now(), start - Duration::hours(1));
+    }
+}
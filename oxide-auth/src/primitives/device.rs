@@ -0,0 +1,670 @@
+//! Device codes track authorizations started on devices without a browser.
+//!
+//! In the [Device Authorization Grant], a device with limited input capabilities such as a TV or a
+//! command line tool asks for a pair of codes. The `user_code` is shown to the user, who enters it
+//! on another device at the verification page of the authorization server and approves the
+//! request. Meanwhile the device polls the token endpoint with its `device_code` until the user
+//! made a decision.
+//!
+//! A [`DeviceCodeStore`] keeps these pending authorizations, records the decision of the user and
+//! enforces the polling interval of devices.
+//!
+//! [Device Authorization Grant]: https://tools.ietf.org/html/rfc8628
+//! [`DeviceCodeStore`]: trait.DeviceCodeStore.html
+use std::collections::HashMap;
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use chrono::Duration;
+use rand::{thread_rng, Rng};
+use serde_json::{Map, Value};
+
+use crate::code_grant::extensions::attach_claims;
+use super::clock::{self, Clock, SharedClock};
+use super::generator::TagGrant;
+use super::grant::{Extensions, Grant};
+use super::registrar::PreGrant;
+use super::Time;
+
+/// Characters of user codes, consonants only to avoid accidental words and ambiguous letters.
+const USER_CODE_CHARACTERS: &str = "BCDFGHJKLMNPQRSTVWXZ";
+
+/// The fewest distinct user codes of a format, as many as there are codes of six digits.
+const MIN_USER_CODE_COMBINATIONS: u64 = 1_000_000;
+
+/// How often a user code is generated anew when it collides with a pending one.
+const USER_CODE_ATTEMPTS: usize = 16;
+
+/// The shape of generated user codes.
+///
+/// Codes consist of random characters of an alphabet, split into groups by dashes for
+/// readability. Since users may type codes in lower case and without dashes, the alphabet is
+/// restricted to upper case letters and digits. The default are eight consonants in groups of
+/// four, such as `WDJB-MJHT`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserCodeFormat {
+    alphabet: Vec<char>,
+    length: usize,
+    group: usize,
+}
+
+/// The codes of a newly started device authorization.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceAuthorization {
+    /// The code with which the device polls for its token.
+    pub device_code: String,
+
+    /// The code the user enters on the verification page.
+    pub user_code: String,
+
+    /// Expiration timestamp of both codes (Utc).
+    pub until: Time,
+
+    /// The minimum time the device should wait between polling requests.
+    pub interval: Duration,
+}
+
+/// The state of a device authorization when polled by the device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DevicePoll {
+    /// The user has not decided yet.
+    Pending,
+
+    /// The user has not decided yet and the device polled before its interval elapsed.
+    ///
+    /// The interval was increased and the device should slow down.
+    SlowDown,
+
+    /// The user approved the request, the grant may be traded for a token.
+    ///
+    /// The authorization is removed, so each approval is only returned once.
+    Approved(Grant),
+
+    /// The user denied the request.
+    Denied,
+
+    /// The codes expired before the user approved the request.
+    Expired,
+
+    /// There is no authorization of the client with this device code.
+    Unknown,
+}
+
+/// Stores the pending authorizations of devices.
+pub trait DeviceCodeStore {
+    /// Start an authorization of a device for the negotiated parameters.
+    fn start(&mut self, grant: PreGrant) -> Result<DeviceAuthorization, ()>;
+
+    /// The parameters of the pending authorization a user code belongs to.
+    ///
+    /// Returns `None` for unknown or expired codes and for codes that were already decided.
+    fn pending(&mut self, user_code: &str) -> Result<Option<PreGrant>, ()>;
+
+    /// Record the approval of the owner, returning whether the code was pending.
+    fn approve(&mut self, user_code: &str, owner_id: String) -> Result<bool, ()>;
+
+    /// Record the approval of the owner with custom claims for the grant, returning whether the
+    /// code was pending.
+    ///
+    /// The default implementation fails for any claims, as the store could not keep them, and
+    /// otherwise approves.
+    fn approve_with_claims(
+        &mut self, user_code: &str, owner_id: String, claims: Map<String, Value>,
+    ) -> Result<bool, ()> {
+        if !claims.is_empty() {
+            return Err(());
+        }
+        self.approve(user_code, owner_id)
+    }
+
+    /// Record that the owner denied the request, returning whether the code was pending.
+    fn deny(&mut self, user_code: &str) -> Result<bool, ()>;
+
+    /// Poll the authorization of a device code issued to the client.
+    fn poll(&mut self, client_id: &str, device_code: &str) -> Result<DevicePoll, ()>;
+}
+
+/// Bring a user code to its canonical form.
+///
+/// Users may type codes in lower case and with or without the separating dash.
+pub fn normalize_user_code(user_code: &str) -> String {
+    user_code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Generate a random user code of eight characters, such as `WDJB-MJHT`.
+pub fn random_user_code() -> String {
+    UserCodeFormat::default().generate()
+}
+
+impl UserCodeFormat {
+    /// Codes of `length` characters from the alphabet, without groups.
+    ///
+    /// Fails if the alphabet has fewer than two distinct characters, contains anything but upper
+    /// case ASCII letters and digits, or if the format has fewer than a million distinct codes.
+    /// Smaller formats would soon run out of free codes and make guessing a pending one easy.
+    pub fn new(alphabet: &str, length: usize) -> Result<Self, ()> {
+        let mut characters: Vec<char> = alphabet.chars().collect();
+        characters.sort_unstable();
+        characters.dedup();
+        let valid = |c: &char| c.is_ascii_uppercase() || c.is_ascii_digit();
+        if characters.len() < 2 || !characters.iter().all(valid) {
+            return Err(());
+        }
+
+        let format = UserCodeFormat {
+            alphabet: characters,
+            length,
+            group: 0,
+        };
+        if format.combinations() < MIN_USER_CODE_COMBINATIONS {
+            return Err(());
+        }
+        Ok(format)
+    }
+
+    /// Codes of `length` decimal digits, easier to enter on numeric keypads.
+    ///
+    /// Fails for fewer than six digits.
+    pub fn numeric(length: usize) -> Result<Self, ()> {
+        UserCodeFormat::new("0123456789", length)
+    }
+
+    /// Separate groups of `size` characters with a dash, or none for a size of zero.
+    pub fn grouped(mut self, size: usize) -> Self {
+        self.group = size;
+        self
+    }
+
+    /// The number of distinct codes of this format.
+    ///
+    /// Saturates at `u64::MAX`. The RFC recommends enough codes that guessing an active one
+    /// within the validity of codes is improbable, in combination with rate limiting.
+    pub fn combinations(&self) -> u64 {
+        let base = self.alphabet.len() as u64;
+        (0..self.length).fold(1u64, |total, _| total.saturating_mul(base))
+    }
+
+    /// Generate a random code.
+    pub fn generate(&self) -> String {
+        let mut rng = thread_rng();
+        let mut code = String::with_capacity(2 * self.length);
+        for i in 0..self.length {
+            if self.group != 0 && i != 0 && i % self.group == 0 {
+                code.push('-');
+            }
+            code.push(self.alphabet[rng.gen_range(0..self.alphabet.len())]);
+        }
+        code
+    }
+}
+
+impl Default for UserCodeFormat {
+    fn default() -> Self {
+        UserCodeFormat::new(USER_CODE_CHARACTERS, 8)
+            .expect("The default user code format is valid")
+            .grouped(4)
+    }
+}
+
+/// An in-memory hash map of device authorizations.
+///
+/// Device codes are generated by the tagger, user codes are random in the default
+/// `UserCodeFormat` unless another format is configured. Codes are valid for ten minutes and
+/// devices are asked to poll at most every five seconds by default.
+pub struct DeviceCodeMap<I: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
+    tagger: I,
+    usage: u64,
+    duration: Duration,
+    interval: Duration,
+    user_code_format: UserCodeFormat,
+    devices: HashMap<String, Device>,
+    user_codes: HashMap<String, String>,
+    clock: SharedClock,
+}
+
+struct Device {
+    user_code: String,
+    grant: PreGrant,
+    until: Time,
+    interval: Duration,
+    last_poll: Option<Time>,
+    decision: Option<Option<String>>,
+    extensions: Extensions,
+}
+
+impl<I: TagGrant> DeviceCodeMap<I> {
+    /// Create a store generating device codes with the `tagger`.
+    pub fn new(tagger: I) -> Self {
+        DeviceCodeMap {
+            tagger,
+            usage: 0,
+            duration: Duration::minutes(10),
+            interval: Duration::seconds(5),
+            user_code_format: UserCodeFormat::default(),
+            devices: HashMap::new(),
+            user_codes: HashMap::new(),
+            clock: clock::system(),
+        }
+    }
+
+    /// Set the validity of codes started after this call.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    /// Set the polling interval of authorizations started after this call.
+    pub fn poll_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Generate user codes in another format.
+    ///
+    /// The format must have considerably more codes than authorizations are pending at any time,
+    /// since new codes are generated until one is not in use.
+    pub fn user_code_format(&mut self, format: UserCodeFormat) {
+        self.user_code_format = format;
+    }
+
+    /// Compute the expiry of codes and the polling intervals from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
+    }
+
+    fn remove(&mut self, device_code: &str) -> Option<Device> {
+        let device = self.devices.remove(device_code)?;
+        self.user_codes.remove(&device.user_code);
+        Some(device)
+    }
+
+    fn decide(&mut self, user_code: &str, decision: Option<String>, claims: Map<String, Value>) -> bool {
+        let now = self.clock.now();
+        let device_code = match self.user_codes.get(&normalize_user_code(user_code)) {
+            Some(device_code) => device_code,
+            None => return false,
+        };
+        match self.devices.get_mut(device_code) {
+            Some(device) if device.decision.is_none() && device.until > now => {
+                device.decision = Some(decision);
+                attach_claims(&mut device.extensions, claims);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a, S: DeviceCodeStore + ?Sized> DeviceCodeStore for &'a mut S {
+    fn start(&mut self, grant: PreGrant) -> Result<DeviceAuthorization, ()> {
+        (**self).start(grant)
+    }
+
+    fn pending(&mut self, user_code: &str) -> Result<Option<PreGrant>, ()> {
+        (**self).pending(user_code)
+    }
+
+    fn approve(&mut self, user_code: &str, owner_id: String) -> Result<bool, ()> {
+        (**self).approve(user_code, owner_id)
+    }
+
+    fn approve_with_claims(
+        &mut self, user_code: &str, owner_id: String, claims: Map<String, Value>,
+    ) -> Result<bool, ()> {
+        (**self).approve_with_claims(user_code, owner_id, claims)
+    }
+
+    fn deny(&mut self, user_code: &str) -> Result<bool, ()> {
+        (**self).deny(user_code)
+    }
+
+    fn poll(&mut self, client_id: &str, device_code: &str) -> Result<DevicePoll, ()> {
+        (**self).poll(client_id, device_code)
+    }
+}
+
+impl<S: DeviceCodeStore + ?Sized> DeviceCodeStore for Box<S> {
+    fn start(&mut self, grant: PreGrant) -> Result<DeviceAuthorization, ()> {
+        (**self).start(grant)
+    }
+
+    fn pending(&mut self, user_code: &str) -> Result<Option<PreGrant>, ()> {
+        (**self).pending(user_code)
+    }
+
+    fn approve(&mut self, user_code: &str, owner_id: String) -> Result<bool, ()> {
+        (**self).approve(user_code, owner_id)
+    }
+
+    fn approve_with_claims(
+        &mut self, user_code: &str, owner_id: String, claims: Map<String, Value>,
+    ) -> Result<bool, ()> {
+        (**self).approve_with_claims(user_code, owner_id, claims)
+    }
+
+    fn deny(&mut self, user_code: &str) -> Result<bool, ()> {
+        (**self).deny(user_code)
+    }
+
+    fn poll(&mut self, client_id: &str, device_code: &str) -> Result<DevicePoll, ()> {
+        (**self).poll(client_id, device_code)
+    }
+}
+
+impl<'a, S: DeviceCodeStore + ?Sized> DeviceCodeStore for MutexGuard<'a, S> {
+    fn start(&mut self, grant: PreGrant) -> Result<DeviceAuthorization, ()> {
+        (**self).start(grant)
+    }
+
+    fn pending(&mut self, user_code: &str) -> Result<Option<PreGrant>, ()> {
+        (**self).pending(user_code)
+    }
+
+    fn approve(&mut self, user_code: &str, owner_id: String) -> Result<bool, ()> {
+        (**self).approve(user_code, owner_id)
+    }
+
+    fn approve_with_claims(
+        &mut self, user_code: &str, owner_id: String, claims: Map<String, Value>,
+    ) -> Result<bool, ()> {
+        (**self).approve_with_claims(user_code, owner_id, claims)
+    }
+
+    fn deny(&mut self, user_code: &str) -> Result<bool, ()> {
+        (**self).deny(user_code)
+    }
+
+    fn poll(&mut self, client_id: &str, device_code: &str) -> Result<DevicePoll, ()> {
+        (**self).poll(client_id, device_code)
+    }
+}
+
+impl<'a, S: DeviceCodeStore + ?Sized> DeviceCodeStore for RwLockWriteGuard<'a, S> {
+    fn start(&mut self, grant: PreGrant) -> Result<DeviceAuthorization, ()> {
+        (**self).start(grant)
+    }
+
+    fn pending(&mut self, user_code: &str) -> Result<Option<PreGrant>, ()> {
+        (**self).pending(user_code)
+    }
+
+    fn approve(&mut self, user_code: &str, owner_id: String) -> Result<bool, ()> {
+        (**self).approve(user_code, owner_id)
+    }
+
+    fn approve_with_claims(
+        &mut self, user_code: &str, owner_id: String, claims: Map<String, Value>,
+    ) -> Result<bool, ()> {
+        (**self).approve_with_claims(user_code, owner_id, claims)
+    }
+
+    fn deny(&mut self, user_code: &str) -> Result<bool, ()> {
+        (**self).deny(user_code)
+    }
+
+    fn poll(&mut self, client_id: &str, device_code: &str) -> Result<DevicePoll, ()> {
+        (**self).poll(client_id, device_code)
+    }
+}
+
+impl<I: TagGrant> DeviceCodeStore for DeviceCodeMap<I> {
+    fn start(&mut self, grant: PreGrant) -> Result<DeviceAuthorization, ()> {
+        let now = self.clock.now();
+        self.devices.retain(|_, device| device.until > now);
+        let devices = &self.devices;
+        self.user_codes
+            .retain(|_, device_code| devices.contains_key(device_code));
+
+        let until = now + self.duration;
+        // The tagger sees the grant as it would be approved, without an owner yet.
+        let tagged = Grant {
+            owner_id: String::new(),
+            client_id: grant.client_id.clone(),
+            scope: grant.scope.clone(),
+            redirect_uri: grant.redirect_uri.to_url(),
+            until,
+            extensions: Extensions::new(),
+        };
+        // Give up instead of searching an almost exhausted code space for the last free codes.
+        let user_code = (0..USER_CODE_ATTEMPTS)
+            .map(|_| self.user_code_format.generate())
+            .find(|code| !self.user_codes.contains_key(&normalize_user_code(code)))
+            .ok_or(())?;
+
+        let next_usage = self.usage.wrapping_add(1);
+        let device_code = self.tagger.tag(next_usage - 1, &tagged)?;
+        self.usage = next_usage;
+
+        self.user_codes
+            .insert(normalize_user_code(&user_code), device_code.clone());
+        self.devices.insert(
+            device_code.clone(),
+            Device {
+                user_code: normalize_user_code(&user_code),
+                grant,
+                until,
+                interval: self.interval,
+                last_poll: None,
+                decision: None,
+                extensions: Extensions::new(),
+            },
+        );
+
+        Ok(DeviceAuthorization {
+            device_code,
+            user_code,
+            until,
+            interval: self.interval,
+        })
+    }
+
+    fn pending(&mut self, user_code: &str) -> Result<Option<PreGrant>, ()> {
+        let now = self.clock.now();
+        let pending = self
+            .user_codes
+            .get(&normalize_user_code(user_code))
+            .and_then(|device_code| self.devices.get(device_code))
+            .filter(|device| device.decision.is_none() && device.until > now)
+            .map(|device| device.grant.clone());
+        Ok(pending)
+    }
+
+    fn approve(&mut self, user_code: &str, owner_id: String) -> Result<bool, ()> {
+        Ok(self.decide(user_code, Some(owner_id), Map::new()))
+    }
+
+    fn approve_with_claims(
+        &mut self, user_code: &str, owner_id: String, claims: Map<String, Value>,
+    ) -> Result<bool, ()> {
+        Ok(self.decide(user_code, Some(owner_id), claims))
+    }
+
+    fn deny(&mut self, user_code: &str) -> Result<bool, ()> {
+        Ok(self.decide(user_code, None, Map::new()))
+    }
+
+    fn poll(&mut self, client_id: &str, device_code: &str) -> Result<DevicePoll, ()> {
+        let now = self.clock.now();
+        let device = match self.devices.get_mut(device_code) {
+            Some(device) if device.grant.client_id == client_id => device,
+            _ => return Ok(DevicePoll::Unknown),
+        };
+
+        if device.until <= now {
+            self.remove(device_code);
+            return Ok(DevicePoll::Expired);
+        }
+
+        match &device.decision {
+            None => {
+                let too_early = device
+                    .last_poll
+                    .is_some_and(|last_poll| now - last_poll < device.interval);
+                device.last_poll = Some(now);
+                if too_early {
+                    // As required by the RFC, the interval increases by 5 seconds.
+                    device.interval += Duration::seconds(5);
+                    Ok(DevicePoll::SlowDown)
+                } else {
+                    Ok(DevicePoll::Pending)
+                }
+            }
+            Some(None) => {
+                self.remove(device_code);
+                Ok(DevicePoll::Denied)
+            }
+            Some(Some(_)) => {
+                let device = self.remove(device_code).unwrap();
+                let owner_id = device.decision.unwrap().unwrap();
+                Ok(DevicePoll::Approved(Grant {
+                    owner_id,
+                    client_id: device.grant.client_id,
+                    scope: device.grant.scope,
+                    redirect_uri: device.grant.redirect_uri.into_url(),
+                    until: now + Duration::minutes(10),
+                    extensions: device.extensions,
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_grant::extensions;
+    use crate::primitives::generator::RandomGenerator;
+
+    fn pre_grant() -> PreGrant {
+        PreGrant {
+            client_id: "Client".to_string(),
+            redirect_uri: "https://example.com/redirect_me"
+                .parse::<url::Url>()
+                .unwrap()
+                .into(),
+            scope: "default".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn user_codes() {
+        let code = random_user_code();
+        assert_eq!(code.len(), 9);
+        assert_eq!(code.as_bytes()[4], b'-');
+        assert_eq!(normalize_user_code("wdjb-mjht "), "WDJBMJHT");
+
+        let numeric = UserCodeFormat::numeric(9).unwrap().grouped(3);
+        assert_eq!(numeric.combinations(), 1_000_000_000);
+        let code = numeric.generate();
+        assert_eq!(code.len(), 11);
+        assert_eq!(normalize_user_code(&code).len(), 9);
+        assert!(normalize_user_code(&code).chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(UserCodeFormat::new("AB", 40).unwrap().combinations(), 1 << 40);
+        assert_eq!(UserCodeFormat::new("AB", 70).unwrap().combinations(), u64::MAX);
+
+        assert!(UserCodeFormat::new("abc", 8).is_err());
+        assert!(UserCodeFormat::new("A-B", 8).is_err());
+        assert!(UserCodeFormat::new("AAAA", 8).is_err());
+        assert!(UserCodeFormat::new("AB", 0).is_err());
+        assert!(UserCodeFormat::new("AB", 19).is_err());
+        assert!(UserCodeFormat::numeric(5).is_err());
+        assert!(UserCodeFormat::numeric(6).is_ok());
+    }
+
+    #[test]
+    fn custom_user_code_format() {
+        let mut store = DeviceCodeMap::new(RandomGenerator::new(16));
+        store.user_code_format(UserCodeFormat::new("XY", 24).unwrap());
+        let started = store.start(pre_grant()).unwrap();
+        assert_eq!(started.user_code.len(), 24);
+        assert!(started.user_code.chars().all(|c| c == 'X' || c == 'Y'));
+        assert_eq!(store.pending(&started.user_code), Ok(Some(pre_grant())));
+    }
+
+    #[test]
+    fn exhausted_user_codes() {
+        let mut store = DeviceCodeMap::new(RandomGenerator::new(16));
+        // Too small for `UserCodeFormat::new`, but an exhausted format of any size behaves alike.
+        store.user_code_format(UserCodeFormat {
+            alphabet: vec!['X'],
+            length: 1,
+            group: 0,
+        });
+        let started = store.start(pre_grant()).unwrap();
+        assert_eq!(started.user_code, "X");
+        assert_eq!(store.start(pre_grant()), Err(()));
+
+        // The code is free again once its authorization was decided and polled.
+        store.deny("X").unwrap();
+        store.poll("Client", &started.device_code).unwrap();
+        assert!(store.start(pre_grant()).is_ok());
+    }
+
+    #[test]
+    fn approve_and_poll() {
+        let mut store = DeviceCodeMap::new(RandomGenerator::new(16));
+        store.poll_interval(Duration::seconds(1));
+        let started = store.start(pre_grant()).unwrap();
+
+        assert_eq!(store.poll("Other", &started.device_code), Ok(DevicePoll::Unknown));
+        assert_eq!(
+            store.poll("Client", &started.device_code),
+            Ok(DevicePoll::Pending)
+        );
+        assert_eq!(
+            store.poll("Client", &started.device_code),
+            Ok(DevicePoll::SlowDown)
+        );
+
+        let lower = started.user_code.to_lowercase();
+        assert_eq!(store.pending(&lower), Ok(Some(pre_grant())));
+        assert_eq!(store.approve(&lower, "Owner".to_string()), Ok(true));
+        assert_eq!(store.deny(&started.user_code), Ok(false));
+        assert_eq!(store.pending(&started.user_code), Ok(None));
+
+        match store.poll("Client", &started.device_code) {
+            Ok(DevicePoll::Approved(grant)) => assert_eq!(grant.owner_id, "Owner"),
+            other => panic!("Expected an approved grant, got {:?}", other),
+        }
+        assert_eq!(
+            store.poll("Client", &started.device_code),
+            Ok(DevicePoll::Unknown)
+        );
+    }
+
+    #[test]
+    fn approve_with_claims() {
+        let mut store = DeviceCodeMap::new(RandomGenerator::new(16));
+        let started = store.start(pre_grant()).unwrap();
+        let mut claims = Map::new();
+        claims.insert("tenant".to_owned(), Value::from(7));
+        assert_eq!(
+            store.approve_with_claims(&started.user_code, "Owner".to_string(), claims.clone()),
+            Ok(true)
+        );
+
+        match store.poll("Client", &started.device_code) {
+            Ok(DevicePoll::Approved(grant)) => {
+                assert_eq!(extensions::claims(&grant.extensions), claims)
+            }
+            other => panic!("Expected an approved grant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deny_and_expire() {
+        let mut store = DeviceCodeMap::new(RandomGenerator::new(16));
+        let denied = store.start(pre_grant()).unwrap();
+        assert_eq!(store.deny(&denied.user_code), Ok(true));
+        assert_eq!(store.poll("Client", &denied.device_code), Ok(DevicePoll::Denied));
+
+        store.valid_for(Duration::seconds(-1));
+        let expired = store.start(pre_grant()).unwrap();
+        assert_eq!(store.pending(&expired.user_code), Ok(None));
+        assert_eq!(
+            store.poll("Client", &expired.device_code),
+            Ok(DevicePoll::Expired)
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! Failures of authorizers and issuers.
+//!
+//! An [`Authorizer`] or [`Issuer`] reports why an operation failed, so that the flows can answer
+//! appropriately instead of treating every failure alike. A grant or token that does not exist is
+//! refused like an invalid one, a temporary failure of the storage backend becomes an
+//! `OAuthError::TemporarilyUnavailable` and a violated invariant an `OAuthError::PrimitiveError`.
+//!
+//! Implementations with their own error types map them in one place, for example:
+//!
+//! ```
+//! # use oxide_auth::primitives::error::PrimitiveError;
+//! enum StoreError {
+//!     Missing,
+//!     Timeout,
+//!     Corrupted,
+//! }
+//!
+//! impl From<StoreError> for PrimitiveError {
+//!     fn from(err: StoreError) -> Self {
+//!         match err {
+//!             StoreError::Missing => PrimitiveError::NotFound,
+//!             StoreError::Timeout => PrimitiveError::Unavailable,
+//!             StoreError::Corrupted => PrimitiveError::Invariant,
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`Authorizer`]: ../authorizer/trait.Authorizer.html
+//! [`Issuer`]: ../issuer/trait.Issuer.html
+use std::error;
+use std::fmt;
+
+/// The class of a failure of an `Authorizer` or `Issuer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PrimitiveError {
+    /// The grant, code or token the operation requires does not exist (any longer).
+    ///
+    /// For example, the refresh token to be refreshed was revoked concurrently. Flows treat this
+    /// like an invalid grant or token of the request.
+    NotFound,
+
+    /// The backend failed temporarily, for example with a lost connection or a timeout.
+    ///
+    /// Retrying the request later may succeed.
+    Unavailable,
+
+    /// The primitive can not perform the operation or is in an inconsistent state.
+    ///
+    /// For example, an issuer that does not support refresh tokens, a failed generator or stored
+    /// data that could not be decoded.
+    Invariant,
+}
+
+impl fmt::Display for PrimitiveError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrimitiveError::NotFound => fmt.write_str("PrimitiveError: Grant or token not found"),
+            PrimitiveError::Unavailable => {
+                fmt.write_str("PrimitiveError: Backend temporarily unavailable")
+            }
+            PrimitiveError::Invariant => fmt.write_str("PrimitiveError: Invariant violated"),
+        }
+    }
+}
+
+impl error::Error for PrimitiveError {}
@@ -0,0 +1,480 @@
+//! Announces security events to resource servers, as Security Event Tokens or plain webhooks.
+//!
+//! Resource servers that cache the results of introspection or validate tokens locally do not learn
+//! about revocations until their caches expire. The [`SecurityEvents`] informs them when a token is
+//! revoked, when a rotated refresh token is used again, or when a client is disabled. Each
+//! receiver is posted either a signed Security Event Token of [RFC 8417], as delivered by [RFC
+//! 8935], or the plain json of the event as a webhook, through a [`DeliverEvent`] transport that
+//! the server implements with its http client of choice. Wrapping an issuer in
+//! [`WithSecurityEvents`] announces its revoked and reused tokens:
+//!
+//! ```
+//! use oxide_auth::primitives::events::{EventFormat, SecurityEvents, WithSecurityEvents};
+//! use oxide_auth::primitives::generator::RandomGenerator;
+//! use oxide_auth::primitives::issuer::TokenMap;
+//! use oxide_auth::primitives::keystore::KeyStore;
+//! use oxide_auth::primitives::jwt::Algorithm;
+//! use oxide_auth::frontends::dev::Url;
+//!
+//! let keys = KeyStore::new();
+//! keys.generate(Algorithm::EdDSA).unwrap();
+//!
+//! let mut events = SecurityEvents::new(keys, "https://auth.example.com",
+//!     |uri: &Url, content_type: &str, body: &str| {
+//!         // POST the body with the content type to the uri.
+//! #       let _ = (uri, content_type, body);
+//!         Ok(())
+//!     });
+//! events.subscribe("https://api.example.com/events".parse().unwrap(), EventFormat::Signed);
+//! events.subscribe("https://cache.example.com/hook".parse().unwrap(), EventFormat::Webhook);
+//!
+//! let issuer = WithSecurityEvents::new(TokenMap::new(RandomGenerator::new(16)), events);
+//! ```
+//!
+//! Only available with the `jwt` feature.
+//!
+//! [RFC 8417]: https://tools.ietf.org/html/rfc8417
+//! [RFC 8935]: https://tools.ietf.org/html/rfc8935
+//! [`SecurityEvents`]: struct.SecurityEvents.html
+//! [`DeliverEvent`]: trait.DeliverEvent.html
+//! [`WithSecurityEvents`]: struct.WithSecurityEvents.html
+use std::collections::HashMap;
+
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::error::PrimitiveError;
+use super::grant::Grant;
+use super::issuer::{IssuedToken, Issuer, RefreshedToken};
+use super::keystore::KeyStore;
+use super::Time;
+
+/// The event of a revoked access or refresh token.
+pub const TOKEN_REVOKED_EVENT: &str =
+    "https://schemas.openid.net/secevent/oauth/event-type/token-revoked";
+
+/// The event of a rotated refresh token that was presented again, which suggests it was stolen.
+pub const REFRESH_REUSE_EVENT: &str =
+    "https://schemas.openid.net/secevent/risc/event-type/credential-compromise";
+
+/// The event of a disabled client.
+pub const CLIENT_DISABLED_EVENT: &str =
+    "https://schemas.openid.net/secevent/oauth/event-type/client-disabled";
+
+/// The content type of signed events, for push delivery.
+pub const SECEVENT_CONTENT_TYPE: &str = "application/secevent+jwt";
+
+/// A single security event, the body of a webhook.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SecurityEvent {
+    /// The type of the event, one of the event uris of this module.
+    pub event: String,
+
+    /// The client concerned by the event.
+    pub client_id: String,
+
+    /// The owner whose authorization is concerned, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+
+    /// The type of the concerned token, `access_token` or `refresh_token`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+
+    /// The time of the event as seconds since the unix epoch.
+    pub toe: i64,
+}
+
+/// The claims of a Security Event Token.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SecurityEventClaims {
+    /// The issuer identifier of the authorization server.
+    pub iss: String,
+
+    /// The receiver of the event.
+    pub aud: String,
+
+    /// The time of issuance as seconds since the unix epoch.
+    pub iat: i64,
+
+    /// The unique id of the token.
+    pub jti: String,
+
+    /// The time of the event as seconds since the unix epoch.
+    pub toe: i64,
+
+    /// The single event of the token, with the remaining fields of the `SecurityEvent`.
+    pub events: Map<String, Value>,
+}
+
+/// How events are posted to a receiver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventFormat {
+    /// A Security Event Token, signed with the current key.
+    Signed,
+
+    /// The json of the `SecurityEvent`.
+    Webhook,
+}
+
+/// Delivers events to the endpoints of receivers.
+///
+/// Implementations post the body with the content type to the uri. Any response other than a
+/// success, or failing to reach the receiver, is an error.
+pub trait DeliverEvent {
+    /// Post the event to the endpoint.
+    fn deliver(&self, uri: &Url, content_type: &str, body: &str) -> Result<(), ()>;
+}
+
+/// Signs and delivers security events to all subscribed receivers.
+pub struct SecurityEvents<D> {
+    keys: KeyStore,
+    issuer: String,
+    receivers: Vec<(Url, EventFormat)>,
+    delivery: D,
+}
+
+/// Announces the revoked and reused tokens of an issuer.
+///
+/// Revoking an access or refresh token is announced with the `TOKEN_REVOKED_EVENT`. Refresh tokens
+/// replaced by a refresh are remembered for 30 days by default, and recovering one of them again
+/// is announced with the `REFRESH_REUSE_EVENT`. Failed deliveries are logged, they do not fail the
+/// underlying operation.
+pub struct WithSecurityEvents<I, D> {
+    /// The wrapped issuer.
+    pub inner: I,
+
+    /// The announcer of events.
+    pub events: SecurityEvents<D>,
+
+    remember_for: Duration,
+    rotated: HashMap<Vec<u8>, Rotated>,
+}
+
+/// The owner and client of a replaced refresh token.
+struct Rotated {
+    client_id: String,
+    owner_id: String,
+    until: Time,
+}
+
+impl<F> DeliverEvent for F
+where
+    F: Fn(&Url, &str, &str) -> Result<(), ()>,
+{
+    fn deliver(&self, uri: &Url, content_type: &str, body: &str) -> Result<(), ()> {
+        self(uri, content_type, body)
+    }
+}
+
+impl SecurityEvent {
+    /// An event about the client and owner of a grant.
+    pub fn new(event: &str, grant: &Grant) -> Self {
+        SecurityEvent {
+            event: event.to_owned(),
+            client_id: grant.client_id.clone(),
+            sub: Some(grant.owner_id.clone()),
+            token_type: None,
+            toe: Utc::now().timestamp(),
+        }
+    }
+
+    /// The event of a disabled client.
+    pub fn client_disabled(client_id: &str) -> Self {
+        SecurityEvent {
+            event: CLIENT_DISABLED_EVENT.to_owned(),
+            client_id: client_id.to_owned(),
+            sub: None,
+            token_type: None,
+            toe: Utc::now().timestamp(),
+        }
+    }
+}
+
+impl<D: DeliverEvent> SecurityEvents<D> {
+    /// Sign tokens with the current key of the store as the issuer `iss`.
+    pub fn new(keys: KeyStore, iss: &str, delivery: D) -> Self {
+        SecurityEvents {
+            keys,
+            issuer: iss.to_owned(),
+            receivers: Vec::new(),
+            delivery,
+        }
+    }
+
+    /// Post all future events to the endpoint of a receiver.
+    pub fn subscribe(&mut self, uri: Url, format: EventFormat) {
+        self.receivers.push((uri, format));
+    }
+
+    /// The claims of a Security Event Token for the receiver.
+    pub fn claims(&self, aud: &Url, event: &SecurityEvent) -> Result<SecurityEventClaims, ()> {
+        let mut jti = [0; 16];
+        thread_rng().try_fill_bytes(&mut jti).map_err(|_| ())?;
+
+        let mut payload = Map::new();
+        payload.insert("client_id".to_owned(), event.client_id.clone().into());
+        if let Some(sub) = &event.sub {
+            payload.insert("sub".to_owned(), sub.clone().into());
+        }
+        if let Some(token_type) = &event.token_type {
+            payload.insert("token_type".to_owned(), token_type.clone().into());
+        }
+        let mut events = Map::new();
+        events.insert(event.event.clone(), Value::Object(payload));
+
+        Ok(SecurityEventClaims {
+            iss: self.issuer.clone(),
+            aud: aud.to_string(),
+            iat: Utc::now().timestamp(),
+            jti: encode_config(jti, URL_SAFE_NO_PAD),
+            toe: event.toe,
+            events,
+        })
+    }
+
+    /// Sign the claims of a Security Event Token.
+    pub fn sign(&self, claims: &SecurityEventClaims) -> Result<String, ()> {
+        self.keys.current().ok_or(())?.sign("secevent+jwt", claims)
+    }
+
+    /// Announce the event to all receivers.
+    ///
+    /// All receivers are posted the event, even if an earlier delivery failed.
+    pub fn emit(&self, event: &SecurityEvent) -> Result<(), ()> {
+        let mut result = Ok(());
+        for (uri, format) in &self.receivers {
+            let delivered = match format {
+                EventFormat::Signed => self
+                    .claims(uri, event)
+                    .and_then(|claims| self.sign(&claims))
+                    .and_then(|token| self.delivery.deliver(uri, SECEVENT_CONTENT_TYPE, &token)),
+                EventFormat::Webhook => {
+                    let body = serde_json::to_string(event).unwrap();
+                    self.delivery.deliver(uri, "application/json", &body)
+                }
+            };
+            result = result.and(delivered);
+        }
+        result
+    }
+
+    /// Announce that the client was disabled, after the server removed it from its registrar.
+    pub fn client_disabled(&self, client_id: &str) -> Result<(), ()> {
+        self.emit(&SecurityEvent::client_disabled(client_id))
+    }
+
+    fn emit_logged(&self, event: &SecurityEvent) {
+        if self.emit(event).is_err() {
+            log::warn!(
+                "Failed to deliver security event {} of {}",
+                event.event,
+                event.client_id
+            );
+        }
+    }
+}
+
+impl<I: Issuer, D: DeliverEvent> WithSecurityEvents<I, D> {
+    /// Announce the events of the issuer.
+    pub fn new(inner: I, events: SecurityEvents<D>) -> Self {
+        WithSecurityEvents {
+            inner,
+            events,
+            remember_for: Duration::days(30),
+            rotated: HashMap::new(),
+        }
+    }
+
+    /// Set how long replaced refresh tokens are remembered for detecting their reuse.
+    ///
+    /// This should be at least the lifetime of refresh tokens of the issuer.
+    pub fn remember_rotated_for(&mut self, duration: Duration) {
+        self.remember_for = duration;
+    }
+
+    fn hash(token: &str) -> Vec<u8> {
+        Sha256::digest(token.as_bytes()).to_vec()
+    }
+}
+
+impl<I: Issuer, D: DeliverEvent> Issuer for WithSecurityEvents<I, D> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        self.inner.issue(grant)
+    }
+
+    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        let now = Utc::now();
+        let rotated = Rotated {
+            client_id: grant.client_id.clone(),
+            owner_id: grant.owner_id.clone(),
+            until: now + self.remember_for,
+        };
+
+        let refreshed = self.inner.refresh(token, grant)?;
+        self.rotated.retain(|_, rotated| rotated.until > now);
+        if refreshed
+            .refresh
+            .as_deref()
+            .is_some_and(|refresh| refresh != token)
+        {
+            self.rotated.insert(Self::hash(token), rotated);
+        }
+        Ok(refreshed)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.inner.recover_token(token)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let recovered = self.inner.recover_refresh(token)?;
+        if recovered.is_none() {
+            if let Some(rotated) = self.rotated.get(&Self::hash(token)) {
+                self.events.emit_logged(&SecurityEvent {
+                    event: REFRESH_REUSE_EVENT.to_owned(),
+                    client_id: rotated.client_id.clone(),
+                    sub: Some(rotated.owner_id.clone()),
+                    token_type: Some("refresh_token".to_owned()),
+                    toe: Utc::now().timestamp(),
+                });
+            }
+        }
+        Ok(recovered)
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        let revoked = match self.inner.recover_token(token)? {
+            Some(grant) => Some((grant, "access_token")),
+            None => self
+                .inner
+                .recover_refresh(token)?
+                .map(|grant| (grant, "refresh_token")),
+        };
+
+        self.inner.revoke(token)?;
+        if let Some((grant, token_type)) = revoked {
+            let mut event = SecurityEvent::new(TOKEN_REVOKED_EVENT, &grant);
+            event.token_type = Some(token_type.to_owned());
+            self.events.emit_logged(&event);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::generator::RandomGenerator;
+    use crate::primitives::issuer::TokenMap;
+    use crate::primitives::jwt::Algorithm;
+    use std::cell::RefCell;
+
+    type Delivered = RefCell<Vec<(Url, String, String)>>;
+
+    fn subscribed<'a>(
+        keys: &KeyStore, delivered: &'a Delivered,
+    ) -> SecurityEvents<impl DeliverEvent + 'a> {
+        let delivery = move |uri: &Url, content_type: &str, body: &str| {
+            let body = body.to_owned();
+            delivered
+                .borrow_mut()
+                .push((uri.clone(), content_type.to_owned(), body));
+            Ok(())
+        };
+        let mut events = SecurityEvents::new(keys.clone(), "https://auth.example.com", delivery);
+        events.subscribe("https://api.example/events".parse().unwrap(), EventFormat::Signed);
+        events.subscribe(
+            "https://cache.example/hook".parse().unwrap(),
+            EventFormat::Webhook,
+        );
+        events
+    }
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::hours(1),
+            extensions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn revoked_tokens() {
+        let keys = KeyStore::new();
+        keys.generate(Algorithm::ES256).unwrap();
+        let delivered = RefCell::new(Vec::new());
+        let events = subscribed(&keys, &delivered);
+
+        let mut issuer = WithSecurityEvents::new(TokenMap::new(RandomGenerator::new(16)), events);
+        let issued = issuer.issue(grant()).unwrap();
+        issuer.revoke(&issued.token).unwrap();
+        issuer.revoke("unknown").unwrap();
+
+        let delivered = delivered.borrow();
+        assert_eq!(delivered.len(), 2);
+        assert_eq!(delivered[0].1, SECEVENT_CONTENT_TYPE);
+        let claims: SecurityEventClaims = keys.verify(&delivered[0].2).unwrap();
+        assert_eq!(claims.iss, "https://auth.example.com");
+        assert_eq!(claims.aud, "https://api.example/events");
+        let payload = &claims.events[TOKEN_REVOKED_EVENT];
+        assert_eq!(payload["sub"], "Owner");
+        assert_eq!(payload["token_type"], "access_token");
+
+        assert_eq!(delivered[1].1, "application/json");
+        let event: SecurityEvent = serde_json::from_str(&delivered[1].2).unwrap();
+        assert_eq!(event.event, TOKEN_REVOKED_EVENT);
+        assert_eq!(event.client_id, "Client");
+    }
+
+    #[test]
+    fn reused_refresh_tokens() {
+        let keys = KeyStore::new();
+        keys.generate(Algorithm::ES256).unwrap();
+        let delivered = RefCell::new(Vec::new());
+        let events = subscribed(&keys, &delivered);
+
+        let mut issuer = WithSecurityEvents::new(TokenMap::new(RandomGenerator::new(16)), events);
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        let refreshed = issuer.refresh(&refresh, grant()).unwrap();
+        assert!(issuer
+            .recover_refresh(refreshed.refresh.as_ref().unwrap())
+            .unwrap()
+            .is_some());
+        assert!(delivered.borrow().is_empty());
+
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+        let event: SecurityEvent = serde_json::from_str(&delivered.borrow()[1].2).unwrap();
+        assert_eq!(event.event, REFRESH_REUSE_EVENT);
+        assert_eq!(event.sub.as_deref(), Some("Owner"));
+    }
+
+    #[test]
+    fn disabled_clients() {
+        let keys = KeyStore::new();
+        let delivered = RefCell::new(Vec::new());
+        let events = subscribed(&keys, &delivered);
+
+        // Without a signing key only the webhook is delivered.
+        assert_eq!(events.client_disabled("Client"), Err(()));
+        let delivered = delivered.borrow();
+        assert_eq!(delivered.len(), 1);
+        let event: SecurityEvent = serde_json::from_str(&delivered[0].2).unwrap();
+        assert_eq!(
+            event,
+            SecurityEvent {
+                toe: event.toe,
+                ..SecurityEvent::client_disabled("Client")
+            }
+        );
+    }
+}
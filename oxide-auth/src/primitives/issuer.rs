@@ -7,12 +7,18 @@ use std::collections::HashMap;
 use std::sync::{Arc, MutexGuard, RwLockWriteGuard};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use chrono::{Duration, Utc};
+use chrono::Duration;
 
 use super::Time;
+use super::error::PrimitiveError;
+use super::clock::{self, Clock, SharedClock};
 use super::grant::Grant;
+use super::scope::Scope;
 use super::generator::{TagGrant, TaggedAssertion, Assertion};
 
+/// The scope value requesting a refresh token, to access resources while the end-user is offline.
+pub const OFFLINE_ACCESS_SCOPE: &str = "offline_access";
+
 /// Issuers create bearer tokens.
 ///
 /// It's the issuers decision whether a refresh token is offered or not. In any case, it is also
@@ -21,16 +27,25 @@ use super::generator::{TagGrant, TaggedAssertion, Assertion};
 /// they do not intend to offer a statefull refresh api).
 pub trait Issuer {
     /// Create a token authorizing the request parameters
-    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()>;
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError>;
 
     /// Refresh a token.
-    fn refresh(&mut self, _refresh: &str, _grant: Grant) -> Result<RefreshedToken, ()>;
+    fn refresh(&mut self, _refresh: &str, _grant: Grant) -> Result<RefreshedToken, PrimitiveError>;
 
     /// Get the values corresponding to a bearer token
-    fn recover_token<'a>(&'a self, _: &'a str) -> Result<Option<Grant>, ()>;
+    fn recover_token<'a>(&'a self, _: &'a str) -> Result<Option<Grant>, PrimitiveError>;
 
     /// Get the values corresponding to a refresh token
-    fn recover_refresh<'a>(&'a self, _: &'a str) -> Result<Option<Grant>, ()>;
+    fn recover_refresh<'a>(&'a self, _: &'a str) -> Result<Option<Grant>, PrimitiveError>;
+
+    /// Revoke an access or refresh token.
+    ///
+    /// Revoking a refresh token should also revoke the access token issued with it. Unknown tokens
+    /// are not an error. The default implementation fails, as is appropriate for issuers that can
+    /// not revoke their tokens such as the `TokenSigner`.
+    fn revoke(&mut self, _token: &str) -> Result<(), PrimitiveError> {
+        Err(PrimitiveError::Invariant)
+    }
 }
 
 /// Token parameters returned to a client.
@@ -68,6 +83,21 @@ pub enum TokenType {
     ///
     /// For this variant and its usage see RFC 6750.
     Bearer,
+
+    /// A token bound to a key, only used together with a proof of possessing that key.
+    ///
+    /// For this variant and its usage see RFC 9449.
+    DPoP,
+}
+
+impl TokenType {
+    /// The `token_type` of this kind of token in token responses.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenType::Bearer => "bearer",
+            TokenType::DPoP => "DPoP",
+        }
+    }
 }
 
 /// Refresh token information returned to a client.
@@ -91,13 +121,59 @@ pub struct RefreshedToken {
     pub token_type: TokenType,
 }
 
+/// The lifetimes of the tokens issued for a grant.
+///
+/// A missing access token lifetime keeps the expiry the grant was issued with, a missing refresh
+/// token lifetime lets refresh tokens live as long as the issuer keeps them.
+///
+/// Refresh tokens can expire in two ways. The `refresh` lifetime is sliding, every refresh issues
+/// a new refresh token living that long, so it ends a session after a period of inactivity. The
+/// `session` lifetime is absolute and counted from the first token of the grant, no refresh
+/// extends any token beyond it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Lifetimes {
+    /// The lifetime of the access token.
+    pub access: Option<Duration>,
+
+    /// The lifetime of each refresh token, restarting with every refresh.
+    pub refresh: Option<Duration>,
+
+    /// The absolute lifetime of all tokens issued for the grant, including refreshed ones.
+    pub session: Option<Duration>,
+}
+
+/// Decides how long the tokens of a grant live.
+///
+/// The policy sees the whole grant, its client, owner and scope, when a token is issued or
+/// refreshed. This allows enforcing short-lived tokens for sensitive scopes or for some clients
+/// while others keep longer sessions. Fixed `Lifetimes` are a policy for all grants, as is any
+/// function from a grant to its `Lifetimes`.
+pub trait LifetimePolicy {
+    /// The lifetimes of the tokens issued for the grant.
+    fn lifetimes(&self, grant: &Grant) -> Lifetimes;
+}
+
+/// Lifetimes chosen by the client and the scope of a grant.
+///
+/// Grants start with the lifetimes of their client, or the default when the client has none. Each
+/// scope rule whose scope is part of the grant may then shorten them, so the most sensitive scope
+/// determines how long the tokens live.
+#[derive(Clone, Debug, Default)]
+pub struct LifetimeRules {
+    default: Lifetimes,
+    clients: HashMap<String, Lifetimes>,
+    scopes: Vec<(Scope, Lifetimes)>,
+}
+
 /// Keeps track of access and refresh tokens by a hash-map.
 ///
 /// The generator is itself trait based and can be chosen during construction. It is assumed to not
 /// be possible (or at least very unlikely during their overlapping lifetime) for two different
 /// grants to generate the same token in the grant tagger.
 pub struct TokenMap<G: TagGrant = Box<dyn TagGrant + Send + Sync + 'static>> {
-    duration: Option<Duration>,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    clock: SharedClock,
+    offline_access: bool,
     generator: G,
     usage: u64,
     access: HashMap<Arc<str>, Arc<Token>>,
@@ -111,15 +187,119 @@ struct Token {
     /// Link to a refresh token for this grant, if it exists.
     refresh: Option<Arc<str>>,
 
+    /// Expiration of the refresh token, if it expires before being used.
+    refresh_until: Option<Time>,
+
+    /// The absolute expiration of the tokens of this grant, kept through refreshes.
+    session_until: Option<Time>,
+
     /// The grant that was originally granted.
     grant: Grant,
 }
 
+impl Lifetimes {
+    /// Keep the shorter of each lifetime.
+    fn shortest(self, other: Lifetimes) -> Lifetimes {
+        fn min(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        Lifetimes {
+            access: min(self.access, other.access),
+            refresh: min(self.refresh, other.refresh),
+            session: min(self.session, other.session),
+        }
+    }
+
+    /// The end of a session starting now.
+    ///
+    /// Issuers remember it with the refresh token, to expire all tokens of the grant at this time.
+    pub fn session_until(&self, now: Time) -> Option<Time> {
+        self.session.map(|session| now + session)
+    }
+
+    /// Set the expiration of the grant within the session, returning that of its refresh token.
+    ///
+    /// Issuers call this with the session end of the first token of the grant whenever they issue
+    /// or refresh a token, a refresh token expiring at `None` is kept until it is used.
+    pub fn expire(&self, grant: &mut Grant, session_until: Option<Time>, now: Time) -> Option<Time> {
+        if let Some(access) = self.access {
+            grant.until = now + access;
+        }
+
+        let refresh_until = self.refresh.map(|refresh| now + refresh);
+        match session_until {
+            Some(end) => {
+                grant.until = grant.until.min(end);
+                Some(refresh_until.map_or(end, |until| until.min(end)))
+            }
+            None => refresh_until,
+        }
+    }
+}
+
+impl LifetimePolicy for Lifetimes {
+    fn lifetimes(&self, _: &Grant) -> Lifetimes {
+        *self
+    }
+}
+
+impl<F> LifetimePolicy for F
+where
+    F: Fn(&Grant) -> Lifetimes,
+{
+    fn lifetimes(&self, grant: &Grant) -> Lifetimes {
+        self(grant)
+    }
+}
+
+impl LifetimeRules {
+    /// Rules giving all grants the default lifetimes.
+    pub fn new(default: Lifetimes) -> Self {
+        LifetimeRules {
+            default,
+            clients: HashMap::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Give the grants of a client other lifetimes than the default.
+    pub fn client(&mut self, client_id: &str, lifetimes: Lifetimes) -> &mut Self {
+        self.clients.insert(client_id.to_owned(), lifetimes);
+        self
+    }
+
+    /// Limit the lifetimes of grants including all of the scope.
+    pub fn scope(&mut self, scope: Scope, lifetimes: Lifetimes) -> &mut Self {
+        self.scopes.push((scope, lifetimes));
+        self
+    }
+}
+
+impl LifetimePolicy for LifetimeRules {
+    fn lifetimes(&self, grant: &Grant) -> Lifetimes {
+        let client = self
+            .clients
+            .get(&grant.client_id)
+            .copied()
+            .unwrap_or(self.default);
+        self.scopes
+            .iter()
+            .filter(|(scope, _)| grant.scope.priviledged_to(scope))
+            .fold(client, |lifetimes, (_, limit)| lifetimes.shortest(*limit))
+    }
+}
+
 impl<G: TagGrant> TokenMap<G> {
     /// Construct a `TokenMap` from the given generator.
     pub fn new(generator: G) -> Self {
         Self {
-            duration: None,
+            lifetimes: Box::new(Lifetimes::default()),
+            clock: clock::system(),
+            offline_access: false,
             generator,
             usage: 0,
             access: HashMap::new(),
@@ -128,42 +308,54 @@ impl<G: TagGrant> TokenMap<G> {
     }
 
     /// Set the validity of all issued grants to the specified duration.
+    ///
+    /// This replaces any previously set lifetime policy.
     pub fn valid_for(&mut self, duration: Duration) {
-        self.duration = Some(duration);
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
     }
 
     /// All grants are valid for their default duration.
     pub fn valid_for_default(&mut self) {
-        self.duration = None;
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Compute the expiry of issued tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
     }
 
-    /// Unconditionally delete grant associated with the token.
+    /// Determine whether refresh tokens require the `offline_access` scope.
     ///
-    /// This is the main advantage over signing tokens. By keeping internal state of allowed
-    /// grants, the resource owner or other instances can revoke a token before it expires
-    /// naturally. There is no differentiation between access and refresh tokens since these should
-    /// have a marginal probability of colliding.
-    pub fn revoke(&mut self, token: &str) {
-        self.access.remove(token);
-        self.refresh.remove(token);
+    /// By default, this option is *off* and every grant receives a refresh token. When on, only
+    /// grants including the `offline_access` scope receive one, as is the convention of OpenID
+    /// Connect, while all others only receive an access token. A refresh that narrows the scope
+    /// to exclude `offline_access` also ends the refresh token.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
     }
 
     /// Directly associate token with grant.
     ///
     /// No checks on the validity of the grant are performed but the expiration time of the grant
-    /// is modified (if a `duration` was previously set).
+    /// is modified (if the lifetime policy sets an access token lifetime).
     pub fn import_grant(&mut self, token: String, mut grant: Grant) {
-        self.set_duration(&mut grant);
+        let now = self.clock.now();
+        self.lifetimes.lifetimes(&grant).expire(&mut grant, None, now);
         let key: Arc<str> = Arc::from(token);
         let token = Token::from_access(key.clone(), grant);
         self.access.insert(key, Arc::new(token));
     }
-
-    fn set_duration(&self, grant: &mut Grant) {
-        if let Some(duration) = &self.duration {
-            grant.until = Utc::now() + *duration;
-        }
-    }
 }
 
 impl Token {
@@ -171,17 +363,33 @@ impl Token {
         Token {
             access,
             refresh: None,
+            refresh_until: None,
+            session_until: None,
             grant,
         }
     }
 
-    fn from_refresh(access: Arc<str>, refresh: Arc<str>, grant: Grant) -> Self {
+    fn from_refresh(
+        access: Arc<str>, refresh: Arc<str>, refresh_until: Option<Time>, session_until: Option<Time>,
+        grant: Grant,
+    ) -> Self {
         Token {
             access,
             refresh: Some(refresh),
+            refresh_until,
+            session_until,
             grant,
         }
     }
+
+    /// The grant as seen through the refresh token, expiring with it.
+    fn refresh_grant(&self) -> Grant {
+        let mut grant = self.grant.clone();
+        if let Some(until) = self.refresh_until {
+            grant.until = until;
+        }
+        grant
+    }
 }
 
 impl IssuedToken {
@@ -195,6 +403,7 @@ impl IssuedToken {
     ///
     /// ```
     /// # use oxide_auth::primitives::issuer::RefreshedToken;
+    /// # use oxide_auth::primitives::error::PrimitiveError;
     /// use oxide_auth::primitives::grant::Grant;
     /// use oxide_auth::primitives::issuer::{Issuer, IssuedToken};
     ///
@@ -208,14 +417,14 @@ impl IssuedToken {
     /// }
     ///
     /// impl Issuer for MyIssuer {
-    ///     fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, ()> {
+    ///     fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
     ///         let token = self.access_token(&grant);
     ///         Ok(IssuedToken::without_refresh(token, grant.until))
     ///     }
     ///     // …
-    /// # fn recover_token<'t>(&'t self, token: &'t str) -> Result<Option<Grant>, ()> { Err(()) }
-    /// # fn recover_refresh<'t>(&'t self, token: &'t str) -> Result<Option<Grant>, ()> { Err(()) }
-    /// # fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, ()> { Err(()) }
+    /// # fn recover_token<'t>(&'t self, token: &'t str) -> Result<Option<Grant>, PrimitiveError> { Err(PrimitiveError::Invariant) }
+    /// # fn recover_refresh<'t>(&'t self, token: &'t str) -> Result<Option<Grant>, PrimitiveError> { Err(PrimitiveError::Invariant) }
+    /// # fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, PrimitiveError> { Err(PrimitiveError::Invariant) }
     /// }
     /// ```
     pub fn without_refresh(token: String, until: Time) -> Self {
@@ -236,8 +445,11 @@ impl IssuedToken {
 }
 
 impl<G: TagGrant> Issuer for TokenMap<G> {
-    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, ()> {
-        self.set_duration(&mut grant);
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let session_until = lifetimes.session_until(now);
+        let refresh_until = lifetimes.expire(&mut grant, session_until, now);
         // The (usage, grant) tuple needs to be unique. Since this wraps after 2^63 operations, we
         // expect the validity time of the grant to have changed by then. This works when you don't
         // set your system time forward/backward ~10billion seconds, assuming ~10^9 operations per
@@ -245,8 +457,14 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
         let next_usage = self.usage.wrapping_add(2);
 
         let (access, refresh) = {
-            let access = self.generator.tag(self.usage, &grant)?;
-            let refresh = self.generator.tag(self.usage.wrapping_add(1), &grant)?;
+            let access = self
+                .generator
+                .tag(self.usage, &grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
+            let refresh = self
+                .generator
+                .tag(self.usage.wrapping_add(1), &grant)
+                .map_err(|()| PrimitiveError::Invariant)?;
             debug_assert!(
                 access.len() > 0,
                 "An empty access token was generated, this is horribly insecure."
@@ -260,8 +478,21 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
 
         let until = grant.until;
         let access_key: Arc<str> = Arc::from(access.clone());
+        if self.offline_access && !offline_access(&grant) {
+            let token = Token::from_access(access_key.clone(), grant);
+            self.access.insert(access_key, Arc::new(token));
+            self.usage = next_usage;
+            return Ok(IssuedToken::without_refresh(access, until));
+        }
+
         let refresh_key: Arc<str> = Arc::from(refresh.clone());
-        let token = Token::from_refresh(access_key.clone(), refresh_key.clone(), grant);
+        let token = Token::from_refresh(
+            access_key.clone(),
+            refresh_key.clone(),
+            refresh_until,
+            session_until,
+            grant,
+        );
         let token = Arc::new(token);
 
         self.access.insert(access_key, token.clone());
@@ -275,23 +506,32 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
         })
     }
 
-    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, ()> {
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
         // Remove the old token.
         let (refresh_key, mut token) = self
             .refresh
             .remove_entry(refresh)
-            // Should only be called on valid refresh tokens.
-            .ok_or(())?;
+            // Should only be called on valid refresh tokens, but it may have been revoked since.
+            .ok_or(PrimitiveError::NotFound)?;
 
         assert!(Arc::ptr_eq(token.refresh.as_ref().unwrap(), &refresh_key));
-        self.set_duration(&mut grant);
+        // The session of the grant keeps its end, only the sliding lifetimes restart.
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let refresh_until = lifetimes.expire(&mut grant, token.session_until, now);
         let until = grant.until;
 
         let tag = self.usage;
-        let new_access = self.generator.tag(tag, &grant)?;
+        let new_access = self
+            .generator
+            .tag(tag, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
 
         let tag = tag.wrapping_add(1);
-        let new_refresh = self.generator.tag(tag, &grant)?;
+        let new_refresh = self
+            .generator
+            .tag(tag, &grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
 
         let new_access_key: Arc<str> = Arc::from(new_access.clone());
         let new_refresh_key: Arc<str> = Arc::from(new_refresh.clone());
@@ -300,6 +540,18 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
             assert!(Arc::ptr_eq(&token, &atoken));
         }
 
+        if self.offline_access && !offline_access(&grant) {
+            let token = Token::from_access(new_access_key.clone(), grant);
+            self.access.insert(new_access_key, Arc::new(token));
+            self.usage = tag.wrapping_add(1);
+            return Ok(RefreshedToken {
+                token: new_access,
+                refresh: None,
+                until,
+                token_type: TokenType::Bearer,
+            });
+        }
+
         {
             // Should now be the only `Arc` pointing to this.
             let mut_token = Arc::get_mut(&mut token)
@@ -307,6 +559,7 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
             // Remove the old access token, insert the new.
             mut_token.access = new_access_key.clone();
             mut_token.refresh = Some(new_refresh_key.clone());
+            mut_token.refresh_until = refresh_until;
             mut_token.grant = grant;
         }
 
@@ -322,12 +575,22 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
         })
     }
 
-    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         Ok(self.access.get(token).map(|token| token.grant.clone()))
     }
 
-    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
-        Ok(self.refresh.get(token).map(|token| token.grant.clone()))
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        Ok(self.refresh.get(token).map(|token| token.refresh_grant()))
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // A refresh token takes its access token with it, but not the other way around.
+        if let Some(refreshable) = self.refresh.remove(token) {
+            self.access.remove(&refreshable.access);
+        } else {
+            self.access.remove(token);
+        }
+        Ok(())
     }
 }
 
@@ -336,11 +599,13 @@ impl<G: TagGrant> Issuer for TokenMap<G> {
 /// Although this token instance allows preservation of memory it also implies that tokens, once
 /// issued, are impossible to revoke.
 pub struct TokenSigner {
-    duration: Option<Duration>,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    clock: SharedClock,
     signer: Assertion,
     // FIXME: make this an AtomicU64 once stable.
     counter: AtomicUsize,
     have_refresh: bool,
+    offline_access: bool,
 }
 
 impl TokenSigner {
@@ -350,10 +615,12 @@ impl TokenSigner {
     /// a new key using a utility such as `openssl rand` that you then store away securely.
     pub fn new(secret: Assertion) -> TokenSigner {
         TokenSigner {
-            duration: None,
+            lifetimes: Box::new(Lifetimes::default()),
+            clock: clock::system(),
             signer: secret,
             counter: AtomicUsize::new(0),
             have_refresh: false,
+            offline_access: false,
         }
     }
 
@@ -372,9 +639,12 @@ impl TokenSigner {
     /// tokens issued for the authorization code grant method. For many users this may seem to
     /// short but should be secure-by-default. You may want to increase the duration, or instead
     /// use long lived refresh token instead (although you currently need to handle refresh tokens
-    /// yourself, coming soonish).
+    /// yourself, coming soonish). This replaces any previously set lifetime policy.
     pub fn valid_for(&mut self, duration: Duration) {
-        self.duration = Some(duration);
+        self.lifetime_policy(Lifetimes {
+            access: Some(duration),
+            ..Lifetimes::default()
+        });
     }
 
     /// Set all grants to be valid for their default duration.
@@ -382,7 +652,23 @@ impl TokenSigner {
     /// This only affects tokens issued after this call. The default duration is 1 (ONE) hour for
     /// tokens issued for the authorization code grant method.
     pub fn valid_for_default(&mut self) {
-        self.duration = None;
+        self.lifetime_policy(Lifetimes::default());
+    }
+
+    /// Decide the lifetimes of access and refresh tokens by a policy.
+    ///
+    /// Since signed tokens can not be revoked, a refresh token lifetime is the only way to end the
+    /// validity of refresh tokens, if they are generated.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Compute the expiry of issued tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
     }
 
     /// Determine whether to generate refresh tokens.
@@ -395,6 +681,14 @@ impl TokenSigner {
         self.have_refresh = refresh;
     }
 
+    /// Determine whether generated refresh tokens require the `offline_access` scope.
+    ///
+    /// By default, this option is *off*. When on, only grants including the `offline_access`
+    /// scope receive a refresh token, if refresh tokens are generated at all.
+    pub fn require_offline_access(&mut self, required: bool) {
+        self.offline_access = required;
+    }
+
     /// Get the next counter value.
     fn next_counter(&self) -> usize {
         // Acquire+Release is overkill. We only need to ensure that each return value occurs at
@@ -403,12 +697,25 @@ impl TokenSigner {
         self.counter.fetch_add(1, Ordering::Relaxed)
     }
 
-    fn refreshable_token(&self, grant: &Grant) -> Result<IssuedToken, ()> {
+    fn refreshable_token(
+        &self, grant: &Grant, refresh_until: Option<Time>,
+    ) -> Result<IssuedToken, PrimitiveError> {
         let first_ctr = self.next_counter() as u64;
         let second_ctr = self.next_counter() as u64;
 
-        let token = self.as_token().sign(first_ctr, grant)?;
-        let refresh = self.as_refresh().sign(second_ctr, grant)?;
+        let token = self
+            .as_token()
+            .sign(first_ctr, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
+        let refresh = match refresh_until {
+            Some(until) => {
+                let mut refresh_grant = grant.clone();
+                refresh_grant.until = until;
+                self.as_refresh().sign(second_ctr, &refresh_grant)
+            }
+            None => self.as_refresh().sign(second_ctr, grant),
+        }
+        .map_err(|()| PrimitiveError::Invariant)?;
 
         Ok(IssuedToken {
             token,
@@ -418,10 +725,13 @@ impl TokenSigner {
         })
     }
 
-    fn unrefreshable_token(&self, grant: &Grant) -> Result<IssuedToken, ()> {
+    fn unrefreshable_token(&self, grant: &Grant) -> Result<IssuedToken, PrimitiveError> {
         let counter = self.next_counter() as u64;
 
-        let token = self.as_token().sign(counter, grant)?;
+        let token = self
+            .as_token()
+            .sign(counter, grant)
+            .map_err(|()| PrimitiveError::Invariant)?;
 
         Ok(IssuedToken::without_refresh(token, grant.until))
     }
@@ -436,117 +746,133 @@ impl TokenSigner {
 }
 
 impl<'s, I: Issuer + ?Sized> Issuer for &'s mut I {
-    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
         (**self).issue(grant)
     }
 
-    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
         (**self).refresh(token, grant)
     }
 
-    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).recover_token(token)
     }
 
-    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).recover_refresh(token)
     }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        (**self).revoke(token)
+    }
 }
 
 impl<I: Issuer + ?Sized> Issuer for Box<I> {
-    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
         (**self).issue(grant)
     }
 
-    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
         (**self).refresh(token, grant)
     }
 
-    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).recover_token(token)
     }
 
-    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).recover_refresh(token)
     }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        (**self).revoke(token)
+    }
 }
 
 impl<'s, I: Issuer + ?Sized> Issuer for MutexGuard<'s, I> {
-    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
         (**self).issue(grant)
     }
 
-    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
         (**self).refresh(token, grant)
     }
 
-    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).recover_token(token)
     }
 
-    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).recover_refresh(token)
     }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        (**self).revoke(token)
+    }
 }
 
 impl<'s, I: Issuer + ?Sized> Issuer for RwLockWriteGuard<'s, I> {
-    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
         (**self).issue(grant)
     }
 
-    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, ()> {
+    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
         (**self).refresh(token, grant)
     }
 
-    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).recover_token(token)
     }
 
-    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (**self).recover_refresh(token)
     }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        (**self).revoke(token)
+    }
 }
 
 impl Issuer for TokenSigner {
-    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
         (&mut &*self).issue(grant)
     }
 
-    fn refresh(&mut self, _refresh: &str, _grant: Grant) -> Result<RefreshedToken, ()> {
-        Err(())
+    fn refresh(&mut self, _refresh: &str, _grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        Err(PrimitiveError::Invariant)
     }
 
-    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (&&*self).recover_token(token)
     }
 
-    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
         (&&*self).recover_refresh(token)
     }
 }
 
 impl<'a> Issuer for &'a TokenSigner {
-    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, ()> {
-        if let Some(duration) = &self.duration {
-            grant.until = Utc::now() + *duration;
-        }
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let now = self.clock.now();
+        let lifetimes = self.lifetimes.lifetimes(&grant);
+        let refresh_until = lifetimes.expire(&mut grant, lifetimes.session_until(now), now);
 
-        if self.have_refresh {
-            self.refreshable_token(&grant)
+        if self.have_refresh && (!self.offline_access || offline_access(&grant)) {
+            self.refreshable_token(&grant, refresh_until)
         } else {
             self.unrefreshable_token(&grant)
         }
     }
 
-    fn refresh(&mut self, _refresh: &str, _grant: Grant) -> Result<RefreshedToken, ()> {
-        Err(())
+    fn refresh(&mut self, _refresh: &str, _grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        Err(PrimitiveError::Invariant)
     }
 
-    fn recover_token<'t>(&'t self, token: &'t str) -> Result<Option<Grant>, ()> {
+    fn recover_token<'t>(&'t self, token: &'t str) -> Result<Option<Grant>, PrimitiveError> {
         Ok(self.as_token().extract(token).ok())
     }
 
-    fn recover_refresh<'t>(&'t self, token: &'t str) -> Result<Option<Grant>, ()> {
+    fn recover_refresh<'t>(&'t self, token: &'t str) -> Result<Option<Grant>, PrimitiveError> {
         if !self.have_refresh {
             return Ok(None);
         }
@@ -555,6 +881,13 @@ impl<'a> Issuer for &'a TokenSigner {
     }
 }
 
+/// Whether the grant includes the `offline_access` scope.
+///
+/// Issuers requiring the scope only attach a refresh token to such grants.
+pub fn offline_access(grant: &Grant) -> bool {
+    grant.scope.iter().any(|scope| scope == OFFLINE_ACCESS_SCOPE)
+}
+
 #[cfg(test)]
 /// Tests for issuer implementations, including those provided here.
 pub mod tests {
@@ -626,6 +959,25 @@ pub mod tests {
         simple_test_suite(&mut token_map);
     }
 
+    #[test]
+    fn token_map_revoke() {
+        let mut token_map = TokenMap::new(RandomGenerator::new(16));
+
+        let issued = token_map.issue(grant_template()).unwrap();
+        Issuer::revoke(&mut token_map, &issued.token).unwrap();
+        assert_eq!(token_map.recover_token(&issued.token), Ok(None));
+        let refresh = issued.refresh.unwrap();
+        assert!(token_map.recover_refresh(&refresh).unwrap().is_some());
+
+        let issued = token_map.issue(grant_template()).unwrap();
+        Issuer::revoke(&mut token_map, issued.refresh.as_ref().unwrap()).unwrap();
+        assert_eq!(token_map.recover_token(&issued.token), Ok(None));
+        assert_eq!(token_map.recover_refresh(&issued.refresh.unwrap()), Ok(None));
+
+        assert!(Issuer::revoke(&mut token_map, "unknown").is_ok());
+        assert!(Issuer::revoke(&mut TokenSigner::ephemeral(), "any").is_err());
+    }
+
     #[test]
     fn random_has_refresh() {
         let mut token_map = TokenMap::new(RandomGenerator::new(16));
@@ -652,6 +1004,134 @@ pub mod tests {
         assert!(refresh != new_refresh);
     }
 
+    #[test]
+    fn offline_access_required() {
+        let mut token_map = TokenMap::new(RandomGenerator::new(16));
+        token_map.require_offline_access(true);
+        let issued = token_map.issue(grant_template()).unwrap();
+        assert!(!issued.refreshable());
+        assert!(token_map.recover_token(&issued.token).unwrap().is_some());
+
+        let offline = Grant {
+            scope: "default offline_access".parse().unwrap(),
+            ..grant_template()
+        };
+        let issued = token_map.issue(offline.clone()).unwrap();
+        let refresh = issued.refresh.expect("No refresh token for offline access");
+        let refreshed = token_map.refresh(&refresh, offline).unwrap();
+        let refresh = refreshed.refresh.expect("No refresh token after refresh");
+
+        // Narrowing the scope on refresh gives up the refresh token.
+        let refreshed = token_map.refresh(&refresh, grant_template()).unwrap();
+        assert!(refreshed.refresh.is_none());
+        assert!(token_map.recover_refresh(&refresh).unwrap().is_none());
+        assert!(token_map.recover_token(&refreshed.token).unwrap().is_some());
+
+        let mut signer = TokenSigner::ephemeral();
+        signer.generate_refresh_tokens(true);
+        signer.require_offline_access(true);
+        assert!(!signer.issue(grant_template()).unwrap().refreshable());
+    }
+
+    #[test]
+    fn lifetime_rules() {
+        let short = Lifetimes {
+            access: Some(Duration::minutes(5)),
+            ..Lifetimes::default()
+        };
+        let long = Lifetimes {
+            access: Some(Duration::hours(10)),
+            refresh: Some(Duration::days(30)),
+            session: Some(Duration::days(90)),
+        };
+        let mut rules = LifetimeRules::new(Lifetimes {
+            access: Some(Duration::hours(1)),
+            refresh: Some(Duration::days(1)),
+            session: None,
+        });
+        rules
+            .client("Trusted", long)
+            .scope("admin".parse().unwrap(), short);
+
+        let mut grant = grant_template();
+        assert_eq!(rules.lifetimes(&grant).access, Some(Duration::hours(1)));
+        grant.client_id = "Trusted".to_owned();
+        assert_eq!(rules.lifetimes(&grant), long);
+        grant.scope = "default admin".parse().unwrap();
+        assert_eq!(
+            rules.lifetimes(&grant),
+            Lifetimes {
+                access: Some(Duration::minutes(5)),
+                refresh: Some(Duration::days(30)),
+                session: Some(Duration::days(90)),
+            }
+        );
+    }
+
+    #[test]
+    fn lifetime_policy_applied() {
+        let mut token_map = TokenMap::new(RandomGenerator::new(16));
+        token_map.lifetime_policy(|grant: &Grant| Lifetimes {
+            access: Some(Duration::minutes(if grant.owner_id == "Owner" { 5 } else { 60 })),
+            refresh: Some(Duration::minutes(-1)),
+            ..Lifetimes::default()
+        });
+
+        let issued = token_map.issue(grant_template()).unwrap();
+        assert!(issued.until <= Utc::now() + Duration::minutes(5));
+        let refresh = issued.refresh.unwrap();
+        let grant = token_map.recover_refresh(&refresh).unwrap().unwrap();
+        assert!(grant.until < Utc::now());
+
+        let mut signer = TokenSigner::ephemeral();
+        signer.generate_refresh_tokens(true);
+        signer.lifetime_policy(Lifetimes {
+            access: Some(Duration::minutes(5)),
+            refresh: Some(Duration::days(1)),
+            ..Lifetimes::default()
+        });
+        let issued = signer.issue(grant_template()).unwrap();
+        assert!(issued.until <= Utc::now() + Duration::minutes(5));
+        let grant = signer.recover_refresh(&issued.refresh.unwrap()).unwrap().unwrap();
+        assert!(grant.until > Utc::now() + Duration::hours(12));
+    }
+
+    #[test]
+    fn refresh_session_expiry() {
+        let mut token_map = TokenMap::new(RandomGenerator::new(16));
+        token_map.lifetime_policy(Lifetimes {
+            access: Some(Duration::hours(1)),
+            refresh: Some(Duration::days(14)),
+            session: Some(Duration::minutes(30)),
+        });
+
+        let issued = token_map.issue(grant_template()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        let session_end = token_map.recover_refresh(&refresh).unwrap().unwrap().until;
+        assert!(session_end <= Utc::now() + Duration::minutes(30));
+        assert_eq!(issued.until, session_end);
+
+        // Refreshing does not extend the session.
+        let refreshed = token_map.refresh(&refresh, grant_template()).unwrap();
+        let refresh = refreshed.refresh.unwrap();
+        assert_eq!(refreshed.until, session_end);
+        let grant = token_map.recover_refresh(&refresh).unwrap().unwrap();
+        assert_eq!(grant.until, session_end);
+
+        // Without a session, each refresh token lives for the sliding lifetime.
+        token_map.lifetime_policy(Lifetimes {
+            refresh: Some(Duration::days(14)),
+            ..Lifetimes::default()
+        });
+        let refresh = token_map.issue(grant_template()).unwrap().refresh.unwrap();
+        let refreshed = token_map.refresh(&refresh, grant_template()).unwrap();
+        let grant = token_map
+            .recover_refresh(&refreshed.refresh.unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(grant.until > Utc::now() + Duration::days(13));
+    }
+
     #[test]
     #[should_panic]
     fn bad_generator() {
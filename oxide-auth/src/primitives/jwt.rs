@@ -0,0 +1,1476 @@
+//! Signed JSON Web Tokens as access tokens.
+//!
+//! A [`JwtIssuer`] issues access tokens in the JWT profile of [RFC 9068]. Resource servers holding
+//! the public key of the issuer validate these tokens on their own, without introspection or a
+//! shared store. The issuer still keeps each grant in a backing issuer, whose opaque token becomes
+//! the `jti` of the JWT, so that refresh tokens and revocation keep working as before:
+//!
+//! ```
+//! # use oxide_auth::primitives::issuer::TokenMap;
+//! # use oxide_auth::primitives::generator::RandomGenerator;
+//! use oxide_auth::primitives::jwt::{JwtIssuer, SigningKey};
+//!
+//! # let pkcs8 = SigningKey::generate_ed25519().unwrap();
+//! let key = SigningKey::ed25519(&pkcs8).unwrap().with_kid("2024-01");
+//! let tokens = TokenMap::new(RandomGenerator::new(16));
+//! let mut issuer = JwtIssuer::new(key, "https://auth.example.com", tokens);
+//! issuer.audience("https://api.example.com");
+//! ```
+//!
+//! Only available with the `jwt` feature.
+//!
+//! [`JwtIssuer`]: struct.JwtIssuer.html
+//! [RFC 9068]: https://tools.ietf.org/html/rfc9068
+use chrono::{Duration, TimeZone, Utc};
+use ring::{digest, hmac};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{self, EcdsaKeyPair, Ed25519KeyPair, KeyPair as _, RsaKeyPair};
+use ring::signature::{RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use serde_json::{Map, Value};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::code_grant::extensions::{attach_claims, unreserved, CLAIMS_EXTENSION};
+use super::clock::{self, Clock, SharedClock};
+use super::error::PrimitiveError;
+use super::grant::{Extensions, Grant, Value as ExtensionValue};
+use super::issuer::{IssuedToken, Issuer, LifetimePolicy, Lifetimes, RefreshedToken, TokenType};
+use super::keystore::KeyStore;
+use super::scope::Scope;
+
+/// The name of the HMAC SHA-256 algorithm of tokens signed with a shared secret.
+pub const HMAC_ALGORITHM: &str = "HS256";
+
+/// The signature algorithms of signing keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// RSASSA-PKCS1-v1_5 using SHA-256.
+    RS256,
+
+    /// ECDSA using P-256 and SHA-256.
+    ES256,
+
+    /// Edwards-curve signatures using Ed25519.
+    EdDSA,
+}
+
+/// A private key signing tokens.
+///
+/// Keys are read from their PKCS#8 document in DER encoding.
+pub struct SigningKey {
+    algorithm: Algorithm,
+    kid: Option<String>,
+    pair: KeyPair,
+    rng: SystemRandom,
+}
+
+enum KeyPair {
+    Rsa(RsaKeyPair),
+    Ecdsa(EcdsaKeyPair),
+    Ed25519(Ed25519KeyPair),
+}
+
+/// The header of a signed token.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Header {
+    /// The name of the signature algorithm.
+    pub alg: String,
+
+    /// The media type of the token, `at+jwt` for access tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+
+    /// The identifier of the signing key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+
+    /// The public key of the signer, embedded in self-signed tokens such as DPoP proofs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwk: Option<Jwk>,
+}
+
+/// A public key in the JSON Web Key format of [RFC 7517].
+///
+/// Only the members of RSA, P-256 and Ed25519 keys are supported.
+///
+/// [RFC 7517]: https://tools.ietf.org/html/rfc7517
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Jwk {
+    /// The key type, `RSA`, `EC` or `OKP`.
+    pub kty: String,
+
+    /// The curve of `EC` and `OKP` keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+
+    /// The x coordinate of `EC` keys or the public key of `OKP` keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+
+    /// The y coordinate of `EC` keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+
+    /// The modulus of `RSA` keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+
+    /// The public exponent of `RSA` keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+
+    /// The identifier of the key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+}
+
+/// A JSON Web Key Set, the `jwks` document of the keys of a party.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct JwkSet {
+    /// The keys of the set.
+    pub keys: Vec<Jwk>,
+}
+
+/// The claims of a JWT access token.
+///
+/// See [RFC 9068, Section 2.2](https://tools.ietf.org/html/rfc9068#section-2.2).
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AccessTokenClaims {
+    /// The issuer identifier of the authorization server.
+    pub iss: String,
+
+    /// The resource owner who authorized the token.
+    pub sub: String,
+
+    /// The resource server the token is intended for.
+    pub aud: String,
+
+    /// The expiry as seconds since the unix epoch.
+    pub exp: i64,
+
+    /// The time of issuance as seconds since the unix epoch.
+    pub iat: i64,
+
+    /// A unique identifier of the token.
+    pub jti: String,
+
+    /// The client the token was issued to.
+    pub client_id: String,
+
+    /// The space separated scopes of the token.
+    pub scope: String,
+
+    /// The custom claims of the grant, such as the roles of the owner.
+    #[serde(flatten)]
+    pub claims: Map<String, Value>,
+}
+
+/// Issues signed JWT access tokens for grants stored in another issuer.
+///
+/// The `jti` of each token is the access token of the backing issuer, which recovers the grant of
+/// a valid token. Refresh tokens are those of the backing issuer. The `aud` claim is the configured
+/// audience or, if there is none, the id of the client. Tokens are signed with the current key of
+/// a `KeyStore` and remain valid after a rotation while the previous key is still published.
+pub struct JwtIssuer<I: Issuer> {
+    inner: I,
+    keys: KeyStore,
+    issuer: String,
+    audience: Option<String>,
+    clock: SharedClock,
+}
+
+/// Issues signed, self-contained JWT access tokens without storing them.
+///
+/// The whole grant is part of the claims of each token, so that recovering a token only verifies
+/// its signature, issuer, audience and expiry. This suits horizontally scaled deployments where
+/// resource servers should not share a token store. Private grant extensions can not be issued,
+/// since the claims are only signed and not encrypted, and access tokens can not be revoked before
+/// they expire. Revoking one succeeds without effect, as RFC 7009 permits for tokens that expire on
+/// their own.
+///
+/// Refresh tokens are only issued with a store for them, which keeps the grants to refresh. The
+/// lifetime policy of that store then decides the expiry of all tokens. Without such a store the
+/// lifetime policy of this issuer decides the expiry of the access tokens.
+pub struct StatelessIssuer {
+    keys: KeyStore,
+    issuer: String,
+    audience: Option<String>,
+    lifetimes: Box<dyn LifetimePolicy + Send + Sync>,
+    refresh: Option<Box<dyn Issuer + Send + Sync>>,
+    clock: SharedClock,
+    leeway: Duration,
+    rng: SystemRandom,
+}
+
+/// The claims of access tokens that custom claims of a grant can not replace.
+const RESERVED_CLAIMS: &[&str] = &[
+    "iss",
+    "sub",
+    "aud",
+    "exp",
+    "nbf",
+    "iat",
+    "jti",
+    "client_id",
+    "scope",
+    "cnf",
+    "redirect_uri",
+    "ext",
+];
+
+/// The claims of the tokens of the `StatelessIssuer`, carrying the rest of the grant.
+#[derive(Deserialize, Serialize)]
+struct StatelessClaims {
+    #[serde(flatten)]
+    token: AccessTokenClaims,
+    redirect_uri: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    ext: HashMap<String, Option<String>>,
+}
+
+/// Signs authorization responses in the JWT Secured Authorization Response Mode (JARM).
+///
+/// The parameters of a response are the claims of a JWT, together with the issuer `iss`, the
+/// client as its audience `aud` and a short expiry `exp`. Clients verify the signature with the
+/// public key of the authorization server.
+pub struct ResponseSigner {
+    keys: KeyStore,
+    issuer: String,
+    lifetime: Duration,
+    clock: SharedClock,
+}
+
+/// The claims of an OpenID Connect ID token.
+///
+/// See [OpenID Connect Core, Section 2](https://openid.net/specs/openid-connect-core-1_0.html#IDToken).
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct IdTokenClaims {
+    /// The issuer identifier of the authorization server.
+    pub iss: String,
+
+    /// The end-user who authenticated.
+    pub sub: String,
+
+    /// The client the token was issued to.
+    pub aud: String,
+
+    /// The expiry as seconds since the unix epoch.
+    pub exp: i64,
+
+    /// The time of issuance as seconds since the unix epoch.
+    pub iat: i64,
+
+    /// The time of the authentication as seconds since the unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>,
+
+    /// The `nonce` of the authentication request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+
+    /// The authentication context class of the authentication.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<String>,
+
+    /// The claims about the end-user, such as `name` or `email`.
+    #[serde(flatten)]
+    pub claims: Map<String, Value>,
+}
+
+/// Provides the claims about an end-user for ID tokens.
+pub trait ClaimsProvider {
+    /// The claims about the owner that the client may learn with the granted scope.
+    ///
+    /// Claims named like one of the registered claims of an ID token are ignored.
+    fn claims(&self, owner_id: &str, client_id: &str, scope: &Scope) -> Result<Map<String, Value>, ()>;
+}
+
+/// Signs the ID tokens of OpenID Connect grants.
+///
+/// The `sub` of a token is the owner of the grant, or its pairwise identifier if the signer has a
+/// `Pairwise`, and its `aud` the client. Further claims about the owner are those of the
+/// `ClaimsProvider`, if there is one.
+pub struct IdTokenSigner {
+    keys: KeyStore,
+    issuer: String,
+    lifetime: Duration,
+    provider: Option<Box<dyn ClaimsProvider + Send + Sync>>,
+    pairwise: Option<Pairwise>,
+    clock: SharedClock,
+}
+
+/// Derives pairwise subject identifiers, so that clients can not correlate owners.
+///
+/// The pairwise `sub` of an owner is the hash of the sector identifier of the client, the owner
+/// and a secret salt, as described in [OpenID Connect Core, Section 8.1]. Clients registered with
+/// the same sector identifier, usually the host of their `sector_identifier_uri`, see the same
+/// subject, while a client without a registered sector is a sector of its own. The salt must stay
+/// the same for subjects to be stable.
+///
+/// Subjects can not be mapped back to the owner. Hints of a logout must name the session of a
+/// pairwise owner with their `sid` claim.
+///
+/// [OpenID Connect Core, Section 8.1]: https://openid.net/specs/openid-connect-core-1_0.html#PairwiseAlg
+#[derive(Clone, Debug)]
+pub struct Pairwise {
+    salt: Vec<u8>,
+    sectors: HashMap<String, String>,
+}
+
+impl Algorithm {
+    /// The name of the algorithm in the `alg` header.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::RS256 => "RS256",
+            Algorithm::ES256 => "ES256",
+            Algorithm::EdDSA => "EdDSA",
+        }
+    }
+
+    fn verification(self) -> &'static dyn signature::VerificationAlgorithm {
+        match self {
+            Algorithm::RS256 => &signature::RSA_PKCS1_2048_8192_SHA256,
+            Algorithm::ES256 => &signature::ECDSA_P256_SHA256_FIXED,
+            Algorithm::EdDSA => &signature::ED25519,
+        }
+    }
+}
+
+impl SigningKey {
+    /// An `RS256` key from an RSA private key.
+    pub fn rs256(pkcs8: &[u8]) -> Result<Self, ()> {
+        let pair = RsaKeyPair::from_pkcs8(pkcs8).map_err(|_| ())?;
+        Ok(SigningKey::with_pair(Algorithm::RS256, KeyPair::Rsa(pair)))
+    }
+
+    /// An `ES256` key from a P-256 private key.
+    pub fn es256(pkcs8: &[u8]) -> Result<Self, ()> {
+        let rng = SystemRandom::new();
+        let pair = EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &rng)
+            .map_err(|_| ())?;
+        Ok(SigningKey::with_pair(Algorithm::ES256, KeyPair::Ecdsa(pair)))
+    }
+
+    /// An `EdDSA` key from an Ed25519 private key.
+    pub fn ed25519(pkcs8: &[u8]) -> Result<Self, ()> {
+        let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8).map_err(|_| ())?;
+        Ok(SigningKey::with_pair(Algorithm::EdDSA, KeyPair::Ed25519(pair)))
+    }
+
+    /// Generate the PKCS#8 document of a new P-256 private key.
+    pub fn generate_es256() -> Result<Vec<u8>, ()> {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .map_err(|_| ())?;
+        Ok(pkcs8.as_ref().to_vec())
+    }
+
+    /// Generate the PKCS#8 document of a new Ed25519 private key.
+    pub fn generate_ed25519() -> Result<Vec<u8>, ()> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(|_| ())?;
+        Ok(pkcs8.as_ref().to_vec())
+    }
+
+    fn with_pair(algorithm: Algorithm, pair: KeyPair) -> Self {
+        SigningKey {
+            algorithm,
+            kid: None,
+            pair,
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Announce the key id in the header of signed tokens.
+    pub fn with_kid(mut self, kid: &str) -> Self {
+        self.kid = Some(kid.to_owned());
+        self
+    }
+
+    /// The signature algorithm of the key.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The key id, if one was assigned.
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    /// The public key, in the encoding `ring` verifies signatures with.
+    ///
+    /// This is the `RSAPublicKey` structure for RSA keys, the uncompressed point for P-256 keys and
+    /// the raw key for Ed25519 keys.
+    pub fn public_key(&self) -> &[u8] {
+        match &self.pair {
+            KeyPair::Rsa(pair) => pair.public_key().as_ref(),
+            KeyPair::Ecdsa(pair) => pair.public_key().as_ref(),
+            KeyPair::Ed25519(pair) => pair.public_key().as_ref(),
+        }
+    }
+
+    /// The public key as a JWK, carrying the key id.
+    pub fn jwk(&self) -> Jwk {
+        let mut jwk = match &self.pair {
+            KeyPair::Rsa(pair) => {
+                let components = RsaPublicKeyComponents::<Vec<u8>>::from(pair.public());
+                Jwk {
+                    kty: "RSA".to_owned(),
+                    n: Some(encode(&components.n)),
+                    e: Some(encode(&components.e)),
+                    ..Jwk::default()
+                }
+            }
+            KeyPair::Ecdsa(pair) => {
+                // The uncompressed point, a tag byte followed by both coordinates.
+                let point = pair.public_key().as_ref();
+                Jwk {
+                    kty: "EC".to_owned(),
+                    crv: Some("P-256".to_owned()),
+                    x: Some(encode(&point[1..33])),
+                    y: Some(encode(&point[33..])),
+                    ..Jwk::default()
+                }
+            }
+            KeyPair::Ed25519(pair) => Jwk {
+                kty: "OKP".to_owned(),
+                crv: Some("Ed25519".to_owned()),
+                x: Some(encode(pair.public_key().as_ref())),
+                ..Jwk::default()
+            },
+        };
+        jwk.kid = self.kid.clone();
+        jwk
+    }
+
+    /// Sign the claims as a compact JWS of the given media type.
+    pub fn sign<C: Serialize>(&self, typ: &str, claims: &C) -> Result<String, ()> {
+        self.sign_with(typ, None, claims)
+    }
+
+    /// Sign the claims with the public key embedded as `jwk` in the header.
+    ///
+    /// This is how DPoP proofs are signed, whose key is not known to the recipient in advance.
+    pub fn sign_with_jwk<C: Serialize>(&self, typ: &str, claims: &C) -> Result<String, ()> {
+        self.sign_with(typ, Some(self.jwk()), claims)
+    }
+
+    fn sign_with<C: Serialize>(&self, typ: &str, jwk: Option<Jwk>, claims: &C) -> Result<String, ()> {
+        let header = Header {
+            alg: self.algorithm.name().to_owned(),
+            typ: Some(typ.to_owned()),
+            kid: self.kid.clone(),
+            jwk,
+        };
+        let header = serde_json::to_vec(&header).map_err(|_| ())?;
+        let claims = serde_json::to_vec(claims).map_err(|_| ())?;
+        let signing_input = format!("{}.{}", encode(&header), encode(&claims));
+
+        let signature = match &self.pair {
+            KeyPair::Rsa(pair) => {
+                let mut signature = vec![0; pair.public().modulus_len()];
+                pair.sign(
+                    &signature::RSA_PKCS1_SHA256,
+                    &self.rng,
+                    signing_input.as_bytes(),
+                    &mut signature,
+                )
+                .map_err(|_| ())?;
+                signature
+            }
+            KeyPair::Ecdsa(pair) => pair
+                .sign(&self.rng, signing_input.as_bytes())
+                .map_err(|_| ())?
+                .as_ref()
+                .to_vec(),
+            KeyPair::Ed25519(pair) => pair.sign(signing_input.as_bytes()).as_ref().to_vec(),
+        };
+
+        Ok(format!("{}.{}", signing_input, encode(&signature)))
+    }
+
+    /// Verify a token signed with this key and return its claims.
+    ///
+    /// Only the signature is checked, the claims must be validated by the caller.
+    pub fn verify<C: DeserializeOwned>(&self, token: &str) -> Result<C, ()> {
+        verify(self.algorithm, self.public_key(), token)
+    }
+}
+
+/// Verify a compact JWS with a public key and return its claims.
+///
+/// The `alg` of the header must be that of the key. Only the signature is checked, the claims must
+/// be validated by the caller.
+pub fn verify<C: DeserializeOwned>(
+    algorithm: Algorithm, public_key: &[u8], token: &str,
+) -> Result<C, ()> {
+    verify_with(algorithm.name(), token, |message, signature| {
+        UnparsedPublicKey::new(algorithm.verification(), public_key)
+            .verify(message, signature)
+            .map_err(|_| ())
+    })
+}
+
+/// Sign the claims as a compact JWS with HMAC SHA-256, `HS256`, using a shared secret.
+pub fn sign_hmac<C: Serialize>(secret: &[u8], typ: &str, claims: &C) -> Result<String, ()> {
+    let header = Header {
+        alg: HMAC_ALGORITHM.to_owned(),
+        typ: Some(typ.to_owned()),
+        kid: None,
+        jwk: None,
+    };
+    let header = serde_json::to_vec(&header).map_err(|_| ())?;
+    let claims = serde_json::to_vec(claims).map_err(|_| ())?;
+    let signing_input = format!("{}.{}", encode(&header), encode(&claims));
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let signature = hmac::sign(&key, signing_input.as_bytes());
+    Ok(format!("{}.{}", signing_input, encode(signature.as_ref())))
+}
+
+/// Verify a compact JWS signed with HMAC SHA-256, `HS256`, and return its claims.
+///
+/// Only the signature is checked, the claims must be validated by the caller.
+pub fn verify_hmac<C: DeserializeOwned>(secret: &[u8], token: &str) -> Result<C, ()> {
+    verify_with(HMAC_ALGORITHM, token, |message, signature| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+        hmac::verify(&key, message, signature).map_err(|_| ())
+    })
+}
+
+/// Decode the header of a compact JWS without verifying its signature.
+pub fn header(token: &str) -> Result<Header, ()> {
+    let (header, _) = token.split_once('.').ok_or(())?;
+    serde_json::from_slice(&decode(header)?).map_err(|_| ())
+}
+
+/// Decode the claims of a compact JWS without verifying its signature.
+///
+/// The claims can not be trusted, this is only useful to find the key for verifying the token.
+pub fn unverified_claims<C: DeserializeOwned>(token: &str) -> Result<C, ()> {
+    let mut parts = token.split('.');
+    let claims = parts.nth(1).ok_or(())?;
+    serde_json::from_slice(&decode(claims)?).map_err(|_| ())
+}
+
+fn verify_with<C, F>(alg: &str, token: &str, check: F) -> Result<C, ()>
+where
+    C: DeserializeOwned,
+    F: FnOnce(&[u8], &[u8]) -> Result<(), ()>,
+{
+    let (signing_input, signature) = token.rsplit_once('.').ok_or(())?;
+    let (_, claims) = signing_input.split_once('.').ok_or(())?;
+
+    if header(token)?.alg != alg {
+        return Err(());
+    }
+
+    check(signing_input.as_bytes(), &decode(signature)?)?;
+    serde_json::from_slice(&decode(claims)?).map_err(|_| ())
+}
+
+/// A store of only the key.
+fn single(key: SigningKey) -> KeyStore {
+    let keys = KeyStore::new();
+    // The thumbprint of the public key of a signing key is always defined.
+    let _ = keys.insert(key);
+    keys
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(encoded: &str) -> Result<Vec<u8>, ()> {
+    base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).map_err(|_| ())
+}
+
+/// A member of a JWK, which must be base64url encoded.
+fn member(value: &Option<String>) -> Result<&str, ()> {
+    let value = value.as_deref().ok_or(())?;
+    decode(value)?;
+    Ok(value)
+}
+
+impl Jwk {
+    /// The signature algorithm of the key, if it is supported.
+    pub fn algorithm(&self) -> Result<Algorithm, ()> {
+        match (self.kty.as_str(), self.crv.as_deref()) {
+            ("RSA", None) => Ok(Algorithm::RS256),
+            ("EC", Some("P-256")) => Ok(Algorithm::ES256),
+            ("OKP", Some("Ed25519")) => Ok(Algorithm::EdDSA),
+            _ => Err(()),
+        }
+    }
+
+    /// The base64url encoded SHA-256 thumbprint of [RFC 7638].
+    ///
+    /// [RFC 7638]: https://tools.ietf.org/html/rfc7638
+    pub fn thumbprint(&self) -> Result<String, ()> {
+        // The required members in lexicographic order, members are checked to be plain base64url.
+        let required = match self.algorithm()? {
+            Algorithm::RS256 => format!(
+                r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+                member(&self.e)?,
+                member(&self.n)?
+            ),
+            Algorithm::ES256 => format!(
+                r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+                member(&self.x)?,
+                member(&self.y)?
+            ),
+            Algorithm::EdDSA => format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, member(&self.x)?),
+        };
+        Ok(encode(
+            digest::digest(&digest::SHA256, required.as_bytes()).as_ref(),
+        ))
+    }
+
+    /// Verify a token signed with this key and return its claims.
+    ///
+    /// Only the signature is checked, the claims must be validated by the caller.
+    pub fn verify<C: DeserializeOwned>(&self, token: &str) -> Result<C, ()> {
+        let algorithm = self.algorithm()?;
+        verify_with(algorithm.name(), token, |message, signature| match algorithm {
+            Algorithm::RS256 => RsaPublicKeyComponents {
+                n: decode(member(&self.n)?)?,
+                e: decode(member(&self.e)?)?,
+            }
+            .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature)
+            .map_err(|_| ()),
+            Algorithm::ES256 => {
+                let mut point = vec![4];
+                point.extend(decode(member(&self.x)?)?);
+                point.extend(decode(member(&self.y)?)?);
+                UnparsedPublicKey::new(algorithm.verification(), point)
+                    .verify(message, signature)
+                    .map_err(|_| ())
+            }
+            Algorithm::EdDSA => {
+                UnparsedPublicKey::new(algorithm.verification(), decode(member(&self.x)?)?)
+                    .verify(message, signature)
+                    .map_err(|_| ())
+            }
+        })
+    }
+}
+
+impl JwkSet {
+    /// Parse a set from its JSON document.
+    pub fn from_json(jwks: &str) -> Result<Self, ()> {
+        serde_json::from_str(jwks).map_err(|_| ())
+    }
+
+    /// Verify a token signed with one of the keys and return its claims.
+    ///
+    /// A token naming a `kid` in its header is only checked with the key of that id, other tokens
+    /// with every key of the set. Only the signature is checked, the claims must be validated by
+    /// the caller.
+    pub fn verify<C: DeserializeOwned>(&self, token: &str) -> Result<C, ()> {
+        let kid = header(token)?.kid;
+        let mut keys = self.keys.iter().filter(|key| kid.is_none() || key.kid == kid);
+        keys.find_map(|key| key.verify(token).ok()).ok_or(())
+    }
+}
+
+impl<I: Issuer> JwtIssuer<I> {
+    /// Sign tokens with the key as the issuer `iss`, keeping grants in `inner`.
+    ///
+    /// The key is the only key of a new store, and receives a key id if it has none.
+    pub fn new(key: SigningKey, iss: &str, inner: I) -> Self {
+        JwtIssuer::with_keys(single(key), iss, inner)
+    }
+
+    /// Sign tokens with the current key of the store as the issuer `iss`, keeping grants in `inner`.
+    pub fn with_keys(keys: KeyStore, iss: &str, inner: I) -> Self {
+        JwtIssuer {
+            inner,
+            keys,
+            issuer: iss.to_owned(),
+            audience: None,
+            clock: clock::system(),
+        }
+    }
+
+    /// Set the `aud` claim of all tokens issued after this call.
+    pub fn audience(&mut self, audience: &str) {
+        self.audience = Some(audience.to_owned());
+    }
+
+    /// Take the time of issuance `iat` of tokens from the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
+    }
+
+    /// The key currently signing the access tokens.
+    pub fn signing_key(&self) -> Option<Arc<SigningKey>> {
+        self.keys.current()
+    }
+
+    /// The store of the keys signing the access tokens.
+    pub fn keys(&self) -> &KeyStore {
+        &self.keys
+    }
+
+    /// Verify the signature and issuer of an access token and return its claims.
+    pub fn claims(&self, token: &str) -> Option<AccessTokenClaims> {
+        let claims: AccessTokenClaims = self.keys.verify(token).ok()?;
+        if claims.iss != self.issuer {
+            return None;
+        }
+        Some(claims)
+    }
+
+    fn sign(&self, grant: &Grant, jti: &str, until: i64) -> Result<String, ()> {
+        let claims = AccessTokenClaims {
+            iss: self.issuer.clone(),
+            sub: grant.owner_id.clone(),
+            aud: self.audience.clone().unwrap_or_else(|| grant.client_id.clone()),
+            exp: until,
+            iat: self.clock.now().timestamp(),
+            jti: jti.to_owned(),
+            client_id: grant.client_id.clone(),
+            scope: grant.scope.to_string(),
+            claims: unreserved(&grant.extensions, RESERVED_CLAIMS),
+        };
+        self.keys.current().ok_or(())?.sign("at+jwt", &claims)
+    }
+}
+
+impl StatelessIssuer {
+    /// Sign tokens with the current key of the store as the issuer `iss`.
+    ///
+    /// The tokens keep the expiry of their grant and no refresh tokens are issued.
+    pub fn new(keys: KeyStore, iss: &str) -> Self {
+        StatelessIssuer {
+            keys,
+            issuer: iss.to_owned(),
+            audience: None,
+            lifetimes: Box::new(Lifetimes::default()),
+            refresh: None,
+            clock: clock::system(),
+            leeway: Duration::zero(),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Set the `aud` claim of all tokens issued after this call.
+    ///
+    /// Only tokens for this audience are recovered afterwards, while without an audience only
+    /// tokens issued for their client are.
+    pub fn audience(&mut self, audience: &str) {
+        self.audience = Some(audience.to_owned());
+    }
+
+    /// Decide the lifetimes of access tokens by a policy, when refresh tokens are not issued.
+    pub fn lifetime_policy<P>(&mut self, policy: P)
+    where
+        P: LifetimePolicy + Send + Sync + 'static,
+    {
+        self.lifetimes = Box::new(policy);
+    }
+
+    /// Issue refresh tokens of the store alongside the signed access tokens.
+    ///
+    /// The store only needs to keep grants for its refresh tokens, the access tokens it creates in
+    /// the process are never handed out.
+    pub fn refresh_tokens<I>(&mut self, store: I)
+    where
+        I: Issuer + Send + Sync + 'static,
+    {
+        self.refresh = Some(Box::new(store));
+    }
+
+    /// Check the expiry of tokens and compute lifetimes from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
+    }
+
+    /// Accept tokens for a while after their expiry, for resource servers with skewed clocks.
+    ///
+    /// Recovered grants expire with the leeway added, so that flows accept them for as long.
+    pub fn leeway(&mut self, leeway: Duration) {
+        self.leeway = leeway;
+    }
+
+    /// The store of the keys signing the access tokens.
+    pub fn keys(&self) -> &KeyStore {
+        &self.keys
+    }
+
+    /// Verify the signature, issuer, audience and expiry of an access token and return its claims.
+    pub fn claims(&self, token: &str) -> Option<AccessTokenClaims> {
+        self.verified(token).map(|claims| claims.token)
+    }
+
+    fn verified(&self, token: &str) -> Option<StatelessClaims> {
+        let claims: StatelessClaims = self.keys.verify(token).ok()?;
+        // Tokens are issued for the client itself when there is no configured audience.
+        let audience = self.audience.as_ref().unwrap_or(&claims.token.client_id);
+        if claims.token.iss != self.issuer
+            || claims.token.aud != *audience
+            || claims.token.exp + self.leeway.num_seconds() <= self.clock.now().timestamp()
+        {
+            return None;
+        }
+        Some(claims)
+    }
+
+    fn sign(&self, grant: &Grant) -> Result<String, ()> {
+        if grant.extensions.private().any(|_| true) {
+            return Err(());
+        }
+
+        let mut jti = [0; 16];
+        self.rng.fill(&mut jti).map_err(|_| ())?;
+        let claims = StatelessClaims {
+            token: AccessTokenClaims {
+                iss: self.issuer.clone(),
+                sub: grant.owner_id.clone(),
+                aud: self.audience.clone().unwrap_or_else(|| grant.client_id.clone()),
+                exp: grant.until.timestamp(),
+                iat: self.clock.now().timestamp(),
+                jti: encode(&jti),
+                client_id: grant.client_id.clone(),
+                scope: grant.scope.to_string(),
+                claims: unreserved(&grant.extensions, RESERVED_CLAIMS),
+            },
+            redirect_uri: grant.redirect_uri.to_string(),
+            // Custom claims are already claims of the token itself.
+            ext: grant
+                .extensions
+                .public()
+                .filter(|(name, _)| *name != CLAIMS_EXTENSION)
+                .map(|(name, content)| (name.to_owned(), content.map(str::to_owned)))
+                .collect(),
+        };
+        self.keys.current().ok_or(())?.sign("at+jwt", &claims)
+    }
+}
+
+impl ResponseSigner {
+    /// Sign responses with the key as the issuer `iss`, valid for five minutes.
+    ///
+    /// The key is the only key of a new store, and receives a key id if it has none.
+    pub fn new(key: SigningKey, iss: &str) -> Self {
+        ResponseSigner::with_keys(single(key), iss)
+    }
+
+    /// Sign responses with the current key of the store as the issuer `iss`.
+    pub fn with_keys(keys: KeyStore, iss: &str) -> Self {
+        ResponseSigner {
+            keys,
+            issuer: iss.to_owned(),
+            lifetime: Duration::minutes(5),
+            clock: clock::system(),
+        }
+    }
+
+    /// Set the lifetime of responses signed after this call.
+    pub fn valid_for(&mut self, lifetime: Duration) {
+        self.lifetime = lifetime;
+    }
+
+    /// Compute the expiry `exp` of responses from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
+    }
+
+    /// The key currently signing the responses.
+    pub fn signing_key(&self) -> Option<Arc<SigningKey>> {
+        self.keys.current()
+    }
+
+    /// The store of the keys signing the responses.
+    pub fn keys(&self) -> &KeyStore {
+        &self.keys
+    }
+
+    /// Sign the parameters of a response to the client.
+    pub fn sign(&self, client_id: &str, parameters: &[(String, String)]) -> Result<String, ()> {
+        let mut claims: serde_json::Map<_, _> = parameters
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone().into()))
+            .collect();
+        claims.insert("iss".to_owned(), self.issuer.clone().into());
+        claims.insert("aud".to_owned(), client_id.into());
+        let exp = (self.clock.now() + self.lifetime).timestamp();
+        claims.insert("exp".to_owned(), exp.into());
+        self.keys.current().ok_or(())?.sign("JWT", &claims)
+    }
+}
+
+impl IdTokenSigner {
+    /// Sign ID tokens with the key as the issuer `iss`, valid for one hour.
+    ///
+    /// The key is the only key of a new store, and receives a key id if it has none.
+    pub fn new(key: SigningKey, iss: &str) -> Self {
+        IdTokenSigner::with_keys(single(key), iss)
+    }
+
+    /// Sign ID tokens with the current key of the store as the issuer `iss`.
+    pub fn with_keys(keys: KeyStore, iss: &str) -> Self {
+        IdTokenSigner {
+            keys,
+            issuer: iss.to_owned(),
+            lifetime: Duration::hours(1),
+            provider: None,
+            pairwise: None,
+            clock: clock::system(),
+        }
+    }
+
+    /// Set the lifetime of tokens signed after this call.
+    pub fn valid_for(&mut self, lifetime: Duration) {
+        self.lifetime = lifetime;
+    }
+
+    /// Compute the issuance `iat` and expiry `exp` of tokens from the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
+    }
+
+    /// Add the claims of the provider to tokens signed after this call.
+    pub fn claims_provider<P>(&mut self, provider: P)
+    where
+        P: ClaimsProvider + Send + Sync + 'static,
+    {
+        self.provider = Some(Box::new(provider));
+    }
+
+    /// Use pairwise subject identifiers in tokens signed after this call.
+    pub fn pairwise(&mut self, pairwise: Pairwise) {
+        self.pairwise = Some(pairwise);
+    }
+
+    /// The `sub` of the owner in tokens for the client.
+    pub fn subject(&self, owner_id: &str, client_id: &str) -> String {
+        match &self.pairwise {
+            Some(pairwise) => pairwise.subject(owner_id, client_id),
+            None => owner_id.to_owned(),
+        }
+    }
+
+    /// The key currently signing the tokens.
+    pub fn signing_key(&self) -> Option<Arc<SigningKey>> {
+        self.keys.current()
+    }
+
+    /// The store of the keys signing the tokens.
+    pub fn keys(&self) -> &KeyStore {
+        &self.keys
+    }
+
+    /// The issuer `iss` of the tokens.
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The claims of an ID token for the grant.
+    ///
+    /// The claims of the authentication itself, `auth_time`, `nonce` and `acr`, are left empty.
+    pub fn claims(&self, grant: &Grant) -> Result<IdTokenClaims, ()> {
+        let mut claims = match &self.provider {
+            Some(provider) => provider.claims(&grant.owner_id, &grant.client_id, &grant.scope)?,
+            None => Map::new(),
+        };
+        for registered in &["iss", "sub", "aud", "exp", "iat", "auth_time", "nonce", "acr"] {
+            claims.remove(*registered);
+        }
+
+        let now = self.clock.now();
+        Ok(IdTokenClaims {
+            iss: self.issuer.clone(),
+            sub: self.subject(&grant.owner_id, &grant.client_id),
+            aud: grant.client_id.clone(),
+            exp: (now + self.lifetime).timestamp(),
+            iat: now.timestamp(),
+            auth_time: None,
+            nonce: None,
+            acr: None,
+            claims,
+        })
+    }
+
+    /// Sign the claims of an ID token.
+    pub fn sign(&self, claims: &IdTokenClaims) -> Result<String, ()> {
+        self.keys.current().ok_or(())?.sign("JWT", claims)
+    }
+}
+
+impl Pairwise {
+    /// Derive subjects with the secret salt.
+    pub fn new(salt: &[u8]) -> Self {
+        Pairwise {
+            salt: salt.to_vec(),
+            sectors: HashMap::new(),
+        }
+    }
+
+    /// Register the sector identifier of a client.
+    pub fn sector(&mut self, client_id: &str, sector_identifier: &str) {
+        self.sectors
+            .insert(client_id.to_owned(), sector_identifier.to_owned());
+    }
+
+    /// The pairwise subject of the owner for the client.
+    pub fn subject(&self, owner_id: &str, client_id: &str) -> String {
+        let sector = self.sectors.get(client_id).map_or(client_id, String::as_str);
+        let mut context = digest::Context::new(&digest::SHA256);
+        context.update(sector.as_bytes());
+        context.update(owner_id.as_bytes());
+        context.update(&self.salt);
+        encode(context.finish().as_ref())
+    }
+}
+
+impl<I: Issuer> Issuer for JwtIssuer<I> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let mut issued = self.inner.issue(grant.clone())?;
+        issued.token = self
+            .sign(&grant, &issued.token, issued.until.timestamp())
+            .map_err(|()| PrimitiveError::Invariant)?;
+        Ok(issued)
+    }
+
+    fn refresh(&mut self, refresh: &str, grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        let mut refreshed = self.inner.refresh(refresh, grant.clone())?;
+        refreshed.token = self
+            .sign(&grant, &refreshed.token, refreshed.until.timestamp())
+            .map_err(|()| PrimitiveError::Invariant)?;
+        Ok(refreshed)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let claims = match self.claims(token) {
+            Some(claims) => claims,
+            None => return Ok(None),
+        };
+        // The stored grant decides, it may have been revoked before the token expired.
+        self.inner.recover_token(&claims.jti)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.inner.recover_refresh(token)
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        match self.claims(token) {
+            Some(claims) => self.inner.revoke(&claims.jti),
+            None => self.inner.revoke(token),
+        }
+    }
+}
+
+impl Issuer for StatelessIssuer {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        let refresh = match &mut self.refresh {
+            Some(store) => {
+                let issued = store.issue(grant.clone())?;
+                grant.until = issued.until;
+                issued.refresh
+            }
+            None => {
+                let now = self.clock.now();
+                let lifetimes = self.lifetimes.lifetimes(&grant);
+                lifetimes.expire(&mut grant, lifetimes.session_until(now), now);
+                None
+            }
+        };
+
+        Ok(IssuedToken {
+            token: self.sign(&grant).map_err(|()| PrimitiveError::Invariant)?,
+            refresh,
+            until: grant.until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        let store = self.refresh.as_mut().ok_or(PrimitiveError::Invariant)?;
+        let mut refreshed = store.refresh(refresh, grant.clone())?;
+        grant.until = refreshed.until;
+        refreshed.token = self.sign(&grant).map_err(|()| PrimitiveError::Invariant)?;
+        Ok(refreshed)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let claims = match self.verified(token) {
+            Some(claims) => claims,
+            None => return Ok(None),
+        };
+
+        let mut extensions = Extensions::new();
+        for (name, content) in claims.ext {
+            extensions.set_raw(name, ExtensionValue::public(content));
+        }
+        attach_claims(&mut extensions, claims.token.claims);
+        Ok(Some(Grant {
+            owner_id: claims.token.sub,
+            client_id: claims.token.client_id,
+            scope: claims
+                .token
+                .scope
+                .parse()
+                .map_err(|_| PrimitiveError::Invariant)?,
+            redirect_uri: claims
+                .redirect_uri
+                .parse()
+                .map_err(|_| PrimitiveError::Invariant)?,
+            until: Utc
+                .timestamp_opt(claims.token.exp, 0)
+                .single()
+                .ok_or(PrimitiveError::Invariant)?
+                + self.leeway,
+            extensions,
+        }))
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        match &self.refresh {
+            Some(store) => store.recover_refresh(token),
+            None => Ok(None),
+        }
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // Signed access tokens stay valid until they expire, there is nothing to revoke.
+        if self.verified(token).is_some() {
+            return Ok(());
+        }
+        match &mut self.refresh {
+            Some(store) => store.revoke(token),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::generator::RandomGenerator;
+    use crate::primitives::grant::Extensions;
+    use crate::primitives::issuer::TokenMap;
+    use chrono::Duration;
+    use serde_json::json;
+
+    fn grant() -> Grant {
+        Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://example.com".parse().unwrap(),
+            until: Utc::now() + Duration::minutes(10),
+            extensions: Extensions::new(),
+        }
+    }
+
+    fn issuer(key: SigningKey) -> JwtIssuer<TokenMap<RandomGenerator>> {
+        JwtIssuer::new(
+            key,
+            "https://auth.example.com",
+            TokenMap::new(RandomGenerator::new(16)),
+        )
+    }
+
+    #[test]
+    fn signed_access_tokens() {
+        let keys = [
+            SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap(),
+            SigningKey::es256(&SigningKey::generate_es256().unwrap()).unwrap(),
+        ];
+
+        for key in keys {
+            let algorithm = key.algorithm();
+            let mut issuer = issuer(key.with_kid("key"));
+            issuer.audience("https://api.example.com");
+            let issued = issuer.issue(grant()).unwrap();
+
+            // A resource server only needs the public key.
+            let public_key = issuer.signing_key().unwrap().public_key().to_vec();
+            let claims: AccessTokenClaims = verify(algorithm, &public_key, &issued.token).unwrap();
+            assert_eq!(claims.sub, "Owner");
+            assert_eq!(claims.aud, "https://api.example.com");
+            assert_eq!(claims.scope, "default");
+            assert_eq!(claims.exp, issued.until.timestamp());
+
+            let recovered = issuer.recover_token(&issued.token).unwrap().unwrap();
+            assert_eq!(recovered.client_id, "Client");
+            let refresh = issued.refresh.unwrap();
+            assert!(issuer.recover_refresh(&refresh).unwrap().is_some());
+
+            let refreshed = issuer.refresh(&refresh, grant()).unwrap();
+            assert!(issuer.recover_token(&refreshed.token).unwrap().is_some());
+            issuer.revoke(&refreshed.token).unwrap();
+            assert_eq!(issuer.recover_token(&refreshed.token), Ok(None));
+        }
+    }
+
+    #[test]
+    fn forged_tokens() {
+        let mut issuer = issuer(SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap());
+        let issued = issuer.issue(grant()).unwrap();
+
+        let other = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let claims = issuer.claims(&issued.token).unwrap();
+        let forged = other.sign("at+jwt", &claims).unwrap();
+        assert_eq!(issuer.recover_token(&forged), Ok(None));
+
+        // The opaque token of the backing issuer is no access token on its own.
+        assert_eq!(issuer.recover_token(&claims.jti), Ok(None));
+    }
+
+    #[test]
+    fn jwk_set() {
+        let key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let other = SigningKey::es256(&SigningKey::generate_es256().unwrap()).unwrap();
+        let set = JwkSet {
+            keys: vec![other.with_kid("other").jwk(), key.jwk()],
+        };
+        let set = JwkSet::from_json(&serde_json::to_string(&set).unwrap()).unwrap();
+
+        let claims = serde_json::json!({ "sub": "Owner" });
+        let token = key.sign("JWT", &claims).unwrap();
+        assert_eq!(set.verify::<serde_json::Value>(&token), Ok(claims.clone()));
+
+        // The key named by the token must be in the set.
+        let named = key.with_kid("other").sign("JWT", &claims).unwrap();
+        assert!(set.verify::<serde_json::Value>(&named).is_err());
+        assert!(JwkSet::default().verify::<serde_json::Value>(&token).is_err());
+    }
+
+    #[test]
+    fn signed_responses() {
+        let key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let public_key = key.public_key().to_vec();
+        let signer = ResponseSigner::new(key, "https://auth.example.com");
+
+        let parameters = [
+            ("code".to_owned(), "AuthorizationCode".to_owned()),
+            ("iss".to_owned(), "https://forged.example.com".to_owned()),
+        ];
+        let response = signer.sign("Client", &parameters).unwrap();
+        let claims: serde_json::Value = verify(Algorithm::EdDSA, &public_key, &response).unwrap();
+        assert_eq!(claims["code"], "AuthorizationCode");
+        assert_eq!(claims["iss"], "https://auth.example.com");
+        assert_eq!(claims["aud"], "Client");
+        assert!(claims["exp"].as_i64().unwrap() > Utc::now().timestamp());
+    }
+
+    struct Profiles;
+
+    impl ClaimsProvider for Profiles {
+        fn claims(&self, owner_id: &str, _: &str, _: &Scope) -> Result<Map<String, Value>, ()> {
+            let mut claims = Map::new();
+            claims.insert("name".to_owned(), owner_id.into());
+            claims.insert("sub".to_owned(), "Forged".into());
+            Ok(claims)
+        }
+    }
+
+    #[test]
+    fn id_token_claims() {
+        let key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let mut signer = IdTokenSigner::new(key, "https://auth.example.com");
+        signer.claims_provider(Profiles);
+
+        let mut claims = signer.claims(&grant()).unwrap();
+        assert_eq!(claims.sub, "Owner");
+        assert_eq!(claims.aud, "Client");
+        assert_eq!(claims.claims["name"], "Owner");
+        assert!(!claims.claims.contains_key("sub"));
+
+        claims.nonce = Some("n-0S6".to_owned());
+        let token = signer.sign(&claims).unwrap();
+        let verified: IdTokenClaims = signer.keys().verify(&token).unwrap();
+        assert_eq!(verified, claims);
+    }
+
+    #[test]
+    fn pairwise_subjects() {
+        let key = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+        let mut signer = IdTokenSigner::new(key, "https://auth.example.com");
+        let mut pairwise = Pairwise::new(b"salt");
+        pairwise.sector("Client", "client.example");
+        pairwise.sector("Sibling", "client.example");
+        signer.pairwise(pairwise.clone());
+
+        let claims = signer.claims(&grant()).unwrap();
+        assert_ne!(claims.sub, "Owner");
+        assert_eq!(claims.sub, signer.subject("Owner", "Sibling"));
+        assert_ne!(claims.sub, signer.subject("Owner", "Other"));
+        assert_ne!(claims.sub, signer.subject("Another", "Client"));
+        assert_ne!(claims.sub, Pairwise::new(b"pepper").subject("Owner", "Client"));
+        assert_eq!(claims.sub, pairwise.subject("Owner", "Client"));
+    }
+
+    #[test]
+    fn jwk_thumbprint() {
+        // The example key of RFC 7638, Section 3.1.
+        let jwk = Jwk {
+            kty: "RSA".to_owned(),
+            n: Some(
+                "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_\
+                 BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_\
+                 FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4v\
+                 MQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw"
+                    .to_owned(),
+            ),
+            e: Some("AQAB".to_owned()),
+            kid: Some("2011-04-29".to_owned()),
+            ..Jwk::default()
+        };
+        assert_eq!(
+            jwk.thumbprint().unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[test]
+    fn embedded_jwk() {
+        let keys = [
+            SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap(),
+            SigningKey::es256(&SigningKey::generate_es256().unwrap()).unwrap(),
+        ];
+
+        for key in keys {
+            let token = key.sign_with_jwk("dpop+jwt", &grant().owner_id).unwrap();
+            let jwk = header(&token).unwrap().jwk.unwrap();
+            assert_eq!(jwk, key.jwk());
+            assert_eq!(jwk.verify::<String>(&token).unwrap(), "Owner");
+
+            let other = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+            assert!(other.jwk().verify::<String>(&token).is_err());
+            assert_ne!(other.jwk().thumbprint(), jwk.thumbprint());
+        }
+    }
+
+    #[test]
+    fn stateless_tokens() {
+        let keys = KeyStore::new();
+        keys.generate(Algorithm::EdDSA).unwrap();
+        let mut issuer = StatelessIssuer::new(keys.clone(), "https://auth.example.com");
+        issuer.audience("https://api.example.com");
+
+        let mut grant = grant();
+        grant
+            .extensions
+            .set_raw("pkce".to_owned(), ExtensionValue::public(Some("S256".to_owned())));
+        let claims = json!({ "tenant": 7, "sub": "Impostor" });
+        attach_claims(&mut grant.extensions, claims.as_object().cloned().unwrap());
+        let issued = issuer.issue(grant.clone()).unwrap();
+        assert!(issued.refresh.is_none());
+
+        // Custom claims are claims of the token, but never replace registered ones.
+        let claims = issuer.claims(&issued.token).unwrap();
+        assert_eq!(claims.sub, "Owner");
+        assert_eq!(Value::Object(claims.claims), json!({ "tenant": 7 }));
+        grant.extensions.set_raw(
+            CLAIMS_EXTENSION.to_owned(),
+            ExtensionValue::public(Some(r#"{"tenant":7}"#.to_owned())),
+        );
+
+        // Any instance with the same keys recovers the grant, without a shared store.
+        let mut other = StatelessIssuer::new(keys.clone(), "https://auth.example.com");
+        other.audience("https://api.example.com");
+        let recovered = other.recover_token(&issued.token).unwrap().unwrap();
+        assert_eq!(recovered.owner_id, grant.owner_id);
+        assert_eq!(recovered.redirect_uri, grant.redirect_uri);
+        assert_eq!(recovered.until.timestamp(), grant.until.timestamp());
+        assert_eq!(recovered.extensions, grant.extensions);
+        other.revoke(&issued.token).unwrap();
+        assert!(other.recover_token(&issued.token).unwrap().is_some());
+
+        let mut elsewhere = StatelessIssuer::new(keys.clone(), "https://auth.example.com");
+        elsewhere.audience("https://other.example.com");
+        assert!(elsewhere.recover_token(&issued.token).unwrap().is_none());
+
+        let expired = issuer
+            .issue(Grant {
+                until: Utc::now() - Duration::minutes(1),
+                ..grant.clone()
+            })
+            .unwrap();
+        assert!(issuer.recover_token(&expired.token).unwrap().is_none());
+
+        grant
+            .extensions
+            .set_raw("secret".to_owned(), ExtensionValue::private(None));
+        assert!(issuer.issue(grant).is_err());
+    }
+
+    #[test]
+    fn stateless_clock() {
+        use crate::primitives::clock::ManualClock;
+        use std::sync::Arc;
+
+        let keys = KeyStore::new();
+        keys.generate(Algorithm::EdDSA).unwrap();
+        let grant = grant();
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let mut issuer = StatelessIssuer::new(keys, "https://auth.example.com");
+        issuer.clock(clock.clone());
+        let issued = issuer.issue(grant.clone()).unwrap();
+
+        clock.advance(Duration::minutes(10));
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+
+        // Instances with a clock running ahead still accept the token for a while.
+        issuer.leeway(Duration::minutes(5));
+        let recovered = issuer.recover_token(&issued.token).unwrap().unwrap();
+        let until = grant.until + Duration::minutes(5);
+        assert_eq!(recovered.until.timestamp(), until.timestamp());
+        clock.advance(Duration::minutes(5));
+        assert!(issuer.recover_token(&issued.token).unwrap().is_none());
+    }
+
+    #[test]
+    fn signer_clocks() {
+        use crate::primitives::clock::ManualClock;
+
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let key = || SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap()).unwrap();
+
+        let mut issuer = issuer(key());
+        issuer.clock(ManualClock::new(now));
+        let issued = issuer.issue(grant()).unwrap();
+        assert_eq!(issuer.claims(&issued.token).unwrap().iat, now.timestamp());
+
+        let mut signer = ResponseSigner::new(key(), "https://auth.example.com");
+        signer.clock(ManualClock::new(now));
+        let response = signer.sign("Client", &[]).unwrap();
+        let claims: serde_json::Value = signer.keys().verify(&response).unwrap();
+        assert_eq!(claims["exp"], (now + Duration::minutes(5)).timestamp());
+
+        let mut signer = IdTokenSigner::new(key(), "https://auth.example.com");
+        signer.clock(ManualClock::new(now));
+        let claims = signer.claims(&grant()).unwrap();
+        assert_eq!(claims.iat, now.timestamp());
+        assert_eq!(claims.exp, (now + Duration::hours(1)).timestamp());
+    }
+
+    #[test]
+    fn stateless_refresh() {
+        let keys = KeyStore::new();
+        keys.generate(Algorithm::ES256).unwrap();
+        let mut issuer = StatelessIssuer::new(keys, "https://auth.example.com");
+        assert!(issuer.refresh("unknown", grant()).is_err());
+
+        issuer.refresh_tokens(TokenMap::new(RandomGenerator::new(16)));
+        let issued = issuer.issue(grant()).unwrap();
+        let refresh = issued.refresh.unwrap();
+        let stored = issuer.recover_refresh(&refresh).unwrap().unwrap();
+
+        let refreshed = issuer.refresh(&refresh, stored).unwrap();
+        assert_ne!(refreshed.token, issued.token);
+        let recovered = issuer.recover_token(&refreshed.token).unwrap().unwrap();
+        assert_eq!(recovered.client_id, "Client");
+
+        let refresh = refreshed.refresh.unwrap_or(refresh);
+        issuer.revoke(&refresh).unwrap();
+        assert!(issuer.recover_refresh(&refresh).unwrap().is_none());
+    }
+}
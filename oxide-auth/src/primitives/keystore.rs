@@ -0,0 +1,227 @@
+//! Manages the signing keys of an authorization server.
+//!
+//! A [`KeyStore`] holds the current signing key together with recently retired keys. Rotating the
+//! store replaces the current key, while tokens signed with the previous key remain verifiable for
+//! an overlap period during which its public key is still published. Stores are cheap handles to
+//! shared state, so one store can be given to the JWT issuer, the ID token signer and the flow
+//! serving the public keys, and rotating it takes effect for all of them:
+//!
+//! ```
+//! use oxide_auth::primitives::jwt::{Algorithm, JwtIssuer};
+//! use oxide_auth::primitives::keystore::KeyStore;
+//! # use oxide_auth::primitives::issuer::TokenMap;
+//! # use oxide_auth::primitives::generator::RandomGenerator;
+//!
+//! let keys = KeyStore::new();
+//! keys.generate(Algorithm::EdDSA).unwrap();
+//!
+//! let tokens = TokenMap::new(RandomGenerator::new(16));
+//! let issuer = JwtIssuer::with_keys(keys.clone(), "https://auth.example.com", tokens);
+//!
+//! // Later, for example once a month.
+//! keys.rotate().unwrap();
+//! assert_eq!(keys.jwks().keys.len(), 2);
+//! ```
+//!
+//! Only available with the `jwt` feature.
+//!
+//! [`KeyStore`]: struct.KeyStore.html
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::de::DeserializeOwned;
+
+use super::jwt::{self, Algorithm, JwkSet, SigningKey};
+
+/// The conventional path below the issuer at which the public keys are served.
+///
+/// Any other path works as well, as long as it is announced as the `jwks_uri` of the metadata.
+pub const JWKS_PATH: &str = "/.well-known/jwks.json";
+
+/// A shared, rotating set of signing keys.
+///
+/// Every key receives a key id when it is added, the [RFC 7638] thumbprint of its public key
+/// unless it already had one. The current key signs all new tokens. Retired keys only verify
+/// tokens and stay published until their overlap has passed, one day by default.
+///
+/// [RFC 7638]: https://tools.ietf.org/html/rfc7638
+#[derive(Clone, Default)]
+pub struct KeyStore {
+    inner: Arc<RwLock<Keys>>,
+}
+
+#[derive(Default)]
+struct Keys {
+    current: Option<Arc<SigningKey>>,
+    retired: Vec<Retired>,
+    overlap: Option<Duration>,
+}
+
+struct Retired {
+    key: Arc<SigningKey>,
+    until: DateTime<Utc>,
+}
+
+impl KeyStore {
+    /// An empty store.
+    ///
+    /// Signing fails until a key has been added or generated.
+    pub fn new() -> Self {
+        KeyStore::default()
+    }
+
+    /// A store with the key as its current key.
+    pub fn with_key(key: SigningKey) -> Result<Self, ()> {
+        let store = KeyStore::new();
+        store.insert(key)?;
+        Ok(store)
+    }
+
+    /// Set how long retired keys are still published and accepted.
+    ///
+    /// This should be at least the lifetime of the tokens signed with the keys, plus the time that
+    /// verifiers cache the published keys.
+    pub fn overlap(&self, overlap: Duration) {
+        self.write().overlap = Some(overlap);
+    }
+
+    /// Make the key the current signing key and return its key id.
+    ///
+    /// The previous current key is retired.
+    pub fn insert(&self, key: SigningKey) -> Result<String, ()> {
+        let key = match key.kid() {
+            Some(_) => key,
+            None => {
+                let kid = key.jwk().thumbprint()?;
+                key.with_kid(&kid)
+            }
+        };
+        let kid = key.kid().unwrap_or_default().to_owned();
+
+        let mut keys = self.write();
+        let now = Utc::now();
+        let until = now + keys.overlap.unwrap_or_else(|| Duration::days(1));
+        if let Some(previous) = keys.current.replace(Arc::new(key)) {
+            keys.retired.push(Retired { key: previous, until });
+        }
+        keys.retired.retain(|retired| retired.until > now);
+        Ok(kid)
+    }
+
+    /// Generate a new key of the algorithm, make it the current key and return its key id.
+    ///
+    /// Only `ES256` and `EdDSA` keys can be generated, RSA keys must be created externally and
+    /// added with `insert`.
+    pub fn generate(&self, algorithm: Algorithm) -> Result<String, ()> {
+        let key = match algorithm {
+            Algorithm::ES256 => SigningKey::es256(&SigningKey::generate_es256()?)?,
+            Algorithm::EdDSA => SigningKey::ed25519(&SigningKey::generate_ed25519()?)?,
+            Algorithm::RS256 => return Err(()),
+        };
+        self.insert(key)
+    }
+
+    /// Replace the current key with a new one of the same algorithm and return its key id.
+    ///
+    /// Fails if there is no current key or its algorithm can not be generated.
+    pub fn rotate(&self) -> Result<String, ()> {
+        let algorithm = self.current().ok_or(())?.algorithm();
+        self.generate(algorithm)
+    }
+
+    /// The key signing new tokens.
+    pub fn current(&self) -> Option<Arc<SigningKey>> {
+        self.read().current.clone()
+    }
+
+    /// The current or a retired key with the key id.
+    pub fn key(&self, kid: &str) -> Option<Arc<SigningKey>> {
+        self.published().into_iter().find(|key| key.kid() == Some(kid))
+    }
+
+    /// The public keys of the current and all retired keys still within their overlap.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.published().iter().map(|key| key.jwk()).collect(),
+        }
+    }
+
+    /// Verify a token signed with a published key and return its claims.
+    ///
+    /// The key is chosen by the `kid` of the token, tokens without one are only checked with the
+    /// current key. Only the signature is checked, the claims must be validated by the caller.
+    pub fn verify<C: DeserializeOwned>(&self, token: &str) -> Result<C, ()> {
+        let key = match jwt::header(token)?.kid {
+            Some(kid) => self.key(&kid),
+            None => self.current(),
+        };
+        key.ok_or(())?.verify(token)
+    }
+
+    fn published(&self) -> Vec<Arc<SigningKey>> {
+        let keys = self.read();
+        let now = Utc::now();
+        let retired = keys
+            .retired
+            .iter()
+            .filter(|retired| retired.until > now)
+            .map(|retired| retired.key.clone());
+        keys.current.iter().cloned().chain(retired).collect()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Keys> {
+        self.inner.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Keys> {
+        self.inner
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assigns_thumbprint_kid() {
+        let keys = KeyStore::new();
+        let kid = keys.generate(Algorithm::ES256).unwrap();
+        let current = keys.current().unwrap();
+        assert_eq!(current.kid(), Some(kid.as_str()));
+        assert_eq!(current.jwk().thumbprint().unwrap(), kid);
+
+        let named = SigningKey::ed25519(&SigningKey::generate_ed25519().unwrap())
+            .unwrap()
+            .with_kid("2024-01");
+        assert_eq!(keys.insert(named).unwrap(), "2024-01");
+    }
+
+    #[test]
+    fn rotation_overlaps() {
+        let keys = KeyStore::new();
+        assert!(keys.rotate().is_err());
+        assert!(keys.generate(Algorithm::RS256).is_err());
+
+        let first = keys.generate(Algorithm::EdDSA).unwrap();
+        let token = keys.current().unwrap().sign("JWT", &"claims").unwrap();
+
+        let second = keys.rotate().unwrap();
+        assert_ne!(first, second);
+        assert_eq!(keys.current().unwrap().algorithm(), Algorithm::EdDSA);
+        assert_eq!(keys.jwks().keys.len(), 2);
+        assert_eq!(keys.verify::<String>(&token).unwrap(), "claims");
+        assert!(keys.jwks().verify::<String>(&token).is_ok());
+
+        // Once the overlap has passed, the retired key is no longer published.
+        let keys = KeyStore::new();
+        keys.overlap(Duration::zero());
+        let first = keys.generate(Algorithm::ES256).unwrap();
+        let token = keys.current().unwrap().sign("JWT", &"claims").unwrap();
+        keys.rotate().unwrap();
+        assert_eq!(keys.jwks().keys.len(), 1);
+        assert!(keys.key(&first).is_none());
+        assert!(keys.verify::<String>(&token).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+//! Notifies clients when owners log out, with OpenID Connect Back-Channel Logout.
+//!
+//! Clients register an endpoint to which the authorization server posts a signed logout token
+//! whenever a session in which they participated ends, as specified in [Back-Channel Logout]. The
+//! [`BackChannelLogout`] creates and signs these tokens and hands them to a [`DeliverLogout`]
+//! transport, which the server implements with its http client of choice. Wrapping the session
+//! registry in [`WithBackChannelLogout`] notifies the clients of every session that ends, for
+//! example in the `EndSessionFlow`. Wrapping an issuer notifies the client of a revoked refresh
+//! token, since the owner will no longer be able to use the client:
+//!
+//! ```
+//! use oxide_auth::primitives::keystore::KeyStore;
+//! use oxide_auth::primitives::jwt::Algorithm;
+//! use oxide_auth::primitives::logout::{BackChannelLogout, WithBackChannelLogout};
+//! use oxide_auth::primitives::session::SessionMap;
+//! use oxide_auth::frontends::dev::Url;
+//!
+//! let keys = KeyStore::new();
+//! keys.generate(Algorithm::EdDSA).unwrap();
+//!
+//! let mut logout = BackChannelLogout::new(keys, "https://auth.example.com", |uri: &Url, token: &str| {
+//!     // POST `logout_token=<token>` as a form to the uri.
+//! #   let _ = (uri, token);
+//!     Ok(())
+//! });
+//! logout.register("LocalClient", "https://client.example/backchannel".parse().unwrap());
+//!
+//! let sessions = WithBackChannelLogout::new(SessionMap::new(), logout);
+//! ```
+//!
+//! Only available with the `jwt` feature.
+//!
+//! [Back-Channel Logout]: https://openid.net/specs/openid-connect-backchannel-1_0.html
+//! [`BackChannelLogout`]: struct.BackChannelLogout.html
+//! [`DeliverLogout`]: trait.DeliverLogout.html
+//! [`WithBackChannelLogout`]: struct.WithBackChannelLogout.html
+use std::collections::HashMap;
+
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use url::Url;
+
+use super::error::PrimitiveError;
+use super::grant::Grant;
+use super::issuer::{IssuedToken, Issuer, RefreshedToken};
+use super::jwt::Pairwise;
+use super::keystore::KeyStore;
+use super::session::{Session, SessionRegistry};
+
+/// The event identifying logout tokens.
+pub const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+
+/// The claims of a logout token.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct LogoutTokenClaims {
+    /// The issuer identifier of the authorization server.
+    pub iss: String,
+
+    /// The owner who logged out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+
+    /// The client that is notified.
+    pub aud: String,
+
+    /// The time of issuance as seconds since the unix epoch.
+    pub iat: i64,
+
+    /// The expiry as seconds since the unix epoch.
+    pub exp: i64,
+
+    /// The unique id of the token.
+    pub jti: String,
+
+    /// The events of the token, only the back-channel logout event.
+    pub events: Map<String, Value>,
+
+    /// The session which ended.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+/// Delivers logout tokens to the back-channel logout endpoints of clients.
+///
+/// Implementations post the token as the `logout_token` parameter of a form to the uri. Any
+/// response other than a success, or failing to reach the client, is an error.
+pub trait DeliverLogout {
+    /// Post the logout token to the endpoint.
+    fn deliver(&self, uri: &Url, logout_token: &str) -> Result<(), ()>;
+}
+
+/// Signs and delivers logout tokens to the clients that registered an endpoint.
+///
+/// Logout tokens name the owner as `sub` and, for ended sessions, the session as `sid`. They are
+/// valid for two minutes by default. With a `Pairwise`, the `sub` is the pairwise subject of the
+/// owner for the notified client, as in its ID tokens.
+pub struct BackChannelLogout<D> {
+    keys: KeyStore,
+    issuer: String,
+    lifetime: Duration,
+    endpoints: HashMap<String, Url>,
+    pairwise: Option<Pairwise>,
+    delivery: D,
+}
+
+/// Notifies clients when sessions end or refresh tokens are revoked.
+///
+/// Wraps a `SessionRegistry`, whose ended sessions are announced to the participating clients, or
+/// an `Issuer`, whose revoked refresh tokens are announced to the client they were issued to.
+/// Failed deliveries are logged, they do not fail the underlying operation.
+pub struct WithBackChannelLogout<T, D> {
+    /// The wrapped registry or issuer.
+    pub inner: T,
+
+    /// The notifier of the clients.
+    pub logout: BackChannelLogout<D>,
+}
+
+impl<F> DeliverLogout for F
+where
+    F: Fn(&Url, &str) -> Result<(), ()>,
+{
+    fn deliver(&self, uri: &Url, logout_token: &str) -> Result<(), ()> {
+        self(uri, logout_token)
+    }
+}
+
+impl<D: DeliverLogout> BackChannelLogout<D> {
+    /// Sign tokens with the current key of the store as the issuer `iss`.
+    pub fn new(keys: KeyStore, iss: &str, delivery: D) -> Self {
+        BackChannelLogout {
+            keys,
+            issuer: iss.to_owned(),
+            lifetime: Duration::minutes(2),
+            endpoints: HashMap::new(),
+            pairwise: None,
+            delivery,
+        }
+    }
+
+    /// Register the back-channel logout endpoint of a client.
+    pub fn register(&mut self, client_id: &str, uri: Url) {
+        self.endpoints.insert(client_id.to_owned(), uri);
+    }
+
+    /// Set the lifetime of tokens signed after this call.
+    pub fn valid_for(&mut self, lifetime: Duration) {
+        self.lifetime = lifetime;
+    }
+
+    /// Name owners by their pairwise subject in tokens signed after this call.
+    pub fn pairwise(&mut self, pairwise: Pairwise) {
+        self.pairwise = Some(pairwise);
+    }
+
+    /// The claims of a logout token for the client.
+    pub fn claims(
+        &self, client_id: &str, sub: Option<&str>, sid: Option<&str>,
+    ) -> Result<LogoutTokenClaims, ()> {
+        let mut jti = [0; 16];
+        thread_rng().try_fill_bytes(&mut jti).map_err(|_| ())?;
+        let mut events = Map::new();
+        events.insert(BACKCHANNEL_LOGOUT_EVENT.to_owned(), Value::Object(Map::new()));
+
+        let now = Utc::now();
+        Ok(LogoutTokenClaims {
+            iss: self.issuer.clone(),
+            sub: sub.map(str::to_owned),
+            aud: client_id.to_owned(),
+            iat: now.timestamp(),
+            exp: (now + self.lifetime).timestamp(),
+            jti: encode_config(jti, URL_SAFE_NO_PAD),
+            events,
+            sid: sid.map(str::to_owned),
+        })
+    }
+
+    /// Sign the claims of a logout token.
+    pub fn sign(&self, claims: &LogoutTokenClaims) -> Result<String, ()> {
+        self.keys.current().ok_or(())?.sign("logout+jwt", claims)
+    }
+
+    /// Notify the clients participating in the ended session.
+    ///
+    /// All clients with an endpoint are notified, even if an earlier delivery failed.
+    pub fn notify_session(&self, session: &Session) -> Result<(), ()> {
+        let mut result = Ok(());
+        for client_id in &session.clients {
+            let notified = self.notify(client_id, &session.owner_id, Some(&session.sid));
+            result = result.and(notified);
+        }
+        result
+    }
+
+    /// Notify the client of the grant that the owner's authorization was revoked.
+    pub fn notify_grant(&self, grant: &Grant) -> Result<(), ()> {
+        self.notify(&grant.client_id, &grant.owner_id, None)
+    }
+
+    fn notify(&self, client_id: &str, sub: &str, sid: Option<&str>) -> Result<(), ()> {
+        let uri = match self.endpoints.get(client_id) {
+            Some(uri) => uri,
+            None => return Ok(()),
+        };
+
+        let sub = match &self.pairwise {
+            Some(pairwise) => pairwise.subject(sub, client_id),
+            None => sub.to_owned(),
+        };
+        let token = self.sign(&self.claims(client_id, Some(&sub), sid)?)?;
+        self.delivery.deliver(uri, &token)
+    }
+}
+
+impl<T, D: DeliverLogout> WithBackChannelLogout<T, D> {
+    /// Notify clients about the registry or issuer.
+    pub fn new(inner: T, logout: BackChannelLogout<D>) -> Self {
+        WithBackChannelLogout { inner, logout }
+    }
+}
+
+impl<S: SessionRegistry, D: DeliverLogout> SessionRegistry for WithBackChannelLogout<S, D> {
+    fn start(&mut self, owner_id: &str) -> Result<String, ()> {
+        self.inner.start(owner_id)
+    }
+
+    fn join(&mut self, sid: &str, client_id: &str) -> Result<(), ()> {
+        self.inner.join(sid, client_id)
+    }
+
+    fn session(&self, sid: &str) -> Result<Option<Session>, ()> {
+        self.inner.session(sid)
+    }
+
+    fn sessions_of(&self, owner_id: &str) -> Result<Vec<Session>, ()> {
+        self.inner.sessions_of(owner_id)
+    }
+
+    fn end(&mut self, sid: &str) -> Result<Option<Session>, ()> {
+        let ended = self.inner.end(sid)?;
+        if let Some(session) = &ended {
+            if self.logout.notify_session(session).is_err() {
+                log::warn!("Failed to deliver back-channel logout of session {}", session.sid);
+            }
+        }
+        Ok(ended)
+    }
+}
+
+impl<I: Issuer, D: DeliverLogout> Issuer for WithBackChannelLogout<I, D> {
+    fn issue(&mut self, grant: Grant) -> Result<IssuedToken, PrimitiveError> {
+        self.inner.issue(grant)
+    }
+
+    fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        self.inner.refresh(token, grant)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.inner.recover_token(token)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.inner.recover_refresh(token)
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), PrimitiveError> {
+        // Only refresh tokens end the authorization of the client, access tokens expire anyway.
+        let family = self.inner.recover_refresh(token)?;
+        self.inner.revoke(token)?;
+        if let Some(grant) = family {
+            if self.logout.notify_grant(&grant).is_err() {
+                log::warn!("Failed to deliver back-channel logout to {}", grant.client_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::generator::RandomGenerator;
+    use crate::primitives::issuer::TokenMap;
+    use crate::primitives::jwt::Algorithm;
+    use crate::primitives::session::SessionMap;
+    use std::cell::RefCell;
+
+    fn delivered() -> (KeyStore, RefCell<Vec<(Url, String)>>) {
+        let keys = KeyStore::new();
+        keys.generate(Algorithm::ES256).unwrap();
+        (keys, RefCell::new(Vec::new()))
+    }
+
+    #[test]
+    fn ended_sessions() {
+        let (keys, tokens) = delivered();
+        let delivery = |uri: &Url, token: &str| {
+            tokens.borrow_mut().push((uri.clone(), token.to_owned()));
+            Ok(())
+        };
+        let mut logout = BackChannelLogout::new(keys.clone(), "https://auth.example.com", delivery);
+        logout.register("Client", "https://client.example/logout".parse().unwrap());
+
+        let mut sessions = WithBackChannelLogout::new(SessionMap::new(), logout);
+        let sid = sessions.start("Owner").unwrap();
+        sessions.join(&sid, "Client").unwrap();
+        sessions.join(&sid, "Unregistered").unwrap();
+        sessions.end(&sid).unwrap().unwrap();
+        assert!(sessions.end(&sid).unwrap().is_none());
+
+        let tokens = tokens.borrow();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].0.as_str(), "https://client.example/logout");
+        let claims: LogoutTokenClaims = keys.verify(&tokens[0].1).unwrap();
+        assert_eq!(claims.iss, "https://auth.example.com");
+        assert_eq!(claims.aud, "Client");
+        assert_eq!(claims.sub.as_deref(), Some("Owner"));
+        assert_eq!(claims.sid.as_deref(), Some(sid.as_str()));
+        assert!(claims.events.contains_key(BACKCHANNEL_LOGOUT_EVENT));
+    }
+
+    #[test]
+    fn revoked_refresh_tokens() {
+        let (keys, tokens) = delivered();
+        let delivery = |uri: &Url, token: &str| {
+            tokens.borrow_mut().push((uri.clone(), token.to_owned()));
+            Err(())
+        };
+        let mut logout = BackChannelLogout::new(keys.clone(), "https://auth.example.com", delivery);
+        logout.register("Client", "https://client.example/logout".parse().unwrap());
+
+        let mut issuer = WithBackChannelLogout::new(TokenMap::new(RandomGenerator::new(16)), logout);
+        let grant = Grant {
+            owner_id: "Owner".to_owned(),
+            client_id: "Client".to_owned(),
+            scope: "default".parse().unwrap(),
+            redirect_uri: "https://client.example/endpoint".parse().unwrap(),
+            until: Utc::now() + Duration::hours(1),
+            extensions: Default::default(),
+        };
+        let issued = issuer.issue(grant).unwrap();
+
+        issuer.logout.pairwise(Pairwise::new(b"salt"));
+
+        // Failed deliveries do not fail the revocation.
+        issuer.revoke(&issued.token).unwrap();
+        assert!(tokens.borrow().is_empty());
+        issuer.revoke(issued.refresh.as_ref().unwrap()).unwrap();
+        assert_eq!(tokens.borrow().len(), 1);
+        assert!(issuer
+            .recover_refresh(issued.refresh.as_ref().unwrap())
+            .unwrap()
+            .is_none());
+
+        let claims: LogoutTokenClaims = keys.verify(&tokens.borrow()[0].1).unwrap();
+        let pairwise = Pairwise::new(b"salt").subject("Owner", "Client");
+        assert_eq!(claims.sub, Some(pairwise));
+        assert!(claims.sid.is_none());
+    }
+}
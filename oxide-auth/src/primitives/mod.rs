@@ -37,19 +37,38 @@ use chrono::Utc;
 use url::Url;
 
 pub mod authorizer;
+pub mod ciba;
+pub mod clock;
+pub mod device;
+pub mod error;
+#[cfg(feature = "jwt")]
+pub mod events;
 pub mod generator;
 pub mod grant;
 pub mod issuer;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+#[cfg(feature = "jwt")]
+pub mod keystore;
+#[cfg(feature = "jwt")]
+pub mod logout;
+pub mod pending;
 pub mod registrar;
+pub mod remote;
+pub mod request_uri;
 pub mod scope;
+pub mod session;
 
 type Time = DateTime<Utc>;
 
 /// Commonly used primitives for frontends and backends.
 pub mod prelude {
     pub use super::authorizer::{Authorizer, AuthMap};
+    pub use super::device::{DeviceCodeMap, DeviceCodeStore};
+    pub use super::error::PrimitiveError;
     pub use super::issuer::{IssuedToken, Issuer, TokenMap, TokenSigner};
     pub use super::generator::{Assertion, TagGrant, RandomGenerator};
     pub use super::registrar::{Registrar, Client, ClientUrl, ClientMap, PreGrant};
+    pub use super::request_uri::{RequestUriMap, RequestUriStore};
     pub use super::scope::Scope;
 }
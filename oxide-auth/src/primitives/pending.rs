@@ -0,0 +1,238 @@
+//! Pending authorizations remember an authorization request across several pages.
+//!
+//! Some servers take the owner through more than one page before a request is decided, for example
+//! a login, a second factor and finally the consent page. A [`PendingStore`] keeps the parameters
+//! of the original request and the progress of the owner on the server, so that each page only
+//! needs to carry an opaque handle back to the authorization endpoint in the `pending` parameter.
+//!
+//! [`PendingStore`]: trait.PendingStore.html
+use std::collections::HashMap;
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use rand::{thread_rng, RngCore};
+
+use super::Time;
+
+/// The parameter carrying the handle of a pending authorization.
+pub const PENDING_PARAMETER: &str = "pending";
+
+/// An authorization request on its way through several pages.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PendingAuthorization {
+    /// The parameters of the original authorization request.
+    pub parameters: HashMap<String, String>,
+
+    /// The number of steps that have been completed.
+    pub step: usize,
+
+    /// The owner, once authenticated by one of the steps.
+    pub owner: Option<String>,
+
+    /// Additional state recorded by the steps, for example the authentication methods used.
+    pub state: HashMap<String, String>,
+}
+
+/// Stores authorizations until they are decided.
+pub trait PendingStore {
+    /// Store a new pending authorization and create a fresh handle referencing it.
+    fn begin(&mut self, pending: PendingAuthorization) -> Result<String, ()>;
+
+    /// The pending authorization with the handle.
+    ///
+    /// Returns `None` for unknown and expired handles.
+    fn get(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()>;
+
+    /// Record the progress of a pending authorization.
+    ///
+    /// Updating an unknown or expired handle fails.
+    fn update(&mut self, handle: &str, pending: PendingAuthorization) -> Result<(), ()>;
+
+    /// Remove a decided authorization, returning it if it was still pending.
+    fn finish(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()>;
+}
+
+/// An in-memory hash map of pending authorizations.
+///
+/// Handles are 16 random bytes in url-safe base64. Pending authorizations expire ten minutes after
+/// they began by default.
+pub struct PendingMap {
+    duration: Duration,
+    pending: HashMap<String, (PendingAuthorization, Time)>,
+}
+
+impl PendingMap {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        PendingMap {
+            duration: Duration::minutes(10),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Set how long authorizations begun after this call stay pending.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+}
+
+impl Default for PendingMap {
+    fn default() -> Self {
+        PendingMap::new()
+    }
+}
+
+impl<S: PendingStore + ?Sized> PendingStore for &mut S {
+    fn begin(&mut self, pending: PendingAuthorization) -> Result<String, ()> {
+        (**self).begin(pending)
+    }
+
+    fn get(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        (**self).get(handle)
+    }
+
+    fn update(&mut self, handle: &str, pending: PendingAuthorization) -> Result<(), ()> {
+        (**self).update(handle, pending)
+    }
+
+    fn finish(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        (**self).finish(handle)
+    }
+}
+
+impl<S: PendingStore + ?Sized> PendingStore for Box<S> {
+    fn begin(&mut self, pending: PendingAuthorization) -> Result<String, ()> {
+        (**self).begin(pending)
+    }
+
+    fn get(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        (**self).get(handle)
+    }
+
+    fn update(&mut self, handle: &str, pending: PendingAuthorization) -> Result<(), ()> {
+        (**self).update(handle, pending)
+    }
+
+    fn finish(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        (**self).finish(handle)
+    }
+}
+
+impl<'a, S: PendingStore + ?Sized> PendingStore for MutexGuard<'a, S> {
+    fn begin(&mut self, pending: PendingAuthorization) -> Result<String, ()> {
+        (**self).begin(pending)
+    }
+
+    fn get(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        (**self).get(handle)
+    }
+
+    fn update(&mut self, handle: &str, pending: PendingAuthorization) -> Result<(), ()> {
+        (**self).update(handle, pending)
+    }
+
+    fn finish(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        (**self).finish(handle)
+    }
+}
+
+impl<'a, S: PendingStore + ?Sized> PendingStore for RwLockWriteGuard<'a, S> {
+    fn begin(&mut self, pending: PendingAuthorization) -> Result<String, ()> {
+        (**self).begin(pending)
+    }
+
+    fn get(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        (**self).get(handle)
+    }
+
+    fn update(&mut self, handle: &str, pending: PendingAuthorization) -> Result<(), ()> {
+        (**self).update(handle, pending)
+    }
+
+    fn finish(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        (**self).finish(handle)
+    }
+}
+
+impl PendingStore for PendingMap {
+    fn begin(&mut self, pending: PendingAuthorization) -> Result<String, ()> {
+        let now = Utc::now();
+        self.pending.retain(|_, (_, until)| *until > now);
+
+        let mut random = [0; 16];
+        thread_rng().try_fill_bytes(&mut random).map_err(|_| ())?;
+        let handle = encode_config(random, URL_SAFE_NO_PAD);
+        self.pending
+            .insert(handle.clone(), (pending, now + self.duration));
+
+        Ok(handle)
+    }
+
+    fn get(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        match self.pending.get(handle) {
+            Some((pending, until)) if *until > Utc::now() => Ok(Some(pending.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    fn update(&mut self, handle: &str, pending: PendingAuthorization) -> Result<(), ()> {
+        match self.pending.get_mut(handle) {
+            Some((current, until)) if *until > Utc::now() => {
+                *current = pending;
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+
+    fn finish(&mut self, handle: &str) -> Result<Option<PendingAuthorization>, ()> {
+        match self.pending.remove(handle) {
+            Some((pending, until)) if until > Utc::now() => Ok(Some(pending)),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending() -> PendingAuthorization {
+        let mut parameters = HashMap::new();
+        parameters.insert("response_type".to_string(), "code".to_string());
+        parameters.insert("client_id".to_string(), "Client".to_string());
+        PendingAuthorization {
+            parameters,
+            ..PendingAuthorization::default()
+        }
+    }
+
+    #[test]
+    fn begin_and_finish() {
+        let mut store = PendingMap::new();
+        let handle = store.begin(pending()).unwrap();
+        assert_ne!(store.begin(pending()).unwrap(), handle);
+        assert_eq!(store.get(&handle), Ok(Some(pending())));
+
+        let mut progressed = pending();
+        progressed.step = 1;
+        progressed.owner = Some("Owner".to_string());
+        assert_eq!(store.update(&handle, progressed.clone()), Ok(()));
+        assert_eq!(store.get(&handle), Ok(Some(progressed.clone())));
+
+        assert_eq!(store.finish(&handle), Ok(Some(progressed)));
+        assert_eq!(store.get(&handle), Ok(None));
+        assert_eq!(store.update(&handle, pending()), Err(()));
+    }
+
+    #[test]
+    fn expired() {
+        let mut store = PendingMap::new();
+        store.valid_for(Duration::seconds(-1));
+        let handle = store.begin(pending()).unwrap();
+        assert_eq!(store.get(&handle), Ok(None));
+        assert_eq!(store.update(&handle, pending()), Err(()));
+        assert_eq!(store.finish(&handle), Ok(None));
+    }
+}
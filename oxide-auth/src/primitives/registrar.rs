@@ -3,7 +3,7 @@
 //! It will govern their redirect urls and allowed scopes to request tokens for. When an oauth
 //! request turns up, it is the registrars duty to verify the requested scope and redirect url for
 //! consistency in the permissions granted and urls registered.
-use super::scope::Scope;
+use super::scope::{Scope, ScopePolicy};
 
 use std::borrow::Cow;
 use std::cmp;
@@ -17,6 +17,7 @@ use argon2::{self, Config};
 use once_cell::sync::Lazy;
 use rand::{RngCore, thread_rng};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use url::{Url, ParseError as ParseUrlError};
 
 /// Registrars provie a way to interact with clients.
@@ -44,6 +45,32 @@ pub trait Registrar {
 
     /// Try to login as client with some authentication.
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError>;
+
+    /// Try to login as client with the certificate of a mutual TLS connection.
+    ///
+    /// This is only called for requests without a passphrase. The default implementation ignores
+    /// the certificate and checks the client as if no authentication was provided, which only
+    /// succeeds for public clients.
+    fn check_certificate(
+        &self, client_id: &str, _certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        self.check(client_id, None)
+    }
+
+    /// The JSON Web Key Set registered for the client, its `jwks` metadata.
+    ///
+    /// The keys verify request objects signed by the client. The default implementation knows of
+    /// no keys for any client.
+    fn jwks(&self, _client_id: &str) -> Result<Option<String>, RegistrarError> {
+        Ok(None)
+    }
+
+    /// The secret shared with the client for signing its `client_secret_jwt` assertions.
+    ///
+    /// The default implementation knows of no secret for any client.
+    fn jwt_secret(&self, _client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        Ok(None)
+    }
 }
 
 /// An url that has been registered.
@@ -159,6 +186,51 @@ impl<'de> Deserialize<'de> for IgnoreLocalPortUrl {
     }
 }
 
+/// How the `redirect_uri` of a request is matched against the redirect URLs of a client.
+///
+/// Each registered URL is always accepted as it was registered, see [`RegisteredUrl`]. The other
+/// modes additionally accept some redirect URIs that differ from the registered ones, and the
+/// redirection then goes to the requested URI.
+///
+/// [`RegisteredUrl`]: enum.RegisteredUrl.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectMatching {
+    /// Only accept the registered URLs. This is the default.
+    #[default]
+    Exact,
+
+    /// Also ignore the port of loopback URLs, for native apps listening on an ephemeral port.
+    ///
+    /// Applies to `http` URLs with the host `127.0.0.1`, `[::1]` or `localhost`, as described in
+    /// [RFC 8252, Section 7.3](https://tools.ietf.org/html/rfc8252#section-7.3).
+    Loopback,
+
+    /// Also accept any URL below a registered one.
+    ///
+    /// The requested URL must have the origin of the registered one and its path must extend the
+    /// registered path by whole segments, it may have any query. This weakens the protection
+    /// against open redirectors on the client and must be chosen explicitly.
+    Prefix,
+}
+
+/// The reason a redirect URI was not accepted for a client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RedirectMismatch {
+    /// The redirect URI contains a fragment, which is never allowed.
+    Fragment,
+
+    /// The redirect URI differs from a registered loopback URL only in its port but the client
+    /// does not use loopback matching.
+    LoopbackPort,
+
+    /// The redirect URI is not below any registered URL of a client using prefix matching.
+    OutsidePrefix,
+
+    /// The redirect URI is not registered for the client.
+    Unregistered,
+}
+
 /// A pair of `client_id` and an optional `redirect_uri`.
 ///
 /// Such a pair is received in an Authorization Code Request. A registrar which allows multiple
@@ -204,6 +276,22 @@ pub struct PreGrant {
     pub scope: Scope,
 }
 
+/// The certificate a client presented in a mutual TLS connection.
+///
+/// See [RFC 8705](https://tools.ietf.org/html/rfc8705). Validating the certificate chain is the
+/// duty of the TLS layer, which provides the subject of the certificate only when the chain leads to
+/// an authority it trusts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// The DER encoding of the certificate.
+    pub der: Vec<u8>,
+
+    /// The subject distinguished name of a certificate issued by a trusted authority.
+    ///
+    /// This is `None` for self-signed certificates, which are only recognized by their thumbprint.
+    pub subject_dn: Option<String>,
+}
+
 /// Handled responses from a registrar.
 #[derive(Clone, Debug)]
 pub enum RegistrarError {
@@ -218,6 +306,12 @@ pub enum RegistrarError {
     /// These should be indistiguishable to avoid security problems.
     Unspecified,
 
+    /// The client is known but the requested redirection url was not accepted for it.
+    ///
+    /// The user agent must not be redirected to the url. The reason can be shown to the resource
+    /// owner instead, it only concerns the url that was part of the request.
+    RedirectMismatch(RedirectMismatch),
+
     /// Something went wrong with this primitive that has no security reason.
     PrimitiveError,
 }
@@ -238,6 +332,10 @@ pub struct Client {
     additional_redirect_uris: Vec<RegisteredUrl>,
     default_scope: Scope,
     client_type: ClientType,
+    tls_client_auth: Option<TlsClientAuth>,
+    jwks: Option<String>,
+    jwt_secret: Option<Vec<u8>>,
+    redirect_matching: RedirectMatching,
 }
 
 /// A client whose credentials have been wrapped by a password policy.
@@ -264,6 +362,25 @@ pub struct EncodedClient {
 
     /// The authentication data.
     pub encoded_client: ClientType,
+
+    /// The certificates accepted for mutual TLS authentication of the client.
+    #[serde(default)]
+    pub tls_client_auth: Option<TlsClientAuth>,
+
+    /// The JSON Web Key Set document of the client's public keys.
+    #[serde(default)]
+    pub jwks: Option<String>,
+
+    /// The secret of the client's `client_secret_jwt` assertions.
+    ///
+    /// Unlike the passphrase this is stored as is, since the signatures of assertions can only be
+    /// verified with the secret itself.
+    #[serde(default)]
+    pub jwt_secret: Option<Vec<u8>>,
+
+    /// How the redirect URI of requests is matched against the registered ones.
+    #[serde(default)]
+    pub redirect_matching: RedirectMatching,
 }
 
 /// Recombines an `EncodedClient` and a  `PasswordPolicy` to check authentication.
@@ -285,11 +402,27 @@ pub enum ClientType {
     },
 }
 
+/// How a client authenticates with mutual TLS, the `tls_client_auth` metadata of a client.
+///
+/// A client with this metadata must authenticate either with a certificate or, if it is also
+/// confidential, with its passphrase. It is never treated as a public client.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TlsClientAuth {
+    /// The `tls_client_auth` method, a certificate of a trusted authority with this subject.
+    SubjectDn(String),
+
+    /// The `self_signed_tls_client_auth` method, one of the registered self-signed certificates.
+    ///
+    /// The certificates are identified by their thumbprint, see `ClientCertificate::thumbprint`.
+    SelfSigned(Vec<String>),
+}
+
 /// A very simple, in-memory hash map of client ids to Client entries.
 #[derive(Default)]
 pub struct ClientMap {
     clients: HashMap<String, EncodedClient>,
     password_policy: Option<Box<dyn PasswordPolicy>>,
+    scope_policy: Option<Box<dyn ScopePolicy>>,
 }
 
 impl fmt::Debug for ClientType {
@@ -500,6 +633,10 @@ impl Client {
             additional_redirect_uris: vec![],
             default_scope,
             client_type: ClientType::Public,
+            tls_client_auth: None,
+            jwks: None,
+            jwt_secret: None,
+            redirect_matching: RedirectMatching::Exact,
         }
     }
 
@@ -515,6 +652,10 @@ impl Client {
             client_type: ClientType::Confidential {
                 passdata: passphrase.to_owned(),
             },
+            tls_client_auth: None,
+            jwks: None,
+            jwt_secret: None,
+            redirect_matching: RedirectMatching::Exact,
         }
     }
 
@@ -524,6 +665,32 @@ impl Client {
         self
     }
 
+    /// Allow the client to authenticate with a certificate in mutual TLS.
+    pub fn with_tls_client_auth(mut self, tls_client_auth: TlsClientAuth) -> Self {
+        self.tls_client_auth = Some(tls_client_auth);
+        self
+    }
+
+    /// Register the public keys of the client, a JSON Web Key Set document.
+    pub fn with_jwks(mut self, jwks: &str) -> Self {
+        self.jwks = Some(jwks.to_owned());
+        self
+    }
+
+    /// Allow the client to authenticate with assertions signed by a shared secret.
+    ///
+    /// These are the `client_secret_jwt` assertions of OpenID Connect, signed with HMAC SHA-256.
+    pub fn with_jwt_secret(mut self, secret: &[u8]) -> Self {
+        self.jwt_secret = Some(secret.to_owned());
+        self
+    }
+
+    /// Choose how redirect URIs of requests are matched, exactly by default.
+    pub fn with_redirect_matching(mut self, redirect_matching: RedirectMatching) -> Self {
+        self.redirect_matching = redirect_matching;
+        self
+    }
+
     /// Obscure the clients authentication data.
     ///
     /// This could apply a one-way function to the passphrase using an adequate password hashing
@@ -543,7 +710,112 @@ impl Client {
             additional_redirect_uris: self.additional_redirect_uris,
             default_scope: self.default_scope,
             encoded_client,
+            tls_client_auth: self.tls_client_auth,
+            jwks: self.jwks,
+            jwt_secret: self.jwt_secret,
+            redirect_matching: self.redirect_matching,
+        }
+    }
+}
+
+impl EncodedClient {
+    /// Choose the redirect URL for a request, following the matching mode of the client.
+    ///
+    /// Without a `redirect_uri` in the request, this is the registered default. A rejected URI
+    /// comes with the precise reason, which may be shown to the user but must never be sent to the
+    /// redirect URI itself.
+    pub fn match_redirect(
+        &self, requested: Option<&ExactUrl>,
+    ) -> Result<RegisteredUrl, RedirectMismatch> {
+        let requested = match requested {
+            None => return Ok(self.redirect_uri.clone()),
+            Some(requested) => requested,
+        };
+
+        let mut registered = std::iter::once(&self.redirect_uri).chain(&self.additional_redirect_uris);
+        if let Some(registered) = registered.clone().find(|&registered| *registered == *requested) {
+            return Ok(registered.clone());
+        }
+
+        let url = requested.to_url();
+        if url.fragment().is_some() {
+            return Err(RedirectMismatch::Fragment);
         }
+
+        let loopback = registered.clone().any(|registered| {
+            is_loopback(&registered.to_url()) && same_but_port(&registered.to_url(), &url)
+        });
+        match self.redirect_matching {
+            RedirectMatching::Loopback if loopback => {
+                return Ok(RegisteredUrl::Exact(requested.clone()))
+            }
+            RedirectMatching::Prefix => {
+                return match registered.any(|registered| is_below(&registered.to_url(), &url)) {
+                    true => Ok(RegisteredUrl::Exact(requested.clone())),
+                    false => Err(RedirectMismatch::OutsidePrefix),
+                }
+            }
+            RedirectMatching::Exact if loopback => return Err(RedirectMismatch::LoopbackPort),
+            _ => (),
+        }
+
+        Err(RedirectMismatch::Unregistered)
+    }
+}
+
+impl RedirectMismatch {
+    /// A description of the mismatch for the user.
+    pub fn description(self) -> &'static str {
+        match self {
+            RedirectMismatch::Fragment => "The redirect_uri must not contain a fragment",
+            RedirectMismatch::LoopbackPort => {
+                "The redirect_uri differs from a registered one in its port, which the client must not change"
+            }
+            RedirectMismatch::OutsidePrefix => "The redirect_uri is not below a registered one",
+            RedirectMismatch::Unregistered => "The redirect_uri is not registered for the client",
+        }
+    }
+}
+
+impl fmt::Display for RedirectMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// Check for the `http` loopback URLs of native apps.
+fn is_loopback(url: &Url) -> bool {
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => return false,
+    };
+    url.scheme() == "http" && (host == "127.0.0.1" || host == "[::1]" || host == "localhost")
+}
+
+/// Check that the URLs only differ in their port.
+fn same_but_port(registered: &Url, requested: &Url) -> bool {
+    let mut requested = requested.clone();
+    let mut registered = registered.clone();
+    requested.set_port(None).is_ok() && registered.set_port(None).is_ok() && registered == requested
+}
+
+/// Check that the requested URL is below the registered one, by whole path segments.
+fn is_below(registered: &Url, requested: &Url) -> bool {
+    if registered.origin() != requested.origin() {
+        return false;
+    }
+
+    let prefix = registered.path().trim_end_matches('/');
+    match requested.path().strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+impl ClientCertificate {
+    /// The base64url encoded SHA-256 hash of the certificate, its `x5t#S256` thumbprint.
+    pub fn thumbprint(&self) -> String {
+        base64::encode_config(Sha256::digest(&self.der), base64::URL_SAFE_NO_PAD)
     }
 }
 
@@ -561,13 +833,30 @@ impl<'a> RegisteredClient<'a> {
     /// passphrase matches.
     pub fn check_authentication(&self, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         match (passphrase, &self.client.encoded_client) {
-            (None, &ClientType::Public) => Ok(()),
+            (None, &ClientType::Public) if self.client.tls_client_auth.is_none() => Ok(()),
             (Some(provided), &ClientType::Confidential { passdata: ref stored }) => {
                 self.policy.check(&self.client.client_id, provided, stored)
             }
             _ => Err(RegistrarError::Unspecified),
         }
     }
+
+    /// Try to authenticate with the certificate of a mutual TLS connection. Clients without
+    /// `tls_client_auth` metadata are checked as if no authentication was provided.
+    pub fn check_certificate(&self, certificate: &ClientCertificate) -> Result<(), RegistrarError> {
+        let accepted = match &self.client.tls_client_auth {
+            None => return self.check_authentication(None),
+            Some(TlsClientAuth::SubjectDn(subject)) => certificate.subject_dn.as_ref() == Some(subject),
+            Some(TlsClientAuth::SelfSigned(thumbprints)) => {
+                thumbprints.contains(&certificate.thumbprint())
+            }
+        };
+
+        match accepted {
+            true => Ok(()),
+            false => Err(RegistrarError::Unspecified),
+        }
+    }
 }
 
 impl cmp::PartialOrd<Self> for PreGrant {
@@ -648,6 +937,15 @@ impl ClientMap {
         self.password_policy = Some(Box::new(new_policy))
     }
 
+    /// Validate requested scopes with a policy instead of granting the default scope.
+    ///
+    /// Without a policy every request is granted the default scope of its client. With one, a
+    /// requested scope is granted if the policy accepts it for the default scope of the client and
+    /// rejected as an invalid scope otherwise. Requests without a scope still receive the default.
+    pub fn set_scope_policy<P: ScopePolicy + 'static>(&mut self, new_policy: P) {
+        self.scope_policy = Some(Box::new(new_policy))
+    }
+
     // This is not an instance method because it needs to borrow the box but register needs &mut
     fn current_policy<'a>(policy: &'a Option<Box<dyn PasswordPolicy>>) -> &'a dyn PasswordPolicy {
         policy
@@ -689,6 +987,20 @@ impl<'s, R: Registrar + ?Sized> Registrar for &'s R {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn check_certificate(
+        &self, client_id: &str, certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        (**self).check_certificate(client_id, certificate)
+    }
+
+    fn jwks(&self, client_id: &str) -> Result<Option<String>, RegistrarError> {
+        (**self).jwks(client_id)
+    }
+
+    fn jwt_secret(&self, client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        (**self).jwt_secret(client_id)
+    }
 }
 
 impl<'s, R: Registrar + ?Sized> Registrar for &'s mut R {
@@ -703,6 +1015,20 @@ impl<'s, R: Registrar + ?Sized> Registrar for &'s mut R {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn check_certificate(
+        &self, client_id: &str, certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        (**self).check_certificate(client_id, certificate)
+    }
+
+    fn jwks(&self, client_id: &str) -> Result<Option<String>, RegistrarError> {
+        (**self).jwks(client_id)
+    }
+
+    fn jwt_secret(&self, client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        (**self).jwt_secret(client_id)
+    }
 }
 
 impl<R: Registrar + ?Sized> Registrar for Box<R> {
@@ -717,6 +1043,20 @@ impl<R: Registrar + ?Sized> Registrar for Box<R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn check_certificate(
+        &self, client_id: &str, certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        (**self).check_certificate(client_id, certificate)
+    }
+
+    fn jwks(&self, client_id: &str) -> Result<Option<String>, RegistrarError> {
+        (**self).jwks(client_id)
+    }
+
+    fn jwt_secret(&self, client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        (**self).jwt_secret(client_id)
+    }
 }
 
 impl<R: Registrar + ?Sized> Registrar for Rc<R> {
@@ -731,6 +1071,20 @@ impl<R: Registrar + ?Sized> Registrar for Rc<R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn check_certificate(
+        &self, client_id: &str, certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        (**self).check_certificate(client_id, certificate)
+    }
+
+    fn jwks(&self, client_id: &str) -> Result<Option<String>, RegistrarError> {
+        (**self).jwks(client_id)
+    }
+
+    fn jwt_secret(&self, client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        (**self).jwt_secret(client_id)
+    }
 }
 
 impl<R: Registrar + ?Sized> Registrar for Arc<R> {
@@ -745,6 +1099,20 @@ impl<R: Registrar + ?Sized> Registrar for Arc<R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn check_certificate(
+        &self, client_id: &str, certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        (**self).check_certificate(client_id, certificate)
+    }
+
+    fn jwks(&self, client_id: &str) -> Result<Option<String>, RegistrarError> {
+        (**self).jwks(client_id)
+    }
+
+    fn jwt_secret(&self, client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        (**self).jwt_secret(client_id)
+    }
 }
 
 impl<'s, R: Registrar + ?Sized + 's> Registrar for MutexGuard<'s, R> {
@@ -759,6 +1127,20 @@ impl<'s, R: Registrar + ?Sized + 's> Registrar for MutexGuard<'s, R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn check_certificate(
+        &self, client_id: &str, certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        (**self).check_certificate(client_id, certificate)
+    }
+
+    fn jwks(&self, client_id: &str) -> Result<Option<String>, RegistrarError> {
+        (**self).jwks(client_id)
+    }
+
+    fn jwt_secret(&self, client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        (**self).jwt_secret(client_id)
+    }
 }
 
 impl<'s, R: Registrar + ?Sized + 's> Registrar for RwLockWriteGuard<'s, R> {
@@ -773,6 +1155,20 @@ impl<'s, R: Registrar + ?Sized + 's> Registrar for RwLockWriteGuard<'s, R> {
     fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
         (**self).check(client_id, passphrase)
     }
+
+    fn check_certificate(
+        &self, client_id: &str, certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        (**self).check_certificate(client_id, certificate)
+    }
+
+    fn jwks(&self, client_id: &str) -> Result<Option<String>, RegistrarError> {
+        (**self).jwks(client_id)
+    }
+
+    fn jwt_secret(&self, client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        (**self).jwt_secret(client_id)
+    }
 }
 
 impl Registrar for ClientMap {
@@ -782,22 +1178,9 @@ impl Registrar for ClientMap {
             Some(stored) => stored,
         };
 
-        // Perform exact matching as motivated in the rfc
-        let registered_url = match bound.redirect_uri {
-            None => client.redirect_uri.clone(),
-            Some(ref url) => {
-                let original = std::iter::once(&client.redirect_uri);
-                let alternatives = client.additional_redirect_uris.iter();
-                if let Some(registered) = original
-                    .chain(alternatives)
-                    .find(|&registered| *registered == *url.as_ref())
-                {
-                    registered.clone()
-                } else {
-                    return Err(RegistrarError::Unspecified);
-                }
-            }
-        };
+        let registered_url = client
+            .match_redirect(bound.redirect_uri.as_deref())
+            .map_err(RegistrarError::RedirectMismatch)?;
 
         Ok(BoundClient {
             client_id: bound.client_id,
@@ -805,16 +1188,22 @@ impl Registrar for ClientMap {
         })
     }
 
-    /// Always overrides the scope with a default scope.
-    fn negotiate(&self, bound: BoundClient, _scope: Option<Scope>) -> Result<PreGrant, RegistrarError> {
+    /// Overrides the scope with a default scope, unless a scope policy was set.
+    fn negotiate(&self, bound: BoundClient, scope: Option<Scope>) -> Result<PreGrant, RegistrarError> {
         let client = self
             .clients
             .get(bound.client_id.as_ref())
             .expect("Bound client appears to not have been constructed with this registrar");
+        let scope = match (&self.scope_policy, scope) {
+            (Some(policy), Some(scope)) => policy
+                .validate(&scope, &client.default_scope)
+                .map_err(|()| RegistrarError::Unspecified)?,
+            _ => client.default_scope.clone(),
+        };
         Ok(PreGrant {
             client_id: bound.client_id.into_owned(),
             redirect_uri: bound.redirect_uri.into_owned(),
-            scope: client.default_scope.clone(),
+            scope,
         })
     }
 
@@ -830,6 +1219,33 @@ impl Registrar for ClientMap {
 
         Ok(())
     }
+
+    fn check_certificate(
+        &self, client_id: &str, certificate: &ClientCertificate,
+    ) -> Result<(), RegistrarError> {
+        let password_policy = Self::current_policy(&self.password_policy);
+
+        self.clients
+            .get(client_id)
+            .ok_or(RegistrarError::Unspecified)
+            .and_then(|client| {
+                RegisteredClient::new(client, password_policy).check_certificate(certificate)
+            })
+    }
+
+    fn jwks(&self, client_id: &str) -> Result<Option<String>, RegistrarError> {
+        self.clients
+            .get(client_id)
+            .map(|client| client.jwks.clone())
+            .ok_or(RegistrarError::Unspecified)
+    }
+
+    fn jwt_secret(&self, client_id: &str) -> Result<Option<Vec<u8>>, RegistrarError> {
+        self.clients
+            .get(client_id)
+            .map(|client| client.jwt_secret.clone())
+            .ok_or(RegistrarError::Unspecified)
+    }
 }
 
 #[cfg(test)]
@@ -921,6 +1337,41 @@ mod tests {
         assert!(client.check_authentication(Some(b"")).is_err());
     }
 
+    #[test]
+    fn tls_client() {
+        let policy = Argon2::default();
+        let certificate = ClientCertificate {
+            der: b"Certificate".to_vec(),
+            subject_dn: None,
+        };
+        let client = Client::public(
+            "ClientId",
+            "https://example.com".parse::<Url>().unwrap().into(),
+            "default".parse().unwrap(),
+        )
+        .with_tls_client_auth(TlsClientAuth::SelfSigned(vec![certificate.thumbprint()]))
+        .encode(&policy);
+        let client = RegisteredClient::new(&client, &policy);
+        assert!(client.check_authentication(None).is_err());
+        assert!(client.check_certificate(&certificate).is_ok());
+        let other = ClientCertificate {
+            der: b"Other certificate".to_vec(),
+            subject_dn: Some("CN=ClientId".to_owned()),
+        };
+        assert!(client.check_certificate(&other).is_err());
+
+        let client = Client::public(
+            "ClientId",
+            "https://example.com".parse::<Url>().unwrap().into(),
+            "default".parse().unwrap(),
+        )
+        .with_tls_client_auth(TlsClientAuth::SubjectDn("CN=ClientId".to_owned()))
+        .encode(&policy);
+        let client = RegisteredClient::new(&client, &policy);
+        assert!(client.check_certificate(&other).is_ok());
+        assert!(client.check_certificate(&certificate).is_err());
+    }
+
     #[test]
     fn with_additional_redirect_uris() {
         let client_id = "ClientId";
@@ -963,6 +1414,68 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn redirect_matching() {
+        let policy = Argon2::default();
+        let client = |registered: &str, matching| {
+            Client::public(
+                "ClientId",
+                registered.parse::<Url>().unwrap().into(),
+                "default".parse().unwrap(),
+            )
+            .with_redirect_matching(matching)
+            .encode(&policy)
+        };
+        let matches = |client: &EncodedClient, requested: &str| {
+            client
+                .match_redirect(Some(&requested.parse().unwrap()))
+                .map(|url| url.as_str().to_owned())
+        };
+
+        let exact = client("http://127.0.0.1:8000/cb", RedirectMatching::Exact);
+        assert_eq!(
+            matches(&exact, "http://127.0.0.1:8000/cb").unwrap(),
+            "http://127.0.0.1:8000/cb"
+        );
+        assert_eq!(
+            matches(&exact, "http://127.0.0.1:9000/cb"),
+            Err(RedirectMismatch::LoopbackPort)
+        );
+        assert_eq!(
+            matches(&exact, "http://127.0.0.1:8000/cb#fragment"),
+            Err(RedirectMismatch::Fragment)
+        );
+
+        let loopback = client("http://[::1]/cb", RedirectMatching::Loopback);
+        assert_eq!(
+            matches(&loopback, "http://[::1]:51004/cb").unwrap(),
+            "http://[::1]:51004/cb"
+        );
+        assert_eq!(
+            matches(&loopback, "http://[::1]:51004/other"),
+            Err(RedirectMismatch::Unregistered)
+        );
+        let remote = client("http://example.com/cb", RedirectMatching::Loopback);
+        assert_eq!(
+            matches(&remote, "http://example.com:8000/cb"),
+            Err(RedirectMismatch::Unregistered)
+        );
+
+        let prefix = client("https://example.com/cb", RedirectMatching::Prefix);
+        assert_eq!(
+            matches(&prefix, "https://example.com/cb/app?tab=1").unwrap(),
+            "https://example.com/cb/app?tab=1"
+        );
+        assert_eq!(
+            matches(&prefix, "https://example.com/cbx"),
+            Err(RedirectMismatch::OutsidePrefix)
+        );
+        assert_eq!(
+            matches(&prefix, "https://example.com.evil/cb/app"),
+            Err(RedirectMismatch::OutsidePrefix)
+        );
+    }
+
     #[test]
     fn client_map() {
         let mut client_map = ClientMap::new();
@@ -1069,6 +1582,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn client_map_scope_policy() {
+        use crate::primitives::scope::ScopeHierarchy;
+
+        let mut client_map = ClientMap::new();
+        client_map.register_client(Client::public(
+            "ClientId",
+            "https://example.com".parse::<Url>().unwrap().into(),
+            "repo:admin api:*".parse().unwrap(),
+        ));
+        let negotiate = |client_map: &ClientMap, scope: Option<&str>| {
+            let bound = client_map
+                .bound_redirect(ClientUrl {
+                    client_id: Cow::Borrowed("ClientId"),
+                    redirect_uri: None,
+                })
+                .unwrap();
+            client_map
+                .negotiate(bound, scope.map(|scope| scope.parse().unwrap()))
+                .map(|pre_grant| pre_grant.scope.to_string())
+        };
+
+        // Without a policy the default scope is always granted.
+        let granted: Scope = negotiate(&client_map, Some("email")).unwrap().parse().unwrap();
+        assert_eq!(granted, "repo:admin api:*".parse().unwrap());
+
+        let mut hierarchy = ScopeHierarchy::new();
+        hierarchy.imply("repo:admin", "repo:read");
+        client_map.set_scope_policy(hierarchy);
+        assert_eq!(negotiate(&client_map, Some("repo:read")).unwrap(), "repo:read");
+        assert_eq!(negotiate(&client_map, Some("api:users")).unwrap(), "api:users");
+        assert!(negotiate(&client_map, Some("email")).is_err());
+        assert!(negotiate(&client_map, None).is_ok());
+    }
+
     #[test]
     fn roundtrip_serialization_ignore_local_port_url() {
         let url = "https://localhost/callback"
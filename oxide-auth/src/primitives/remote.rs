@@ -0,0 +1,597 @@
+//! Validates tokens issued by a remote authorization server.
+//!
+//! Resource servers that do not issue tokens themselves can use these primitives in place of a
+//! local `Issuer` in a `ResourceFlow`, for example with `resource_flow`. The
+//! [`RemoteIntrospectionGuard`] asks the introspection endpoint of the authorization server about
+//! each token, as specified in [RFC 7662]. The transport is left to the server, which implements
+//! [`Introspect`] with its http client of choice:
+//!
+//! ```
+//! use oxide_auth::code_grant::introspection::IntrospectionResponse;
+//! use oxide_auth::primitives::remote::RemoteIntrospectionGuard;
+//!
+//! let guard = RemoteIntrospectionGuard::new(
+//!     |token: &str| -> Result<IntrospectionResponse, ()> {
+//!         // POST `token` to the introspection endpoint and parse the json response.
+//! #       let _ = token;
+//!         Ok(IntrospectionResponse::inactive())
+//!     },
+//!     "https://auth.example.com".parse().unwrap(),
+//! );
+//! ```
+//!
+//! Tokens in the JWT profile of [RFC 9068], as issued by many identity providers, can instead be
+//! validated locally by the [`JwtValidator`] with the published keys of their issuer. This is only
+//! available with the `jwt` feature.
+//!
+//! [RFC 7662]: https://tools.ietf.org/html/rfc7662
+//! [RFC 9068]: https://tools.ietf.org/html/rfc9068
+//! [`RemoteIntrospectionGuard`]: struct.RemoteIntrospectionGuard.html
+//! [`Introspect`]: trait.Introspect.html
+//! [`JwtValidator`]: struct.JwtValidator.html
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use chrono::{Duration, TimeZone, Utc};
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "jwt")]
+use serde::Deserialize;
+
+#[cfg(feature = "jwt")]
+use crate::code_grant::assertion::Audience;
+use crate::code_grant::extensions::{
+    attach_claims, Authentication, OpenIdConnect, RichAuthorization, AUTHORIZATION_DETAILS_EXTENSION,
+    OPENID_EXTENSION,
+};
+use crate::code_grant::introspection::IntrospectionResponse;
+use super::clock::{self, Clock, SharedClock};
+use super::error::PrimitiveError;
+use super::grant::{Extensions, Grant, Value};
+use super::issuer::{IssuedToken, Issuer, RefreshedToken};
+#[cfg(feature = "jwt")]
+use super::jwt::{self, JwkSet};
+use super::{Time, Url};
+
+/// Asks the introspection endpoint of an authorization server about a token.
+///
+/// Implementations send the token in an authenticated request to the endpoint and parse the json
+/// response. Failing to reach the endpoint or to parse its response is an error, while any token
+/// the endpoint does not know is described by an inactive response.
+pub trait Introspect {
+    /// The description of the token by the authorization server.
+    fn introspect(&self, token: &str) -> Result<IntrospectionResponse, ()>;
+}
+
+/// Validates bearer tokens with a remote introspection endpoint.
+///
+/// Responses are cached for a short time, so that not every request to the resource server causes
+/// a request to the authorization server. Active tokens are cached for five minutes by default but
+/// never beyond their expiry, inactive ones for thirty seconds. Tokens are only kept as their hash
+/// and at most ten thousand of them are cached at once. Errors of the introspection are not
+/// cached.
+///
+/// Recovered grants have the scope, client, owner, expiry, authorization details and the `acr` and
+/// `auth_time` of the authentication described by the response. Since introspection does not
+/// describe a redirect uri, theirs is the url of the authorization server. The guard never issues
+/// or refreshes tokens.
+pub struct RemoteIntrospectionGuard<C: Introspect> {
+    client: C,
+    issuer: Url,
+    active_for: Duration,
+    inactive_for: Duration,
+    capacity: usize,
+    cache: Mutex<HashMap<Vec<u8>, Cached>>,
+    clock: SharedClock,
+}
+
+/// A cached response, with the grant of an active token.
+struct Cached {
+    grant: Option<Grant>,
+    until: Time,
+}
+
+/// Fetches the JSON Web Key Set of an identity provider.
+///
+/// Implementations request the document at the `jwks_uri` of the provider. Only available with the
+/// `jwt` feature.
+#[cfg(feature = "jwt")]
+pub trait FetchJwks {
+    /// The current keys of the provider.
+    fn fetch(&self) -> Result<JwkSet, ()>;
+}
+
+/// Validates JWT access tokens of an identity provider with its published keys.
+///
+/// The signature of a token must be made with one of the keys of the provider, whose key set is
+/// fetched once and reused for an hour by default. A token signed with an unknown key causes the
+/// set to be fetched again early, at most once a minute, so that rotated keys are picked up. The
+/// issuer `iss` must be the provider and the audience `aud` must include one of the accepted
+/// audiences, while the expiry `exp` and not-before `nbf` are checked with a leeway of one minute
+/// by default.
+///
+/// Recovered grants have the subject, client, expiry, scope and authentication of the token. The
+/// scope is read from the space separated `scope` claim of [RFC 9068], or from an `scp` list as
+/// used by some providers. Since tokens do not describe a redirect uri, theirs is the url of the
+/// provider. The validator never issues or refreshes tokens. Only available with the `jwt` feature.
+#[cfg(feature = "jwt")]
+pub struct JwtValidator<F: FetchJwks> {
+    fetcher: F,
+    issuer: Url,
+    audience: Vec<String>,
+    leeway: Duration,
+    refresh_after: Duration,
+    keys: Mutex<Option<FetchedKeys>>,
+    clock: SharedClock,
+}
+
+#[cfg(feature = "jwt")]
+struct FetchedKeys {
+    jwks: JwkSet,
+    fetched: Time,
+}
+
+/// The claims of an access token by a remote provider.
+#[cfg(feature = "jwt")]
+#[derive(Deserialize)]
+struct RemoteClaims {
+    iss: String,
+    #[serde(default)]
+    sub: Option<String>,
+    aud: Audience,
+    exp: i64,
+    #[serde(default)]
+    nbf: Option<i64>,
+    #[serde(default)]
+    client_id: Option<String>,
+    #[serde(default)]
+    azp: Option<String>,
+    #[serde(default)]
+    scope: Option<String>,
+    #[serde(default)]
+    scp: Option<Vec<String>>,
+    #[serde(default)]
+    acr: Option<String>,
+    #[serde(default)]
+    auth_time: Option<i64>,
+}
+
+impl<F> Introspect for F
+where
+    F: Fn(&str) -> Result<IntrospectionResponse, ()>,
+{
+    fn introspect(&self, token: &str) -> Result<IntrospectionResponse, ()> {
+        self(token)
+    }
+}
+
+impl<C: Introspect> RemoteIntrospectionGuard<C> {
+    /// Introspect tokens of the authorization server `issuer` with the client.
+    pub fn new(client: C, issuer: Url) -> Self {
+        RemoteIntrospectionGuard {
+            client,
+            issuer,
+            active_for: Duration::minutes(5),
+            inactive_for: Duration::seconds(30),
+            capacity: 10_000,
+            cache: Mutex::new(HashMap::new()),
+            clock: clock::system(),
+        }
+    }
+
+    /// Check cached responses and compute their expiry from the time of the clock.
+    pub fn clock<K: Clock + Send + Sync + 'static>(&mut self, clock: K) {
+        self.clock = clock::shared(clock);
+    }
+
+    /// Set the longest time that a response describing an active token is reused.
+    ///
+    /// This bounds the time a revoked token is still accepted. A zero duration disables caching.
+    pub fn cache_active_for(&mut self, duration: Duration) {
+        self.active_for = duration;
+    }
+
+    /// Set the time that a response describing an inactive token is reused.
+    pub fn cache_inactive_for(&mut self, duration: Duration) {
+        self.inactive_for = duration;
+    }
+
+    /// Set the largest number of cached responses.
+    pub fn cache_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// Forget all cached responses.
+    pub fn clear_cache(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Vec<u8>, Cached>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn grant(&self, response: IntrospectionResponse) -> Option<Grant> {
+        if !response.active {
+            return None;
+        }
+
+        let now = self.clock.now();
+        let until = match response.exp {
+            Some(exp) => Utc.timestamp_opt(exp, 0).single()?,
+            None => now + self.active_for,
+        };
+        let mut extensions = Extensions::new();
+        if let Some(details) = response.authorization_details {
+            let details = Value::public(Some(RichAuthorization::encode(&details)));
+            extensions.set_raw(AUTHORIZATION_DETAILS_EXTENSION.to_owned(), details);
+        }
+        authenticated(&mut extensions, response.acr, response.auth_time);
+        attach_claims(&mut extensions, response.claims);
+
+        Some(Grant {
+            owner_id: response.sub.unwrap_or_default(),
+            client_id: response.client_id.unwrap_or_default(),
+            scope: response.scope.unwrap_or_default().parse().ok()?,
+            redirect_uri: self.issuer.clone(),
+            until,
+            extensions,
+        })
+    }
+}
+
+impl<C: Introspect> Issuer for RemoteIntrospectionGuard<C> {
+    fn issue(&mut self, _: Grant) -> Result<IssuedToken, PrimitiveError> {
+        Err(PrimitiveError::Invariant)
+    }
+
+    fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        Err(PrimitiveError::Invariant)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        let key = Sha256::digest(token.as_bytes()).to_vec();
+        let now = self.clock.now();
+        if let Some(cached) = self.lock().get(&key).filter(|cached| cached.until > now) {
+            return Ok(cached.grant.clone());
+        }
+
+        let response = self
+            .client
+            .introspect(token)
+            .map_err(|()| PrimitiveError::Unavailable)?;
+        let grant = self.grant(response);
+        let until = match &grant {
+            Some(grant) => grant.until.min(now + self.active_for),
+            None => now + self.inactive_for,
+        };
+
+        let mut cache = self.lock();
+        cache.retain(|_, cached| cached.until > now);
+        if cache.len() < self.capacity {
+            let grant = grant.clone();
+            cache.insert(key, Cached { grant, until });
+        }
+        Ok(grant)
+    }
+
+    fn recover_refresh<'a>(&'a self, _: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl<F> FetchJwks for F
+where
+    F: Fn() -> Result<JwkSet, ()>,
+{
+    fn fetch(&self) -> Result<JwkSet, ()> {
+        self()
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl<F: FetchJwks> JwtValidator<F> {
+    /// Validate tokens of the provider `issuer` intended for the audience.
+    pub fn new(fetcher: F, issuer: Url, audience: &str) -> Self {
+        JwtValidator {
+            fetcher,
+            issuer,
+            audience: vec![audience.to_owned()],
+            leeway: Duration::minutes(1),
+            refresh_after: Duration::hours(1),
+            keys: Mutex::new(None),
+            clock: clock::system(),
+        }
+    }
+
+    /// Also accept tokens intended for another audience.
+    pub fn accept_audience(&mut self, audience: &str) {
+        self.audience.push(audience.to_owned());
+    }
+
+    /// Set the leeway for skewed clocks when checking expiry and not-before.
+    ///
+    /// Validated grants expire with the leeway added, so that flows accept them for as long.
+    pub fn leeway(&mut self, leeway: Duration) {
+        self.leeway = leeway;
+    }
+
+    /// Check expiry and the age of the cached key set against the time of the clock.
+    pub fn clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = clock::shared(clock);
+    }
+
+    /// Set how long the fetched key set is reused.
+    pub fn refresh_after(&mut self, duration: Duration) {
+        self.refresh_after = duration;
+    }
+
+    /// Verify the signature and claims of a token and return the grant it describes.
+    ///
+    /// Tokens that are invalid for any reason describe no grant, while failing to fetch the keys
+    /// is an `Unavailable` error.
+    pub fn validate(&self, token: &str) -> Result<Option<Grant>, PrimitiveError> {
+        let claims = match self.verify(token)? {
+            Some(claims) => claims,
+            None => return Ok(None),
+        };
+
+        let now = self.clock.now().timestamp();
+        let leeway = self.leeway.num_seconds();
+        let started = match claims.nbf {
+            Some(nbf) => nbf - leeway <= now,
+            None => true,
+        };
+        // Parsed urls gain a trailing slash that the issuer identifier usually lacks.
+        let issuer = self.issuer.as_str();
+        let trusted = claims.iss == issuer || claims.iss == issuer.trim_end_matches('/');
+        if !trusted || !claims.aud.intended_for(&self.audience) || !started || claims.exp + leeway <= now
+        {
+            return Ok(None);
+        }
+
+        let scope = match (claims.scope, claims.scp) {
+            (Some(scope), _) => scope,
+            (None, Some(scp)) => scp.join(" "),
+            (None, None) => String::new(),
+        };
+        let client_id = claims.client_id.or(claims.azp).unwrap_or_default();
+        let mut extensions = Extensions::new();
+        authenticated(&mut extensions, claims.acr, claims.auth_time);
+        let grant = Grant {
+            owner_id: claims.sub.unwrap_or_else(|| client_id.clone()),
+            client_id,
+            scope: match scope.parse() {
+                Ok(scope) => scope,
+                Err(_) => return Ok(None),
+            },
+            redirect_uri: self.issuer.clone(),
+            until: Utc
+                .timestamp_opt(claims.exp, 0)
+                .single()
+                .ok_or(PrimitiveError::Invariant)?
+                + self.leeway,
+            extensions,
+        };
+        Ok(Some(grant))
+    }
+
+    /// Verify the signature with the cached keys, fetching them again if necessary.
+    fn verify(&self, token: &str) -> Result<Option<RemoteClaims>, PrimitiveError> {
+        let kid = match jwt::header(token) {
+            Ok(header) => header.kid,
+            Err(_) => return Ok(None),
+        };
+
+        let now = self.clock.now();
+        let mut keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        let stale = match &*keys {
+            None => true,
+            Some(cached) if cached.fetched + self.refresh_after <= now => true,
+            // A key unknown to the cached set may have been added by a rotation.
+            Some(cached) => {
+                let unknown = kid.is_some() && !cached.jwks.keys.iter().any(|key| key.kid == kid);
+                unknown && cached.fetched + Duration::minutes(1) <= now
+            }
+        };
+
+        if stale {
+            let jwks = self.fetcher.fetch().map_err(|()| PrimitiveError::Unavailable)?;
+            *keys = Some(FetchedKeys { jwks, fetched: now });
+        }
+
+        let jwks = &keys.as_ref().ok_or(PrimitiveError::Invariant)?.jwks;
+        Ok(jwks.verify(token).ok())
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl<F: FetchJwks> Issuer for JwtValidator<F> {
+    fn issue(&mut self, _: Grant) -> Result<IssuedToken, PrimitiveError> {
+        Err(PrimitiveError::Invariant)
+    }
+
+    fn refresh(&mut self, _: &str, _: Grant) -> Result<RefreshedToken, PrimitiveError> {
+        Err(PrimitiveError::Invariant)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        self.validate(token)
+    }
+
+    fn recover_refresh<'a>(&'a self, _: &'a str) -> Result<Option<Grant>, PrimitiveError> {
+        Ok(None)
+    }
+}
+
+/// Record the authentication of the owner, as described by the authorization server.
+///
+/// This lets a `StepUp` of the resource check tokens of a remote authorization server.
+fn authenticated(extensions: &mut Extensions, acr: Option<String>, auth_time: Option<i64>) {
+    if let Some(auth_time) = auth_time {
+        let authentication = Authentication {
+            nonce: None,
+            auth_time,
+            acr,
+            acr_values: Vec::new(),
+            max_age: None,
+        };
+        let encoded = Value::public(Some(OpenIdConnect::encode(&authentication)));
+        extensions.set_raw(OPENID_EXTENSION.to_owned(), encoded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_grant::extensions::authentication;
+    use serde_json::Map;
+    use std::cell::Cell;
+
+    fn active() -> IntrospectionResponse {
+        IntrospectionResponse {
+            active: true,
+            scope: Some("read write".to_owned()),
+            client_id: Some("Client".to_owned()),
+            sub: Some("Owner".to_owned()),
+            exp: Some((Utc::now() + Duration::hours(1)).timestamp()),
+            token_type: Some("bearer".to_owned()),
+            authorization_details: None,
+            acr: Some("mfa".to_owned()),
+            auth_time: Some(Utc::now().timestamp()),
+            claims: Map::new(),
+        }
+    }
+
+    #[test]
+    fn caches_responses() {
+        let calls = Cell::new(0);
+        let client = |token: &str| {
+            calls.set(calls.get() + 1);
+            match token {
+                "valid" => Ok(active()),
+                "unreachable" => Err(()),
+                _ => Ok(IntrospectionResponse::inactive()),
+            }
+        };
+        let guard = RemoteIntrospectionGuard::new(client, "https://auth.example.com".parse().unwrap());
+
+        let grant = guard.recover_token("valid").unwrap().unwrap();
+        assert_eq!(grant.owner_id, "Owner");
+        assert_eq!(grant.scope, "read write".parse().unwrap());
+        let authentication = authentication(&grant.extensions).unwrap();
+        assert_eq!(authentication.acr.as_deref(), Some("mfa"));
+        assert!(guard.recover_token("valid").unwrap().is_some());
+        assert_eq!(calls.get(), 1);
+
+        assert!(guard.recover_token("unknown").unwrap().is_none());
+        assert!(guard.recover_token("unknown").unwrap().is_none());
+        assert_eq!(calls.get(), 2);
+
+        // Errors are retried on the next request.
+        assert_eq!(
+            guard.recover_token("unreachable"),
+            Err(PrimitiveError::Unavailable)
+        );
+        assert_eq!(
+            guard.recover_token("unreachable"),
+            Err(PrimitiveError::Unavailable)
+        );
+        assert_eq!(calls.get(), 4);
+
+        guard.clear_cache();
+        assert!(guard.recover_token("valid").unwrap().is_some());
+        assert_eq!(calls.get(), 5);
+    }
+
+    #[test]
+    fn cache_expires() {
+        let calls = Cell::new(0);
+        let client = |_: &str| {
+            calls.set(calls.get() + 1);
+            Ok(active())
+        };
+        let mut guard =
+            RemoteIntrospectionGuard::new(client, "https://auth.example.com".parse().unwrap());
+        guard.cache_active_for(Duration::zero());
+
+        assert!(guard.recover_token("valid").unwrap().is_some());
+        assert!(guard.recover_token("valid").unwrap().is_some());
+        assert_eq!(calls.get(), 2);
+
+        guard.cache_active_for(Duration::minutes(5));
+        guard.cache_capacity(0);
+        assert!(guard.recover_token("valid").unwrap().is_some());
+        assert!(guard.recover_token("valid").unwrap().is_some());
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[cfg(feature = "jwt")]
+    #[test]
+    fn validates_jwt() {
+        use crate::primitives::jwt::SigningKey;
+
+        let generate = |kid: &str| {
+            SigningKey::es256(&SigningKey::generate_es256().unwrap())
+                .unwrap()
+                .with_kid(kid)
+        };
+        let first = generate("first");
+        let second = generate("second");
+
+        let published = Cell::new(1);
+        let fetches = Cell::new(0);
+        let fetcher = || {
+            fetches.set(fetches.get() + 1);
+            let keys = [&first, &second];
+            Ok(JwkSet {
+                keys: keys[..published.get()].iter().map(|key| key.jwk()).collect(),
+            })
+        };
+        let validator = JwtValidator::new(fetcher, "https://idp.example.com".parse().unwrap(), "api");
+
+        let exp = (Utc::now() + Duration::hours(1)).timestamp();
+        let claims = serde_json::json!({
+            "iss": "https://idp.example.com",
+            "sub": "Owner",
+            "aud": ["api", "other"],
+            "exp": exp,
+            "client_id": "Client",
+            "scp": ["read", "write"],
+        });
+        let token = first.sign("at+jwt", &claims).unwrap();
+        let grant = validator.recover_token(&token).unwrap().unwrap();
+        assert_eq!(grant.owner_id, "Owner");
+        assert_eq!(grant.client_id, "Client");
+        assert_eq!(grant.scope, "read write".parse().unwrap());
+        // The grant expires with the default leeway of a minute.
+        assert_eq!(grant.until.timestamp(), exp + 60);
+        assert_eq!(fetches.get(), 1);
+
+        let mut invalid = claims.clone();
+        invalid["aud"] = "other".into();
+        let token = first.sign("at+jwt", &invalid).unwrap();
+        assert!(validator.recover_token(&token).unwrap().is_none());
+
+        let mut invalid = claims.clone();
+        invalid["iss"] = "https://evil.example.com".into();
+        let token = first.sign("at+jwt", &invalid).unwrap();
+        assert!(validator.recover_token(&token).unwrap().is_none());
+
+        let mut invalid = claims.clone();
+        invalid["exp"] = (Utc::now() - Duration::hours(1)).timestamp().into();
+        let token = first.sign("at+jwt", &invalid).unwrap();
+        assert!(validator.recover_token(&token).unwrap().is_none());
+        assert_eq!(fetches.get(), 1);
+
+        // Keys unknown to the cached set are fetched again, at most once a minute.
+        published.set(2);
+        let token = second.sign("at+jwt", &claims).unwrap();
+        assert!(validator.recover_token(&token).unwrap().is_none());
+        assert_eq!(fetches.get(), 1);
+
+        *validator.keys.lock().unwrap() = None;
+        assert!(validator.recover_token(&token).unwrap().is_some());
+        assert_eq!(fetches.get(), 2);
+    }
+}
@@ -0,0 +1,190 @@
+//! Request uris stand in for authorization requests pushed by clients beforehand.
+//!
+//! With [Pushed Authorization Requests], a client first posts the parameters of its authorization
+//! request directly to the authorization server, authenticating itself as it would at the token
+//! endpoint. It receives a `request_uri` in exchange and sends the user-agent to the authorization
+//! endpoint with only this uri and its `client_id`, so that the parameters are neither exposed to
+//! nor modifiable by the user-agent.
+//!
+//! A [`RequestUriStore`] keeps the pushed parameters until the authorization endpoint resolves the
+//! uri. Each uri can be resolved only once and only before it expires.
+//!
+//! [Pushed Authorization Requests]: https://tools.ietf.org/html/rfc9126
+//! [`RequestUriStore`]: trait.RequestUriStore.html
+use std::collections::HashMap;
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use rand::{thread_rng, RngCore};
+
+use super::Time;
+
+/// The prefix of request uris referencing pushed requests.
+pub const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+/// The authorization parameters pushed by a client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PushedRequest {
+    /// The authenticated client which pushed the request.
+    pub client_id: String,
+
+    /// The parameters of the authorization request, without client credentials.
+    pub parameters: HashMap<String, String>,
+}
+
+/// The reference to a pushed request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PushedUri {
+    /// The uri with which the client refers to the request at the authorization endpoint.
+    pub request_uri: String,
+
+    /// Expiration timestamp of the uri (Utc).
+    pub until: Time,
+}
+
+/// Stores pushed authorization requests until they are used.
+pub trait RequestUriStore {
+    /// Store the parameters of a request and create a fresh uri referencing them.
+    fn push(&mut self, request: PushedRequest) -> Result<PushedUri, ()>;
+
+    /// Resolve and remove the request a client has pushed.
+    ///
+    /// Returns `None` for unknown and expired uris and for uris pushed by another client. A uri
+    /// must only ever resolve once.
+    fn pull(&mut self, client_id: &str, request_uri: &str) -> Result<Option<PushedRequest>, ()>;
+}
+
+/// An in-memory hash map of pushed requests.
+///
+/// Uris consist of the `REQUEST_URI_PREFIX` and a random part of 16 bytes. They are valid for
+/// sixty seconds by default.
+pub struct RequestUriMap {
+    duration: Duration,
+    requests: HashMap<String, (PushedRequest, Time)>,
+}
+
+impl RequestUriMap {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        RequestUriMap {
+            duration: Duration::seconds(60),
+            requests: HashMap::new(),
+        }
+    }
+
+    /// Set the validity of uris created after this call.
+    pub fn valid_for(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+}
+
+impl Default for RequestUriMap {
+    fn default() -> Self {
+        RequestUriMap::new()
+    }
+}
+
+impl<'a, S: RequestUriStore + ?Sized> RequestUriStore for &'a mut S {
+    fn push(&mut self, request: PushedRequest) -> Result<PushedUri, ()> {
+        (**self).push(request)
+    }
+
+    fn pull(&mut self, client_id: &str, request_uri: &str) -> Result<Option<PushedRequest>, ()> {
+        (**self).pull(client_id, request_uri)
+    }
+}
+
+impl<S: RequestUriStore + ?Sized> RequestUriStore for Box<S> {
+    fn push(&mut self, request: PushedRequest) -> Result<PushedUri, ()> {
+        (**self).push(request)
+    }
+
+    fn pull(&mut self, client_id: &str, request_uri: &str) -> Result<Option<PushedRequest>, ()> {
+        (**self).pull(client_id, request_uri)
+    }
+}
+
+impl<'a, S: RequestUriStore + ?Sized> RequestUriStore for MutexGuard<'a, S> {
+    fn push(&mut self, request: PushedRequest) -> Result<PushedUri, ()> {
+        (**self).push(request)
+    }
+
+    fn pull(&mut self, client_id: &str, request_uri: &str) -> Result<Option<PushedRequest>, ()> {
+        (**self).pull(client_id, request_uri)
+    }
+}
+
+impl<'a, S: RequestUriStore + ?Sized> RequestUriStore for RwLockWriteGuard<'a, S> {
+    fn push(&mut self, request: PushedRequest) -> Result<PushedUri, ()> {
+        (**self).push(request)
+    }
+
+    fn pull(&mut self, client_id: &str, request_uri: &str) -> Result<Option<PushedRequest>, ()> {
+        (**self).pull(client_id, request_uri)
+    }
+}
+
+impl RequestUriStore for RequestUriMap {
+    fn push(&mut self, request: PushedRequest) -> Result<PushedUri, ()> {
+        let now = Utc::now();
+        self.requests.retain(|_, (_, until)| *until > now);
+
+        let mut random = [0; 16];
+        thread_rng().try_fill_bytes(&mut random).map_err(|_| ())?;
+        let request_uri = format!("{}{}", REQUEST_URI_PREFIX, encode_config(random, URL_SAFE_NO_PAD));
+        let until = now + self.duration;
+        self.requests.insert(request_uri.clone(), (request, until));
+
+        Ok(PushedUri { request_uri, until })
+    }
+
+    fn pull(&mut self, client_id: &str, request_uri: &str) -> Result<Option<PushedRequest>, ()> {
+        match self.requests.get(request_uri) {
+            Some((request, _)) if request.client_id == client_id => (),
+            _ => return Ok(None),
+        }
+
+        let (request, until) = self.requests.remove(request_uri).unwrap();
+        if until <= Utc::now() {
+            return Ok(None);
+        }
+
+        Ok(Some(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pushed() -> PushedRequest {
+        let mut parameters = HashMap::new();
+        parameters.insert("response_type".to_string(), "code".to_string());
+        parameters.insert("client_id".to_string(), "Client".to_string());
+        PushedRequest {
+            client_id: "Client".to_string(),
+            parameters,
+        }
+    }
+
+    #[test]
+    fn push_and_pull() {
+        let mut store = RequestUriMap::new();
+        let uri = store.push(pushed()).unwrap();
+        assert!(uri.request_uri.starts_with(REQUEST_URI_PREFIX));
+        assert_ne!(store.push(pushed()).unwrap().request_uri, uri.request_uri);
+
+        assert_eq!(store.pull("Other", &uri.request_uri), Ok(None));
+        assert_eq!(store.pull("Client", &uri.request_uri), Ok(Some(pushed())));
+        assert_eq!(store.pull("Client", &uri.request_uri), Ok(None));
+    }
+
+    #[test]
+    fn expired() {
+        let mut store = RequestUriMap::new();
+        store.valid_for(Duration::seconds(-1));
+        let uri = store.push(pushed()).unwrap();
+        assert_eq!(store.pull("Client", &uri.request_uri), Ok(None));
+    }
+}
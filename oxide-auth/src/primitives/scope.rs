@@ -1,7 +1,7 @@
 //! Defines the Scope type and parsing/formatting according to the rfc.
 use std::{cmp, fmt, str};
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 
 /// Scope of a given grant or resource, a set of scope-tokens separated by spaces.
@@ -97,6 +97,102 @@ impl Scope {
     }
 }
 
+/// Decides which scope-tokens include others, beyond their plain equality.
+///
+/// The plain comparison of scopes only considers equal scope-tokens. A policy instead allows a
+/// scope-token such as `repo:admin` to include `repo:read`, or a wildcard such as `api:*` to
+/// include every scope-token below `api:`. Registrars consult it to validate the scope requested
+/// by a client against the scope registered for it.
+pub trait ScopePolicy: Send + Sync {
+    /// Whether the scope-token `granted` includes the scope-token `required`.
+    fn implies(&self, granted: &str, required: &str) -> bool;
+
+    /// Whether the scope `granted` includes every scope-token of the scope `required`.
+    fn covers(&self, granted: &Scope, required: &Scope) -> bool {
+        required
+            .iter()
+            .all(|required| granted.iter().any(|granted| self.implies(granted, required)))
+    }
+
+    /// Validate the scope requested by a client against the scope registered for it.
+    ///
+    /// Returns the scope to grant. By default this is the requested scope if the registered scope
+    /// covers it and an error otherwise.
+    fn validate(&self, requested: &Scope, registered: &Scope) -> Result<Scope, ()> {
+        if self.covers(registered, requested) {
+            Ok(requested.clone())
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// A `ScopePolicy` of scope-tokens implying others, with wildcards.
+///
+/// Implications are transitive, when `repo:admin` implies `repo:write` and `repo:write` implies
+/// `repo:read` then `repo:admin` implies `repo:read` as well. A scope-token ending in `*` is a
+/// wildcard including all scope-tokens that start with the part before the `*`, so `api:*`
+/// includes `api:read` and `api:users:write` while `*` includes everything.
+///
+/// ```
+/// # use oxide_auth::primitives::scope::{Scope, ScopeHierarchy, ScopePolicy};
+/// let mut hierarchy = ScopeHierarchy::new();
+/// hierarchy
+///     .imply("repo:admin", "repo:write")
+///     .imply("repo:write", "repo:read");
+///
+/// let registered = "repo:admin api:*".parse::<Scope>().unwrap();
+/// let requested = "repo:read api:users".parse::<Scope>().unwrap();
+/// assert!(hierarchy.covers(&registered, &requested));
+/// assert!(!hierarchy.covers(&requested, &registered));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ScopeHierarchy {
+    implied: HashMap<String, Vec<String>>,
+}
+
+impl ScopeHierarchy {
+    /// A hierarchy in which scope-tokens only imply themselves and the matches of wildcards.
+    pub fn new() -> Self {
+        ScopeHierarchy::default()
+    }
+
+    /// Let the scope-token `scope` imply the scope-token `implied`.
+    pub fn imply(&mut self, scope: &str, implied: &str) -> &mut Self {
+        self.implied
+            .entry(scope.to_owned())
+            .or_default()
+            .push(implied.to_owned());
+        self
+    }
+
+    fn matches(granted: &str, required: &str) -> bool {
+        match granted.strip_suffix('*') {
+            Some(prefix) => required.starts_with(prefix),
+            None => granted == required,
+        }
+    }
+}
+
+impl ScopePolicy for ScopeHierarchy {
+    fn implies(&self, granted: &str, required: &str) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![granted];
+        while let Some(scope) = pending.pop() {
+            if Self::matches(scope, required) {
+                return true;
+            }
+
+            if visited.insert(scope) {
+                let implied = self.implied.get(scope).into_iter().flatten();
+                pending.extend(implied.map(String::as_str));
+            }
+        }
+
+        false
+    }
+}
+
 /// Error returned from parsing a scope as encoded in an authorization token request.
 #[derive(Debug)]
 pub enum ParseScopeErr {
@@ -216,6 +312,29 @@ mod tests {
         assert!(!scope_uncmp.allow_access(&scope_base));
     }
 
+    #[test]
+    fn scope_hierarchy() {
+        let mut hierarchy = ScopeHierarchy::new();
+        hierarchy
+            .imply("repo:admin", "repo:write")
+            .imply("repo:write", "repo:read")
+            .imply("repo:read", "repo:admin")
+            .imply("admin", "api:*");
+
+        assert!(hierarchy.implies("repo:admin", "repo:read"));
+        assert!(!hierarchy.implies("repo:read", "repo:delete"));
+        assert!(hierarchy.implies("api:*", "api:users:read"));
+        assert!(hierarchy.implies("admin", "api:users"));
+        assert!(!hierarchy.implies("api:*", "apis"));
+        assert!(hierarchy.implies("*", "anything"));
+
+        let registered = "repo:write api:*".parse::<Scope>().unwrap();
+        let requested = "repo:read api:users".parse::<Scope>().unwrap();
+        assert_eq!(hierarchy.validate(&requested, &registered), Ok(requested.clone()));
+        let excessive = "repo:read email".parse::<Scope>().unwrap();
+        assert!(hierarchy.validate(&excessive, &registered).is_err());
+    }
+
     #[test]
     fn test_iterating() {
         let scope = "cap1 cap2 cap3".parse::<Scope>().unwrap();
@@ -0,0 +1,236 @@
+//! Sessions of owners at the authorization server.
+//!
+//! A session begins when an owner logs in and lasts until the owner logs out. The clients which
+//! the owner authorized during a session are recorded with it, so that logging out can end the
+//! sessions of a particular client and notify the clients participating in a session. Recording
+//! sessions is up to the server, usually in its login page and in its solicitor:
+//!
+//! ```
+//! use oxide_auth::primitives::session::{SessionMap, SessionRegistry};
+//!
+//! let mut sessions = SessionMap::new();
+//! // After authenticating the owner, remember the `sid` in a session cookie.
+//! let sid = sessions.start("alice").unwrap();
+//! // When the owner authorizes a client.
+//! sessions.join(&sid, "LocalClient").unwrap();
+//! ```
+//!
+//! The [`SessionRegistry`] is used by the `EndSessionFlow` to end the sessions of owners logging
+//! out through a client.
+//!
+//! [`SessionRegistry`]: trait.SessionRegistry.html
+use std::collections::HashMap;
+use std::sync::{MutexGuard, RwLockWriteGuard};
+
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use chrono::Utc;
+use rand::{thread_rng, RngCore};
+
+use super::Time;
+
+/// The session of an owner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    /// The session id, the `sid` claim of OpenID Connect.
+    pub sid: String,
+
+    /// The owner who logged in.
+    pub owner_id: String,
+
+    /// The clients the owner has authorized during the session.
+    pub clients: Vec<String>,
+
+    /// The time of the login.
+    pub started: Time,
+}
+
+/// Keeps the sessions of owners.
+pub trait SessionRegistry {
+    /// Start a new session of the owner and return its id.
+    fn start(&mut self, owner_id: &str) -> Result<String, ()>;
+
+    /// Record that the owner authorized the client during the session.
+    ///
+    /// Joining an unknown session fails.
+    fn join(&mut self, sid: &str, client_id: &str) -> Result<(), ()>;
+
+    /// The session with the id, if it has not ended.
+    fn session(&self, sid: &str) -> Result<Option<Session>, ()>;
+
+    /// All current sessions of the owner.
+    fn sessions_of(&self, owner_id: &str) -> Result<Vec<Session>, ()>;
+
+    /// End a session, returning it if it had not ended yet.
+    fn end(&mut self, sid: &str) -> Result<Option<Session>, ()>;
+}
+
+/// An in-memory hash map of sessions.
+///
+/// Session ids are 16 random bytes in url-safe base64. Sessions last until they are ended.
+#[derive(Default)]
+pub struct SessionMap {
+    sessions: HashMap<String, Session>,
+}
+
+impl SessionMap {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        SessionMap::default()
+    }
+}
+
+impl<S: SessionRegistry + ?Sized> SessionRegistry for &mut S {
+    fn start(&mut self, owner_id: &str) -> Result<String, ()> {
+        (**self).start(owner_id)
+    }
+
+    fn join(&mut self, sid: &str, client_id: &str) -> Result<(), ()> {
+        (**self).join(sid, client_id)
+    }
+
+    fn session(&self, sid: &str) -> Result<Option<Session>, ()> {
+        (**self).session(sid)
+    }
+
+    fn sessions_of(&self, owner_id: &str) -> Result<Vec<Session>, ()> {
+        (**self).sessions_of(owner_id)
+    }
+
+    fn end(&mut self, sid: &str) -> Result<Option<Session>, ()> {
+        (**self).end(sid)
+    }
+}
+
+impl<S: SessionRegistry + ?Sized> SessionRegistry for Box<S> {
+    fn start(&mut self, owner_id: &str) -> Result<String, ()> {
+        (**self).start(owner_id)
+    }
+
+    fn join(&mut self, sid: &str, client_id: &str) -> Result<(), ()> {
+        (**self).join(sid, client_id)
+    }
+
+    fn session(&self, sid: &str) -> Result<Option<Session>, ()> {
+        (**self).session(sid)
+    }
+
+    fn sessions_of(&self, owner_id: &str) -> Result<Vec<Session>, ()> {
+        (**self).sessions_of(owner_id)
+    }
+
+    fn end(&mut self, sid: &str) -> Result<Option<Session>, ()> {
+        (**self).end(sid)
+    }
+}
+
+impl<'a, S: SessionRegistry + ?Sized> SessionRegistry for MutexGuard<'a, S> {
+    fn start(&mut self, owner_id: &str) -> Result<String, ()> {
+        (**self).start(owner_id)
+    }
+
+    fn join(&mut self, sid: &str, client_id: &str) -> Result<(), ()> {
+        (**self).join(sid, client_id)
+    }
+
+    fn session(&self, sid: &str) -> Result<Option<Session>, ()> {
+        (**self).session(sid)
+    }
+
+    fn sessions_of(&self, owner_id: &str) -> Result<Vec<Session>, ()> {
+        (**self).sessions_of(owner_id)
+    }
+
+    fn end(&mut self, sid: &str) -> Result<Option<Session>, ()> {
+        (**self).end(sid)
+    }
+}
+
+impl<'a, S: SessionRegistry + ?Sized> SessionRegistry for RwLockWriteGuard<'a, S> {
+    fn start(&mut self, owner_id: &str) -> Result<String, ()> {
+        (**self).start(owner_id)
+    }
+
+    fn join(&mut self, sid: &str, client_id: &str) -> Result<(), ()> {
+        (**self).join(sid, client_id)
+    }
+
+    fn session(&self, sid: &str) -> Result<Option<Session>, ()> {
+        (**self).session(sid)
+    }
+
+    fn sessions_of(&self, owner_id: &str) -> Result<Vec<Session>, ()> {
+        (**self).sessions_of(owner_id)
+    }
+
+    fn end(&mut self, sid: &str) -> Result<Option<Session>, ()> {
+        (**self).end(sid)
+    }
+}
+
+impl SessionRegistry for SessionMap {
+    fn start(&mut self, owner_id: &str) -> Result<String, ()> {
+        let mut random = [0; 16];
+        thread_rng().try_fill_bytes(&mut random).map_err(|_| ())?;
+        let sid = encode_config(random, URL_SAFE_NO_PAD);
+
+        let session = Session {
+            sid: sid.clone(),
+            owner_id: owner_id.to_owned(),
+            clients: Vec::new(),
+            started: Utc::now(),
+        };
+        self.sessions.insert(sid.clone(), session);
+        Ok(sid)
+    }
+
+    fn join(&mut self, sid: &str, client_id: &str) -> Result<(), ()> {
+        let session = self.sessions.get_mut(sid).ok_or(())?;
+        if !session.clients.iter().any(|client| client == client_id) {
+            session.clients.push(client_id.to_owned());
+        }
+        Ok(())
+    }
+
+    fn session(&self, sid: &str) -> Result<Option<Session>, ()> {
+        Ok(self.sessions.get(sid).cloned())
+    }
+
+    fn sessions_of(&self, owner_id: &str) -> Result<Vec<Session>, ()> {
+        Ok(self
+            .sessions
+            .values()
+            .filter(|session| session.owner_id == owner_id)
+            .cloned()
+            .collect())
+    }
+
+    fn end(&mut self, sid: &str) -> Result<Option<Session>, ()> {
+        Ok(self.sessions.remove(sid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_and_end() {
+        let mut sessions = SessionMap::new();
+        let first = sessions.start("Owner").unwrap();
+        let second = sessions.start("Owner").unwrap();
+        assert_ne!(first, second);
+        sessions.start("Other").unwrap();
+
+        sessions.join(&first, "Client").unwrap();
+        sessions.join(&first, "Client").unwrap();
+        assert_eq!(sessions.session(&first).unwrap().unwrap().clients, ["Client"]);
+        assert!(sessions.join("unknown", "Client").is_err());
+        assert_eq!(sessions.sessions_of("Owner").unwrap().len(), 2);
+
+        let ended = sessions.end(&first).unwrap().unwrap();
+        assert_eq!(ended.owner_id, "Owner");
+        assert_eq!(sessions.end(&first), Ok(None));
+        assert_eq!(sessions.session(&first), Ok(None));
+        assert_eq!(sessions.sessions_of("Owner").unwrap().len(), 1);
+    }
+}